@@ -0,0 +1,37 @@
+// Correlating a run's observations into candidate recipients. Exhaustive enumeration takes
+// minutes an observation at these scales (route_enumeration measures it on bounded routes),
+// so this times the Monte Carlo analysis large networks are run with.
+
+use std::sync::OnceLock;
+use criterion::{Criterion, criterion_group, criterion_main};
+
+use thelma::bench::{BENCH_SCALES, bench_network, bench_observations};
+use thelma::models::HTLC;
+use thelma::surveillance::{AnalysisMode, HTLCAnalyzer};
+
+const PAYMENTS: usize = 200;
+const SAMPLES: usize = 1_000;
+
+fn correlation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("correlation");
+    group.sample_size(10);
+    for &nodes in BENCH_SCALES {
+        // Criterion calls the routine once per sample; the inputs are built on the first
+        let input: OnceLock<(HTLCAnalyzer, Vec<HTLC>)> = OnceLock::new();
+        group.bench_function(format!("{}/monte_carlo", nodes), |b| {
+            let (analyzer, observations) = input.get_or_init(|| {
+                let network_map = bench_network(nodes).expect("bench network");
+                // One malicious node in a hundred
+                let observations = bench_observations(&network_map, PAYMENTS, nodes / 100).expect("bench observations");
+                let mut analyzer = HTLCAnalyzer::new(network_map);
+                analyzer.set_analysis_mode(AnalysisMode::MonteCarlo { samples: SAMPLES });
+                (analyzer, observations)
+            });
+            b.iter(|| analyzer.correlate_observations(observations));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, correlation);
+criterion_main!(benches);
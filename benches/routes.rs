@@ -0,0 +1,50 @@
+// Route enumeration from one observer: every route whose timelock fits the budget of an HTLC
+// it forwarded, at each scale and route length
+
+use std::sync::{Arc, OnceLock, RwLock};
+use criterion::{Criterion, criterion_group, criterion_main};
+
+use thelma::bench::{BENCH_SCALES, bench_network};
+use thelma::error::read_lock;
+use thelma::models::LightningNetworkMap;
+
+// Route lengths searched at each scale. Four hops takes seconds a search at 1k nodes, and
+// three more than ten minutes at 10k.
+fn hops(nodes: usize) -> &'static [usize] {
+    if nodes >= 10_000 { &[2] } else { &[2, 3] }
+}
+// Forwarding delta assumed per hop when setting the budget, plus the final hop's
+const BUDGET_PER_HOP: u32 = 40;
+
+fn route_enumeration(c: &mut Criterion) {
+    let mut group = c.benchmark_group("route_enumeration");
+    for &nodes in BENCH_SCALES {
+        // Built on first use, so benchmarks filtered out on the command line cost nothing, and
+        // kept across the samples criterion calls the routine for
+        let input: OnceLock<(Arc<RwLock<LightningNetworkMap>>, String)> = OnceLock::new();
+        let input = || input.get_or_init(|| {
+            let network_map = bench_network(nodes).expect("bench network");
+            // A node of median degree, so the search is a typical forwarder's rather than a hub's
+            let observer = {
+                let network = read_lock(&network_map);
+                let mut keys: Vec<&String> = network.nodes.keys().collect();
+                keys.sort_by_key(|key| (network.degree(key), key.as_str()));
+                keys[keys.len() / 2].clone()
+            };
+            (network_map, observer)
+        });
+
+        for &hops in hops(nodes) {
+            let budget = BUDGET_PER_HOP * (hops as u32 + 1);
+            group.bench_function(format!("{}/{}_hops", nodes, hops), |b| {
+                let (network_map, observer) = input();
+                let network = read_lock(network_map);
+                b.iter(|| network.find_possible_routes_with_budget(observer, budget, hops, 100_000_000));
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, route_enumeration);
+criterion_main!(benches);
@@ -0,0 +1,29 @@
+// Generating large topologies, channel policies included
+
+use std::sync::{Arc, RwLock};
+use criterion::{Criterion, criterion_group, criterion_main};
+
+use thelma::bench::{BENCH_SCALES, BENCH_SEED};
+use thelma::models::LightningNetworkMap;
+use thelma::simulation::{NetworkGenerator, topology_from_name};
+
+fn topology(c: &mut Criterion) {
+    let mut group = c.benchmark_group("topology");
+    group.sample_size(10);
+    for name in ["scale-free", "small-world"] {
+        for &nodes in BENCH_SCALES {
+            group.bench_function(format!("{}/{}", name, nodes), |b| b.iter(|| {
+                let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(780000)));
+                let mut topology = topology_from_name(name, None).expect("topology");
+                NetworkGenerator::seeded(BENCH_SEED)
+                    .create_network(network_map.clone(), topology.as_mut(), nodes)
+                    .expect("bench network");
+                network_map
+            }));
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, topology);
+criterion_main!(benches);
@@ -0,0 +1,46 @@
+/*
+ * C API for THELMA's timelock analyzer. Build the shared library with `cargo build --release`
+ * and link against target/release/libthelma.so (libthelma.dylib on macOS).
+ *
+ * Functions returning int give 0 on success and -1 on failure; thelma_last_error() then
+ * says why. Strings are NUL-terminated UTF-8. A handle must not be used from two threads
+ * at once.
+ */
+#ifndef THELMA_H
+#define THELMA_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct ThelmaAnalyzer ThelmaAnalyzer;
+
+/* An analyzer for an empty graph at the given block height, or NULL on failure */
+ThelmaAnalyzer *thelma_analyzer_new(uint32_t block_height);
+void thelma_analyzer_free(ThelmaAnalyzer *analyzer);
+
+int thelma_add_node(ThelmaAnalyzer *analyzer, const char *pub_key, const char *alias,
+                    uint32_t cltv_expiry_delta);
+/* Both ends must have been added first. Capacity is in satoshis. */
+int thelma_add_channel(ThelmaAnalyzer *analyzer, const char *channel_id, const char *node1,
+                       const char *node2, uint64_t capacity);
+
+/* An HTLC forwarded by one of the caller's nodes, which joins the adversary. Amount in msat. */
+int thelma_submit_observation(ThelmaAnalyzer *analyzer, const char *payment_hash, uint32_t cltv_expiry,
+                              uint64_t amount, uint32_t observed_at_block, const char *observed_by_node);
+
+/* The JSON report on everything submitted so far, or NULL on failure. Free it with
+ * thelma_string_free. */
+char *thelma_run_analysis(ThelmaAnalyzer *analyzer);
+void thelma_string_free(char *value);
+
+/* Why the last failed call on this thread failed, or NULL. Valid until the next failure. */
+const char *thelma_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,46 @@
+// Inputs for `cargo bench --features bench`: the networks and observations the criterion
+// targets in benches/ measure route enumeration, correlation and topology generation on,
+// built the same way every run so estimates stay comparable.
+
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::models::{HTLC, LightningNetworkMap};
+use crate::simulation::{NetworkGenerator, PaymentSimulator, SimulatorConfig, topology_from_name};
+use crate::surveillance::{RandomPlacement, SurveillanceConfig, SurveillanceOperation};
+use crate::error::{ThelmaError, lock_mutex};
+
+// Network sizes every bench target runs at
+pub const BENCH_SCALES: &[usize] = &[1_000, 10_000];
+// Seed every network, adversary and payment set is built from
+pub const BENCH_SEED: u64 = 7;
+
+// Scale-free network of `node_count` nodes, the same every time
+pub fn bench_network(node_count: usize) -> Result<Arc<RwLock<LightningNetworkMap>>, ThelmaError> {
+    let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(780000)));
+    let mut topology = topology_from_name("scale-free", None)?;
+    NetworkGenerator::seeded(BENCH_SEED).create_network(network_map.clone(), topology.as_mut(), node_count)?;
+    Ok(network_map)
+}
+
+// What `malicious_count` random nodes observe of `payment_count` payments
+pub fn bench_observations(network_map: &Arc<RwLock<LightningNetworkMap>>,
+                          payment_count: usize,
+                          malicious_count: usize) -> Result<Vec<HTLC>, ThelmaError> {
+    let config = SurveillanceConfig::with_strategy(Box::new(RandomPlacement::seeded(BENCH_SEED)), malicious_count);
+    let operation = SurveillanceOperation::new(network_map.clone(), config)?;
+    let malicious_nodes = operation.get_malicious_nodes().to_vec();
+    let surveillance = Arc::new(Mutex::new(operation));
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        let config = SimulatorConfig::new().malicious_nodes(malicious_nodes).seed(BENCH_SEED);
+        let mut simulator = PaymentSimulator::new(network_map.clone(), config);
+        let observer = simulator.register_observer(surveillance.clone());
+        simulator.simulate_payments(payment_count).await?;
+        simulator.close_events();
+        observer.await?;
+        Ok::<_, ThelmaError>(())
+    })?;
+    let observations = lock_mutex(&surveillance).observation_batches().flatten().collect();
+    Ok(observations)
+}
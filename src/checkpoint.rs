@@ -0,0 +1,292 @@
+// Checkpoints of a running experiment, so long runs survive crashes and restarts
+
+use std::fs;
+use std::io;
+use log::info;
+
+use crate::models::{Channel, ChannelFees, ChannelStatus, HTLC, LightningNetworkMap, Node, ShadowOffset};
+use crate::simulation::PaymentRecord;
+use crate::surveillance::observation_store::{htlc_from_json, htlc_to_json};
+use crate::error::ThelmaError;
+
+// Default file checkpoints are written to
+pub const DEFAULT_CHECKPOINT_FILE: &str = "thelma_checkpoint.json";
+
+// Everything needed to pick a run back up where it left off
+pub struct Checkpoint {
+    // Command line the run was started with, so a resumed run uses the same settings
+    pub args: Vec<String>,
+    pub network: LightningNetworkMap,
+    pub malicious_nodes: Vec<String>,
+    pub observations: Vec<HTLC>,
+    pub payment_records: Vec<PaymentRecord>,
+    // Payments simulated so far, routed or not
+    pub payments_attempted: usize,
+    // Seed the simulator's RNG continues from
+    pub rng_seed: u64,
+    // Length of the event trace at this point, if one is being recorded
+    pub trace_offset: u64,
+}
+
+impl Checkpoint {
+    // Write the checkpoint next to its destination first, so a crash mid-write leaves
+    // the previous checkpoint intact
+    pub fn save(&self, path: &str) -> Result<(), ThelmaError> {
+        let data = serde_json::json!({
+            "args": self.args,
+            "network": network_to_json(&self.network),
+            "malicious_nodes": self.malicious_nodes,
+            "observations": self.observations.iter().map(htlc_to_json).collect::<Vec<_>>(),
+            "payment_records": self.payment_records.iter().map(record_to_json).collect::<Vec<_>>(),
+            "payments_attempted": self.payments_attempted,
+            "rng_seed": self.rng_seed,
+            "trace_offset": self.trace_offset,
+        });
+
+        let partial = format!("{}.tmp", path);
+        fs::write(&partial, serde_json::to_string(&data)?)?;
+        fs::rename(&partial, path)?;
+
+        info!("Checkpoint after {} payments saved to {}", self.payments_attempted, path);
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<Self, ThelmaError> {
+        let data: serde_json::Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+
+        Ok(Checkpoint {
+            args: strings(&data, "args")?,
+            network: network_from_json(&data["network"])?,
+            malicious_nodes: strings(&data, "malicious_nodes")?,
+            observations: array(&data, "observations")?.iter()
+                .map(htlc_from_json)
+                .collect::<Result<_, _>>()?,
+            payment_records: array(&data, "payment_records")?.iter()
+                .map(record_from_json)
+                .collect::<Result<_, _>>()?,
+            payments_attempted: number(&data, "payments_attempted")? as usize,
+            rng_seed: number(&data, "rng_seed")?,
+            trace_offset: number(&data, "trace_offset")?,
+        })
+    }
+}
+
+// Nodes and channels of a network, also used as the header of event traces
+pub(crate) fn network_to_json(network: &LightningNetworkMap) -> serde_json::Value {
+    let mut nodes: Vec<&Node> = network.nodes.values().collect();
+    nodes.sort_by(|a, b| a.pub_key.cmp(&b.pub_key));
+
+    serde_json::json!({
+        "block_height": network.current_block_height,
+        "nodes": nodes.into_iter().map(node_to_json).collect::<Vec<_>>(),
+        "channels": network.channels.iter().map(channel_to_json).collect::<Vec<_>>(),
+    })
+}
+
+pub(crate) fn network_from_json(value: &serde_json::Value) -> Result<LightningNetworkMap, ThelmaError> {
+    let mut network = LightningNetworkMap::new(number(value, "block_height")? as u32);
+    for node in array(value, "nodes")? {
+        network.add_node(node_from_json(node)?);
+    }
+    for channel in array(value, "channels")? {
+        network.add_channel(channel_from_json(channel)?);
+    }
+    Ok(network)
+}
+
+// A file missing a field was truncated or edited by hand
+fn missing(field: &str) -> ThelmaError {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{} is missing", field)).into()
+}
+
+fn number(value: &serde_json::Value, field: &str) -> Result<u64, ThelmaError> {
+    value[field].as_u64().ok_or_else(|| missing(field))
+}
+
+fn text<'a>(value: &'a serde_json::Value, field: &str) -> Result<&'a str, ThelmaError> {
+    value[field].as_str().ok_or_else(|| missing(field))
+}
+
+fn flag(value: &serde_json::Value, field: &str) -> Result<bool, ThelmaError> {
+    value[field].as_bool().ok_or_else(|| missing(field))
+}
+
+fn array<'a>(value: &'a serde_json::Value, field: &str) -> Result<&'a Vec<serde_json::Value>, ThelmaError> {
+    value[field].as_array().ok_or_else(|| missing(field))
+}
+
+fn strings(value: &serde_json::Value, field: &str) -> Result<Vec<String>, ThelmaError> {
+    array(value, field)?.iter()
+        .map(|s| s.as_str().map(str::to_string).ok_or_else(|| missing(field)))
+        .collect()
+}
+
+fn node_to_json(node: &Node) -> serde_json::Value {
+    serde_json::json!({
+        "pub_key": node.pub_key,
+        "alias": node.alias,
+        "cltv_expiry_delta": node.cltv_expiry_delta,
+        "min_final_cltv_expiry_delta": node.min_final_cltv_expiry_delta,
+        "base_fee_msat": node.base_fee_msat,
+        "fee_rate_ppm": node.fee_rate_ppm,
+        "shadow_offset": [node.shadow_offset.min, node.shadow_offset.max],
+        "label": node.label,
+        "tor_only": node.tor_only,
+        "region": node.region,
+    })
+}
+
+fn node_from_json(value: &serde_json::Value) -> Result<Node, ThelmaError> {
+    let mut node = Node::new(text(value, "pub_key")?, text(value, "alias")?, number(value, "cltv_expiry_delta")? as u32)
+        .with_min_final_cltv_expiry_delta(number(value, "min_final_cltv_expiry_delta")? as u32);
+    node.base_fee_msat = number(value, "base_fee_msat")?;
+    node.fee_rate_ppm = number(value, "fee_rate_ppm")?;
+    // Checkpoints from before offset profiles leave it out
+    if let Some([min, max]) = value["shadow_offset"].as_array().map(Vec::as_slice) {
+        if let (Some(min), Some(max)) = (min.as_u64(), max.as_u64()) {
+            node.shadow_offset = ShadowOffset { min: min as u32, max: max as u32 };
+        }
+    }
+    node.label = value["label"].as_str().map(str::to_string);
+    node.tor_only = value["tor_only"].as_bool().unwrap_or(false);
+    node.region = value["region"].as_str().map(str::to_string);
+    Ok(node)
+}
+
+fn channel_to_json(channel: &Channel) -> serde_json::Value {
+    serde_json::json!({
+        "channel_id": channel.channel_id,
+        "node1": channel.node1,
+        "node2": channel.node2,
+        "capacity": channel.capacity,
+        "htlc_minimum_msat": channel.htlc_minimum_msat,
+        "htlc_maximum_msat": channel.htlc_maximum_msat,
+        "status": channel.status.name(),
+        "last_update": channel.last_update,
+        "max_accepted_htlcs": channel.max_accepted_htlcs,
+        "reserve_sat": channel.reserve_sat,
+        "dust_limit_sat": channel.dust_limit_sat,
+        "max_dust_exposure_msat": channel.max_dust_exposure_msat,
+        "node1_fees": channel.node1_fees.map(fees_to_json),
+        "node2_fees": channel.node2_fees.map(fees_to_json),
+        "node1_cltv_delta": channel.node1_cltv_delta,
+        "node2_cltv_delta": channel.node2_cltv_delta,
+    })
+}
+
+fn fees_to_json(fees: ChannelFees) -> serde_json::Value {
+    serde_json::json!([fees.base_fee_msat, fees.fee_rate_ppm])
+}
+
+// Channel fees were added later, so older checkpoints have none
+fn fees_from_json(value: &serde_json::Value) -> Option<ChannelFees> {
+    match value.as_array().map(Vec::as_slice) {
+        Some([base, rate]) => Some(ChannelFees { base_fee_msat: base.as_u64()?, fee_rate_ppm: rate.as_u64()? }),
+        _ => None,
+    }
+}
+
+fn channel_from_json(value: &serde_json::Value) -> Result<Channel, ThelmaError> {
+    // Checkpoints from before channel statuses only hold active channels
+    let status = match value["status"].as_str() {
+        Some(name) => ChannelStatus::from_name(name)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("unknown channel status {}", name)))?,
+        None => ChannelStatus::Active,
+    };
+    let mut channel = Channel::new(text(value, "channel_id")?, text(value, "node1")?, text(value, "node2")?,
+                                   number(value, "capacity")?)
+        .with_htlc_limits(number(value, "htlc_minimum_msat")?, number(value, "htlc_maximum_msat")?)
+        .with_status(status);
+    channel.last_update = value["last_update"].as_u64().map(|block| block as u32);
+    if let Some(max_accepted_htlcs) = value["max_accepted_htlcs"].as_u64() {
+        channel.max_accepted_htlcs = max_accepted_htlcs as u16;
+    }
+    if let (Some(dust_limit), Some(exposure)) = (value["dust_limit_sat"].as_u64(), value["max_dust_exposure_msat"].as_u64()) {
+        channel = channel.with_dust_limits(dust_limit, exposure);
+    }
+    if let Some(reserve) = value["reserve_sat"].as_u64() {
+        channel.reserve_sat = reserve;
+    }
+    channel.node1_fees = fees_from_json(&value["node1_fees"]);
+    channel.node2_fees = fees_from_json(&value["node2_fees"]);
+    channel.node1_cltv_delta = value["node1_cltv_delta"].as_u64().map(|delta| delta as u32);
+    channel.node2_cltv_delta = value["node2_cltv_delta"].as_u64().map(|delta| delta as u32);
+    Ok(channel)
+}
+
+fn record_to_json(record: &PaymentRecord) -> serde_json::Value {
+    serde_json::json!({
+        "payment_hash": record.payment_hash,
+        "sender": record.sender,
+        "receiver": record.receiver,
+        "path": record.path,
+        "amount": record.amount,
+        "observed": record.observed,
+        "cover": record.cover,
+    })
+}
+
+fn record_from_json(value: &serde_json::Value) -> Result<PaymentRecord, ThelmaError> {
+    Ok(PaymentRecord {
+        payment_hash: text(value, "payment_hash")?.to_string(),
+        sender: text(value, "sender")?.to_string(),
+        receiver: text(value, "receiver")?.to_string(),
+        path: strings(value, "path")?,
+        amount: number(value, "amount")?,
+        observed: flag(value, "observed")?,
+        cover: flag(value, "cover")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_round_trips() {
+        let mut network = LightningNetworkMap::new(700000);
+        let mut alice = Node::new("alice", "Alice", 40);
+        alice.base_fee_msat = 1234;
+        network.add_node(alice);
+        network.add_node(Node::new("bob", "Bob", 144).with_min_final_cltv_expiry_delta(80));
+        network.add_channel(Channel::new("chan1", "alice", "bob", 500_000).with_htlc_limits(2000, 9_000_000));
+
+        let checkpoint = Checkpoint {
+            args: vec!["thelma".to_string(), "20".to_string(), "--checkpoint-every".to_string(), "5".to_string()],
+            network,
+            malicious_nodes: vec!["bob".to_string()],
+            observations: vec![HTLC::new("h1", 700080, 100000, 700000, "bob")],
+            payment_records: vec![PaymentRecord {
+                payment_hash: "h1".to_string(),
+                sender: "alice".to_string(),
+                receiver: "bob".to_string(),
+                path: vec!["alice".to_string(), "bob".to_string()],
+                amount: 100000,
+                observed: true,
+                cover: false,
+            }],
+            payments_attempted: 3,
+            rng_seed: u64::MAX - 7,
+            trace_offset: 512,
+        };
+
+        let path = std::env::temp_dir().join(format!("thelma-checkpoint-test-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        checkpoint.save(path).unwrap();
+        let restored = Checkpoint::load(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(restored.args, checkpoint.args);
+        assert_eq!(restored.network.current_block_height, 700000);
+        assert_eq!(restored.network.nodes, checkpoint.network.nodes);
+        assert_eq!(restored.network.channels_between("alice", "bob")[0].htlc_minimum_msat, 2000);
+        assert_eq!(restored.malicious_nodes, checkpoint.malicious_nodes);
+        assert_eq!(restored.observations[0].cltv_expiry, 700080);
+        assert_eq!(restored.payment_records[0].path, checkpoint.payment_records[0].path);
+        assert_eq!(restored.payments_attempted, 3);
+        assert_eq!(restored.rng_seed, u64::MAX - 7);
+        assert_eq!(restored.trace_offset, 512);
+
+        assert!(Checkpoint::load(path).is_err());
+    }
+}
@@ -0,0 +1,434 @@
+// Side-by-side comparison of surveillance effectiveness with and without defenses
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Write;
+use std::time::Duration;
+use log::info;
+
+use crate::models::LightningNetworkMap;
+use crate::simulation::PaymentRecord;
+use crate::surveillance::{LinkingAccuracy, PotentialRecipient};
+use crate::error::ThelmaError;
+use crate::latex::{self, LatexTable, LATEX_PREAMBLE_NOTE};
+
+// Rough per-hop forwarding latency used to estimate payment completion times when they
+// weren't measured
+pub const ESTIMATED_HOP_LATENCY_MS: f64 = 100.0;
+
+// Headline metrics for a single simulation scenario
+#[derive(Debug, Clone)]
+pub struct ScenarioMetrics {
+    pub label: String,
+    pub payments: usize,
+    pub observed_payments: usize,
+    // Observed payments whose top-ranked candidate was the true recipient
+    pub recipients_identified: usize,
+    // Observed payments where the true recipient appeared anywhere in the candidate set
+    pub recipients_in_candidates: usize,
+    pub avg_anonymity_set: f64,
+    // Distinct candidates left for each analyzed payment, smallest first
+    pub anonymity_sets: Vec<usize>,
+    pub avg_hops: f64,
+    pub avg_fee_msat: f64,
+    pub avg_latency_ms: f64,
+    // Dummy payments routed alongside the real ones
+    pub cover_payments: usize,
+    pub cover_fee_msat_per_payment: f64,
+    // What senders paid on top of amount and fees, per payment
+    pub avg_overpayment_msat: f64,
+    // How well the attacker linked observations into payments, when it had to
+    pub linking: Option<LinkingAccuracy>,
+    // Payments the attacker produced candidates for, real or not
+    pub analyzed_payments: usize,
+    // Attacker-side analysis cost
+    pub observations: usize,
+    pub analysis_time_ms: f64,
+}
+
+impl ScenarioMetrics {
+    // Score surveillance results against the simulator's ground truth
+    pub fn compute(label: &str,
+                   records: &[PaymentRecord],
+                   results: &HashMap<String, Vec<PotentialRecipient>>,
+                   network: &LightningNetworkMap) -> Self {
+        let routed: Vec<&PaymentRecord> = records.iter()
+            .filter(|r| r.path.len() >= 2 && !r.cover)
+            .collect();
+        let cover: Vec<&PaymentRecord> = records.iter()
+            .filter(|r| r.path.len() >= 2 && r.cover)
+            .collect();
+        let payments = routed.len();
+
+        let mut observed_payments = 0;
+        let mut recipients_identified = 0;
+        let mut recipients_in_candidates = 0;
+        let mut anonymity_sets = Vec::new();
+
+        for record in routed.iter().filter(|r| r.observed) {
+            observed_payments += 1;
+
+            if let Some(candidates) = results.get(&record.payment_hash) {
+                let unique: HashSet<&String> = candidates.iter().map(|c| &c.node_id).collect();
+                anonymity_sets.push(unique.len());
+
+                if candidates.first().map(|c| &c.node_id) == Some(&record.receiver) {
+                    recipients_identified += 1;
+                }
+                if unique.contains(&record.receiver) {
+                    recipients_in_candidates += 1;
+                }
+            }
+        }
+
+        let total_hops: usize = routed.iter().map(|r| r.hop_count()).sum();
+        let total_fees: u64 = routed.iter().map(|r| network.route_fee_msat(&r.path, r.amount)).sum();
+        let cover_fees: u64 = cover.iter().map(|r| network.route_fee_msat(&r.path, r.amount)).sum();
+
+        let avg = |total: f64, count: usize| if count == 0 { 0.0 } else { total / count as f64 };
+        let avg_hops = avg(total_hops as f64, payments);
+        anonymity_sets.sort_unstable();
+
+        ScenarioMetrics {
+            label: label.to_string(),
+            payments,
+            observed_payments,
+            recipients_identified,
+            recipients_in_candidates,
+            avg_anonymity_set: avg(anonymity_sets.iter().sum::<usize>() as f64, anonymity_sets.len()),
+            anonymity_sets,
+            avg_hops,
+            avg_fee_msat: avg(total_fees as f64, payments),
+            avg_latency_ms: avg_hops * ESTIMATED_HOP_LATENCY_MS,
+            cover_payments: cover.len(),
+            cover_fee_msat_per_payment: avg(cover_fees as f64, payments),
+            avg_overpayment_msat: 0.0,
+            linking: None,
+            analyzed_payments: results.len(),
+            observations: 0,
+            analysis_time_ms: 0.0,
+        }
+    }
+
+    // Attach the attacker's analysis cost, measured by the caller around run_analysis
+    pub fn record_analysis_cost(&mut self, observations: usize, elapsed: Duration) {
+        self.observations = observations;
+        self.analysis_time_ms = elapsed.as_secs_f64() * 1000.0;
+    }
+
+    // Replace the estimated latency with the time senders actually waited, in total over
+    // every payment
+    pub fn record_latency(&mut self, waiting_ms: u64) {
+        self.avg_latency_ms = if self.payments == 0 { 0.0 } else { waiting_ms as f64 / self.payments as f64 };
+    }
+
+    // Total senders overpaid, over every payment
+    pub fn record_overpayment(&mut self, overpaid_msat: u64) {
+        self.avg_overpayment_msat = if self.payments == 0 { 0.0 } else { overpaid_msat as f64 / self.payments as f64 };
+    }
+
+    pub fn record_linking(&mut self, linking: LinkingAccuracy) {
+        self.linking = Some(linking);
+    }
+
+    // Fraction of routed payments seen by at least one malicious node
+    pub fn observation_rate(&self) -> f64 {
+        if self.payments == 0 { 0.0 } else { self.observed_payments as f64 / self.payments as f64 }
+    }
+
+    // Fraction of observed payments whose recipient was ranked first
+    pub fn identification_rate(&self) -> f64 {
+        if self.observed_payments == 0 { 0.0 } else { self.recipients_identified as f64 / self.observed_payments as f64 }
+    }
+
+    // Fraction of the attacker's analyzed payments that pointed at a real recipient.
+    // Cover traffic inflates the denominator without adding true positives.
+    pub fn attacker_precision(&self) -> f64 {
+        if self.analyzed_payments == 0 { 0.0 } else { self.recipients_identified as f64 / self.analyzed_payments as f64 }
+    }
+
+    // Fraction of observed payments whose recipient was among the candidates
+    pub fn candidate_recall(&self) -> f64 {
+        if self.observed_payments == 0 { 0.0 } else { self.recipients_in_candidates as f64 / self.observed_payments as f64 }
+    }
+
+    // Smallest anonymity set at least `share` of analyzed payments stay within, so 0.5 is the
+    // median; 0 when nothing was analyzed
+    pub fn anonymity_set_percentile(&self, share: f64) -> usize {
+        if self.anonymity_sets.is_empty() {
+            return 0;
+        }
+        let rank = (share * self.anonymity_sets.len() as f64).ceil() as usize;
+        self.anonymity_sets[rank.clamp(1, self.anonymity_sets.len()) - 1]
+    }
+
+    // Analyzed payments narrowed down to a single candidate
+    pub fn unique_share(&self) -> f64 {
+        let unique = self.anonymity_sets.iter().take_while(|&&size| size <= 1).count();
+        if self.anonymity_sets.is_empty() { 0.0 } else { unique as f64 / self.anonymity_sets.len() as f64 }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "label": self.label,
+            "payments": self.payments,
+            "observed_payments": self.observed_payments,
+            "recipients_identified": self.recipients_identified,
+            "recipients_in_candidates": self.recipients_in_candidates,
+            "observation_rate": self.observation_rate(),
+            "identification_rate": self.identification_rate(),
+            "candidate_recall": self.candidate_recall(),
+            "avg_anonymity_set": self.avg_anonymity_set,
+            "avg_hops": self.avg_hops,
+            "avg_fee_msat": self.avg_fee_msat,
+            "avg_latency_ms": self.avg_latency_ms,
+            "cover_payments": self.cover_payments,
+            "cover_fee_msat_per_payment": self.cover_fee_msat_per_payment,
+            "avg_overpayment_msat": self.avg_overpayment_msat,
+            "linking_precision": self.linking.as_ref().map(LinkingAccuracy::precision),
+            "linking_recall": self.linking.as_ref().map(LinkingAccuracy::recall),
+            "analyzed_payments": self.analyzed_payments,
+            "attacker_precision": self.attacker_precision(),
+            "observations": self.observations,
+            "analysis_time_ms": self.analysis_time_ms,
+        })
+    }
+}
+
+// Comparison of a baseline run against one or more defended runs
+pub struct DefenseComparison {
+    baseline: ScenarioMetrics,
+    scenarios: Vec<ScenarioMetrics>,
+}
+
+impl DefenseComparison {
+    pub fn new(baseline: ScenarioMetrics) -> Self {
+        DefenseComparison {
+            baseline,
+            scenarios: Vec::new(),
+        }
+    }
+
+    pub fn add_scenario(&mut self, metrics: ScenarioMetrics) {
+        self.scenarios.push(metrics);
+    }
+
+    // Baseline first, then every defended scenario
+    fn all_scenarios(&self) -> impl Iterator<Item = &ScenarioMetrics> {
+        std::iter::once(&self.baseline).chain(self.scenarios.iter())
+    }
+
+    // Generate a markdown report comparing privacy, fee and latency tradeoffs
+    pub fn generate_text_report(&self) -> String {
+        let mut report = String::from("## THELMA: Defense Comparison Report\n\n");
+
+        report.push_str("### Privacy\n\n");
+        report.push_str("| Scenario | Payments | Observed | Recipient identified | Recipient in candidates | Attacker precision | Avg anonymity set |\n");
+        report.push_str("|---|---|---|---|---|---|---|\n");
+
+        for metrics in self.all_scenarios() {
+            report.push_str(&format!("| {} | {} | {:.1}% | {:.1}% | {:.1}% | {:.1}% | {:.2} |\n",
+                                     metrics.label,
+                                     metrics.payments,
+                                     metrics.observation_rate() * 100.0,
+                                     metrics.identification_rate() * 100.0,
+                                     metrics.candidate_recall() * 100.0,
+                                     metrics.attacker_precision() * 100.0,
+                                     metrics.avg_anonymity_set));
+        }
+
+        if self.all_scenarios().any(|metrics| metrics.linking.is_some()) {
+            report.push_str("\n### Linking\n\n");
+            report.push_str("| Scenario | Observations | Clusters | Payments | Linking precision | Linking recall |\n");
+            report.push_str("|---|---|---|---|---|---|\n");
+            for metrics in self.all_scenarios() {
+                if let Some(linking) = &metrics.linking {
+                    report.push_str(&format!("| {} | {} | {} | {} | {:.1}% | {:.1}% |\n",
+                                             metrics.label,
+                                             linking.observations,
+                                             linking.clusters,
+                                             linking.payments,
+                                             linking.precision() * 100.0,
+                                             linking.recall() * 100.0));
+                }
+            }
+        }
+
+        report.push_str("\n### Cost\n\n");
+        report.push_str("| Scenario | Avg hops | Avg fee (msat) | Cover fee per payment (msat) | Overpaid per payment (msat) | Latency (ms) | Cover payments | Attacker observations | Attacker analysis (ms) |\n");
+        report.push_str("|---|---|---|---|---|---|---|---|---|\n");
+
+        for metrics in self.all_scenarios() {
+            report.push_str(&format!("| {} | {:.2} | {:.0} | {:.0} | {:.0} | {:.0} | {} | {} | {:.1} |\n",
+                                     metrics.label,
+                                     metrics.avg_hops,
+                                     metrics.avg_fee_msat,
+                                     metrics.cover_fee_msat_per_payment,
+                                     metrics.avg_overpayment_msat,
+                                     metrics.avg_latency_ms,
+                                     metrics.cover_payments,
+                                     metrics.observations,
+                                     metrics.analysis_time_ms));
+        }
+
+        for metrics in &self.scenarios {
+            report.push_str(&format!("\n### {} vs {}\n", metrics.label, self.baseline.label));
+            report.push_str(&format!("- Identification rate: {:+.1} percentage points\n",
+                                     (metrics.identification_rate() - self.baseline.identification_rate()) * 100.0));
+            report.push_str(&format!("- Attacker precision: {:+.1} percentage points\n",
+                                     (metrics.attacker_precision() - self.baseline.attacker_precision()) * 100.0));
+            report.push_str(&format!("- Anonymity set size: {:+.2} candidates\n",
+                                     metrics.avg_anonymity_set - self.baseline.avg_anonymity_set));
+            if let (Some(linking), Some(baseline)) = (&metrics.linking, &self.baseline.linking) {
+                report.push_str(&format!("- Linking recall: {:+.1} percentage points\n",
+                                         (linking.recall() - baseline.recall()) * 100.0));
+            }
+            report.push_str(&format!("- Fee overhead: {:+.0} msat per payment\n",
+                                     metrics.avg_fee_msat - self.baseline.avg_fee_msat));
+            if metrics.avg_overpayment_msat > 0.0 {
+                report.push_str(&format!("- Overpayment: {:.0} msat per payment\n", metrics.avg_overpayment_msat));
+            }
+            report.push_str(&format!("- Latency overhead: {:+.0} ms per payment\n",
+                                     metrics.avg_latency_ms - self.baseline.avg_latency_ms));
+            report.push_str(&format!("- Attacker analysis cost: {:+} observations, {:+.1} ms\n",
+                                     metrics.observations as i64 - self.baseline.observations as i64,
+                                     metrics.analysis_time_ms - self.baseline.analysis_time_ms));
+        }
+
+        report
+    }
+
+    // Generate a JSON version of the comparison
+    pub fn generate_json_report(&self) -> String {
+        let scenarios: Vec<serde_json::Value> = self.all_scenarios().map(ScenarioMetrics::to_json).collect();
+
+        serde_json::to_string_pretty(&serde_json::json!({ "scenarios": scenarios }))
+            .unwrap_or_else(|_| "Error generating JSON report".to_string())
+    }
+
+    // Paper-ready tables: the attack's headline accuracy, and the spread of anonymity sets,
+    // one row per scenario
+    pub fn generate_latex(&self) -> String {
+        let mut accuracy = LatexTable::new("Timelock attack accuracy per scenario.", "tab:thelma-accuracy")
+            .column("Scenario", 'l')
+            .column("Payments", 'r')
+            .column("Observed (\\%)", 'r')
+            .column("Identified (\\%)", 'r')
+            .column("In candidates (\\%)", 'r')
+            .column("Precision (\\%)", 'r');
+        let mut anonymity = LatexTable::new("Anonymity sets left to the attacker: distinct candidate recipients \
+                                             per analyzed payment.", "tab:thelma-anonymity-sets")
+            .column("Scenario", 'l')
+            .column("Analyzed", 'r')
+            .column("Mean", 'r')
+            .column("Median", 'r')
+            .column("90th pct.", 'r')
+            .column("Max", 'r')
+            .column("Unique (\\%)", 'r');
+        for metrics in self.all_scenarios() {
+            accuracy.row(vec![latex::escape(&metrics.label), metrics.payments.to_string(),
+                              latex::percent(metrics.observation_rate()), latex::percent(metrics.identification_rate()),
+                              latex::percent(metrics.candidate_recall()), latex::percent(metrics.attacker_precision())]);
+            anonymity.row(vec![latex::escape(&metrics.label), metrics.anonymity_sets.len().to_string(),
+                               format!("{:.2}", metrics.avg_anonymity_set),
+                               metrics.anonymity_set_percentile(0.5).to_string(),
+                               metrics.anonymity_set_percentile(0.9).to_string(),
+                               metrics.anonymity_set_percentile(1.0).to_string(),
+                               latex::percent(metrics.unique_share())]);
+        }
+        format!("{}\n{}\n{}", LATEX_PREAMBLE_NOTE, accuracy.generate_latex(), anonymity.generate_latex())
+    }
+
+    pub fn save_latex_to_file(&self, filename: &str) -> Result<(), ThelmaError> {
+        std::fs::write(filename, self.generate_latex())?;
+        info!("LaTeX tables saved to {}", filename);
+        Ok(())
+    }
+
+    // Save the comparison report to file
+    pub fn save_report_to_file(&self, filename: &str) -> Result<(), ThelmaError> {
+        let mut file = File::create(filename)?;
+        file.write_all(self.generate_text_report().as_bytes())?;
+
+        info!("Defense comparison saved to {}", filename);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Node;
+    use crate::test_fixtures::{candidates, record};
+
+    #[test]
+    fn test_scenario_metrics() {
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["a", "b", "c", "d"] {
+            network.add_node(Node::new(key, key, 40));
+        }
+
+        let records = vec![
+            record("h1", &["a", "b", "c"]),
+            record("h2", &["a", "b", "c", "d"]),
+            PaymentRecord { observed: false, ..record("h3", &["a", "b"]) },
+        ];
+
+        let mut results = HashMap::new();
+        results.insert("h1".to_string(), candidates(&[("c", 1.0), ("d", 1.0)]));
+        results.insert("h2".to_string(), candidates(&[("c", 1.0), ("d", 1.0)]));
+
+        let metrics = ScenarioMetrics::compute("baseline", &records, &results, &network);
+
+        assert_eq!(metrics.payments, 3);
+        assert_eq!(metrics.observed_payments, 2);
+        assert_eq!(metrics.recipients_identified, 1);
+        assert_eq!(metrics.recipients_in_candidates, 2);
+        assert_eq!(metrics.avg_anonymity_set, 2.0);
+        assert_eq!(metrics.avg_hops, 2.0);
+        assert_eq!(metrics.identification_rate(), 0.5);
+
+        // A cover payment the attacker also analyzed dilutes its precision
+        let mut with_cover = records.clone();
+        let mut dummy = record("h4", &["b", "c", "d"]);
+        dummy.cover = true;
+        with_cover.push(dummy);
+        results.insert("h4".to_string(), candidates(&[("c", 1.0)]));
+
+        let diluted = ScenarioMetrics::compute("cover", &with_cover, &results, &network);
+
+        assert_eq!(diluted.payments, 3);
+        assert_eq!(diluted.cover_payments, 1);
+        assert_eq!(diluted.recipients_identified, 1);
+        assert!(diluted.attacker_precision() < metrics.attacker_precision());
+    }
+
+    #[test]
+    fn test_latex_tables_cover_every_scenario() {
+        let network = LightningNetworkMap::new(700000);
+        let records: Vec<PaymentRecord> = (0..4)
+            .map(|i| record(&format!("h{}", i), &["a", "b", "c"]))
+            .collect();
+        let mut results = HashMap::new();
+        results.insert("h0".to_string(), candidates(&[("c", 1.0)]));
+        results.insert("h1".to_string(), candidates(&[("c", 1.0), ("d", 1.0)]));
+        results.insert("h2".to_string(), candidates(&[("d", 1.0), ("c", 1.0), ("e", 1.0)]));
+        results.insert("h3".to_string(), candidates(&[("c", 1.0), ("c", 1.0)]));
+
+        let baseline = ScenarioMetrics::compute("Baseline", &records, &results, &network);
+        assert_eq!(baseline.anonymity_sets, vec![1, 1, 2, 3]);
+        assert_eq!(baseline.anonymity_set_percentile(0.5), 1);
+        assert_eq!(baseline.anonymity_set_percentile(0.9), 3);
+        assert_eq!(baseline.unique_share(), 0.5);
+
+        let mut comparison = DefenseComparison::new(baseline);
+        comparison.add_scenario(ScenarioMetrics::compute("Decoy hops (p=0.50, depth=2)", &records, &HashMap::new(), &network));
+        let latex = comparison.generate_latex();
+        assert!(latex.contains("    Baseline & 4 & 100.0 & 75.0 & 100.0 & 75.0 \\\\\n"));
+        assert!(latex.contains("    Baseline & 4 & 1.75 & 1 & 3 & 3 & 50.0 \\\\\n"));
+        // Nothing analyzed leaves empty statistics rather than a panic
+        assert!(latex.contains("    Decoy hops (p=0.50, depth=2) & 0 & 0.00 & 0 & 0 & 0 & 0.0 \\\\\n"));
+        assert_eq!(latex.matches("\\begin{table}").count(), 2);
+        assert!(latex.contains("\\caption{Anonymity sets left to the attacker: distinct candidate recipients per analyzed payment.}"));
+    }
+}
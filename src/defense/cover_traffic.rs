@@ -0,0 +1,50 @@
+// Cover-traffic defense: honest nodes emit dummy payments to drown out real ones
+
+use rand::Rng;
+
+// Configuration for cover traffic emitted alongside real payments
+#[derive(Debug, Clone)]
+pub struct CoverTrafficDefense {
+    // Average number of dummy payments emitted per real payment
+    pub rate: f64,
+}
+
+impl CoverTrafficDefense {
+    pub fn new(rate: f64) -> Self {
+        CoverTrafficDefense {
+            rate: rate.max(0.0),
+        }
+    }
+
+    // Number of dummy payments to emit after a real one. The fractional part of
+    // the rate is applied as a probability so the long-run average matches it.
+    pub fn dummies_for_payment<R: Rng + ?Sized>(&self, rng: &mut R) -> usize {
+        let whole = self.rate.trunc() as usize;
+        let fraction = self.rate.fract();
+
+        if fraction > 0.0 && rng.random_bool(fraction) {
+            whole + 1
+        } else {
+            whole
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dummy_rate() {
+        let mut rng = rand::rng();
+
+        assert_eq!(CoverTrafficDefense::new(0.0).dummies_for_payment(&mut rng), 0);
+        assert_eq!(CoverTrafficDefense::new(2.0).dummies_for_payment(&mut rng), 2);
+
+        let fractional = CoverTrafficDefense::new(1.5);
+        for _ in 0..20 {
+            let n = fractional.dummies_for_payment(&mut rng);
+            assert!(n == 1 || n == 2);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+// Route-length padding defense: senders detour through extra, unnecessary hops
+
+use std::collections::HashSet;
+use rand::Rng;
+
+use crate::models::LightningNetworkMap;
+use crate::simulation::utils::find_path_avoiding;
+
+// Attempts at finding a usable detour before giving up and keeping the original route
+const MAX_PADDING_ATTEMPTS: usize = 5;
+
+// Configuration for decoy-hop route padding
+#[derive(Debug, Clone)]
+pub struct DecoyHopDefense {
+    // Probability that a given payment gets padded
+    pub probability: f64,
+    // Maximum number of decoy hops inserted into a padded route
+    pub max_extra_hops: usize,
+}
+
+impl DecoyHopDefense {
+    pub fn new(probability: f64, max_extra_hops: usize) -> Self {
+        DecoyHopDefense {
+            probability: probability.clamp(0.0, 1.0),
+            max_extra_hops,
+        }
+    }
+
+    // Possibly pad a route by detouring through decoy nodes before the final hop.
+    // The extra hops consume CLTV budget downstream of any observer on the original
+    // route, making the recipient look further away than it really is.
+    pub fn pad_path<R: Rng + ?Sized>(&self,
+                                     network: &LightningNetworkMap,
+                                     path: &[String],
+                                     amount_msat: u64,
+                                     rng: &mut R) -> Vec<String> {
+        if path.len() < 2 || self.max_extra_hops == 0 || !rng.random_bool(self.probability) {
+            return path.to_vec();
+        }
+
+        let extra_hops = rng.random_range(1..=self.max_extra_hops);
+
+        for _ in 0..MAX_PADDING_ATTEMPTS {
+            if let Some(padded) = Self::try_detour(network, path, extra_hops, amount_msat, rng) {
+                return padded;
+            }
+        }
+
+        path.to_vec()
+    }
+
+    // Random walk away from the last forwarding node, then find the way back to the recipient
+    fn try_detour<R: Rng + ?Sized>(network: &LightningNetworkMap,
+                                   path: &[String],
+                                   extra_hops: usize,
+                                   amount_msat: u64,
+                                   rng: &mut R) -> Option<Vec<String>> {
+        let recipient = path.last()?;
+        let mut padded: Vec<String> = path[..path.len() - 1].to_vec();
+        let mut used: HashSet<String> = path.iter().cloned().collect();
+
+        for _ in 0..extra_hops {
+            let current = padded.last()?;
+            let candidates: Vec<&String> = network.get_neighbors(current)?
+                .iter()
+                .filter(|n| !used.contains(*n) && network.can_carry(current, n, amount_msat))
+                .collect();
+
+            if candidates.is_empty() {
+                break;
+            }
+
+            let next = candidates[rng.random_range(0..candidates.len())].clone();
+            used.insert(next.clone());
+            padded.push(next);
+        }
+
+        if padded.len() < path.len() {
+            return None;
+        }
+
+        // Reconnect to the recipient without revisiting any node already on the route
+        used.remove(recipient);
+        let detour_end = padded.last()?.clone();
+        used.remove(&detour_end);
+        let reconnect = find_path_avoiding(network, &detour_end, recipient, &used, amount_msat);
+
+        if reconnect.len() < 2 {
+            return None;
+        }
+
+        padded.extend_from_slice(&reconnect[1..]);
+        Some(padded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Node, Channel};
+
+    #[test]
+    fn test_pad_path_adds_decoy_hops() {
+        let mut network = LightningNetworkMap::new(700000);
+
+        for i in 1..=5 {
+            network.add_node(Node::new(&format!("node{}", i), &format!("Node {}", i), 40));
+        }
+
+        // Ring: node1 - node2 - node3 - node4 - node5 - node1
+        for i in 1..=5 {
+            network.add_channel(Channel::new(
+                &format!("chan{}", i),
+                &format!("node{}", i),
+                &format!("node{}", i % 5 + 1),
+                1_000_000,
+            ));
+        }
+
+        let path = vec!["node1".to_string(), "node2".to_string()];
+        let defense = DecoyHopDefense::new(1.0, 2);
+        let mut rng = rand::rng();
+
+        let padded = defense.pad_path(&network, &path, 100_000, &mut rng);
+
+        // The only detour from node1 to node2 goes the long way round the ring
+        assert_eq!(padded.first(), path.first());
+        assert_eq!(padded.last(), path.last());
+        assert!(padded.len() > path.len());
+
+        let unique: HashSet<&String> = padded.iter().collect();
+        assert_eq!(unique.len(), padded.len());
+
+        // A defense that never triggers leaves the route untouched
+        let disabled = DecoyHopDefense::new(0.0, 2);
+        assert_eq!(disabled.pad_path(&network, &path, 100_000, &mut rng), path);
+    }
+}
@@ -0,0 +1,280 @@
+// Defender view: how exposed a single node's payments were to the adversary
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Write;
+use log::info;
+
+use crate::models::HTLC;
+use crate::simulation::PaymentRecord;
+use crate::surveillance::{PotentialRecipient, SurveillanceOperation};
+use crate::error::ThelmaError;
+
+// Exposure of a node's payments in one role (sender or recipient)
+#[derive(Debug, Clone, Default)]
+pub struct ExposureStats {
+    pub payments: usize,
+    pub observed: usize,
+    // Payments where the attacker pinned the node down as the endpoint
+    pub identified: usize,
+    // Payments where the node was among the attacker's candidates
+    pub in_candidates: usize,
+    anonymity_total: usize,
+    analyzed: usize,
+}
+
+impl ExposureStats {
+    pub fn observation_rate(&self) -> f64 {
+        if self.payments == 0 { 0.0 } else { self.observed as f64 / self.payments as f64 }
+    }
+
+    // Average number of candidates the node was hidden among, over analyzed payments
+    pub fn avg_anonymity_set(&self) -> f64 {
+        if self.analyzed == 0 { 0.0 } else { self.anonymity_total as f64 / self.analyzed as f64 }
+    }
+
+    fn record_candidates(&mut self, candidate_count: usize, included: bool, identified: bool) {
+        self.analyzed += 1;
+        self.anonymity_total += candidate_count;
+        if included {
+            self.in_candidates += 1;
+        }
+        if identified {
+            self.identified += 1;
+        }
+    }
+}
+
+// How much a single malicious node contributed to the defender's exposure
+#[derive(Debug, Clone)]
+pub struct AdversaryImpact {
+    pub node_id: String,
+    pub observed_payments: usize,
+    pub identified_payments: usize,
+}
+
+// Exposure report from the point of view of one node operator
+#[derive(Debug, Clone)]
+pub struct DefenderView {
+    pub node_id: String,
+    pub node_alias: Option<String>,
+    pub as_sender: ExposureStats,
+    pub as_recipient: ExposureStats,
+    // Malicious nodes ordered from most to least harmful
+    pub adversaries: Vec<AdversaryImpact>,
+}
+
+impl DefenderView {
+    pub fn compute(node_id: &str,
+                   node_alias: Option<String>,
+                   records: &[PaymentRecord],
+                   results: &HashMap<String, Vec<PotentialRecipient>>,
+                   surveillance: &SurveillanceOperation) -> Self {
+        // Only the observations of this node's own payments matter
+        let own_payments: HashSet<&str> = records.iter()
+            .filter(|r| r.sender == node_id || r.receiver == node_id)
+            .map(|r| r.payment_hash.as_str())
+            .collect();
+        let mut observations_by_hash: HashMap<String, Vec<HTLC>> = HashMap::new();
+        for htlc in surveillance.observation_batches().flatten() {
+            if own_payments.contains(htlc.payment_hash.as_str()) {
+                observations_by_hash.entry(htlc.payment_hash.clone()).or_default().push(htlc);
+            }
+        }
+
+        let mut as_sender = ExposureStats::default();
+        let mut as_recipient = ExposureStats::default();
+        let mut impact: HashMap<String, AdversaryImpact> = HashMap::new();
+
+        for record in records.iter().filter(|r| !r.cover && r.path.len() >= 2) {
+            let is_sender = record.sender == node_id;
+            let is_recipient = record.receiver == node_id;
+            if !is_sender && !is_recipient {
+                continue;
+            }
+
+            let observations = observations_by_hash.get(record.payment_hash.as_str());
+            let mut identified = false;
+
+            if is_sender {
+                as_sender.payments += 1;
+                if let Some(observations) = observations {
+                    as_sender.observed += 1;
+
+                    // The observation with the largest expiry sits closest to the sender
+                    if let Some(first) = observations.iter().max_by_key(|h| h.cltv_expiry) {
+                        let candidates: HashSet<String> = surveillance.backtrack_senders(first).into_iter().collect();
+                        let included = candidates.contains(node_id);
+                        let pinned = included && candidates.len() == 1;
+                        as_sender.record_candidates(candidates.len(), included, pinned);
+                        identified |= pinned;
+                    }
+                }
+            }
+
+            if is_recipient {
+                as_recipient.payments += 1;
+                if observations.is_some() {
+                    as_recipient.observed += 1;
+
+                    if let Some(candidates) = results.get(&record.payment_hash) {
+                        let unique: HashSet<&String> = candidates.iter().map(|c| &c.node_id).collect();
+                        let top = candidates.first().map(|c| c.node_id.as_str()) == Some(node_id);
+                        as_recipient.record_candidates(unique.len(), unique.contains(&node_id.to_string()), top);
+                        identified |= top;
+                    }
+                }
+            }
+
+            let observers: HashSet<&str> = observations.into_iter()
+                .flatten()
+                .map(|h| h.observed_by_node.as_str())
+                .collect();
+
+            for observer in observers {
+                let entry = impact.entry(observer.to_string()).or_insert_with(|| AdversaryImpact {
+                    node_id: observer.to_string(),
+                    observed_payments: 0,
+                    identified_payments: 0,
+                });
+                entry.observed_payments += 1;
+                if identified {
+                    entry.identified_payments += 1;
+                }
+            }
+        }
+
+        let mut adversaries: Vec<AdversaryImpact> = impact.into_values().collect();
+        adversaries.sort_by(|a, b| b.identified_payments.cmp(&a.identified_payments)
+            .then(b.observed_payments.cmp(&a.observed_payments))
+            .then(a.node_id.cmp(&b.node_id)));
+
+        DefenderView {
+            node_id: node_id.to_string(),
+            node_alias,
+            as_sender,
+            as_recipient,
+            adversaries,
+        }
+    }
+
+    // Generate a markdown exposure report for the node operator
+    pub fn generate_text_report(&self) -> String {
+        let name = self.node_alias.clone().unwrap_or_else(|| "Unknown Node".to_string());
+        let mut report = format!("## THELMA: Defender View for {} ({})\n\n", name, self.node_id);
+
+        report.push_str("| Role | Payments | Observed | Identified | In candidate set | Avg anonymity set |\n");
+        report.push_str("|---|---|---|---|---|---|\n");
+
+        for (role, stats) in [("Sender", &self.as_sender), ("Recipient", &self.as_recipient)] {
+            report.push_str(&format!("| {} | {} | {} ({:.1}%) | {} | {} | {:.2} |\n",
+                                     role,
+                                     stats.payments,
+                                     stats.observed,
+                                     stats.observation_rate() * 100.0,
+                                     stats.identified,
+                                     stats.in_candidates,
+                                     stats.avg_anonymity_set()));
+        }
+
+        report.push_str("\n### Most harmful adversary placements\n");
+        if self.adversaries.is_empty() {
+            report.push_str("None of this node's payments were observed.\n");
+        }
+
+        for (i, adversary) in self.adversaries.iter().enumerate() {
+            report.push_str(&format!("{}. {} - observed {} payments, {} led to identification\n",
+                                     i+1, adversary.node_id, adversary.observed_payments,
+                                     adversary.identified_payments));
+        }
+
+        report
+    }
+
+    // Generate a JSON version of the defender view
+    pub fn generate_json_report(&self) -> String {
+        let role = |stats: &ExposureStats| serde_json::json!({
+            "payments": stats.payments,
+            "observed": stats.observed,
+            "observation_rate": stats.observation_rate(),
+            "identified": stats.identified,
+            "in_candidates": stats.in_candidates,
+            "avg_anonymity_set": stats.avg_anonymity_set(),
+        });
+
+        let adversaries: Vec<serde_json::Value> = self.adversaries.iter()
+            .map(|a| serde_json::json!({
+                "node_id": a.node_id,
+                "observed_payments": a.observed_payments,
+                "identified_payments": a.identified_payments,
+            }))
+            .collect();
+
+        serde_json::to_string_pretty(&serde_json::json!({
+            "node_id": self.node_id,
+            "node_alias": self.node_alias,
+            "as_sender": role(&self.as_sender),
+            "as_recipient": role(&self.as_recipient),
+            "adversaries": adversaries,
+        })).unwrap_or_else(|_| "Error generating JSON report".to_string())
+    }
+
+    // Save the defender view to file
+    pub fn save_report_to_file(&self, filename: &str) -> Result<(), ThelmaError> {
+        let mut file = File::create(filename)?;
+        file.write_all(self.generate_text_report().as_bytes())?;
+
+        info!("Defender view saved to {}", filename);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, RwLock};
+    use crate::models::{Node, Channel, LightningNetworkMap};
+    use crate::surveillance::SurveillanceConfig;
+    use crate::test_fixtures::record;
+
+    #[test]
+    fn test_defender_view() {
+        let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(700000)));
+        {
+            let mut network = network_map.write().unwrap();
+            for key in ["alice", "mallory", "bob"] {
+                network.add_node(Node::new(key, key, 40));
+            }
+            network.add_channel(Channel::new("chan1", "alice", "mallory", 1_000_000));
+            network.add_channel(Channel::new("chan2", "mallory", "bob", 1_000_000));
+        }
+
+        let config = SurveillanceConfig::observing(vec!["mallory".to_string()]);
+        let mut surveillance = SurveillanceOperation::new(network_map, config).unwrap();
+        surveillance.record_htlc_observation(HTLC::new("h1", 700080, 100000, 700000, "mallory")).unwrap();
+
+        let path: Vec<String> = ["alice", "mallory", "bob"].iter().map(|s| s.to_string()).collect();
+        let records = vec![record("h1", &["alice", "mallory", "bob"])];
+
+        let mut results = HashMap::new();
+        let recipient = PotentialRecipient { route: path[1..].to_vec(), ..PotentialRecipient::new("bob", 1.0) };
+        results.insert("h1".to_string(), vec![recipient]);
+
+        let view = DefenderView::compute("bob", None, &records, &results, &surveillance);
+
+        assert_eq!(view.as_recipient.payments, 1);
+        assert_eq!(view.as_recipient.observed, 1);
+        assert_eq!(view.as_recipient.identified, 1);
+        assert_eq!(view.as_recipient.avg_anonymity_set(), 1.0);
+        assert_eq!(view.as_sender.payments, 0);
+        assert_eq!(view.adversaries.len(), 1);
+        assert_eq!(view.adversaries[0].identified_payments, 1);
+
+        // Alice is one of mallory's two neighbours, so she stays hidden among them
+        let view = DefenderView::compute("alice", None, &records, &results, &surveillance);
+        assert_eq!(view.as_sender.observed, 1);
+        assert_eq!(view.as_sender.in_candidates, 1);
+        assert_eq!(view.as_sender.identified, 0);
+        assert_eq!(view.as_sender.avg_anonymity_set(), 2.0);
+    }
+}
@@ -0,0 +1,154 @@
+// Per-payment diff of the simulator's ground truth against what the attacker inferred,
+// for error analysis the aggregate metrics can't support
+
+use std::collections::{HashMap, HashSet};
+use log::info;
+
+use crate::simulation::PaymentRecord;
+use crate::surveillance::PotentialRecipient;
+use crate::error::ThelmaError;
+
+// Candidates listed per payment unless another count is asked for
+pub const DEFAULT_TOP_K: usize = 5;
+
+// One payment: what really happened next to what the attacker concluded
+#[derive(Debug, Clone)]
+pub struct PaymentDiff {
+    pub payment_hash: String,
+    pub sender: String,
+    pub receiver: String,
+    pub route: Vec<String>,
+    // Malicious nodes on the route, in route order
+    pub observers: Vec<String>,
+    // Candidates with the largest share first, with their share of the payment's confidence
+    pub top_candidates: Vec<(String, f64)>,
+    pub candidate_count: usize,
+    // Where the true recipient ranked among distinct candidates, from 1
+    pub truth_rank: Option<usize>,
+}
+
+impl PaymentDiff {
+    pub fn truth_in_candidates(&self) -> bool {
+        self.truth_rank.is_some()
+    }
+
+    pub fn truth_in_top_k(&self) -> bool {
+        self.truth_rank.is_some_and(|rank| rank <= self.top_candidates.len())
+    }
+}
+
+pub struct InferenceDiff {
+    pub top_k: usize,
+    pub payments: Vec<PaymentDiff>,
+}
+
+impl InferenceDiff {
+    // Every routed real payment, observed or not, in the order they were made
+    pub fn compute(records: &[PaymentRecord],
+                   results: &HashMap<String, Vec<PotentialRecipient>>,
+                   malicious_nodes: &[String],
+                   top_k: usize) -> Self {
+        let malicious: HashSet<&String> = malicious_nodes.iter().collect();
+        let payments = records.iter()
+            .filter(|r| r.path.len() >= 2 && !r.cover)
+            .map(|record| {
+                let candidates = results.get(&record.payment_hash).map(Vec::as_slice).unwrap_or(&[]);
+                let total: f32 = candidates.iter().map(|c| c.confidence_score).sum();
+
+                // Several routes can end at the same node, so nodes are ranked by the share of
+                // all their routes together; ties keep the analyzer's order
+                let mut ranked: Vec<(String, f64)> = Vec::new();
+                for candidate in candidates {
+                    let share = if total > 0.0 { (candidate.confidence_score / total) as f64 } else { 0.0 };
+                    match ranked.iter_mut().find(|(node, _)| *node == candidate.node_id) {
+                        Some(entry) => entry.1 += share,
+                        None => ranked.push((candidate.node_id.clone(), share)),
+                    }
+                }
+                ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+                PaymentDiff {
+                    payment_hash: record.payment_hash.clone(),
+                    sender: record.sender.clone(),
+                    receiver: record.receiver.clone(),
+                    route: record.path.clone(),
+                    observers: record.path.iter().filter(|n| malicious.contains(n)).cloned().collect(),
+                    candidate_count: ranked.len(),
+                    truth_rank: ranked.iter().position(|(node, _)| *node == record.receiver).map(|i| i + 1),
+                    top_candidates: ranked.into_iter().take(top_k).collect(),
+                }
+            })
+            .collect();
+
+        InferenceDiff { top_k, payments }
+    }
+
+    // One JSON object per line, so the file streams into pandas or jq
+    pub fn generate_json_lines(&self) -> String {
+        let mut lines = String::new();
+        for payment in &self.payments {
+            let candidates: Vec<serde_json::Value> = payment.top_candidates.iter()
+                .map(|(node, share)| serde_json::json!({ "node_id": node, "share": share }))
+                .collect();
+            let line = serde_json::json!({
+                "payment_hash": payment.payment_hash,
+                "sender": payment.sender,
+                "receiver": payment.receiver,
+                "route": payment.route,
+                "observers": payment.observers,
+                "observed": !payment.observers.is_empty(),
+                "top_candidates": candidates,
+                "candidate_count": payment.candidate_count,
+                "truth_rank": payment.truth_rank,
+                "truth_in_candidates": payment.truth_in_candidates(),
+                "truth_in_top_k": payment.truth_in_top_k(),
+            });
+            lines.push_str(&line.to_string());
+            lines.push('\n');
+        }
+        lines
+    }
+
+    pub fn save_to_file(&self, filename: &str) -> Result<(), ThelmaError> {
+        std::fs::write(filename, self.generate_json_lines())?;
+        info!("Inference diff of {} payments saved to {}", self.payments.len(), filename);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{candidates, record};
+
+    #[test]
+    fn test_inference_diff_ranks_truth_among_candidates() {
+        let records = vec![
+            record("p1", &["a", "m", "c"]),
+            PaymentRecord { observed: false, ..record("p2", &["a", "b", "d"]) },
+        ];
+        let results = HashMap::from([(
+            "p1".to_string(),
+            candidates(&[("e", 1.5), ("d", 1.0), ("d", 1.0), ("c", 0.5)]),
+        )]);
+        let diff = InferenceDiff::compute(&records, &results, &["m".to_string()], 2);
+
+        let observed = &diff.payments[0];
+        assert_eq!(observed.observers, vec!["m".to_string()]);
+        assert_eq!(observed.candidate_count, 3);
+        assert_eq!(observed.top_candidates[0], ("d".to_string(), 0.5));
+        assert_eq!(observed.truth_rank, Some(3));
+        assert!(observed.truth_in_candidates() && !observed.truth_in_top_k());
+
+        let unobserved = &diff.payments[1];
+        assert!(unobserved.observers.is_empty() && unobserved.top_candidates.is_empty());
+        assert_eq!(unobserved.truth_rank, None);
+
+        let lines: Vec<serde_json::Value> = diff.generate_json_lines().lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["truth_rank"], 3);
+        assert_eq!(lines[1]["observed"], false);
+    }
+}
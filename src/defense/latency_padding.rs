@@ -0,0 +1,53 @@
+// Latency-padding defense: honest nodes wait a random while before forwarding an HTLC, so
+// how long a payment takes to resolve says less about how far it still had to go
+
+use rand::Rng;
+
+// Configuration for the random delay honest nodes add before forwarding
+#[derive(Debug, Clone)]
+pub struct LatencyPaddingDefense {
+    // Longest delay a node adds, drawn uniformly from zero up to it
+    pub max_delay_ms: u64,
+}
+
+impl LatencyPaddingDefense {
+    pub fn new(max_delay_ms: u64) -> Self {
+        LatencyPaddingDefense { max_delay_ms }
+    }
+
+    pub fn delay<R: Rng + ?Sized>(&self, rng: &mut R) -> u64 {
+        rng.random_range(0..=self.max_delay_ms)
+    }
+
+    // Add the delays to the hold times of nodes on a route. A node holds its HTLC through
+    // its own delay and every one added after it, and the last node forwards nothing.
+    pub fn pad<R: Rng + ?Sized>(&self, holds: &mut [u64], honest: &[bool], rng: &mut R) {
+        let mut added = 0;
+        for i in (0..holds.len().saturating_sub(1)).rev() {
+            if honest.get(i).copied().unwrap_or(true) {
+                added += self.delay(rng);
+            }
+            holds[i] += added;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_padding_accumulates_towards_the_sender() {
+        let mut rng = rand::rng();
+        let defense = LatencyPaddingDefense::new(50);
+        let mut holds = vec![400, 200, 0];
+        defense.pad(&mut holds, &[true, false, true], &mut rng);
+
+        assert_eq!(holds[2], 0);
+        // The malicious middle node doesn't pad, so only the sender's delay is added
+        assert_eq!(holds[1], 200);
+        assert!((400..=450).contains(&holds[0]));
+
+        assert_eq!(LatencyPaddingDefense::new(0).delay(&mut rng), 0);
+    }
+}
@@ -0,0 +1,21 @@
+pub mod decoy_hops;
+pub mod cover_traffic;
+pub mod latency_padding;
+pub mod overpayment;
+pub mod payment_splitting;
+pub mod comparison;
+pub mod defender_view;
+pub mod inference_diff;
+pub mod roc;
+pub mod trampoline;
+
+pub use decoy_hops::*;
+pub use cover_traffic::*;
+pub use latency_padding::*;
+pub use overpayment::*;
+pub use payment_splitting::*;
+pub use comparison::*;
+pub use defender_view::*;
+pub use inference_diff::*;
+pub use roc::*;
+pub use trampoline::*;
@@ -0,0 +1,62 @@
+// Amount-randomization defense: senders overpay by a random few percent, leaving some of it
+// to the recipient and some as extra fees at forwarding hops. Amounts then shrink along a
+// route by more than the fees the network charges, which breaks linking observations by
+// amount.
+
+use rand::Rng;
+
+// Configuration for random overpayment
+#[derive(Debug, Clone)]
+pub struct OverpaymentDefense {
+    // Most a sender overpays, as a share of the payment's amount
+    pub max_share: f64,
+}
+
+impl OverpaymentDefense {
+    pub fn new(max_share: f64) -> Self {
+        OverpaymentDefense {
+            max_share: max_share.clamp(0.0, 1.0),
+        }
+    }
+
+    // Overpay on a route whose HTLC amounts are given sender first. Every node after the
+    // sender keeps a random extra, the recipient absorbing its own, so every HTLC grows by
+    // what the nodes after it keep. Returns the total overpaid.
+    pub fn overpay<R: Rng + ?Sized>(&self, amounts: &mut [u64], rng: &mut R) -> u64 {
+        let Some(&amount) = amounts.last() else { return 0 };
+        let receivers = amounts.len().saturating_sub(1);
+        if receivers == 0 {
+            return 0;
+        }
+        let max_extra = (amount as f64 * self.max_share / receivers as f64) as u64;
+
+        let mut added = 0;
+        for i in (1..amounts.len()).rev() {
+            added += rng.random_range(0..=max_extra);
+            amounts[i] += added;
+        }
+        amounts[0] = amounts[1];
+        added
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overpayment_stays_within_share() {
+        let mut rng = rand::rng();
+        let defense = OverpaymentDefense::new(0.03);
+        let mut amounts = vec![100_200, 100_200, 100_100, 100_000];
+        let overpaid = defense.overpay(&mut amounts, &mut rng);
+
+        assert!(overpaid <= 3_000);
+        assert_eq!(amounts[0], amounts[1]);
+        assert_eq!(amounts[0], 100_200 + overpaid);
+        // Amounts still shrink towards the recipient
+        assert!(amounts.windows(2).all(|pair| pair[0] >= pair[1]));
+
+        assert_eq!(OverpaymentDefense::new(0.0).overpay(&mut amounts, &mut rng), 0);
+    }
+}
@@ -0,0 +1,237 @@
+// Payment-splitting defense: senders split payments into multi-part shards and send each
+// over a route sharing no intermediary with the others, so no single forwarding node sees
+// the full amount. The report counts how much of each payment the adversary's nodes saw.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Write;
+use log::info;
+use rand::Rng;
+
+use crate::models::LightningNetworkMap;
+use crate::simulation::PaymentRecord;
+use crate::simulation::utils::find_path_avoiding;
+use crate::error::ThelmaError;
+
+// Configuration for multi-part payments over node-disjoint routes
+#[derive(Debug, Clone)]
+pub struct PaymentSplittingDefense {
+    // Most shards a payment is split into
+    pub max_shards: usize,
+}
+
+impl PaymentSplittingDefense {
+    pub fn new(max_shards: usize) -> Self {
+        PaymentSplittingDefense {
+            max_shards: max_shards.max(1),
+        }
+    }
+
+    // Split a payment going over `path` into shards, each with its own route through
+    // intermediaries no other shard uses. The first keeps `path`. Shards get random
+    // shares of the amount, and a payment with no second disjoint route isn't split.
+    pub fn split<R: Rng + ?Sized>(&self,
+                                  network: &LightningNetworkMap,
+                                  path: &[String],
+                                  amount_msat: u64,
+                                  rng: &mut R) -> Vec<(Vec<String>, u64)> {
+        let (Some(sender), Some(receiver)) = (path.first(), path.last()) else { return Vec::new() };
+        let shard_amount = amount_msat / self.max_shards as u64;
+        let mut paths = vec![path.to_vec()];
+        let mut avoid: HashSet<String> = path[1..path.len() - 1].iter().cloned().collect();
+        while paths.len() < self.max_shards {
+            let next = find_path_avoiding(network, sender, receiver, &avoid, shard_amount);
+            if next.len() < 2 || paths.contains(&next) {
+                break;
+            }
+            avoid.extend(next[1..next.len() - 1].iter().cloned());
+            paths.push(next);
+        }
+        if paths.len() == 1 {
+            return vec![(path.to_vec(), amount_msat)];
+        }
+
+        // Every shard gets between half and one and a half times an even share
+        let weights: Vec<f64> = paths.iter().map(|_| rng.random_range(0.5..1.5)).collect();
+        let total: f64 = weights.iter().sum();
+        let mut amounts: Vec<u64> = weights.iter().map(|w| (amount_msat as f64 * w / total) as u64).collect();
+        let assigned: u64 = amounts.iter().sum();
+        amounts[0] += amount_msat - assigned;
+
+        paths.into_iter().zip(amounts).collect()
+    }
+}
+
+// How much of the payments' amounts the adversary's forwarding nodes saw
+#[derive(Debug, Clone, Default)]
+pub struct ShardExposure {
+    pub label: String,
+    pub payments: usize,
+    pub split_payments: usize,
+    // Payments a malicious intermediary forwarded any shard of
+    pub observed_payments: usize,
+    // Observed payments every shard of which crossed a malicious intermediary, so the
+    // adversary could add up the full amount
+    pub reconstructed_payments: usize,
+    // Malicious intermediaries on a payment's routes, and the share of the amount each saw
+    pub observers: usize,
+    pub full_amount_observers: usize,
+    observer_share_total: f64,
+    observed_share_total: f64,
+}
+
+impl ShardExposure {
+    // Score the payments against what crossed malicious intermediaries. Payments missing
+    // from `shards` went in one piece over their recorded path.
+    pub fn compute(label: &str,
+                   records: &[PaymentRecord],
+                   shards: &HashMap<String, Vec<(Vec<String>, u64)>>,
+                   malicious_nodes: &[String]) -> Self {
+        let malicious: HashSet<&String> = malicious_nodes.iter().collect();
+        let mut exposure = ShardExposure { label: label.to_string(), ..ShardExposure::default() };
+
+        for record in records.iter().filter(|r| r.path.len() >= 2 && !r.cover) {
+            exposure.payments += 1;
+            let whole = [(record.path.clone(), record.amount)];
+            let routes = shards.get(&record.payment_hash).map_or(&whole[..], Vec::as_slice);
+            if routes.len() > 1 {
+                exposure.split_payments += 1;
+            }
+
+            let total: u64 = routes.iter().map(|(_, amount)| amount).sum();
+            let mut seen = 0;
+            for (path, amount) in routes {
+                let watchers = path[1..path.len() - 1].iter().filter(|node| malicious.contains(node)).count();
+                if watchers == 0 {
+                    continue;
+                }
+                seen += amount;
+                let share = *amount as f64 / total.max(1) as f64;
+                exposure.observers += watchers;
+                exposure.observer_share_total += share * watchers as f64;
+                if *amount == total {
+                    exposure.full_amount_observers += watchers;
+                }
+            }
+            if seen > 0 {
+                exposure.observed_payments += 1;
+                exposure.observed_share_total += seen as f64 / total.max(1) as f64;
+                if seen == total {
+                    exposure.reconstructed_payments += 1;
+                }
+            }
+        }
+        exposure
+    }
+
+    // Average share of a payment's amount a single malicious intermediary saw
+    pub fn avg_observer_share(&self) -> f64 {
+        if self.observers == 0 { 0.0 } else { self.observer_share_total / self.observers as f64 }
+    }
+
+    // Average share of an observed payment's amount all malicious intermediaries saw together
+    pub fn avg_observed_share(&self) -> f64 {
+        if self.observed_payments == 0 { 0.0 } else { self.observed_share_total / self.observed_payments as f64 }
+    }
+
+    pub fn reconstruction_rate(&self) -> f64 {
+        if self.observed_payments == 0 { 0.0 } else { self.reconstructed_payments as f64 / self.observed_payments as f64 }
+    }
+}
+
+// Exposure without splitting next to exposure with it
+pub struct SplittingReport {
+    scenarios: Vec<ShardExposure>,
+}
+
+impl SplittingReport {
+    pub fn new(baseline: ShardExposure) -> Self {
+        SplittingReport { scenarios: vec![baseline] }
+    }
+
+    pub fn add_scenario(&mut self, exposure: ShardExposure) {
+        self.scenarios.push(exposure);
+    }
+
+    pub fn generate_text_report(&self) -> String {
+        let mut report = String::from("## THELMA: Payment Splitting\n\n");
+        report.push_str("| Scenario | Payments | Split | Observed | Amount share per observer | Observers seeing full amount | Amount share seen | Full amount reconstructed |\n");
+        report.push_str("|---|---|---|---|---|---|---|---|\n");
+        for exposure in &self.scenarios {
+            report.push_str(&format!("| {} | {} | {} | {} | {:.1}% | {} of {} | {:.1}% | {:.1}% |\n",
+                                     exposure.label,
+                                     exposure.payments,
+                                     exposure.split_payments,
+                                     exposure.observed_payments,
+                                     exposure.avg_observer_share() * 100.0,
+                                     exposure.full_amount_observers,
+                                     exposure.observers,
+                                     exposure.avg_observed_share() * 100.0,
+                                     exposure.reconstruction_rate() * 100.0));
+        }
+        report
+    }
+
+    pub fn generate_json_report(&self) -> String {
+        let scenarios: Vec<serde_json::Value> = self.scenarios.iter()
+            .map(|exposure| serde_json::json!({
+                "label": exposure.label,
+                "payments": exposure.payments,
+                "split_payments": exposure.split_payments,
+                "observed_payments": exposure.observed_payments,
+                "observers": exposure.observers,
+                "full_amount_observers": exposure.full_amount_observers,
+                "avg_observer_share": exposure.avg_observer_share(),
+                "avg_observed_share": exposure.avg_observed_share(),
+                "reconstructed_payments": exposure.reconstructed_payments,
+                "reconstruction_rate": exposure.reconstruction_rate(),
+            }))
+            .collect();
+        serde_json::to_string_pretty(&serde_json::json!({ "scenarios": scenarios }))
+            .unwrap_or_else(|_| "Error generating JSON report".to_string())
+    }
+
+    pub fn save_report_to_file(&self, filename: &str) -> Result<(), ThelmaError> {
+        let mut file = File::create(filename)?;
+        file.write_all(self.generate_text_report().as_bytes())?;
+
+        info!("Payment splitting report saved to {}", filename);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Channel, Node};
+    use crate::test_fixtures::record;
+
+    #[test]
+    fn test_shards_avoid_each_others_intermediaries() {
+        // Two ways from a to d, through b or through c
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["a", "b", "c", "d"] {
+            network.add_node(Node::new(key, key, 40));
+        }
+        for (id, x, y) in [("ab", "a", "b"), ("bd", "b", "d"), ("ac", "a", "c"), ("cd", "c", "d")] {
+            network.add_channel(Channel::new(id, x, y, 10_000_000));
+        }
+        let path: Vec<String> = ["a", "b", "d"].iter().map(|n| n.to_string()).collect();
+        let shards = PaymentSplittingDefense::new(3).split(&network, &path, 90_000, &mut rand::rng());
+
+        assert_eq!(shards.len(), 2);
+        assert_eq!(shards[0].0, path);
+        assert_eq!(shards[1].0, vec!["a", "c", "d"]);
+        assert_eq!(shards.iter().map(|(_, amount)| amount).sum::<u64>(), 90_000);
+
+        // b sees one shard, and nobody sees c's
+        let record = PaymentRecord { amount: 90_000, ..record("h1", &["a", "b", "d"]) };
+        let split = HashMap::from([("h1".to_string(), shards.clone())]);
+        let exposure = ShardExposure::compute("Split", std::slice::from_ref(&record), &split, &["b".to_string()]);
+        assert_eq!((exposure.observed_payments, exposure.reconstructed_payments), (1, 0));
+        assert!(exposure.avg_observer_share() < 1.0);
+
+        let whole = ShardExposure::compute("Whole", &[record], &HashMap::new(), &["b".to_string()]);
+        assert_eq!((whole.full_amount_observers, whole.reconstructed_payments), (1, 1));
+    }
+}
@@ -0,0 +1,160 @@
+// ROC and precision-recall curves of the attacker's "this candidate is the recipient" calls
+// as the confidence threshold varies, the standard evaluation artifacts for comparing
+// deanonymization attacks across papers
+
+use std::collections::HashMap;
+use log::info;
+
+use crate::simulation::PaymentRecord;
+use crate::surveillance::PotentialRecipient;
+use crate::error::ThelmaError;
+
+// Points kept in an exported curve; longer curves are thinned evenly
+pub const MAX_CURVE_POINTS: usize = 1000;
+
+// Calls made at one threshold: every candidate holding at least `threshold` of its payment's
+// confidence is called the recipient
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThresholdPoint {
+    pub threshold: f64,
+    pub true_positives: usize,
+    pub false_positives: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct RocCurve {
+    // Highest threshold first
+    pub points: Vec<ThresholdPoint>,
+    // Observed payments, each with one true recipient to find
+    pub positives: usize,
+    // Candidates that weren't the recipient
+    pub negatives: usize,
+}
+
+impl RocCurve {
+    // Score every observed payment's candidates. A node's routes are added up, so its
+    // confidence is its share of the payment's; a recipient never named stays a miss at
+    // every threshold.
+    pub fn compute(records: &[PaymentRecord], results: &HashMap<String, Vec<PotentialRecipient>>) -> Self {
+        let mut scored: Vec<(f64, bool)> = Vec::new();
+        let mut positives = 0;
+        for record in records.iter().filter(|r| r.path.len() >= 2 && !r.cover) {
+            let Some(candidates) = results.get(&record.payment_hash).filter(|c| !c.is_empty()) else { continue };
+            positives += 1;
+            let total: f32 = candidates.iter().map(|c| c.confidence_score).sum();
+            let mut per_node: HashMap<&str, f32> = HashMap::new();
+            for candidate in candidates {
+                *per_node.entry(&candidate.node_id).or_insert(0.0) += candidate.confidence_score;
+            }
+            for (node, confidence) in per_node {
+                let share = if total > 0.0 { f64::from(confidence / total) } else { 0.0 };
+                scored.push((share, node == record.receiver));
+            }
+        }
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let mut points: Vec<ThresholdPoint> = Vec::new();
+        let (mut true_positives, mut false_positives) = (0, 0);
+        for (i, &(share, correct)) in scored.iter().enumerate() {
+            if correct { true_positives += 1 } else { false_positives += 1 }
+            // Candidates tied on confidence are called together
+            if scored.get(i + 1).is_none_or(|next| next.0 < share) {
+                points.push(ThresholdPoint { threshold: share, true_positives, false_positives });
+            }
+        }
+
+        RocCurve { points, positives, negatives: false_positives }
+    }
+
+    pub fn true_positive_rate(&self, point: &ThresholdPoint) -> f64 {
+        if self.positives == 0 { 0.0 } else { point.true_positives as f64 / self.positives as f64 }
+    }
+
+    pub fn false_positive_rate(&self, point: &ThresholdPoint) -> f64 {
+        if self.negatives == 0 { 0.0 } else { point.false_positives as f64 / self.negatives as f64 }
+    }
+
+    pub fn precision(&self, point: &ThresholdPoint) -> f64 {
+        let called = point.true_positives + point.false_positives;
+        if called == 0 { 1.0 } else { point.true_positives as f64 / called as f64 }
+    }
+
+    // (false positive rate, true positive rate) from the origin on
+    pub fn roc_points(&self) -> Vec<(f64, f64)> {
+        std::iter::once((0.0, 0.0))
+            .chain(self.points.iter().map(|p| (self.false_positive_rate(p), self.true_positive_rate(p))))
+            .collect()
+    }
+
+    // (recall, precision), recall rising
+    pub fn precision_recall_points(&self) -> Vec<(f64, f64)> {
+        self.points.iter().map(|p| (self.true_positive_rate(p), self.precision(p))).collect()
+    }
+
+    // Area under the ROC curve, by trapezoids
+    pub fn auc(&self) -> f64 {
+        self.roc_points().windows(2).map(|pair| (pair[1].0 - pair[0].0) * (pair[1].1 + pair[0].1) / 2.0).sum()
+    }
+
+    // Precision averaged over the recall gained at each threshold
+    pub fn average_precision(&self) -> f64 {
+        let mut previous = 0.0;
+        self.precision_recall_points().into_iter()
+            .map(|(recall, precision)| {
+                let gained = recall - previous;
+                previous = recall;
+                gained * precision
+            })
+            .sum()
+    }
+
+    pub fn generate_csv(&self) -> String {
+        let mut csv = String::from("threshold,true_positives,false_positives,true_positive_rate,\
+                                    false_positive_rate,precision,recall\n");
+        let step = self.points.len().div_ceil(MAX_CURVE_POINTS).max(1);
+        let last = self.points.len().saturating_sub(1);
+        let kept = self.points.iter().enumerate().filter(|(i, _)| i % step == 0 || *i == last);
+        for (_, point) in kept {
+            csv.push_str(&format!("{:.6},{},{},{:.6},{:.6},{:.6},{:.6}\n",
+                                  point.threshold, point.true_positives, point.false_positives,
+                                  self.true_positive_rate(point), self.false_positive_rate(point),
+                                  self.precision(point), self.true_positive_rate(point)));
+        }
+        csv
+    }
+
+    pub fn save_csv(&self, filename: &str) -> Result<(), ThelmaError> {
+        std::fs::write(filename, self.generate_csv())?;
+
+        info!("ROC and precision-recall points saved to {} (AUC {:.3}, average precision {:.3})",
+              filename, self.auc(), self.average_precision());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{candidates, record};
+
+    #[test]
+    fn test_curves_follow_the_threshold() {
+        let records = [record("p1", &["s", "a"]), record("p2", &["s", "d"]), record("p3", &["s", "e"])];
+        let results = HashMap::from([
+            // a's two routes add up to 0.6
+            ("p1".to_string(), candidates(&[("a", 0.3), ("b", 0.4), ("a", 0.3)])),
+            ("p2".to_string(), candidates(&[("c", 0.8), ("d", 0.2)])),
+        ]);
+        let curve = RocCurve::compute(&records, &results);
+
+        assert_eq!((curve.positives, curve.negatives), (2, 2));
+        let thresholds: Vec<f64> = curve.points.iter().map(|p| (p.threshold * 10.0).round() / 10.0).collect();
+        assert_eq!(thresholds, vec![0.8, 0.6, 0.4, 0.2]);
+        assert_eq!(curve.roc_points(), vec![(0.0, 0.0), (0.5, 0.0), (0.5, 0.5), (1.0, 0.5), (1.0, 1.0)]);
+        assert_eq!(curve.auc(), 0.25);
+        assert_eq!(curve.precision_recall_points()[1], (0.5, 0.5));
+        assert_eq!(curve.generate_csv().lines().count(), 5);
+
+        assert_eq!(RocCurve::compute(&records, &HashMap::new()).auc(), 0.0);
+    }
+}
@@ -0,0 +1,206 @@
+// Trampoline evaluation: trampoline routing spares light senders pathfinding by handing
+// payments to a trampoline node, whose onion layer names the recipient. That moves trust to
+// the trampoline, so this compares what a malicious node learns when it is the trampoline
+// against what it learns as an ordinary hop on someone else's route.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Write;
+use log::info;
+
+use crate::models::HTLC;
+use crate::simulation::PaymentRecord;
+use crate::surveillance::{rate, SurveillanceOperation};
+use crate::error::ThelmaError;
+
+// What malicious nodes learned from the HTLCs they saw in one role
+#[derive(Debug, Clone, Default)]
+pub struct RoleExposure {
+    pub sightings: usize,
+    // The top recipient candidate was the real recipient
+    pub recipients_identified: usize,
+    pub recipients_in_candidates: usize,
+    // The real sender was among the senders backtracked from the observation
+    pub senders_in_candidates: usize,
+    // The observer was the sender's direct peer
+    pub sender_adjacent: usize,
+    recipient_candidates: usize,
+    sender_candidates: usize,
+}
+
+impl RoleExposure {
+    fn record(&mut self, record: &PaymentRecord, htlc: &HTLC, recipients: &[String], senders: &[String]) {
+        self.sightings += 1;
+        if recipients.first() == Some(&record.receiver) {
+            self.recipients_identified += 1;
+        }
+        let unique: HashSet<&String> = recipients.iter().collect();
+        if unique.contains(&record.receiver) {
+            self.recipients_in_candidates += 1;
+        }
+        self.recipient_candidates += unique.len();
+        if senders.contains(&record.sender) {
+            self.senders_in_candidates += 1;
+        }
+        self.sender_candidates += senders.len();
+        if record.path.get(1) == Some(&htlc.observed_by_node) {
+            self.sender_adjacent += 1;
+        }
+    }
+
+    pub fn identification_rate(&self) -> f64 {
+        rate(self.recipients_identified, self.sightings)
+    }
+
+    pub fn sender_recall(&self) -> f64 {
+        rate(self.senders_in_candidates, self.sightings)
+    }
+
+    pub fn sender_adjacency_rate(&self) -> f64 {
+        rate(self.sender_adjacent, self.sightings)
+    }
+
+    pub fn avg_recipient_set(&self) -> f64 {
+        avg(self.recipient_candidates, self.sightings)
+    }
+
+    pub fn avg_sender_set(&self) -> f64 {
+        avg(self.sender_candidates, self.sightings)
+    }
+}
+
+fn avg(total: usize, count: usize) -> f64 {
+    if count == 0 { 0.0 } else { total as f64 / count as f64 }
+}
+
+// Malicious trampolines against malicious ordinary hops, judged one observation at a time
+#[derive(Debug, Clone, Default)]
+pub struct TrampolineReport {
+    pub trampoline: RoleExposure,
+    pub ordinary: RoleExposure,
+    // Malicious nodes that served as a trampoline at least once
+    pub malicious_trampolines: Vec<String>,
+}
+
+impl TrampolineReport {
+    // Analyze every observation on its own and score it against the payment it belongs to.
+    // Observations whose payment isn't known, like those of unrouted attempts, are skipped.
+    pub fn compute(surveillance: &SurveillanceOperation,
+                   records: &[PaymentRecord],
+                   hop_owners: &HashMap<String, String>) -> Self {
+        let by_hash: HashMap<&str, &PaymentRecord> = records.iter()
+            .filter(|record| !record.cover)
+            .map(|record| (record.payment_hash.as_str(), record))
+            .collect();
+
+        let mut report = TrampolineReport::default();
+        let mut trampolines = HashSet::new();
+        for htlc in surveillance.observation_batches().flatten() {
+            let owner = hop_owners.get(&htlc.payment_hash).map_or(htlc.payment_hash.as_str(), String::as_str);
+            let Some(record) = by_hash.get(owner) else { continue };
+            let recipients: Vec<String> = surveillance.analyze_single_htlc(&htlc).into_iter()
+                .map(|candidate| candidate.node_id)
+                .collect();
+            let senders = surveillance.backtrack_senders(&htlc);
+            if htlc.trampoline_destination().is_some() {
+                trampolines.insert(htlc.observed_by_node.clone());
+                report.trampoline.record(record, &htlc, &recipients, &senders);
+            } else if !htlc.reached_recipient() {
+                report.ordinary.record(record, &htlc, &recipients, &senders);
+            }
+        }
+        report.malicious_trampolines = trampolines.into_iter().collect();
+        report.malicious_trampolines.sort();
+        report
+    }
+
+    pub fn generate_text_report(&self) -> String {
+        let mut report = String::from("## THELMA: Trampoline Evaluation\n\n");
+        if self.malicious_trampolines.is_empty() {
+            report.push_str("No malicious node served as a trampoline.\n\n");
+        } else {
+            report.push_str(&format!("Malicious trampolines: {}\n\n", self.malicious_trampolines.join(", ")));
+        }
+        report.push_str("| Role | Sightings | Recipient identified | Avg recipient candidates | Sender in candidates | Avg sender candidates | Sender's peer |\n");
+        report.push_str("|---|---|---|---|---|---|---|\n");
+        for (role, exposure) in [("Trampoline", &self.trampoline), ("Ordinary hop", &self.ordinary)] {
+            report.push_str(&format!("| {} | {} | {:.1}% | {:.2} | {:.1}% | {:.2} | {:.1}% |\n",
+                                     role,
+                                     exposure.sightings,
+                                     exposure.identification_rate() * 100.0,
+                                     exposure.avg_recipient_set(),
+                                     exposure.sender_recall() * 100.0,
+                                     exposure.avg_sender_set(),
+                                     exposure.sender_adjacency_rate() * 100.0));
+        }
+        if self.trampoline.sightings > 0 && self.ordinary.sightings > 0 {
+            report.push_str(&format!("\nA trampoline identifies the recipient {:+.1} percentage points more often \
+                                      than an ordinary hop.\n",
+                                     (self.trampoline.identification_rate() - self.ordinary.identification_rate()) * 100.0));
+        }
+        report
+    }
+
+    pub fn generate_json_report(&self) -> String {
+        let role = |exposure: &RoleExposure| serde_json::json!({
+            "sightings": exposure.sightings,
+            "recipients_identified": exposure.recipients_identified,
+            "recipients_in_candidates": exposure.recipients_in_candidates,
+            "identification_rate": exposure.identification_rate(),
+            "avg_recipient_candidates": exposure.avg_recipient_set(),
+            "senders_in_candidates": exposure.senders_in_candidates,
+            "avg_sender_candidates": exposure.avg_sender_set(),
+            "sender_adjacent": exposure.sender_adjacent,
+        });
+        serde_json::to_string_pretty(&serde_json::json!({
+            "malicious_trampolines": self.malicious_trampolines,
+            "trampoline": role(&self.trampoline),
+            "ordinary_hop": role(&self.ordinary),
+        })).unwrap_or_else(|_| "Error generating JSON report".to_string())
+    }
+
+    pub fn save_report_to_file(&self, filename: &str) -> Result<(), ThelmaError> {
+        let mut file = File::create(filename)?;
+        file.write_all(self.generate_text_report().as_bytes())?;
+
+        info!("Trampoline evaluation saved to {}", filename);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, RwLock};
+    use crate::models::{Channel, HopPayload, LightningNetworkMap, Node};
+    use crate::surveillance::SurveillanceConfig;
+    use crate::test_fixtures::record;
+
+    #[test]
+    fn test_trampoline_learns_the_recipient() {
+        // a - t - m - b, and a - c - t: t routes a's payments on, m forwards for someone else
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["a", "c", "t", "m", "b"] {
+            network.add_node(Node::new(key, key, 40));
+        }
+        for (id, x, y) in [("at", "a", "t"), ("tm", "t", "m"), ("mb", "m", "b"), ("ac", "a", "c"), ("ct", "c", "t")] {
+            network.add_channel(Channel::new(id, x, y, 10_000_000));
+        }
+        let network = Arc::new(RwLock::new(network));
+        let mut operation = SurveillanceOperation::new(network,
+            SurveillanceConfig::observing(vec!["t".to_string(), "m".to_string()])).unwrap();
+        operation.record_htlc_observation(HTLC::new("h1", 700_120, 50_000, 700_000, "t")
+            .with_onion(Some("at"), HopPayload::forward("tm", 50_000, 700_080).via_trampoline("b"))).unwrap();
+        operation.record_htlc_observation(HTLC::new("h2", 700_080, 50_000, 700_000, "m")
+            .with_onion(Some("tm"), HopPayload::forward("mb", 50_000, 700_040))).unwrap();
+
+        let records = vec![record("h1", &["a", "t", "m", "b"]), record("h2", &["c", "t", "m", "b"])];
+        let report = TrampolineReport::compute(&operation, &records, &HashMap::new());
+
+        assert_eq!(report.malicious_trampolines, vec!["t"]);
+        assert_eq!((report.trampoline.sightings, report.trampoline.recipients_identified), (1, 1));
+        assert_eq!(report.trampoline.sender_adjacent, 1);
+        assert_eq!(report.ordinary.sightings, 1);
+        assert_eq!(report.ordinary.sender_adjacent, 0);
+    }
+}
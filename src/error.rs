@@ -0,0 +1,81 @@
+// Errors THELMA can fail with
+
+use std::io;
+use std::sync::{Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use thiserror::Error;
+
+// Every failure surfaced by the simulator and the analysis, grouped by cause so callers
+// can react to them and the binary can say what went wrong
+#[derive(Debug, Error)]
+pub enum ThelmaError {
+    // The network graph lacks what was asked of it or couldn't be loaded
+    #[error("graph error: {0}")]
+    Graph(String),
+    // A payment couldn't be set up between the requested endpoints
+    #[error("routing error: {0}")]
+    Routing(String),
+    // Settings or command-line arguments that can't be used
+    #[error("invalid configuration: {0}")]
+    Config(String),
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    // Graph snapshots and spilled observations that don't parse
+    #[error("malformed JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    // A run drifted from the golden report it was checked against
+    #[error("regression: {0}")]
+    Regression(String),
+    // A report template that doesn't parse or fails to render
+    #[error("report template: {0}")]
+    Template(#[from] minijinja::Error),
+    // A simulation task panicked or was cancelled
+    #[error("simulation task failed: {0}")]
+    Task(#[from] tokio::task::JoinError),
+    // The run registry's database couldn't be read or written
+    #[cfg(feature = "native")]
+    #[error("run registry: {0}")]
+    Registry(#[from] rusqlite::Error),
+}
+
+// Shared state stays consistent between operations, so a panic in one task shouldn't
+// bring down every other holder of the lock. These hand out the data regardless.
+pub fn read_lock<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn write_lock<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn lock_mutex<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::simulation::topology_from_name;
+
+    #[test]
+    fn test_errors_are_matchable_and_locks_survive_panics() {
+        assert!(matches!(topology_from_name("hypercube", None), Err(ThelmaError::Config(_))));
+
+        let error = topology_from_name("imported", Some("/nonexistent/graph.json")).unwrap()
+            .generate(&mut crate::models::LightningNetworkMap::new(700000), 0, &mut rand::SeedableRng::seed_from_u64(0))
+            .unwrap_err();
+        assert!(matches!(error, ThelmaError::Graph(_)));
+        assert!(error.to_string().contains("/nonexistent/graph.json"));
+
+        // A holder panicking doesn't take the shared state down with it
+        let shared = Arc::new(RwLock::new(1));
+        let poisoner = shared.clone();
+        let _ = std::thread::spawn(move || {
+            let _guard = poisoner.write().unwrap();
+            panic!("worker failed");
+        }).join();
+        assert!(shared.is_poisoned());
+        *write_lock(&shared) += 1;
+        assert_eq!(*read_lock(&shared), 2);
+    }
+}
@@ -0,0 +1,202 @@
+// C API for embedding the analyzer in non-Rust Lightning tooling; see include/thelma.h.
+//
+// Callers build a graph on an opaque handle, submit the HTLCs their nodes saw and ask for
+// the analysis as a JSON report. Every pointer argument must be either null or valid for
+// the duration of the call, strings must be NUL-terminated UTF-8, and a handle must not be
+// used from two threads at once. Functions returning int give 0 on success and -1 on
+// failure, with the reason available from `thelma_last_error` on the same thread.
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char, c_int};
+use std::ptr;
+use std::sync::{Arc, RwLock};
+
+use crate::models::{Channel, HTLC, LightningNetworkMap, Node};
+use crate::surveillance::{SurveillanceConfig, SurveillanceOperation};
+use crate::error::{ThelmaError, read_lock, write_lock};
+
+thread_local! {
+    // Message of the last failed call on this thread
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+// A graph and everything observed on it
+pub struct ThelmaAnalyzer {
+    network: Arc<RwLock<LightningNetworkMap>>,
+    operation: SurveillanceOperation,
+}
+
+fn set_last_error(error: &ThelmaError) {
+    // Interior NULs can't cross into C, so they are dropped from the message
+    let message = CString::new(error.to_string().replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+// Turn the outcome of a call into its C status code
+fn status(result: Result<(), ThelmaError>) -> c_int {
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            set_last_error(&e);
+            -1
+        }
+    }
+}
+
+unsafe fn string_arg<'a>(value: *const c_char, name: &str) -> Result<&'a str, ThelmaError> {
+    if value.is_null() {
+        return Err(ThelmaError::Config(format!("{} is null", name)));
+    }
+    CStr::from_ptr(value).to_str()
+        .map_err(|_| ThelmaError::Config(format!("{} is not valid UTF-8", name)))
+}
+
+unsafe fn analyzer_arg<'a>(analyzer: *mut ThelmaAnalyzer) -> Result<&'a mut ThelmaAnalyzer, ThelmaError> {
+    analyzer.as_mut().ok_or_else(|| ThelmaError::Config("analyzer is null".to_string()))
+}
+
+// Create an analyzer for an empty graph at the given block height. Returns null on failure.
+#[no_mangle]
+pub extern "C" fn thelma_analyzer_new(block_height: u32) -> *mut ThelmaAnalyzer {
+    let network = Arc::new(RwLock::new(LightningNetworkMap::new(block_height)));
+    // Observing nodes join the adversary as their observations arrive
+    match SurveillanceOperation::new(network.clone(), SurveillanceConfig::observing(Vec::new())) {
+        Ok(operation) => Box::into_raw(Box::new(ThelmaAnalyzer { network, operation })),
+        Err(e) => {
+            set_last_error(&e);
+            ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn thelma_analyzer_free(analyzer: *mut ThelmaAnalyzer) {
+    if !analyzer.is_null() {
+        drop(Box::from_raw(analyzer));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn thelma_add_node(analyzer: *mut ThelmaAnalyzer,
+                                         pub_key: *const c_char,
+                                         alias: *const c_char,
+                                         cltv_expiry_delta: u32) -> c_int {
+    status((|| {
+        let analyzer = analyzer_arg(analyzer)?;
+        let node = Node::new(string_arg(pub_key, "pub_key")?, string_arg(alias, "alias")?, cltv_expiry_delta);
+        write_lock(&analyzer.network).add_node(node);
+        Ok(())
+    })())
+}
+
+// Both ends must have been added first. Capacity is in satoshis.
+#[no_mangle]
+pub unsafe extern "C" fn thelma_add_channel(analyzer: *mut ThelmaAnalyzer,
+                                            channel_id: *const c_char,
+                                            node1: *const c_char,
+                                            node2: *const c_char,
+                                            capacity: u64) -> c_int {
+    status((|| {
+        let analyzer = analyzer_arg(analyzer)?;
+        let (node1, node2) = (string_arg(node1, "node1")?, string_arg(node2, "node2")?);
+        let mut network = write_lock(&analyzer.network);
+        if let Some(unknown) = [node1, node2].into_iter().find(|node| !network.nodes.contains_key(*node)) {
+            return Err(ThelmaError::Graph(format!("channel endpoint {} is not in the graph", unknown)));
+        }
+        network.add_channel(Channel::new(string_arg(channel_id, "channel_id")?, node1, node2, capacity));
+        Ok(())
+    })())
+}
+
+// Record an HTLC one of the caller's nodes forwarded. The amount is in millisatoshis.
+#[no_mangle]
+pub unsafe extern "C" fn thelma_submit_observation(analyzer: *mut ThelmaAnalyzer,
+                                                   payment_hash: *const c_char,
+                                                   cltv_expiry: u32,
+                                                   amount: u64,
+                                                   observed_at_block: u32,
+                                                   observed_by_node: *const c_char) -> c_int {
+    status((|| {
+        let analyzer = analyzer_arg(analyzer)?;
+        let observer = string_arg(observed_by_node, "observed_by_node")?;
+        if !read_lock(&analyzer.network).nodes.contains_key(observer) {
+            return Err(ThelmaError::Graph(format!("observing node {} is not in the graph", observer)));
+        }
+        let htlc = HTLC::new(string_arg(payment_hash, "payment_hash")?, cltv_expiry, amount, observed_at_block, observer);
+        analyzer.operation.register_malicious_node(observer);
+        analyzer.operation.record_htlc_observation(htlc)
+    })())
+}
+
+// Analyze everything submitted so far and return the JSON report, the same one the
+// command-line tool writes to thelma_report.json. Free it with `thelma_string_free`.
+// Returns null on failure.
+#[no_mangle]
+pub unsafe extern "C" fn thelma_run_analysis(analyzer: *mut ThelmaAnalyzer) -> *mut c_char {
+    let report = analyzer_arg(analyzer).and_then(|analyzer| {
+        CString::new(analyzer.operation.generate_json_report(&analyzer.operation.run_analysis()))
+            .map_err(|e| ThelmaError::Config(e.to_string()))
+    });
+    match report {
+        Ok(report) => report.into_raw(),
+        Err(e) => {
+            set_last_error(&e);
+            ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn thelma_string_free(value: *mut c_char) {
+    if !value.is_null() {
+        drop(CString::from_raw(value));
+    }
+}
+
+// Why the last failed call on this thread failed, or null if none has. The message stays
+// valid until the next failure on the same thread.
+#[no_mangle]
+pub extern "C" fn thelma_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c(value: &str) -> CString {
+        CString::new(value).unwrap()
+    }
+
+    #[test]
+    fn test_c_api_analyzes_submitted_observations() {
+        unsafe {
+            let analyzer = thelma_analyzer_new(780000);
+            for key in ["a", "b", "c", "d"] {
+                assert_eq!(thelma_add_node(analyzer, c(key).as_ptr(), c(key).as_ptr(), 40), 0);
+            }
+            for (id, node1, node2) in [("ab", "a", "b"), ("bc", "b", "c"), ("cd", "c", "d")] {
+                assert_eq!(thelma_add_channel(analyzer, c(id).as_ptr(), c(node1).as_ptr(), c(node2).as_ptr(),
+                                              1_000_000), 0);
+            }
+
+            // Unknown nodes are refused with a readable reason
+            assert_eq!(thelma_add_channel(analyzer, c("ax").as_ptr(), c("a").as_ptr(), c("x").as_ptr(), 1), -1);
+            let error = CStr::from_ptr(thelma_last_error()).to_str().unwrap();
+            assert!(error.contains("channel endpoint x"));
+            assert_eq!(thelma_submit_observation(analyzer, ptr::null(), 780100, 5000, 780000, c("b").as_ptr()), -1);
+
+            assert_eq!(thelma_submit_observation(analyzer, c("p1").as_ptr(), 780120, 5000, 780000, c("b").as_ptr()), 0);
+            let report = thelma_run_analysis(analyzer);
+            assert!(!report.is_null());
+            let json: serde_json::Value = serde_json::from_str(CStr::from_ptr(report).to_str().unwrap()).unwrap();
+            assert_eq!(json["total_payments"], 1);
+            assert!(json["payments"]["p1"]["recipient_count"].as_u64().unwrap() > 0);
+
+            thelma_string_free(report);
+            thelma_analyzer_free(analyzer);
+            assert!(thelma_run_analysis(ptr::null_mut()).is_null());
+        }
+    }
+}
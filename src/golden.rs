@@ -0,0 +1,176 @@
+// `thelma golden`: a fixed seeded scenario whose report is checked against a stored golden
+// file, so refactors of the scorer, the analyzer or routing show up as behavior shifts. Counts
+// and node ids have to match exactly; confidences and averages within a tolerance.
+
+use std::sync::{Arc, Mutex, RwLock};
+use serde_json::Value;
+
+use crate::models::LightningNetworkMap;
+use crate::defense::ScenarioMetrics;
+use crate::simulation::{NetworkGenerator, PaymentSimulator, SimulatorConfig, topology_from_name};
+use crate::surveillance::{RandomPlacement, SurveillanceConfig, SurveillanceOperation};
+use crate::surveillance::accuracy::rank_candidates;
+use crate::error::{ThelmaError, lock_mutex, read_lock};
+
+// How far a float may drift from the golden value before it counts as a change
+pub const DEFAULT_GOLDEN_TOLERANCE: f64 = 1e-3;
+// Candidates kept per payment, best first
+const GOLDEN_TOP_CANDIDATES: usize = 5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GoldenScenario {
+    pub topology: String,
+    pub node_count: usize,
+    pub payment_count: usize,
+    pub malicious_count: usize,
+    pub seed: u64,
+}
+
+impl Default for GoldenScenario {
+    fn default() -> Self {
+        GoldenScenario {
+            topology: "scale-free".to_string(),
+            node_count: 30,
+            payment_count: 60,
+            malicious_count: 4,
+            seed: 42,
+        }
+    }
+}
+
+impl GoldenScenario {
+    // Simulate and analyze the scenario, and report what the attacker concluded about every
+    // payment. Everything is sorted, so the same code gives the same report.
+    pub async fn run(&self) -> Result<Value, ThelmaError> {
+        let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(780000)));
+        let mut topology = topology_from_name(&self.topology, None)?;
+        NetworkGenerator::seeded(self.seed).create_network(network_map.clone(), topology.as_mut(), self.node_count)?;
+
+        let config = SurveillanceConfig::with_strategy(Box::new(RandomPlacement::seeded(self.seed)), self.malicious_count);
+        let operation = SurveillanceOperation::new(network_map.clone(), config)?;
+        let mut malicious_nodes = operation.get_malicious_nodes().to_vec();
+        let surveillance = Arc::new(Mutex::new(operation));
+
+        let config = SimulatorConfig::new().malicious_nodes(malicious_nodes.clone()).seed(self.seed);
+        let mut simulator = PaymentSimulator::new(network_map.clone(), config);
+        let observer = simulator.register_observer(surveillance.clone());
+        simulator.simulate_payments(self.payment_count).await?;
+        simulator.close_events();
+        observer.await?;
+
+        let results = lock_mutex(&surveillance).run_analysis();
+        let metrics = ScenarioMetrics::compute("golden", simulator.payment_records(), &results, &read_lock(&network_map));
+        let mut records: Vec<_> = simulator.payment_records().iter().filter(|r| !r.cover).collect();
+        records.sort_by(|a, b| a.payment_hash.cmp(&b.payment_hash));
+        let payments: Vec<Value> = records.iter()
+            .map(|record| {
+                let mut ranked = results.get(&record.payment_hash).map(|c| rank_candidates(c)).unwrap_or_default();
+                ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+                let candidates: Vec<Value> = ranked.iter()
+                    .take(GOLDEN_TOP_CANDIDATES)
+                    .map(|(node, confidence)| serde_json::json!({ "node_id": node, "confidence": confidence }))
+                    .collect();
+                serde_json::json!({
+                    "payment_hash": record.payment_hash,
+                    "sender": record.sender,
+                    "receiver": record.receiver,
+                    "path": record.path,
+                    "observed": record.observed,
+                    "candidates": candidates,
+                })
+            })
+            .collect();
+        malicious_nodes.sort();
+
+        Ok(serde_json::json!({
+            "scenario": {
+                "topology": self.topology,
+                "nodes": self.node_count,
+                "payments": self.payment_count,
+                "malicious": self.malicious_count,
+                "seed": self.seed,
+            },
+            "malicious_nodes": malicious_nodes,
+            "metrics": {
+                "payments": metrics.payments,
+                "observed_payments": metrics.observed_payments,
+                "recipients_identified": metrics.recipients_identified,
+                "recipients_in_candidates": metrics.recipients_in_candidates,
+                "avg_anonymity_set": metrics.avg_anonymity_set,
+                "avg_hops": metrics.avg_hops,
+                "avg_fee_msat": metrics.avg_fee_msat,
+            },
+            "payments": payments,
+        }))
+    }
+}
+
+// Where a report differs from the golden one, as `path: expected ..., got ...` lines. Floats
+// within `tolerance` of each other count as equal.
+pub fn compare_reports(expected: &Value, actual: &Value, tolerance: f64) -> Vec<String> {
+    let mut differences = Vec::new();
+    compare_at("$", expected, actual, tolerance, &mut differences);
+    differences
+}
+
+fn compare_at(path: &str, expected: &Value, actual: &Value, tolerance: f64, differences: &mut Vec<String>) {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            for (key, value) in expected {
+                match actual.get(key) {
+                    Some(other) => compare_at(&format!("{}.{}", path, key), value, other, tolerance, differences),
+                    None => differences.push(format!("{}.{}: missing", path, key)),
+                }
+            }
+            for key in actual.keys().filter(|key| !expected.contains_key(*key)) {
+                differences.push(format!("{}.{}: not in the golden report", path, key));
+            }
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            if expected.len() != actual.len() {
+                differences.push(format!("{}: expected {} entries, got {}", path, expected.len(), actual.len()));
+            }
+            for (i, (value, other)) in expected.iter().zip(actual).enumerate() {
+                compare_at(&format!("{}[{}]", path, i), value, other, tolerance, differences);
+            }
+        }
+        (Value::Number(a), Value::Number(b)) if a.is_f64() || b.is_f64() => {
+            let (a, b) = (a.as_f64().unwrap_or(f64::NAN), b.as_f64().unwrap_or(f64::NAN));
+            if (a - b).abs() > tolerance || a.is_nan() != b.is_nan() {
+                differences.push(format!("{}: expected {}, got {}", path, a, b));
+            }
+        }
+        _ if expected != actual => differences.push(format!("{}: expected {}, got {}", path, expected, actual)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_comparison_tolerates_float_noise_only() {
+        let golden = serde_json::json!({"metrics": {"payments": 4, "avg_hops": 2.5}, "candidates": [{"node_id": "a", "confidence": 0.75}]});
+        let noisy = serde_json::json!({"metrics": {"payments": 4, "avg_hops": 2.5000001}, "candidates": [{"node_id": "a", "confidence": 0.7504}]});
+        assert!(compare_reports(&golden, &noisy, DEFAULT_GOLDEN_TOLERANCE).is_empty());
+
+        let shifted = serde_json::json!({"metrics": {"payments": 5, "avg_hops": 2.5}, "candidates": [{"node_id": "b", "confidence": 0.9}], "extra": 1});
+        let differences = compare_reports(&golden, &shifted, DEFAULT_GOLDEN_TOLERANCE);
+        assert_eq!(differences, vec![
+            "$.candidates[0].confidence: expected 0.75, got 0.9".to_string(),
+            "$.candidates[0].node_id: expected \"a\", got \"b\"".to_string(),
+            "$.metrics.payments: expected 4, got 5".to_string(),
+            "$.extra: not in the golden report".to_string(),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_golden_scenario_matches_the_stored_report() {
+        let golden: Value = serde_json::from_str(include_str!("../tests/golden/report.json")).unwrap();
+        let report = GoldenScenario::default().run().await.unwrap();
+        let differences = compare_reports(&golden, &report, DEFAULT_GOLDEN_TOLERANCE);
+        assert!(differences.is_empty(), "behavior shifted, rerun `thelma golden tests/golden/report.json --update` \
+                                         if intended:\n{}", differences.join("\n"));
+    }
+}
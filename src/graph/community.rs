@@ -0,0 +1,190 @@
+// Community detection over the Lightning Network graph
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::models::LightningNetworkMap;
+
+// Upper bound on aggregation levels in case modularity keeps creeping up
+const MAX_LOUVAIN_LEVELS: usize = 20;
+// Minimum modularity gain for a node to change community
+const MIN_GAIN: f64 = 1e-12;
+
+// Assignment of every node to a community
+#[derive(Debug, Clone, Default)]
+pub struct Communities {
+    assignments: HashMap<String, usize>,
+    members: BTreeMap<usize, Vec<String>>,
+}
+
+impl Communities {
+    // Louvain modularity optimization. Nodes are visited in pubkey order, so the
+    // result is deterministic for a given graph.
+    pub fn detect(network: &LightningNetworkMap) -> Self {
+        let mut nodes: Vec<&String> = network.nodes.keys().collect();
+        nodes.sort();
+        let index: HashMap<&String, usize> = nodes.iter().enumerate().map(|(i, k)| (*k, i)).collect();
+
+        // Weighted adjacency; every channel adds one unit of weight in each direction
+        let mut graph: Vec<HashMap<usize, f64>> = vec![HashMap::new(); nodes.len()];
+        for channel in &network.channels {
+            if let (Some(&a), Some(&b)) = (index.get(&channel.node1), index.get(&channel.node2)) {
+                if a != b {
+                    *graph[a].entry(b).or_insert(0.0) += 1.0;
+                    *graph[b].entry(a).or_insert(0.0) += 1.0;
+                }
+            }
+        }
+
+        // membership[i] = community of original node i at the current level
+        let mut membership: Vec<usize> = (0..nodes.len()).collect();
+
+        for _ in 0..MAX_LOUVAIN_LEVELS {
+            let (level_communities, moved) = local_moving(&graph);
+            if !moved {
+                break;
+            }
+
+            let (renumbered, count) = renumber(&level_communities);
+            for community in membership.iter_mut() {
+                *community = renumbered[*community];
+            }
+            graph = aggregate(&graph, &renumbered, count);
+        }
+
+        let (membership, _) = renumber(&membership);
+
+        let mut communities = Communities::default();
+        for (i, node) in nodes.iter().enumerate() {
+            communities.assignments.insert((*node).clone(), membership[i]);
+            communities.members.entry(membership[i]).or_default().push((*node).clone());
+        }
+
+        communities
+    }
+
+    pub fn community_of(&self, node_id: &str) -> Option<usize> {
+        self.assignments.get(node_id).copied()
+    }
+
+    pub fn members(&self, community_id: usize) -> &[String] {
+        self.members.get(&community_id).map(|m| m.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn count(&self) -> usize {
+        self.members.len()
+    }
+}
+
+// One Louvain pass: greedily move nodes to the neighboring community with the best gain
+fn local_moving(graph: &[HashMap<usize, f64>]) -> (Vec<usize>, bool) {
+    let n = graph.len();
+    let degree: Vec<f64> = graph.iter().map(|edges| edges.values().sum()).collect();
+    let total_weight: f64 = degree.iter().sum();
+
+    let mut community: Vec<usize> = (0..n).collect();
+    let mut community_total = degree.clone();
+    let mut moved_any = false;
+
+    if total_weight == 0.0 {
+        return (community, false);
+    }
+
+    loop {
+        let mut moved = false;
+
+        for node in 0..n {
+            let current = community[node];
+            let k = degree[node];
+
+            // Sorted so that ties resolve towards the smallest community id
+            let mut links: BTreeMap<usize, f64> = BTreeMap::new();
+            for (&neighbor, &weight) in &graph[node] {
+                if neighbor != node {
+                    *links.entry(community[neighbor]).or_insert(0.0) += weight;
+                }
+            }
+
+            community_total[current] -= k;
+
+            let gain = |c: usize, w: f64| w - community_total[c] * k / total_weight;
+            let mut best = current;
+            let mut best_gain = gain(current, links.get(&current).copied().unwrap_or(0.0));
+
+            for (&c, &w) in &links {
+                let g = gain(c, w);
+                if g > best_gain + MIN_GAIN {
+                    best = c;
+                    best_gain = g;
+                }
+            }
+
+            community_total[best] += k;
+            if best != current {
+                community[node] = best;
+                moved = true;
+                moved_any = true;
+            }
+        }
+
+        if !moved {
+            break;
+        }
+    }
+
+    (community, moved_any)
+}
+
+// Map arbitrary community ids onto 0..count in order of first appearance
+fn renumber(communities: &[usize]) -> (Vec<usize>, usize) {
+    let mut ids: HashMap<usize, usize> = HashMap::new();
+    let renumbered = communities.iter()
+        .map(|c| {
+            let next = ids.len();
+            *ids.entry(*c).or_insert(next)
+        })
+        .collect();
+    (renumbered, ids.len())
+}
+
+// Collapse each community into a single node, keeping internal weight as a self-loop
+fn aggregate(graph: &[HashMap<usize, f64>], community: &[usize], count: usize) -> Vec<HashMap<usize, f64>> {
+    let mut aggregated: Vec<HashMap<usize, f64>> = vec![HashMap::new(); count];
+    for (node, edges) in graph.iter().enumerate() {
+        for (&neighbor, &weight) in edges {
+            *aggregated[community[node]].entry(community[neighbor]).or_insert(0.0) += weight;
+        }
+    }
+    aggregated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Node, Channel};
+
+    #[test]
+    fn test_two_cliques_bridged() {
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["a1", "a2", "a3", "a4", "b1", "b2", "b3", "b4"] {
+            network.add_node(Node::new(key, key, 40));
+        }
+
+        for group in [["a1", "a2", "a3", "a4"], ["b1", "b2", "b3", "b4"]] {
+            for i in 0..group.len() {
+                for j in (i + 1)..group.len() {
+                    network.add_channel(Channel::new(&format!("{}-{}", group[i], group[j]),
+                                                     group[i], group[j], 1_000_000));
+                }
+            }
+        }
+        network.add_channel(Channel::new("bridge", "a1", "b1", 1_000_000));
+
+        let communities = Communities::detect(&network);
+
+        assert_eq!(communities.count(), 2);
+        assert_eq!(communities.community_of("a1"), communities.community_of("a4"));
+        assert_eq!(communities.community_of("b1"), communities.community_of("b3"));
+        assert_ne!(communities.community_of("a1"), communities.community_of("b1"));
+        assert_eq!(communities.members(communities.community_of("a3").unwrap()).len(), 4);
+    }
+}
@@ -0,0 +1,156 @@
+// Expected observation coverage of an adversary, computed from path probabilities instead
+// of simulated payments
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::Write;
+use log::info;
+use rayon::prelude::*;
+
+use crate::models::LightningNetworkMap;
+use crate::error::ThelmaError;
+
+// Share of payments an adversary can expect to observe when every ordered pair of connected
+// nodes is equally likely to pay and each payment takes one of the fewest-hop routes at
+// random. A payment is observed when any node on its route, ends included, is malicious,
+// as in the simulator. Channel capacities and fees are ignored.
+#[derive(Debug, Clone)]
+pub struct CoverageEstimate {
+    pub malicious_nodes: Vec<String>,
+    // Ordered sender/receiver pairs with a route between them
+    pub pairs: usize,
+    // Expected number of those pairs whose payment is observed
+    pub expected_observed: f64,
+}
+
+impl CoverageEstimate {
+    pub fn compute(network: &LightningNetworkMap, malicious_nodes: &[String]) -> Self {
+        let mut nodes: Vec<&String> = network.nodes.keys().collect();
+        nodes.sort();
+        let index: HashMap<&String, usize> = nodes.iter().enumerate().map(|(i, k)| (*k, i)).collect();
+        // The same routable, one-edge-per-peer view the centrality scores use
+        let adjacency: Vec<Vec<usize>> = nodes.iter()
+            .map(|key| network.routing_peers(key).into_iter().filter_map(|nb| index.get(nb).copied()).collect())
+            .collect();
+        let malicious: HashSet<usize> = malicious_nodes.iter().filter_map(|key| index.get(key).copied()).collect();
+
+        // Every sender is independent, so fan out with rayon as the centrality search does
+        let (pairs, expected_observed) = (0..nodes.len()).into_par_iter()
+            .map(|source| single_source_coverage(&adjacency, &malicious, source))
+            .reduce(|| (0, 0.0), |(p1, o1), (p2, o2)| (p1 + p2, o1 + o2));
+
+        CoverageEstimate { malicious_nodes: malicious_nodes.to_vec(), pairs, expected_observed }
+    }
+
+    pub fn coverage(&self) -> f64 {
+        if self.pairs == 0 {
+            return 0.0;
+        }
+        self.expected_observed / self.pairs as f64
+    }
+
+    // Generate the markdown coverage report
+    pub fn generate_text_report(&self) -> String {
+        let mut report = String::from("## THELMA: Expected Coverage\n\n");
+        report.push_str(&format!("Adversary: {}\n", self.malicious_nodes.join(", ")));
+        report.push_str(&format!("Connected sender/receiver pairs: {}\n", self.pairs));
+        report.push_str(&format!("Expected observed payments: {:.1}%\n", self.coverage() * 100.0));
+        report.push_str("\nAssumes uniformly random senders and receivers, each paying over a random fewest-hop route.\n");
+        report
+    }
+
+    // Generate a JSON version of the estimate
+    pub fn generate_json_report(&self) -> String {
+        serde_json::to_string_pretty(&serde_json::json!({
+            "malicious_nodes": self.malicious_nodes,
+            "pairs": self.pairs,
+            "expected_observed": self.expected_observed,
+            "coverage": self.coverage(),
+        })).unwrap_or_else(|_| "Error generating JSON report".to_string())
+    }
+
+    // Save the coverage report to file
+    pub fn save_report_to_file(&self, filename: &str) -> Result<(), ThelmaError> {
+        let mut file = File::create(filename)?;
+        file.write_all(self.generate_text_report().as_bytes())?;
+
+        info!("Coverage estimate saved to {}", filename);
+        Ok(())
+    }
+}
+
+// BFS from one sender, counting fewest-hop routes to every receiver and how many of them
+// pass no malicious node. Returns the receivers reached and the expected observed payments.
+fn single_source_coverage(adjacency: &[Vec<usize>], malicious: &HashSet<usize>, source: usize) -> (usize, f64) {
+    let n = adjacency.len();
+    let mut sigma = vec![0.0f64; n];
+    // Routes that stay clear of the adversary up to, but not including, the node itself
+    let mut unseen = vec![0.0f64; n];
+    let mut distance = vec![-1i64; n];
+    let mut queue = VecDeque::new();
+
+    sigma[source] = 1.0;
+    unseen[source] = 1.0;
+    distance[source] = 0;
+    queue.push_back(source);
+
+    // Nodes leave the queue in distance order, so their counts are final by then
+    let mut order = Vec::with_capacity(n);
+    while let Some(v) = queue.pop_front() {
+        order.push(v);
+        let passes = if malicious.contains(&v) { 0.0 } else { unseen[v] };
+        for &w in &adjacency[v] {
+            if distance[w] < 0 {
+                distance[w] = distance[v] + 1;
+                queue.push_back(w);
+            }
+            if distance[w] == distance[v] + 1 {
+                sigma[w] += sigma[v];
+                unseen[w] += passes;
+            }
+        }
+    }
+
+    // A malicious sender sees all of its own payments
+    let receivers = order.iter().skip(1);
+    let observed = if malicious.contains(&source) {
+        receivers.count() as f64
+    } else {
+        receivers.map(|&receiver| {
+            if malicious.contains(&receiver) { 1.0 } else { 1.0 - unseen[receiver] / sigma[receiver] }
+        }).sum()
+    };
+
+    (order.len() - 1, observed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Channel, Node};
+
+    #[test]
+    fn test_coverage_splits_between_equal_routes() {
+        // s and t are joined through x and through y, so half of their payments cross x
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["s", "t", "x", "y"] {
+            network.add_node(Node::new(key, key, 40));
+        }
+        for (node1, node2) in [("s", "x"), ("x", "t"), ("s", "y"), ("y", "t")] {
+            network.add_channel(Channel::new(&format!("{}{}", node1, node2), node1, node2, 1_000_000));
+        }
+
+        // x sends or receives 6 of the 12 payments, and sees half of the 2 between s and t
+        let estimate = CoverageEstimate::compute(&network, &["x".to_string()]);
+        assert_eq!(estimate.pairs, 12);
+        assert!((estimate.expected_observed - 7.0).abs() < 1e-9);
+
+        // Together x and y sit on every route between s and t
+        let both = CoverageEstimate::compute(&network, &["x".to_string(), "y".to_string()]);
+        assert!((both.coverage() - 1.0).abs() < 1e-9);
+        // The square is symmetric, so s sees as much as x does
+        let sender = CoverageEstimate::compute(&network, &["s".to_string()]);
+        assert!((sender.coverage() - estimate.coverage()).abs() < 1e-9);
+        assert_eq!(CoverageEstimate::compute(&network, &[]).coverage(), 0.0);
+    }
+}
@@ -0,0 +1,231 @@
+// Centrality measures over the Lightning Network graph
+
+use std::collections::{HashMap, VecDeque};
+use rayon::prelude::*;
+
+use crate::models::LightningNetworkMap;
+use crate::simulation::PaymentRecord;
+
+// Which centrality measure to rank nodes by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CentralityMeasure {
+    Degree,
+    Betweenness,
+    Closeness,
+}
+
+impl CentralityMeasure {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "degree" => Some(CentralityMeasure::Degree),
+            "betweenness" => Some(CentralityMeasure::Betweenness),
+            "closeness" => Some(CentralityMeasure::Closeness),
+            _ => None,
+        }
+    }
+}
+
+// Normalized centrality scores for every node in the network
+#[derive(Debug, Clone, Default)]
+pub struct CentralityScores {
+    pub degree: HashMap<String, f64>,
+    pub betweenness: HashMap<String, f64>,
+    pub closeness: HashMap<String, f64>,
+}
+
+impl CentralityScores {
+    pub fn compute(network: &LightningNetworkMap) -> Self {
+        let mut nodes: Vec<&String> = network.nodes.keys().collect();
+        nodes.sort();
+        let n = nodes.len();
+
+        let index: HashMap<&String, usize> = nodes.iter().enumerate().map(|(i, k)| (*k, i)).collect();
+
+        // Index-based adjacency so the per-source searches don't hash strings. Payments pick
+        // peers, not channels, so parallel channels count once and idle ones not at all.
+        let adjacency: Vec<Vec<usize>> = nodes.iter()
+            .map(|key| network.routing_peers(key).into_iter().filter_map(|nb| index.get(nb).copied()).collect())
+            .collect();
+
+        let norm = if n > 1 { (n - 1) as f64 } else { 1.0 };
+
+        // Brandes' algorithm: each source contributes independently, so fan out with rayon
+        let (betweenness, closeness) = (0..n).into_par_iter()
+            .map(|source| single_source_scores(&adjacency, source))
+            .fold(|| (vec![0.0; n], vec![0.0; n]), |(mut between, mut close), (dependency, source, closeness)| {
+                for (total, d) in between.iter_mut().zip(dependency) {
+                    *total += d;
+                }
+                close[source] = closeness;
+                (between, close)
+            })
+            .reduce(|| (vec![0.0; n], vec![0.0; n]), |(mut b1, mut c1), (b2, c2)| {
+                for (a, b) in b1.iter_mut().zip(b2) {
+                    *a += b;
+                }
+                for (a, b) in c1.iter_mut().zip(c2) {
+                    *a += b;
+                }
+                (b1, c1)
+            });
+
+        // Undirected graph: every pair was counted from both ends
+        let pair_norm = if n > 2 { ((n - 1) * (n - 2)) as f64 } else { 1.0 };
+
+        let mut scores = CentralityScores::default();
+        for (i, key) in nodes.iter().enumerate() {
+            scores.degree.insert((*key).clone(), adjacency[i].len() as f64 / norm);
+            scores.betweenness.insert((*key).clone(), betweenness[i] / pair_norm);
+            scores.closeness.insert((*key).clone(), closeness[i]);
+        }
+
+        scores
+    }
+
+    pub fn scores(&self, measure: CentralityMeasure) -> &HashMap<String, f64> {
+        match measure {
+            CentralityMeasure::Degree => &self.degree,
+            CentralityMeasure::Betweenness => &self.betweenness,
+            CentralityMeasure::Closeness => &self.closeness,
+        }
+    }
+
+    // All nodes ordered from most to least central (ties broken by pubkey)
+    pub fn ranked(&self, measure: CentralityMeasure) -> Vec<(String, f64)> {
+        let mut ranked: Vec<(String, f64)> = self.scores(measure).iter()
+            .map(|(k, v)| (k.clone(), *v))
+            .collect();
+        ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then(a.0.cmp(&b.0)));
+        ranked
+    }
+
+    // Report the honest nodes expected to see the most traffic, next to what they actually forwarded
+    pub fn generate_traffic_report(&self,
+                                   network: &LightningNetworkMap,
+                                   malicious_nodes: &[String],
+                                   records: &[PaymentRecord],
+                                   top_n: usize) -> String {
+        let mut forwarded: HashMap<&String, usize> = HashMap::new();
+        for record in records.iter().filter(|r| r.path.len() >= 3) {
+            for hop in &record.path[1..record.path.len() - 1] {
+                *forwarded.entry(hop).or_default() += 1;
+            }
+        }
+
+        let mut report = String::from("## THELMA: Honest Nodes Seeing the Most Traffic\n\n");
+        report.push_str("| Node | Betweenness | Degree | Closeness | Payments forwarded |\n");
+        report.push_str("|---|---|---|---|---|\n");
+
+        let honest = self.ranked(CentralityMeasure::Betweenness).into_iter()
+            .filter(|(key, _)| !malicious_nodes.contains(key))
+            .take(top_n);
+
+        for (key, betweenness) in honest {
+            let alias = network.nodes.get(&key).map(|n| n.alias.clone()).unwrap_or_else(|| key.clone());
+            report.push_str(&format!("| {} ({}) | {:.4} | {:.4} | {:.4} | {} |\n",
+                                     alias, key, betweenness,
+                                     self.degree.get(&key).copied().unwrap_or(0.0),
+                                     self.closeness.get(&key).copied().unwrap_or(0.0),
+                                     forwarded.get(&key).copied().unwrap_or(0)));
+        }
+
+        report
+    }
+}
+
+// BFS from one source: returns its betweenness dependencies, the source index and its closeness
+fn single_source_scores(adjacency: &[Vec<usize>], source: usize) -> (Vec<f64>, usize, f64) {
+    let n = adjacency.len();
+    let mut stack = Vec::with_capacity(n);
+    let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); n];
+    let mut sigma = vec![0.0f64; n];
+    let mut distance = vec![-1i64; n];
+    let mut queue = VecDeque::new();
+
+    sigma[source] = 1.0;
+    distance[source] = 0;
+    queue.push_back(source);
+
+    while let Some(v) = queue.pop_front() {
+        stack.push(v);
+        for &w in &adjacency[v] {
+            if distance[w] < 0 {
+                distance[w] = distance[v] + 1;
+                queue.push_back(w);
+            }
+            if distance[w] == distance[v] + 1 {
+                sigma[w] += sigma[v];
+                predecessors[w].push(v);
+            }
+        }
+    }
+
+    let mut dependency = vec![0.0f64; n];
+    while let Some(w) = stack.pop() {
+        for &v in &predecessors[w] {
+            dependency[v] += sigma[v] / sigma[w] * (1.0 + dependency[w]);
+        }
+    }
+    dependency[source] = 0.0;
+
+    // Closeness scaled by the reachable fraction so disconnected nodes aren't overrated
+    let reachable: Vec<i64> = distance.iter().copied().filter(|&d| d > 0).collect();
+    let total_distance: i64 = reachable.iter().sum();
+    let closeness = if total_distance > 0 && n > 1 {
+        let r = reachable.len() as f64;
+        (r / total_distance as f64) * (r / (n - 1) as f64)
+    } else {
+        0.0
+    };
+
+    (dependency, source, closeness)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Node, Channel, ChannelStatus};
+
+    #[test]
+    fn test_star_centrality() {
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["hub", "a", "b", "c"] {
+            network.add_node(Node::new(key, key, 40));
+        }
+        for leaf in ["a", "b", "c"] {
+            network.add_channel(Channel::new(&format!("chan-{}", leaf), "hub", leaf, 1_000_000));
+        }
+
+        let scores = CentralityScores::compute(&network);
+
+        // Every shortest path between leaves runs through the hub
+        assert!((scores.betweenness["hub"] - 1.0).abs() < 1e-9);
+        assert_eq!(scores.betweenness["a"], 0.0);
+        assert_eq!(scores.degree["hub"], 1.0);
+        assert!((scores.closeness["hub"] - 1.0).abs() < 1e-9);
+        assert!(scores.closeness["a"] < scores.closeness["hub"]);
+
+        assert_eq!(scores.ranked(CentralityMeasure::Betweenness)[0].0, "hub");
+    }
+
+    #[test]
+    fn test_parallel_and_idle_channels_do_not_inflate_centrality() {
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["a", "b", "c", "d"] {
+            network.add_node(Node::new(key, key, 40));
+        }
+        // A path a-b-c with three channels on each hop, and a zombie channel to d
+        for i in 0..3 {
+            network.add_channel(Channel::new(&format!("ab-{}", i), "a", "b", 1_000_000));
+            network.add_channel(Channel::new(&format!("bc-{}", i), "b", "c", 1_000_000));
+        }
+        network.add_channel(Channel::new("bd", "b", "d", 1_000_000).with_status(ChannelStatus::Zombie));
+
+        let scores = CentralityScores::compute(&network);
+
+        // b reaches two of three other nodes and sits on the one a-c shortest path
+        assert!((scores.degree["b"] - 2.0 / 3.0).abs() < 1e-9);
+        assert!((scores.betweenness["b"] - 2.0 / 6.0).abs() < 1e-9);
+        assert_eq!(scores.degree["d"], 0.0);
+    }
+}
@@ -0,0 +1,11 @@
+pub mod metrics;
+pub mod statistics;
+pub mod community;
+pub mod coverage;
+pub mod snapshot;
+
+pub use metrics::*;
+pub use statistics::*;
+pub use community::*;
+pub use coverage::*;
+pub use snapshot::*;
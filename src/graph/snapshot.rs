@@ -0,0 +1,278 @@
+// Snapshots of the network map taken as a run goes on, and structural diffs between them,
+// so experiments where the graph churns can tell what changed between any two points
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+use crate::models::{ChannelFees, ChannelStatus, LightningNetworkMap};
+use crate::simulation::Observer;
+use crate::error::{ThelmaError, read_lock};
+
+// The parts of a node's announcement routing depends on
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct NodeState {
+    cltv_expiry_delta: u32,
+    base_fee_msat: u64,
+    fee_rate_ppm: u64,
+}
+
+// The parts of a channel routing depends on
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ChannelState {
+    node1: String,
+    node2: String,
+    capacity: u64,
+    status: ChannelStatus,
+    node1_fees: Option<ChannelFees>,
+    node2_fees: Option<ChannelFees>,
+}
+
+// The network as it was after `payments` payments finished, at block `block`
+#[derive(Debug, Clone)]
+pub struct GraphSnapshot {
+    pub payments: u64,
+    pub block: u32,
+    nodes: BTreeMap<String, NodeState>,
+    channels: BTreeMap<String, ChannelState>,
+}
+
+impl GraphSnapshot {
+    pub fn of(network: &LightningNetworkMap, payments: u64) -> Self {
+        let nodes = network.nodes.values()
+            .map(|node| (node.pub_key.clone(), NodeState {
+                cltv_expiry_delta: node.cltv_expiry_delta,
+                base_fee_msat: node.base_fee_msat,
+                fee_rate_ppm: node.fee_rate_ppm,
+            }))
+            .collect();
+        let channels = network.channels.iter()
+            .map(|channel| (channel.channel_id.clone(), ChannelState {
+                node1: channel.node1.clone(),
+                node2: channel.node2.clone(),
+                capacity: channel.capacity,
+                status: channel.status,
+                node1_fees: channel.node1_fees,
+                node2_fees: channel.node2_fees,
+            }))
+            .collect();
+        GraphSnapshot { payments, block: network.current_block_height, nodes, channels }
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.channels.len()
+    }
+}
+
+// What changed from one snapshot to a later one. Policy changes are nodes whose delta or
+// fees changed, and channels whose capacity, status or per-end fees did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GraphDiff {
+    pub nodes_added: Vec<String>,
+    pub nodes_removed: Vec<String>,
+    pub channels_added: Vec<String>,
+    pub channels_removed: Vec<String>,
+    pub node_policy_changes: Vec<String>,
+    pub channel_policy_changes: Vec<String>,
+}
+
+impl GraphDiff {
+    pub fn between(old: &GraphSnapshot, new: &GraphSnapshot) -> Self {
+        let (nodes_added, nodes_removed, node_policy_changes) = compare(&old.nodes, &new.nodes);
+        let (channels_added, channels_removed, channel_policy_changes) = compare(&old.channels, &new.channels);
+        GraphDiff { nodes_added, nodes_removed, channels_added, channels_removed, node_policy_changes, channel_policy_changes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == GraphDiff::default()
+    }
+
+    // One line of counts, like `+2 nodes, -1 channel, 3 policy changes`
+    pub fn summary(&self) -> String {
+        if self.is_empty() {
+            return "no changes".to_string();
+        }
+        let policy_changes = self.node_policy_changes.len() + self.channel_policy_changes.len();
+        let parts = [
+            (self.nodes_added.len(), "+", "node"),
+            (self.nodes_removed.len(), "-", "node"),
+            (self.channels_added.len(), "+", "channel"),
+            (self.channels_removed.len(), "-", "channel"),
+            (policy_changes, "", "policy change"),
+        ];
+        parts.iter()
+            .filter(|(count, _, _)| *count > 0)
+            .map(|(count, sign, what)| format!("{}{} {}{}", sign, count, what, if *count == 1 { "" } else { "s" }))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "nodes_added": self.nodes_added,
+            "nodes_removed": self.nodes_removed,
+            "channels_added": self.channels_added,
+            "channels_removed": self.channels_removed,
+            "node_policy_changes": self.node_policy_changes,
+            "channel_policy_changes": self.channel_policy_changes,
+        })
+    }
+}
+
+// Keys only in `new`, only in `old`, and in both with different values, each in key order
+fn compare<T: PartialEq>(old: &BTreeMap<String, T>, new: &BTreeMap<String, T>) -> (Vec<String>, Vec<String>, Vec<String>) {
+    let added = new.keys().filter(|key| !old.contains_key(*key)).cloned().collect();
+    let removed = old.keys().filter(|key| !new.contains_key(*key)).cloned().collect();
+    let changed = new.iter()
+        .filter(|(key, value)| old.get(*key).is_some_and(|before| before != *value))
+        .map(|(key, _)| key.clone())
+        .collect();
+    (added, removed, changed)
+}
+
+// Snapshots the network before the first payment and every `interval` finished payments
+// after it, skipping points where the topology hasn't moved since the last snapshot
+pub struct SnapshotRecorder {
+    network: Arc<RwLock<LightningNetworkMap>>,
+    interval: u64,
+    finished: u64,
+    topology_version: u64,
+    snapshots: Vec<GraphSnapshot>,
+}
+
+impl SnapshotRecorder {
+    pub fn new(network: Arc<RwLock<LightningNetworkMap>>, interval: u64) -> Self {
+        let (topology_version, first) = {
+            let network = read_lock(&network);
+            (network.topology_version(), GraphSnapshot::of(&network, 0))
+        };
+        SnapshotRecorder { network, interval: interval.max(1), finished: 0, topology_version, snapshots: vec![first] }
+    }
+
+    pub fn snapshots(&self) -> &[GraphSnapshot] {
+        &self.snapshots
+    }
+
+    // Take a snapshot now unless nothing changed since the last one
+    pub fn snapshot(&mut self) {
+        let network = read_lock(&self.network);
+        if network.topology_version() != self.topology_version {
+            self.topology_version = network.topology_version();
+            self.snapshots.push(GraphSnapshot::of(&network, self.finished));
+        }
+    }
+
+    // Diffs between consecutive snapshots
+    pub fn diffs(&self) -> Vec<(&GraphSnapshot, &GraphSnapshot, GraphDiff)> {
+        self.snapshots.windows(2)
+            .map(|pair| (&pair[0], &pair[1], GraphDiff::between(&pair[0], &pair[1])))
+            .collect()
+    }
+
+    fn payment_finished(&mut self) {
+        self.finished += 1;
+        if self.finished.is_multiple_of(self.interval) {
+            self.snapshot();
+        }
+    }
+
+    pub fn generate_text_report(&self) -> String {
+        let mut report = String::from("## THELMA: Graph Changes\n\n");
+        let diffs = self.diffs();
+        if diffs.is_empty() {
+            report.push_str(&format!("The graph didn't change over {} payments.\n", self.finished));
+            return report;
+        }
+        for (old, new, diff) in &diffs {
+            report.push_str(&format!("- Payments {}-{} (blocks {}-{}): {} ({} nodes, {} channels)\n",
+                                     old.payments, new.payments, old.block, new.block, diff.summary(),
+                                     new.node_count(), new.channel_count()));
+        }
+        let first = &self.snapshots[0];
+        let last = &self.snapshots[self.snapshots.len() - 1];
+        report.push_str(&format!("\nOverall: {}\n", GraphDiff::between(first, last).summary()));
+        report
+    }
+
+    pub fn generate_json_report(&self) -> String {
+        let snapshots: Vec<serde_json::Value> = self.snapshots.iter()
+            .map(|snapshot| serde_json::json!({
+                "payments": snapshot.payments,
+                "block": snapshot.block,
+                "nodes": snapshot.node_count(),
+                "channels": snapshot.channel_count(),
+            }))
+            .collect();
+        let diffs: Vec<serde_json::Value> = self.diffs().iter()
+            .map(|(old, new, diff)| {
+                let mut value = diff.to_json();
+                value["from_payments"] = serde_json::json!(old.payments);
+                value["to_payments"] = serde_json::json!(new.payments);
+                value
+            })
+            .collect();
+        serde_json::to_string_pretty(&serde_json::json!({ "snapshots": snapshots, "diffs": diffs }))
+            .unwrap_or_default()
+    }
+}
+
+impl Observer for SnapshotRecorder {
+    fn on_settle(&mut self, _payment_hash: &str) -> Result<(), ThelmaError> {
+        self.payment_finished();
+        Ok(())
+    }
+
+    fn on_fail(&mut self, _sender: &str, _receiver: &str, _reason: &str) -> Result<(), ThelmaError> {
+        self.payment_finished();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Channel, Node};
+
+    #[test]
+    fn test_diff_between_snapshots() {
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["a", "b", "c"] {
+            network.add_node(Node::new(key, key, 40));
+        }
+        network.add_channel(Channel::new("ab", "a", "b", 1_000_000));
+        network.add_channel(Channel::new("bc", "b", "c", 1_000_000));
+        let network = Arc::new(RwLock::new(network));
+        let mut recorder = SnapshotRecorder::new(network.clone(), 2);
+
+        // Nothing changed yet, so no snapshot is taken
+        recorder.on_fail("a", "c", "no route").unwrap();
+        recorder.on_settle("hash").unwrap();
+        assert_eq!(recorder.snapshots().len(), 1);
+
+        {
+            let mut network = network.write().unwrap();
+            network.remove_node("c");
+            network.add_node(Node::new("d", "d", 40));
+            network.add_channel(Channel::new("bd", "b", "d", 1_000_000));
+            network.update_channel_policy("ab", "a", ChannelFees { base_fee_msat: 0, fee_rate_ppm: 10 });
+            network.add_node(Node::new("b", "b", 80));
+        }
+        recorder.on_settle("hash").unwrap();
+        assert_eq!(recorder.snapshots().len(), 1);
+        recorder.on_settle("hash").unwrap();
+        assert_eq!(recorder.snapshots().len(), 2);
+
+        let diff = GraphDiff::between(&recorder.snapshots()[0], &recorder.snapshots()[1]);
+        assert_eq!(diff.nodes_added, vec!["d"]);
+        assert_eq!(diff.nodes_removed, vec!["c"]);
+        assert_eq!(diff.channels_added, vec!["bd"]);
+        assert_eq!(diff.channels_removed, vec!["bc"]);
+        assert_eq!(diff.node_policy_changes, vec!["b"]);
+        assert_eq!(diff.channel_policy_changes, vec!["ab"]);
+        assert_eq!(diff.summary(), "+1 node, -1 node, +1 channel, -1 channel, 2 policy changes");
+        assert!(recorder.generate_text_report().contains("- Payments 0-4"));
+    }
+}
@@ -0,0 +1,312 @@
+// Topology statistics so readers can judge how representative a network is
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use rayon::prelude::*;
+
+use crate::models::LightningNetworkMap;
+
+// Summary of a numeric distribution
+#[derive(Debug, Clone, Default)]
+pub struct DistributionSummary {
+    pub min: u64,
+    pub max: u64,
+    pub mean: f64,
+    pub median: u64,
+    pub p10: u64,
+    pub p90: u64,
+}
+
+impl DistributionSummary {
+    pub fn from_values(mut values: Vec<u64>) -> Self {
+        if values.is_empty() {
+            return DistributionSummary::default();
+        }
+
+        values.sort_unstable();
+        let percentile = |p: f64| values[((values.len() - 1) as f64 * p).round() as usize];
+
+        DistributionSummary {
+            min: values[0],
+            max: values[values.len() - 1],
+            mean: values.iter().sum::<u64>() as f64 / values.len() as f64,
+            median: percentile(0.5),
+            p10: percentile(0.1),
+            p90: percentile(0.9),
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "min": self.min,
+            "max": self.max,
+            "mean": self.mean,
+            "median": self.median,
+            "p10": self.p10,
+            "p90": self.p90,
+        })
+    }
+}
+
+// Topology statistics of a Lightning Network graph
+#[derive(Debug, Clone)]
+pub struct NetworkStatistics {
+    pub node_count: usize,
+    pub channel_count: usize,
+    pub degree: DistributionSummary,
+    // Number of nodes with each distinct degree
+    pub degree_histogram: BTreeMap<usize, usize>,
+    pub avg_clustering_coefficient: f64,
+    pub connected_components: usize,
+    // Longest shortest path within any connected component
+    pub diameter: usize,
+    pub avg_shortest_path: f64,
+    pub capacity: DistributionSummary,
+    pub total_capacity: u64,
+    pub articulation_points: Vec<String>,
+}
+
+impl NetworkStatistics {
+    pub fn compute(network: &LightningNetworkMap) -> Self {
+        let mut nodes: Vec<&String> = network.nodes.keys().collect();
+        nodes.sort();
+        let index: HashMap<&String, usize> = nodes.iter().enumerate().map(|(i, k)| (*k, i)).collect();
+
+        // Simple graph view: parallel channels collapse into a single edge
+        let adjacency: Vec<Vec<usize>> = nodes.iter()
+            .map(|key| {
+                let unique: HashSet<usize> = network.get_neighbors(key)
+                    .map(|neighbors| neighbors.iter().filter_map(|nb| index.get(nb).copied()).collect())
+                    .unwrap_or_default();
+                let mut unique: Vec<usize> = unique.into_iter().filter(|&nb| nb != index[key]).collect();
+                unique.sort_unstable();
+                unique
+            })
+            .collect();
+
+        let degrees: Vec<usize> = adjacency.iter().map(|a| a.len()).collect();
+        let mut degree_histogram = BTreeMap::new();
+        for &d in &degrees {
+            *degree_histogram.entry(d).or_insert(0) += 1;
+        }
+
+        let (diameter, path_total, path_count) = (0..adjacency.len()).into_par_iter()
+            .map(|source| {
+                let distances = bfs_distances(&adjacency, source);
+                let reachable = distances.iter().filter_map(|d| *d).filter(|&d| d > 0);
+                reachable.fold((0, 0, 0), |(max, total, count), d| (max.max(d), total + d, count + 1))
+            })
+            .reduce(|| (0, 0, 0), |a, b| (a.0.max(b.0), a.1 + b.1, a.2 + b.2));
+
+        let capacities: Vec<u64> = network.channels.iter().map(|c| c.capacity).collect();
+
+        NetworkStatistics {
+            node_count: nodes.len(),
+            channel_count: network.channels.len(),
+            degree: DistributionSummary::from_values(degrees.iter().map(|&d| d as u64).collect()),
+            degree_histogram,
+            avg_clustering_coefficient: average_clustering(&adjacency),
+            connected_components: network.connected_components(),
+            diameter,
+            avg_shortest_path: if path_count == 0 { 0.0 } else { path_total as f64 / path_count as f64 },
+            total_capacity: capacities.iter().sum(),
+            capacity: DistributionSummary::from_values(capacities),
+            articulation_points: articulation_points(&adjacency).into_iter()
+                .map(|i| nodes[i].clone())
+                .collect(),
+        }
+    }
+
+    // Generate the markdown network statistics section
+    pub fn generate_text_report(&self) -> String {
+        let mut report = String::from("## THELMA: Network Statistics\n\n");
+
+        report.push_str(&format!("Nodes: {}\n", self.node_count));
+        report.push_str(&format!("Channels: {}\n", self.channel_count));
+        report.push_str(&format!("Connected components: {}\n", self.connected_components));
+        report.push_str(&format!("Diameter: {} hops\n", self.diameter));
+        report.push_str(&format!("Average shortest path: {:.2} hops\n", self.avg_shortest_path));
+        report.push_str(&format!("Average clustering coefficient: {:.4}\n", self.avg_clustering_coefficient));
+        report.push_str(&format!("Articulation points: {}", self.articulation_points.len()));
+        if !self.articulation_points.is_empty() {
+            report.push_str(&format!(" ({})", self.articulation_points.join(", ")));
+        }
+        report.push('\n');
+
+        report.push_str("\n### Degree Distribution\n");
+        report.push_str(&format!("min {} / median {} / mean {:.2} / max {}\n\n",
+                                 self.degree.min, self.degree.median, self.degree.mean, self.degree.max));
+        report.push_str("| Degree | Nodes |\n|---|---|\n");
+        for (degree, count) in &self.degree_histogram {
+            report.push_str(&format!("| {} | {} |\n", degree, count));
+        }
+
+        report.push_str("\n### Capacity Distribution (sat)\n");
+        report.push_str(&format!("Total: {}\n", self.total_capacity));
+        report.push_str(&format!("min {} / p10 {} / median {} / mean {:.0} / p90 {} / max {}\n",
+                                 self.capacity.min, self.capacity.p10, self.capacity.median,
+                                 self.capacity.mean, self.capacity.p90, self.capacity.max));
+
+        report
+    }
+
+    // Generate a JSON version of the statistics
+    pub fn generate_json_report(&self) -> String {
+        let histogram: serde_json::Map<String, serde_json::Value> = self.degree_histogram.iter()
+            .map(|(d, c)| (d.to_string(), serde_json::Value::from(*c)))
+            .collect();
+
+        serde_json::to_string_pretty(&serde_json::json!({
+            "node_count": self.node_count,
+            "channel_count": self.channel_count,
+            "degree": self.degree.to_json(),
+            "degree_histogram": histogram,
+            "avg_clustering_coefficient": self.avg_clustering_coefficient,
+            "connected_components": self.connected_components,
+            "diameter": self.diameter,
+            "avg_shortest_path": self.avg_shortest_path,
+            "capacity": self.capacity.to_json(),
+            "total_capacity": self.total_capacity,
+            "articulation_points": self.articulation_points,
+        })).unwrap_or_else(|_| "Error generating JSON report".to_string())
+    }
+}
+
+fn bfs_distances(adjacency: &[Vec<usize>], source: usize) -> Vec<Option<usize>> {
+    let mut distances = vec![None; adjacency.len()];
+    let mut queue = VecDeque::new();
+    distances[source] = Some(0);
+    queue.push_back(source);
+
+    while let Some(v) = queue.pop_front() {
+        let next = distances[v].unwrap() + 1;
+        for &w in &adjacency[v] {
+            if distances[w].is_none() {
+                distances[w] = Some(next);
+                queue.push_back(w);
+            }
+        }
+    }
+
+    distances
+}
+
+// Mean local clustering coefficient (nodes with degree < 2 count as zero)
+fn average_clustering(adjacency: &[Vec<usize>]) -> f64 {
+    if adjacency.is_empty() {
+        return 0.0;
+    }
+
+    let total: f64 = adjacency.par_iter()
+        .map(|neighbors| {
+            let k = neighbors.len();
+            if k < 2 {
+                return 0.0;
+            }
+            let mut links = 0;
+            for (i, &a) in neighbors.iter().enumerate() {
+                for &b in &neighbors[i + 1..] {
+                    if adjacency[a].binary_search(&b).is_ok() {
+                        links += 1;
+                    }
+                }
+            }
+            2.0 * links as f64 / (k * (k - 1)) as f64
+        })
+        .sum();
+
+    total / adjacency.len() as f64
+}
+
+// Nodes whose removal disconnects the graph (iterative Tarjan to avoid deep recursion)
+fn articulation_points(adjacency: &[Vec<usize>]) -> Vec<usize> {
+    let n = adjacency.len();
+    let mut discovery = vec![usize::MAX; n];
+    let mut low = vec![0; n];
+    let mut parent = vec![usize::MAX; n];
+    let mut is_articulation = vec![false; n];
+    let mut timer = 0;
+
+    for root in 0..n {
+        if discovery[root] != usize::MAX {
+            continue;
+        }
+
+        let mut root_children = 0;
+        discovery[root] = timer;
+        low[root] = timer;
+        timer += 1;
+
+        // Stack of (node, index of next neighbor to visit)
+        let mut stack = vec![(root, 0)];
+        while let Some(frame) = stack.last_mut() {
+            let v = frame.0;
+            if frame.1 < adjacency[v].len() {
+                let w = adjacency[v][frame.1];
+                frame.1 += 1;
+
+                if discovery[w] == usize::MAX {
+                    parent[w] = v;
+                    discovery[w] = timer;
+                    low[w] = timer;
+                    timer += 1;
+                    if v == root {
+                        root_children += 1;
+                    }
+                    stack.push((w, 0));
+                } else if w != parent[v] {
+                    low[v] = low[v].min(discovery[w]);
+                }
+            } else {
+                stack.pop();
+                let p = parent[v];
+                if p != usize::MAX {
+                    low[p] = low[p].min(low[v]);
+                    if p != root && low[v] >= discovery[p] {
+                        is_articulation[p] = true;
+                    }
+                }
+            }
+        }
+
+        if root_children > 1 {
+            is_articulation[root] = true;
+        }
+    }
+
+    (0..n).filter(|&v| is_articulation[v]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Node, Channel};
+
+    #[test]
+    fn test_network_statistics() {
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["a", "b", "c", "d"] {
+            network.add_node(Node::new(key, key, 40));
+        }
+
+        // Triangle a-b-c with d hanging off c
+        network.add_channel(Channel::new("ab", "a", "b", 1_000_000));
+        network.add_channel(Channel::new("bc", "b", "c", 2_000_000));
+        network.add_channel(Channel::new("ca", "c", "a", 3_000_000));
+        network.add_channel(Channel::new("cd", "c", "d", 4_000_000));
+
+        let stats = NetworkStatistics::compute(&network);
+
+        assert_eq!(stats.node_count, 4);
+        assert_eq!(stats.channel_count, 4);
+        assert_eq!(stats.connected_components, 1);
+        assert_eq!(stats.diameter, 2);
+        assert_eq!(stats.articulation_points, vec!["c".to_string()]);
+        assert_eq!(stats.degree.max, 3);
+        assert_eq!(stats.degree_histogram[&2], 2);
+        assert_eq!(stats.total_capacity, 10_000_000);
+
+        // a and b are fully clustered, c has 1 of 3 possible links, d has degree 1
+        let expected = (1.0 + 1.0 + 1.0 / 3.0 + 0.0) / 4.0;
+        assert!((stats.avg_clustering_coefficient - expected).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,252 @@
+// Just enough HTTP/1.1 to serve the API and metrics endpoints without a web framework,
+// plus WebSocket upgrades for streaming events
+
+use std::sync::Arc;
+use futures_util::{SinkExt, StreamExt};
+use log::debug;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::Role;
+
+use crate::error::ThelmaError;
+
+// Request headers beyond this are refused
+const MAX_HEADER_BYTES: usize = 64 * 1024;
+// Request bodies beyond this are refused unless the server allows more: room for a sizeable
+// `lncli describegraph` dump without letting a client claim the whole memory
+pub const DEFAULT_MAX_BODY_BYTES: usize = 128 * 1024 * 1024;
+
+pub struct Request {
+    pub method: String,
+    // Path without the query string
+    pub path: String,
+    // Header names are lowercased
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    // Whether the client asked to switch to a WebSocket
+    pub fn is_websocket_upgrade(&self) -> bool {
+        self.header("upgrade").is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket"))
+    }
+}
+
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+    // Messages to stream over a WebSocket instead of answering with a body
+    events: Option<broadcast::Receiver<String>>,
+}
+
+impl Response {
+    pub fn json(status: u16, value: &serde_json::Value) -> Self {
+        Response::text(status, "application/json", value.to_string())
+    }
+
+    pub fn text(status: u16, content_type: &'static str, body: String) -> Self {
+        Response { status, content_type, body, events: None }
+    }
+
+    // Upgrade the connection to a WebSocket and send every message from `events` as a
+    // text frame until either side goes away
+    pub fn websocket(events: broadcast::Receiver<String>) -> Self {
+        Response { events: Some(events), ..Response::text(101, "text/plain", String::new()) }
+    }
+
+    // A JSON body of the form {"error": message}
+    pub fn error(status: u16, message: &str) -> Self {
+        Response::json(status, &serde_json::json!({ "error": message }))
+    }
+
+    pub fn not_found() -> Self {
+        Response::error(404, "not found")
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error",
+    }
+}
+
+// Accept connections until the process exits, answering each request with `handler`.
+// Handlers run on the blocking pool, since analysis can keep them busy for a while.
+// Bodies over `max_body_bytes` are refused.
+pub async fn serve<F>(listener: TcpListener, max_body_bytes: usize, handler: Arc<F>)
+where
+    F: Fn(Request) -> Response + Send + Sync + 'static,
+{
+    while let Ok((mut stream, peer)) = listener.accept().await {
+        let handler = handler.clone();
+        tokio::spawn(async move {
+            let mut websocket_key = None;
+            let mut response = match read_request(&mut stream, max_body_bytes).await {
+                Ok(Some(request)) => {
+                    debug!("{} {} from {}", request.method, request.path, peer);
+                    websocket_key = request.header("sec-websocket-key").map(str::to_string);
+                    tokio::task::spawn_blocking(move || handler(request)).await
+                        .unwrap_or_else(|e| Response::error(500, &e.to_string()))
+                }
+                Ok(None) => Response::error(413, "request too large"),
+                Err(e) => Response::error(400, &e.to_string()),
+            };
+
+            if let Some(events) = response.events.take() {
+                match websocket_key {
+                    Some(key) => return stream_events(stream, &key, events).await,
+                    None => response = Response::error(400, "missing Sec-WebSocket-Key"),
+                }
+            }
+            if let Err(e) = write_response(&mut stream, &response).await {
+                debug!("Failed to answer request from {}: {}", peer, e);
+            }
+        });
+    }
+}
+
+// Read one request. `None` means it was larger than we accept.
+async fn read_request(stream: &mut TcpStream, max_body_bytes: usize) -> Result<Option<Request>, ThelmaError> {
+    let mut data = Vec::new();
+    let mut chunk = [0u8; 8192];
+    let header_end = loop {
+        if let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end;
+        }
+        if data.len() > MAX_HEADER_BYTES {
+            return Ok(None);
+        }
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        data.extend_from_slice(&chunk[..read]);
+    };
+
+    let head = String::from_utf8_lossy(&data[..header_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let target = request_line.next().unwrap_or_default();
+    let path = target.split('?').next().unwrap_or_default().to_string();
+
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+    let content_length = headers.iter()
+        .find(|(name, _)| name == "content-length")
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .unwrap_or(0);
+    if content_length > max_body_bytes {
+        return Ok(None);
+    }
+
+    // The buffer grows as the body arrives rather than trusting Content-Length up front
+    let mut body = data.split_off(header_end + 4);
+    if body.len() < content_length {
+        let rest = (content_length - body.len()) as u64;
+        (&mut *stream).take(rest).read_to_end(&mut body).await?;
+        if body.len() < content_length {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+    }
+    body.truncate(content_length);
+
+    Ok(Some(Request { method, path, headers, body }))
+}
+
+async fn write_response(stream: &mut TcpStream, response: &Response) -> Result<(), ThelmaError> {
+    let head = format!("HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                       response.status, reason(response.status), response.content_type, response.body.len());
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(response.body.as_bytes()).await?;
+    Ok(())
+}
+
+// Complete the WebSocket handshake and forward events until the client closes the socket
+// or the sender goes away. Clients that fall behind skip what they missed.
+async fn stream_events(mut stream: TcpStream, key: &str, mut events: broadcast::Receiver<String>) {
+    let peer = stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_default();
+    let head = format!("HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                        Sec-WebSocket-Accept: {}\r\n\r\n", derive_accept_key(key.as_bytes()));
+    if let Err(e) = stream.write_all(head.as_bytes()).await {
+        debug!("Failed to upgrade connection from {}: {}", peer, e);
+        return;
+    }
+
+    let (mut sink, mut incoming) = WebSocketStream::from_raw_socket(stream, Role::Server, None).await.split();
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(text) => {
+                    if let Err(e) = sink.send(Message::text(text)).await {
+                        debug!("WebSocket to {} closed: {}", peer, e);
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => debug!("WebSocket to {} skipped {} events", peer, skipped),
+                Err(RecvError::Closed) => break,
+            },
+            // Pings are answered along with the next frame sent
+            message = incoming.next() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    let _ = sink.close().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Send raw bytes, close our side and read the whole answer
+    async fn exchange(addr: &str, request: &[u8]) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request).await.unwrap();
+        stream.shutdown().await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_bodies_are_read_as_they_arrive_up_to_the_limit() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let echo = |request: Request| Response::text(200, "text/plain", String::from_utf8_lossy(&request.body).into_owned());
+        tokio::spawn(serve(listener, 16, Arc::new(echo)));
+
+        let response = exchange(&addr, b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK") && response.ends_with("\r\n\r\nhello"), "{}", response);
+
+        // A huge Content-Length is refused before anything is allocated for it
+        let response = exchange(&addr, b"POST / HTTP/1.1\r\nContent-Length: 1000000000000\r\n\r\nhello").await;
+        assert!(response.starts_with("HTTP/1.1 413"), "{}", response);
+
+        // A body cut short of what was announced is a bad request
+        let response = exchange(&addr, b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nhel").await;
+        assert!(response.starts_with("HTTP/1.1 400"), "{}", response);
+    }
+}
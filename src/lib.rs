@@ -0,0 +1,40 @@
+// THELMA as a library: the network models, payment simulation and timelock analysis the
+// command-line tool is built on. Without the `native` feature this core builds for
+// wasm32-unknown-unknown, the `wasm` feature adds bindings for running it in a browser, and
+// the `ffi` feature a C API for embedding the analyzer in other tooling.
+
+pub mod models;
+pub mod surveillance;
+pub mod simulation;
+pub mod defense;
+pub mod graph;
+pub mod error;
+pub mod logging;
+pub mod checkpoint;
+pub mod latex;
+#[cfg(feature = "native")]
+pub mod timing;
+#[cfg(feature = "native")]
+pub mod http;
+#[cfg(feature = "native")]
+pub mod server;
+#[cfg(feature = "native")]
+pub mod tui;
+#[cfg(feature = "native")]
+pub mod shell;
+#[cfg(feature = "native")]
+pub mod study;
+#[cfg(feature = "native")]
+pub mod golden;
+#[cfg(feature = "native")]
+pub mod registry;
+#[cfg(feature = "plots")]
+pub mod plots;
+#[cfg(feature = "bench")]
+pub mod bench;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(test)]
+mod test_fixtures;
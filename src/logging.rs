@@ -0,0 +1,190 @@
+// Console output levels and progress bars
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "native")]
+use std::time::Duration;
+#[cfg(feature = "native")]
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use crate::error::lock_mutex;
+
+// How much THELMA prints while it runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verbosity {
+    // Warnings and errors only, no progress bars
+    Quiet,
+    // Setup, summaries and reports, with progress bars for long loops
+    Normal,
+    // Every payment and observation as well
+    Verbose,
+    // Also the analyzer's per-route reasoning
+    Trace,
+}
+
+impl Verbosity {
+    // `-q`/`--quiet`, `-v`/`--verbose` or `-vv` anywhere on the command line
+    pub fn from_args(args: &[String]) -> Self {
+        let mut verbosity = Verbosity::Normal;
+        for arg in args.iter().skip(1) {
+            verbosity = match arg.as_str() {
+                "-q" | "--quiet" => Verbosity::Quiet,
+                "-v" | "--verbose" => Verbosity::Verbose,
+                "-vv" => Verbosity::Trace,
+                _ => verbosity,
+            };
+        }
+        verbosity
+    }
+
+    fn level_filter(self) -> LevelFilter {
+        match self {
+            Verbosity::Quiet => LevelFilter::Warn,
+            Verbosity::Normal => LevelFilter::Info,
+            Verbosity::Verbose => LevelFilter::Debug,
+            Verbosity::Trace => LevelFilter::Trace,
+        }
+    }
+}
+
+// Progress bars are hidden until `init` says otherwise, so tests and library users
+// don't draw any
+static SHOW_PROGRESS: AtomicBool = AtomicBool::new(false);
+// The bar currently drawn; log lines are printed above it rather than through it
+#[cfg(feature = "native")]
+static ACTIVE_BAR: Mutex<Option<ProgressBar>> = Mutex::new(None);
+// Lines held back while something else owns the terminal, printed once it lets go
+static HELD: Mutex<Option<Vec<(Level, String)>>> = Mutex::new(None);
+
+struct ConsoleLogger;
+
+impl Log for ConsoleLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        if let Some(held) = lock_mutex(&HELD).as_mut() {
+            held.push((record.level(), record.args().to_string()));
+            return;
+        }
+
+        let print = || print_line(record.level(), &record.args().to_string());
+        #[cfg(feature = "native")]
+        if let Some(bar) = lock_mutex(&ACTIVE_BAR).as_ref() {
+            return bar.suspend(print);
+        }
+        print();
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: ConsoleLogger = ConsoleLogger;
+
+fn print_line(level: Level, line: &str) {
+    match level {
+        Level::Error => eprintln!("error: {}", line),
+        Level::Warn => eprintln!("warning: {}", line),
+        _ => println!("{}", line),
+    }
+}
+
+// Hold console output and progress bars back, e.g. while a full-screen view is drawn
+pub fn hold_output() {
+    lock_mutex(&HELD).get_or_insert_with(Vec::new);
+}
+
+// Print everything held back since `hold_output` and go back to printing directly
+pub fn release_output() {
+    for (level, line) in lock_mutex(&HELD).take().unwrap_or_default() {
+        print_line(level, &line);
+    }
+}
+
+// Route the `log` macros to the console at the given verbosity
+pub fn init(verbosity: Verbosity) {
+    // Only fails when a logger is already installed, which is fine to keep
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(verbosity.level_filter());
+    SHOW_PROGRESS.store(verbosity != Verbosity::Quiet, Ordering::Relaxed);
+}
+
+// Progress through a long loop, drawn on stderr when it is a terminal. Clones share
+// the same bar, so parallel workers can all advance it.
+#[cfg(feature = "native")]
+#[derive(Clone)]
+pub struct Progress {
+    bar: ProgressBar,
+}
+
+#[cfg(feature = "native")]
+impl Progress {
+    pub fn start(total: usize, message: &str) -> Self {
+        if !SHOW_PROGRESS.load(Ordering::Relaxed) || lock_mutex(&HELD).is_some() {
+            return Progress { bar: ProgressBar::hidden() };
+        }
+
+        let bar = ProgressBar::new(total as u64).with_message(message.to_string());
+        if let Ok(style) = ProgressStyle::with_template("{msg} [{bar:40}] {pos}/{len} ({per_sec}, eta {eta})") {
+            bar.set_style(style.progress_chars("=> "));
+        }
+        bar.enable_steady_tick(Duration::from_millis(200));
+        *lock_mutex(&ACTIVE_BAR) = Some(bar.clone());
+        Progress { bar }
+    }
+
+    pub fn inc(&self) {
+        self.bar.inc(1);
+    }
+
+    // Remove the bar, leaving the summary logged after it as the only trace
+    pub fn finish(self) {
+        self.bar.finish_and_clear();
+        lock_mutex(&ACTIVE_BAR).take();
+    }
+}
+
+// Builds without the native feature have no terminal to draw on
+#[cfg(not(feature = "native"))]
+#[derive(Clone)]
+pub struct Progress;
+
+#[cfg(not(feature = "native"))]
+impl Progress {
+    pub fn start(_total: usize, _message: &str) -> Self {
+        Progress
+    }
+
+    pub fn inc(&self) {}
+
+    pub fn finish(self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verbosity_from_args() {
+        let args = |list: &[&str]| -> Vec<String> { list.iter().map(|s| s.to_string()).collect() };
+
+        assert_eq!(Verbosity::from_args(&args(&["thelma", "20"])), Verbosity::Normal);
+        assert_eq!(Verbosity::from_args(&args(&["thelma", "-q", "20"])), Verbosity::Quiet);
+        assert_eq!(Verbosity::from_args(&args(&["thelma", "20", "--verbose"])), Verbosity::Verbose);
+        assert_eq!(Verbosity::from_args(&args(&["thelma", "resume", "cp.json", "-vv"])), Verbosity::Trace);
+        assert_eq!(Verbosity::Quiet.level_filter(), LevelFilter::Warn);
+
+        // Hidden unless the binary asked for progress bars
+        let progress = Progress::start(10, "Testing");
+        progress.inc();
+        #[cfg(feature = "native")]
+        assert!(progress.bar.is_hidden());
+        progress.finish();
+    }
+}
@@ -1,116 +1,2077 @@
-use std::sync::{Arc, Mutex};
-use std::error::Error;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::env;
+use std::process::ExitCode;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use log::{debug, info, warn};
+use tracing::info_span;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::IndexedRandom;
 
-pub mod models;
-pub mod surveillance;
-pub mod simulation;
+use thelma::models::{LightningNetworkMap, NodeLabels, ShadowOffset, ShadowOffsetMix, DEFAULT_INVOICE_EXPIRY_BLOCKS, DEFAULT_MAX_CLTV_EXPIRY};
+use thelma::surveillance::{SurveillanceConfig, DEFAULT_STALE_AFTER_BLOCKS, SurveillanceOperation, PotentialRecipient, ReportTemplate, AdversaryBudget, AttackEconomics, AnalysisMode,
+                           AdversaryStrategy, CentralPlacement, RandomPlacement, SurveillanceMetrics, serve_metrics,
+                           PlacementPlan, TrafficModel, CytoscapeExport, ProbeDetector, FingerprintLinker, attribute_to_payments, DEFAULT_ROUTE_CACHE_CAPACITY, DEFAULT_SPILL_PARTITIONS,
+                           DEFAULT_TRAFFIC_SAMPLES, DEFAULT_UPTIME_PERIOD,
+                           CoalitionComparison, CoalitionResult, split_coalitions, LspComparison, LspScenario, ObservationSharing, SharingReport, AccuracyThresholds,
+                           RecipientProfile};
+use thelma::simulation::{AmountDistribution, CltvConstruction, CltvDeltaDistribution, FeeModel, GossipSchedule, NetworkGenerator, HopLatency, DEFAULT_HOP_LATENCY_MS, LspClients, DEFAULT_LSP_COUNT, RegionLatency, PaymentRecord, PaymentSimulator, RetryPolicy, SimulatorConfig, Router,
+                         GexfRecorder, Trace, TraceRecorder, router_from_name, topology_from_name};
+use thelma::graph::{CentralityMeasure, CentralityScores, Communities, CoverageEstimate, NetworkStatistics, SnapshotRecorder};
+use thelma::defense::{DecoyHopDefense, CoverTrafficDefense, LatencyPaddingDefense, OverpaymentDefense,
+                     PaymentSplittingDefense, ShardExposure, SplittingReport, DefenseComparison, DefenderView, ScenarioMetrics, InferenceDiff, RocCurve, TrampolineReport, DEFAULT_TOP_K};
+use thelma::error::{ThelmaError, lock_mutex, read_lock, write_lock};
+use thelma::checkpoint::{Checkpoint, DEFAULT_CHECKPOINT_FILE};
+use thelma::logging::{self, Verbosity};
+use thelma::timing::PhaseTimer;
+use thelma::server::{ApiServer, DEFAULT_SERVE_ADDR};
+use thelma::tui::{LiveStats, LiveView};
+use thelma::shell::Shell;
+use thelma::golden::{GoldenScenario, compare_reports, DEFAULT_GOLDEN_TOLERANCE};
+use thelma::registry::{RunRecord, RunRegistry, DEFAULT_REGISTRY_FILE, RUNS_COMMANDS, generate_comparison_report,
+                       generate_list_report};
+use thelma::study::{ExperimentSettings, ScalingStudy, StudyParameter, DEFAULT_STUDY_SEED, STUDY_KINDS};
+#[cfg(feature = "plots")]
+use thelma::plots::{AnonymityCdf, ConfidenceHistogram, LineChart, PlotFormat, PLOT_FORMATS, ThresholdCurveChart, save_plot};
 
-use models::LightningNetworkMap;
-use surveillance::SurveillanceOperation;
-use simulation::{NetworkGenerator, PaymentSimulator};
+// Report communities once the best single candidate holds less than half the confidence
+const DEFAULT_COMMUNITY_THRESHOLD: f32 = 0.5;
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    println!("Starting THELMA: Timelock Heuristic Evaluation for Lightning Movement Analysis");
-    println!("==========================================================================");
+// Options parsed from the command line
+struct CliOptions {
+    node_count: usize,
+    // Topology model the network is built from, and the graph file `imported` loads or the
+    // fixture `fixture` builds
+    topology: String,
+    topology_file: Option<String>,
+    // Empirical CLTV deltas for generated nodes
+    cltv_delta_file: Option<String>,
+    // Labels (exchange, merchant, ...) and aliases for nodes by public key
+    labels_file: Option<String>,
+    // How generated nodes' fee policies are drawn
+    fees: FeeModel,
+    // Shadow offset profiles spread over nodes, and profiles of particular nodes
+    shadow_offsets: Option<ShadowOffsetMix>,
+    node_shadow_offsets: Vec<(String, ShadowOffset)>,
+    // Share of generated channels doubled up by a parallel one
+    parallel_channels: f64,
+    // --disabled-channels and --zombie-channels
+    disabled_channels: f64,
+    zombie_channels: f64,
+    // Mine blocks and gossip channel updates between payments
+    gossip: Option<GossipSchedule>,
+    // HTLCs each channel side accepts in flight, and payments an HTLC stays in flight for
+    max_accepted_htlcs: Option<u16>,
+    htlc_hold: u64,
+    // Retries after a payment fails in flight, and blocks invoices stay payable for
+    retries: u32,
+    invoice_expiry: u32,
+    // Share of channels refusing HTLCs below their dust limit
+    refuse_dust: f64,
+    // --tor-share
+    tor_share: f64,
+    // Regions nodes are spread over and the latency between them: the built-in world or a
+    // file, loaded once the run starts
+    regions_spec: Option<String>,
+    regions: Option<RegionLatency>,
+    // Milliseconds messages take over a link, and whether the analysis times settlements
+    hop_latency: u64,
+    settlement_timing: bool,
+    // Share of the time our nodes are online to observe, and payments their windows repeat over
+    uptime: Option<f64>,
+    uptime_period: u64,
+    // Sizes of adversary coalitions that keep their observations to themselves
+    coalitions: Vec<usize>,
+    // Payments observations take to reach the adversary's hub and msat each costs to send,
+    // and the payment after which to also analyze what had reached it
+    share_delay: Option<u64>,
+    share_cost_msat: u64,
+    analyze_at: Option<u64>,
+    // Best-connected nodes senders hand their payments to for routing
+    trampolines: usize,
+    // Wallet clients attached to the best-connected nodes as their LSPs, how many LSPs, and
+    // the share of clients whose channel opens just in time
+    lsp_clients: usize,
+    lsps: usize,
+    jit_share: f64,
+    // Confidence the analysis takes off per hop through channels stale at payment time
+    stale_penalty: Option<f64>,
+    // Blocks after funding by which the analysis takes a channel to be mature
+    channel_maturity: Option<u32>,
+    payment_count: usize,
+    malicious_count: usize,
+    decoy_hops: Option<DecoyHopDefense>,
+    cover_traffic: Option<CoverTrafficDefense>,
+    latency_padding: Option<LatencyPaddingDefense>,
+    overpayment: Option<OverpaymentDefense>,
+    payment_splitting: Option<PaymentSplittingDefense>,
+    defender_node: Option<String>,
+    budget: AdversaryBudget,
+    // Centrality measure used to place malicious nodes (random when unset)
+    placement: Option<CentralityMeasure>,
+    // Fresh capital the adversary spends on channels from its nodes
+    attack_capital_sat: u64,
+    // Individual confidence share below which candidate communities are reported
+    community_threshold: Option<f32>,
+    // Top-k and set-size cut-offs the report's identification rates are given at
+    accuracy_thresholds: AccuracyThresholds,
+    // Largest total route timelock senders accept
+    max_cltv_expiry: u32,
+    // Which delta senders add, and the analysis assumes, for each forwarding hop
+    cltv_construction: CltvConstruction,
+    // Total timelock cap the attacker assumes senders' implementations use
+    sender_cltv_cap: Option<u32>,
+    // Leave payments that look like probes out of the analysis
+    exclude_probes: bool,
+    // Lock payments with PTLCs and link observations by fingerprint instead of hash
+    ptlc: bool,
+    analysis_mode: AnalysisMode,
+    // Route searches the analyzer keeps for reuse
+    route_cache_capacity: usize,
+    // Bytes one observation's route enumeration may take before it's sampled instead
+    memory_budget: Option<usize>,
+    // Most hops routes are searched, if not as deep as each observation likely needs
+    max_search_hops: Option<usize>,
+    // How long one payment's analysis may take, if limited
+    time_budget: Option<Duration>,
+    // Nodes candidates are restricted to, if not every node
+    recipient_profile: Option<RecipientProfile>,
+    // Concurrent tasks the payment workload is spread across
+    workers: usize,
+    // Path selection for every sender, and overrides for individual senders
+    router: Arc<dyn Router>,
+    node_routers: Vec<(String, Arc<dyn Router>)>,
+    // How payment amounts are drawn
+    amounts: AmountDistribution,
+    // Directory observations beyond `spill_limit` are written to
+    spill_dir: Option<String>,
+    spill_limit: usize,
+    // Refine candidates as observations arrive
+    live_analysis: bool,
+    // Address the Prometheus metrics endpoint listens on
+    metrics_addr: Option<String>,
+    // Show the full-screen live view while simulating
+    tui: bool,
+    // Address `thelma serve` listens on, and whether it also serves the dashboard
+    serve_addr: String,
+    dashboard: bool,
+    // Largest request body `thelma serve` accepts, in bytes
+    max_body: Option<usize>,
+    // Save progress every this many payments, to `checkpoint_file`
+    checkpoint_every: Option<usize>,
+    checkpoint_file: String,
+    // The command line itself, saved with checkpoints
+    args: Vec<String>,
+    // File the baseline traffic is recorded to
+    trace_file: Option<String>,
+    // File a dynamic GEXF timeline of the traffic is written to
+    gexf_file: Option<String>,
+    // Payments between snapshots of the graph, diffed in the network report
+    snapshot_every: Option<u64>,
+    // File a Cytoscape.js elements JSON of the network and the attacker's conclusions goes to
+    cytoscape_file: Option<String>,
+    // File each payment's ground truth and the attacker's top `top_k` candidates go to
+    inference_diff_file: Option<String>,
+    top_k: usize,
+    // File the ROC and precision-recall points of the attacker's calls go to
+    roc_file: Option<String>,
+    // Report templates each rendered to their own file, with the JSON report as context
+    templates: Vec<String>,
+    // Adversary a recorded trace is replayed against, or whose coverage is estimated
+    replay_malicious: Vec<String>,
+    // Nodes attack-place may choose (the malicious count when unset), and payments it
+    // samples to estimate coverage
+    placement_budget: Option<usize>,
+    traffic_samples: usize,
+    // Parameter a study sweeps and the values it takes, with repeats per value from `seed`
+    study_parameter: Option<StudyParameter>,
+    study_values: Vec<usize>,
+    repeats: usize,
+    // Seed of a run, or of a study's first repeat; random for runs when unset
+    seed: Option<u64>,
+    // Chart the results, as png or svg
+    plot: bool,
+    plot_format: String,
+    // Rewrite the golden report instead of checking against it, and how far floats may drift
+    golden_update: bool,
+    golden_tolerance: f64,
+    // Database runs are recorded in, and whether this run is
+    registry_file: String,
+    record_run: bool,
+}
+
+// Where a resumed run picks up
+struct ResumePoint {
+    payments_attempted: usize,
+    payment_records: Vec<PaymentRecord>,
+    trace_offset: u64,
+}
 
+#[tokio::main]
+async fn main() -> ExitCode {
     // Parse command line args
     let args: Vec<String> = env::args().collect();
-    let (node_count, payment_count, malicious_count) = parse_args(&args);
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        print_usage();
+        return ExitCode::SUCCESS;
+    }
+    logging::init(Verbosity::from_args(&args));
+    let timer = PhaseTimer::install();
 
-    println!("Simulation parameters:");
-    println!("  Network size:      {} nodes", node_count);
-    println!("  Payments to sim:   {}", payment_count);
-    println!("  Malicious nodes:   {}", malicious_count);
+    match run(&args).await {
+        Ok(()) => {
+            info!("\n{}", timer.generate_text_report());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("\nError: {}", e);
+            if let ThelmaError::Config(_) = e {
+                eprintln!("Run `thelma --help` for the available options.");
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
 
-    // Initialize network with current block height
-    let current_block_height = 780000;
-    let network_map = Arc::new(Mutex::new(LightningNetworkMap::new(current_block_height)));
+async fn run(args: &[String]) -> Result<(), ThelmaError> {
+    info!("Starting THELMA: Timelock Heuristic Evaluation for Lightning Movement Analysis");
+    info!("==========================================================================");
 
-    // Create a simulated network
-    println!("\nGenerating network topology...");
-    let mut generator = NetworkGenerator::new();
-    generator.create_scale_free_network(network_map.clone(), node_count, 3)?;
+    // `thelma resume <file>` picks a checkpointed run back up with its original settings
+    let resumed = match args.get(1).map(String::as_str) {
+        Some("replay") => return replay(args),
+        Some("serve") => return serve(args).await,
+        Some("shell") => return shell(args).await,
+        Some("attack-place") => return attack_place(args),
+        Some("coverage") => return coverage(args),
+        Some("study") => return study(args).await,
+        Some("golden") => return golden(args).await,
+        Some("runs") => return runs(args),
+        Some("resume") => {
+            let path = args.get(2)
+                .ok_or_else(|| ThelmaError::Config("resume needs a checkpoint file".to_string()))?;
+            let checkpoint = Checkpoint::load(path)?;
+            info!("Resuming {} after {} payments", path, checkpoint.payments_attempted);
+            Some((path.clone(), checkpoint))
+        }
+        _ => None,
+    };
+    let mut options = parse_args(resumed.as_ref().map_or(args, |(_, checkpoint)| &checkpoint.args));
+    let resumed = resumed.map(|(path, checkpoint)| {
+        // Keep checkpointing to the file the run was resumed from
+        options.checkpoint_file = path;
+        checkpoint
+    });
+    options.regions = options.regions_spec.as_deref().map(RegionLatency::load).transpose()?;
+    // Catch a broken template before spending a whole run on it
+    let templates = load_templates(&options)?;
+    // Files written from here on are this run's outputs
+    let started = SystemTime::now();
+    let (node_count, payment_count, malicious_count) =
+        (options.node_count, options.payment_count, options.malicious_count);
+    // Catch a bad chart format before spending a whole run on it
+    #[cfg(feature = "plots")]
+    if options.plot {
+        plot_format(&options)?;
+    }
 
-    // Select some nodes to be malicious observers
-    println!("\nSelecting malicious surveillance nodes...");
-    let malicious_nodes = generator.select_malicious_nodes(network_map.clone(), malicious_count);
+    info!("Simulation parameters:");
+    info!("  Network size:      {} nodes", node_count);
+    match (options.topology.as_str(), &options.topology_file) {
+        ("fixture", Some(fixture)) => info!("  Topology:          {} fixture (node count ignored)", fixture),
+        _ => info!("  Topology:          {}", options.topology),
+    }
+    info!("  Payments to sim:   {}", payment_count);
+    info!("  Malicious nodes:   {}", malicious_count);
+    if let Some(decoy) = &options.decoy_hops {
+        info!("  Decoy hops:        p={:.2}, up to {} extra", decoy.probability, decoy.max_extra_hops);
+    }
+    if let Some(cover) = &options.cover_traffic {
+        info!("  Cover traffic:     {:.2} dummy payments per payment", cover.rate);
+    }
+    if let Some(padding) = &options.latency_padding {
+        info!("  Latency padding:   up to {} ms per honest hop", padding.max_delay_ms);
+    }
+    if let Some(overpayment) = &options.overpayment {
+        info!("  Overpayment:       up to {:.1}% of the amount", overpayment.max_share * 100.0);
+    }
+    if let Some(splitting) = &options.payment_splitting {
+        info!("  Payment splitting: up to {} shards over disjoint routes", splitting.max_shards);
+    }
+    if let (Some(spec), Some(regions)) = (&options.regions_spec, &options.regions) {
+        info!("  Regions:           {} ({} regions, {} ms between nodes on average)",
+              spec, regions.regions.len(), regions.mean_ms());
+    }
+    if options.tor_share > 0.0 {
+        info!("  Tor-only nodes:    {:.0}% of generated nodes", options.tor_share * 100.0);
+    }
+    if options.lsp_clients > 0 {
+        info!("  LSP clients:       {} behind {} LSPs, {:.0}% on just-in-time channels",
+              options.lsp_clients, options.lsps, options.jit_share * 100.0);
+    }
+    if options.workers > 1 {
+        info!("  Workers:           {}", options.workers);
+    }
+    info!("  Router:            {}", options.router.name());
+    for (node, router) in &options.node_routers {
+        info!("    {} routes with {}", node, router.name());
+    }
+
+    // One seed generates the network, places the adversary and draws the payments, so the run
+    // registry's record of it reproduces the run with `--seed`
+    let seed = match &resumed {
+        Some(checkpoint) => checkpoint.rng_seed,
+        None => options.seed.unwrap_or_else(rand::random),
+    };
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    // Continue a checkpointed run on its own network and adversary, or set up a fresh one
+    let (network_map, mut operation, progress) = match resumed {
+        Some(checkpoint) => {
+            let network_map = Arc::new(RwLock::new(checkpoint.network));
+            let config = surveillance_config(&options, &network_map,
+                                             SurveillanceConfig::observing(checkpoint.malicious_nodes));
+            let mut operation = SurveillanceOperation::new(network_map.clone(), config)?;
+            operation.record_multiple_observations(checkpoint.observations)?;
+            let progress = ResumePoint {
+                payments_attempted: checkpoint.payments_attempted,
+                payment_records: checkpoint.payment_records,
+                trace_offset: checkpoint.trace_offset,
+            };
+            (network_map, operation, Some(progress))
+        }
+        None => {
+            let (network_map, operation) = start_experiment(&options, seed)?;
+            (network_map, operation, None)
+        }
+    };
+    let malicious_nodes = operation.get_malicious_nodes().to_vec();
 
-    println!("Malicious nodes:");
+    info!("Malicious nodes:");
     for node in &malicious_nodes {
-        let network = network_map.lock().unwrap();
+        let network = read_lock(&network_map);
         let alias = match network.nodes.get(node) {
             Some(n) => n.alias.clone(),
             None => "Unknown".to_string(),
         };
-        println!("  • {} ({})", alias, node);
+        info!("  • {} ({})", alias, node);
     }
 
-    // Initialize surveillance operation
-    let surveillance = Arc::new(Mutex::new(
-        SurveillanceOperation::new(network_map.clone(), malicious_nodes)
-    ));
+    // Mobile-wallet clients behind the best-connected nodes. Checkpoints keep the clients but
+    // not which of them wait for a just-in-time channel, so resumed runs skip the comparison.
+    let lsp_clients = if options.lsp_clients == 0 {
+        None
+    } else if progress.is_some() {
+        warn!("LSP clients aren't checkpointed, skipping the LSP comparison for the resumed run");
+        None
+    } else {
+        let mut network = write_lock(&network_map);
+        let lsps = trampoline_nodes(&network, options.lsps);
+        info!("LSPs: {}", lsps.join(", "));
+        Some(LspClients::attach(&mut network, lsps, options.lsp_clients, options.jit_share, &mut rng))
+    };
+    let jit_channels = lsp_clients.as_ref().map(|clients| clients.jit_clients.clone()).unwrap_or_default();
 
-    // Run the simulation
-    println!("\nSimulating {} Lightning payments...", payment_count);
-    let mut simulator = PaymentSimulator::new(network_map.clone(), surveillance.clone(), 50);
-    let observed = simulator.simulate_payments(payment_count).await?;
+    // Expose ingestion and analysis counters for operators to scrape
+    if let Some(addr) = &options.metrics_addr {
+        if !options.live_analysis {
+            warn!("Without --live only observations are counted on the metrics endpoint");
+        }
+        let metrics = Arc::new(SurveillanceMetrics::new());
+        operation.set_metrics(metrics.clone());
+        serve_metrics(addr, metrics).await?;
+    }
+    let surveillance = Arc::new(Mutex::new(operation));
 
-    println!("\nSimulation complete. {}/{} payments observed by surveillance nodes.",
-             observed, payment_count);
+    // Competing adversaries each watch the same traffic from their own share of the nodes.
+    // Checkpoints only keep the pooled adversary's observations, so resumed runs skip them.
+    let mut coalitions = Vec::new();
+    if !options.coalitions.is_empty() && progress.is_some() {
+        warn!("Coalitions aren't checkpointed, skipping them for the resumed run");
+    } else {
+        for (i, nodes) in split_coalitions(&malicious_nodes, &options.coalitions).into_iter().enumerate() {
+            let config = surveillance_config(&options, &network_map, SurveillanceConfig::observing(nodes.clone()));
+            let operation = SurveillanceOperation::new(network_map.clone(), config)?;
+            info!("Coalition {}: {}", i + 1, nodes.join(", "));
+            coalitions.push((format!("Coalition {}", i + 1), nodes, Arc::new(Mutex::new(operation))));
+        }
+    }
+
+    // Malicious LSPs, and as many malicious nodes placed at random, watching the same traffic
+    let mut lsp_adversaries = Vec::new();
+    if let Some(clients) = &lsp_clients {
+        let mut candidates: Vec<String> = read_lock(&network_map).nodes.keys()
+            .filter(|node| !clients.is_client(node) && !clients.lsps.contains(node))
+            .cloned()
+            .collect();
+        candidates.sort();
+        let random: Vec<String> = candidates.choose_multiple(&mut rng, clients.lsps.len()).cloned().collect();
+        for (label, nodes) in [("Malicious LSPs", clients.lsps.clone()), ("Random placement", random)] {
+            let config = surveillance_config(&options, &network_map, SurveillanceConfig::observing(nodes.clone()));
+            let operation = SurveillanceOperation::new(network_map.clone(), config)?;
+            lsp_adversaries.push((label.to_string(), nodes, Arc::new(Mutex::new(operation))));
+        }
+    }
+
+    // Run the simulation; Ctrl-C stops it and reports on what was simulated so far
+    let stop = watch_for_interrupt();
+    let mut config = simulator_config(&options, &malicious_nodes, &jit_channels, &stop)
+        .trampolines(trampoline_nodes(&read_lock(&network_map), options.trampolines));
+    config = config.seed(seed);
+    let mut simulator = PaymentSimulator::new(network_map.clone(), config);
+
+    // Record the traffic so it can be replayed against other adversaries
+    let recorder = match (&options.trace_file, &progress) {
+        (Some(path), Some(progress)) => Some(TraceRecorder::resume(path, progress.trace_offset,
+                                                                   progress.payments_attempted as u64)?),
+        (Some(path), None) => Some(TraceRecorder::create(path, &read_lock(&network_map))?),
+        (None, _) => None,
+    }.map(|recorder| Arc::new(Mutex::new(recorder)));
+
+    // Timeline of the traffic for Gephi
+    let timeline = options.gexf_file.as_ref()
+        .map(|_| Arc::new(Mutex::new(GexfRecorder::new(network_map.clone(), &malicious_nodes))));
+
+    // Snapshots of the graph to diff, for runs where it churns
+    let snapshots = options.snapshot_every
+        .map(|interval| Arc::new(Mutex::new(SnapshotRecorder::new(network_map.clone(), interval))));
+
+    if let Some(progress) = progress {
+        simulator.resume(progress.payments_attempted, progress.payment_records);
+    }
+
+    // Watch the run unfold in a full-screen view instead of the log
+    let live_view = if options.tui {
+        let stats = Arc::new(Mutex::new(LiveStats::new(&malicious_nodes, payment_count, simulator.payments_attempted())));
+        let view = LiveView::start(stats.clone(), surveillance.clone(), stop.clone())?;
+        Some((stats, view))
+    } else {
+        None
+    };
+
+    // Simulate in slices so everything observed so far can be checkpointed in between
+    info!("\nSimulating {} Lightning payments...", payment_count.saturating_sub(simulator.payments_attempted()));
+    let slice = options.checkpoint_every.unwrap_or(payment_count).max(1);
+    while simulator.payments_attempted() < payment_count && !simulator.was_interrupted() {
+        let count = slice.min(payment_count - simulator.payments_attempted());
+        let observer = simulator.register_observer(surveillance.clone());
+        let tracer = recorder.as_ref().map(|recorder| simulator.register_observer(recorder.clone()));
+        let viewer = live_view.as_ref().map(|(stats, _)| simulator.register_observer(stats.clone()));
+        let animator = timeline.as_ref().map(|timeline| simulator.register_observer(timeline.clone()));
+        let snapshotter = snapshots.as_ref().map(|snapshots| simulator.register_observer(snapshots.clone()));
+        let coalition_observers: Vec<_> = coalitions.iter().chain(lsp_adversaries.iter())
+            .map(|(_, _, operation)| simulator.register_observer(operation.clone()))
+            .collect();
+        simulator.simulate_payments(count).await?;
+        simulator.close_events();
+        observer.await?;
+        for coalition_observer in coalition_observers {
+            coalition_observer.await?;
+        }
+        if let Some(tracer) = tracer {
+            tracer.await?;
+        }
+        if let Some(viewer) = viewer {
+            viewer.await?;
+        }
+        if let Some(animator) = animator {
+            animator.await?;
+        }
+        if let Some(snapshotter) = snapshotter {
+            snapshotter.await?;
+        }
+
+        if options.checkpoint_every.is_some() {
+            let trace_offset = match &recorder {
+                Some(recorder) => lock_mutex(recorder).offset()?,
+                None => 0,
+            };
+            save_checkpoint(&options, &mut simulator, &surveillance, &network_map, &malicious_nodes, trace_offset)?;
+        }
+    }
+    if let Some((_, view)) = live_view {
+        view.finish();
+    }
+    if let (Some(recorder), Some(path)) = (&recorder, &options.trace_file) {
+        lock_mutex(recorder).offset()?;
+        info!("Traffic trace saved to {}", path);
+    }
+    if let (Some(timeline), Some(path)) = (&timeline, &options.gexf_file) {
+        lock_mutex(timeline).save_to_file(path)?;
+    }
+    let observed = simulator.payment_records().iter().filter(|r| r.observed && !r.cover).count();
+
+    let interrupted = simulator.was_interrupted();
+    if interrupted {
+        let simulated = simulator.payments_attempted();
+        info!("\nSimulation interrupted. {}/{} payments observed out of {} simulated.",
+              observed, payment_count, simulated);
+        lock_mutex(&surveillance).mark_partial(simulated, payment_count);
+    } else {
+        info!("\nSimulation complete. {}/{} payments observed by surveillance nodes.",
+              observed, payment_count);
+    }
+    let missed = lock_mutex(&surveillance).missed_observations();
+    if missed > 0 {
+        info!("{} HTLCs went unseen while malicious nodes were offline", missed);
+    }
 
     // Generate and print the report
-    println!("\nGenerating surveillance analysis report...");
-    let surveillance = surveillance.lock().unwrap();
-    let report = surveillance.generate_report();
+    let reporting = info_span!("reporting").entered();
+    lock_mutex(&surveillance).set_ground_truth(simulator.payment_records());
+    if let Some(linking) = lock_mutex(&surveillance).score_linking(simulator.hop_owners()) {
+        info!("\nFingerprint linking grouped {} observations into {} payments (really {}): \
+               precision {:.1}%, recall {:.1}%",
+              linking.observations, linking.clusters, linking.payments,
+              linking.precision() * 100.0, linking.recall() * 100.0);
+    }
+    info!("\nGenerating surveillance analysis report...");
+    // One analysis serves every report and export below
+    let analysis_started = Instant::now();
+    let results = lock_mutex(&surveillance).run_analysis();
+    let analysis_time = analysis_started.elapsed();
+    // Under PTLCs results are keyed by the linker's clusters; ground truth by payment
+    let attributed = attribute_to_payments(results.clone(), simulator.hop_owners());
+    {
+        let surveillance = lock_mutex(&surveillance);
+        // The full report lists every candidate route, so it only goes to the console
+        // when asked for; it is always saved below
+        if log::log_enabled!(log::Level::Debug) {
+            debug!("\n{}", surveillance.generate_report(&results));
+        }
+
+        // Save the report to a file
+        surveillance.save_report(&results, "thelma_report.md")?;
+
+        // Also save as JSON for programmatic use
+        let json_report = surveillance.generate_json_report(&results);
+        std::fs::write("thelma_report.json", json_report)?;
+
+        info!("\nReports saved to thelma_report.md and thelma_report.json");
+        save_templated_reports(&templates, &surveillance, &results)?;
+
+        if let Some(path) = &options.cytoscape_file {
+            let export = CytoscapeExport::compute(&read_lock(&network_map), &malicious_nodes,
+                                                  simulator.payment_records(), &attributed);
+            export.save_to_file(path)?;
+        }
+        if let Some(path) = &options.inference_diff_file {
+            InferenceDiff::compute(simulator.payment_records(), &attributed, &malicious_nodes,
+                                   options.top_k).save_to_file(path)?;
+        }
+        if let Some(path) = &options.roc_file {
+            RocCurve::compute(simulator.payment_records(), &attributed).save_csv(path)?;
+        }
+
+        // Report exposure from the point of view of a single node operator
+        if let Some(node_id) = &options.defender_node {
+            let alias = read_lock(&network_map).nodes.get(node_id).map(|n| n.alias.clone());
+            if alias.is_none() {
+                warn!("\nDefender node {} is not in the network, skipping defender view", node_id);
+            } else {
+                let view = DefenderView::compute(node_id, alias, simulator.payment_records(), &attributed, &surveillance);
+
+                info!("\n{}", view.generate_text_report());
+                view.save_report_to_file("thelma_defender_view.md")?;
+                std::fs::write("thelma_defender_view.json", view.generate_json_report())?;
+            }
+        }
+    }
+
+    // Catch whatever changed after the last full interval
+    if let Some(snapshots) = &snapshots {
+        lock_mutex(snapshots).snapshot();
+    }
+
+    // Describe the topology so readers can judge whether results generalize,
+    // and rank honest nodes by how much traffic their position should attract
+    {
+        let network = read_lock(&network_map);
+        let statistics = NetworkStatistics::compute(&network);
+        let centrality = CentralityScores::compute(&network);
+
+        let mut network_report = statistics.generate_text_report();
+        network_report.push('\n');
+        network_report.push_str(&centrality.generate_traffic_report(&network, &malicious_nodes,
+                                                                    simulator.payment_records(), 10));
+        if let Some(snapshots) = &snapshots {
+            let snapshots = lock_mutex(snapshots);
+            network_report.push('\n');
+            network_report.push_str(&snapshots.generate_text_report());
+            std::fs::write("thelma_snapshots.json", snapshots.generate_json_report())?;
+        }
+        info!("\n{}", network_report);
+        std::fs::write("thelma_network.md", network_report)?;
+        std::fs::write("thelma_network.json", statistics.generate_json_report())?;
+    }
+
+    let mut baseline_metrics = score_results("Baseline", simulator.payment_records(), simulator.hop_owners(),
+                                             (&attributed, analysis_time), &surveillance, &network_map);
+    baseline_metrics.record_latency(simulator.waiting_ms());
+    baseline_metrics.record_overpayment(simulator.overpaid_msat());
+    let headline_metrics = baseline_metrics.to_json();
+
+    // Weigh what the attack cost against what it achieved
+    let economics = {
+        let network = read_lock(&network_map);
+        AttackEconomics::compute(&options.budget, &malicious_nodes, &network,
+                                 simulator.payment_records(), baseline_metrics.recipients_identified)
+    };
+    info!("\n{}", economics.generate_text_report());
+    economics.save_report_to_file("thelma_economics.md")?;
+    std::fs::write("thelma_economics.json", economics.generate_json_report())?;
+    if options.plot {
+        draw_plots(&options, simulator.payment_records(), &results, &malicious_nodes)?;
+    }
+
+    // Score every coalition on what it saw alone against the pooled adversary
+    if !coalitions.is_empty() {
+        let mut comparison = CoalitionComparison::new(CoalitionResult::compute("All malicious nodes", &malicious_nodes,
+                                                                              simulator.payment_records(), &attributed));
+        for (label, nodes, operation) in &coalitions {
+            let results = attribute_to_payments(lock_mutex(operation).run_analysis(), simulator.hop_owners());
+            comparison.add_coalition(CoalitionResult::compute(label, nodes, simulator.payment_records(), &results));
+        }
+        info!("\n{}", comparison.generate_text_report());
+        comparison.save_report_to_file("thelma_coalitions.md")?;
+        std::fs::write("thelma_coalitions.json", comparison.generate_json_report())?;
+    }
+
+    // How badly malicious LSPs expose their clients, against nodes placed at random and the
+    // adversary as configured
+    if let Some(clients) = &lsp_clients {
+        let opened = clients.jit_channels_opened(&read_lock(&network_map));
+        let mut comparison = LspComparison::new(clients, opened);
+        for (label, nodes, operation) in &lsp_adversaries {
+            let results = attribute_to_payments(lock_mutex(operation).run_analysis(), simulator.hop_owners());
+            comparison.add_scenario(LspScenario::compute(label, nodes, clients, simulator.payment_records(), &results));
+        }
+        comparison.add_scenario(LspScenario::compute("Configured adversary", &malicious_nodes, clients,
+                                                     simulator.payment_records(), &attributed));
+        info!("\n{}", comparison.generate_text_report());
+        comparison.save_report_to_file("thelma_lsp.md")?;
+        std::fs::write("thelma_lsp.json", comparison.generate_json_report())?;
+    }
+
+    // What malicious trampolines learned against malicious nodes on ordinary routes
+    if options.trampolines > 0 {
+        let report = TrampolineReport::compute(&lock_mutex(&surveillance), simulator.payment_records(),
+                                               simulator.hop_owners());
+        info!("\n{}", report.generate_text_report());
+        report.save_report_to_file("thelma_trampoline.md")?;
+        std::fs::write("thelma_trampoline.json", report.generate_json_report())?;
+    }
+
+    // What the adversary knew as payments happened, or at a given time, against hindsight
+    {
+        let surveillance = lock_mutex(&surveillance);
+        if let Some(sharing) = surveillance.sharing() {
+            let score = |label: &str, results| {
+                let results = attribute_to_payments(results, simulator.hop_owners());
+                CoalitionResult::compute(label, &malicious_nodes, simulator.payment_records(), &results)
+            };
+            let mut report = SharingReport::new(sharing);
+            report.add_analysis(score("Retrospective", surveillance.run_analysis_at(u64::MAX)));
+            report.add_analysis(score("Real-time", surveillance.run_realtime_analysis()));
+            if let Some(at) = options.analyze_at {
+                report.add_analysis(score(&format!("After payment {}", at), surveillance.run_analysis_at(at)));
+            }
+            info!("\n{}", report.generate_text_report());
+            report.save_report_to_file("thelma_sharing.md")?;
+            std::fs::write("thelma_sharing.json", report.generate_json_report())?;
+        }
+    }
+    drop(reporting);
+
+    // Defended scenarios replay the full workload, which an interrupted run never finished
+    let mut comparison = DefenseComparison::new(baseline_metrics);
+    if interrupted {
+        if options.decoy_hops.is_some() || options.cover_traffic.is_some() || options.latency_padding.is_some()
+            || options.overpayment.is_some() || options.payment_splitting.is_some() {
+            info!("\nSkipping defense scenarios after interruption");
+        }
+        comparison.save_latex_to_file("thelma_tables.tex")?;
+        return register_run(&options, args, started, seed, headline_metrics);
+    }
+
+    let mut defended = false;
+
+    // Re-run the same workload against the same adversary with each defense enabled
+    if let Some(decoy) = options.decoy_hops.clone() {
+        info!("\nSimulating {} payments with decoy-hop route padding...", payment_count);
+        let label = format!("Decoy hops (p={:.2}, depth={})", decoy.probability, decoy.max_extra_hops);
+        let (metrics, _) = run_defended_scenario(&network_map, &malicious_nodes, &jit_channels, &options, &stop,
+                                            &label, |config| config.decoy_hops(decoy)).await?;
+        comparison.add_scenario(metrics);
+        defended = true;
+    }
+
+    if let Some(cover) = options.cover_traffic.clone() {
+        info!("\nSimulating {} payments with cover traffic...", payment_count);
+        let label = format!("Cover traffic (rate={:.2})", cover.rate);
+        let (metrics, _) = run_defended_scenario(&network_map, &malicious_nodes, &jit_channels, &options, &stop,
+                                            &label, |config| config.cover_traffic(cover)).await?;
+        comparison.add_scenario(metrics);
+        defended = true;
+    }
+
+    if let Some(padding) = options.latency_padding.clone() {
+        info!("\nSimulating {} payments with latency padding...", payment_count);
+        let label = format!("Latency padding (up to {} ms)", padding.max_delay_ms);
+        let (metrics, _) = run_defended_scenario(&network_map, &malicious_nodes, &jit_channels, &options, &stop,
+                                            &label, |config| config.latency_padding(padding)).await?;
+        comparison.add_scenario(metrics);
+        defended = true;
+    }
+
+    if let Some(overpayment) = options.overpayment.clone() {
+        info!("\nSimulating {} payments with random overpayment...", payment_count);
+        let label = format!("Overpayment (up to {:.1}%)", overpayment.max_share * 100.0);
+        let (metrics, _) = run_defended_scenario(&network_map, &malicious_nodes, &jit_channels, &options, &stop,
+                                            &label, |config| config.overpayment(overpayment)).await?;
+        comparison.add_scenario(metrics);
+        defended = true;
+    }
+
+    if let Some(splitting) = options.payment_splitting.clone() {
+        info!("\nSimulating {} payments split over disjoint routes...", payment_count);
+        let label = format!("Payment splitting (up to {} shards)", splitting.max_shards);
+        let (metrics, split_simulator) = run_defended_scenario(&network_map, &malicious_nodes, &jit_channels, &options, &stop,
+                                                               &label, |config| config.payment_splitting(splitting)).await?;
+        comparison.add_scenario(metrics);
+        defended = true;
+
+        // How much of each payment malicious intermediaries saw, whole and split
+        let mut report = SplittingReport::new(ShardExposure::compute("Baseline", simulator.payment_records(),
+                                                                     simulator.payment_shards(), &malicious_nodes));
+        report.add_scenario(ShardExposure::compute(&label, split_simulator.payment_records(),
+                                                   split_simulator.payment_shards(), &malicious_nodes));
+        info!("\n{}", report.generate_text_report());
+        report.save_report_to_file("thelma_splitting.md")?;
+        std::fs::write("thelma_splitting.json", report.generate_json_report())?;
+    }
+
+    if defended {
+        info!("\n{}", comparison.generate_text_report());
+        comparison.save_report_to_file("thelma_defense_comparison.md")?;
+        std::fs::write("thelma_defense_comparison.json", comparison.generate_json_report())?;
+    }
+    comparison.save_latex_to_file("thelma_tables.tex")?;
+
+    register_run(&options, args, started, seed, headline_metrics)
+}
+
+fn load_templates(options: &CliOptions) -> Result<Vec<ReportTemplate>, ThelmaError> {
+    options.templates.iter().map(ReportTemplate::load).collect()
+}
+
+// Render each template with the run's report and save it next to the built-in ones
+fn save_templated_reports(templates: &[ReportTemplate], surveillance: &SurveillanceOperation,
+                          results: &HashMap<String, Vec<PotentialRecipient>>) -> Result<(), ThelmaError> {
+    for template in templates {
+        std::fs::write(template.output_file(), surveillance.render_template(template, results)?)?;
+        info!("Rendered {} to {}", template.name(), template.output_file());
+    }
+    Ok(())
+}
+
+// Record a finished run in the registry: its settings, seed, commit, baseline metrics and
+// every file it wrote
+fn register_run(options: &CliOptions, args: &[String], started: SystemTime, seed: u64,
+                metrics: serde_json::Value) -> Result<(), ThelmaError> {
+    if !options.record_run {
+        return Ok(());
+    }
+    let started_at = started.duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+    let config = serde_json::json!({
+        "nodes": options.node_count,
+        "payments": options.payment_count,
+        "malicious": options.malicious_count,
+        "topology": options.topology,
+        "topology_file": options.topology_file,
+        "router": options.router.name(),
+        "workers": options.workers,
+    });
+
+    // Reports land in the working directory under fixed names; the rest wherever the options said
+    let mut artifacts: Vec<std::path::PathBuf> = std::fs::read_dir(".")?
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            name.starts_with("thelma_") && name != options.registry_file
+                && entry.metadata().and_then(|m| m.modified()).is_ok_and(|modified| modified >= started)
+        })
+        .map(|entry| entry.path())
+        .collect();
+    let checkpoint = options.checkpoint_every.map(|_| options.checkpoint_file.clone());
+    for path in [&options.trace_file, &options.gexf_file, &options.cytoscape_file,
+                 &options.inference_diff_file, &options.roc_file, &checkpoint].into_iter().flatten() {
+        artifacts.push(path.into());
+    }
+    let mut artifacts: Vec<String> = artifacts.into_iter()
+        .filter_map(|path| std::fs::canonicalize(path).ok())
+        .map(|path| path.display().to_string())
+        .collect();
+    artifacts.sort();
+    artifacts.dedup();
+
+    let run = RunRecord::new(args, started_at)
+        .seed(seed)
+        .config(config)
+        .metrics(metrics)
+        .artifacts(artifacts);
+    let id = RunRegistry::open(&options.registry_file)?.record(&run)?;
+    info!("\nRun {} recorded in {}", id, options.registry_file);
+    Ok(())
+}
+
+// Apply the network options to a generator
+fn network_generator(options: &CliOptions, generator: NetworkGenerator) -> Result<NetworkGenerator, ThelmaError> {
+    let mut generator = generator
+        .fees(options.fees)
+        .parallel_channels(options.parallel_channels)
+        .refuse_dust(options.refuse_dust)
+        .tor_only(options.tor_share)
+        .inactive_channels(options.disabled_channels, options.zombie_channels);
+    if let Some(regions) = &options.regions {
+        generator = generator.regions(regions.clone());
+    }
+    if let Some(schedule) = options.gossip {
+        generator = generator.gossip(schedule);
+    }
+    if let Some(max_accepted_htlcs) = options.max_accepted_htlcs {
+        generator = generator.max_accepted_htlcs(max_accepted_htlcs);
+    }
+    if let Some(path) = &options.cltv_delta_file {
+        generator = generator.cltv_deltas(CltvDeltaDistribution::load(path)?);
+    }
+    if let Some(mix) = &options.shadow_offsets {
+        generator = generator.shadow_offsets(mix.clone());
+    }
+    for (node, offset) in &options.node_shadow_offsets {
+        generator = generator.node_shadow_offset(node, *offset);
+    }
+    Ok(generator)
+}
+
+// Generate the network and let the adversary's strategy pick its observers and spend
+// its capital
+// Build the run's topology into `network_map` with the options' policies, labels applied
+fn generate_network(options: &CliOptions,
+                    network_map: &Arc<RwLock<LightningNetworkMap>>,
+                    seed: u64,
+                    node_count: usize) -> Result<(), ThelmaError> {
+    let mut generator = network_generator(options, NetworkGenerator::seeded(seed))?;
+    let mut topology = topology_from_name(&options.topology, options.topology_file.as_deref())?;
+    generator.create_network(network_map.clone(), topology.as_mut(), node_count)?;
+    if let Some(path) = &options.labels_file {
+        NodeLabels::load(path)?.apply(&mut write_lock(network_map));
+    }
+    Ok(())
+}
+
+fn start_experiment(options: &CliOptions, seed: u64) -> Result<(Arc<RwLock<LightningNetworkMap>>, SurveillanceOperation), ThelmaError> {
+    // Initialize network with current block height
+    let current_block_height = 780000;
+    let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(current_block_height)));
+
+    // Create a simulated network
+    info!("\nGenerating network topology...");
+    generate_network(options, &network_map, seed, options.node_count)?;
+
+    info!("\nSelecting malicious surveillance nodes...");
+    let strategy: Box<dyn AdversaryStrategy> = match options.placement {
+        Some(measure) => Box::new(CentralPlacement::new(measure)),
+        None => Box::new(RandomPlacement::seeded(seed)),
+    };
+    let config = surveillance_config(options, &network_map,
+                                     SurveillanceConfig::with_strategy(strategy, options.malicious_count));
+    let mut operation = SurveillanceOperation::new(network_map.clone(), config)?;
+    if options.attack_capital_sat > 0 {
+        let opened = operation.invest(options.attack_capital_sat);
+        info!("Adversary opened {} channels with {} sat", opened, options.attack_capital_sat);
+    }
+
+    Ok((network_map, operation))
+}
+
+// Apply the analysis options to the adversary's configuration
+fn surveillance_config(options: &CliOptions,
+                       network_map: &Arc<RwLock<LightningNetworkMap>>,
+                       config: SurveillanceConfig) -> SurveillanceConfig {
+    let mut config = config
+        .max_cltv_expiry(options.max_cltv_expiry)
+        .cltv_construction(options.cltv_construction)
+        .analysis_mode(options.analysis_mode)
+        .route_cache_capacity(options.route_cache_capacity)
+        .accuracy_thresholds(options.accuracy_thresholds.clone());
+    if options.live_analysis {
+        config = config.live_analysis();
+    }
+    if let Some(bytes) = options.memory_budget {
+        config = config.memory_budget(bytes);
+    }
+    if let Some(hops) = options.max_search_hops {
+        config = config.max_search_hops(hops);
+    }
+    if let Some(budget) = options.time_budget {
+        config = config.time_budget(budget);
+    }
+    if let Some(profile) = &options.recipient_profile {
+        config = config.plausible_recipients(profile.clone());
+    }
+    if let Some(dir) = &options.spill_dir {
+        config = config.spill_to(dir, options.spill_limit, DEFAULT_SPILL_PARTITIONS);
+    }
+    if let Some(cap) = options.sender_cltv_cap {
+        config = config.sender_cltv_cap(cap);
+    }
+    if options.exclude_probes {
+        config = config.exclude_probes(ProbeDetector::default());
+    }
+    if options.ptlc {
+        config = config.link_by_fingerprint(FingerprintLinker::default());
+    }
+    if let Some(penalty) = options.stale_penalty {
+        config = config.deprioritize_stale_channels(penalty, DEFAULT_STALE_AFTER_BLOCKS);
+    }
+    if let Some(blocks) = options.channel_maturity {
+        config = config.weigh_channel_age(blocks);
+    }
+    if options.settlement_timing {
+        config = config.time_settlements(settlement_hop_ms(options));
+        if let Some(regions) = &options.regions {
+            config = config.infer_geography(regions.clone());
+        }
+    }
+    if let Some(uptime) = options.uptime {
+        config = config.observation_windows(uptime, options.uptime_period);
+    }
+    if options.share_delay.is_some() || options.analyze_at.is_some() {
+        let sharing = ObservationSharing::new(options.share_delay.unwrap_or(0)).cost_msat(options.share_cost_msat);
+        config = config.share_observations(sharing);
+    }
+    if let Some(threshold) = options.community_threshold {
+        let communities = Communities::detect(&read_lock(network_map));
+        info!("Detected {} communities for cluster-level inference", communities.count());
+        config = config.community_inference(communities, threshold);
+    }
+    config
+}
+
+// Answer API requests until the process is stopped. Analysis options apply to every
+// graph uploaded.
+async fn serve(args: &[String]) -> Result<(), ThelmaError> {
+    // Skip `serve` itself, which would otherwise be taken as a positional argument
+    let options = parse_args(&args[1..]);
+    let addr = options.serve_addr.clone();
+    let metrics_addr = options.metrics_addr.clone();
+    let dashboard = options.dashboard;
+    let max_body = options.max_body;
+
+    let mut server = ApiServer::new(Box::new(move |network_map| {
+        surveillance_config(&options, network_map, SurveillanceConfig::observing(Vec::new()))
+    }));
+    if let Some(metrics_addr) = metrics_addr {
+        let metrics = Arc::new(SurveillanceMetrics::new());
+        serve_metrics(&metrics_addr, metrics.clone()).await?;
+        server = server.metrics(metrics);
+    }
+    if dashboard {
+        info!("Dashboard at http://{}/", addr);
+        server = server.dashboard();
+    }
+    if let Some(max_body) = max_body {
+        server = server.max_body_bytes(max_body);
+    }
+    server.serve(&addr).await
+}
+
+// Build a network by hand and try the attack on it, one command at a time. Analysis and
+// routing options apply to everything done in the shell.
+async fn shell(args: &[String]) -> Result<(), ThelmaError> {
+    let options = parse_args(&args[1..]);
+    let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(780000)));
+    let config = surveillance_config(&options, &network_map, SurveillanceConfig::observing(Vec::new()));
+    let operation = SurveillanceOperation::new(network_map.clone(), config)?;
+    let simulator = SimulatorConfig::new()
+        .max_cltv_expiry(options.max_cltv_expiry)
+        .cltv_construction(options.cltv_construction)
+        .router(options.router.clone());
+    Shell::new(network_map, operation, simulator).run().await
+}
+
+// Work out where an adversary with `--budget` nodes should sit to observe the most payments,
+// without simulating the attack itself
+fn attack_place(args: &[String]) -> Result<(), ThelmaError> {
+    let options = parse_args(&args[1..]);
+    let budget = options.placement_budget.unwrap_or(options.malicious_count);
+    // `--seed` fixes the network, the sampled payments and the random baseline
+    let seed = options.seed.unwrap_or_else(rand::random);
+
+    let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(780000)));
+    generate_network(&options, &network_map, seed, options.node_count)?;
+    let network = read_lock(&network_map);
+
+    info!("Sampling {} payments over {} nodes with the {} router...",
+          options.traffic_samples, network.nodes.len(), options.router.name());
+    let model = TrafficModel::sample(&network, options.router.as_ref(), options.amounts,
+                                     options.traffic_samples, &mut StdRng::seed_from_u64(seed));
+    let mut plan = PlacementPlan::greedy(&model, budget);
+
+    // The strategies the main simulation places nodes with, for comparison
+    let baselines: Vec<Box<dyn AdversaryStrategy>> = vec![
+        Box::new(CentralPlacement::new(CentralityMeasure::Degree)),
+        Box::new(CentralPlacement::new(CentralityMeasure::Betweenness)),
+        Box::new(RandomPlacement::seeded(seed)),
+    ];
+    for mut strategy in baselines {
+        let nodes = strategy.select_nodes(&network, plan.steps.len());
+        plan.add_baseline(&strategy.name(), model.coverage(&nodes));
+    }
+
+    info!("\n{}", plan.generate_text_report());
+    plan.save_report_to_file("thelma_placement.md")?;
+    std::fs::write("thelma_placement.json", plan.generate_json_report())?;
+    info!("JSON placement saved to thelma_placement.json");
+    Ok(())
+}
+
+// Estimate how much traffic an adversary would observe straight from the graph, for trying
+// out placements faster than a simulation could
+fn coverage(args: &[String]) -> Result<(), ThelmaError> {
+    let options = parse_args(&args[1..]);
+    let seed = options.seed.unwrap_or_else(rand::random);
+    let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(780000)));
+    generate_network(&options, &network_map, seed, options.node_count)?;
+    let network = read_lock(&network_map);
+
+    // An explicit adversary, or one placed the way a simulation would place it
+    let malicious_nodes = if options.replay_malicious.is_empty() {
+        let mut strategy: Box<dyn AdversaryStrategy> = match options.placement {
+            Some(measure) => Box::new(CentralPlacement::new(measure)),
+            None => Box::new(RandomPlacement::seeded(seed)),
+        };
+        strategy.select_nodes(&network, options.malicious_count)
+    } else {
+        options.replay_malicious.clone()
+    };
+    if let Some(unknown) = malicious_nodes.iter().find(|node| !network.nodes.contains_key(*node)) {
+        return Err(ThelmaError::Config(format!("malicious node {} is not in the network", unknown)));
+    }
+
+    let estimate = CoverageEstimate::compute(&network, &malicious_nodes);
+    info!("\n{}", estimate.generate_text_report());
+    estimate.save_report_to_file("thelma_coverage.md")?;
+    std::fs::write("thelma_coverage.json", estimate.generate_json_report())?;
+    info!("JSON coverage estimate saved to thelma_coverage.json");
+    Ok(())
+}
+
+// Repeat a seeded experiment across values of one parameter and write the results out for
+// plotting
+async fn study(args: &[String]) -> Result<(), ThelmaError> {
+    let kind = args.get(2).map(String::as_str).unwrap_or("");
+    if kind != "scaling" {
+        return Err(ThelmaError::Config(format!("unknown study '{}' (expected one of: {})", kind, STUDY_KINDS.join(", "))));
+    }
+    // Everything after the study's name is options
+    let mut options = parse_args(&args[2..]);
+    options.regions = options.regions_spec.as_deref().map(RegionLatency::load).transpose()?;
+    let parameter = options.study_parameter
+        .ok_or_else(|| ThelmaError::Config("study needs --param nodes, payments or malicious".to_string()))?;
+    if options.study_values.is_empty() {
+        return Err(ThelmaError::Config("study needs --values <n,n,...>".to_string()));
+    }
+    let mut scaling = ScalingStudy::new(parameter, options.study_values.clone())
+        .repeats(options.repeats)
+        .seed(options.seed.unwrap_or(DEFAULT_STUDY_SEED));
+    let plot = options.plot;
+    #[cfg(feature = "plots")]
+    let plot_format = plot_format(&options)?;
+
+    // Every run is set up as `thelma` itself would set it up
+    let options = Arc::new(options);
+    let (network_options, simulator_options, surveillance_options) = (options.clone(), options.clone(), options.clone());
+    let stop = Arc::new(AtomicBool::new(false));
+    let settings = ExperimentSettings {
+        node_count: options.node_count,
+        payment_count: options.payment_count,
+        malicious_count: options.malicious_count,
+        placement: options.placement,
+        network: Box::new(move |seed, node_count| {
+            let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(780000)));
+            generate_network(&network_options, &network_map, seed, node_count)?;
+            Ok(network_map)
+        }),
+        simulator: Box::new(move |network_map, malicious_nodes| {
+            simulator_config(&simulator_options, malicious_nodes, &HashMap::new(), &stop)
+                .trampolines(trampoline_nodes(&read_lock(network_map), simulator_options.trampolines))
+                // Runs follow each other back to back, with no pause between payments
+                .delay_ms(0)
+        }),
+        surveillance: Box::new(move |network_map, config| surveillance_config(&surveillance_options, network_map, config)),
+    };
+
+    info!("Sweeping {} over {:?}, {} run(s) each", parameter.name(), scaling.values, scaling.repeats);
+    scaling.run(&settings).await?;
+
+    info!("\n{:>10}  {:>9}  {:>9}", parameter.name(), "coverage", "accuracy");
+    for (value, coverage, accuracy) in scaling.means() {
+        info!("{:>10}  {:>8.1}%  {:>8.1}%", value, coverage * 100.0, accuracy * 100.0);
+    }
+    scaling.save_csv("thelma_study_scaling.csv")?;
+    scaling.save_latex("thelma_study_scaling.tex")?;
+    if plot {
+        #[cfg(feature = "plots")]
+        scaling.save_plot("thelma_study_scaling", plot_format)?;
+        #[cfg(not(feature = "plots"))]
+        warn!("Built without the plots feature, so no chart was drawn; the CSV has everything");
+    }
+    Ok(())
+}
 
-    println!("\n{}", report);
+// `thelma runs list|show <id>|compare <a> <b>`: look back over recorded runs
+fn runs(args: &[String]) -> Result<(), ThelmaError> {
+    let command = args.get(2).map(String::as_str).unwrap_or("list");
+    let options = parse_args(&args[2..]);
+    let registry = RunRegistry::open(&options.registry_file)?;
+    let run = |position: usize| -> Result<RunRecord, ThelmaError> {
+        let id = args.get(position).and_then(|v| v.parse().ok())
+            .ok_or_else(|| ThelmaError::Config(format!("runs {} needs a run id", command)))?;
+        registry.get(id)?
+            .ok_or_else(|| ThelmaError::Config(format!("no run {} in {}", id, options.registry_file)))
+    };
+    match command {
+        "list" => println!("{}", generate_list_report(&registry.list()?)),
+        "show" => println!("{}", run(3)?.generate_text_report()),
+        "compare" => println!("{}", generate_comparison_report(&run(3)?, &run(4)?)),
+        other => return Err(ThelmaError::Config(format!(
+            "unknown runs command `{}`, expected one of: {}", other, RUNS_COMMANDS.join(", ")))),
+    }
+    Ok(())
+}
 
-    // Save the report to a file
-    surveillance.save_report("thelma_report.md")?;
+// Run the fixed golden scenario and check its report against a stored one, or store it
+async fn golden(args: &[String]) -> Result<(), ThelmaError> {
+    let path = args.get(2)
+        .ok_or_else(|| ThelmaError::Config("golden needs a report file".to_string()))?;
+    // Everything after the report file is options
+    let options = parse_args(&args[2..]);
+    let report = GoldenScenario::default().run().await?;
+    let contents = serde_json::to_string_pretty(&report)?;
 
-    // Also save as JSON for programmatic use
-    let json_report = surveillance.generate_json_report();
-    std::fs::write("thelma_report.json", json_report)?;
+    if options.golden_update || !std::path::Path::new(path).exists() {
+        std::fs::write(path, contents + "\n")?;
+        info!("Golden report written to {}", path);
+        return Ok(());
+    }
+    let golden: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    let differences = compare_reports(&golden, &report, options.golden_tolerance);
+    if differences.is_empty() {
+        info!("Report matches {} (tolerance {})", path, options.golden_tolerance);
+        return Ok(());
+    }
+    for difference in differences.iter().take(20) {
+        warn!("  {}", difference);
+    }
+    if differences.len() > 20 {
+        warn!("  ... and {} more", differences.len() - 20);
+    }
+    std::fs::write("thelma_golden_actual.json", contents)?;
+    info!("This run's report saved to thelma_golden_actual.json");
+    Err(ThelmaError::Regression(format!("{} differences from {}; rerun with --update if they are intended",
+                                        differences.len(), path)))
+}
 
-    println!("\nReports saved to thelma_report.md and thelma_report.json");
+// Chart confidence, anonymity sets, coverage and ROC curves next to the reports
+#[cfg(feature = "plots")]
+fn draw_plots(options: &CliOptions,
+              records: &[PaymentRecord],
+              results: &HashMap<String, Vec<PotentialRecipient>>,
+              malicious_nodes: &[String]) -> Result<(), ThelmaError> {
+    let format = plot_format(options)?;
+    save_plot(&ConfidenceHistogram::from_results(records, results), "thelma_confidence", format)?;
+    save_plot(&AnonymityCdf::from_results(records, results), "thelma_anonymity_sets", format)?;
+    save_plot(&LineChart::coverage_by_adversary_size(records, malicious_nodes), "thelma_coverage_curve", format)?;
+    let roc = RocCurve::compute(records, results);
+    save_plot(&ThresholdCurveChart::roc(&roc), "thelma_roc", format)?;
+    save_plot(&ThresholdCurveChart::precision_recall(&roc), "thelma_precision_recall", format)?;
+    Ok(())
+}
 
+#[cfg(not(feature = "plots"))]
+fn draw_plots(options: &CliOptions,
+              _records: &[PaymentRecord],
+              _results: &HashMap<String, Vec<PotentialRecipient>>,
+              _malicious_nodes: &[String]) -> Result<(), ThelmaError> {
+    warn!("Built without the plots feature, so no {} charts were drawn", options.plot_format);
     Ok(())
 }
 
+#[cfg(feature = "plots")]
+fn plot_format(options: &CliOptions) -> Result<PlotFormat, ThelmaError> {
+    PlotFormat::from_name(&options.plot_format).ok_or_else(|| {
+        ThelmaError::Config(format!("unknown plot format '{}' (expected one of: {})",
+                                    options.plot_format, PLOT_FORMATS.join(", ")))
+    })
+}
+
+// Re-run the attacker's analysis over recorded traffic with another set of malicious
+// nodes, without simulating anything
+fn replay(args: &[String]) -> Result<(), ThelmaError> {
+    let path = args.get(2)
+        .ok_or_else(|| ThelmaError::Config("replay needs a trace file".to_string()))?;
+    // Everything after the trace file is options
+    let options = parse_args(&args[2..]);
+    if options.replay_malicious.is_empty() {
+        return Err(ThelmaError::Config("replay needs --malicious <node,node,...>".to_string()));
+    }
+
+    let templates = load_templates(&options)?;
+
+    let trace = Trace::load(path)?;
+    info!("Replaying {} events from {}", trace.events.len(), path);
+    if let Some(unknown) = options.replay_malicious.iter().find(|node| !trace.network.nodes.contains_key(*node)) {
+        return Err(ThelmaError::Config(format!("malicious node {} is not in the traced network", unknown)));
+    }
+
+    let network_map = Arc::new(RwLock::new(trace.network.clone()));
+    if let Some(path) = &options.labels_file {
+        NodeLabels::load(path)?.apply(&mut write_lock(&network_map));
+    }
+    let config = surveillance_config(&options, &network_map,
+                                     SurveillanceConfig::observing(options.replay_malicious.clone()));
+    let mut operation = SurveillanceOperation::new(network_map.clone(), config)?;
+    trace.replay(&mut operation)?;
+    operation.set_ground_truth(&trace.payment_records(&options.replay_malicious));
+    if let Some(path) = &options.gexf_file {
+        let mut timeline = GexfRecorder::new(network_map.clone(), &options.replay_malicious);
+        trace.replay(&mut timeline)?;
+        timeline.save_to_file(path)?;
+    }
+    let surveillance = Arc::new(Mutex::new(operation));
+
+    let records = trace.payment_records(&options.replay_malicious);
+    let started = Instant::now();
+    let results = lock_mutex(&surveillance).run_analysis();
+    let metrics = score_results("Replay", &records, &HashMap::new(), (&results, started.elapsed()),
+                                &surveillance, &network_map);
+    info!("\nAdversary {} observed {}/{} payments ({:.1}%), identified {:.1}% of recipients \
+           (precision {:.1}%, avg anonymity set {:.2})",
+          options.replay_malicious.join(","), metrics.observed_payments, metrics.payments,
+          metrics.observation_rate() * 100.0, metrics.identification_rate() * 100.0,
+          metrics.attacker_precision() * 100.0, metrics.avg_anonymity_set);
+
+    let surveillance = lock_mutex(&surveillance);
+    surveillance.save_report(&results, "thelma_report.md")?;
+    std::fs::write("thelma_report.json", surveillance.generate_json_report(&results))?;
+    info!("Reports saved to thelma_report.md and thelma_report.json");
+    save_templated_reports(&templates, &surveillance, &results)?;
+    if let Some(path) = &options.cytoscape_file {
+        CytoscapeExport::compute(&read_lock(&network_map), &options.replay_malicious, &records,
+                                 &results).save_to_file(path)?;
+    }
+    if let Some(path) = &options.inference_diff_file {
+        InferenceDiff::compute(&records, &results, &options.replay_malicious,
+                               options.top_k).save_to_file(path)?;
+    }
+    if let Some(path) = &options.roc_file {
+        RocCurve::compute(&records, &results).save_csv(path)?;
+    }
+    DefenseComparison::new(metrics).save_latex_to_file("thelma_tables.tex")?;
+    Ok(())
+}
+
+// Save everything the run has produced so far, so `thelma resume` can continue it
+fn save_checkpoint(options: &CliOptions,
+                   simulator: &mut PaymentSimulator,
+                   surveillance: &Arc<Mutex<SurveillanceOperation>>,
+                   network_map: &Arc<RwLock<LightningNetworkMap>>,
+                   malicious_nodes: &[String],
+                   trace_offset: u64) -> Result<(), ThelmaError> {
+    let checkpoint = Checkpoint {
+        args: options.args.clone(),
+        network: read_lock(network_map).clone(),
+        malicious_nodes: malicious_nodes.to_vec(),
+        observations: lock_mutex(surveillance).observation_batches().flatten().collect(),
+        payment_records: simulator.payment_records().to_vec(),
+        payments_attempted: simulator.payments_attempted(),
+        rng_seed: simulator.reseed(),
+        trace_offset,
+    };
+    checkpoint.save(&options.checkpoint_file)
+}
+
+// Run the attacker's analysis and score it against the simulator's ground truth
+fn score_scenario(label: &str,
+                  records: &[PaymentRecord],
+                  hop_owners: &HashMap<String, String>,
+                  surveillance: &Arc<Mutex<SurveillanceOperation>>,
+                  network_map: &Arc<RwLock<LightningNetworkMap>>) -> ScenarioMetrics {
+    let started = Instant::now();
+    let results = attribute_to_payments(lock_mutex(surveillance).run_analysis(), hop_owners);
+    score_results(label, records, hop_owners, (&results, started.elapsed()), surveillance, network_map)
+}
+
+// Score results already attributed to payments, along with how long the analysis took
+fn score_results(label: &str,
+                 records: &[PaymentRecord],
+                 hop_owners: &HashMap<String, String>,
+                 (results, elapsed): (&HashMap<String, Vec<PotentialRecipient>>, Duration),
+                 surveillance: &Arc<Mutex<SurveillanceOperation>>,
+                 network_map: &Arc<RwLock<LightningNetworkMap>>) -> ScenarioMetrics {
+    let mut surveillance = lock_mutex(surveillance);
+    let network = read_lock(network_map);
+    let mut metrics = ScenarioMetrics::compute(label, records, results, &network);
+    metrics.record_analysis_cost(surveillance.observation_count(), elapsed);
+    // Linking is only scored where identifiers differ per hop
+    if !hop_owners.is_empty() {
+        if let Some(linking) = surveillance.score_linking(hop_owners) {
+            metrics.record_linking(linking);
+        }
+    }
+    metrics
+}
+
+// Raise a flag on the first Ctrl-C so the simulation winds down between payments;
+// a second Ctrl-C exits immediately
+fn watch_for_interrupt() -> Arc<AtomicBool> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let flag = stop.clone();
+    tokio::spawn(async move {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            if flag.swap(true, Ordering::Relaxed) {
+                std::process::exit(130);
+            }
+            warn!("\nInterrupted, finishing the current payment and writing a partial report (Ctrl-C again to quit)");
+        }
+    });
+    stop
+}
+
+// The best-connected nodes, which light senders would pick as trampolines
+fn trampoline_nodes(network: &LightningNetworkMap, count: usize) -> Vec<String> {
+    let mut nodes: Vec<(usize, &String)> = network.nodes.keys()
+        .map(|node| (network.degree(node), node))
+        .collect();
+    nodes.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+    nodes.into_iter().take(count).map(|(_, node)| node.clone()).collect()
+}
+
+// Latency the adversary times settlements by: the average over regions when links have
+// regional latencies
+fn settlement_hop_ms(options: &CliOptions) -> u64 {
+    options.regions.as_ref().map_or(options.hop_latency, RegionLatency::mean_ms)
+}
+
+// Settings shared by the baseline and every defended scenario
+fn simulator_config(options: &CliOptions,
+                    malicious_nodes: &[String],
+                    jit_channels: &HashMap<String, String>,
+                    stop: &Arc<AtomicBool>) -> SimulatorConfig {
+    let mut config = SimulatorConfig::new()
+        .delay_ms(50)
+        .stop_signal(stop.clone())
+        .workers(options.workers)
+        .max_cltv_expiry(options.max_cltv_expiry)
+        .cltv_construction(options.cltv_construction)
+        .amounts(options.amounts)
+        .malicious_nodes(malicious_nodes.to_vec())
+        .router(options.router.clone())
+        .ptlc(options.ptlc)
+        .htlc_hold(options.htlc_hold)
+        .invoice_expiry(options.invoice_expiry)
+        .hop_latency(HopLatency::new(options.hop_latency))
+        .jit_channels(jit_channels.clone());
+    if let Some(regions) = &options.regions {
+        config = config.regions(regions.clone());
+    }
+    if options.retries > 0 {
+        config = config.retries(RetryPolicy::new(options.retries));
+    }
+    if let Some(schedule) = options.gossip {
+        config = config.gossip(schedule);
+    }
+    for (node, router) in &options.node_routers {
+        config = config.node_router(node, router.clone());
+    }
+    config
+}
+
+// Replay the workload on the same network and adversary with a defense enabled
+async fn run_defended_scenario(network_map: &Arc<RwLock<LightningNetworkMap>>,
+                               malicious_nodes: &[String],
+                               jit_channels: &HashMap<String, String>,
+                               options: &CliOptions,
+                               stop: &Arc<AtomicBool>,
+                               label: &str,
+                               configure: impl FnOnce(SimulatorConfig) -> SimulatorConfig)
+                               -> Result<(ScenarioMetrics, PaymentSimulator), ThelmaError> {
+    let _span = info_span!("defense_scenario");
+    let mut config = SurveillanceConfig::observing(malicious_nodes.to_vec())
+        .max_cltv_expiry(options.max_cltv_expiry)
+        .cltv_construction(options.cltv_construction);
+    if options.ptlc {
+        config = config.link_by_fingerprint(FingerprintLinker::default());
+    }
+    if let Some(penalty) = options.stale_penalty {
+        config = config.deprioritize_stale_channels(penalty, DEFAULT_STALE_AFTER_BLOCKS);
+    }
+    if let Some(blocks) = options.channel_maturity {
+        config = config.weigh_channel_age(blocks);
+    }
+    if options.settlement_timing {
+        config = config.time_settlements(settlement_hop_ms(options));
+        if let Some(regions) = &options.regions {
+            config = config.infer_geography(regions.clone());
+        }
+    }
+    if let Some(uptime) = options.uptime {
+        config = config.observation_windows(uptime, options.uptime_period);
+    }
+    if options.share_delay.is_some() || options.analyze_at.is_some() {
+        let sharing = ObservationSharing::new(options.share_delay.unwrap_or(0)).cost_msat(options.share_cost_msat);
+        config = config.share_observations(sharing);
+    }
+    let surveillance = Arc::new(Mutex::new(SurveillanceOperation::new(network_map.clone(), config)?));
+    let config = configure(simulator_config(options, malicious_nodes, jit_channels, stop))
+        .trampolines(trampoline_nodes(&read_lock(network_map), options.trampolines));
+    let mut simulator = PaymentSimulator::new(network_map.clone(), config);
+    let observer = simulator.register_observer(surveillance.clone());
+    simulator.simulate_payments(options.payment_count).await?;
+    simulator.close_events();
+    observer.await?;
+
+    let mut metrics = score_scenario(label, simulator.payment_records(), simulator.hop_owners(), &surveillance, network_map);
+    metrics.record_latency(simulator.waiting_ms());
+    metrics.record_overpayment(simulator.overpaid_msat());
+    Ok((metrics, simulator))
+}
+
 // Parse command line arguments with sensible defaults
-fn parse_args(args: &[String]) -> (usize, usize, usize) {
+fn parse_args(args: &[String]) -> CliOptions {
     // Default values
     let mut node_count = 20;
+    let mut topology = "scale-free".to_string();
+    let mut topology_file = None;
+    let mut cltv_delta_file = None;
+    let mut labels_file = None;
+    let mut fees = FeeModel::default();
+    let mut shadow_offsets = None;
+    let mut node_shadow_offsets = Vec::new();
+    let mut parallel_channels = 0.0;
+    let mut disabled_channels = 0.0;
+    let mut zombie_channels = 0.0;
+    let mut gossip = None;
+    let mut max_accepted_htlcs = None;
+    let mut htlc_hold = 0;
+    let mut retries = 0;
+    let mut invoice_expiry = DEFAULT_INVOICE_EXPIRY_BLOCKS;
+    let mut refuse_dust = 0.0;
+    let mut tor_share = 0.0;
+    let mut regions_spec = None;
+    let mut hop_latency = DEFAULT_HOP_LATENCY_MS;
+    let mut settlement_timing = false;
+    let mut uptime = None;
+    let mut uptime_period = DEFAULT_UPTIME_PERIOD;
+    let mut coalitions = Vec::new();
+    let mut share_delay = None;
+    let mut share_cost_msat = 0;
+    let mut analyze_at = None;
+    let mut trampolines = 0;
+    let mut lsp_clients = 0;
+    let mut lsps = DEFAULT_LSP_COUNT;
+    let mut jit_share = 0.0;
+    let mut stale_penalty = None;
+    let mut channel_maturity = None;
     let mut payment_count = 50;
     let mut malicious_count = 3;
+    let mut decoy_probability = None;
+    let mut decoy_depth = 2;
+    let mut cover_rate = None;
+    let mut padding_ms = None;
+    let mut overpay_share = None;
+    let mut split_shards = None;
+    let mut defender_node = None;
+    let mut budget = AdversaryBudget::default();
+    let mut placement = None;
+    let mut attack_capital_sat = 0;
+    let mut community_threshold = None;
+    let mut accuracy_thresholds = AccuracyThresholds::default();
+    let mut max_cltv_expiry = DEFAULT_MAX_CLTV_EXPIRY;
+    let mut cltv_construction = CltvConstruction::default();
+    let mut sender_cltv_cap = None;
+    let mut exclude_probes = false;
+    let mut ptlc = false;
+    let mut analysis_mode = AnalysisMode::Exhaustive;
+    let mut workers = 1;
+    let mut router = router_from_name("bfs").unwrap();
+    let mut node_routers = Vec::new();
+    let mut amounts = AmountDistribution::default();
+    let mut route_cache_capacity = DEFAULT_ROUTE_CACHE_CAPACITY;
+    let mut memory_budget = None;
+    let mut max_search_hops = None;
+    let mut time_budget = None;
+    let mut recipient_profile = None;
+    let mut spill_dir = None;
+    let mut spill_limit = 100_000;
+    let mut live_analysis = false;
+    let mut metrics_addr = None;
+    let mut serve_addr = DEFAULT_SERVE_ADDR.to_string();
+    let mut dashboard = false;
+    let mut max_body = None;
+    let mut tui = false;
+    let mut checkpoint_every = None;
+    let mut checkpoint_file = DEFAULT_CHECKPOINT_FILE.to_string();
+    let mut trace_file = None;
+    let mut gexf_file = None;
+    let mut snapshot_every = None;
+    let mut cytoscape_file = None;
+    let mut inference_diff_file = None;
+    let mut roc_file = None;
+    let mut templates = Vec::new();
+    let mut top_k = DEFAULT_TOP_K;
+    let mut replay_malicious = Vec::new();
+    let mut placement_budget = None;
+    let mut traffic_samples = DEFAULT_TRAFFIC_SAMPLES;
+    let mut study_parameter = None;
+    let mut study_values = Vec::new();
+    let mut repeats = 1;
+    let mut seed = None;
+    let mut plot = false;
+    let mut plot_format = "png".to_string();
+    let mut golden_update = false;
+    let mut golden_tolerance = DEFAULT_GOLDEN_TOLERANCE;
+    let mut registry_file = DEFAULT_REGISTRY_FILE.to_string();
+    let mut record_run = true;
 
-    // Process args if provided
-    if args.len() > 1 {
-        if let Ok(n) = args[1].parse() {
-            node_count = n;
+    // Split flags from positional arguments
+    let mut positional = Vec::new();
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--decoy-prob" => {
+                decoy_probability = iter.next().and_then(|v| v.parse::<f64>().ok());
+            }
+            "--topology" => {
+                if let Some(name) = iter.next() {
+                    topology = name.clone();
+                }
+            }
+            "--topology-file" | "--graph" => {
+                topology_file = iter.next().cloned();
+                // A graph file only makes sense for an imported topology
+                topology = "imported".to_string();
+            }
+            "--fixture" => {
+                topology_file = iter.next().cloned();
+                topology = "fixture".to_string();
+            }
+            "--cltv-deltas" => {
+                cltv_delta_file = iter.next().cloned();
+            }
+            "--labels" => {
+                labels_file = iter.next().cloned();
+            }
+            "--fees" => {
+                if let Some(model) = iter.next().and_then(|v| FeeModel::from_name(v)) {
+                    fees = model;
+                }
+            }
+            "--parallel-channels" => {
+                if let Some(share) = iter.next().and_then(|v| v.parse::<f64>().ok()).filter(|s| (0.0..=1.0).contains(s)) {
+                    parallel_channels = share;
+                }
+            }
+            "--disabled-channels" => {
+                if let Some(share) = iter.next().and_then(|v| v.parse::<f64>().ok()).filter(|s| (0.0..=1.0).contains(s)) {
+                    disabled_channels = share;
+                }
+            }
+            "--zombie-channels" => {
+                if let Some(share) = iter.next().and_then(|v| v.parse::<f64>().ok()).filter(|s| (0.0..=1.0).contains(s)) {
+                    zombie_channels = share;
+                }
+            }
+            "--gossip" => {
+                if let Some(blocks) = iter.next().and_then(|v| v.parse::<u32>().ok()).filter(|b| *b > 0) {
+                    gossip = Some(GossipSchedule::new(blocks));
+                }
+            }
+            "--max-htlcs" => {
+                max_accepted_htlcs = iter.next().and_then(|v| v.parse::<u16>().ok()).filter(|n| *n > 0);
+            }
+            "--htlc-hold" => {
+                if let Some(payments) = iter.next().and_then(|v| v.parse::<u64>().ok()) {
+                    htlc_hold = payments;
+                }
+            }
+            "--retries" => {
+                if let Some(n) = iter.next().and_then(|v| v.parse::<u32>().ok()) {
+                    retries = n;
+                }
+            }
+            "--invoice-expiry" => {
+                if let Some(blocks) = iter.next().and_then(|v| v.parse::<u32>().ok()).filter(|b| *b > 0) {
+                    invoice_expiry = blocks;
+                }
+            }
+            "--refuse-dust" => {
+                if let Some(share) = iter.next().and_then(|v| v.parse::<f64>().ok()).filter(|s| (0.0..=1.0).contains(s)) {
+                    refuse_dust = share;
+                }
+            }
+            "--regions" => {
+                regions_spec = iter.next().cloned();
+            }
+            "--tor-share" => {
+                if let Some(share) = iter.next().and_then(|v| v.parse::<f64>().ok()).filter(|s| (0.0..=1.0).contains(s)) {
+                    tor_share = share;
+                }
+            }
+            "--hop-latency" => {
+                if let Some(ms) = iter.next().and_then(|v| v.parse::<u64>().ok()) {
+                    hop_latency = ms;
+                }
+            }
+            "--settlement-timing" => {
+                settlement_timing = true;
+            }
+            "--uptime" => {
+                uptime = iter.next().and_then(|v| v.parse::<f64>().ok()).filter(|u| (0.0..=1.0).contains(u));
+            }
+            "--coalitions" => {
+                // Comma-separated sizes, e.g. 2,1
+                if let Some(sizes) = iter.next() {
+                    coalitions = sizes.split(',').filter_map(|size| size.parse::<usize>().ok()).filter(|size| *size > 0).collect();
+                }
+            }
+            "--share-delay" => {
+                share_delay = iter.next().and_then(|v| v.parse::<u64>().ok());
+            }
+            "--share-cost" => {
+                if let Some(msat) = iter.next().and_then(|v| v.parse::<u64>().ok()) {
+                    share_cost_msat = msat;
+                }
+            }
+            "--trampolines" => {
+                if let Some(count) = iter.next().and_then(|v| v.parse::<usize>().ok()) {
+                    trampolines = count;
+                }
+            }
+            "--lsp-clients" => {
+                if let Some(count) = iter.next().and_then(|v| v.parse::<usize>().ok()) {
+                    lsp_clients = count;
+                }
+            }
+            "--lsps" => {
+                if let Some(count) = iter.next().and_then(|v| v.parse::<usize>().ok()).filter(|n| *n > 0) {
+                    lsps = count;
+                }
+            }
+            "--jit-share" => {
+                if let Some(share) = iter.next().and_then(|v| v.parse::<f64>().ok()).filter(|p| (0.0..=1.0).contains(p)) {
+                    jit_share = share;
+                }
+            }
+            "--analyze-at" => {
+                analyze_at = iter.next().and_then(|v| v.parse::<u64>().ok());
+            }
+            "--uptime-period" => {
+                if let Some(payments) = iter.next().and_then(|v| v.parse::<u64>().ok()).filter(|p| *p > 0) {
+                    uptime_period = payments;
+                }
+            }
+            "--stale-penalty" => {
+                stale_penalty = iter.next().and_then(|v| v.parse::<f64>().ok()).filter(|p| (0.0..=1.0).contains(p));
+            }
+            "--channel-age-prior" => {
+                channel_maturity = iter.next().and_then(|v| v.parse::<u32>().ok()).filter(|b| *b > 0);
+            }
+            "--shadow-offsets" => {
+                if let Some(mix) = iter.next().and_then(|v| ShadowOffsetMix::from_spec(v)) {
+                    shadow_offsets = Some(mix);
+                }
+            }
+            "--node-offset" => {
+                // <node>=<profile>, repeatable
+                let assignment = iter.next().and_then(|v| v.split_once('='));
+                if let Some((node, offset)) = assignment.and_then(|(node, name)| Some((node, ShadowOffset::from_name(name)?))) {
+                    node_shadow_offsets.push((node.to_string(), offset));
+                }
+            }
+            "--decoy-depth" => {
+                if let Some(n) = iter.next().and_then(|v| v.parse().ok()) {
+                    decoy_depth = n;
+                }
+            }
+            "--cover-rate" => {
+                cover_rate = iter.next().and_then(|v| v.parse::<f64>().ok());
+            }
+            "--split" => {
+                split_shards = iter.next().and_then(|v| v.parse::<usize>().ok()).filter(|n| *n >= 2);
+            }
+            "--overpay" => {
+                overpay_share = iter.next().and_then(|v| v.parse::<f64>().ok());
+            }
+            "--latency-padding" => {
+                padding_ms = iter.next().and_then(|v| v.parse::<u64>().ok());
+            }
+            "--defender" => {
+                defender_node = iter.next().cloned();
+            }
+            "--campaign-days" => {
+                if let Some(days) = iter.next().and_then(|v| v.parse().ok()) {
+                    budget.campaign_days = days;
+                }
+            }
+            "--capital-cost" => {
+                if let Some(rate) = iter.next().and_then(|v| v.parse().ok()) {
+                    budget.annual_cost_of_capital = rate;
+                }
+            }
+            "--attack-capital" => {
+                if let Some(sat) = iter.next().and_then(|v| v.parse().ok()) {
+                    attack_capital_sat = sat;
+                }
+            }
+            "--placement" => {
+                placement = iter.next().and_then(|v| CentralityMeasure::from_name(v));
+            }
+            "--communities" => {
+                community_threshold = Some(DEFAULT_COMMUNITY_THRESHOLD);
+            }
+            "--community-threshold" => {
+                community_threshold = iter.next().and_then(|v| v.parse().ok());
+            }
+            "--accuracy-k" => {
+                if let Some(values) = iter.next() {
+                    let top_k = values.split(',').filter_map(|v| v.trim().parse().ok()).collect();
+                    accuracy_thresholds = AccuracyThresholds::new(top_k, accuracy_thresholds.set_sizes);
+                }
+            }
+            "--accuracy-set-sizes" => {
+                if let Some(values) = iter.next() {
+                    let set_sizes = values.split(',').filter_map(|v| v.trim().parse().ok()).collect();
+                    accuracy_thresholds = AccuracyThresholds::new(accuracy_thresholds.top_k, set_sizes);
+                }
+            }
+            "--max-cltv" => {
+                if let Some(blocks) = iter.next().and_then(|v| v.parse().ok()) {
+                    max_cltv_expiry = blocks;
+                }
+            }
+            "--cltv-construction" => {
+                if let Some(construction) = iter.next().and_then(|v| CltvConstruction::from_name(v)) {
+                    cltv_construction = construction;
+                }
+            }
+            "--sender-cltv-cap" => {
+                sender_cltv_cap = iter.next().and_then(|v| v.parse().ok());
+            }
+            "--exclude-probes" => {
+                exclude_probes = true;
+            }
+            "--ptlc" => {
+                ptlc = true;
+            }
+            "--monte-carlo" => {
+                if let Some(samples) = iter.next().and_then(|v| v.parse().ok()) {
+                    analysis_mode = AnalysisMode::MonteCarlo { samples };
+                }
+            }
+            "--hierarchical" => {
+                if let Some(hubs) = iter.next().and_then(|v| v.parse().ok()) {
+                    analysis_mode = AnalysisMode::Hierarchical { hubs };
+                }
+            }
+            "--route-cache" => {
+                if let Some(n) = iter.next().and_then(|v| v.parse().ok()) {
+                    route_cache_capacity = n;
+                }
+            }
+            "--recipients" => {
+                recipient_profile = iter.next().and_then(|v| RecipientProfile::parse(v).ok());
+            }
+            "--memory-budget" => {
+                if let Some(mb) = iter.next().and_then(|v| v.parse::<f64>().ok()) {
+                    memory_budget = Some((mb * 1024.0 * 1024.0) as usize);
+                }
+            }
+            "--max-hops" => {
+                max_search_hops = iter.next().and_then(|v| v.parse::<usize>().ok());
+            }
+            "--time-budget" => {
+                time_budget = iter.next().and_then(|v| v.parse::<u64>().ok()).map(Duration::from_millis);
+            }
+            "--live" => {
+                live_analysis = true;
+            }
+            "--metrics-addr" => {
+                metrics_addr = iter.next().cloned();
+            }
+            "--addr" => {
+                if let Some(addr) = iter.next() {
+                    serve_addr = addr.clone();
+                }
+            }
+            "--dashboard" => {
+                dashboard = true;
+            }
+            "--max-body" => {
+                if let Some(mb) = iter.next().and_then(|v| v.parse::<f64>().ok()) {
+                    max_body = Some((mb * 1024.0 * 1024.0) as usize);
+                }
+            }
+            "--tui" => {
+                tui = true;
+            }
+            "--spill-dir" => {
+                spill_dir = iter.next().cloned();
+            }
+            "--spill-limit" => {
+                if let Some(n) = iter.next().and_then(|v| v.parse().ok()) {
+                    spill_limit = n;
+                }
+            }
+            "--router" => {
+                if let Some(r) = iter.next().and_then(|v| router_from_name(v)) {
+                    router = r;
+                }
+            }
+            "--node-router" => {
+                // <node>=<router>, repeatable
+                let assignment = iter.next().and_then(|v| v.split_once('='));
+                if let Some((node, r)) = assignment.and_then(|(node, name)| Some((node, router_from_name(name)?))) {
+                    node_routers.push((node.to_string(), r));
+                }
+            }
+            "--amounts" => {
+                if let Some(distribution) = iter.next().and_then(|v| AmountDistribution::from_spec(v)) {
+                    amounts = distribution;
+                }
+            }
+            "--checkpoint-every" => {
+                checkpoint_every = iter.next().and_then(|v| v.parse().ok()).filter(|&n| n > 0);
+            }
+            "--checkpoint" => {
+                if let Some(file) = iter.next() {
+                    checkpoint_file = file.clone();
+                }
+            }
+            "--trace" => {
+                trace_file = iter.next().cloned();
+            }
+            "--gexf" => {
+                gexf_file = iter.next().cloned();
+            }
+            "--snapshot-every" => {
+                snapshot_every = iter.next().and_then(|v| v.parse::<u64>().ok()).filter(|n| *n > 0);
+            }
+            "--cytoscape" => {
+                cytoscape_file = iter.next().cloned();
+            }
+            "--inference-diff" => {
+                inference_diff_file = iter.next().cloned();
+            }
+            "--roc" => {
+                roc_file = iter.next().cloned();
+            }
+            "--template" => {
+                // Repeatable
+                if let Some(file) = iter.next() {
+                    templates.push(file.clone());
+                }
+            }
+            "--top-k" => {
+                if let Some(k) = iter.next().and_then(|v| v.parse().ok()).filter(|&k| k > 0) {
+                    top_k = k;
+                }
+            }
+            "--malicious" => {
+                if let Some(nodes) = iter.next() {
+                    replay_malicious.extend(nodes.split(',').filter(|n| !n.is_empty()).map(str::to_string));
+                }
+            }
+            "--budget" => {
+                placement_budget = iter.next().and_then(|v| v.parse().ok());
+            }
+            "--samples" => {
+                if let Some(n) = iter.next().and_then(|v| v.parse().ok()) {
+                    traffic_samples = n;
+                }
+            }
+            "--param" => {
+                study_parameter = iter.next().and_then(|v| StudyParameter::from_name(v));
+            }
+            "--values" => {
+                if let Some(values) = iter.next() {
+                    study_values = values.split(',').filter_map(|v| v.trim().parse().ok()).collect();
+                }
+            }
+            "--repeats" => {
+                if let Some(n) = iter.next().and_then(|v| v.parse().ok()) {
+                    repeats = n;
+                }
+            }
+            "--seed" => {
+                if let Some(n) = iter.next().and_then(|v| v.parse().ok()) {
+                    seed = Some(n);
+                }
+            }
+            "--plot" => {
+                plot = true;
+            }
+            "--plot-format" => {
+                if let Some(format) = iter.next() {
+                    plot_format = format.clone();
+                }
+            }
+            "--update" => {
+                golden_update = true;
+            }
+            "--tolerance" => {
+                if let Some(tolerance) = iter.next().and_then(|v| v.parse().ok()) {
+                    golden_tolerance = tolerance;
+                }
+            }
+            "--registry" => {
+                if let Some(file) = iter.next() {
+                    registry_file = file.clone();
+                }
+            }
+            "--no-registry" => {
+                record_run = false;
+            }
+            // Output levels are set up before parsing, see `Verbosity::from_args`
+            "-q" | "--quiet" | "-v" | "--verbose" | "-vv" => {}
+            "--workers" => {
+                if let Some(n) = iter.next().and_then(|v| v.parse().ok()) {
+                    workers = n;
+                }
+            }
+            _ => positional.push(arg),
         }
     }
 
-    if args.len() > 2 {
-        if let Ok(n) = args[2].parse() {
-            payment_count = n;
-        }
+    // Process args if provided
+    if let Some(Ok(n)) = positional.first().map(|a| a.parse()) {
+        node_count = n;
     }
 
-    if args.len() > 3 {
-        if let Ok(n) = args[3].parse() {
-            malicious_count = n;
+    if let Some(Ok(n)) = positional.get(1).map(|a| a.parse()) {
+        payment_count = n;
+    }
 
-            // Ensure we don't have more malicious nodes than total nodes
-            if malicious_count > node_count {
-                malicious_count = node_count / 4;
-            }
+    if let Some(Ok(n)) = positional.get(2).map(|a| a.parse()) {
+        malicious_count = n;
+
+        // Ensure we don't have more malicious nodes than total nodes
+        if malicious_count > node_count {
+            malicious_count = node_count / 4;
         }
     }
 
-    (node_count, payment_count, malicious_count)
+    CliOptions {
+        node_count,
+        topology,
+        topology_file,
+        cltv_delta_file,
+        labels_file,
+        fees,
+        shadow_offsets,
+        node_shadow_offsets,
+        parallel_channels,
+        disabled_channels,
+        zombie_channels,
+        gossip,
+        max_accepted_htlcs,
+        htlc_hold,
+        retries,
+        invoice_expiry,
+        refuse_dust,
+        tor_share,
+        regions_spec,
+        regions: None,
+        hop_latency,
+        // Padding is meant to blunt the timing attack, so the adversary times settlements
+        settlement_timing: settlement_timing || padding_ms.is_some(),
+        uptime,
+        uptime_period,
+        coalitions,
+        share_delay,
+        share_cost_msat,
+        analyze_at,
+        trampolines,
+        lsp_clients,
+        lsps,
+        jit_share,
+        stale_penalty,
+        channel_maturity,
+        payment_count,
+        malicious_count,
+        decoy_hops: decoy_probability.map(|p| DecoyHopDefense::new(p, decoy_depth)),
+        cover_traffic: cover_rate.map(CoverTrafficDefense::new),
+        latency_padding: padding_ms.map(LatencyPaddingDefense::new),
+        overpayment: overpay_share.map(OverpaymentDefense::new),
+        payment_splitting: split_shards.map(PaymentSplittingDefense::new),
+        defender_node,
+        budget,
+        placement,
+        attack_capital_sat,
+        community_threshold,
+        accuracy_thresholds,
+        max_cltv_expiry,
+        cltv_construction,
+        sender_cltv_cap,
+        exclude_probes,
+        ptlc,
+        analysis_mode,
+        route_cache_capacity,
+        memory_budget,
+        max_search_hops,
+        time_budget,
+        recipient_profile,
+        workers,
+        router,
+        node_routers,
+        amounts,
+        spill_dir,
+        spill_limit,
+        live_analysis,
+        metrics_addr,
+        serve_addr,
+        dashboard,
+        max_body,
+        tui,
+        checkpoint_every,
+        checkpoint_file,
+        args: args.to_vec(),
+        trace_file,
+        gexf_file,
+        snapshot_every,
+        cytoscape_file,
+        inference_diff_file,
+        roc_file,
+        templates,
+        top_k,
+        replay_malicious,
+        placement_budget,
+        traffic_samples,
+        study_parameter,
+        study_values,
+        repeats,
+        seed,
+        plot,
+        plot_format,
+        golden_update,
+        golden_tolerance,
+        registry_file,
+        record_run,
+    }
 }
 
 // Display usage information
@@ -118,13 +2079,222 @@ fn print_usage() {
     println!("THELMA: Timelock Heuristic Evaluation for Lightning Movement Analysis");
     println!();
     println!("Usage:");
-    println!("  thelma [nodes] [payments] [malicious]");
+    println!("  thelma [nodes] [payments] [malicious] [options]");
+    println!("  thelma resume <checkpoint>");
+    println!("  thelma replay <trace> --malicious <node,node,...> [analysis options]");
+    println!("  thelma serve [--addr <host:port>] [analysis options]");
+    println!("  thelma shell [analysis options]   (type `help` inside for commands)");
+    println!("  thelma attack-place [--graph <file>] --budget <n> [network and simulation options]");
+    println!("  thelma coverage [--graph <file>] [--malicious <node,node,...>] [network and adversary options]");
+    println!("  thelma study scaling --param <name> --values <n,n,...> [--repeats <n>] [--seed <s>] [--plot]");
+    println!("               [nodes] [payments] [malicious] [options]");
+    println!("  thelma golden <report.json> [--update] [--tolerance <x>]");
+    println!("  thelma runs list|show <id>|compare <a> <b> [--registry <file>]");
     println!();
     println!("Arguments:");
     println!("  nodes       - Number of nodes in the network (default: 20)");
     println!("  payments    - Number of payments to simulate (default: 50)");
     println!("  malicious   - Number of malicious nodes (default: 3)");
     println!();
+    println!("Network options:");
+    println!("  --topology <name>   - Topology model: simple, scale-free, small-world, imported or fixture");
+    println!("                        (default: scale-free)");
+    println!("  --topology-file <f> - Import the graph from an `lncli describegraph` JSON dump");
+    println!("  --graph <f>         - Same as --topology-file");
+    println!("  --fixture <name>    - Run on a fixed fixture network instead: line, ring, star, diamond or");
+    println!("                        two-hub (ignores the node count)");
+    println!("  --cltv-deltas <f>   - Draw generated nodes' CLTV deltas from an empirical distribution:");
+    println!("                        `delta[,count]` lines, or a describegraph JSON snapshot");
+    println!("  --labels <f>        - Label nodes (exchange, merchant, lsp, ...) from a JSON object keyed");
+    println!("                        by public key, and break identification down by label (also with replay)");
+    println!("  --fees <model>      - Fee policies of generated nodes: mainnet (many zero-fee nodes,");
+    println!("                        a heavy tail of expensive ones; default) or default (LND's");
+    println!("                        1 sat + 1 ppm everywhere)");
+    println!("  --parallel-channels <share> - Share of generated channels given a parallel channel");
+    println!("                        with its own capacity and fees (default: 0)");
+    println!("  --disabled-channels <share> - Share of generated channels whose peers disabled them");
+    println!("  --zombie-channels <share> - Share of generated channels left as zombies: in the graph");
+    println!("                        but long inactive. Neither kind routes (default: 0)");
+    println!("  --gossip <blocks>   - Gossip channel updates, each channel re-announcing its policy");
+    println!("                        every <blocks> on average, with 6 blocks mined per payment");
+    println!("  --max-htlcs <n>     - HTLCs each side of a channel accepts in flight (default: 483)");
+    println!("  --htlc-hold <n>     - Keep every payment's HTLCs in flight while n more payments start,");
+    println!("                        failing payments whose route has a hop out of slots (default: 0)");
+    println!("  --retries <n>       - Retry payments that fail in flight up to n times under the same");
+    println!("                        invoice, a block apart (default: 0)");
+    println!("  --invoice-expiry <blocks> - Blocks an invoice stays payable for; senders abandon");
+    println!("                        retries once it expires (default: 6)");
+    println!("  --refuse-dust <share> - Share of channels refusing HTLCs below their dust limit");
+    println!("                        (default: 0)");
+    println!("  --tor-share <share> - Share of generated nodes reachable only over Tor, whose links");
+    println!("                        are 5x slower and less steady; imported graphs use the");
+    println!("                        addresses nodes announced (default: 0)");
+    println!("  --regions <world|f> - Spread nodes over regions and time links by the latency between");
+    println!("                        them: the built-in world (five continents) or a JSON file of");
+    println!("                        regions with shares and a latency_ms matrix. With");
+    println!("                        --settlement-timing the adversary guesses recipients' regions");
+    println!("  --hop-latency <ms>  - Milliseconds a message takes over a link, setting how long");
+    println!("                        HTLCs take to settle or fail (default: 100)");
+    println!("  --settlement-timing - Weigh routes by how long observed HTLCs took to settle or fail");
+    println!("  --uptime <share>    - Keep each malicious node online for this share of the time,");
+    println!("                        dropping what it would have seen while offline");
+    println!("  --uptime-period <n> - Payments each node's online window repeats over (default: 100)");
+    println!("  --coalitions <sizes> - Split the malicious nodes into coalitions of these sizes, e.g.");
+    println!("                        2,1, that don't share observations, and compare their accuracy");
+    println!("  --share-delay <n>   - Payments observations take to reach the first malicious node,");
+    println!("                        comparing real-time analysis against retrospective");
+    println!("  --share-cost <msat> - What sending each observation to it costs (default: 0)");
+    println!("  --analyze-at <n>    - Also analyze what had reached it by the end of payment n");
+    println!("  --trampolines <n>   - Senders hand payments to the closest of the n best-connected");
+    println!("                        nodes, and malicious trampolines are compared to ordinary hops");
+    println!("  --lsp-clients <n>   - Attach n mobile-wallet clients, each with a single channel to one");
+    println!("                        of the best-connected nodes as its LSP, and compare malicious");
+    println!("                        LSPs with malicious nodes placed at random");
+    println!("  --lsps <k>          - Best-connected nodes acting as LSPs (default: 3)");
+    println!("  --jit-share <p>     - Share of clients whose LSP opens their channel zero-conf when");
+    println!("                        they are first paid (default: 0)");
+    println!("  --stale-penalty <p> - Take p off a route's confidence for every hop whose channels");
+    println!("                        weren't gossiped in the week before the payment");
+    println!("  --channel-age-prior <blocks> - Weigh routes by their channels' age, read from their");
+    println!("                        short channel ids: younger than <blocks> routes less");
+    println!("  --shadow-offsets <mix> - Spread shadow offset profiles over nodes by weight, e.g.");
+    println!("                        none:0.6,standard:0.4 (profiles: none, narrow, standard, wide");
+    println!("                        or <min>-<max> blocks; default: standard everywhere)");
+    println!("  --node-offset <node>=<profile> - Shadow offset profile of one node (repeatable)");
+    println!();
+    println!("Adversary options:");
+    println!("  --placement <m>     - Place malicious nodes on the most central nodes by m");
+    println!("                        (degree, betweenness, closeness; default: random)");
+    println!("  --attack-capital <s> - Fresh capital (sat) the adversary spends on new channels");
+    println!("                        towards well-connected nodes (default: 0)");
+    println!("  --sender-cltv-cap <b> - Assume senders cap total route timelock at b blocks and");
+    println!("                        narrow the sender anonymity set accordingly");
+    println!("  --exclude-probes    - Leave payments that look like probes (tiny or round amounts,");
+    println!("                        rapid retries, repeated hash prefixes) out of the analysis");
+    println!("  --ptlc              - Lock payments with PTLCs, so hops don't share a hash, and link");
+    println!("                        observations by amount, timelock and timing instead");
+    println!("  --monte-carlo <n>   - Estimate recipients from n sampled routes per observation");
+    println!("                        instead of enumerating every route (for large graphs)");
+    println!("  --hierarchical <n>  - Find the n likeliest final hubs first, then only search routes");
+    println!("                        to their leaves (for hub-and-spoke graphs)");
+    println!("  --route-cache <n>   - Route searches kept for reuse across observations (default: 64,");
+    println!("                        0 disables)");
+    println!("  --recipients <spec> - Only name recipients matching any of: sinks (seen being paid),");
+    println!("                        leaves (a single peer) or operator labels, comma separated");
+    println!("  --memory-budget <mb> - Sample an observation's routes (as --monte-carlo 1000) when");
+    println!("                        enumerating them would take more than mb MiB");
+    println!("  --max-hops <n>      - Search routes at most n hops deep (default: deep enough for");
+    println!("                        99.9% of each observation's likely hop counts, sampling");
+    println!("                        searches past 5 hops that outgrow 64 MiB)");
+    println!("  --time-budget <ms>  - Stop analyzing a payment after ms milliseconds and report the");
+    println!("                        candidates found so far, flagged as incomplete");
+    println!("  --live              - Refine candidates as each observation arrives rather than");
+    println!("                        analyzing everything after the simulation");
+    println!("  --metrics-addr <a>  - Serve Prometheus metrics on host:port at /metrics");
+    println!("  --addr <a>          - With serve: address the API listens on (default: 127.0.0.1:8080)");
+    println!("  --dashboard         - With serve: serve a browser dashboard at /");
+    println!("  --max-body <mb>     - With serve: largest request body accepted, which bounds");
+    println!("                        uploaded graphs (default: 128)");
+    println!("  --budget <n>        - With attack-place: nodes the adversary can run (default: malicious)");
+    println!("  --samples <n>       - With attack-place: payments sampled to estimate coverage");
+    println!("                        (default: 2000)");
+    println!();
+    println!("Protocol options:");
+    println!("  --max-cltv <b>      - Largest total route timelock senders accept (default: 2016)");
+    println!("  --cltv-construction <name> - Delta senders add, and the analyzer assumes, per forwarding");
+    println!("                        hop: bolt4 (the one announced for the outgoing channel; default)");
+    println!("                        or legacy (the forwarder's usual delta, as earlier versions did)");
+    println!();
+    println!("Simulation options:");
+    println!("  --router <name>     - Path selection for senders: bfs (fewest hops), dijkstra");
+    println!("                        (cheapest fees) or randomized (detours; default: bfs)");
+    println!("  --node-router <node>=<name> - Use a different router for one sender (repeatable)");
+    println!("  --amounts <spec>    - Payment amounts in msat: fixed:<a>, uniform:<min>-<max> or");
+    println!("                        log-uniform:<min>-<max> (default: uniform:10000-1000000)");
+    println!("  --workers <n>       - Simulate payments on n concurrent tasks (default: 1)");
+    println!("  --seed <s>          - Seed the network, the adversary and the payments (default: random,");
+    println!("                        recorded in the run registry)");
+    println!("  --spill-dir <dir>   - Spill observations beyond the memory limit to files in dir");
+    println!("  --spill-limit <n>   - Observations kept in memory when spilling (default: 100000)");
+    println!("  --checkpoint-every <n> - Save progress every n payments so the run can be resumed");
+    println!("  --checkpoint <file> - Checkpoint file (default: thelma_checkpoint.json)");
+    println!("  --registry <file>   - Database runs are recorded in and `runs` reads (default: thelma_runs.db)");
+    println!("  --no-registry       - Don't record this run");
+    println!("  --trace <file>      - Record every simulated event to a trace file for replay");
+    println!("  --gexf <file>       - Write a dynamic GEXF timeline of the traffic for Gephi (also with replay)");
+    println!("  --snapshot-every <n> - Snapshot the graph every n payments and report what changed");
+    println!("                        between snapshots");
+    println!("  --cytoscape <file>  - Write the network and candidate confidence as Cytoscape.js elements JSON");
+    println!("  --inference-diff <file> - Write each payment's true route next to the attacker's top");
+    println!("                        candidates, one JSON line per payment");
+    println!("  --top-k <k>         - Candidates listed per payment in the inference diff (default: 5)");
+    println!("  --roc <file>        - Write ROC and precision-recall points of the attacker's calls as CSV");
+    println!("  --template <file>   - Also render the report through a Jinja-style template, to");
+    println!("                        thelma_report_<name> (repeatable, also with replay)");
+    println!("  --malicious <nodes> - With replay or coverage: comma-separated nodes the adversary runs");
+    println!();
+    println!("Output options:");
+    println!("  -q, --quiet         - Only print warnings and errors; reports are still written");
+    println!("  -v, --verbose       - Log every payment and observation (-vv: also route analysis)");
+    println!("  --tui               - Full-screen live view of the simulation (q stops it)");
+    println!("  --plot              - Chart confidence, anonymity sets, coverage and ROC curves (needs the plots feature)");
+    println!("  --plot-format <f>   - Chart format: png or svg (default: png)");
+    println!();
+    println!("Defense options:");
+    println!("  --decoy-prob <p>    - Pad routes with decoy hops with probability p and compare");
+    println!("  --decoy-depth <n>   - Maximum decoy hops added to a padded route (default: 2)");
+    println!("  --cover-rate <r>    - Honest nodes emit r dummy payments per real payment and compare");
+    println!("  --latency-padding <ms> - Honest nodes wait up to ms before forwarding and compare;");
+    println!("                        the adversary times settlements");
+    println!("  --overpay <share>   - Senders overpay by up to this share of the amount and compare;");
+    println!("                        with --ptlc the comparison includes linking accuracy");
+    println!("  --split <n>         - Senders split payments into up to n shards over routes sharing");
+    println!("                        no intermediary, and compare how much of them was seen");
+    println!();
+    println!("Report options:");
+    println!("  --defender <node>   - Report how exposed this node's own payments were");
+    println!("  --communities       - Report candidate recipient communities when no single");
+    println!("                        candidate holds at least half of the confidence");
+    println!("  --community-threshold <s> - Same, with a custom confidence share threshold");
+    println!("  --accuracy-k <k,k,...> - Top-k cut-offs identification rates are reported at (default: 1,3,10)");
+    println!("  --accuracy-set-sizes <n,n,...> - Candidate set sizes identification rates are reported");
+    println!("                        at (default: 1,5,10)");
+    println!("  --campaign-days <d> - Days of real time the simulated payments represent (default: 30)");
+    println!("  --capital-cost <r>  - Annual cost of capital locked by the attacker (default: 0.05)");
+    println!();
+    println!("Study options:");
+    println!("  --param <name>      - Parameter to sweep: nodes, payments or malicious");
+    println!("  --values <n,n,...>  - Values the parameter takes");
+    println!("  --repeats <n>       - Runs per value, with seeds counting up from --seed (default: 1)");
+    println!("  --seed <s>          - Seed of each value's first run (default: 1)");
+    println!("  --plot              - Also chart the study as thelma_study_scaling.png");
+    println!();
+    println!("Golden options:");
+    println!("  --update            - Rewrite the golden report from this run instead of checking it");
+    println!("  --tolerance <x>     - How far confidences and averages may drift (default: 0.001)");
+    println!();
     println!("Example:");
     println!("  thelma 50 100 5   # 50 nodes, 100 payments, 5 malicious nodes");
+    println!("  thelma 50 100 5 --decoy-prob 0.5 --decoy-depth 3");
+    println!("  thelma 50 100 5 --defender node7");
+    println!("  thelma 50 100 5 --plot --plot-format svg");
+    println!("  thelma 500 100000 20 --checkpoint-every 1000");
+    println!("  thelma resume thelma_checkpoint.json");
+    println!("  thelma 200 100000 10 --live --metrics-addr 127.0.0.1:9184");
+    println!("  thelma 50 100 5 --trace traffic.jsonl");
+    println!("  thelma replay traffic.jsonl --malicious node3,node17");
+    println!("  thelma 50 100 5 --gexf thelma_timeline.gexf");
+    println!("  thelma 50 100 5 --cytoscape thelma_elements.json");
+    println!("  thelma 50 100 5 --inference-diff thelma_diff.jsonl --top-k 3");
+    println!("  thelma serve --addr 0.0.0.0:8080 --communities");
+    println!("  thelma serve --live --dashboard");
+    println!("  thelma shell < scenario.txt");
+    println!("  thelma attack-place --graph describegraph.json --budget 5 --router dijkstra");
+    println!("  thelma coverage --graph describegraph.json --malicious node3,node17");
+    println!("  thelma study scaling --param nodes --values 100,500,1000,5000 --repeats 3 --plot");
+    println!("  thelma golden tests/golden/report.json");
+    println!("  thelma 50 100 5 --template templates/paper.tex.j2");
+    println!("  thelma runs compare 12 15");
+    println!();
+    println!("Press Ctrl-C during a run to stop early and write partial reports.");
 }
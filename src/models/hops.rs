@@ -0,0 +1,201 @@
+// Remaining hops: how far an HTLC still has to go can't be read off its timelock exactly,
+// since forwarding deltas differ by node, the recipient's invoice delta is unknown and the
+// sender pads the final timelock. Given priors on all three, every hop count gets a
+// probability instead of only a bound.
+
+use crate::models::htlc::MAX_ROUTE_HOPS;
+use crate::models::invoice::FinalDeltaPrior;
+use crate::models::network::LightningNetworkMap;
+use crate::models::shadow::ShadowOffsetPrior;
+
+// Probability of each number of hops left, from 0 (the observer is the recipient) up
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HopCountDistribution {
+    probabilities: Vec<f64>,
+}
+
+impl HopCountDistribution {
+    // Nothing known beyond a bound: every count up to it as likely as the others
+    pub fn up_to(max_hops: usize) -> Self {
+        HopCountDistribution { probabilities: vec![1.0 / (max_hops + 1) as f64; max_hops + 1] }
+    }
+
+    // Weights per hop count, normalized. Empty when no count has any weight.
+    pub fn from_weights(mut weights: Vec<f64>) -> Self {
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            return HopCountDistribution::default();
+        }
+        for weight in &mut weights {
+            *weight /= total;
+        }
+        while weights.last() == Some(&0.0) {
+            weights.pop();
+        }
+        HopCountDistribution { probabilities: weights }
+    }
+
+    pub fn probability(&self, hops: usize) -> f64 {
+        self.probabilities.get(hops).copied().unwrap_or(0.0)
+    }
+
+    // Most hops with any probability; None when no count fits
+    pub fn max_hops(&self) -> Option<usize> {
+        self.probabilities.len().checked_sub(1)
+    }
+
+    pub fn most_likely(&self) -> Option<usize> {
+        (0..self.probabilities.len()).max_by(|a, b| self.probabilities[*a].total_cmp(&self.probabilities[*b]))
+    }
+
+    // Fewest hops that, with every count below, take at least `share` of the probability
+    pub fn covering(&self, share: f64) -> Option<usize> {
+        let mut total = 0.0;
+        self.probabilities.iter().position(|p| {
+            total += p;
+            total >= share - 1e-12
+        }).or(self.max_hops())
+    }
+
+    // Probability of more than `hops` hops left
+    pub fn beyond(&self, hops: usize) -> f64 {
+        self.probabilities.iter().skip(hops + 1).sum()
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.probabilities.iter().enumerate().map(|(hops, p)| hops as f64 * p).sum()
+    }
+
+    // Probability relative to the most likely count, from 0 to 1. Every count weighs 1 when
+    // none fits, so routes aren't all ruled out by priors that missed.
+    pub fn weight(&self, hops: usize) -> f64 {
+        let peak = self.probabilities.iter().copied().fold(0.0, f64::max);
+        if peak > 0.0 { self.probability(hops) / peak } else { 1.0 }
+    }
+}
+
+// Priors on a network's forwarding deltas, invoice deltas and shadow offsets, with the
+// distribution of summed forwarding deltas worked out for every route length
+#[derive(Debug, Clone)]
+pub struct HopCountModel {
+    topology_version: u64,
+    // Largest budget the model answers for
+    horizon: u32,
+    // sums[h][x]: probability that h forwarding deltas add up to x blocks
+    sums: Vec<Vec<f64>>,
+    finals: FinalDeltaPrior,
+    shadow: ShadowOffsetPrior,
+}
+
+impl HopCountModel {
+    // Deltas as the network's nodes announce them, each node counting once, for budgets up
+    // to `horizon` blocks
+    pub fn of(network: &LightningNetworkMap, horizon: u32) -> Self {
+        let mut deltas = vec![0.0; horizon as usize + 1];
+        for node in network.nodes.values() {
+            if let Some(share) = deltas.get_mut(node.cltv_expiry_delta as usize) {
+                *share += 1.0;
+            }
+        }
+        let total: f64 = deltas.iter().sum();
+        let deltas: Vec<(usize, f64)> = deltas.into_iter().enumerate()
+            .filter(|(_, count)| *count > 0.0)
+            .map(|(delta, count)| (delta, count / total))
+            .collect();
+
+        let mut sums = Vec::with_capacity(MAX_ROUTE_HOPS + 1);
+        let mut current = vec![0.0; horizon as usize + 1];
+        current[0] = 1.0;
+        for _ in 0..=MAX_ROUTE_HOPS {
+            let mut next = vec![0.0; current.len()];
+            for (blocks, p) in current.iter().enumerate().filter(|(_, p)| **p > 0.0) {
+                for (delta, share) in &deltas {
+                    if let Some(slot) = next.get_mut(blocks + delta) {
+                        *slot += p * share;
+                    }
+                }
+            }
+            sums.push(std::mem::replace(&mut current, next));
+        }
+
+        HopCountModel {
+            topology_version: network.topology_version(),
+            horizon,
+            sums,
+            finals: FinalDeltaPrior::of(network),
+            shadow: ShadowOffsetPrior::of(network),
+        }
+    }
+
+    pub fn topology_version(&self) -> u64 {
+        self.topology_version
+    }
+
+    pub fn horizon(&self) -> u32 {
+        self.horizon
+    }
+
+    pub fn final_deltas(&self) -> &FinalDeltaPrior {
+        &self.finals
+    }
+
+    // How many hops an HTLC with `budget` blocks left still has to go. The observer's own
+    // delta counts, so h hops spend h forwarding deltas, then the invoice delta and the
+    // sender's offset.
+    pub fn distribution(&self, budget: u32) -> HopCountDistribution {
+        let budget = budget.min(self.horizon);
+        let weights = self.sums.iter()
+            .map(|sums| {
+                self.finals.deltas().iter()
+                    .filter(|(delta, _)| *delta <= budget)
+                    .map(|(delta, share)| {
+                        let left = budget - delta;
+                        let offsets: f64 = (0..=left.min(self.shadow.max_offset()))
+                            .map(|offset| self.shadow.likelihood(offset) * sums[(left - offset) as usize])
+                            .sum();
+                        share * offsets
+                    })
+                    .sum()
+            })
+            .collect();
+        HopCountDistribution::from_weights(weights)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Node, ShadowOffset};
+
+    #[test]
+    fn test_hop_counts_follow_the_delta_priors() {
+        // Half the nodes ask for 20 blocks and half for 40; invoices for 40, no offsets
+        let mut network = LightningNetworkMap::new(700000);
+        for (i, delta) in [20, 40, 20, 40].into_iter().enumerate() {
+            network.add_node(Node::new(&format!("n{}", i), "n", delta).with_shadow_offset(ShadowOffset::NONE));
+        }
+        let model = HopCountModel::of(&network, 2016);
+
+        // 80 blocks: one 40-block hop, two 20-block hops, or the recipient itself is out
+        let hops = model.distribution(80);
+        assert_eq!(hops.probability(0), 0.0);
+        assert!((hops.probability(1) - 0.5 / 0.75).abs() < 1e-9);
+        assert!((hops.probability(2) - 0.25 / 0.75).abs() < 1e-9);
+        assert_eq!(hops.max_hops(), Some(2));
+        assert_eq!(hops.most_likely(), Some(1));
+        assert_eq!(hops.weight(2), 0.5);
+
+        assert_eq!(model.distribution(40).max_hops(), Some(0));
+        // Too little for any recipient: nothing fits and nothing is ruled out
+        let none = model.distribution(10);
+        assert_eq!(none.max_hops(), None);
+        assert_eq!(none.weight(3), 1.0);
+
+        let flat = HopCountDistribution::up_to(3);
+        assert_eq!(flat.mean(), 1.5);
+        assert_eq!(flat.covering(0.5), Some(1));
+        assert_eq!(flat.covering(1.0), Some(3));
+        assert_eq!(flat.beyond(2), 0.25);
+        assert_eq!(none.covering(0.99), None);
+    }
+}
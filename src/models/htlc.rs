@@ -2,8 +2,13 @@ pub const DEFAULT_FINAL_CLTV_DELTA: u32 = 40;  // Common default in LND
 pub const CLTV_EXPIRY_DELTA_MIN: u32 = 14;     // Minimum per-hop CLTV delta
 pub const CLTV_RANDOM_OFFSET_MIN: u32 = 0;
 pub const CLTV_RANDOM_OFFSET_MAX: u32 = 3 * DEFAULT_FINAL_CLTV_DELTA;  // Maximum random padding
+pub const MAX_ROUTE_HOPS: usize = 20;          // BOLT #4 onion packet limit
+pub const DEFAULT_MAX_CLTV_EXPIRY: u32 = 2016; // LND's cap on total route timelock
+pub const MAX_ACCEPTED_HTLCS: u16 = 483;       // BOLT #2 cap on HTLCs in flight per channel side
 
-// Represent a HTLC forwarded through the network
+use crate::models::{FinalDeltaPrior, HopCountDistribution, HopCountModel, HopPayload};
+
+// Represent a HTLC forwarded through the network, as the node it reached sees it
 #[derive(Debug, Clone)]
 pub struct HTLC {
     pub payment_hash: String,
@@ -11,6 +16,11 @@ pub struct HTLC {
     pub amount: u64,
     pub observed_at_block: u32,
     pub observed_by_node: String,
+    // Channel the HTLC came in over; None for the sender's own, and for observations
+    // recorded without it
+    pub incoming_channel: Option<String>,
+    // The node's layer of the onion, when the observation carries it
+    pub payload: Option<HopPayload>,
 }
 
 impl HTLC {
@@ -21,9 +31,38 @@ impl HTLC {
             amount,
             observed_at_block,
             observed_by_node: observed_by_node.to_string(),
+            incoming_channel: None,
+            payload: None,
         }
     }
 
+    // Add what the node read off the wire and out of the onion
+    pub fn with_onion(mut self, incoming_channel: Option<&str>, payload: HopPayload) -> Self {
+        self.incoming_channel = incoming_channel.map(str::to_string);
+        self.payload = Some(payload);
+        self
+    }
+
+    // The onion told the observer it's the recipient
+    pub fn reached_recipient(&self) -> bool {
+        self.payload.as_ref().is_some_and(HopPayload::is_final)
+    }
+
+    // Node the onion asks the observer, as a trampoline, to route the payment to
+    pub fn trampoline_destination(&self) -> Option<&str> {
+        self.payload.as_ref()?.trampoline_destination.as_deref()
+    }
+
+    // Channel the onion has the observer forward over
+    pub fn outgoing_channel(&self) -> Option<&str> {
+        self.payload.as_ref()?.short_channel_id.as_deref()
+    }
+
+    // What leaves the observer: the onion's amount when known, else what came in
+    pub fn forwarded_amount(&self) -> u64 {
+        self.payload.as_ref().map_or(self.amount, |payload| payload.amt_to_forward)
+    }
+
     // Calculate the remaining CLTV "budget" for this HTLC
     pub fn remaining_cltv_budget(&self) -> u32 {
         self.cltv_expiry.saturating_sub(self.observed_at_block)
@@ -38,38 +77,172 @@ impl HTLC {
 
     // Estimate approximate maximum hops remaining on route
     pub fn max_remaining_hops(&self) -> usize {
+        self.max_remaining_hops_for_final_delta(DEFAULT_FINAL_CLTV_DELTA)
+    }
+
+    fn max_remaining_hops_for_final_delta(&self, min_final_cltv_expiry_delta: u32) -> usize {
         let budget = self.remaining_cltv_budget();
 
-        // Estimate using minimum CLTV delta (most hops possible)
-        let theoretical_max = (budget.saturating_sub(DEFAULT_FINAL_CLTV_DELTA) / CLTV_EXPIRY_DELTA_MIN) as usize;
+        // Estimate using minimum CLTV delta (most hops possible), up to what an onion holds.
+        // How deep routes are searched is the analyzer's call.
+        let theoretical_max = (budget.saturating_sub(min_final_cltv_expiry_delta) / CLTV_EXPIRY_DELTA_MIN) as usize;
+        std::cmp::min(theoretical_max, MAX_ROUTE_HOPS)
+    }
 
-        // Cap to a reasonable number for performance
-        std::cmp::min(theoretical_max, 5)
+    // Estimate how many hops can sit between the sender and the observer, given the
+    // cap the sender's implementation puts on the total route timelock. The CLTV
+    // consumed upstream of the observer can't exceed what's left under the cap.
+    pub fn max_upstream_hops(&self, sender_cltv_cap: u32) -> usize {
+        let upstream_budget = sender_cltv_cap.saturating_sub(self.remaining_cltv_budget());
+        std::cmp::min((upstream_budget / CLTV_EXPIRY_DELTA_MIN) as usize, MAX_ROUTE_HOPS)
     }
 
-    // Detailed timelock analysis info
+    // Detailed timelock analysis info, for a recipient whose invoice uses any of the deltas
+    // implementations ship with
     pub fn timelock_analysis(&self) -> TimelockAnalysis {
+        self.timelock_analysis_with_prior(&FinalDeltaPrior::default())
+    }
+
+    // Timelock analysis against the min_final_cltv_expiry_delta of a candidate
+    // recipient's invoice
+    pub fn timelock_analysis_for_invoice(&self, min_final_cltv_expiry_delta: u32) -> TimelockAnalysis {
+        self.timelock_analysis_with_prior(&FinalDeltaPrior::exactly(min_final_cltv_expiry_delta))
+    }
+
+    // Timelock analysis for a recipient whose invoice delta is only known as a distribution.
+    // Budget and hop bounds assume the smallest delta, which leaves the most room.
+    pub fn timelock_analysis_with_prior(&self, prior: &FinalDeltaPrior) -> TimelockAnalysis {
         let remaining_budget = self.remaining_cltv_budget();
-        let final_delta_estimate = remaining_budget.saturating_sub(DEFAULT_FINAL_CLTV_DELTA);
-        let could_be_final = final_delta_estimate <= CLTV_RANDOM_OFFSET_MAX;
-        let max_hops = self.max_remaining_hops();
+        let min_final_cltv_expiry_delta = prior.smallest();
+        let final_delta_estimate = remaining_budget.saturating_sub(min_final_cltv_expiry_delta);
+        let final_hop_likelihood = prior.share(|delta| {
+            remaining_budget >= delta && remaining_budget - delta <= CLTV_RANDOM_OFFSET_MAX
+        });
+        let max_hops = self.max_remaining_hops_for_final_delta(min_final_cltv_expiry_delta);
 
         TimelockAnalysis {
+            min_final_cltv_expiry_delta,
             remaining_cltv_budget: remaining_budget,
             estimated_final_delta: final_delta_estimate,
-            could_be_final_hop: could_be_final,
+            could_be_final_hop: final_hop_likelihood > 0.0,
+            final_hop_likelihood,
             max_remaining_hops: max_hops,
+            remaining_hops: HopCountDistribution::up_to(max_hops),
         }
     }
+
+    // Timelock analysis under a network's priors, with a probability for every number of
+    // hops left rather than only the bound
+    pub fn timelock_analysis_with_model(&self, model: &HopCountModel) -> TimelockAnalysis {
+        let mut analysis = self.timelock_analysis_with_prior(model.final_deltas());
+        analysis.remaining_hops = self.remaining_hops(model);
+        analysis
+    }
+
+    // How likely each number of hops left is under a network's priors
+    pub fn remaining_hops(&self, model: &HopCountModel) -> HopCountDistribution {
+        model.distribution(self.remaining_cltv_budget())
+    }
+}
+
+// How an HTLC a node forwarded was resolved, as that node saw it: the preimage coming back
+// from downstream or a failure, and how long after the HTLC arrived
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HtlcResolution {
+    // The lock the node saw, a payment hash or with PTLCs its own point
+    pub payment_hash: String,
+    pub observed_by_node: String,
+    pub settled: bool,
+    pub hold_ms: u64,
+}
+
+impl HtlcResolution {
+    pub fn new(payment_hash: &str, observed_by_node: &str, settled: bool, hold_ms: u64) -> Self {
+        HtlcResolution {
+            payment_hash: payment_hash.to_string(),
+            observed_by_node: observed_by_node.to_string(),
+            settled,
+            hold_ms,
+        }
+    }
+}
+
+// An HTLC offered over one channel of a route, by the node upstream to the one downstream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelHtlc {
+    pub channel_id: String,
+    pub from: String,
+    pub to: String,
+    // The lock the downstream node sees, a payment hash or with PTLCs its own point
+    pub payment_hash: String,
+    pub amount: u64,
+    pub cltv_expiry: u32,
+}
+
+// Every HTLC of one payment route, in order from the sender to the recipient
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HtlcChain {
+    pub hops: Vec<ChannelHtlc>,
+}
+
+// A forwarder's part of a payment: the HTLC it accepted and the one it offered in exchange
+#[derive(Debug, Clone, Copy)]
+pub struct HtlcForward<'a> {
+    pub incoming: &'a ChannelHtlc,
+    pub outgoing: &'a ChannelHtlc,
+}
+
+impl HtlcChain {
+    pub fn new(hops: Vec<ChannelHtlc>) -> Self {
+        HtlcChain { hops }
+    }
+
+    // Incoming and outgoing HTLC of every node between the sender and the recipient
+    pub fn forwards(&self) -> impl Iterator<Item = HtlcForward<'_>> {
+        self.hops.windows(2).map(|pair| HtlcForward { incoming: &pair[0], outgoing: &pair[1] })
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::Value::Array(self.hops.iter().map(|hop| serde_json::json!({
+            "channel_id": hop.channel_id,
+            "from": hop.from,
+            "to": hop.to,
+            "payment_hash": hop.payment_hash,
+            "amount": hop.amount,
+            "cltv_expiry": hop.cltv_expiry,
+        })).collect())
+    }
+}
+
+impl HtlcForward<'_> {
+    pub fn node(&self) -> &str {
+        &self.incoming.to
+    }
+
+    // What the node kept for forwarding
+    pub fn fee_msat(&self) -> u64 {
+        self.incoming.amount.saturating_sub(self.outgoing.amount)
+    }
+
+    // Blocks the node gave itself between the two HTLCs' expiries
+    pub fn cltv_delta(&self) -> u32 {
+        self.incoming.cltv_expiry.saturating_sub(self.outgoing.cltv_expiry)
+    }
 }
 
 // Struct for timelock analysis results
 #[derive(Debug, Clone)]
 pub struct TimelockAnalysis {
+    // Smallest invoice delta the analysis allowed the recipient
+    pub min_final_cltv_expiry_delta: u32,
     pub remaining_cltv_budget: u32,
     pub estimated_final_delta: u32,
     pub could_be_final_hop: bool,
+    // Probability under the prior that the observer's next hop is the recipient
+    pub final_hop_likelihood: f64,
     pub max_remaining_hops: usize,
+    // Probability of each number of hops left, flat up to the bound unless priors were given
+    pub remaining_hops: HopCountDistribution,
 }
 
 #[cfg(test)]
@@ -127,5 +300,49 @@ mod tests {
         // Budget for many hops
         let multi_hop_htlc = HTLC::new("hash", 700200, 100000, 700000, "node");
         assert!(multi_hop_htlc.max_remaining_hops() > 1);
+        // 160 blocks past the invoice delta fit eleven minimum-delta hops, all of them counted
+        assert_eq!(multi_hop_htlc.max_remaining_hops(), 11);
+
+        // Never more than an onion holds
+        let far_htlc = HTLC::new("hash", 702016, 100000, 700000, "node");
+        assert_eq!(far_htlc.max_remaining_hops(), MAX_ROUTE_HOPS);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_max_upstream_hops() {
+        // Almost the whole cap is still left: the observer must be the sender's first hop
+        let htlc = HTLC::new("hash", 700190, 100000, 700000, "node");
+        assert_eq!(htlc.max_upstream_hops(200), 0);
+
+        // Room for two minimum-delta hops upstream
+        assert_eq!(htlc.max_upstream_hops(190 + 2 * CLTV_EXPIRY_DELTA_MIN), 2);
+
+        // Generous caps tell us nothing beyond the protocol's route length limit
+        assert_eq!(htlc.max_upstream_hops(DEFAULT_MAX_CLTV_EXPIRY), MAX_ROUTE_HOPS);
+    }
+
+    #[test]
+    fn test_invoice_final_delta() {
+        // 18 blocks left: too few for a default 40-block invoice, fine for an 18-block one
+        let htlc = HTLC::new("hash", 700018, 100000, 700000, "node");
+        assert!(!htlc.timelock_analysis_for_invoice(40).could_be_final_hop);
+
+        let analysis = htlc.timelock_analysis_for_invoice(18);
+        assert!(analysis.could_be_final_hop);
+        assert_eq!(analysis.estimated_final_delta, 0);
+
+        // Not knowing the invoice, only Core Lightning's share of recipients fit
+        let analysis = htlc.timelock_analysis();
+        assert_eq!(analysis.min_final_cltv_expiry_delta, 18);
+        assert!((analysis.final_hop_likelihood - 0.25).abs() < 1e-9);
+        // 100 blocks fit 18, 40 and 80 with some shadow offset, but not 144
+        let htlc = HTLC::new("hash", 700100, 100000, 700000, "node");
+        assert!((htlc.timelock_analysis().final_hop_likelihood - 0.85).abs() < 1e-9);
+
+        let prior = FinalDeltaPrior::from_weights([(80, 3.0), (18, 1.0), (80, 0.0)]);
+        assert_eq!(prior.smallest(), 18);
+        let mut rng = rand::rng();
+        let eighties = (0..1000).filter(|_| prior.sample(&mut rng) == 80).count();
+        assert!((650..850).contains(&eighties));
+    }
+}
@@ -0,0 +1,130 @@
+// Invoices issued by payment recipients
+
+use rand::Rng;
+
+use crate::models::htlc::DEFAULT_FINAL_CLTV_DELTA;
+use crate::models::network::LightningNetworkMap;
+
+// BOLT 11's default expiry of an hour, in blocks
+pub const DEFAULT_INVOICE_EXPIRY_BLOCKS: u32 = 6;
+
+// Represent a BOLT 11 invoice, reduced to the fields that matter for timelock analysis
+#[derive(Debug, Clone)]
+pub struct Invoice {
+    pub payment_hash: String,
+    pub payee: String,
+    pub amount_msat: u64,
+    // Blocks the payee needs between receiving the HTLC and its expiry. Chosen by the
+    // payee's implementation and unrelated to the delta it charges when forwarding.
+    pub min_final_cltv_expiry_delta: u32,
+    // Block the invoice was issued at, and blocks it can be paid for after that
+    pub created_at: u32,
+    pub expiry_blocks: u32,
+}
+
+impl Invoice {
+    pub fn new(payment_hash: &str, payee: &str, amount_msat: u64, min_final_cltv_expiry_delta: u32, created_at: u32) -> Self {
+        Invoice {
+            payment_hash: payment_hash.to_string(),
+            payee: payee.to_string(),
+            amount_msat,
+            min_final_cltv_expiry_delta,
+            created_at,
+            expiry_blocks: DEFAULT_INVOICE_EXPIRY_BLOCKS,
+        }
+    }
+
+    pub fn expiry_blocks(mut self, blocks: u32) -> Self {
+        self.expiry_blocks = blocks;
+        self
+    }
+
+    // First block the invoice can no longer be paid in
+    pub fn expires_at(&self) -> u32 {
+        self.created_at.saturating_add(self.expiry_blocks)
+    }
+
+    pub fn is_expired(&self, height: u32) -> bool {
+        height >= self.expires_at()
+    }
+
+    // Expiry of the HTLC the payee must receive, before any random offset from the sender
+    pub fn final_cltv_expiry(&self, current_height: u32) -> u32 {
+        current_height + self.min_final_cltv_expiry_delta
+    }
+}
+
+// Invoice deltas implementations ship with, by rough share of recipients: Core Lightning's
+// 18, LND's 40 and 80, and the day some wallets ask for to stay safe while offline
+pub const IMPLEMENTATION_FINAL_DELTAS: &[(u32, f64)] = &[(18, 0.25), (40, 0.2), (80, 0.4), (144, 0.15)];
+
+// What a recipient's `min_final_cltv_expiry_delta` is believed to be: each delta with its
+// probability
+#[derive(Debug, Clone, PartialEq)]
+pub struct FinalDeltaPrior {
+    deltas: Vec<(u32, f64)>,
+}
+
+impl Default for FinalDeltaPrior {
+    fn default() -> Self {
+        FinalDeltaPrior::from_weights(IMPLEMENTATION_FINAL_DELTAS.iter().copied())
+    }
+}
+
+impl FinalDeltaPrior {
+    // Deltas with their weights, normalized. Without any positive weight every invoice is
+    // assumed to use the default delta.
+    pub fn from_weights(weights: impl IntoIterator<Item = (u32, f64)>) -> Self {
+        let mut deltas: Vec<(u32, f64)> = Vec::new();
+        for (delta, weight) in weights.into_iter().filter(|(_, weight)| *weight > 0.0) {
+            match deltas.iter_mut().find(|(known, _)| *known == delta) {
+                Some(entry) => entry.1 += weight,
+                None => deltas.push((delta, weight)),
+            }
+        }
+        if deltas.is_empty() {
+            return FinalDeltaPrior::exactly(DEFAULT_FINAL_CLTV_DELTA);
+        }
+        deltas.sort_by_key(|(delta, _)| *delta);
+        let total: f64 = deltas.iter().map(|(_, weight)| weight).sum();
+        for entry in &mut deltas {
+            entry.1 /= total;
+        }
+        FinalDeltaPrior { deltas }
+    }
+
+    // A recipient known to ask for this delta
+    pub fn exactly(delta: u32) -> Self {
+        FinalDeltaPrior { deltas: vec![(delta, 1.0)] }
+    }
+
+    // The deltas the network's nodes put in their invoices, by how many nodes use each
+    pub fn of(network: &LightningNetworkMap) -> Self {
+        FinalDeltaPrior::from_weights(network.nodes.values().map(|node| (node.min_final_cltv_expiry_delta, 1.0)))
+    }
+
+    pub fn sample(&self, rng: &mut impl Rng) -> u32 {
+        let mut draw = rng.random::<f64>();
+        for (delta, share) in &self.deltas {
+            if draw < *share {
+                return *delta;
+            }
+            draw -= share;
+        }
+        self.deltas[self.deltas.len() - 1].0
+    }
+
+    // Every delta with its probability, smallest first
+    pub fn deltas(&self) -> &[(u32, f64)] {
+        &self.deltas
+    }
+
+    pub fn smallest(&self) -> u32 {
+        self.deltas[0].0
+    }
+
+    // Probability the recipient's delta is one `fits` accepts
+    pub fn share(&self, fits: impl Fn(u32) -> bool) -> f64 {
+        self.deltas.iter().filter(|(delta, _)| fits(*delta)).map(|(_, share)| share).sum()
+    }
+}
@@ -0,0 +1,98 @@
+// Node labels from an external file: what kind of operator runs a node (exchange, merchant,
+// LSP, personal, ...), so results can be broken down by who was paid
+
+use std::collections::HashMap;
+use serde_json::Value;
+use log::info;
+
+use crate::models::network::LightningNetworkMap;
+use crate::error::ThelmaError;
+
+// Group reports put nodes without a label in
+pub const UNLABELED: &str = "unlabeled";
+
+#[derive(Debug, Clone, Default)]
+pub struct NodeLabels {
+    // Label and, when the file gives one, alias of each public key
+    labels: HashMap<String, (String, Option<String>)>,
+}
+
+impl NodeLabels {
+    // A JSON object keyed by public key, each value either the label itself or an object
+    // with a `label` and optionally an `alias`:
+    // {"02ab...": "exchange", "03cd...": {"label": "lsp", "alias": "Some LSP"}}
+    pub fn parse(json: &str) -> Result<Self, ThelmaError> {
+        let value: Value = serde_json::from_str(json)?;
+        let entries = value.as_object()
+            .ok_or_else(|| ThelmaError::Config("labels file must be an object keyed by public key".to_string()))?;
+
+        let mut labels = HashMap::new();
+        for (pub_key, entry) in entries {
+            let (label, alias) = match entry {
+                Value::String(label) => (label.as_str(), None),
+                Value::Object(fields) => (fields.get("label").and_then(Value::as_str).unwrap_or(""),
+                                          fields.get("alias").and_then(Value::as_str)),
+                _ => ("", None),
+            };
+            let label = label.trim().to_lowercase();
+            if label.is_empty() {
+                return Err(ThelmaError::Config(format!("no label for node {} in the labels file", pub_key)));
+            }
+            labels.insert(pub_key.clone(), (label, alias.map(str::to_string)));
+        }
+        Ok(NodeLabels { labels })
+    }
+
+    pub fn load(path: &str) -> Result<Self, ThelmaError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| ThelmaError::Config(format!("can't read labels file {}: {}", path, e)))?;
+        NodeLabels::parse(&contents)
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    // Label the network's nodes, replacing their aliases where the file gives one. Returns
+    // the nodes labeled; keys not in the network are ignored.
+    pub fn apply(&self, network: &mut LightningNetworkMap) -> usize {
+        let mut labeled = 0;
+        for (pub_key, (label, alias)) in &self.labels {
+            if let Some(node) = network.nodes.get_mut(pub_key) {
+                node.label = Some(label.clone());
+                if let Some(alias) = alias {
+                    node.alias = alias.clone();
+                }
+                labeled += 1;
+            }
+        }
+        info!("Labeled {} of {} nodes in the labels file", labeled, self.labels.len());
+        labeled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Node;
+
+    #[test]
+    fn test_labels_applied_to_known_nodes() {
+        let mut network = LightningNetworkMap::new(700000);
+        network.add_node(Node::new("a", "a", 40));
+        network.add_node(Node::new("b", "b", 40));
+        let labels = NodeLabels::parse(r#"{"a": "Exchange", "b": {"label": "lsp", "alias": "Big LSP"}, "z": "merchant"}"#)
+            .unwrap();
+
+        assert_eq!(labels.apply(&mut network), 2);
+        assert_eq!(network.nodes["a"].label.as_deref(), Some("exchange"));
+        assert_eq!((network.nodes["b"].label.as_deref(), network.nodes["b"].alias.as_str()), (Some("lsp"), "Big LSP"));
+
+        assert!(NodeLabels::parse(r#"{"a": {"alias": "no label"}}"#).is_err());
+        assert!(NodeLabels::parse("[]").is_err());
+    }
+}
@@ -1,5 +1,18 @@
 pub mod network;
 pub mod htlc;
+pub mod invoice;
+pub mod onion;
+pub mod shadow;
+pub mod scid;
+pub mod labels;
+pub mod hops;
 
 pub use network::*;
-pub use htlc::*;
\ No newline at end of file
+pub use htlc::*;
+pub use invoice::*;
+pub use onion::*;
+pub use shadow::*;
+pub use scid::*;
+pub use labels::*;
+pub use hops::*;
+
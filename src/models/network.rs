@@ -1,11 +1,47 @@
 use std::collections::{HashMap, HashSet};
+use std::ops::RangeInclusive;
+use std::time::Instant;
+use rand::Rng;
+use petgraph::algo::kosaraju_scc;
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use petgraph::stable_graph::StableUnGraph;
+use petgraph::visit::EdgeRef;
+use log::trace;
+
+use crate::models::htlc::{DEFAULT_FINAL_CLTV_DELTA, CLTV_EXPIRY_DELTA_MIN, MAX_ACCEPTED_HTLCS};
+use crate::models::invoice::Invoice;
+use crate::models::shadow::ShadowOffset;
+use crate::models::scid::ShortChannelId;
+
+pub const DEFAULT_BASE_FEE_MSAT: u64 = 1000;  // Common default in LND
+pub const DEFAULT_FEE_RATE_PPM: u64 = 1;
+pub const DEFAULT_HTLC_MINIMUM_MSAT: u64 = 1000;  // Common default in LND
+pub const DEFAULT_DUST_LIMIT_SAT: u64 = 354;  // BOLT #3 floor, used by LND and LDK
+pub const DEFAULT_MAX_DUST_EXPOSURE_MSAT: u64 = 500_000_000;  // LND's default
+// Share of its capacity each side keeps in reserve, as BOLT #2 recommends
+const CHANNEL_RESERVE_PERCENT: u64 = 1;
+// Chance a sampled route stops at a node that could be the recipient, rather than continuing
+const ROUTE_SAMPLE_STOP_PROBABILITY: f64 = 0.5;
 
 // Represent a Lightning Network Node
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Node {
     pub pub_key: String,
     pub alias: String,
+    // Delta this node adds when forwarding
     pub cltv_expiry_delta: u32,
+    // Delta this node asks for in its own invoices, when it is the recipient
+    pub min_final_cltv_expiry_delta: u32,
+    pub base_fee_msat: u64,
+    pub fee_rate_ppm: u64,
+    // Random offset this node adds to the final timelock of payments it sends
+    pub shadow_offset: ShadowOffset,
+    // Kind of operator running the node, when a labels file says
+    pub label: Option<String>,
+    // Reachable only over Tor: its node announcement lists onion addresses alone
+    pub tor_only: bool,
+    // Region the node runs in, when the run models geography
+    pub region: Option<String>,
 }
 
 impl Node {
@@ -14,17 +50,144 @@ impl Node {
             pub_key: pub_key.to_string(),
             alias: alias.to_string(),
             cltv_expiry_delta,
+            min_final_cltv_expiry_delta: DEFAULT_FINAL_CLTV_DELTA,
+            base_fee_msat: DEFAULT_BASE_FEE_MSAT,
+            fee_rate_ppm: DEFAULT_FEE_RATE_PPM,
+            shadow_offset: ShadowOffset::default(),
+            label: None,
+            tor_only: false,
+            region: None,
+        }
+    }
+
+    pub fn with_min_final_cltv_expiry_delta(mut self, delta: u32) -> Self {
+        self.min_final_cltv_expiry_delta = delta;
+        self
+    }
+
+    pub fn with_shadow_offset(mut self, offset: ShadowOffset) -> Self {
+        self.shadow_offset = offset;
+        self
+    }
+
+    // Issue an invoice for receiving the given amount at block `height`
+    pub fn create_invoice(&self, payment_hash: &str, amount_msat: u64, height: u32) -> Invoice {
+        Invoice::new(payment_hash, &self.pub_key, amount_msat, self.min_final_cltv_expiry_delta, height)
+    }
+
+    // Fee charged by this node for forwarding the given amount
+    pub fn forwarding_fee_msat(&self, amount_msat: u64) -> u64 {
+        self.base_fee_msat + amount_msat * self.fee_rate_ppm / 1_000_000
+    }
+}
+
+// Names accepted by `ChannelStatus::from_name`
+pub const CHANNEL_STATUS_NAMES: &[&str] = &["active", "disabled", "zombie"];
+
+// Whether a channel still routes. Disabled channels had their `channel_update` flag set by
+// their peers; zombies stay in the graph long after anyone last updated them. Neither
+// forwards, but both still count as edges of the topology.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChannelStatus {
+    #[default]
+    Active,
+    Disabled,
+    Zombie,
+}
+
+impl ChannelStatus {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "active" => Some(ChannelStatus::Active),
+            "disabled" => Some(ChannelStatus::Disabled),
+            "zombie" => Some(ChannelStatus::Zombie),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChannelStatus::Active => "active",
+            ChannelStatus::Disabled => "disabled",
+            ChannelStatus::Zombie => "zombie",
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        *self == ChannelStatus::Active
+    }
+}
+
+// Names accepted by `CltvConstruction::from_name`
+pub const CLTV_CONSTRUCTION_NAMES: &[&str] = &["bolt4", "legacy"];
+
+// Which delta a route's timelock counts for each forwarding hop, when senders build it
+// and when the analysis retraces it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CltvConstruction {
+    // As BOLT #4 has it: the delta the forwarder announced for the channel it forwards over
+    #[default]
+    Bolt4,
+    // The forwarder's usual delta whatever the channel, as the simulator used to, kept to
+    // compare accuracy figures against earlier runs
+    Legacy,
+}
+
+impl CltvConstruction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bolt4" => Some(CltvConstruction::Bolt4),
+            "legacy" => Some(CltvConstruction::Legacy),
+            _ => None,
         }
     }
 }
 
-// Represent a channel between two nodes
+// Fees a node charges for forwarding over one particular channel, overriding its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelFees {
+    pub base_fee_msat: u64,
+    pub fee_rate_ppm: u64,
+}
+
+impl ChannelFees {
+    pub fn fee_msat(&self, amount_msat: u64) -> u64 {
+        self.base_fee_msat + amount_msat * self.fee_rate_ppm / 1_000_000
+    }
+}
+
+// Represent a channel between two nodes. Nodes may share several channels, told apart by
+// their short channel ids.
 #[derive(Debug, Clone)]
 pub struct Channel {
     pub channel_id: String,
     pub node1: String,
     pub node2: String,
     pub capacity: u64,
+    // Smallest and largest HTLC the channel policy accepts
+    pub htlc_minimum_msat: u64,
+    pub htlc_maximum_msat: u64,
+    // HTLCs each side accepts in flight at once
+    pub max_accepted_htlcs: u16,
+    // Balance (sat) neither side may spend below, so the channel never carries its full
+    // capacity
+    pub reserve_sat: u64,
+    // HTLCs below the dust limit (sat) get no output of their own on the commitment
+    // transaction; their total in flight is capped by the dust exposure, so a channel
+    // with none refuses them outright
+    pub dust_limit_sat: u64,
+    pub max_dust_exposure_msat: u64,
+    pub status: ChannelStatus,
+    // Block of the newest `channel_update` announced for the channel, if any was seen
+    pub last_update: Option<u32>,
+    // Fees each end charges for forwarding out over this channel, when they differ from
+    // the node's usual ones
+    pub node1_fees: Option<ChannelFees>,
+    pub node2_fees: Option<ChannelFees>,
+    // CLTV delta each end asks for forwarding out over this channel, when it differs from
+    // the node's usual one
+    pub node1_cltv_delta: Option<u32>,
+    pub node2_cltv_delta: Option<u32>,
 }
 
 impl Channel {
@@ -34,16 +197,212 @@ impl Channel {
             node1: node1.to_string(),
             node2: node2.to_string(),
             capacity,
+            htlc_minimum_msat: DEFAULT_HTLC_MINIMUM_MSAT,
+            htlc_maximum_msat: capacity.saturating_mul(1000),
+            max_accepted_htlcs: MAX_ACCEPTED_HTLCS,
+            reserve_sat: (capacity * CHANNEL_RESERVE_PERCENT / 100).max(DEFAULT_DUST_LIMIT_SAT),
+            dust_limit_sat: DEFAULT_DUST_LIMIT_SAT,
+            max_dust_exposure_msat: DEFAULT_MAX_DUST_EXPOSURE_MSAT,
+            status: ChannelStatus::Active,
+            last_update: None,
+            node1_fees: None,
+            node2_fees: None,
+            node1_cltv_delta: None,
+            node2_cltv_delta: None,
+        }
+    }
+
+    // Override the default HTLC size limits
+    pub fn with_htlc_limits(mut self, htlc_minimum_msat: u64, htlc_maximum_msat: u64) -> Self {
+        self.htlc_minimum_msat = htlc_minimum_msat;
+        self.htlc_maximum_msat = htlc_maximum_msat;
+        self
+    }
+
+    // Override the dust limit and how much dust the channel holds in flight; the reserve
+    // never drops below the dust limit
+    pub fn with_dust_limits(mut self, dust_limit_sat: u64, max_dust_exposure_msat: u64) -> Self {
+        self.dust_limit_sat = dust_limit_sat;
+        self.max_dust_exposure_msat = max_dust_exposure_msat;
+        self.reserve_sat = self.reserve_sat.max(dust_limit_sat);
+        self
+    }
+
+    pub fn with_reserve(mut self, reserve_sat: u64) -> Self {
+        self.reserve_sat = reserve_sat;
+        self
+    }
+
+    // Most a single HTLC can move over the channel once the reserve is kept back
+    pub fn spendable_msat(&self) -> u64 {
+        self.capacity.saturating_sub(self.reserve_sat).saturating_mul(1000)
+    }
+
+    pub fn is_dust(&self, amount_msat: u64) -> bool {
+        amount_msat < self.dust_limit_sat.saturating_mul(1000)
+    }
+
+    pub fn with_max_accepted_htlcs(mut self, max_accepted_htlcs: u16) -> Self {
+        self.max_accepted_htlcs = max_accepted_htlcs;
+        self
+    }
+
+    pub fn with_status(mut self, status: ChannelStatus) -> Self {
+        self.status = status;
+        self
+    }
+
+    // Have `node` charge these fees for forwarding over this channel
+    pub fn with_fees(mut self, node: &str, fees: ChannelFees) -> Self {
+        if node == self.node1 {
+            self.node1_fees = Some(fees);
+        } else if node == self.node2 {
+            self.node2_fees = Some(fees);
+        }
+        self
+    }
+
+    // Have `node` ask for this CLTV delta for forwarding over this channel
+    pub fn with_cltv_delta(mut self, node: &str, delta: u32) -> Self {
+        if node == self.node1 {
+            self.node1_cltv_delta = Some(delta);
+        } else if node == self.node2 {
+            self.node2_cltv_delta = Some(delta);
+        }
+        self
+    }
+
+    // CLTV delta `node` asks for on this channel, if it set one of its own for it
+    pub fn cltv_delta_of(&self, node: &str) -> Option<u32> {
+        if node == self.node1 {
+            self.node1_cltv_delta
+        } else if node == self.node2 {
+            self.node2_cltv_delta
+        } else {
+            None
+        }
+    }
+
+    // Fees `node` charges on this channel, if it set ones of its own for it
+    pub fn fees_of(&self, node: &str) -> Option<ChannelFees> {
+        if node == self.node1 {
+            self.node1_fees
+        } else if node == self.node2 {
+            self.node2_fees
+        } else {
+            None
+        }
+    }
+
+    // The channel's other end, seen from `node`
+    pub fn peer_of(&self, node: &str) -> Option<&str> {
+        if node == self.node1 {
+            Some(&self.node2)
+        } else if node == self.node2 {
+            Some(&self.node1)
+        } else {
+            None
+        }
+    }
+
+    pub fn with_last_update(mut self, block: u32) -> Self {
+        self.last_update = Some(block);
+        self
+    }
+
+    // Where the channel was funded, if its id is a short channel id
+    pub fn short_channel_id(&self) -> Option<ShortChannelId> {
+        ShortChannelId::parse(&self.channel_id)
+    }
+
+    // Whether the channel routes at all and its capacity, reserve, dust exposure and policy
+    // allow this amount
+    pub fn can_forward(&self, amount_msat: u64) -> bool {
+        self.status.is_active()
+            && amount_msat >= self.htlc_minimum_msat
+            && amount_msat <= self.htlc_maximum_msat
+            && amount_msat <= self.spendable_msat()
+            && (!self.is_dust(amount_msat) || amount_msat <= self.max_dust_exposure_msat)
+    }
+}
+
+// Interned node identifier, valid for the network map that issued it. Route searches
+// work on these instead of pubkey strings to avoid hashing and cloning in hot loops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(u32);
+
+impl NodeId {
+    fn index(self) -> NodeIndex {
+        NodeIndex::new(self.0 as usize)
+    }
+
+    fn from_index(index: NodeIndex) -> Self {
+        NodeId(index.index() as u32)
+    }
+
+    fn slot(self) -> usize {
+        self.0 as usize
+    }
+}
+
+// The parts of a node's policy route searches need, stored densely by NodeId
+#[derive(Debug, Clone, Copy)]
+struct RoutingPolicy {
+    cltv_expiry_delta: u32,
+    min_final_cltv_expiry_delta: u32,
+    base_fee_msat: u64,
+    fee_rate_ppm: u64,
+}
+
+impl RoutingPolicy {
+    // Assumed for nodes only known from their channels
+    const UNANNOUNCED: RoutingPolicy = RoutingPolicy {
+        cltv_expiry_delta: CLTV_EXPIRY_DELTA_MIN,
+        min_final_cltv_expiry_delta: DEFAULT_FINAL_CLTV_DELTA,
+        base_fee_msat: 0,
+        fee_rate_ppm: 0,
+    };
+
+    fn of(node: &Node) -> Self {
+        RoutingPolicy {
+            cltv_expiry_delta: node.cltv_expiry_delta,
+            min_final_cltv_expiry_delta: node.min_final_cltv_expiry_delta,
+            base_fee_msat: node.base_fee_msat,
+            fee_rate_ppm: node.fee_rate_ppm,
         }
     }
+
+    fn forwarding_fee_msat(&self, amount_msat: u64) -> u64 {
+        self.base_fee_msat + amount_msat * self.fee_rate_ppm / 1_000_000
+    }
+
+    fn fees(&self) -> ChannelFees {
+        ChannelFees { base_fee_msat: self.base_fee_msat, fee_rate_ppm: self.fee_rate_ppm }
+    }
 }
 
-// Core data structure for tracking Lightning Network state
+// Core data structure for tracking Lightning Network state. Add, change and remove nodes
+// and channels through the methods here so the graph and symbol table stay in sync.
+#[derive(Clone)]
 pub struct LightningNetworkMap {
     pub nodes: HashMap<String, Node>,
     pub channels: Vec<Channel>,
+    // Each node's channel peers, once per channel, kept in step with the graph below
     pub adjacency_list: HashMap<String, Vec<String>>,
     pub current_block_height: u32,
+    // Channel graph keyed by NodeId; edge weights index into `channels`
+    graph: StableUnGraph<(), usize>,
+    // Short channel id -> index into `channels`, and each channel's edge in the graph
+    channel_index: HashMap<String, usize>,
+    edges: Vec<EdgeIndex>,
+    // Symbol table: pubkey -> NodeId, and NodeId -> pubkey
+    node_ids: HashMap<String, NodeId>,
+    pub_keys: Vec<String>,
+    policies: Vec<RoutingPolicy>,
+    // Largest shadow offset any node adds, which bounds how much slack a route may leave
+    max_shadow_offset: u32,
+    // Bumped whenever nodes or channels change, so cached searches can tell they're stale
+    topology_version: u64,
 }
 
 impl LightningNetworkMap {
@@ -53,94 +412,702 @@ impl LightningNetworkMap {
             channels: Vec::new(),
             adjacency_list: HashMap::new(),
             current_block_height,
+            graph: StableUnGraph::default(),
+            channel_index: HashMap::new(),
+            edges: Vec::new(),
+            node_ids: HashMap::new(),
+            pub_keys: Vec::new(),
+            policies: Vec::new(),
+            max_shadow_offset: 0,
+            topology_version: 0,
         }
     }
 
     pub fn add_node(&mut self, node: Node) {
-        self.adjacency_list.entry(node.pub_key.clone()).or_insert(Vec::new());
-        self.nodes.insert(node.pub_key.clone(), node);
+        let id = self.intern(&node.pub_key);
+        self.adjacency_list.entry(node.pub_key.clone()).or_default();
+        self.policies[id.slot()] = RoutingPolicy::of(&node);
+        let offset = node.shadow_offset.max;
+        let replaced = self.nodes.insert(node.pub_key.clone(), node);
+        // A replaced node may have been the one adding the largest offset
+        self.max_shadow_offset = match replaced {
+            Some(old) if old.shadow_offset.max == self.max_shadow_offset =>
+                self.nodes.values().map(|n| n.shadow_offset.max).max().unwrap_or(0),
+            _ => self.max_shadow_offset.max(offset),
+        };
+        self.topology_version += 1;
     }
 
+    // Add a channel, or replace the one with the same short channel id
     pub fn add_channel(&mut self, channel: Channel) {
-        // Update adjacency list
-        self.adjacency_list.entry(channel.node1.clone())
-            .or_insert(Vec::new())
-            .push(channel.node2.clone());
+        let a = self.intern(&channel.node1);
+        let b = self.intern(&channel.node2);
+        match self.channel_index.get(&channel.channel_id) {
+            Some(&index) => {
+                self.unlink(self.edges[index]);
+                self.graph.remove_edge(self.edges[index]);
+                self.edges[index] = self.graph.add_edge(a.index(), b.index(), index);
+                self.link(self.edges[index]);
+                self.channels[index] = channel;
+            }
+            None => {
+                let index = self.channels.len();
+                self.edges.push(self.graph.add_edge(a.index(), b.index(), index));
+                self.link(self.edges[index]);
+                self.channel_index.insert(channel.channel_id.clone(), index);
+                self.channels.push(channel);
+            }
+        }
+
+        self.topology_version += 1;
+    }
+
+    // Each end of a channel's edge and where the edge sits among that end's edges. Peers in
+    // `adjacency_list` sit at the same positions, so they come in the graph's own order.
+    fn edge_positions(&self, edge: EdgeIndex) -> Vec<(NodeId, NodeId, usize)> {
+        let Some((a, b)) = self.graph.edge_endpoints(edge) else {
+            return Vec::new();
+        };
+        let ends = if a == b { vec![(a, b)] } else { vec![(a, b), (b, a)] };
+        ends.into_iter()
+            .filter_map(|(node, peer)| self.graph.edges(node).position(|e| e.id() == edge)
+                .map(|position| (NodeId::from_index(node), NodeId::from_index(peer), position)))
+            .collect()
+    }
+
+    fn link(&mut self, edge: EdgeIndex) {
+        for (node, peer, position) in self.edge_positions(edge) {
+            let peer = self.pub_key(peer).clone();
+            self.adjacency_list.entry(self.pub_key(node).clone()).or_default().insert(position, peer);
+        }
+    }
+
+    fn unlink(&mut self, edge: EdgeIndex) {
+        for (node, _, position) in self.edge_positions(edge) {
+            if let Some(peers) = self.adjacency_list.get_mut(&self.pub_keys[node.slot()]) {
+                peers.remove(position);
+            }
+        }
+    }
+
+    // The channel with this short channel id
+    pub fn channel(&self, channel_id: &str) -> Option<&Channel> {
+        self.channel_index.get(channel_id).map(|&index| &self.channels[index])
+    }
+
+    // Take a channel out of the graph, e.g. once it's closed. The last channel moves into its
+    // slot, so indices into `channels` don't survive this.
+    pub fn remove_channel(&mut self, channel_id: &str) -> Option<Channel> {
+        let index = self.channel_index.remove(channel_id)?;
+        self.unlink(self.edges[index]);
+        self.graph.remove_edge(self.edges.swap_remove(index));
+        let channel = self.channels.swap_remove(index);
+        if let Some(moved) = self.channels.get(index) {
+            self.channel_index.insert(moved.channel_id.clone(), index);
+            self.graph[self.edges[index]] = index;
+        }
+        self.topology_version += 1;
+        Some(channel)
+    }
+
+    // Take a node and all its channels out of the graph. Returns the node's announcement,
+    // None if it never made one, though its channels are removed either way.
+    pub fn remove_node(&mut self, pub_key: &str) -> Option<Node> {
+        let id = self.node_id(pub_key)?;
+        let channel_ids: Vec<String> = self.graph.edges(id.index())
+            .map(|edge| self.channels[*edge.weight()].channel_id.clone())
+            .collect();
+        for channel_id in &channel_ids {
+            self.remove_channel(channel_id);
+        }
+
+        // Graph slots are never reused, so the node stays behind as an isolated tombstone
+        // and comes back under a new NodeId if it's added again
+        self.node_ids.remove(pub_key);
+        self.adjacency_list.remove(pub_key);
+        self.policies[id.slot()] = RoutingPolicy::UNANNOUNCED;
+        let removed = self.nodes.remove(pub_key);
+        if removed.as_ref().is_some_and(|node| node.shadow_offset.max == self.max_shadow_offset) {
+            self.max_shadow_offset = self.nodes.values().map(|n| n.shadow_offset.max).max().unwrap_or(0);
+        }
+        self.topology_version += 1;
+        removed
+    }
+
+    // Apply a `channel_update` from one end of a channel: the fees that node now charges
+    // for forwarding over it. Returns false if there's no such channel or the node isn't
+    // one of its ends.
+    pub fn update_channel_policy(&mut self, channel_id: &str, node: &str, fees: ChannelFees) -> bool {
+        let Some(&index) = self.channel_index.get(channel_id) else {
+            return false;
+        };
+        let channel = &self.channels[index];
+        if channel.node1 != node && channel.node2 != node {
+            return false;
+        }
+        self.channels[index] = channel.clone().with_fees(node, fees);
+        self.topology_version += 1;
+        true
+    }
+
+    // Change whether a channel routes. Returns false if there's no such channel.
+    pub fn set_channel_status(&mut self, channel_id: &str, status: ChannelStatus) -> bool {
+        let Some(&index) = self.channel_index.get(channel_id) else {
+            return false;
+        };
+        self.channels[index].status = status;
+        self.topology_version += 1;
+        true
+    }
+
+    // Number of channels that are in the graph but don't route
+    pub fn inactive_channel_count(&self) -> usize {
+        self.channels.iter().filter(|c| !c.status.is_active()).count()
+    }
+
+    pub fn topology_version(&self) -> u64 {
+        self.topology_version
+    }
+
+    // Most blocks any sender's shadow offset adds to a route's timelock
+    pub fn max_shadow_offset(&self) -> u32 {
+        self.max_shadow_offset
+    }
+
+    // Rough heap footprint of the map: nodes, channels, the graph and its indexes, counting
+    // allocated capacity and string contents but not allocator overhead
+    pub fn memory_bytes(&self) -> usize {
+        use std::mem::size_of;
+        let strings = |s: &[&String]| s.iter().map(|s| s.capacity()).sum::<usize>();
+        let nodes = self.nodes.capacity() * (size_of::<String>() + size_of::<Node>() + 1)
+            + self.nodes.iter()
+                .map(|(key, node)| key.capacity() + node.pub_key.capacity() + node.alias.capacity()
+                    + node.label.as_ref().map_or(0, String::capacity)
+                    + node.region.as_ref().map_or(0, String::capacity))
+                .sum::<usize>();
+        let channels = self.channels.capacity() * size_of::<Channel>()
+            + self.channels.iter()
+                .map(|c| strings(&[&c.channel_id, &c.node1, &c.node2]))
+                .sum::<usize>();
+        // Each graph node holds its first outgoing and incoming edge; each edge its weight,
+        // ends and the next edges around both ends
+        let graph = self.graph.node_count() * 2 * size_of::<EdgeIndex>()
+            + self.graph.edge_count() * (size_of::<usize>() + 2 * size_of::<NodeIndex>() + 2 * size_of::<EdgeIndex>());
+        let indexes = self.channel_index.capacity() * (size_of::<String>() + size_of::<usize>() + 1)
+            + self.channel_index.keys().map(String::capacity).sum::<usize>()
+            + self.edges.capacity() * size_of::<EdgeIndex>()
+            + self.node_ids.capacity() * (size_of::<String>() + size_of::<NodeId>() + 1)
+            + self.node_ids.keys().map(String::capacity).sum::<usize>()
+            + self.pub_keys.capacity() * size_of::<String>()
+            + self.pub_keys.iter().map(String::capacity).sum::<usize>()
+            + self.policies.capacity() * size_of::<RoutingPolicy>()
+            + self.adjacency_list.capacity() * (2 * size_of::<String>() + size_of::<Vec<String>>() + 1)
+            + self.adjacency_list.iter()
+                .map(|(key, peers)| key.capacity() + peers.capacity() * size_of::<String>()
+                    + peers.iter().map(String::capacity).sum::<usize>())
+                .sum::<usize>();
+        nodes + channels + graph + indexes
+    }
+
+    // Bytes one hop of an enumerated route takes once its node is turned back into a pubkey,
+    // on top of the hop's place in the `RouteSet`
+    pub fn route_hop_bytes(&self) -> usize {
+        let keys = self.pub_keys.iter().map(String::len).sum::<usize>() / self.pub_keys.len().max(1);
+        std::mem::size_of::<NodeId>() + std::mem::size_of::<String>() + keys
+    }
+
+    // Channels may reference nodes we haven't seen an announcement for yet
+    fn intern(&mut self, pub_key: &str) -> NodeId {
+        if let Some(&id) = self.node_ids.get(pub_key) {
+            return id;
+        }
+        // Graph nodes are never removed, so indices stay dense and match the table slots
+        let id = NodeId::from_index(self.graph.add_node(()));
+        self.node_ids.insert(pub_key.to_string(), id);
+        self.pub_keys.push(pub_key.to_string());
+        self.policies.push(RoutingPolicy::UNANNOUNCED);
+        id
+    }
+
+    pub fn node_id(&self, pub_key: &str) -> Option<NodeId> {
+        self.node_ids.get(pub_key).copied()
+    }
+
+    pub fn pub_key(&self, id: NodeId) -> &String {
+        &self.pub_keys[id.slot()]
+    }
+
+    pub fn pub_keys_of(&self, path: &[NodeId]) -> Vec<String> {
+        path.iter().map(|&id| self.pub_key(id).clone()).collect()
+    }
+
+    fn channels_iter<'a>(&'a self, a: &str, b: &str) -> impl Iterator<Item = &'a Channel> + 'a {
+        let endpoints = self.node_id(a).zip(self.node_id(b));
+        endpoints.into_iter()
+            .flat_map(move |(a, b)| self.graph.edges_connecting(a.index(), b.index()))
+            .map(move |edge| &self.channels[*edge.weight()])
+    }
+
+    // All channels between two nodes
+    pub fn channels_between(&self, a: &str, b: &str) -> Vec<&Channel> {
+        self.channels_iter(a, b).collect()
+    }
+
+    // Number of channels a node has
+    pub fn degree(&self, node_pub_key: &str) -> usize {
+        self.node_id(node_pub_key)
+            .map(|id| self.graph.edges(id.index()).count())
+            .unwrap_or(0)
+    }
+
+    // Number of connected components, counting isolated nodes but not removed ones.
+    // Channels are undirected, so these are exactly the strongly connected components.
+    pub fn connected_components(&self) -> usize {
+        kosaraju_scc(&self.graph).into_iter()
+            .filter(|component| component.iter().any(|&index| self.is_live(NodeId::from_index(index))))
+            .count()
+    }
+
+    // Whether a graph slot still belongs to a node rather than a removed one's tombstone
+    fn is_live(&self, id: NodeId) -> bool {
+        self.node_ids.get(self.pub_key(id)) == Some(&id)
+    }
+
+    // Largest capacity (sat) available between two nodes
+    pub fn max_capacity_between(&self, a: &str, b: &str) -> Option<u64> {
+        self.channels_iter(a, b).map(|c| c.capacity).max()
+    }
+
+    // Whether some channel between two nodes could carry the amount at all
+    pub fn can_carry(&self, a: &str, b: &str, amount_msat: u64) -> bool {
+        self.channels_iter(a, b).any(|c| c.can_forward(amount_msat))
+    }
 
-        self.adjacency_list.entry(channel.node2.clone())
-            .or_insert(Vec::new())
-            .push(channel.node1.clone());
+    // Probability that a route can carry the amount, assuming each channel's balance is
+    // uniformly distributed over its capacity. Small channels make large payments implausible;
+    // a hop goes through if any of its parallel channels has the balance.
+    pub fn route_capacity_plausibility(&self, route: &[String], amount_msat: u64) -> f32 {
+        let mut probability = 1.0f64;
 
-        self.channels.push(channel);
+        for hop in route.windows(2) {
+            let mut all_short = 1.0f64;
+            let mut usable = false;
+            for channel in self.channels_iter(&hop[0], &hop[1]).filter(|c| c.can_forward(amount_msat)) {
+                all_short *= amount_msat as f64 / (channel.spendable_msat() + 1) as f64;
+                usable = true;
+            }
+            if !usable {
+                return 0.0;
+            }
+
+            probability *= 1.0 - all_short;
+        }
+
+        probability as f32
+    }
+
+    // Fees `from` charges for forwarding the amount on to `to`, over the cheapest channel
+    // between them that can carry it. None when no channel can.
+    pub fn hop_fee_msat(&self, from: &str, to: &str, amount_msat: u64) -> Option<u64> {
+        self.hop_channel(from, to, amount_msat).map(|channel| self.channel_fees(channel, from).fee_msat(amount_msat))
     }
 
-    // Get all neighbors of a node
+    // The channel a sender would have `from` forward over to reach `to`: the cheapest that
+    // can carry the amount, the biggest among equally cheap ones
+    pub fn hop_channel(&self, from: &str, to: &str, amount_msat: u64) -> Option<&Channel> {
+        self.channels_iter(from, to)
+            .filter(|c| c.can_forward(amount_msat))
+            .min_by_key(|c| (self.channel_fees(c, from).fee_msat(amount_msat), std::cmp::Reverse(c.capacity)))
+    }
+
+    // What `node` charges on the channel: its own fees for it, or its usual ones
+    fn channel_fees(&self, channel: &Channel, node: &str) -> ChannelFees {
+        channel.fees_of(node).unwrap_or_else(|| match self.node_id(node) {
+            Some(id) => self.policies[id.slot()].fees(),
+            None => RoutingPolicy::UNANNOUNCED.fees(),
+        })
+    }
+
+    // Delta `node` adds forwarding over the channel: under BOLT #4 the one it announced for
+    // the channel, if it did, and otherwise its usual one
+    fn forwarding_delta(&self, node: NodeId, channel: &Channel, construction: CltvConstruction) -> u32 {
+        let usual = self.policies[node.slot()].cltv_expiry_delta;
+        match construction {
+            CltvConstruction::Bolt4 => channel.cltv_delta_of(self.pub_key(node)).unwrap_or(usual),
+            CltvConstruction::Legacy => usual,
+        }
+    }
+
+    // Delta `from` adds forwarding the amount on to `to`, over the channel a sender would
+    // have it use
+    pub fn hop_cltv_delta(&self, from: &str, to: &str, amount_msat: u64, construction: CltvConstruction) -> u32 {
+        let Some(id) = self.node_id(from) else { return 0 };
+        match self.hop_channel(from, to, amount_msat).or_else(|| self.channels_iter(from, to).next()) {
+            Some(channel) => self.forwarding_delta(id, channel, construction),
+            None => self.policies[id.slot()].cltv_expiry_delta,
+        }
+    }
+
+    // Get all neighbors of a node (once per channel, so parallel channels repeat a neighbor)
     pub fn get_neighbors(&self, node_pub_key: &str) -> Option<&Vec<String>> {
         self.adjacency_list.get(node_pub_key)
     }
 
-    // Find possible routes from a node given a remaining CLTV budget
+    // Distinct peers a node can route through, however many channels it has to each,
+    // leaving out peers reached only over disabled or zombie channels
+    pub fn routing_peers(&self, node_pub_key: &str) -> Vec<&String> {
+        let Some(id) = self.node_id(node_pub_key) else { return Vec::new() };
+        let mut seen = HashSet::new();
+        self.usable_neighbors(id, None)
+            .filter(|&(peer, _)| peer != id && seen.insert(peer))
+            .map(|(peer, _)| self.pub_key(peer))
+            .collect()
+    }
+
+    // Neighbors reachable over a channel whose policy accepts the amount, once per such channel.
+    // Without an amount every channel that routes at all counts.
+    fn usable_neighbors(&self, id: NodeId, amount_msat: Option<u64>) -> impl Iterator<Item = (NodeId, &Channel)> + '_ {
+        self.graph.edges(id.index())
+            // Undirected edges are reported with the queried node as their source
+            .map(move |edge| (NodeId::from_index(edge.target()), &self.channels[*edge.weight()]))
+            .filter(move |(_, channel)| match amount_msat {
+                Some(amount) => channel.can_forward(amount),
+                None => channel.status.is_active(),
+            })
+    }
+
+    // Whether every hop of a route has a channel that could carry the amount
+    pub fn route_can_carry(&self, route: &[NodeId], amount_msat: u64) -> bool {
+        route.windows(2).all(|hop| {
+            self.graph.edges_connecting(hop[0].index(), hop[1].index())
+                .any(|edge| self.channels[*edge.weight()].can_forward(amount_msat))
+        })
+    }
+
+    // Total routing fees paid along a path (every node except sender and recipient forwards,
+    // each over the cheapest channel to the next node)
+    pub fn route_fee_msat(&self, path: &[String], amount_msat: u64) -> u64 {
+        if path.len() < 3 {
+            return 0;
+        }
+
+        path[1..].windows(2)
+            .filter(|hop| self.nodes.contains_key(&hop[0]))
+            .map(|hop| self.hop_fee_msat(&hop[0], &hop[1], amount_msat)
+                .unwrap_or_else(|| self.nodes[&hop[0]].forwarding_fee_msat(amount_msat)))
+            .sum()
+    }
+
+    // Find possible routes from a node given the CLTV budget of the HTLC it received,
+    // skipping channels too small to have carried the observed amount. The budget
+    // covers the starting node's own forwarding delta. Routes are built as BOLT #4 has it.
     pub fn find_possible_routes_with_budget(&self,
                                             starting_node: &str,
                                             cltv_budget: u32,
-                                            max_hops: usize) -> Vec<Vec<String>> {
-        let mut routes = Vec::new();
-        let mut visited = HashSet::new();
-        let mut current_path = vec![starting_node.to_string()];
+                                            max_hops: usize,
+                                            amount_msat: u64) -> Vec<Vec<String>> {
+        trace!("Starting node: {}", starting_node);
+        trace!("Budget: {}", cltv_budget);
+        trace!("Max hops: {}", max_hops);
+
+        // The sender's random offset means the route itself may need up to that much less
+        self.find_routes_in_window(starting_node,
+                                   cltv_budget.saturating_sub(self.max_shadow_offset),
+                                   cltv_budget,
+                                   max_hops,
+                                   Some(amount_msat))
+            .iter()
+            .map(|(route, _)| self.pub_keys_of(route))
+            .collect()
+    }
+
+    // Find every route from a node whose total timelock (forwarding deltas plus the
+    // recipient's invoice delta) lies between `lowest` and `highest`, along with that
+    // timelock. Without an amount, channel limits are ignored. Routes are built as
+    // BOLT #4 has it.
+    pub fn find_routes_in_window(&self,
+                                 starting_node: &str,
+                                 lowest: u32,
+                                 highest: u32,
+                                 max_hops: usize,
+                                 amount_msat: Option<u64>) -> RouteSet {
+        match self.find_routes_in_window_limited(starting_node, lowest..=highest, max_hops, amount_msat,
+                                                 CltvConstruction::default(), SearchLimits::NONE) {
+            BoundedRoutes::Complete(routes) => routes,
+            _ => RouteSet::default(),
+        }
+    }
+
+    // Same as `find_routes_in_window` for routes built the given way, held to the limits
+    pub fn find_routes_in_window_limited(&self,
+                                         starting_node: &str,
+                                         window: RangeInclusive<u32>,
+                                         max_hops: usize,
+                                         amount_msat: Option<u64>,
+                                         construction: CltvConstruction,
+                                         limits: SearchLimits) -> BoundedRoutes {
+        let Some(start) = self.node_id(starting_node) else {
+            return BoundedRoutes::Complete(RouteSet::default());
+        };
+
+        let mut search = RouteSearch {
+            network: self,
+            lowest: *window.start(),
+            highest: *window.end(),
+            max_depth: max_hops,
+            amount_msat,
+            construction,
+            bounds: RemainingCostBounds::compute(self, max_hops, amount_msat, construction),
+            visited: vec![false; self.pub_keys.len()],
+            path: vec![start],
+            routes: RouteSet::default(),
+            max_stored_hops: limits.max_stored_hops,
+            exceeded: false,
+            deadline: limits.deadline,
+            visits: 0,
+            timed_out: false,
+        };
+        search.dfs(start, 0);
+
+        if search.exceeded {
+            BoundedRoutes::TooMany
+        } else if search.timed_out {
+            BoundedRoutes::TimedOut(search.routes)
+        } else {
+            BoundedRoutes::Complete(search.routes)
+        }
+    }
+
+    // Find routes from a node to any of a known set of candidate recipients within the CLTV
+    // budget. Searches forward from the start and backward from the candidates, each for
+    // about half the hops, and joins the halves where they meet. Much cheaper than
+    // enumerating every route on hub-heavy graphs, where the branching factor explodes.
+    pub fn find_routes_to_candidates(&self,
+                                     starting_node: &str,
+                                     candidates: &HashSet<String>,
+                                     cltv_budget: u32,
+                                     max_hops: usize,
+                                     amount_msat: u64,
+                                     construction: CltvConstruction) -> Vec<Vec<String>> {
+        let Some(start) = self.node_id(starting_node) else {
+            return Vec::new();
+        };
+        let forward_half = HalfSearch { budget: cltv_budget, depth: max_hops.div_ceil(2), amount_msat, construction };
+        let backward_half = HalfSearch { depth: max_hops / 2, ..forward_half };
 
-        println!("Starting node: {}", starting_node);
-        println!("Budget: {}", cltv_budget);
-        println!("Max hops: {}", max_hops);
-        println!("Current Path: {:?}", current_path);
+        // Middle node -> (path from start up to it, forwarding deltas used before it)
+        let mut forward: HashMap<NodeId, Vec<(Vec<NodeId>, u32)>> = HashMap::new();
+        let mut path = vec![start];
+        self.collect_forward_halves(&mut forward, &mut path, 0, forward_half);
 
-        self.dfs_routes(&mut routes, &mut visited, &mut current_path, starting_node, cltv_budget, 0, max_hops);
+        let mut routes: Vec<Vec<NodeId>> = Vec::new();
 
+        for candidate in candidates {
+            if !self.nodes.contains_key(candidate) {
+                continue;
+            }
+            let Some(candidate) = self.node_id(candidate).filter(|&id| id != start) else {
+                continue;
+            };
+
+            // Middle node -> (path from it to the candidate, timelock needed from it onwards)
+            let mut backward: Vec<(Vec<NodeId>, u32)> = Vec::new();
+            let final_delta = self.policies[candidate.slot()].min_final_cltv_expiry_delta;
+            let mut suffix = vec![candidate];
+            self.collect_backward_halves(&mut backward, &mut suffix, final_delta, backward_half);
+
+            for (suffix, needed) in &backward {
+                let Some(halves) = forward.get(&suffix[0]) else { continue };
+                let backward_hops = suffix.len() - 1;
+
+                for (prefix, used) in halves {
+                    // Split every route at the same place so it's only found once
+                    let forward_hops = prefix.len() - 1;
+                    if forward_hops != backward_hops && forward_hops != backward_hops + 1 {
+                        continue;
+                    }
+                    if forward_hops + backward_hops == 0 {
+                        continue;
+                    }
+
+                    let total = used + needed;
+                    if total > cltv_budget || cltv_budget - total > self.max_shadow_offset {
+                        continue;
+                    }
+                    if suffix[1..].iter().any(|node| prefix.contains(node)) {
+                        continue;
+                    }
+
+                    let mut route = prefix.clone();
+                    route.extend_from_slice(&suffix[1..]);
+                    routes.push(route);
+                }
+            }
+        }
+
+        // Parallel channels list the same neighbor twice
+        let mut routes: Vec<Vec<String>> = routes.iter().map(|route| self.pub_keys_of(route)).collect();
+        routes.sort();
+        routes.dedup();
         routes
     }
 
-    // DFS helper for route finding
-    fn dfs_routes(&self,
-                  routes: &mut Vec<Vec<String>>,
-                  visited: &mut HashSet<String>,
-                  current_path: &mut Vec<String>,
-                  current_node: &str,
-                  budget: u32,
-                  used_budget: u32,
-                  max_depth: usize) {
-        if current_path.len().saturating_sub(1) > max_depth || used_budget > budget {
+    // Smallest timelock a route of up to `max_hops` hops from this node needs to end at each
+    // other node, ignoring channel limits
+    pub fn reachability_from(&self, starting_node: &str, max_hops: usize, construction: CltvConstruction) -> Reachability {
+        let mut reachability = Reachability {
+            topology_version: self.topology_version,
+            min_timelock: vec![u32::MAX; self.pub_keys.len()],
+        };
+        let Some(start) = self.node_id(starting_node) else { return reachability };
+
+        // Forwarding deltas used getting to each node, relaxed one hop at a time over walks,
+        // which can only make the bound lower
+        let mut used = vec![u32::MAX; self.pub_keys.len()];
+        used[start.slot()] = 0;
+        for _ in 0..max_hops {
+            let mut next = used.clone();
+            for slot in (0..used.len()).filter(|&slot| used[slot] != u32::MAX) {
+                let node = NodeId(slot as u32);
+                for (neighbor, channel) in self.usable_neighbors(node, None) {
+                    let onward = used[slot].saturating_add(self.forwarding_delta(node, channel, construction));
+                    if onward < next[neighbor.slot()] {
+                        next[neighbor.slot()] = onward;
+                    }
+                }
+            }
+            if next == used {
+                break;
+            }
+            used = next;
+        }
+
+        for (slot, &used) in used.iter().enumerate() {
+            if used != u32::MAX && slot != start.slot() {
+                reachability.min_timelock[slot] = self.timelock_ending_at(NodeId(slot as u32), used);
+            }
+        }
+        reachability
+    }
+
+    // Every simple path from the start up to `depth` hops, keyed by the node it ends at
+    fn collect_forward_halves(&self,
+                              halves: &mut HashMap<NodeId, Vec<(Vec<NodeId>, u32)>>,
+                              path: &mut Vec<NodeId>,
+                              used_budget: u32,
+                              search: HalfSearch) {
+        let current = path[path.len() - 1];
+        halves.entry(current).or_default().push((path.clone(), used_budget));
+
+        if path.len() > search.depth {
             return;
         }
 
-        visited.insert(current_node.to_string());
+        for (neighbor, channel) in self.usable_neighbors(current, Some(search.amount_msat)) {
+            let onward_budget = used_budget + self.forwarding_delta(current, channel, search.construction);
+            if onward_budget < search.budget && !path.contains(&neighbor) {
+                path.push(neighbor);
+                self.collect_forward_halves(halves, path, onward_budget, search);
+                path.pop();
+            }
+        }
+    }
 
-        // If we've used a plausible amount of the budget, this could be a destination
-        // For simplicity, we're using a constant DEFAULT_FINAL_CLTV_DELTA assumption
-        // In a real implementation, this would consider the node's actual preferences
-        const DEFAULT_FINAL_CLTV_DELTA: u32 = 40;
+    // Every simple path of up to `depth` hops ending at the candidate, walked backwards.
+    // `needed` is the timelock the HTLC must carry when it reaches the suffix's first node.
+    fn collect_backward_halves(&self,
+                               halves: &mut Vec<(Vec<NodeId>, u32)>,
+                               suffix: &mut Vec<NodeId>,
+                               needed: u32,
+                               search: HalfSearch) {
+        halves.push((suffix.clone(), needed));
 
-        if current_path.len() > 1 && used_budget <= budget &&
-            used_budget >= budget - DEFAULT_FINAL_CLTV_DELTA {
-            routes.push(current_path.clone());
+        if suffix.len() > search.depth {
+            return;
+        }
+
+        let current = suffix[0];
+        for (neighbor, channel) in self.usable_neighbors(current, Some(search.amount_msat)) {
+            let needed_before = needed + self.forwarding_delta(neighbor, channel, search.construction);
+            if needed_before <= search.budget && !suffix.contains(&neighbor) {
+                suffix.insert(0, neighbor);
+                self.collect_backward_halves(halves, suffix, needed_before, search);
+                suffix.remove(0);
+            }
         }
+    }
+
+    // Sample one random route from a node within the CLTV budget. Each hop is chosen with
+    // probability proportional to channel capacity over the next node's fee, mimicking how
+    // senders' pathfinding favors big, cheap channels. Returns None on a dead end.
+    pub fn sample_route_with_budget<R: Rng + ?Sized>(&self,
+                                                     starting_node: &str,
+                                                     cltv_budget: u32,
+                                                     max_hops: usize,
+                                                     amount_msat: u64,
+                                                     construction: CltvConstruction,
+                                                     rng: &mut R) -> Option<Vec<String>> {
+        let mut path = vec![self.node_id(starting_node)?];
+        let mut used_budget = 0;
+        // Each neighbor with its weight and the budget used once the HTLC reaches it
+        let mut options: Vec<(NodeId, f64, u32)> = Vec::new();
 
-        if let Some(neighbors) = self.get_neighbors(current_node) {
-            for neighbor in neighbors {
-                if !visited.contains(neighbor) {
-                    // Get CLTV delta for the next hop
-                    let next_hop_delta = match self.nodes.get(neighbor) {
-                        Some(node) => node.cltv_expiry_delta,
-                        None => 14, // Minimum per-hop CLTV delta if unknown
-                    };
+        loop {
+            let current = path[path.len() - 1];
+            let can_end = path.len() > 1 && self.could_end_at(current, used_budget, cltv_budget);
+
+            options.clear();
+            if path.len() <= max_hops {
+                for (neighbor, channel) in self.usable_neighbors(current, Some(amount_msat)) {
+                    let onward_budget = used_budget + self.forwarding_delta(current, channel, construction);
+                    if path.contains(&neighbor) || onward_budget >= cltv_budget {
+                        continue;
+                    }
+                    let fee = self.policies[neighbor.slot()].forwarding_fee_msat(amount_msat);
+                    let weight = channel.capacity as f64 / (1.0 + fee as f64);
+
+                    // Parallel channels: the biggest one counts
+                    match options.iter_mut().find(|(n, _, _)| *n == neighbor) {
+                        Some(option) if weight > option.1 => *option = (neighbor, weight, onward_budget),
+                        Some(_) => {}
+                        None => options.push((neighbor, weight, onward_budget)),
+                    }
+                }
+            }
+
+            if options.is_empty() {
+                return if can_end { Some(self.pub_keys_of(&path)) } else { None };
+            }
+            if can_end && rng.random_bool(ROUTE_SAMPLE_STOP_PROBABILITY) {
+                return Some(self.pub_keys_of(&path));
+            }
 
-                    current_path.push(neighbor.clone());
-                    self.dfs_routes(routes, visited, current_path, neighbor,
-                                    budget, used_budget + next_hop_delta, max_depth);
-                    current_path.pop();
+            let total: f64 = options.iter().map(|(_, w, _)| w).sum();
+            let mut pick = rng.random::<f64>() * total;
+            let (mut next, _, mut onward_budget) = options[options.len() - 1];
+            for &(neighbor, weight, onward) in &options {
+                if pick < weight {
+                    (next, onward_budget) = (neighbor, onward);
+                    break;
                 }
+                pick -= weight;
             }
+
+            path.push(next);
+            used_budget = onward_budget;
         }
+    }
 
-        visited.remove(current_node);
+    // Whether an HTLC that has used `used_budget` blocks of forwarding deltas can end at this
+    // node: what's left must match the delta its invoices ask for, plus the sender's shadow offset
+    fn could_end_at(&self, node: NodeId, used_budget: u32, budget: u32) -> bool {
+        let required = self.timelock_ending_at(node, used_budget);
+        required <= budget && budget - required <= self.max_shadow_offset
+    }
+
+    // Total timelock a route needs if it ends at this node
+    fn timelock_ending_at(&self, node: NodeId, used_budget: u32) -> u32 {
+        used_budget + self.policies[node.slot()].min_final_cltv_expiry_delta
     }
 
     #[cfg(test)]
@@ -154,9 +1121,231 @@ impl LightningNetworkMap {
     }
 }
 
+// When a route search gives up: once the routes found hold more than `max_stored_hops` hops
+// between them, or with the routes found so far once the deadline passes
+#[derive(Debug, Clone, Copy)]
+pub struct SearchLimits {
+    pub max_stored_hops: usize,
+    pub deadline: Option<Instant>,
+}
+
+impl SearchLimits {
+    pub const NONE: SearchLimits = SearchLimits { max_stored_hops: usize::MAX, deadline: None };
+}
+
+// What a route search held to limits came back with
+#[derive(Debug, Clone)]
+pub enum BoundedRoutes {
+    // Every route in the window
+    Complete(RouteSet),
+    // The routes found before the deadline passed
+    TimedOut(RouteSet),
+    // The routes would have held more hops than allowed
+    TooMany,
+}
+
+// Routes with the total timelock each one needs, stored back to back so large result sets
+// stay compact
+#[derive(Debug, Clone, Default)]
+pub struct RouteSet {
+    hops: Vec<NodeId>,
+    // End of each route in `hops`, and its timelock
+    ends: Vec<(usize, u32)>,
+}
+
+impl RouteSet {
+    fn push(&mut self, route: &[NodeId], timelock: u32) {
+        self.hops.extend_from_slice(route);
+        self.ends.push((self.hops.len(), timelock));
+    }
+
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    // Hops stored across all routes
+    pub fn hop_count(&self) -> usize {
+        self.hops.len()
+    }
+
+    pub fn memory_bytes(&self) -> usize {
+        self.hops.capacity() * std::mem::size_of::<NodeId>() + self.ends.capacity() * std::mem::size_of::<(usize, u32)>()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&[NodeId], u32)> + '_ {
+        let starts = std::iter::once(0).chain(self.ends.iter().map(|&(end, _)| end));
+        starts.zip(&self.ends).map(|(start, &(end, timelock))| (&self.hops[start..end], timelock))
+    }
+}
+
+// Smallest timelock any route from one node needs to end at each other node. A lower bound,
+// so a node needing more than an HTLC has left can't be its recipient.
+#[derive(Debug, Clone)]
+pub struct Reachability {
+    topology_version: u64,
+    // Indexed by NodeId; u32::MAX where no route ends
+    min_timelock: Vec<u32>,
+}
+
+impl Reachability {
+    // Topology version of the network it was computed on
+    pub fn topology_version(&self) -> u64 {
+        self.topology_version
+    }
+
+    pub fn min_timelock(&self, network: &LightningNetworkMap, node: &str) -> Option<u32> {
+        let id = network.node_id(node)?;
+        self.min_timelock.get(id.slot()).copied().filter(|&timelock| timelock != u32::MAX)
+    }
+
+    pub fn reachable_within(&self, network: &LightningNetworkMap, node: &str, budget: u32) -> bool {
+        self.min_timelock(network, node).is_some_and(|timelock| timelock <= budget)
+    }
+
+    // Nodes some route within the budget can end at
+    pub fn count_within(&self, budget: u32) -> usize {
+        self.min_timelock.iter().filter(|&&timelock| timelock != u32::MAX && timelock <= budget).count()
+    }
+}
+
+// What each half of a search from both ends is held to
+#[derive(Clone, Copy)]
+struct HalfSearch {
+    budget: u32,
+    depth: usize,
+    amount_msat: u64,
+    construction: CltvConstruction,
+}
+
+// State of one exhaustive route enumeration
+struct RouteSearch<'a> {
+    network: &'a LightningNetworkMap,
+    // Window the route's total timelock must fall in
+    lowest: u32,
+    highest: u32,
+    max_depth: usize,
+    amount_msat: Option<u64>,
+    construction: CltvConstruction,
+    bounds: RemainingCostBounds,
+    visited: Vec<bool>,
+    path: Vec<NodeId>,
+    routes: RouteSet,
+    // Set once the routes found hold more hops than this
+    max_stored_hops: usize,
+    exceeded: bool,
+    // Set once the deadline passed, checked every DEADLINE_CHECK_VISITS nodes
+    deadline: Option<Instant>,
+    visits: u32,
+    timed_out: bool,
+}
+
+const DEADLINE_CHECK_VISITS: u32 = 1024;
+
+impl RouteSearch<'_> {
+    // DFS helper for route finding
+    fn dfs(&mut self, current: NodeId, used_budget: u32) {
+        let hops = self.path.len() - 1;
+        if self.exceeded || self.timed_out || hops > self.max_depth || used_budget > self.highest {
+            return;
+        }
+        if let Some(deadline) = self.deadline {
+            if self.visits.is_multiple_of(DEADLINE_CHECK_VISITS) && Instant::now() >= deadline {
+                self.timed_out = true;
+                return;
+            }
+            self.visits += 1;
+        }
+
+        // Nothing reachable from here can end the route inside the window
+        if !self.bounds.can_finish(current, self.max_depth - hops, used_budget, self.lowest, self.highest) {
+            return;
+        }
+
+        self.visited[current.slot()] = true;
+
+        let timelock = self.network.timelock_ending_at(current, used_budget);
+        if hops > 0 && (self.lowest..=self.highest).contains(&timelock) {
+            self.routes.push(&self.path, timelock);
+            if self.routes.hop_count() > self.max_stored_hops {
+                self.exceeded = true;
+            }
+        }
+
+        // Forwarding onwards costs this node's delta for the channel it forwards over.
+        // Parallel channels with the same delta lead to the same routes, so visit each
+        // neighbor once per delta.
+        let network = self.network;
+        let mut explored: Vec<(NodeId, u32)> = Vec::new();
+        for (neighbor, channel) in network.usable_neighbors(current, self.amount_msat) {
+            let onward_budget = used_budget + network.forwarding_delta(current, channel, self.construction);
+            if !self.visited[neighbor.slot()] && !explored.contains(&(neighbor, onward_budget)) {
+                explored.push((neighbor, onward_budget));
+                self.path.push(neighbor);
+                self.dfs(neighbor, onward_budget);
+                self.path.pop();
+            }
+        }
+
+        self.visited[current.slot()] = false;
+    }
+}
+
+// Smallest and largest timelock a route can still need after reaching each node, for
+// every number of hops left. Computed over walks rather than simple paths, so both
+// bounds are admissible: no real route from a node needs less or more than them.
+struct RemainingCostBounds {
+    // Indexed by NodeId, then by hops left
+    min_cost: Vec<Vec<u32>>,
+    max_cost: Vec<Vec<u32>>,
+}
+
+impl RemainingCostBounds {
+    fn compute(network: &LightningNetworkMap, max_hops: usize, amount_msat: Option<u64>,
+               construction: CltvConstruction) -> Self {
+        // No hops left: the route has to end here
+        let mut min_cost: Vec<Vec<u32>> = network.policies.iter()
+            .map(|policy| vec![policy.min_final_cltv_expiry_delta])
+            .collect();
+        let mut max_cost = min_cost.clone();
+
+        for hops_left in 1..=max_hops {
+            for slot in 0..network.policies.len() {
+                let policy = network.policies[slot];
+                let mut lowest = policy.min_final_cltv_expiry_delta;
+                let mut highest = policy.min_final_cltv_expiry_delta;
+
+                for (neighbor, channel) in network.usable_neighbors(NodeId(slot as u32), amount_msat) {
+                    let delta = network.forwarding_delta(NodeId(slot as u32), channel, construction);
+                    lowest = lowest.min(delta + min_cost[neighbor.slot()][hops_left - 1]);
+                    highest = highest.max(delta + max_cost[neighbor.slot()][hops_left - 1]);
+                }
+
+                min_cost[slot].push(lowest);
+                max_cost[slot].push(highest);
+            }
+        }
+
+        RemainingCostBounds { min_cost, max_cost }
+    }
+
+    // Whether some route continuing from `node` could end with the total timelock between
+    // `lowest` and `highest`, given what has been used before reaching it
+    fn can_finish(&self, node: NodeId, hops_left: usize, used_budget: u32, lowest: u32, highest: u32) -> bool {
+        let (lo, hi) = (&self.min_cost[node.slot()], &self.max_cost[node.slot()]);
+        let hops_left = hops_left.min(lo.len() - 1);
+
+        used_budget + lo[hops_left] <= highest && used_budget + hi[hops_left] >= lowest
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::models::htlc::CLTV_RANDOM_OFFSET_MAX;
 
     #[test]
     fn test_add_node() {
@@ -205,14 +1394,341 @@ mod tests {
         network.add_channel(Channel::new("chan2", "node2", "node3", 1000000));
         network.add_channel(Channel::new("chan3", "node3", "node4", 1000000));
 
-        // Budget for exactly 2 hops (node1 -> node2 -> node3)
-        let routes = network.find_possible_routes_with_budget("node1", 40, 3);
+        // Budget for exactly 2 hops (node1 -> node2 -> node3): two forwarding deltas
+        // plus node3's default min_final_cltv_expiry_delta
+        let routes = network.find_possible_routes_with_budget("node1", 80, 3, 100_000);
         println!("2-hop Routes: {:?}", routes);
         assert!(routes.contains(&vec!["node1".to_string(), "node2".to_string(), "node3".to_string()]));
 
         // Budget for all 3 hops
-        let routes = network.find_possible_routes_with_budget("node1", 60, 3);
+        let routes = network.find_possible_routes_with_budget("node1", 100, 3, 100_000);
         println!("3-hop Routes: {:?}", routes);
         assert!(routes.contains(&vec!["node1".to_string(), "node2".to_string(), "node3".to_string(), "node4".to_string()]));
     }
+
+    #[test]
+    fn test_bidirectional_search_matches_dfs() {
+        let mut network = LightningNetworkMap::new(700000);
+        for i in 1..=7 {
+            network.add_node(Node::new(&format!("node{}", i), &format!("Node {}", i), 14 + i * 3)
+                .with_min_final_cltv_expiry_delta(18 + i * 4));
+        }
+
+        // A hub with spokes, plus a ring around the spokes
+        for i in 2..=7 {
+            network.add_channel(Channel::new(&format!("hub{}", i), "node1", &format!("node{}", i), 1_000_000));
+            network.add_channel(Channel::new(&format!("ring{}", i), &format!("node{}", i),
+                                             &format!("node{}", i % 6 + 2), 1_000_000));
+        }
+
+        let candidates: HashSet<String> = ["node4", "node6", "node7"].iter().map(|s| s.to_string()).collect();
+
+        let mut total_routes = 0;
+        for budget in [60, 120, 200] {
+            let mut expected: Vec<Vec<String>> = network.find_possible_routes_with_budget("node2", budget, 5, 100_000)
+                .into_iter()
+                .filter(|route| candidates.contains(route.last().unwrap()))
+                .collect();
+            expected.sort();
+            expected.dedup();
+
+            let found = network.find_routes_to_candidates("node2", &candidates, budget, 5, 100_000, CltvConstruction::Bolt4);
+            assert_eq!(found, expected);
+            total_routes += found.len();
+        }
+        assert!(total_routes > 0);
+    }
+
+    #[test]
+    fn test_remaining_cost_bounds() {
+        let mut network = LightningNetworkMap::new(700000);
+        network.add_node(Node::new("node1", "Node 1", 20));
+        network.add_node(Node::new("node2", "Node 2", 30).with_min_final_cltv_expiry_delta(18));
+        network.add_node(Node::new("node3", "Node 3", 40));
+        network.add_channel(Channel::new("chan1", "node1", "node2", 1_000_000));
+        network.add_channel(Channel::new("chan2", "node2", "node3", 1_000_000));
+
+        let bounds = RemainingCostBounds::compute(&network, 2, Some(100_000), CltvConstruction::Bolt4);
+        let node1 = network.node_id("node1").unwrap();
+
+        // From node1 a route ends at node1 (40), at node2 (20 + 18) or at node3 (20 + 30 + 40)
+        assert_eq!(bounds.min_cost[node1.slot()], vec![40, 38, 38]);
+        assert_eq!(bounds.max_cost[node1.slot()], vec![40, 40, 90]);
+
+        // A budget far beyond anything two hops can consume is pruned right away
+        assert!(bounds.can_finish(node1, 2, 0, 200 - CLTV_RANDOM_OFFSET_MAX, 200));
+        assert!(!bounds.can_finish(node1, 2, 0, 300 - CLTV_RANDOM_OFFSET_MAX, 300));
+        assert!(network.find_possible_routes_with_budget("node1", 300, 2, 100_000).is_empty());
+    }
+
+    #[test]
+    fn test_node_interning() {
+        let mut network = LightningNetworkMap::new(700000);
+        network.add_node(Node::new("node1", "Node 1", 20));
+        // node2 is only known from its channel until it announces itself
+        network.add_channel(Channel::new("chan1", "node1", "node2", 1_000_000));
+
+        let node2 = network.node_id("node2").unwrap();
+        assert_eq!(network.pub_key(node2), "node2");
+        assert_eq!(network.policies[node2.slot()].cltv_expiry_delta, CLTV_EXPIRY_DELTA_MIN);
+
+        // Announcing it later keeps the same id and picks up its policy
+        network.add_node(Node::new("node2", "Node 2", 30));
+        assert_eq!(network.node_id("node2"), Some(node2));
+        assert_eq!(network.policies[node2.slot()].cltv_expiry_delta, 30);
+        assert_eq!(network.node_id("unknown"), None);
+    }
+
+    #[test]
+    fn test_capacity_constraints() {
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["node1", "node2", "node3"] {
+            network.add_node(Node::new(key, key, 20));
+        }
+
+        // node2 -> node3 is a tiny channel
+        network.add_channel(Channel::new("chan1", "node1", "node2", 1_000_000));
+        network.add_channel(Channel::new("chan2", "node2", "node3", 10_000));
+
+        let route = vec!["node1".to_string(), "node2".to_string(), "node3".to_string()];
+
+        // A payment larger than the small channel can't have taken this route
+        assert_eq!(network.route_capacity_plausibility(&route, 20_000_000), 0.0);
+        let routes = network.find_possible_routes_with_budget("node1", 80, 3, 20_000_000);
+        assert!(!routes.contains(&route));
+
+        // Small payments are plausible, large ones through the same channel much less so
+        let small = network.route_capacity_plausibility(&route, 1_000);
+        let large = network.route_capacity_plausibility(&route, 9_000_000);
+        assert!(small > 0.99);
+        assert!(large < 0.2);
+
+        assert!(network.find_possible_routes_with_budget("node1", 80, 3, 1_000).contains(&route));
+    }
+
+    #[test]
+    fn test_htlc_limits() {
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["node1", "node2", "node3"] {
+            network.add_node(Node::new(key, key, 20));
+        }
+
+        network.add_channel(Channel::new("chan1", "node1", "node2", 1_000_000));
+        network.add_channel(Channel::new("chan2", "node2", "node3", 1_000_000)
+            .with_htlc_limits(50_000, 5_000_000));
+
+        let route = vec!["node1".to_string(), "node2".to_string(), "node3".to_string()];
+
+        // Below the minimum and above the maximum are both rejected despite ample capacity
+        assert!(!network.can_carry("node2", "node3", 10_000));
+        assert!(!network.can_carry("node2", "node3", 6_000_000));
+        assert_eq!(network.route_capacity_plausibility(&route, 10_000), 0.0);
+        assert!(!network.find_possible_routes_with_budget("node1", 80, 3, 6_000_000).contains(&route));
+
+        assert!(network.find_possible_routes_with_budget("node1", 80, 3, 100_000).contains(&route));
+    }
+
+    #[test]
+    fn test_inactive_channels_do_not_route() {
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["node1", "node2", "node3"] {
+            network.add_node(Node::new(key, key, 20));
+        }
+        network.add_channel(Channel::new("chan1", "node1", "node2", 1_000_000));
+        network.add_channel(Channel::new("chan2", "node2", "node3", 1_000_000).with_status(ChannelStatus::Zombie));
+
+        let route = vec!["node1".to_string(), "node2".to_string(), "node3".to_string()];
+        // Still part of the topology, but nothing goes through it
+        assert_eq!(network.degree("node3"), 1);
+        assert!(!network.can_carry("node2", "node3", 100_000));
+        assert!(!network.find_possible_routes_with_budget("node1", 80, 3, 100_000).contains(&route));
+
+        let version = network.topology_version();
+        assert!(network.set_channel_status("chan2", ChannelStatus::Active));
+        assert!(network.topology_version() > version);
+        assert!(network.find_possible_routes_with_budget("node1", 80, 3, 100_000).contains(&route));
+
+        assert!(network.set_channel_status("chan1", ChannelStatus::Disabled));
+        assert!(!network.set_channel_status("chan9", ChannelStatus::Disabled));
+        assert_eq!(network.inactive_channel_count(), 1);
+        assert!(!network.can_carry("node1", "node2", 100_000));
+    }
+
+    #[test]
+    fn test_reserve_and_dust_limits() {
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["a", "b", "c", "d"] {
+            network.add_node(Node::new(key, key, 20));
+        }
+        network.add_channel(Channel::new("ab", "a", "b", 1_000_000).with_htlc_limits(1, 1_000_000_000));
+        network.add_channel(Channel::new("bc", "b", "c", 1_000_000).with_htlc_limits(1, 1_000_000_000));
+        // Refuses dust, so tiny payments have to go the long way round
+        network.add_channel(Channel::new("bd", "b", "d", 1_000_000).with_htlc_limits(1, 1_000_000_000)
+            .with_dust_limits(546, 0));
+        network.add_channel(Channel::new("cd", "c", "d", 1_000_000).with_htlc_limits(1, 1_000_000_000));
+
+        // The 1% reserve is never spendable
+        let channel = network.channel("ab").unwrap();
+        assert_eq!(channel.spendable_msat(), 990_000_000);
+        assert!(channel.can_forward(990_000_000) && !channel.can_forward(990_000_001));
+        // Nor is a reserve below the dust limit
+        assert_eq!(Channel::new("tiny", "a", "b", 10_000).reserve_sat, DEFAULT_DUST_LIMIT_SAT);
+
+        assert!(!network.can_carry("b", "d", 500_000));
+        assert!(network.can_carry("b", "d", 546_000));
+        let direct: Vec<String> = ["a", "b", "d"].iter().map(|n| n.to_string()).collect();
+        let around: Vec<String> = ["a", "b", "c", "d"].iter().map(|n| n.to_string()).collect();
+        let tiny = network.find_possible_routes_with_budget("a", 100, 4, 500_000);
+        assert!(!tiny.contains(&direct) && tiny.contains(&around));
+        assert!(network.find_possible_routes_with_budget("a", 100, 4, 600_000).contains(&direct));
+    }
+
+    #[test]
+    fn test_removals_keep_graph_consistent() {
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["a", "b", "c", "d"] {
+            network.add_node(Node::new(key, key, 20));
+        }
+        network.add_channel(Channel::new("ab", "a", "b", 1_000_000));
+        network.add_channel(Channel::new("bc", "b", "c", 1_000_000));
+        network.add_channel(Channel::new("cd", "c", "d", 1_000_000));
+        network.add_channel(Channel::new("bd", "b", "d", 1_000_000));
+
+        // The last channel moves into the removed one's slot and stays reachable by id
+        let version = network.topology_version();
+        assert_eq!(network.remove_channel("ab").map(|c| c.channel_id), Some("ab".to_string()));
+        assert!(network.remove_channel("ab").is_none());
+        assert!(network.topology_version() > version);
+        assert_eq!(network.channel("bd").map(|c| c.node2.as_str()), Some("d"));
+        assert_eq!(network.channels_between("b", "d").len(), 1);
+        assert_eq!(network.degree("a"), 0);
+        assert_eq!(network.connected_components(), 2);
+
+        assert!(network.update_channel_policy("cd", "c", ChannelFees { base_fee_msat: 5000, fee_rate_ppm: 0 }));
+        assert!(!network.update_channel_policy("cd", "a", ChannelFees { base_fee_msat: 5000, fee_rate_ppm: 0 }));
+        assert_eq!(network.hop_fee_msat("c", "d", 100_000), Some(5000));
+
+        assert!(network.remove_node("b").is_some());
+        assert!(network.channel("bc").is_none() && network.channel("bd").is_none());
+        assert_eq!(network.channels.len(), 1);
+        assert_eq!(network.node_id("b"), None);
+        assert_eq!(network.connected_components(), 2);
+
+        // Coming back gives the node a fresh slot without any of its old channels
+        network.add_node(Node::new("b", "b", 20));
+        assert_eq!(network.degree("b"), 0);
+        assert_eq!(network.connected_components(), 3);
+        network.add_channel(Channel::new("bc", "b", "c", 1_000_000));
+        let route: Vec<String> = ["b", "c", "d"].iter().map(|n| n.to_string()).collect();
+        assert!(network.find_possible_routes_with_budget("b", 80, 3, 100_000).contains(&route));
+    }
+
+    #[test]
+    fn test_adjacency_list_follows_the_graph() {
+        let mut network = LightningNetworkMap::new(700000);
+        network.add_channel(Channel::new("ab1", "a", "b", 1_000_000));
+        network.add_channel(Channel::new("ca", "c", "a", 1_000_000));
+        network.add_channel(Channel::new("ab2", "a", "b", 1_000_000));
+        network.add_channel(Channel::new("ad", "a", "d", 1_000_000));
+        // Replacing a channel may move its ends; removing one drops exactly its own entries
+        network.add_channel(Channel::new("ab1", "b", "a", 2_000_000));
+        network.remove_channel("ab2");
+
+        assert_eq!(network.get_neighbors("a").map(Vec::len), Some(3));
+        assert_eq!(network.adjacency_list["b"], vec!["a".to_string()]);
+        for key in ["a", "b", "c", "d"] {
+            let id = network.node_id(key).unwrap();
+            let in_graph: Vec<&String> = network.graph.neighbors(id.index())
+                .map(|neighbor| network.pub_key(NodeId::from_index(neighbor)))
+                .collect();
+            assert_eq!(network.adjacency_list[key].iter().collect::<Vec<_>>(), in_graph, "{}", key);
+        }
+
+        network.remove_node("a");
+        assert!(network.get_neighbors("a").is_none());
+        assert!(network.adjacency_list["c"].is_empty());
+    }
+
+    #[test]
+    fn test_route_fee() {
+        let mut network = LightningNetworkMap::new(700000);
+        network.add_node(Node::new("node1", "Node 1", 40));
+        network.add_node(Node::new("node2", "Node 2", 40));
+        network.add_node(Node::new("node3", "Node 3", 40));
+
+        let path = vec!["node1".to_string(), "node2".to_string(), "node3".to_string()];
+
+        // Only node2 forwards: base fee + 1 ppm of 2_000_000 msat
+        assert_eq!(network.route_fee_msat(&path, 2_000_000), DEFAULT_BASE_FEE_MSAT + 2);
+
+        // Direct payments pay no routing fees
+        assert_eq!(network.route_fee_msat(&path[1..], 2_000_000), 0);
+    }
+
+    #[test]
+    fn test_reachability_bounds_where_routes_end() {
+        // a - b - c - d - e with deltas of 40: ending k hops on takes k forwarding deltas and
+        // the recipient's 40
+        let network = crate::simulation::fixture("line").unwrap();
+        let reachability = network.reachability_from("a", 2, CltvConstruction::Bolt4);
+        let timelocks: Vec<Option<u32>> = ["a", "b", "c", "d", "e"].iter()
+            .map(|node| reachability.min_timelock(&network, node))
+            .collect();
+        assert_eq!(timelocks, vec![None, Some(80), Some(120), None, None]);
+        assert!(reachability.reachable_within(&network, "c", 120));
+        assert!(!reachability.reachable_within(&network, "c", 119));
+        assert_eq!(reachability.count_within(100), 1);
+
+        // Every route the search finds ends where reachability allows
+        let from_b = network.reachability_from("b", crate::models::MAX_ROUTE_HOPS, CltvConstruction::Bolt4);
+        for (route, timelock) in network.find_routes_in_window("b", 0, 400, 4, None).iter() {
+            let recipient = network.pub_key(*route.last().unwrap());
+            assert!(from_b.min_timelock(&network, recipient).unwrap() <= timelock);
+        }
+    }
+
+    #[test]
+    fn test_route_search_stops_at_the_deadline() {
+        let network = crate::simulation::fixture("star").unwrap();
+        let search = |limits| network.find_routes_in_window_limited("hub", 0..=120, 4, None, CltvConstruction::Bolt4, limits);
+        assert!(matches!(search(SearchLimits::NONE), BoundedRoutes::Complete(routes) if routes.len() == 5));
+        // Already past: nothing explored, and said so
+        let deadline = Some(Instant::now());
+        assert!(matches!(search(SearchLimits { deadline, ..SearchLimits::NONE }),
+                         BoundedRoutes::TimedOut(routes) if routes.is_empty()));
+        assert!(matches!(search(SearchLimits { max_stored_hops: 1, deadline: None }), BoundedRoutes::TooMany));
+    }
+
+    #[test]
+    fn test_searches_follow_the_cltv_construction() {
+        // b usually asks for 20 blocks, but 144 on its channel to c
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["a", "b", "c"] {
+            network.add_node(Node::new(key, key, 20));
+        }
+        network.add_channel(Channel::new("a-b", "a", "b", 1_000_000));
+        network.add_channel(Channel::new("b-c", "b", "c", 1_000_000).with_cltv_delta("b", 144));
+
+        // a's 20, b's delta for b-c, and c's invoice delta of 40
+        for (construction, timelock) in [(CltvConstruction::Bolt4, 204), (CltvConstruction::Legacy, 80)] {
+            let routes = match network.find_routes_in_window_limited("a", 0..=300, 3, Some(100_000), construction,
+                                                                     SearchLimits::NONE) {
+                BoundedRoutes::Complete(routes) => routes,
+                _ => panic!("search was unlimited"),
+            };
+            let to_c: Vec<u32> = routes.iter()
+                .filter(|(route, _)| network.pub_key(*route.last().unwrap()) == "c")
+                .map(|(_, timelock)| timelock)
+                .collect();
+            assert_eq!(to_c, vec![timelock]);
+            assert_eq!(network.reachability_from("a", 3, construction).min_timelock(&network, "c"), Some(timelock));
+
+            let route = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+            let candidates: HashSet<String> = HashSet::from(["c".to_string()]);
+            let budget = timelock + CLTV_RANDOM_OFFSET_MAX;
+            assert_eq!(network.find_routes_to_candidates("a", &candidates, budget, 3, 100_000, construction), vec![route]);
+        }
+        assert_eq!(network.hop_cltv_delta("b", "c", 100_000, CltvConstruction::Bolt4), 144);
+        assert_eq!(network.hop_cltv_delta("c", "b", 100_000, CltvConstruction::Bolt4), 20);
+    }
 }
@@ -0,0 +1,48 @@
+// The layer of a payment's onion a node decrypts. BOLT #4 gives every forwarding node what
+// to send on and over which channel, and the final node only what it should receive, so a
+// node learns its neighbors on the route and nothing further. A trampoline node is the
+// exception: its layer names the node to reach, and it finds the rest of the route itself.
+
+// Per-hop payload as a node reads it from the onion
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HopPayload {
+    // What the node is asked to pass on, or to accept when it's the recipient
+    pub amt_to_forward: u64,
+    pub outgoing_cltv_value: u32,
+    // Channel to forward over, absent for the recipient. Nodes may forward over another
+    // channel to the same peer.
+    pub short_channel_id: Option<String>,
+    // Node a trampoline is asked to route the payment to, from its trampoline onion
+    pub trampoline_destination: Option<String>,
+}
+
+impl HopPayload {
+    pub fn forward(short_channel_id: &str, amt_to_forward: u64, outgoing_cltv_value: u32) -> Self {
+        HopPayload {
+            amt_to_forward,
+            outgoing_cltv_value,
+            short_channel_id: Some(short_channel_id.to_string()),
+            trampoline_destination: None,
+        }
+    }
+
+    pub fn final_hop(amount_msat: u64, cltv_expiry: u32) -> Self {
+        HopPayload {
+            amt_to_forward: amount_msat,
+            outgoing_cltv_value: cltv_expiry,
+            short_channel_id: None,
+            trampoline_destination: None,
+        }
+    }
+
+    // The node reading this payload is a trampoline asked to reach `destination`
+    pub fn via_trampoline(mut self, destination: &str) -> Self {
+        self.trampoline_destination = Some(destination.to_string());
+        self
+    }
+
+    // The node reading this payload is the recipient
+    pub fn is_final(&self) -> bool {
+        self.short_channel_id.is_none()
+    }
+}
@@ -0,0 +1,86 @@
+// Short channel ids: where a channel's funding output sits on chain, as the block it
+// confirmed in, the transaction's index in that block and the output's index in the
+// transaction. LND writes them as one number, everything else as `<block>x<tx>x<output>`.
+
+use std::fmt;
+use rand::Rng;
+
+// Transactions a block holds at most, give or take, and outputs a funding transaction has
+const MAX_TX_INDEX: u32 = 4000;
+const MAX_OUTPUT_INDEX: u16 = 2;
+// Mean age of a generated channel, about a year, and the confirmations it needs first
+const MEAN_CHANNEL_AGE_BLOCKS: f64 = 52_560.0;
+const FUNDING_CONFIRMATIONS: u32 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ShortChannelId {
+    pub block: u32,
+    pub tx_index: u32,
+    pub output_index: u16,
+}
+
+impl ShortChannelId {
+    pub fn new(block: u32, tx_index: u32, output_index: u16) -> Self {
+        ShortChannelId { block, tx_index, output_index }
+    }
+
+    // `<block>x<tx>x<output>` or LND's packed number. Ids that encode block 0 aren't short
+    // channel ids, just numbers.
+    pub fn parse(id: &str) -> Option<Self> {
+        let scid = match id.split('x').collect::<Vec<_>>()[..] {
+            [block, tx_index, output_index] => ShortChannelId::new(
+                block.parse().ok()?, tx_index.parse().ok()?, output_index.parse().ok()?),
+            [number] => ShortChannelId::from_u64(number.parse().ok()?),
+            _ => return None,
+        };
+        (scid.block > 0 && scid.tx_index < 1 << 24).then_some(scid)
+    }
+
+    // Block in the top 24 bits, transaction in the next 24, output in the low 16
+    pub fn from_u64(id: u64) -> Self {
+        ShortChannelId::new((id >> 40) as u32, ((id >> 16) & 0xff_ffff) as u32, (id & 0xffff) as u16)
+    }
+
+    pub fn to_u64(&self) -> u64 {
+        ((self.block as u64) << 40) | ((self.tx_index as u64) << 16) | self.output_index as u64
+    }
+
+    // Blocks since the funding transaction confirmed, None if it hadn't by `height`
+    pub fn age_at(&self, height: u32) -> Option<u32> {
+        height.checked_sub(self.block)
+    }
+
+    // An id for a channel funded before `height`, at an exponentially distributed age so
+    // young channels outnumber old ones the way they do on mainnet
+    pub fn random(height: u32, rng: &mut impl Rng) -> Self {
+        let age = -(1.0 - rng.random::<f64>()).ln() * MEAN_CHANNEL_AGE_BLOCKS;
+        let block = height.saturating_sub(FUNDING_CONFIRMATIONS + age.round() as u32).max(1);
+        ShortChannelId::new(block, rng.random_range(0..MAX_TX_INDEX), rng.random_range(0..MAX_OUTPUT_INDEX))
+    }
+}
+
+impl fmt::Display for ShortChannelId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}x{}", self.block, self.tx_index, self.output_index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_channel_id_forms() {
+        let scid = ShortChannelId::new(800_000, 1234, 1);
+        assert_eq!(scid.to_string(), "800000x1234x1");
+        assert_eq!(ShortChannelId::parse("800000x1234x1"), Some(scid));
+        assert_eq!(ShortChannelId::parse(&scid.to_u64().to_string()), Some(scid));
+        assert_eq!(scid.age_at(800_144), Some(144));
+        assert_eq!(scid.age_at(799_999), None);
+
+        // Small numbers and names aren't short channel ids
+        assert_eq!(ShortChannelId::parse("101"), None);
+        assert_eq!(ShortChannelId::parse("chan1"), None);
+        assert_eq!(ShortChannelId::parse("1x2"), None);
+    }
+}
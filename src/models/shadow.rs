@@ -0,0 +1,178 @@
+// Shadow offsets: the random blocks a sender adds to the final timelock so the last hop
+// doesn't give away the recipient. Implementations differ in whether they add one and how
+// large, so each node carries the profile of the software it runs.
+
+use rand::Rng;
+
+use crate::models::htlc::{CLTV_RANDOM_OFFSET_MIN, CLTV_RANDOM_OFFSET_MAX};
+use crate::models::network::LightningNetworkMap;
+
+// Names accepted by `ShadowOffset::from_name`, besides `<min>-<max>`
+pub const SHADOW_OFFSET_NAMES: &[&str] = &["none", "narrow", "standard", "wide"];
+
+// Offsets a sender picks from, uniformly between `min` and `max` blocks inclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ShadowOffset {
+    pub min: u32,
+    pub max: u32,
+}
+
+impl Default for ShadowOffset {
+    fn default() -> Self {
+        ShadowOffset::STANDARD
+    }
+}
+
+impl ShadowOffset {
+    pub const NONE: ShadowOffset = ShadowOffset { min: 0, max: 0 };
+    pub const NARROW: ShadowOffset = ShadowOffset { min: 0, max: 40 };
+    pub const STANDARD: ShadowOffset = ShadowOffset { min: CLTV_RANDOM_OFFSET_MIN, max: CLTV_RANDOM_OFFSET_MAX };
+    pub const WIDE: ShadowOffset = ShadowOffset { min: 0, max: 2 * CLTV_RANDOM_OFFSET_MAX };
+
+    // A named profile, or an explicit `<min>-<max>` range in blocks
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(ShadowOffset::NONE),
+            "narrow" => Some(ShadowOffset::NARROW),
+            "standard" => Some(ShadowOffset::STANDARD),
+            "wide" => Some(ShadowOffset::WIDE),
+            _ => {
+                let (min, max) = name.split_once('-')?;
+                let (min, max) = (min.parse().ok()?, max.parse().ok()?);
+                (min <= max).then_some(ShadowOffset { min, max })
+            }
+        }
+    }
+
+    pub fn sample(&self, rng: &mut impl Rng) -> u32 {
+        rng.random_range(self.min..=self.max)
+    }
+
+    // Chance of this profile picking exactly `offset`
+    pub fn likelihood(&self, offset: u32) -> f64 {
+        if (self.min..=self.max).contains(&offset) {
+            1.0 / (self.max - self.min + 1) as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+// Profiles spread over nodes in proportion to their weights, e.g. to model a network where
+// most nodes run software that adds no offset
+#[derive(Debug, Clone)]
+pub struct ShadowOffsetMix {
+    pub profiles: Vec<(ShadowOffset, f64)>,
+}
+
+impl ShadowOffsetMix {
+    // Comma-separated `<profile>:<weight>` pairs, like `none:0.6,standard:0.4`
+    pub fn from_spec(spec: &str) -> Option<Self> {
+        let profiles = spec.split(',')
+            .map(|part| {
+                let (name, weight) = part.rsplit_once(':')?;
+                let weight: f64 = weight.parse().ok()?;
+                (weight >= 0.0).then_some((ShadowOffset::from_name(name)?, weight))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        (profiles.iter().map(|(_, weight)| weight).sum::<f64>() > 0.0).then_some(ShadowOffsetMix { profiles })
+    }
+
+    pub fn sample(&self, rng: &mut impl Rng) -> ShadowOffset {
+        let total: f64 = self.profiles.iter().map(|(_, weight)| weight).sum();
+        let mut pick = rng.random_range(0.0..total);
+        for &(profile, weight) in &self.profiles {
+            if pick < weight {
+                return profile;
+            }
+            pick -= weight;
+        }
+        self.profiles[self.profiles.len() - 1].0
+    }
+
+    // Give every node a profile from the mix, visiting nodes in key order so a seeded RNG
+    // assigns the same profiles every time
+    pub fn assign(&self, network: &mut LightningNetworkMap, rng: &mut impl Rng) {
+        let mut keys: Vec<String> = network.nodes.keys().cloned().collect();
+        keys.sort();
+        for key in keys {
+            if let Some(node) = network.nodes.get(&key) {
+                let node = node.clone().with_shadow_offset(self.sample(rng));
+                network.add_node(node);
+            }
+        }
+    }
+}
+
+// What the attacker expects of an unknown sender's offset: the profiles of all nodes, each
+// weighted by how many nodes use it
+#[derive(Debug, Clone)]
+pub struct ShadowOffsetPrior {
+    profiles: Vec<(ShadowOffset, f64)>,
+    // Likelihood of the most likely offset, to scale the rest against
+    peak: f64,
+}
+
+impl ShadowOffsetPrior {
+    pub fn of(network: &LightningNetworkMap) -> Self {
+        let mut profiles: Vec<(ShadowOffset, f64)> = Vec::new();
+        for node in network.nodes.values() {
+            match profiles.iter_mut().find(|(profile, _)| *profile == node.shadow_offset) {
+                Some(entry) => entry.1 += 1.0,
+                None => profiles.push((node.shadow_offset, 1.0)),
+            }
+        }
+        let total = network.nodes.len().max(1) as f64;
+        for entry in &mut profiles {
+            entry.1 /= total;
+        }
+
+        let mut prior = ShadowOffsetPrior { profiles, peak: 0.0 };
+        // The mixture is piecewise constant, so its peak is at one of the profiles' minimums
+        prior.peak = prior.profiles.iter().map(|(profile, _)| prior.likelihood(profile.min)).fold(0.0, f64::max);
+        prior
+    }
+
+    // Chance that a random sender picked exactly `offset`
+    pub fn likelihood(&self, offset: u32) -> f64 {
+        self.profiles.iter().map(|(profile, share)| share * profile.likelihood(offset)).sum()
+    }
+
+    // Likelihood relative to the most likely offset, from 0 to 1
+    pub fn weight(&self, offset: u32) -> f64 {
+        if self.peak > 0.0 { self.likelihood(offset) / self.peak } else { 1.0 }
+    }
+
+    // Largest offset any node adds
+    pub fn max_offset(&self) -> u32 {
+        self.profiles.iter().map(|(profile, _)| profile.max).max().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Node;
+
+    #[test]
+    fn test_offset_prior_favors_common_profiles() {
+        assert_eq!(ShadowOffset::from_name("10-20"), Some(ShadowOffset { min: 10, max: 20 }));
+        assert_eq!(ShadowOffset::from_name("20-10"), None);
+        let mix = ShadowOffsetMix::from_spec("none:3,narrow:1").unwrap();
+        assert_eq!(mix.profiles, vec![(ShadowOffset::NONE, 3.0), (ShadowOffset::NARROW, 1.0)]);
+        assert!(ShadowOffsetMix::from_spec("none:0").is_none());
+        assert!(ShadowOffsetMix::from_spec("bogus:1").is_none());
+
+        // Three nodes add nothing, one adds up to 40 blocks
+        let mut network = LightningNetworkMap::new(700000);
+        for (i, profile) in [ShadowOffset::NONE, ShadowOffset::NONE, ShadowOffset::NONE, ShadowOffset::NARROW].iter().enumerate() {
+            network.add_node(Node::new(&format!("n{}", i), "n", 40).with_shadow_offset(*profile));
+        }
+        let prior = ShadowOffsetPrior::of(&network);
+        assert_eq!(prior.max_offset(), 40);
+        assert_eq!(prior.weight(0), 1.0);
+        assert!((prior.likelihood(20) - 0.25 / 41.0).abs() < 1e-12);
+        assert!(prior.weight(20) < 0.01);
+        assert_eq!(prior.weight(41), 0.0);
+    }
+}
@@ -0,0 +1,385 @@
+// Charts of a run's results, drawn with plotters as PNG or SVG next to the reports
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use log::info;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+
+use crate::defense::RocCurve;
+use crate::simulation::PaymentRecord;
+use crate::surveillance::PotentialRecipient;
+use crate::error::ThelmaError;
+
+// Names accepted by `--plot-format`
+pub const PLOT_FORMATS: &[&str] = &["png", "svg"];
+
+// Pixel size of every chart
+const CHART_SIZE: (u32, u32) = (800, 500);
+
+// Bars the confidence histogram splits [0, 1] into
+const CONFIDENCE_BINS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PlotFormat {
+    #[default]
+    Png,
+    Svg,
+}
+
+impl PlotFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "png" => Some(PlotFormat::Png),
+            "svg" => Some(PlotFormat::Svg),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PlotFormat::Png => "png",
+            PlotFormat::Svg => "svg",
+        }
+    }
+}
+
+// A chart that can be drawn on any plotters backend
+pub trait Plot {
+    fn render<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> Result<(), Box<dyn Error>>
+        where DB::ErrorType: 'static;
+}
+
+// Draw `plot` to `<stem>.png` or `<stem>.svg` and return the file name
+pub fn save_plot(plot: &impl Plot, stem: &str, format: PlotFormat) -> Result<String, ThelmaError> {
+    let filename = format!("{}.{}", stem, format.extension());
+    let drawn = match format {
+        PlotFormat::Png => {
+            let root = BitMapBackend::new(&filename, CHART_SIZE).into_drawing_area();
+            plot.render(&root).and_then(|_| Ok(root.present()?))
+        }
+        PlotFormat::Svg => {
+            let root = SVGBackend::new(&filename, CHART_SIZE).into_drawing_area();
+            plot.render(&root).and_then(|_| Ok(root.present()?))
+        }
+    };
+    drawn.map_err(|e| ThelmaError::Io(std::io::Error::other(format!("can't draw {}: {}", filename, e))))?;
+
+    info!("Plot saved to {}", filename);
+    Ok(filename)
+}
+
+// Label, color and points of one line
+type Series = (String, RGBColor, Vec<(f64, f64)>);
+
+// Shares of payments (0 to 1) against a numeric x axis, one line per series
+pub struct LineChart {
+    title: String,
+    x_desc: String,
+    y_desc: String,
+    series: Vec<Series>,
+}
+
+impl LineChart {
+    pub fn new(title: &str, x_desc: &str, y_desc: &str) -> Self {
+        LineChart { title: title.to_string(), x_desc: x_desc.to_string(), y_desc: y_desc.to_string(), series: Vec::new() }
+    }
+
+    pub fn series(mut self, label: &str, color: RGBColor, points: Vec<(f64, f64)>) -> Self {
+        self.series.push((label.to_string(), color, points));
+        self
+    }
+
+    // Share of observed payments as the adversary grows, adding its nodes in the order given
+    pub fn coverage_by_adversary_size(records: &[PaymentRecord], malicious_nodes: &[String]) -> Self {
+        let routed: Vec<&PaymentRecord> = records.iter().filter(|r| r.path.len() >= 2 && !r.cover).collect();
+        let mut adversary = HashSet::new();
+        let points = malicious_nodes.iter().enumerate().map(|(i, node)| {
+            adversary.insert(node);
+            let observed = routed.iter().filter(|r| r.path.iter().any(|hop| adversary.contains(hop))).count();
+            ((i + 1) as f64, observed as f64 / routed.len().max(1) as f64)
+        }).collect();
+
+        LineChart::new("Coverage vs. adversary size", "malicious nodes", "share of payments observed")
+            .series("coverage", BLUE, points)
+    }
+}
+
+impl Plot for LineChart {
+    fn render<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> Result<(), Box<dyn Error>>
+        where DB::ErrorType: 'static {
+        let xs = self.series.iter().flat_map(|(_, _, points)| points.iter().map(|p| p.0));
+        let low = xs.clone().fold(f64::INFINITY, f64::min).min(0.0);
+        let high = xs.fold(low + 1.0, f64::max);
+
+        root.fill(&WHITE)?;
+        let mut chart = ChartBuilder::on(root)
+            .caption(&self.title, ("sans-serif", 22))
+            .margin(15)
+            .x_label_area_size(40)
+            .y_label_area_size(50)
+            .build_cartesian_2d(low..high, 0.0..1.0)?;
+        chart.configure_mesh()
+            .x_desc(&self.x_desc)
+            .y_desc(&self.y_desc)
+            .x_label_formatter(&|x| format!("{:.0}", x))
+            .draw()?;
+
+        for (label, color, points) in &self.series {
+            let color = *color;
+            chart.draw_series(points.iter().map(|&point| Circle::new(point, 3, color.filled())))?;
+            chart.draw_series(LineSeries::new(points.clone(), color))?
+                .label(label)
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+        }
+        chart.configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+        Ok(())
+    }
+}
+
+// How much of each observed payment's confidence its top candidate holds, split by whether
+// that candidate was the real recipient
+pub struct ConfidenceHistogram {
+    identified: Vec<f64>,
+    missed: Vec<f64>,
+}
+
+impl ConfidenceHistogram {
+    pub fn from_results(records: &[PaymentRecord], results: &HashMap<String, Vec<PotentialRecipient>>) -> Self {
+        let mut histogram = ConfidenceHistogram { identified: Vec::new(), missed: Vec::new() };
+        for record in records.iter().filter(|r| r.observed && !r.cover) {
+            let Some(best) = results.get(&record.payment_hash).and_then(|candidates| candidates.first()) else { continue };
+            let total: f32 = results[&record.payment_hash].iter().map(|c| c.confidence_score).sum();
+            let share = if total > 0.0 { (best.confidence_score / total) as f64 } else { 0.0 };
+            if best.node_id == record.receiver {
+                histogram.identified.push(share);
+            } else {
+                histogram.missed.push(share);
+            }
+        }
+        histogram
+    }
+
+    fn bins(shares: &[f64]) -> [usize; CONFIDENCE_BINS] {
+        let mut bins = [0; CONFIDENCE_BINS];
+        for share in shares {
+            bins[((share * CONFIDENCE_BINS as f64) as usize).min(CONFIDENCE_BINS - 1)] += 1;
+        }
+        bins
+    }
+}
+
+impl Plot for ConfidenceHistogram {
+    fn render<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> Result<(), Box<dyn Error>>
+        where DB::ErrorType: 'static {
+        let identified = ConfidenceHistogram::bins(&self.identified);
+        let missed = ConfidenceHistogram::bins(&self.missed);
+        let tallest = identified.iter().zip(&missed).map(|(a, b)| a + b).max().unwrap_or(0).max(1);
+        let width = 1.0 / CONFIDENCE_BINS as f64;
+
+        root.fill(&WHITE)?;
+        let mut chart = ChartBuilder::on(root)
+            .caption("Top candidate confidence", ("sans-serif", 22))
+            .margin(15)
+            .x_label_area_size(40)
+            .y_label_area_size(50)
+            .build_cartesian_2d(0.0..1.0, 0..tallest + 1)?;
+        chart.configure_mesh()
+            .x_desc("share of the payment's confidence held by the top candidate")
+            .y_desc("observed payments")
+            .draw()?;
+
+        // Identified payments at the bottom of each bar, misses stacked on top
+        let stacks = [
+            ("recipient ranked first", GREEN, identified, [0; CONFIDENCE_BINS]),
+            ("someone else ranked first", RED, missed, identified),
+        ];
+        for (label, color, counts, base) in stacks {
+            chart.draw_series((0..CONFIDENCE_BINS).filter(|&bin| counts[bin] > 0).map(|bin| {
+                let x = bin as f64 * width;
+                Rectangle::new([(x, base[bin]), (x + width * 0.95, base[bin] + counts[bin])], color.mix(0.7).filled())
+            }))?
+                .label(label)
+                .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 15, y + 5)], color.mix(0.7).filled()));
+        }
+        chart.configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+        Ok(())
+    }
+}
+
+// Cumulative distribution of anonymity-set sizes: the distinct candidate recipients the
+// adversary is left with for each observed payment
+pub struct AnonymityCdf {
+    sizes: Vec<usize>,
+}
+
+impl AnonymityCdf {
+    pub fn from_results(records: &[PaymentRecord], results: &HashMap<String, Vec<PotentialRecipient>>) -> Self {
+        let mut sizes: Vec<usize> = records.iter()
+            .filter(|r| r.observed && !r.cover)
+            .filter_map(|r| results.get(&r.payment_hash))
+            .map(|candidates| candidates.iter().map(|c| &c.node_id).collect::<HashSet<_>>().len())
+            .collect();
+        sizes.sort();
+        AnonymityCdf { sizes }
+    }
+
+    // (size, share of payments whose set is at most that size), one step per distinct size
+    pub fn steps(&self) -> Vec<(usize, f64)> {
+        let total = self.sizes.len() as f64;
+        let mut steps: Vec<(usize, f64)> = Vec::new();
+        for (i, &size) in self.sizes.iter().enumerate() {
+            let share = (i + 1) as f64 / total;
+            match steps.last_mut() {
+                Some(last) if last.0 == size => last.1 = share,
+                _ => steps.push((size, share)),
+            }
+        }
+        steps
+    }
+}
+
+impl Plot for AnonymityCdf {
+    fn render<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> Result<(), Box<dyn Error>>
+        where DB::ErrorType: 'static {
+        let steps = self.steps();
+        let largest = steps.last().map_or(1, |step| step.0).max(1);
+
+        root.fill(&WHITE)?;
+        let mut chart = ChartBuilder::on(root)
+            .caption("Anonymity set sizes", ("sans-serif", 22))
+            .margin(15)
+            .x_label_area_size(40)
+            .y_label_area_size(50)
+            .build_cartesian_2d(0..largest + 1, 0.0..1.0)?;
+        chart.configure_mesh()
+            .x_desc("candidate recipients")
+            .y_desc("share of observed payments (cumulative)")
+            .draw()?;
+
+        // Flat until the next size is reached, then up
+        let mut points = vec![(0, 0.0)];
+        for &(size, share) in &steps {
+            points.push((size, points.last().map_or(0.0, |p| p.1)));
+            points.push((size, share));
+        }
+        points.push((largest + 1, points.last().map_or(0.0, |p| p.1)));
+        chart.draw_series(LineSeries::new(points, BLUE.stroke_width(2)))?;
+        Ok(())
+    }
+}
+
+// ROC or precision-recall curve of the attacker's calls as the confidence threshold varies,
+// next to what guessing would achieve
+pub struct ThresholdCurveChart {
+    title: String,
+    x_desc: String,
+    y_desc: String,
+    label: String,
+    points: Vec<(f64, f64)>,
+    chance: Vec<(f64, f64)>,
+}
+
+impl ThresholdCurveChart {
+    pub fn roc(curve: &RocCurve) -> Self {
+        ThresholdCurveChart {
+            title: "ROC curve".to_string(),
+            x_desc: "false positive rate".to_string(),
+            y_desc: "true positive rate".to_string(),
+            label: format!("attacker (AUC {:.3})", curve.auc()),
+            points: curve.roc_points(),
+            chance: vec![(0.0, 0.0), (1.0, 1.0)],
+        }
+    }
+
+    pub fn precision_recall(curve: &RocCurve) -> Self {
+        // Calling every candidate is right this often
+        let base_rate = curve.positives as f64 / (curve.positives + curve.negatives).max(1) as f64;
+        ThresholdCurveChart {
+            title: "Precision-recall curve".to_string(),
+            x_desc: "recall".to_string(),
+            y_desc: "precision".to_string(),
+            label: format!("attacker (AP {:.3})", curve.average_precision()),
+            points: curve.precision_recall_points(),
+            chance: vec![(0.0, base_rate), (1.0, base_rate)],
+        }
+    }
+}
+
+impl Plot for ThresholdCurveChart {
+    fn render<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> Result<(), Box<dyn Error>>
+        where DB::ErrorType: 'static {
+        root.fill(&WHITE)?;
+        let mut chart = ChartBuilder::on(root)
+            .caption(&self.title, ("sans-serif", 22))
+            .margin(15)
+            .x_label_area_size(40)
+            .y_label_area_size(50)
+            .build_cartesian_2d(0.0..1.0, 0.0..1.0)?;
+        chart.configure_mesh()
+            .x_desc(&self.x_desc)
+            .y_desc(&self.y_desc)
+            .draw()?;
+
+        chart.draw_series(LineSeries::new(self.chance.clone(), BLACK.mix(0.4)))?
+            .label("chance")
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLACK.mix(0.4)));
+        chart.draw_series(LineSeries::new(self.points.clone(), BLUE.stroke_width(2)))?
+            .label(&self.label)
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLUE.stroke_width(2)));
+        chart.configure_series_labels()
+            .position(SeriesLabelPosition::LowerRight)
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{candidates, record};
+
+    #[test]
+    fn test_charts_summarize_results_and_draw() {
+        let records = vec![
+            record("p1", &["s", "m", "a"]),
+            record("p2", &["s", "m", "b"]),
+            record("p3", &["s", "m", "c"]),
+        ];
+        let results = HashMap::from([
+            ("p1".to_string(), candidates(&[("a", 3.0), ("b", 1.0)])),
+            ("p2".to_string(), candidates(&[("a", 1.0), ("b", 1.0), ("b", 1.0)])),
+            ("p3".to_string(), candidates(&[("c", 1.0)])),
+        ]);
+
+        let histogram = ConfidenceHistogram::from_results(&records, &results);
+        assert_eq!(histogram.identified, vec![0.75, 1.0]);
+        assert_eq!(ConfidenceHistogram::bins(&histogram.missed)[3], 1);
+
+        // Two payments leave two candidates, one leaves just the recipient
+        let cdf = AnonymityCdf::from_results(&records, &results);
+        assert_eq!(cdf.steps(), vec![(1, 1.0 / 3.0), (2, 1.0)]);
+
+        let curve = LineChart::coverage_by_adversary_size(&records, &["x".to_string(), "m".to_string()]);
+        assert_eq!(curve.series[0].2, vec![(1.0, 0.0), (2.0, 1.0)]);
+
+        // Three of the five candidates are the recipient
+        let precision_recall = ThresholdCurveChart::precision_recall(&RocCurve::compute(&records, &results));
+        assert_eq!(precision_recall.chance[0], (0.0, 3.0 / 5.0));
+
+        let dir = std::env::temp_dir().join(format!("thelma_plots_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let stem = dir.join("cdf");
+        let file = save_plot(&cdf, stem.to_str().unwrap(), PlotFormat::Svg).unwrap();
+        assert!(std::fs::read_to_string(&file).unwrap().starts_with("<svg"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,334 @@
+// `thelma runs`: every run's settings, seed, commit, headline metrics and output files kept
+// in a local SQLite database, so weeks of experiments stay findable and comparable
+
+use std::collections::BTreeSet;
+use std::path::Path;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde_json::Value;
+
+use crate::error::ThelmaError;
+
+pub const DEFAULT_REGISTRY_FILE: &str = "thelma_runs.db";
+// Commit the binary was built from, empty outside a git checkout
+pub const GIT_HASH: &str = env!("THELMA_GIT_HASH");
+// Subcommands of `thelma runs`, in the order they're listed in usage
+pub const RUNS_COMMANDS: &[&str] = &["list", "show", "compare"];
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    started_at INTEGER NOT NULL,
+    args TEXT NOT NULL,
+    seed INTEGER,
+    git_hash TEXT NOT NULL,
+    config TEXT NOT NULL,
+    metrics TEXT NOT NULL,
+    artifacts TEXT NOT NULL
+)";
+
+// One run as the registry keeps it
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunRecord {
+    // Assigned when the run is recorded
+    pub id: i64,
+    // Seconds since the Unix epoch
+    pub started_at: u64,
+    pub args: Vec<String>,
+    // Seed of the payment workload, when the run had one
+    pub seed: Option<u64>,
+    pub git_hash: String,
+    // Settings worth listing and comparing runs by, beyond the command line
+    pub config: Value,
+    pub metrics: Value,
+    // Files the run wrote, as absolute paths
+    pub artifacts: Vec<String>,
+}
+
+impl RunRecord {
+    pub fn new(args: &[String], started_at: u64) -> Self {
+        RunRecord {
+            id: 0,
+            started_at,
+            args: args.to_vec(),
+            seed: None,
+            git_hash: GIT_HASH.to_string(),
+            config: Value::Null,
+            metrics: Value::Null,
+            artifacts: Vec::new(),
+        }
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn config(mut self, config: Value) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn metrics(mut self, metrics: Value) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    pub fn artifacts(mut self, artifacts: Vec<String>) -> Self {
+        self.artifacts = artifacts;
+        self
+    }
+
+    pub fn generate_text_report(&self) -> String {
+        let mut report = format!("## THELMA: Run {}\n\n", self.id);
+        report.push_str(&format!("Started: {} UTC\n", format_utc(self.started_at)));
+        report.push_str(&format!("Command: {}\n", self.args.join(" ")));
+        report.push_str(&format!("Seed: {}\n", self.seed.map_or("-".to_string(), |seed| seed.to_string())));
+        report.push_str(&format!("Commit: {}\n\n", if self.git_hash.is_empty() { "-" } else { &self.git_hash }));
+        for (title, values) in [("Config", &self.config), ("Metrics", &self.metrics)] {
+            report.push_str(&format!("### {}\n\n| Key | Value |\n|---|---|\n", title));
+            for (key, value) in values.as_object().into_iter().flatten() {
+                report.push_str(&format!("| {} | {} |\n", key, format_value(Some(value))));
+            }
+            report.push('\n');
+        }
+        report.push_str("### Artifacts\n\n");
+        for path in &self.artifacts {
+            report.push_str(&format!("- {}\n", path));
+        }
+        report
+    }
+
+    pub fn to_json(&self) -> Value {
+        serde_json::json!({
+            "id": self.id,
+            "started_at": self.started_at,
+            "args": self.args,
+            "seed": self.seed,
+            "git_hash": self.git_hash,
+            "config": self.config,
+            "metrics": self.metrics,
+            "artifacts": self.artifacts,
+        })
+    }
+}
+
+// The database of recorded runs
+pub struct RunRegistry {
+    connection: Connection,
+}
+
+impl RunRegistry {
+    // Open the registry at `path`, creating it on first use
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, ThelmaError> {
+        RunRegistry::with_connection(Connection::open(path)?)
+    }
+
+    // A registry that lives only as long as it's open
+    pub fn in_memory() -> Result<Self, ThelmaError> {
+        RunRegistry::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(connection: Connection) -> Result<Self, ThelmaError> {
+        connection.execute(SCHEMA, [])?;
+        Ok(RunRegistry { connection })
+    }
+
+    // Store a run, returning the id it was given
+    pub fn record(&self, run: &RunRecord) -> Result<i64, ThelmaError> {
+        self.connection.execute(
+            "INSERT INTO runs (started_at, args, seed, git_hash, config, metrics, artifacts)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                run.started_at as i64,
+                serde_json::to_string(&run.args)?,
+                // SQLite integers are signed; the bits round-trip
+                run.seed.map(|seed| seed as i64),
+                run.git_hash,
+                run.config.to_string(),
+                run.metrics.to_string(),
+                serde_json::to_string(&run.artifacts)?,
+            ],
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    // Every run, oldest first
+    pub fn list(&self) -> Result<Vec<RunRecord>, ThelmaError> {
+        let mut statement = self.connection.prepare(
+            "SELECT id, started_at, args, seed, git_hash, config, metrics, artifacts FROM runs ORDER BY id")?;
+        let rows = statement.query_map([], StoredRun::read)?.collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter().map(StoredRun::parse).collect()
+    }
+
+    pub fn get(&self, id: i64) -> Result<Option<RunRecord>, ThelmaError> {
+        let row = self.connection.query_row(
+            "SELECT id, started_at, args, seed, git_hash, config, metrics, artifacts FROM runs WHERE id = ?1",
+            [id],
+            StoredRun::read,
+        ).optional()?;
+        row.map(StoredRun::parse).transpose()
+    }
+}
+
+// A row as stored, JSON columns still as text
+struct StoredRun {
+    id: i64,
+    started_at: i64,
+    args: String,
+    seed: Option<i64>,
+    git_hash: String,
+    config: String,
+    metrics: String,
+    artifacts: String,
+}
+
+impl StoredRun {
+    fn read(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(StoredRun {
+            id: row.get(0)?,
+            started_at: row.get(1)?,
+            args: row.get(2)?,
+            seed: row.get(3)?,
+            git_hash: row.get(4)?,
+            config: row.get(5)?,
+            metrics: row.get(6)?,
+            artifacts: row.get(7)?,
+        })
+    }
+
+    fn parse(self) -> Result<RunRecord, ThelmaError> {
+        Ok(RunRecord {
+            id: self.id,
+            started_at: self.started_at as u64,
+            args: serde_json::from_str(&self.args)?,
+            seed: self.seed.map(|seed| seed as u64),
+            git_hash: self.git_hash,
+            config: serde_json::from_str(&self.config)?,
+            metrics: serde_json::from_str(&self.metrics)?,
+            artifacts: serde_json::from_str(&self.artifacts)?,
+        })
+    }
+}
+
+// One line per run, for finding the ones worth a closer look
+pub fn generate_list_report(runs: &[RunRecord]) -> String {
+    let mut report = String::from("## THELMA: Recorded Runs\n\n");
+    report.push_str("| Run | Started (UTC) | Commit | Seed | Nodes | Payments | Malicious | Identified | Command |\n");
+    report.push_str("|---|---|---|---|---|---|---|---|---|\n");
+    for run in runs {
+        report.push_str(&format!("| {} | {} | {} | {} | {} | {} | {} | {} | {} |\n",
+                                 run.id,
+                                 format_utc(run.started_at),
+                                 if run.git_hash.is_empty() { "-" } else { &run.git_hash },
+                                 run.seed.map_or("-".to_string(), |seed| seed.to_string()),
+                                 format_value(run.config.get("nodes")),
+                                 format_value(run.config.get("payments")),
+                                 format_value(run.config.get("malicious")),
+                                 format_value(run.metrics.get("identification_rate")),
+                                 run.args.get(1..).unwrap_or_default().join(" ")));
+    }
+    report
+}
+
+// Two runs side by side: the settings they differ in, then every metric with the change
+// from the first to the second
+pub fn generate_comparison_report(first: &RunRecord, second: &RunRecord) -> String {
+    let mut report = format!("## THELMA: Run {} vs Run {}\n\n", first.id, second.id);
+    report.push_str(&format!("| | Run {} | Run {} |\n|---|---|---|\n", first.id, second.id));
+    report.push_str(&format!("| Started (UTC) | {} | {} |\n", format_utc(first.started_at), format_utc(second.started_at)));
+    report.push_str(&format!("| Commit | {} | {} |\n", first.git_hash, second.git_hash));
+    report.push_str(&format!("| Command | {} | {} |\n\n",
+                             first.args.get(1..).unwrap_or_default().join(" "),
+                             second.args.get(1..).unwrap_or_default().join(" ")));
+
+    let differing: Vec<String> = keys(&first.config, &second.config).into_iter()
+        .filter(|key| first.config.get(key) != second.config.get(key))
+        .collect();
+    if !differing.is_empty() {
+        report.push_str("### Config Differences\n\n| Key | First | Second |\n|---|---|---|\n");
+        for key in differing {
+            report.push_str(&format!("| {} | {} | {} |\n", key,
+                                     format_value(first.config.get(&key)), format_value(second.config.get(&key))));
+        }
+        report.push('\n');
+    }
+
+    report.push_str("### Metrics\n\n| Metric | First | Second | Change |\n|---|---|---|---|\n");
+    for key in keys(&first.metrics, &second.metrics) {
+        let (a, b) = (first.metrics.get(&key), second.metrics.get(&key));
+        let change = match (a.and_then(Value::as_f64), b.and_then(Value::as_f64)) {
+            (Some(a), Some(b)) => format!("{:+.4}", b - a),
+            _ => "-".to_string(),
+        };
+        report.push_str(&format!("| {} | {} | {} | {} |\n", key, format_value(a), format_value(b), change));
+    }
+    report
+}
+
+// Keys of either object, sorted
+fn keys(first: &Value, second: &Value) -> BTreeSet<String> {
+    [first, second].into_iter()
+        .filter_map(Value::as_object)
+        .flat_map(|object| object.keys().cloned())
+        .collect()
+}
+
+fn format_value(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => "-".to_string(),
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Number(number)) if number.is_f64() => format!("{:.4}", number.as_f64().unwrap_or_default()),
+        Some(value) => value.to_string(),
+    }
+}
+
+// "YYYY-MM-DD HH:MM" for seconds since the Unix epoch
+pub fn format_utc(seconds: u64) -> String {
+    let days = (seconds / 86400) as i64;
+    let minutes = seconds % 86400 / 60;
+    // Days to a civil date, after Howard Hinnant's algorithm
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, minutes / 60, minutes % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runs_are_recorded_listed_and_compared() {
+        let registry = RunRegistry::in_memory().unwrap();
+        let args: Vec<String> = ["thelma", "100", "200", "10"].iter().map(|arg| arg.to_string()).collect();
+        let first = RunRecord::new(&args, 1_792_000_000)
+            .seed(u64::MAX)
+            .config(serde_json::json!({ "nodes": 100, "router": "shortest" }))
+            .metrics(serde_json::json!({ "identification_rate": 0.25, "observed_payments": 40 }))
+            .artifacts(vec!["/runs/thelma_report.json".to_string()]);
+        let second = RunRecord::new(&args, 1_792_086_400)
+            .config(serde_json::json!({ "nodes": 100, "router": "cheapest" }))
+            .metrics(serde_json::json!({ "identification_rate": 0.5, "observed_payments": 40 }));
+        assert_eq!(registry.record(&first).unwrap(), 1);
+        assert_eq!(registry.record(&second).unwrap(), 2);
+
+        let runs = registry.list().unwrap();
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0], RunRecord { id: 1, ..first });
+        assert_eq!(registry.get(2).unwrap().unwrap().seed, None);
+        assert!(registry.get(3).unwrap().is_none());
+        assert!(generate_list_report(&runs).contains("| 1 | 2026-10-14 17:46 |"));
+        assert_eq!(format_utc(951_782_400), "2000-02-29 00:00");
+
+        // Only the router differs; the identification rate doubled
+        let comparison = generate_comparison_report(&runs[0], &runs[1]);
+        assert!(comparison.contains("| router | shortest | cheapest |"));
+        assert!(!comparison.contains("| nodes |"));
+        assert!(comparison.contains("| identification_rate | 0.2500 | 0.5000 | +0.2500 |"));
+    }
+}
@@ -0,0 +1,345 @@
+// `thelma serve`: a JSON API over a surveillance operation, for web frontends and notebooks
+
+use std::sync::{Arc, Mutex, RwLock};
+use log::info;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+
+use crate::models::{HTLC, LightningNetworkMap};
+use crate::surveillance::{SurveillanceConfig, SurveillanceMetrics, SurveillanceOperation};
+use crate::surveillance::observation_store::{htlc_from_json, htlc_to_json};
+use crate::simulation::topology::parse_describegraph;
+use crate::checkpoint::{network_from_json, network_to_json};
+use crate::http::{self, Request, Response};
+use crate::error::{ThelmaError, lock_mutex, read_lock};
+
+pub const DEFAULT_SERVE_ADDR: &str = "127.0.0.1:8080";
+
+// Height uploaded `lncli describegraph` dumps are placed at, as for fresh experiments
+const DEFAULT_BLOCK_HEIGHT: u32 = 780000;
+
+// Live events buffered per WebSocket client before a slow one starts skipping
+const EVENT_BUFFER: usize = 1024;
+
+// Candidates included with each live observation event
+const STREAMED_CANDIDATES: usize = 10;
+
+// Single-page dashboard served at `/` when enabled
+const DASHBOARD: &str = include_str!("../assets/dashboard.html");
+
+// Builds the surveillance settings for a newly uploaded graph
+pub type ConfigFactory = dyn Fn(&Arc<RwLock<LightningNetworkMap>>) -> SurveillanceConfig + Send + Sync;
+
+// One uploaded graph and everything observed and analyzed on it
+struct Session {
+    network: Arc<RwLock<LightningNetworkMap>>,
+    operation: SurveillanceOperation,
+    // JSON and text reports of the last analysis
+    reports: Option<(String, String)>,
+}
+
+pub struct ApiServer {
+    configure: Box<ConfigFactory>,
+    metrics: Option<Arc<SurveillanceMetrics>>,
+    session: Mutex<Option<Session>>,
+    // JSON events pushed to WebSocket clients of `/live`
+    events: broadcast::Sender<String>,
+    // Serve the dashboard at `/`
+    dashboard: bool,
+    // Largest request body accepted, which bounds the graphs that can be uploaded
+    max_body_bytes: usize,
+}
+
+impl ApiServer {
+    pub fn new(configure: Box<ConfigFactory>) -> Self {
+        let (events, _) = broadcast::channel(EVENT_BUFFER);
+        ApiServer {
+            configure,
+            metrics: None,
+            session: Mutex::new(None),
+            events,
+            dashboard: false,
+            max_body_bytes: http::DEFAULT_MAX_BODY_BYTES,
+        }
+    }
+
+    // Count observations and analysis of every session on these metrics
+    pub fn metrics(mut self, metrics: Arc<SurveillanceMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    // Also serve the browser dashboard at `/`
+    pub fn dashboard(mut self) -> Self {
+        self.dashboard = true;
+        self
+    }
+
+    // Accept request bodies up to this size, for graphs larger than the default allows
+    pub fn max_body_bytes(mut self, max_body_bytes: usize) -> Self {
+        self.max_body_bytes = max_body_bytes;
+        self
+    }
+
+    // Serve the API on `addr` until the process exits
+    pub async fn serve(self, addr: &str) -> Result<(), ThelmaError> {
+        let listener = TcpListener::bind(addr).await?;
+        info!("Serving the THELMA API on http://{}", listener.local_addr()?);
+
+        let max_body_bytes = self.max_body_bytes;
+        let server = Arc::new(self);
+        http::serve(listener, max_body_bytes, Arc::new(move |request: Request| server.handle(request))).await;
+        Ok(())
+    }
+
+    pub fn handle(&self, request: Request) -> Response {
+        let result = match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/") if self.dashboard => Ok(Response::text(200, "text/html; charset=utf-8", DASHBOARD.to_string())),
+            ("GET", "/status") => Ok(self.status()),
+            ("GET", "/graph") => Ok(self.graph()),
+            ("POST", "/graph") => self.upload_graph(&request.body),
+            ("POST", "/observations") => self.post_observations(&request.body),
+            ("DELETE", "/observations") => self.clear_observations(),
+            ("POST", "/analysis") => self.analyze(),
+            ("GET", "/report") => self.report(|(json, _)| Response::text(200, "application/json", json.clone())),
+            ("GET", "/report/text") => self.report(|(_, text)| Response::text(200, "text/markdown", text.clone())),
+            ("GET", "/live") if request.is_websocket_upgrade() => Ok(Response::websocket(self.events.subscribe())),
+            ("GET", "/live") => Ok(Response::error(400, "/live only accepts WebSocket connections")),
+            (_, "/status" | "/graph" | "/observations" | "/analysis" | "/report" | "/report/text" | "/live") => {
+                Ok(Response::error(405, "method not allowed"))
+            }
+            _ => Ok(Response::not_found()),
+        };
+
+        result.unwrap_or_else(|e| match e {
+            ThelmaError::Graph(_) | ThelmaError::Config(_) | ThelmaError::Json(_) => Response::error(400, &e.to_string()),
+            // Observations missing fields
+            ThelmaError::Io(ref io) if io.kind() == std::io::ErrorKind::InvalidData => {
+                Response::error(400, &e.to_string())
+            }
+            _ => Response::error(500, &e.to_string()),
+        })
+    }
+
+    fn status(&self) -> Response {
+        let session = lock_mutex(&self.session);
+        let status = match &*session {
+            Some(session) => {
+                let network = read_lock(&session.network);
+                serde_json::json!({
+                    "graph": { "nodes": network.nodes.len(), "channels": network.channels.len() },
+                    "malicious_nodes": session.operation.get_malicious_nodes(),
+                    "observations": session.operation.observation_count(),
+                    "analyzed": session.reports.is_some(),
+                })
+            }
+            None => serde_json::json!({ "graph": null }),
+        };
+        Response::json(200, &status)
+    }
+
+    // The loaded network in THELMA's own format
+    fn graph(&self) -> Response {
+        match &*lock_mutex(&self.session) {
+            Some(session) => Response::json(200, &network_to_json(&read_lock(&session.network))),
+            None => no_graph(),
+        }
+    }
+
+    // Start over on a new graph, either an `lncli describegraph` dump or a THELMA network
+    // as found in checkpoints and trace headers
+    fn upload_graph(&self, body: &[u8]) -> Result<Response, ThelmaError> {
+        let graph: serde_json::Value = serde_json::from_slice(body)?;
+        let network = if graph.get("edges").is_some() {
+            let (nodes, channels) = parse_describegraph(&graph, DEFAULT_BLOCK_HEIGHT)?;
+            let mut network = LightningNetworkMap::new(DEFAULT_BLOCK_HEIGHT);
+            for node in nodes {
+                network.add_node(node);
+            }
+            for channel in channels {
+                network.add_channel(channel);
+            }
+            network
+        } else {
+            network_from_json(&graph)?
+        };
+        let (nodes, channels) = (network.nodes.len(), network.channels.len());
+
+        let network = Arc::new(RwLock::new(network));
+        let mut operation = SurveillanceOperation::new(network.clone(), (self.configure)(&network))?;
+        if let Some(metrics) = &self.metrics {
+            operation.set_metrics(metrics.clone());
+        }
+        *lock_mutex(&self.session) = Some(Session { network, operation, reports: None });
+
+        info!("Loaded graph with {} nodes and {} channels", nodes, channels);
+        let loaded = serde_json::json!({ "nodes": nodes, "channels": channels });
+        self.publish("graph", &loaded);
+        Ok(Response::json(201, &loaded))
+    }
+
+    // Record one observation or an array of them. Observing nodes join the adversary.
+    fn post_observations(&self, body: &[u8]) -> Result<Response, ThelmaError> {
+        let value: serde_json::Value = serde_json::from_slice(body)?;
+        let htlcs: Vec<HTLC> = match value.as_array() {
+            Some(entries) => entries.iter().map(htlc_from_json).collect::<Result<_, _>>()?,
+            None => vec![htlc_from_json(&value)?],
+        };
+
+        let mut session = lock_mutex(&self.session);
+        let Some(session) = session.as_mut() else { return Ok(no_graph()) };
+        {
+            let network = read_lock(&session.network);
+            if let Some(unknown) = htlcs.iter().find(|htlc| !network.nodes.contains_key(&htlc.observed_by_node)) {
+                return Err(ThelmaError::Graph(format!("observing node {} is not in the graph",
+                                                      unknown.observed_by_node)));
+            }
+        }
+
+        let accepted = htlcs.len();
+        for htlc in htlcs {
+            session.operation.register_malicious_node(&htlc.observed_by_node);
+            let mut event = htlc_to_json(&htlc);
+            let payment_hash = htlc.payment_hash.clone();
+            session.operation.record_htlc_observation(htlc)?;
+
+            // With live analysis, the payment's updated ranking goes along with it
+            if let Some(candidates) = session.operation.live_candidates(&payment_hash) {
+                event["candidate_count"] = candidates.len().into();
+                event["candidates"] = candidates.iter().take(STREAMED_CANDIDATES)
+                    .map(|candidate| serde_json::json!({
+                        "node_id": candidate.node_id,
+                        "confidence": candidate.confidence_score,
+                    }))
+                    .collect();
+            }
+            self.publish("observation", &event);
+        }
+        session.reports = None;
+
+        Ok(Response::json(200, &serde_json::json!({
+            "accepted": accepted,
+            "observations": session.operation.observation_count(),
+        })))
+    }
+
+    fn clear_observations(&self) -> Result<Response, ThelmaError> {
+        let mut session = lock_mutex(&self.session);
+        let Some(session) = session.as_mut() else { return Ok(no_graph()) };
+        session.operation.clear_observations()?;
+        session.reports = None;
+        self.publish("cleared", &serde_json::json!({}));
+        Ok(Response::json(200, &serde_json::json!({ "observations": 0 })))
+    }
+
+    // Analyze everything observed so far and keep the reports for `GET /report`
+    fn analyze(&self) -> Result<Response, ThelmaError> {
+        let mut session = lock_mutex(&self.session);
+        let Some(session) = session.as_mut() else { return Ok(no_graph()) };
+
+        let results = session.operation.run_analysis();
+        let json = session.operation.generate_json_report(&results);
+        let text = session.operation.generate_report(&results);
+        let response = Response::text(200, "application/json", json.clone());
+        let payments = serde_json::from_str::<serde_json::Value>(&json)?["total_payments"].clone();
+        session.reports = Some((json, text));
+        self.publish("analysis", &serde_json::json!({ "total_payments": payments }));
+        Ok(response)
+    }
+
+    // Tell WebSocket clients what happened; `data`'s fields are sent alongside the type
+    fn publish(&self, kind: &str, data: &serde_json::Value) {
+        let mut event = serde_json::Map::new();
+        event.insert("type".to_string(), kind.into());
+        if let Some(fields) = data.as_object() {
+            event.extend(fields.clone());
+        }
+        // Nobody listening is fine
+        let _ = self.events.send(serde_json::Value::Object(event).to_string());
+    }
+
+    fn report(&self, render: impl Fn(&(String, String)) -> Response) -> Result<Response, ThelmaError> {
+        let session = lock_mutex(&self.session);
+        Ok(match session.as_ref().and_then(|session| session.reports.as_ref()) {
+            Some(reports) => render(reports),
+            None => Response::error(409, "nothing analyzed yet; POST /analysis first"),
+        })
+    }
+}
+
+fn no_graph() -> Response {
+    Response::error(409, "no graph loaded; POST /graph first")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, path: &str, body: serde_json::Value) -> Request {
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            headers: Vec::new(),
+            body: body.to_string().into_bytes(),
+        }
+    }
+
+    fn body(response: &Response) -> serde_json::Value {
+        serde_json::from_str(&response.body).unwrap()
+    }
+
+    #[test]
+    fn test_api_analyzes_posted_observations() {
+        let server = ApiServer::new(Box::new(|_| SurveillanceConfig::observing(Vec::new()).live_analysis()));
+        let mut live = server.events.subscribe();
+        assert_eq!(server.handle(request("POST", "/observations", serde_json::json!([]))).status, 409);
+
+        // a - b - c - d in describegraph form
+        let nodes: Vec<_> = ["a", "b", "c", "d"].iter()
+            .map(|key| serde_json::json!({ "pub_key": key, "alias": key }))
+            .collect();
+        let edges: Vec<_> = [("ab", "a", "b"), ("bc", "b", "c"), ("cd", "c", "d")].iter()
+            .map(|(id, n1, n2)| serde_json::json!({
+                "channel_id": id, "node1_pub": n1, "node2_pub": n2, "capacity": "1000000",
+            }))
+            .collect();
+        let response = server.handle(request("POST", "/graph", serde_json::json!({ "nodes": nodes, "edges": edges })));
+        assert_eq!(response.status, 201);
+        assert_eq!(body(&response)["channels"], 3);
+
+        let htlc = serde_json::json!({
+            "payment_hash": "p1", "cltv_expiry": 780080, "amount": 100000,
+            "observed_at_block": 780000, "observed_by_node": "b",
+        });
+        let response = server.handle(request("POST", "/observations", htlc));
+        assert_eq!(body(&response)["observations"], 1);
+
+        // WebSocket clients hear about the graph, then the observation with its ranking
+        let graph: serde_json::Value = serde_json::from_str(&live.try_recv().unwrap()).unwrap();
+        assert_eq!(graph["type"], "graph");
+        let observation: serde_json::Value = serde_json::from_str(&live.try_recv().unwrap()).unwrap();
+        assert_eq!(observation["type"], "observation");
+        assert_eq!(observation["observed_by_node"], "b");
+        assert_eq!(observation["candidates"][0]["node_id"], "c");
+        assert_eq!(server.handle(request("GET", "/report", serde_json::Value::Null)).status, 409);
+
+        let response = server.handle(request("POST", "/analysis", serde_json::Value::Null));
+        assert_eq!(response.status, 200);
+        assert_eq!(body(&response)["total_payments"], 1);
+        let report = server.handle(request("GET", "/report", serde_json::Value::Null));
+        assert_eq!(report.body, response.body);
+
+        let status = body(&server.handle(request("GET", "/status", serde_json::Value::Null)));
+        assert_eq!(status["malicious_nodes"], serde_json::json!(["b"]));
+        assert_eq!(server.handle(request("POST", "/observations", serde_json::json!({}))).status, 400);
+        assert_eq!(server.handle(request("PUT", "/graph", serde_json::Value::Null)).status, 405);
+        let graph = body(&server.handle(request("GET", "/graph", serde_json::Value::Null)));
+        assert_eq!(graph["nodes"].as_array().unwrap().len(), 4);
+
+        // The dashboard is opt-in
+        assert_eq!(server.handle(request("GET", "/", serde_json::Value::Null)).status, 404);
+        let server = server.dashboard();
+        let page = server.handle(request("GET", "/", serde_json::Value::Null));
+        assert!(page.content_type.starts_with("text/html") && page.body.contains("/live"));
+    }
+}
@@ -0,0 +1,317 @@
+// `thelma shell`: build a small network by hand, send payments through it and watch what
+// the adversary makes of them
+
+use std::io::{IsTerminal, Write};
+use std::sync::{Arc, Mutex, RwLock};
+use log::{info, warn};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::models::{Channel, ChannelFees, LightningNetworkMap, Node};
+use crate::simulation::{AmountDistribution, Observer, PaymentRecord, PaymentSimulator, SimulatorConfig};
+use crate::surveillance::SurveillanceOperation;
+use crate::error::{ThelmaError, lock_mutex, read_lock, write_lock};
+
+// CLTV delta of nodes added without one
+const DEFAULT_NODE_CLTV_DELTA: u32 = 40;
+
+// Capacity (sat) of channels added without one
+const DEFAULT_CHANNEL_CAPACITY: u64 = 1_000_000;
+
+const HELP: &str = "\
+Commands:
+  addnode <id> [cltv_delta]            - Add a node (default delta: 40 blocks)
+  addchannel <a> <b> [capacity_sat]    - Open a channel between two nodes (default: 1000000 sat)
+  removenode <id>                      - Take a node and its channels out of the network
+  closechannel <channel>               - Close a channel
+  setfee <channel> <node> <base_msat> <ppm> - Change what node charges to forward over channel
+  observe <node>                       - Let the adversary watch HTLCs forwarded by node
+  observe                              - List the adversary's nodes and what they observed
+  pay <from> <to> <amount_msat>        - Route a payment and show who saw it
+  analyze <hash>                       - Rank candidate recipients of a payment (hash prefix is enough)
+  report                               - Full surveillance report
+  show                                 - List nodes, channels and payments
+  help                                 - This list
+  quit                                 - Leave the shell
+Lines starting with # are ignored, so scripts can be piped in.";
+
+// Why the last payment failed, if it did
+#[derive(Default)]
+struct FailureWatcher {
+    reason: Option<String>,
+}
+
+impl Observer for FailureWatcher {
+    fn on_fail(&mut self, _sender: &str, _receiver: &str, reason: &str) -> Result<(), ThelmaError> {
+        self.reason = Some(reason.to_string());
+        Ok(())
+    }
+}
+
+pub struct Shell {
+    network: Arc<RwLock<LightningNetworkMap>>,
+    surveillance: Arc<Mutex<SurveillanceOperation>>,
+    // Router and limits payments are made with
+    simulator: SimulatorConfig,
+    // Payments routed so far, oldest first
+    payments: Vec<PaymentRecord>,
+}
+
+impl Shell {
+    // `operation` watches `network`, which starts out however the caller left it
+    pub fn new(network: Arc<RwLock<LightningNetworkMap>>,
+               operation: SurveillanceOperation,
+               simulator: SimulatorConfig) -> Self {
+        Shell { network, surveillance: Arc::new(Mutex::new(operation)), simulator, payments: Vec::new() }
+    }
+
+    // Read commands from stdin until `quit` or end of input. Failed commands are reported
+    // and the shell carries on.
+    pub async fn run(mut self) -> Result<(), ThelmaError> {
+        let interactive = std::io::stdin().is_terminal();
+        if interactive {
+            info!("Type `help` for the available commands");
+        }
+
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        loop {
+            if interactive {
+                let mut stdout = std::io::stdout();
+                write!(stdout, "thelma> ")?;
+                stdout.flush()?;
+            }
+            let Some(line) = lines.next_line().await? else { break };
+            match self.execute(&line).await {
+                Ok(Some(output)) if !output.is_empty() => info!("{}", output),
+                Ok(Some(_)) => {}
+                Ok(None) => break,
+                Err(e) => warn!("{}", e),
+            }
+        }
+        Ok(())
+    }
+
+    // Run one command line and return what it printed, or None when it asked to quit
+    pub async fn execute(&mut self, line: &str) -> Result<Option<String>, ThelmaError> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let output = match words.as_slice() {
+            [] => String::new(),
+            [first, ..] if first.starts_with('#') => String::new(),
+            ["help"] => HELP.to_string(),
+            ["quit" | "exit"] => return Ok(None),
+            ["addnode", id] => self.add_node(id, DEFAULT_NODE_CLTV_DELTA),
+            ["addnode", id, delta] => self.add_node(id, number(delta, "cltv_delta")?),
+            ["addchannel", node1, node2] => self.add_channel(node1, node2, DEFAULT_CHANNEL_CAPACITY)?,
+            ["addchannel", node1, node2, capacity] => self.add_channel(node1, node2, number(capacity, "capacity")?)?,
+            ["removenode", id] => self.remove_node(id)?,
+            ["closechannel", channel_id] => self.close_channel(channel_id)?,
+            ["setfee", channel_id, node, base, rate] => {
+                let fees = ChannelFees { base_fee_msat: number(base, "base_msat")?, fee_rate_ppm: number(rate, "ppm")? };
+                self.set_fee(channel_id, node, fees)?
+            }
+            ["observe"] => self.observations(),
+            ["observe", node] => self.observe(node)?,
+            ["pay", sender, receiver, amount] => self.pay(sender, receiver, number(amount, "amount")?).await?,
+            ["analyze", hash] => self.analyze(hash)?,
+            ["report"] => {
+                let surveillance = lock_mutex(&self.surveillance);
+                surveillance.generate_report(&surveillance.run_analysis())
+            }
+            ["show"] => self.show(),
+            [command, ..] => {
+                return Err(ThelmaError::Config(format!("can't run `{}` with those arguments, see `help`", command)));
+            }
+        };
+        Ok(Some(output))
+    }
+
+    fn add_node(&mut self, id: &str, cltv_delta: u32) -> String {
+        write_lock(&self.network).add_node(Node::new(id, id, cltv_delta));
+        format!("Added node {} with a CLTV delta of {} blocks", id, cltv_delta)
+    }
+
+    fn add_channel(&mut self, node1: &str, node2: &str, capacity: u64) -> Result<String, ThelmaError> {
+        let mut network = write_lock(&self.network);
+        self.require_nodes(&network, &[node1, node2])?;
+        // Channels may have been closed since, so the count alone could repeat an id
+        let channel_id = (network.channels.len()..)
+            .map(|n| format!("{}-{}-{}", node1, node2, n))
+            .find(|id| network.channel(id).is_none())
+            .unwrap_or_default();
+        network.add_channel(Channel::new(&channel_id, node1, node2, capacity));
+        Ok(format!("Opened channel {} with {} sat", channel_id, capacity))
+    }
+
+    fn remove_node(&mut self, id: &str) -> Result<String, ThelmaError> {
+        let mut network = write_lock(&self.network);
+        self.require_nodes(&network, &[id])?;
+        let channels = network.degree(id);
+        network.remove_node(id);
+        Ok(format!("Removed node {} and its {} channels", id, channels))
+    }
+
+    fn close_channel(&mut self, channel_id: &str) -> Result<String, ThelmaError> {
+        match write_lock(&self.network).remove_channel(channel_id) {
+            Some(channel) => Ok(format!("Closed channel {} between {} and {}", channel_id, channel.node1, channel.node2)),
+            None => Err(ThelmaError::Graph(format!("there's no channel {}, see `show`", channel_id))),
+        }
+    }
+
+    fn set_fee(&mut self, channel_id: &str, node: &str, fees: ChannelFees) -> Result<String, ThelmaError> {
+        if !write_lock(&self.network).update_channel_policy(channel_id, node, fees) {
+            return Err(ThelmaError::Graph(format!("{} is not an end of a channel {}", node, channel_id)));
+        }
+        Ok(format!("{} now charges {} msat + {} ppm on {}", node, fees.base_fee_msat, fees.fee_rate_ppm, channel_id))
+    }
+
+    fn observe(&mut self, node: &str) -> Result<String, ThelmaError> {
+        self.require_nodes(&read_lock(&self.network), &[node])?;
+        lock_mutex(&self.surveillance).register_malicious_node(node);
+        Ok(format!("The adversary now watches HTLCs forwarded by {}", node))
+    }
+
+    fn observations(&self) -> String {
+        let surveillance = lock_mutex(&self.surveillance);
+        let nodes = surveillance.get_malicious_nodes();
+        if nodes.is_empty() {
+            return "The adversary watches no nodes yet; add one with `observe <node>`".to_string();
+        }
+        format!("The adversary watches {} and has recorded {} HTLC observations",
+                nodes.join(", "), surveillance.observation_count())
+    }
+
+    async fn pay(&mut self, sender: &str, receiver: &str, amount: u64) -> Result<String, ThelmaError> {
+        let malicious = lock_mutex(&self.surveillance).get_malicious_nodes().to_vec();
+        let config = self.simulator.clone()
+            .amounts(AmountDistribution::Fixed(amount))
+            .malicious_nodes(malicious.clone());
+        let mut simulator = PaymentSimulator::new(self.network.clone(), config);
+        let failure = Arc::new(Mutex::new(FailureWatcher::default()));
+        let observer = simulator.register_observer(self.surveillance.clone());
+        let watcher = simulator.register_observer(failure.clone());
+        simulator.simulate_specific_payment(sender, receiver).await?;
+        simulator.close_events();
+        observer.await?;
+        watcher.await?;
+
+        let Some(record) = simulator.payment_records().first().cloned() else {
+            let reason = lock_mutex(&failure).reason.take().unwrap_or_else(|| "no route".to_string());
+            return Ok(format!("Payment from {} to {} failed: {}", sender, receiver, reason));
+        };
+
+        let watchers: Vec<&String> = record.path.iter().filter(|node| malicious.contains(node)).collect();
+        let seen = if watchers.is_empty() {
+            "Not seen by the adversary".to_string()
+        } else {
+            format!("Seen by {}", watchers.iter().map(|node| node.as_str()).collect::<Vec<_>>().join(", "))
+        };
+        let output = format!("Paid {} msat from {} to {} over {}\nPayment hash: {}\n{}",
+                             amount, sender, receiver, record.path.join(" > "), record.payment_hash, seen);
+        self.payments.push(record);
+        Ok(output)
+    }
+
+    fn analyze(&self, prefix: &str) -> Result<String, ThelmaError> {
+        let matching: Vec<&PaymentRecord> = self.payments.iter()
+            .filter(|payment| payment.payment_hash.starts_with(prefix))
+            .collect();
+        let payment = match matching.as_slice() {
+            [payment] => *payment,
+            [] => return Err(ThelmaError::Config(format!("no payment hash starts with {}", prefix))),
+            _ => return Err(ThelmaError::Config(format!("{} payments match {}, give more of the hash",
+                                                        matching.len(), prefix))),
+        };
+
+        let results = lock_mutex(&self.surveillance).run_analysis();
+        let Some(candidates) = results.get(&payment.payment_hash).filter(|candidates| !candidates.is_empty()) else {
+            return Ok(format!("The adversary has no candidates for {}", payment.payment_hash));
+        };
+
+        let mut output = format!("Candidate recipients of {} (actually {}):", payment.payment_hash, payment.receiver);
+        for (rank, candidate) in candidates.iter().enumerate() {
+            let marker = if candidate.node_id == payment.receiver { "  <- recipient" } else { "" };
+            output.push_str(&format!("\n  {}. {} confidence {:.3} via {}{}", rank + 1, candidate.node_id,
+                                     candidate.confidence_score, candidate.route.join(" > "), marker));
+        }
+        Ok(output)
+    }
+
+    fn show(&self) -> String {
+        let network = read_lock(&self.network);
+        let mut nodes: Vec<&Node> = network.nodes.values().collect();
+        nodes.sort_by(|a, b| a.pub_key.cmp(&b.pub_key));
+        let malicious = lock_mutex(&self.surveillance).get_malicious_nodes().to_vec();
+
+        let mut output = format!("Block height {}\nNodes:", network.current_block_height);
+        for node in nodes {
+            let role = if malicious.contains(&node.pub_key) { " (adversary)" } else { "" };
+            output.push_str(&format!("\n  {} delta {}{}", node.pub_key, node.cltv_expiry_delta, role));
+        }
+        output.push_str("\nChannels:");
+        for channel in &network.channels {
+            let status = if channel.status.is_active() { String::new() } else { format!(" ({})", channel.status.name()) };
+            output.push_str(&format!("\n  {} {} <-> {} {} sat{}", channel.channel_id, channel.node1, channel.node2,
+                                     channel.capacity, status));
+        }
+        output.push_str("\nPayments:");
+        for payment in &self.payments {
+            output.push_str(&format!("\n  {} {} -> {} {} msat", payment.payment_hash, payment.sender,
+                                     payment.receiver, payment.amount));
+        }
+        output
+    }
+
+    fn require_nodes(&self, network: &LightningNetworkMap, nodes: &[&str]) -> Result<(), ThelmaError> {
+        match nodes.iter().find(|node| !network.nodes.contains_key(**node)) {
+            Some(unknown) => Err(ThelmaError::Graph(format!("{} is not in the network, add it with `addnode`", unknown))),
+            None => Ok(()),
+        }
+    }
+}
+
+fn number<T: std::str::FromStr>(value: &str, name: &str) -> Result<T, ThelmaError> {
+    value.parse().map_err(|_| ThelmaError::Config(format!("{} must be a number, got {}", name, value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::surveillance::SurveillanceConfig;
+
+    #[tokio::test]
+    async fn test_shell_script_pays_and_analyzes() {
+        let network = Arc::new(RwLock::new(LightningNetworkMap::new(780000)));
+        let operation = SurveillanceOperation::new(network.clone(), SurveillanceConfig::observing(Vec::new())).unwrap();
+        let mut shell = Shell::new(network, operation, SimulatorConfig::new());
+
+        for line in ["# a line", "addnode a", "addnode b", "addnode c 80", "addnode d",
+                     "addchannel a b", "addchannel b c", "addchannel c d 500000", "observe b"] {
+            shell.execute(line).await.unwrap();
+        }
+        assert!(shell.execute("addchannel a x").await.unwrap_err().to_string().contains("x is not in the network"));
+        assert!(shell.execute("pay a d lots").await.is_err());
+        assert!(shell.execute("frobnicate").await.is_err());
+
+        let paid = shell.execute("pay a d 50000").await.unwrap().unwrap();
+        assert!(paid.contains("over a > b > c > d"));
+        assert!(paid.contains("Seen by b"));
+
+        let analysis = shell.execute("analyze hash_").await.unwrap().unwrap();
+        assert!(analysis.contains("(actually d)"));
+        assert!(analysis.contains("  1. "));
+
+        assert!(shell.execute("show").await.unwrap().unwrap().contains("b delta 40 (adversary)"));
+
+        // A pricier hop and then a missing one change what payments can do
+        assert!(shell.execute("setfee a-b-0 c 1 0").await.is_err());
+        shell.execute("setfee b-c-1 b 7000 0").await.unwrap();
+        assert!(shell.execute("pay a d 50000").await.unwrap().unwrap().contains("over a > b > c > d"));
+        shell.execute("closechannel b-c-1").await.unwrap();
+        assert!(shell.execute("closechannel b-c-1").await.is_err());
+        assert!(shell.execute("pay a d 50000").await.unwrap().unwrap().contains("failed"));
+        assert_eq!(shell.execute("addchannel c d").await.unwrap().unwrap(), "Opened channel c-d-3 with 1000000 sat");
+        shell.execute("removenode d").await.unwrap();
+        assert!(shell.execute("removenode d").await.is_err());
+        assert!(!shell.execute("show").await.unwrap().unwrap().contains("c-d-"));
+        assert!(shell.execute("quit").await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,345 @@
+// Settings for the payment simulator
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use rand::Rng;
+
+use crate::models::DEFAULT_INVOICE_EXPIRY_BLOCKS;
+pub use crate::models::{CltvConstruction, CLTV_CONSTRUCTION_NAMES};
+use crate::models::htlc::DEFAULT_MAX_CLTV_EXPIRY;
+use crate::simulation::router::{BfsRouter, Router};
+use crate::simulation::gossip::GossipSchedule;
+use crate::simulation::retry::RetryPolicy;
+use crate::simulation::latency::HopLatency;
+use crate::simulation::regions::RegionLatency;
+use crate::defense::{DecoyHopDefense, CoverTrafficDefense, LatencyPaddingDefense, OverpaymentDefense,
+                     PaymentSplittingDefense};
+
+// How payment amounts (msat) are drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AmountDistribution {
+    // Every payment carries the same amount
+    Fixed(u64),
+    // Uniform over [min, max)
+    Uniform { min_msat: u64, max_msat: u64 },
+    // Uniform in log space, so small payments dominate as they do on the real network
+    LogUniform { min_msat: u64, max_msat: u64 },
+}
+
+impl Default for AmountDistribution {
+    fn default() -> Self {
+        AmountDistribution::Uniform { min_msat: 10_000, max_msat: 1_000_000 }
+    }
+}
+
+impl AmountDistribution {
+    pub fn sample(&self, rng: &mut impl Rng) -> u64 {
+        match *self {
+            AmountDistribution::Fixed(amount) => amount,
+            AmountDistribution::Uniform { min_msat, max_msat } => {
+                if max_msat <= min_msat {
+                    return min_msat;
+                }
+                rng.random_range(min_msat..max_msat)
+            }
+            AmountDistribution::LogUniform { min_msat, max_msat } => {
+                let (low, high) = ((min_msat.max(1) as f64).ln(), (max_msat.max(1) as f64).ln());
+                if high <= low {
+                    return min_msat;
+                }
+                (rng.random_range(low..high).exp() as u64).clamp(min_msat, max_msat)
+            }
+        }
+    }
+
+    // Parse `fixed:<msat>`, `uniform:<min>-<max>` or `log-uniform:<min>-<max>`
+    pub fn from_spec(spec: &str) -> Option<Self> {
+        let (kind, range) = spec.split_once(':')?;
+        if kind == "fixed" {
+            return range.parse().ok().map(AmountDistribution::Fixed);
+        }
+
+        let (min, max) = range.split_once('-')?;
+        let (min_msat, max_msat) = (min.parse().ok()?, max.parse().ok()?);
+        match kind {
+            "uniform" => Some(AmountDistribution::Uniform { min_msat, max_msat }),
+            "log-uniform" => Some(AmountDistribution::LogUniform { min_msat, max_msat }),
+            _ => None,
+        }
+    }
+}
+
+// Everything a `PaymentSimulator` can be tuned with. Built up with the chained setters
+// below, so new options don't change the simulator's constructor.
+#[derive(Clone)]
+pub struct SimulatorConfig {
+    // Delay between simulated payments for more realistic behavior
+    pub(crate) delay_ms: u64,
+    // Number of tasks payments are spread across
+    pub(crate) workers: usize,
+    // Senders refuse routes whose total timelock exceeds this
+    pub(crate) max_cltv_expiry: u32,
+    pub(crate) amounts: AmountDistribution,
+    // Ground truth about who the adversary is, to label observed payments and keep
+    // cover traffic among honest nodes
+    pub(crate) malicious_nodes: Vec<String>,
+    // Path selection used by senders without a router of their own
+    pub(crate) router: Arc<dyn Router>,
+    pub(crate) node_routers: HashMap<String, Arc<dyn Router>>,
+    // Optional route padding applied by senders
+    pub(crate) decoy_hops: Option<DecoyHopDefense>,
+    // Optional dummy payments emitted by honest nodes
+    pub(crate) cover_traffic: Option<CoverTrafficDefense>,
+    // Optional random delays honest nodes add before forwarding
+    pub(crate) latency_padding: Option<LatencyPaddingDefense>,
+    // Optional random overpayment by senders
+    pub(crate) overpayment: Option<OverpaymentDefense>,
+    // Optional multi-part payments over node-disjoint routes
+    pub(crate) payment_splitting: Option<PaymentSplittingDefense>,
+    // Raised to stop simulating before the requested number of payments
+    pub(crate) stop: Option<Arc<AtomicBool>>,
+    // Fixed RNG seed, e.g. to continue a checkpointed run; random when unset
+    pub(crate) seed: Option<u64>,
+    // Give every hop its own payment point instead of one shared hash
+    pub(crate) ptlc: bool,
+    // Mine blocks and gossip channel updates between payments
+    pub(crate) gossip: Option<GossipSchedule>,
+    // Payments an HTLC stays in flight for, holding a slot on every hop
+    pub(crate) htlc_hold: u64,
+    // Retry payments that fail in flight, and blocks recipients' invoices stay payable for
+    pub(crate) retries: Option<RetryPolicy>,
+    pub(crate) invoice_expiry_blocks: u32,
+    // Time HTLCs and their resolutions take to cross each link
+    pub(crate) hop_latency: HopLatency,
+    // Nodes senders hand their payments to for routing the rest of the way, if any
+    pub(crate) trampolines: Vec<String>,
+    // Wallet clients whose LSP opens their channel when they are first paid, with that LSP
+    pub(crate) jit_channels: HashMap<String, String>,
+    // Latency between the regions nodes run in, in place of `hop_latency`'s mean
+    pub(crate) regions: Option<RegionLatency>,
+    // How senders build the timelocks of their routes
+    pub(crate) cltv_construction: CltvConstruction,
+}
+
+impl Default for SimulatorConfig {
+    fn default() -> Self {
+        SimulatorConfig {
+            delay_ms: 0,
+            workers: 1,
+            max_cltv_expiry: DEFAULT_MAX_CLTV_EXPIRY,
+            amounts: AmountDistribution::default(),
+            malicious_nodes: Vec::new(),
+            router: Arc::new(BfsRouter),
+            node_routers: HashMap::new(),
+            decoy_hops: None,
+            cover_traffic: None,
+            latency_padding: None,
+            overpayment: None,
+            payment_splitting: None,
+            stop: None,
+            seed: None,
+            ptlc: false,
+            gossip: None,
+            htlc_hold: 0,
+            retries: None,
+            invoice_expiry_blocks: DEFAULT_INVOICE_EXPIRY_BLOCKS,
+            hop_latency: HopLatency::default(),
+            trampolines: Vec::new(),
+            jit_channels: HashMap::new(),
+            regions: None,
+            cltv_construction: CltvConstruction::default(),
+        }
+    }
+}
+
+impl SimulatorConfig {
+    pub fn new() -> Self {
+        SimulatorConfig::default()
+    }
+
+    // Pause between payments
+    pub fn delay_ms(mut self, delay_ms: u64) -> Self {
+        self.delay_ms = delay_ms;
+        self
+    }
+
+    // Spread payments across this many concurrent tasks. All of them publish to the same
+    // event bus.
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    // Cap on the total timelock senders accept for a route
+    pub fn max_cltv_expiry(mut self, max_cltv_expiry: u32) -> Self {
+        self.max_cltv_expiry = max_cltv_expiry;
+        self
+    }
+
+    pub fn amounts(mut self, amounts: AmountDistribution) -> Self {
+        self.amounts = amounts;
+        self
+    }
+
+    // Nodes run by the adversary, used to mark which payments it could have seen
+    pub fn malicious_nodes(mut self, malicious_nodes: Vec<String>) -> Self {
+        self.malicious_nodes = malicious_nodes;
+        self
+    }
+
+    // Path selection used by every sender without its own router
+    pub fn router(mut self, router: Arc<dyn Router>) -> Self {
+        self.router = router;
+        self
+    }
+
+    // Path selection used when this node sends
+    pub fn node_router(mut self, node: &str, router: Arc<dyn Router>) -> Self {
+        self.node_routers.insert(node.to_string(), router);
+        self
+    }
+
+    // Router this node sends with
+    pub(crate) fn router_of(&self, node: &str) -> &Arc<dyn Router> {
+        self.node_routers.get(node).unwrap_or(&self.router)
+    }
+
+    // Have senders pad their routes with decoy hops
+    pub fn decoy_hops(mut self, defense: DecoyHopDefense) -> Self {
+        self.decoy_hops = Some(defense);
+        self
+    }
+
+    // Have honest nodes emit dummy payments alongside real ones
+    pub fn cover_traffic(mut self, defense: CoverTrafficDefense) -> Self {
+        self.cover_traffic = Some(defense);
+        self
+    }
+
+    // Have honest nodes wait a random while before forwarding HTLCs
+    pub fn latency_padding(mut self, defense: LatencyPaddingDefense) -> Self {
+        self.latency_padding = Some(defense);
+        self
+    }
+
+    // Have senders overpay by a random share of the amount
+    pub fn overpayment(mut self, defense: OverpaymentDefense) -> Self {
+        self.overpayment = Some(defense);
+        self
+    }
+
+    // Have senders split payments into shards over routes sharing no intermediary
+    pub fn payment_splitting(mut self, defense: PaymentSplittingDefense) -> Self {
+        self.payment_splitting = Some(defense);
+        self
+    }
+
+    // Stop between payments once this flag is set, e.g. from a Ctrl-C handler
+    pub fn stop_signal(mut self, stop: Arc<AtomicBool>) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    // Draw payments from an RNG seeded with this value
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    // Lock payments with PTLCs, so each hop sees a different point and observers can no
+    // longer match a payment's HTLCs by hash
+    pub fn ptlc(mut self, ptlc: bool) -> Self {
+        self.ptlc = ptlc;
+        self
+    }
+
+    // Let time pass after every payment, with channels re-announcing their policies on
+    // the schedule
+    pub fn gossip(mut self, schedule: GossipSchedule) -> Self {
+        self.gossip = Some(schedule);
+        self
+    }
+
+    // Keep every payment's HTLCs in flight while this many more payments start, so busy
+    // channels run out of HTLC slots
+    pub fn htlc_hold(mut self, payments: u64) -> Self {
+        self.htlc_hold = payments;
+        self
+    }
+
+    // Have senders try payments that fail in flight again under the same invoice, mining
+    // blocks between attempts, until the policy's attempts run out or the invoice expires
+    pub fn retries(mut self, policy: RetryPolicy) -> Self {
+        self.retries = Some(policy);
+        self
+    }
+
+    // Blocks recipients' invoices can be paid for after they're issued
+    pub fn invoice_expiry(mut self, blocks: u32) -> Self {
+        self.invoice_expiry_blocks = blocks;
+        self
+    }
+
+    // How long messages take over each link, which sets how long every node on a route
+    // waits for its HTLC to settle or fail
+    pub fn hop_latency(mut self, latency: HopLatency) -> Self {
+        self.hop_latency = latency;
+        self
+    }
+
+    // Have senders route to the closest of these trampoline nodes and leave it to find the
+    // route on to the recipient
+    pub fn trampolines(mut self, nodes: Vec<String>) -> Self {
+        self.trampolines = nodes;
+        self
+    }
+
+    // Open each of these clients a zero-conf channel from its LSP just before the first
+    // payment to it is routed
+    pub fn jit_channels(mut self, clients: HashMap<String, String>) -> Self {
+        self.jit_channels = clients;
+        self
+    }
+
+    // Time links by the regions of the nodes at their ends, with `hop_latency`'s jitter.
+    // Links to nodes without a region keep its mean.
+    pub fn regions(mut self, regions: RegionLatency) -> Self {
+        self.regions = Some(regions);
+        self
+    }
+
+    pub fn cltv_construction(mut self, construction: CltvConstruction) -> Self {
+        self.cltv_construction = construction;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_amount_distributions_stay_in_range() {
+        let mut rng = rand::rng();
+        let distributions = [
+            AmountDistribution::from_spec("fixed:5000").unwrap(),
+            AmountDistribution::from_spec("uniform:1000-2000").unwrap(),
+            AmountDistribution::from_spec("log-uniform:1000-100000000").unwrap(),
+        ];
+
+        assert_eq!(distributions[0].sample(&mut rng), 5000);
+        for _ in 0..1000 {
+            assert!((1000..2000).contains(&distributions[1].sample(&mut rng)));
+            assert!((1000..=100_000_000).contains(&distributions[2].sample(&mut rng)));
+        }
+
+        // Log-uniform amounts are mostly small: the median sits near the geometric mean
+        let mut samples: Vec<u64> = (0..1001).map(|_| distributions[2].sample(&mut rng)).collect();
+        samples.sort();
+        assert!(samples[500] < 1_000_000);
+
+        assert_eq!(AmountDistribution::from_spec("zipf:1-2"), None);
+        assert_eq!(AmountDistribution::from_spec("uniform:1000"), None);
+    }
+}
@@ -0,0 +1,138 @@
+// Empirical CLTV delta distributions. The timelock heuristic works better the more nodes
+// share a delta, so generated networks are only as telling as their deltas are realistic.
+
+use std::collections::BTreeMap;
+use rand::Rng;
+use serde_json::Value;
+use log::info;
+
+use crate::models::LightningNetworkMap;
+use crate::simulation::topology::parse_describegraph;
+use crate::error::ThelmaError;
+
+// Forwarding deltas with how often each occurs
+#[derive(Debug, Clone)]
+pub struct CltvDeltaDistribution {
+    deltas: Vec<u32>,
+    // Running total of the weights, for sampling by binary search
+    cumulative: Vec<f64>,
+}
+
+impl CltvDeltaDistribution {
+    // Deltas with their weights. Zero deltas and non-positive weights are dropped.
+    pub fn from_weights(weights: impl IntoIterator<Item = (u32, f64)>) -> Result<Self, ThelmaError> {
+        let mut merged: BTreeMap<u32, f64> = BTreeMap::new();
+        for (delta, weight) in weights {
+            if delta > 0 && weight > 0.0 {
+                *merged.entry(delta).or_default() += weight;
+            }
+        }
+        if merged.is_empty() {
+            return Err(ThelmaError::Config("CLTV delta distribution has no positive deltas".to_string()));
+        }
+
+        let mut total = 0.0;
+        let (deltas, cumulative) = merged.into_iter()
+            .map(|(delta, weight)| {
+                total += weight;
+                (delta, total)
+            })
+            .unzip();
+        Ok(CltvDeltaDistribution { deltas, cumulative })
+    }
+
+    // Read a distribution from a file. An LND `describegraph` snapshot (`.json`) contributes
+    // every announced node's delta; anything else is text with one `delta` or
+    // `delta,count` per line, `#` starting comments.
+    pub fn load(path: &str) -> Result<Self, ThelmaError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| ThelmaError::Config(format!("can't read CLTV delta file {}: {}", path, e)))?;
+
+        let distribution = if path.ends_with(".json") {
+            let graph: Value = serde_json::from_str(&contents)?;
+            let (nodes, _) = parse_describegraph(&graph, 0)?;
+            CltvDeltaDistribution::from_weights(nodes.iter().map(|node| (node.cltv_expiry_delta, 1.0)))?
+        } else {
+            CltvDeltaDistribution::parse(&contents)?
+        };
+        info!("Loaded {} distinct CLTV deltas from {} (mean {:.1})", distribution.deltas.len(), path, distribution.mean());
+        Ok(distribution)
+    }
+
+    fn parse(text: &str) -> Result<Self, ThelmaError> {
+        let mut weights = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut fields = line.split(|c: char| c == ',' || c.is_whitespace()).filter(|f| !f.is_empty());
+            let delta = fields.next().and_then(|f| f.parse::<u32>().ok());
+            let weight = match fields.next() {
+                Some(field) => field.parse::<f64>().ok(),
+                None => Some(1.0),
+            };
+            match (delta, weight) {
+                (Some(delta), Some(weight)) => weights.push((delta, weight)),
+                _ => return Err(ThelmaError::Config(format!("line {} of the CLTV delta file isn't `delta[,count]`: {}",
+                                                            i + 1, line))),
+            }
+        }
+        CltvDeltaDistribution::from_weights(weights)
+    }
+
+    pub fn sample(&self, rng: &mut impl Rng) -> u32 {
+        let total = self.cumulative[self.cumulative.len() - 1];
+        let target = rng.random_range(0.0..total);
+        let index = self.cumulative.partition_point(|&running| running <= target);
+        self.deltas[index.min(self.deltas.len() - 1)]
+    }
+
+    pub fn mean(&self) -> f64 {
+        let mut previous = 0.0;
+        let mut sum = 0.0;
+        for (delta, &running) in self.deltas.iter().zip(&self.cumulative) {
+            sum += *delta as f64 * (running - previous);
+            previous = running;
+        }
+        sum / previous
+    }
+
+    // Give every node a delta drawn from the distribution. Nodes are visited in key order,
+    // so a seeded RNG assigns the same deltas every time.
+    pub fn assign(&self, network: &mut LightningNetworkMap, rng: &mut impl Rng) {
+        let mut keys: Vec<String> = network.nodes.keys().cloned().collect();
+        keys.sort();
+        for key in keys {
+            if let Some(node) = network.nodes.get(&key) {
+                let mut node = node.clone();
+                node.cltv_expiry_delta = self.sample(rng);
+                network.add_node(node);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn test_delta_distribution_follows_weights() {
+        let distribution = CltvDeltaDistribution::parse("# delta,count\n40,3\n144 1\n\n18\n0,5\n").unwrap();
+        assert!((distribution.mean() - (40.0 * 3.0 + 144.0 + 18.0) / 5.0).abs() < 1e-9);
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut counts: BTreeMap<u32, usize> = BTreeMap::new();
+        for _ in 0..5000 {
+            *counts.entry(distribution.sample(&mut rng)).or_default() += 1;
+        }
+        assert_eq!(counts.keys().copied().collect::<Vec<_>>(), vec![18, 40, 144]);
+        assert!(counts[&40] > 2 * counts[&144] && counts[&40] > 2 * counts[&18]);
+
+        assert!(CltvDeltaDistribution::parse("40,abc\n").is_err());
+        assert!(CltvDeltaDistribution::parse("# nothing\n").is_err());
+    }
+}
@@ -0,0 +1,24 @@
+// Events published by the payment simulator
+
+use crate::models::{HTLC, HtlcResolution};
+
+// Events buffered per subscriber before slow subscribers start missing them
+pub const DEFAULT_EVENT_CAPACITY: usize = 65_536;
+
+// Something that happened in the simulated network. Observers and defense evaluators
+// subscribe to these instead of being wired into the simulator.
+#[derive(Debug, Clone)]
+pub enum NetworkEvent {
+    // A node on the route received an HTLC, with the expiry it carried at that node
+    HtlcForwarded(HTLC),
+    // A node on the route saw its HTLC settled or failed back
+    HtlcResolved(HtlcResolution),
+    // The recipient accepted the payment
+    PaymentSettled { payment_hash: String },
+    // The sender couldn't find a usable route
+    PaymentFailed { sender: String, receiver: String, reason: String },
+    // The chain moved on
+    BlocksMined { height: u32 },
+    // A channel's policy was re-announced in a `channel_update` at this block
+    ChannelUpdated { channel_id: String, block: u32 },
+}
@@ -0,0 +1,120 @@
+// Fee policies for generated nodes. With every node charging the same, fee-aware routers
+// and fee-based heuristics have nothing to go on, so by default policies are drawn the way
+// mainnet's look: most nodes charge no or the default base fee, a few charge a lot.
+
+use std::f64::consts::PI;
+use rand::Rng;
+
+use crate::models::{LightningNetworkMap, DEFAULT_BASE_FEE_MSAT, DEFAULT_FEE_RATE_PPM};
+
+// Names accepted by `FeeModel::from_name`, in the order they're listed in usage
+pub const FEE_MODEL_NAMES: &[&str] = &["mainnet", "default"];
+
+// Shares of nodes charging no base fee and the implementations' default; the rest charge
+// up to the maximum, spread log-uniformly
+const MAINNET_ZERO_BASE_SHARE: f64 = 0.45;
+const MAINNET_DEFAULT_BASE_SHARE: f64 = 0.35;
+const MAINNET_MAX_BASE_FEE_MSAT: u64 = 20_000;
+// Share of nodes charging no proportional fee; the rest are log-normal around the median
+const MAINNET_ZERO_RATE_SHARE: f64 = 0.15;
+const MAINNET_MEDIAN_RATE_PPM: f64 = 50.0;
+const MAINNET_RATE_SIGMA: f64 = 1.5;
+const MAINNET_MAX_FEE_RATE_PPM: u64 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FeeModel {
+    // Many zero-fee nodes and a heavy tail of expensive ones
+    #[default]
+    Mainnet,
+    // Every node on LND's defaults
+    Default,
+}
+
+impl FeeModel {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "mainnet" => Some(FeeModel::Mainnet),
+            "default" => Some(FeeModel::Default),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FeeModel::Mainnet => "mainnet",
+            FeeModel::Default => "default",
+        }
+    }
+
+    // A node's base fee (msat) and proportional fee (ppm)
+    pub fn sample(&self, rng: &mut impl Rng) -> (u64, u64) {
+        match self {
+            FeeModel::Default => (DEFAULT_BASE_FEE_MSAT, DEFAULT_FEE_RATE_PPM),
+            FeeModel::Mainnet => {
+                let pick = rng.random::<f64>();
+                let base = if pick < MAINNET_ZERO_BASE_SHARE {
+                    0
+                } else if pick < MAINNET_ZERO_BASE_SHARE + MAINNET_DEFAULT_BASE_SHARE {
+                    DEFAULT_BASE_FEE_MSAT
+                } else {
+                    let log_max = (MAINNET_MAX_BASE_FEE_MSAT as f64).ln();
+                    rng.random_range(0.0..=log_max).exp().round() as u64
+                };
+
+                let rate = if rng.random_bool(MAINNET_ZERO_RATE_SHARE) {
+                    0
+                } else {
+                    // Box-Muller for a standard normal draw
+                    let (u1, u2) = (1.0 - rng.random::<f64>(), rng.random::<f64>());
+                    let normal = (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos();
+                    let rate = (MAINNET_MEDIAN_RATE_PPM.ln() + MAINNET_RATE_SIGMA * normal).exp();
+                    (rate.round() as u64).clamp(1, MAINNET_MAX_FEE_RATE_PPM)
+                };
+                (base, rate)
+            }
+        }
+    }
+
+    // Give every node a fee policy, visiting nodes in key order so a seeded RNG assigns the
+    // same policies every time
+    pub fn assign(&self, network: &mut LightningNetworkMap, rng: &mut impl Rng) {
+        let mut keys: Vec<String> = network.nodes.keys().cloned().collect();
+        keys.sort();
+        for key in keys {
+            if let Some(node) = network.nodes.get(&key) {
+                let mut node = node.clone();
+                (node.base_fee_msat, node.fee_rate_ppm) = self.sample(rng);
+                network.add_node(node);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn test_mainnet_fees_are_skewed() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let samples: Vec<(u64, u64)> = (0..10_000).map(|_| FeeModel::Mainnet.sample(&mut rng)).collect();
+        let share = |count: usize| count as f64 / samples.len() as f64;
+
+        assert!((share(samples.iter().filter(|(base, _)| *base == 0).count()) - 0.45).abs() < 0.03);
+        assert!((share(samples.iter().filter(|(_, rate)| *rate == 0).count()) - 0.15).abs() < 0.03);
+        assert!(samples.iter().all(|&(base, rate)| base <= MAINNET_MAX_BASE_FEE_MSAT && rate <= MAINNET_MAX_FEE_RATE_PPM));
+
+        // A heavy tail: the mean rate sits well above the median
+        let mut rates: Vec<u64> = samples.iter().map(|(_, rate)| *rate).filter(|rate| *rate > 0).collect();
+        rates.sort();
+        let median = rates[rates.len() / 2] as f64;
+        let mean = rates.iter().sum::<u64>() as f64 / rates.len() as f64;
+        assert!((35.0..70.0).contains(&median));
+        assert!(mean > 2.0 * median);
+
+        assert_eq!(FeeModel::Default.sample(&mut rng), (DEFAULT_BASE_FEE_MSAT, DEFAULT_FEE_RATE_PPM));
+        assert_eq!(FeeModel::from_name("mainnet"), Some(FeeModel::Mainnet));
+    }
+}
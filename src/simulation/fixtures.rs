@@ -0,0 +1,138 @@
+// Small named networks that come out the same every time: fixed keys, channel ids, deltas and
+// capacities, no randomness at all. Analyzer behaviors can be shown, and regression-tested,
+// on graphs whose every route is known.
+
+use rand::rngs::StdRng;
+
+use crate::models::{Channel, LightningNetworkMap, Node, ShortChannelId};
+use crate::simulation::topology::TopologyGenerator;
+use crate::error::ThelmaError;
+
+pub const FIXTURE_NAMES: &[&str] = &["line", "ring", "star", "diamond", "two-hub"];
+
+// Forwarding delta and capacity (sat) of every fixture node and channel
+const FIXTURE_CLTV_DELTA: u32 = 40;
+const FIXTURE_CHANNEL_SAT: u64 = 1_000_000;
+// Fixture channels were all opened this many blocks before the network's height
+const FIXTURE_CHANNEL_AGE: u32 = 1_000;
+
+// a - b - c - d - e
+pub fn line(network: &mut LightningNetworkMap) {
+    build(network, &["a", "b", "c", "d", "e"], &[("a", "b"), ("b", "c"), ("c", "d"), ("d", "e")]);
+}
+
+// a - b - c - d - e - f - a
+pub fn ring(network: &mut LightningNetworkMap) {
+    build(network, &["a", "b", "c", "d", "e", "f"],
+          &[("a", "b"), ("b", "c"), ("c", "d"), ("d", "e"), ("e", "f"), ("f", "a")]);
+}
+
+// hub with leaves a to e
+pub fn star(network: &mut LightningNetworkMap) {
+    build(network, &["hub", "a", "b", "c", "d", "e"],
+          &[("hub", "a"), ("hub", "b"), ("hub", "c"), ("hub", "d"), ("hub", "e")]);
+}
+
+// s reaches r over a or b, two routes of the same length
+pub fn diamond(network: &mut LightningNetworkMap) {
+    build(network, &["s", "a", "b", "r"], &[("s", "a"), ("s", "b"), ("a", "r"), ("b", "r")]);
+}
+
+// hub1 serves a to c and hub2 d to f, the hubs joined by one channel
+pub fn two_hub(network: &mut LightningNetworkMap) {
+    build(network, &["hub1", "hub2", "a", "b", "c", "d", "e", "f"],
+          &[("hub1", "hub2"), ("hub1", "a"), ("hub1", "b"), ("hub1", "c"),
+            ("hub2", "d"), ("hub2", "e"), ("hub2", "f")]);
+}
+
+// Add the named fixture to the network
+pub fn build_fixture(name: &str, network: &mut LightningNetworkMap) -> Result<(), ThelmaError> {
+    match name {
+        "line" => line(network),
+        "ring" => ring(network),
+        "star" => star(network),
+        "diamond" => diamond(network),
+        "two-hub" => two_hub(network),
+        _ => return Err(ThelmaError::Config(format!("unknown fixture '{}' (expected one of: {})",
+                                                    name, FIXTURE_NAMES.join(", ")))),
+    }
+    Ok(())
+}
+
+// The named fixture on its own, at block 700000
+pub fn fixture(name: &str) -> Result<LightningNetworkMap, ThelmaError> {
+    let mut network = LightningNetworkMap::new(700000);
+    build_fixture(name, &mut network)?;
+    Ok(network)
+}
+
+fn build(network: &mut LightningNetworkMap, nodes: &[&str], channels: &[(&str, &str)]) {
+    for key in nodes {
+        network.add_node(Node::new(key, key, FIXTURE_CLTV_DELTA));
+    }
+    let block = network.current_block_height.saturating_sub(FIXTURE_CHANNEL_AGE);
+    for (i, (node1, node2)) in channels.iter().enumerate() {
+        let id = ShortChannelId::new(block, i as u32 + 1, 0).to_string();
+        network.add_channel(Channel::new(&id, node1, node2, FIXTURE_CHANNEL_SAT));
+    }
+}
+
+// A fixture as a topology, so runs can use one in place of a generated network. The node
+// count is ignored.
+pub struct FixtureTopology {
+    fixture: String,
+}
+
+impl FixtureTopology {
+    pub fn new(fixture: &str) -> Self {
+        FixtureTopology { fixture: fixture.to_string() }
+    }
+}
+
+impl TopologyGenerator for FixtureTopology {
+    fn name(&self) -> &'static str {
+        "fixture"
+    }
+
+    fn generate(&mut self, network: &mut LightningNetworkMap, _node_count: usize, _rng: &mut StdRng) -> Result<(), ThelmaError> {
+        build_fixture(&self.fixture, network)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, RwLock};
+    use crate::models::HTLC;
+    use crate::surveillance::HTLCAnalyzer;
+
+    #[test]
+    fn test_fixtures_are_fixed() {
+        let sizes: Vec<(usize, usize)> = FIXTURE_NAMES.iter()
+            .map(|name| {
+                let network = fixture(name).unwrap();
+                (network.nodes.len(), network.channels.len())
+            })
+            .collect();
+        assert_eq!(sizes, vec![(5, 4), (6, 6), (6, 5), (4, 4), (8, 7)]);
+
+        let ids = |network: &LightningNetworkMap| network.channels.iter().map(|c| c.channel_id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids(&fixture("two-hub").unwrap()), ids(&fixture("two-hub").unwrap()));
+        assert_eq!(fixture("star").unwrap().degree("hub"), 5);
+        assert!(fixture("hypercube").is_err());
+    }
+
+    #[test]
+    fn test_line_hides_the_direction() {
+        // Seen at b with its own delta and a final delta left, the recipient is one hop on,
+        // and nothing says which way the payment was heading
+        let network_map = Arc::new(RwLock::new(fixture("line").unwrap()));
+        let analyzer = HTLCAnalyzer::new(network_map);
+        let htlc = HTLC::new("p", 700000 + 2 * FIXTURE_CLTV_DELTA, 100_000, 700000, "b");
+        let recipients = analyzer.analyze_htlc(&htlc);
+        let mut nodes: Vec<&str> = recipients.iter().map(|r| r.node_id.as_str()).collect();
+        nodes.sort();
+        assert_eq!(nodes, vec!["a", "c"]);
+        assert_eq!(recipients[0].confidence_score, recipients[1].confidence_score);
+    }
+}
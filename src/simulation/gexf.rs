@@ -0,0 +1,246 @@
+// Dynamic GEXF export of a run, so Gephi's timeline can animate the traffic each node
+// handled and the channels that opened and closed
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use log::info;
+
+use crate::models::{HTLC, LightningNetworkMap};
+use crate::simulation::observer::Observer;
+use crate::error::{ThelmaError, read_lock};
+
+// When a node or channel existed, plus how much traffic it carried during each payment
+struct Timeline {
+    start: u64,
+    end: Option<u64>,
+    traffic: BTreeMap<u64, u32>,
+}
+
+impl Timeline {
+    fn starting(at: u64) -> Self {
+        Timeline { start: at, end: None, traffic: BTreeMap::new() }
+    }
+}
+
+// Watches the traffic and turns it into a GEXF graph in dynamic mode. Time is the same
+// virtual clock traces use: payment t happens during [t, t+1), so the timeline steps
+// through payments in the order they finished.
+pub struct GexfRecorder {
+    network: Arc<RwLock<LightningNetworkMap>>,
+    malicious: HashSet<String>,
+    clock: u64,
+    // Topology version last synced, so churn is only looked for when the graph changed
+    topology_version: u64,
+    nodes: BTreeMap<String, Timeline>,
+    channels: BTreeMap<String, Timeline>,
+    // Last hop each in-flight payment reached, to tell which channel the next one used
+    last_hop: HashMap<String, String>,
+}
+
+impl GexfRecorder {
+    pub fn new(network: Arc<RwLock<LightningNetworkMap>>, malicious: &[String]) -> Self {
+        let mut recorder = GexfRecorder {
+            network,
+            malicious: malicious.iter().cloned().collect(),
+            clock: 0,
+            topology_version: 0,
+            nodes: BTreeMap::new(),
+            channels: BTreeMap::new(),
+            last_hop: HashMap::new(),
+        };
+        recorder.sync_topology();
+        recorder
+    }
+
+    // Open timelines for nodes and channels that appeared and close those that went away
+    fn sync_topology(&mut self) {
+        let network = read_lock(&self.network);
+        if !self.nodes.is_empty() && network.topology_version() == self.topology_version {
+            return;
+        }
+        self.topology_version = network.topology_version();
+
+        let clock = self.clock;
+        sync_timelines(&mut self.nodes, network.nodes.keys(), clock);
+        sync_timelines(&mut self.channels, network.channels.iter().map(|c| &c.channel_id), clock);
+    }
+
+    fn payment_finished(&mut self) {
+        self.clock += 1;
+        self.sync_topology();
+    }
+
+    pub fn generate_gexf(&self) -> String {
+        let network = read_lock(&self.network);
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str("<gexf xmlns=\"http://gexf.net/1.3\" version=\"1.3\">\n");
+        xml.push_str("  <meta>\n    <creator>THELMA</creator>\n");
+        xml.push_str(&format!("    <description>{} payments over {} nodes; time counts finished payments</description>\n",
+                              self.clock, self.nodes.len()));
+        xml.push_str("  </meta>\n");
+        xml.push_str(&format!("  <graph mode=\"dynamic\" defaultedgetype=\"undirected\" timeformat=\"double\" \
+                               timerepresentation=\"interval\" start=\"0\" end=\"{}\">\n", self.clock));
+
+        xml.push_str("    <attributes class=\"node\" mode=\"static\">\n");
+        xml.push_str("      <attribute id=\"malicious\" title=\"malicious\" type=\"boolean\"/>\n");
+        xml.push_str("      <attribute id=\"cltv_expiry_delta\" title=\"cltv_expiry_delta\" type=\"integer\"/>\n");
+        xml.push_str("    </attributes>\n");
+        xml.push_str("    <attributes class=\"node\" mode=\"dynamic\">\n");
+        xml.push_str("      <attribute id=\"htlcs\" title=\"htlcs\" type=\"integer\"/>\n");
+        xml.push_str("    </attributes>\n");
+        xml.push_str("    <attributes class=\"edge\" mode=\"static\">\n");
+        xml.push_str("      <attribute id=\"capacity\" title=\"capacity\" type=\"long\"/>\n");
+        xml.push_str("    </attributes>\n");
+        xml.push_str("    <attributes class=\"edge\" mode=\"dynamic\">\n");
+        xml.push_str("      <attribute id=\"payments\" title=\"payments\" type=\"integer\"/>\n");
+        xml.push_str("    </attributes>\n");
+
+        xml.push_str("    <nodes>\n");
+        for (key, timeline) in &self.nodes {
+            let node = network.nodes.get(key);
+            let label = node.map(|n| n.alias.as_str()).unwrap_or(key);
+            xml.push_str(&format!("      <node id=\"{}\" label=\"{}\"{}>\n",
+                                  escape(key), escape(label), interval(timeline)));
+            xml.push_str("        <attvalues>\n");
+            xml.push_str(&format!("          <attvalue for=\"malicious\" value=\"{}\"/>\n",
+                                  self.malicious.contains(key)));
+            if let Some(node) = node {
+                xml.push_str(&format!("          <attvalue for=\"cltv_expiry_delta\" value=\"{}\"/>\n",
+                                      node.cltv_expiry_delta));
+            }
+            push_traffic(&mut xml, "htlcs", timeline);
+            xml.push_str("        </attvalues>\n      </node>\n");
+        }
+        xml.push_str("    </nodes>\n");
+
+        // Closed channels are gone from the map, so they're written without their ends
+        // and capacity rather than dropped
+        let open: HashMap<&str, (&str, &str, u64)> = network.channels.iter()
+            .map(|c| (c.channel_id.as_str(), (c.node1.as_str(), c.node2.as_str(), c.capacity)))
+            .collect();
+        xml.push_str("    <edges>\n");
+        for (id, timeline) in &self.channels {
+            let Some(&(source, target, capacity)) = open.get(id.as_str()) else { continue };
+            xml.push_str(&format!("      <edge id=\"{}\" source=\"{}\" target=\"{}\"{}>\n",
+                                  escape(id), escape(source), escape(target), interval(timeline)));
+            xml.push_str("        <attvalues>\n");
+            xml.push_str(&format!("          <attvalue for=\"capacity\" value=\"{}\"/>\n", capacity));
+            push_traffic(&mut xml, "payments", timeline);
+            xml.push_str("        </attvalues>\n      </edge>\n");
+        }
+        xml.push_str("    </edges>\n  </graph>\n</gexf>\n");
+        xml
+    }
+
+    pub fn save_to_file(&self, filename: &str) -> Result<(), ThelmaError> {
+        std::fs::write(filename, self.generate_gexf())?;
+        info!("GEXF timeline saved to {}", filename);
+        Ok(())
+    }
+}
+
+impl Observer for GexfRecorder {
+    fn on_htlc_forward(&mut self, htlc: &HTLC) -> Result<(), ThelmaError> {
+        let node = &htlc.observed_by_node;
+        if let Some(timeline) = self.nodes.get_mut(node) {
+            *timeline.traffic.entry(self.clock).or_default() += 1;
+        }
+
+        // HTLCs of one payment arrive hop by hop, so the previous hop names the channel
+        if let Some(previous) = self.last_hop.insert(htlc.payment_hash.clone(), node.clone()) {
+            let network = read_lock(&self.network);
+            let channel = network.channels_between(&previous, node).into_iter()
+                .map(|c| c.channel_id.as_str())
+                .find(|id| self.channels.get(*id).is_some_and(|t| t.end.is_none()));
+            if let Some(timeline) = channel.and_then(|id| self.channels.get_mut(id)) {
+                *timeline.traffic.entry(self.clock).or_default() += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn on_settle(&mut self, payment_hash: &str) -> Result<(), ThelmaError> {
+        self.last_hop.remove(payment_hash);
+        self.payment_finished();
+        Ok(())
+    }
+
+    fn on_fail(&mut self, _sender: &str, _receiver: &str, _reason: &str) -> Result<(), ThelmaError> {
+        self.payment_finished();
+        Ok(())
+    }
+}
+
+fn sync_timelines<'a>(timelines: &mut BTreeMap<String, Timeline>,
+                      present: impl Iterator<Item = &'a String>,
+                      clock: u64) {
+    let present: HashSet<&String> = present.collect();
+    for (key, timeline) in timelines.iter_mut() {
+        if timeline.end.is_none() && !present.contains(key) {
+            timeline.end = Some(clock);
+        }
+    }
+    for key in present {
+        // A channel reopened under the same id keeps its original start
+        let timeline = timelines.entry(key.clone()).or_insert_with(|| Timeline::starting(clock));
+        timeline.end = None;
+    }
+}
+
+fn interval(timeline: &Timeline) -> String {
+    match timeline.end {
+        Some(end) => format!(" start=\"{}\" end=\"{}\"", timeline.start, end),
+        None => format!(" start=\"{}\"", timeline.start),
+    }
+}
+
+fn push_traffic(xml: &mut String, attribute: &str, timeline: &Timeline) {
+    for (at, count) in &timeline.traffic {
+        xml.push_str(&format!("          <attvalue for=\"{}\" value=\"{}\" start=\"{}\" end=\"{}\"/>\n",
+                              attribute, count, at, at + 1));
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Channel, Node};
+
+    #[test]
+    fn test_gexf_tracks_traffic_and_churn_over_time() {
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["a", "b", "c"] {
+            network.add_node(Node::new(key, &format!("<{}>", key), 40));
+        }
+        network.add_channel(Channel::new("ab", "a", "b", 1_000_000));
+        network.add_channel(Channel::new("bc", "b", "c", 1_000_000));
+        let network = Arc::new(RwLock::new(network));
+        let mut recorder = GexfRecorder::new(network.clone(), &["b".to_string()]);
+
+        // Payment 0 fails, payment 1 goes a -> b -> c
+        recorder.on_fail("a", "c", "no route").unwrap();
+        for node in ["a", "b", "c"] {
+            recorder.on_htlc_forward(&HTLC::new("p1", 700100, 1000, 700000, node)).unwrap();
+        }
+        recorder.on_settle("p1").unwrap();
+        crate::error::write_lock(&network).add_channel(Channel::new("ac", "a", "c", 500_000));
+        recorder.on_settle("p2").unwrap();
+
+        let gexf = recorder.generate_gexf();
+        assert!(gexf.contains("mode=\"dynamic\""));
+        assert!(gexf.contains("end=\"3\">"));
+        assert!(gexf.contains("<node id=\"b\" label=\"&lt;b&gt;\" start=\"0\">"));
+        assert!(gexf.contains("<attvalue for=\"malicious\" value=\"true\"/>"));
+        assert!(gexf.contains("<attvalue for=\"htlcs\" value=\"1\" start=\"1\" end=\"2\"/>"));
+        assert_eq!(gexf.matches("<attvalue for=\"payments\" value=\"1\" start=\"1\" end=\"2\"/>").count(), 2);
+        // The channel opened after payment 2 starts there
+        assert!(gexf.contains("<edge id=\"ac\" source=\"a\" target=\"c\" start=\"3\">"));
+    }
+}
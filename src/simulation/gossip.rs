@@ -0,0 +1,55 @@
+// Channel gossip: every channel re-announces its policy with a `channel_update` now and
+// then, while the chain moves on between payments. Who updated when is public, so it's
+// something the adversary can weigh routes by.
+
+use rand::Rng;
+
+use crate::models::LightningNetworkMap;
+
+// Blocks between a channel's updates on average, about a week
+pub const DEFAULT_GOSSIP_REFRESH_BLOCKS: u32 = 1008;
+// Blocks mined between two payments, about an hour
+pub const DEFAULT_GOSSIP_BLOCKS_PER_PAYMENT: u32 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GossipSchedule {
+    pub refresh_blocks: u32,
+    pub blocks_per_payment: u32,
+}
+
+impl Default for GossipSchedule {
+    fn default() -> Self {
+        GossipSchedule::new(DEFAULT_GOSSIP_REFRESH_BLOCKS)
+    }
+}
+
+impl GossipSchedule {
+    pub fn new(refresh_blocks: u32) -> Self {
+        GossipSchedule {
+            refresh_blocks: refresh_blocks.max(1),
+            blocks_per_payment: DEFAULT_GOSSIP_BLOCKS_PER_PAYMENT,
+        }
+    }
+
+    pub fn blocks_per_payment(mut self, blocks: u32) -> Self {
+        self.blocks_per_payment = blocks;
+        self
+    }
+
+    // Date every channel's last update back by an exponential age, as if updates had been
+    // arriving at this rate all along. Channels are visited in order, so a seeded RNG
+    // assigns the same ages every time.
+    pub fn assign_ages(&self, network: &mut LightningNetworkMap, rng: &mut impl Rng) {
+        let height = network.current_block_height;
+        for channel in &mut network.channels {
+            let age = -(1.0 - rng.random::<f64>()).ln() * self.refresh_blocks as f64;
+            channel.last_update = Some(height.saturating_sub(age.round() as u32));
+        }
+    }
+
+    // Indices of the channels that send an update while `blocks` blocks pass
+    pub fn updates(&self, network: &LightningNetworkMap, blocks: u32, rng: &mut impl Rng) -> Vec<usize> {
+        let chance = (blocks as f64 / self.refresh_blocks as f64).min(1.0);
+        (0..network.channels.len()).filter(|_| rng.random_bool(chance)).collect()
+    }
+}
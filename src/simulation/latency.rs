@@ -0,0 +1,89 @@
+// Time an HTLC takes to cross a link, so nodes on a route see their HTLCs resolve after a
+// delay that grows with how far the payment still had to go. Settling or failing an HTLC
+// takes it back over every link it was forwarded over.
+
+use rand::Rng;
+
+// Milliseconds a message takes over one link on average
+pub const DEFAULT_HOP_LATENCY_MS: u64 = 100;
+// How far a single crossing strays from the mean, as a share of it either way
+pub const DEFAULT_HOP_JITTER: f64 = 0.5;
+// Links to Tor-only nodes go through circuits: several times slower and far less steady
+pub const TOR_LATENCY_FACTOR: u64 = 5;
+pub const TOR_HOP_JITTER: f64 = 0.9;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HopLatency {
+    pub mean_ms: u64,
+    pub jitter: f64,
+}
+
+impl Default for HopLatency {
+    fn default() -> Self {
+        HopLatency::new(DEFAULT_HOP_LATENCY_MS)
+    }
+}
+
+impl HopLatency {
+    pub fn new(mean_ms: u64) -> Self {
+        HopLatency { mean_ms, jitter: DEFAULT_HOP_JITTER }
+    }
+
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    // The same link when one end is reachable only over Tor
+    pub fn over_tor(&self) -> Self {
+        HopLatency { mean_ms: self.mean_ms * TOR_LATENCY_FACTOR, jitter: TOR_HOP_JITTER }
+    }
+
+    // One crossing, uniform within the jitter around the mean
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> u64 {
+        let spread = self.mean_ms as f64 * self.jitter;
+        if spread <= 0.0 {
+            return self.mean_ms;
+        }
+        (self.mean_ms as f64 + rng.random_range(-spread..=spread)).round() as u64
+    }
+
+    // How long each of `nodes` holds its HTLC when the last of them resolves it: the time
+    // for the HTLC to reach that node and for the preimage or failure to come back. The
+    // last node resolves its own at once.
+    pub fn hold_times<R: Rng>(&self, nodes: usize, rng: &mut R) -> Vec<u64> {
+        HopLatency::hold_times_over(&vec![*self; nodes.saturating_sub(1)], rng)
+    }
+
+    // The same over links of their own latencies, the first between the first two nodes
+    pub fn hold_times_over<R: Rng>(links: &[HopLatency], rng: &mut R) -> Vec<u64> {
+        let mut holds = vec![0; links.len() + 1];
+        for (i, link) in links.iter().enumerate().rev() {
+            holds[i] = holds[i + 1] + link.sample(rng) + link.sample(rng);
+        }
+        holds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn test_holds_grow_towards_the_sender() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let holds = HopLatency::new(100).hold_times(4, &mut rng);
+        assert_eq!(holds[3], 0);
+        assert!(holds.windows(2).all(|pair| pair[0] - pair[1] >= 100 && pair[0] - pair[1] <= 300));
+
+        let fixed = HopLatency::new(100).jitter(0.0);
+        assert_eq!(fixed.hold_times(3, &mut rng), vec![400, 200, 0]);
+
+        // A Tor link takes five times as long on average
+        let tor = fixed.over_tor().jitter(0.0);
+        assert_eq!(HopLatency::hold_times_over(&[fixed, tor], &mut rng), vec![1200, 1000, 0]);
+        assert_eq!(HopLatency::hold_times_over(&[], &mut rng), vec![0]);
+    }
+}
@@ -0,0 +1,113 @@
+// Mobile-wallet clients: leaf nodes whose only channel goes to a Lightning Service Provider.
+// Some get that channel just in time, opened zero-conf by the LSP when they are first paid,
+// so until then nobody can pay them and they can't pay anyone.
+
+use std::collections::HashMap;
+use rand::Rng;
+use rand::seq::IndexedRandom;
+use log::info;
+
+use crate::models::{Channel, LightningNetworkMap, Node};
+use crate::simulation::topology::new_short_channel_id;
+
+// LSPs clients are spread over, when not told otherwise
+pub const DEFAULT_LSP_COUNT: usize = 3;
+// Capacity (sat) of a client's channel opened up front
+pub const CLIENT_CHANNEL_SAT: u64 = 500_000;
+// Smallest channel (sat) an LSP opens just in time; larger payments get twice their amount
+pub const JIT_CHANNEL_MIN_SAT: u64 = 100_000;
+// Forwarding delta clients announce; they never forward
+const CLIENT_CLTV_DELTA: u32 = 40;
+
+#[derive(Debug, Clone, Default)]
+pub struct LspClients {
+    pub lsps: Vec<String>,
+    // Every client with its LSP
+    pub clients: Vec<(String, String)>,
+    // Clients whose channel opens the first time they are paid, with their LSP
+    pub jit_clients: HashMap<String, String>,
+}
+
+impl LspClients {
+    // Add `count` clients, each with one channel to an LSP picked at random. A `jit_share`
+    // of them get no channel until they are paid.
+    pub fn attach(network: &mut LightningNetworkMap,
+                  lsps: Vec<String>,
+                  count: usize,
+                  jit_share: f64,
+                  rng: &mut impl Rng) -> Self {
+        let mut clients = LspClients { lsps, ..LspClients::default() };
+        if clients.lsps.is_empty() {
+            return clients;
+        }
+        for i in 1..=count {
+            let client = format!("client{}", i);
+            let Some(lsp) = clients.lsps.choose(rng).cloned() else { break };
+            network.add_node(Node::new(&client, &format!("Wallet {}", i), CLIENT_CLTV_DELTA));
+            if rng.random_bool(jit_share.clamp(0.0, 1.0)) {
+                clients.jit_clients.insert(client.clone(), lsp.clone());
+            } else {
+                let id = new_short_channel_id(network, rng);
+                network.add_channel(Channel::new(&id, &lsp, &client, CLIENT_CHANNEL_SAT));
+            }
+            clients.clients.push((client, lsp));
+        }
+        info!("Attached {} wallet clients to {} LSPs, {} of them on just-in-time channels",
+              clients.clients.len(), clients.lsps.len(), clients.jit_clients.len());
+        clients
+    }
+
+    pub fn is_client(&self, node: &str) -> bool {
+        self.clients.iter().any(|(client, _)| client == node)
+    }
+
+    // Just-in-time clients whose channel has been opened
+    pub fn jit_channels_opened(&self, network: &LightningNetworkMap) -> usize {
+        self.jit_clients.keys().filter(|client| network.degree(client) > 0).count()
+    }
+}
+
+// Open a client's channel from its LSP, zero-conf, sized for the payment about to reach it.
+// Returns whether a channel was opened.
+pub fn open_jit_channel(network: &mut LightningNetworkMap,
+                        client: &str,
+                        lsp: &str,
+                        amount_msat: u64,
+                        rng: &mut impl Rng) -> bool {
+    if network.degree(client) > 0 || !network.nodes.contains_key(lsp) {
+        return false;
+    }
+    let capacity = (amount_msat / 1000 * 2).max(JIT_CHANNEL_MIN_SAT);
+    let id = new_short_channel_id(network, rng);
+    network.add_channel(Channel::new(&id, lsp, client, capacity));
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clients_hang_off_their_lsp() {
+        let mut rng = rand::rng();
+        let mut network = LightningNetworkMap::new(700000);
+        network.add_node(Node::new("lsp", "lsp", 40));
+        let clients = LspClients::attach(&mut network, vec!["lsp".to_string()], 4, 0.0, &mut rng);
+
+        assert_eq!(clients.clients.len(), 4);
+        assert!(clients.is_client("client3") && !clients.is_client("lsp"));
+        assert_eq!(network.degree("lsp"), 4);
+        assert!(clients.clients.iter().all(|(client, lsp)| network.degree(client) == 1 && lsp == "lsp"));
+
+        // Just-in-time clients have no channel until the first payment reaches them
+        let mut network = LightningNetworkMap::new(700000);
+        network.add_node(Node::new("lsp", "lsp", 40));
+        let jit = LspClients::attach(&mut network, vec!["lsp".to_string()], 1, 1.0, &mut rng);
+        let (client, lsp) = &jit.clients[0];
+        assert_eq!((network.degree(client), jit.jit_channels_opened(&network)), (0, 0));
+        assert!(open_jit_channel(&mut network, client, lsp, 300_000_000, &mut rng));
+        assert!(!open_jit_channel(&mut network, client, lsp, 1_000, &mut rng));
+        assert_eq!(jit.jit_channels_opened(&network), 1);
+        assert!(network.can_carry(lsp, client, 300_000_000));
+    }
+}
@@ -1,7 +1,40 @@
+pub mod config;
+pub mod deltas;
+pub mod events;
+pub mod fees;
+pub mod fixtures;
+pub mod gexf;
+pub mod gossip;
+pub mod latency;
+pub mod lsp;
 pub mod network_generator;
+pub mod observer;
 pub mod payment_simulator;
+pub mod regions;
+pub mod retry;
+pub mod router;
+pub mod slots;
+pub mod topology;
+pub mod trace;
 pub mod utils;
 
+pub use config::{AmountDistribution, CltvConstruction, SimulatorConfig, CLTV_CONSTRUCTION_NAMES};
+pub use deltas::CltvDeltaDistribution;
+pub use events::NetworkEvent;
+pub use fees::{FeeModel, FEE_MODEL_NAMES};
+pub use fixtures::{FixtureTopology, build_fixture, fixture, FIXTURE_NAMES};
+pub use gexf::GexfRecorder;
+pub use gossip::GossipSchedule;
+pub use latency::{HopLatency, DEFAULT_HOP_LATENCY_MS};
+pub use lsp::{LspClients, DEFAULT_LSP_COUNT};
 pub use network_generator::NetworkGenerator;
+pub use observer::Observer;
 pub use payment_simulator::PaymentSimulator;
-pub use utils::{generate_random_path, generate_randomized_path, find_all_paths};
\ No newline at end of file
+pub use payment_simulator::PaymentRecord;
+pub use regions::{RegionLatency, WORLD_REGIONS};
+pub use retry::RetryPolicy;
+pub use router::{Router, Route, router_from_name, ROUTER_NAMES};
+pub use slots::{HtlcSlots, Reservation};
+pub use topology::{TopologyGenerator, topology_from_name, TOPOLOGY_NAMES};
+pub use trace::{Trace, TraceRecorder, TracedEvent};
+pub use utils::{generate_random_path, generate_randomized_path, find_all_paths, find_path_avoiding};
\ No newline at end of file
@@ -1,192 +1,308 @@
 // Helper for generating test Lightning Networks
 
-use std::sync::{Arc, Mutex};
-use std::error::Error;
-use rand::Rng;
-
-use crate::models::{Node, Channel, LightningNetworkMap};
+use std::sync::{Arc, RwLock};
+use log::{info, warn};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use tracing::info_span;
+
+use crate::models::{Channel, ChannelFees, ChannelStatus, LightningNetworkMap, ShadowOffset, ShadowOffsetMix};
+use crate::graph::CentralityMeasure;
+use crate::simulation::deltas::CltvDeltaDistribution;
+use crate::simulation::fees::FeeModel;
+use crate::simulation::gossip::GossipSchedule;
+use crate::simulation::regions::RegionLatency;
+use crate::simulation::topology::{new_short_channel_id, ScaleFreeTopology, SimpleTopology, TopologyGenerator};
+use crate::surveillance::{AdversaryStrategy, CentralPlacement, RandomPlacement};
+use crate::error::{ThelmaError, read_lock, write_lock};
 
 // Network generator for simulations
 pub struct NetworkGenerator {
-    pub rng: rand::rngs::ThreadRng,
+    // Every topology draws from this, so a seeded generator builds the same network each time
+    pub rng: StdRng,
+    // Forwarding deltas generated nodes are given in place of the topology's own
+    cltv_deltas: Option<CltvDeltaDistribution>,
+    // Fee policies generated nodes are given
+    fees: FeeModel,
+    // Shadow offset profiles spread over all nodes, then set for particular ones
+    shadow_offsets: Option<ShadowOffsetMix>,
+    node_shadow_offsets: Vec<(String, ShadowOffset)>,
+    // Share of generated channels that get a parallel channel with its own fees
+    parallel_share: f64,
+    // Shares of generated channels that are disabled and that are zombies
+    disabled_share: f64,
+    zombie_share: f64,
+    // Gossip generated channels are dated back by, if any
+    gossip: Option<GossipSchedule>,
+    // HTLCs each side of every channel accepts in flight, when not the protocol's maximum
+    max_accepted_htlcs: Option<u16>,
+    // Share of channels whose operators allow no dust exposure at all
+    dust_refusing_share: f64,
+    // Share of generated nodes reachable only over Tor
+    tor_share: f64,
+    // Regions nodes are spread over, if geography is modeled
+    regions: Option<RegionLatency>,
+}
+
+impl Default for NetworkGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl NetworkGenerator {
     pub fn new() -> Self {
         NetworkGenerator {
-            rng: rand::rng(),
+            rng: StdRng::from_rng(&mut rand::rng()),
+            cltv_deltas: None,
+            fees: FeeModel::default(),
+            shadow_offsets: None,
+            node_shadow_offsets: Vec::new(),
+            parallel_share: 0.0,
+            disabled_share: 0.0,
+            zombie_share: 0.0,
+            gossip: None,
+            max_accepted_htlcs: None,
+            dust_refusing_share: 0.0,
+            tor_share: 0.0,
+            regions: None,
         }
     }
 
-    // Create a simple test network with specified number of nodes
-    pub fn create_simple_network(&mut self,
-                                 network_map: Arc<Mutex<LightningNetworkMap>>,
-                                 node_count: usize) -> Result<(), Box<dyn Error>> {
-        let mut network = network_map.lock().unwrap();
-
-        // Add nodes with reasonable CLTV deltas
-        for i in 0..node_count {
-            // Generate a random CLTV delta between 14 and 50
-            let cltv_delta = if i % 5 == 0 {
-                // Every 5th node has standard delta of 40
-                40
-            } else {
-                self.rng.random_range(14..=50)
-            };
-
-            let node = Node::new(
-                &format!("node{}", i+1),
-                &format!("Node {}", i+1),
-                cltv_delta
-            );
-
-            network.add_node(node);
+    pub fn seeded(seed: u64) -> Self {
+        NetworkGenerator {
+            rng: StdRng::seed_from_u64(seed),
+            cltv_deltas: None,
+            fees: FeeModel::default(),
+            shadow_offsets: None,
+            node_shadow_offsets: Vec::new(),
+            parallel_share: 0.0,
+            disabled_share: 0.0,
+            zombie_share: 0.0,
+            gossip: None,
+            max_accepted_htlcs: None,
+            dust_refusing_share: 0.0,
+            tor_share: 0.0,
+            regions: None,
         }
+    }
 
-        println!("Created {} nodes", node_count);
+    // Draw generated nodes' CLTV deltas from an empirical distribution. Imported graphs
+    // keep the deltas they were announced with.
+    pub fn cltv_deltas(mut self, distribution: CltvDeltaDistribution) -> Self {
+        self.cltv_deltas = Some(distribution);
+        self
+    }
 
-        // Create a connected ring topology to ensure reachability
-        for i in 0..node_count {
-            let channel = Channel::new(
-                &format!("chan{}", i+1),
-                &format!("node{}", i+1),
-                &format!("node{}", (i+1) % node_count + 1),
-                1_000_000 + self.rng.random_range(0..5_000_000)
-            );
+    // How generated nodes' fee policies are drawn. Imported graphs keep their announced fees.
+    pub fn fees(mut self, fees: FeeModel) -> Self {
+        self.fees = fees;
+        self
+    }
 
-            network.add_channel(channel);
-        }
+    // Let each side of every channel hold at most this many HTLCs in flight. Gossip doesn't
+    // carry the limit, so it applies to imported channels too.
+    pub fn max_accepted_htlcs(mut self, max_accepted_htlcs: u16) -> Self {
+        self.max_accepted_htlcs = Some(max_accepted_htlcs);
+        self
+    }
 
-        // Add some random cross connections for a more realistic network
-        let extra_channels = node_count / 2;
-        for i in 0..extra_channels {
-            let node1 = self.rng.random_range(1..=node_count);
-            let mut node2 = self.rng.random_range(1..=node_count);
+    // Have this share of the channels refuse HTLCs below their dust limit. Like the HTLC
+    // limit it isn't gossiped, so imported channels get it too.
+    pub fn refuse_dust(mut self, share: f64) -> Self {
+        self.dust_refusing_share = share.clamp(0.0, 1.0);
+        self
+    }
 
-            // Ensure we don't connect a node to itself
-            while node1 == node2 {
-                node2 = self.rng.random_range(1..=node_count);
-            }
+    // Make this share of the generated nodes Tor-only. Imported graphs keep the addresses
+    // their nodes announced.
+    pub fn tor_only(mut self, share: f64) -> Self {
+        self.tor_share = share.clamp(0.0, 1.0);
+        self
+    }
 
-            let channel = Channel::new(
-                &format!("xchan{}", i+1),
-                &format!("node{}", node1),
-                &format!("node{}", node2),
-                500_000 + self.rng.random_range(0..3_000_000)
-            );
+    // Put every node, imported ones too, in a region drawn by the regions' shares
+    pub fn regions(mut self, regions: RegionLatency) -> Self {
+        self.regions = Some(regions);
+        self
+    }
 
-            network.add_channel(channel);
-        }
+    // Give this share of the generated channels a second channel between the same nodes,
+    // with a capacity and fees of its own
+    pub fn parallel_channels(mut self, share: f64) -> Self {
+        self.parallel_share = share.clamp(0.0, 1.0);
+        self
+    }
 
-        println!("Created {} channels", node_count + extra_channels);
+    // Leave shares of the generated channels disabled or as zombies, which stay in the
+    // graph but never route. Imported graphs keep the statuses they were announced with.
+    pub fn inactive_channels(mut self, disabled_share: f64, zombie_share: f64) -> Self {
+        self.disabled_share = disabled_share.clamp(0.0, 1.0);
+        self.zombie_share = zombie_share.clamp(0.0, 1.0);
+        self
+    }
 
-        Ok(())
+    // Date generated channels' last `channel_update` back as if they had been gossiping on
+    // this schedule. Imported graphs keep the update times of their snapshot.
+    pub fn gossip(mut self, schedule: GossipSchedule) -> Self {
+        self.gossip = Some(schedule);
+        self
     }
 
-    // Create a scale-free network using preferential attachment
-    // This better models real-world network topologies where some nodes are hubs
-    pub fn create_scale_free_network(&mut self,
-                                     network_map: Arc<Mutex<LightningNetworkMap>>,
-                                     node_count: usize,
-                                     min_connections: usize) -> Result<(), Box<dyn Error>> {
-        let mut network = network_map.lock().unwrap();
-
-        // Add nodes
-        for i in 0..node_count {
-            let cltv_delta = match i % 10 {
-                0 => 40,  // LND default
-                1 => 34,  // Eclair default
-                2 => 42,  // C-lightning default
-                _ => self.rng.random_range(14..=50),
-            };
+    // Spread shadow offset profiles over the nodes, e.g. so only some of them add one
+    pub fn shadow_offsets(mut self, mix: ShadowOffsetMix) -> Self {
+        self.shadow_offsets = Some(mix);
+        self
+    }
 
-            let node = Node::new(
-                &format!("node{}", i+1),
-                &format!("Node {}", i+1),
-                cltv_delta
-            );
+    // Give one node a shadow offset profile of its own
+    pub fn node_shadow_offset(mut self, node: &str, offset: ShadowOffset) -> Self {
+        self.node_shadow_offsets.push((node.to_string(), offset));
+        self
+    }
 
-            network.add_node(node);
+    // Populate the network with any topology model
+    pub fn create_network(&mut self,
+                          network_map: Arc<RwLock<LightningNetworkMap>>,
+                          topology: &mut dyn TopologyGenerator,
+                          node_count: usize) -> Result<(), ThelmaError> {
+        let _span = info_span!("topology").entered();
+        info!("Using the {} topology", topology.name());
+        let mut network = write_lock(&network_map);
+        topology.generate(&mut network, node_count, &mut self.rng)?;
+        // Imported graphs and fixtures keep the policies they came with
+        if !matches!(topology.name(), "imported" | "fixture") {
+            if let Some(distribution) = &self.cltv_deltas {
+                distribution.assign(&mut network, &mut self.rng);
+            }
+            self.fees.assign(&mut network, &mut self.rng);
+            self.add_parallel_channels(&mut network);
+            self.retire_channels(&mut network);
+            self.hide_behind_tor(&mut network);
+            if let Some(schedule) = &self.gossip {
+                schedule.assign_ages(&mut network, &mut self.rng);
+            }
         }
-
-        println!("Created {} nodes", node_count);
-
-        // If we have at least min_connections nodes, create initial fully-connected cluster
-        let initial_nodes = std::cmp::min(node_count, min_connections);
-        for i in 0..initial_nodes {
-            for j in (i+1)..initial_nodes {
-                let channel = Channel::new(
-                    &format!("chan{}-{}", i+1, j+1),
-                    &format!("node{}", i+1),
-                    &format!("node{}", j+1),
-                    1_000_000 + self.rng.random_range(0..5_000_000)
-                );
-
-                network.add_channel(channel);
+        if let Some(max_accepted_htlcs) = self.max_accepted_htlcs {
+            for channel in &mut network.channels {
+                channel.max_accepted_htlcs = max_accepted_htlcs;
             }
         }
-
-        // Add remaining nodes using preferential attachment
-        let mut channel_count = initial_nodes * (initial_nodes - 1) / 2;
-
-        for i in initial_nodes..node_count {
-            // Connect to min_connections existing nodes with probability proportional
-            // to their current degree (number of connections)
-
-            // Count connections for each existing node
-            let mut connection_counts = Vec::new();
-            for j in 0..i {
-                let connections = network.adjacency_list[&format!("node{}", j+1)].len();
-                connection_counts.push((j, connections));
+        if self.dust_refusing_share > 0.0 {
+            for channel in &mut network.channels {
+                if self.rng.random_bool(self.dust_refusing_share) {
+                    channel.max_dust_exposure_msat = 0;
+                }
             }
+        }
+        if let Some(mix) = &self.shadow_offsets {
+            mix.assign(&mut network, &mut self.rng);
+        }
+        if let Some(regions) = &self.regions {
+            regions.assign(&mut network, &mut self.rng);
+        }
+        for (key, offset) in &self.node_shadow_offsets {
+            match network.nodes.get(key) {
+                Some(node) => {
+                    let node = node.clone().with_shadow_offset(*offset);
+                    network.add_node(node);
+                }
+                None => warn!("Node {} is not in the network, ignoring its shadow offset", key),
+            }
+        }
+        Ok(())
+    }
 
-            // Sort by connection count (descending)
-            connection_counts.sort_by(|a, b| b.1.cmp(&a.1));
-
-            // Connect to the top min_connections nodes
-            for k in 0..std::cmp::min(min_connections, i) {
-                let j = connection_counts[k].0;
+    fn add_parallel_channels(&mut self, network: &mut LightningNetworkMap) {
+        if self.parallel_share <= 0.0 {
+            return;
+        }
+        let mut parallel = Vec::new();
+        for channel in &network.channels {
+            if !self.rng.random_bool(self.parallel_share) {
+                continue;
+            }
+            let capacity = (channel.capacity as f64 * self.rng.random_range(0.5..2.0)) as u64;
+            let mut fees = || {
+                let (base_fee_msat, fee_rate_ppm) = self.fees.sample(&mut self.rng);
+                ChannelFees { base_fee_msat, fee_rate_ppm }
+            };
+            let (node1_fees, node2_fees) = (fees(), fees());
+            parallel.push(Channel::new(&new_short_channel_id(network, &mut self.rng), &channel.node1, &channel.node2, capacity)
+                .with_fees(&channel.node1, node1_fees)
+                .with_fees(&channel.node2, node2_fees));
+        }
+        info!("Added {} parallel channels", parallel.len());
+        for channel in parallel {
+            network.add_channel(channel);
+        }
+    }
 
-                let channel = Channel::new(
-                    &format!("chan{}-{}", i+1, j+1),
-                    &format!("node{}", i+1),
-                    &format!("node{}", j+1),
-                    500_000 + self.rng.random_range(0..3_000_000)
-                );
+    fn hide_behind_tor(&mut self, network: &mut LightningNetworkMap) {
+        if self.tor_share <= 0.0 {
+            return;
+        }
+        let mut keys: Vec<String> = network.nodes.keys().cloned().collect();
+        keys.sort();
+        let mut hidden = 0;
+        for key in keys {
+            if self.rng.random_bool(self.tor_share) {
+                if let Some(node) = network.nodes.get_mut(&key) {
+                    node.tor_only = true;
+                    hidden += 1;
+                }
+            }
+        }
+        info!("{} of {} nodes are reachable only over Tor", hidden, network.nodes.len());
+    }
 
-                network.add_channel(channel);
-                channel_count += 1;
+    fn retire_channels(&mut self, network: &mut LightningNetworkMap) {
+        if self.disabled_share <= 0.0 && self.zombie_share <= 0.0 {
+            return;
+        }
+        let mut retired = Vec::new();
+        for channel in &network.channels {
+            let pick = self.rng.random::<f64>();
+            if pick < self.zombie_share {
+                retired.push((channel.channel_id.clone(), ChannelStatus::Zombie));
+            } else if pick < self.zombie_share + self.disabled_share {
+                retired.push((channel.channel_id.clone(), ChannelStatus::Disabled));
             }
         }
+        for (channel_id, status) in &retired {
+            network.set_channel_status(channel_id, *status);
+        }
+        info!("{} of {} channels are disabled or zombies", retired.len(), network.channels.len());
+    }
 
-        println!("Created {} channels", channel_count);
+    // Create a simple test network with specified number of nodes
+    pub fn create_simple_network(&mut self,
+                                 network_map: Arc<RwLock<LightningNetworkMap>>,
+                                 node_count: usize) -> Result<(), ThelmaError> {
+        SimpleTopology.generate(&mut write_lock(&network_map), node_count, &mut self.rng)
+    }
 
-        Ok(())
+    // Create a scale-free network using preferential attachment
+    pub fn create_scale_free_network(&mut self,
+                                     network_map: Arc<RwLock<LightningNetworkMap>>,
+                                     node_count: usize,
+                                     min_connections: usize) -> Result<(), ThelmaError> {
+        ScaleFreeTopology::new(min_connections).generate(&mut write_lock(&network_map), node_count, &mut self.rng)
     }
 
     // Select a random subset of nodes as malicious observers
     pub fn select_malicious_nodes(&mut self,
-                                  network_map: Arc<Mutex<LightningNetworkMap>>,
+                                  network_map: Arc<RwLock<LightningNetworkMap>>,
                                   count: usize) -> Vec<String> {
-        let network = network_map.lock().unwrap();
-        let all_nodes: Vec<String> = network.nodes.keys().cloned().collect();
-
-        // Select random nodes to be malicious
-        let mut malicious_nodes = Vec::new();
-        let mut indices: Vec<usize> = (0..all_nodes.len()).collect();
-
-        // Shuffle indices
-        for i in 0..indices.len() {
-            let j = self.rng.random_range(i..indices.len());
-            indices.swap(i, j);
-        }
-
-        // Select the first 'count' indices
-        for i in 0..std::cmp::min(count, all_nodes.len()) {
-            malicious_nodes.push(all_nodes[indices[i]].clone());
-        }
+        RandomPlacement::new().select_nodes(&read_lock(&network_map), count)
+    }
 
-        malicious_nodes
+    // Place malicious observers on the most central nodes, as a well-resourced attacker would
+    pub fn select_central_nodes(&mut self,
+                                network_map: Arc<RwLock<LightningNetworkMap>>,
+                                count: usize,
+                                measure: CentralityMeasure) -> Vec<String> {
+        CentralPlacement::new(measure).select_nodes(&read_lock(&network_map), count)
     }
 }
 
@@ -196,20 +312,20 @@ mod tests {
 
     #[test]
     fn test_simple_network_generation() {
-        let network_map = Arc::new(Mutex::new(LightningNetworkMap::new(700000)));
+        let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(700000)));
         let mut generator = NetworkGenerator::new();
 
         let node_count = 10;
         generator.create_simple_network(network_map.clone(), node_count).unwrap();
 
-        let network = network_map.lock().unwrap();
+        let network = network_map.read().unwrap();
         assert_eq!(network.nodes.len(), node_count);
         assert!(network.channels.len() >= node_count); // At least one channel per node
     }
 
     #[test]
     fn test_malicious_node_selection() {
-        let network_map = Arc::new(Mutex::new(LightningNetworkMap::new(700000)));
+        let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(700000)));
         let mut generator = NetworkGenerator::new();
 
         // Create a network with 20 nodes
@@ -228,4 +344,20 @@ mod tests {
 
         assert_eq!(unique_nodes.len(), 5);
     }
+
+    #[test]
+    fn test_central_node_selection() {
+        let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(700000)));
+        let mut generator = NetworkGenerator::new();
+
+        generator.create_scale_free_network(network_map.clone(), 20, 3).unwrap();
+
+        // The initial cluster attracts every later attachment, so it dominates by degree
+        let hubs = generator.select_central_nodes(network_map.clone(), 3, CentralityMeasure::Degree);
+
+        assert_eq!(hubs.len(), 3);
+        for hub in ["node1", "node2", "node3"] {
+            assert!(hubs.contains(&hub.to_string()));
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,76 @@
+// Pluggable observers of simulated traffic
+
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use log::warn;
+
+use crate::models::{HTLC, HtlcResolution};
+use crate::simulation::events::NetworkEvent;
+use crate::error::{ThelmaError, lock_mutex};
+
+// Something watching the simulated network. Every callback defaults to doing nothing, so
+// implementors only handle what they care about.
+pub trait Observer: Send {
+    // A node on a payment's route received an HTLC
+    fn on_htlc_forward(&mut self, _htlc: &HTLC) -> Result<(), ThelmaError> {
+        Ok(())
+    }
+
+    // A node on a payment's route saw its HTLC settled or failed back
+    fn on_htlc_resolved(&mut self, _resolution: &HtlcResolution) -> Result<(), ThelmaError> {
+        Ok(())
+    }
+
+    // The recipient accepted a payment
+    fn on_settle(&mut self, _payment_hash: &str) -> Result<(), ThelmaError> {
+        Ok(())
+    }
+
+    // A payment couldn't be routed
+    fn on_fail(&mut self, _sender: &str, _receiver: &str, _reason: &str) -> Result<(), ThelmaError> {
+        Ok(())
+    }
+
+    // The chain reached a new height
+    fn on_blocks_mined(&mut self, _height: u32) -> Result<(), ThelmaError> {
+        Ok(())
+    }
+
+    // A channel's policy was gossiped again
+    fn on_channel_update(&mut self, _channel_id: &str, _block: u32) -> Result<(), ThelmaError> {
+        Ok(())
+    }
+}
+
+// Hand one event to the matching callback
+pub fn dispatch(observer: &mut dyn Observer, event: &NetworkEvent) -> Result<(), ThelmaError> {
+    match event {
+        NetworkEvent::HtlcForwarded(htlc) => observer.on_htlc_forward(htlc),
+        NetworkEvent::HtlcResolved(resolution) => observer.on_htlc_resolved(resolution),
+        NetworkEvent::PaymentSettled { payment_hash } => observer.on_settle(payment_hash),
+        NetworkEvent::PaymentFailed { sender, receiver, reason } => observer.on_fail(sender, receiver, reason),
+        NetworkEvent::BlocksMined { height } => observer.on_blocks_mined(*height),
+        NetworkEvent::ChannelUpdated { channel_id, block } => observer.on_channel_update(channel_id, *block),
+    }
+}
+
+// Feed events from the bus to an observer until the bus closes
+pub fn spawn_observer<O: Observer + 'static>(observer: Arc<Mutex<O>>,
+                                             mut events: broadcast::Receiver<NetworkEvent>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    if let Err(e) = dispatch(&mut *lock_mutex(&observer), &event) {
+                        warn!("Observer failed to handle event: {}", e);
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    warn!("Observer fell behind and missed {} events", missed);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    })
+}
@@ -1,48 +1,218 @@
 // Simulation of Lightning Network payments for surveillance testing
 
-use std::sync::{Arc, Mutex};
-use std::error::Error;
-use rand::Rng;
-use tokio::time::{sleep, Duration};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::Ordering;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use log::{debug, info};
+use tracing::{debug_span, info_span};
 
-use crate::models::{HTLC, LightningNetworkMap};
-use crate::models::htlc::{DEFAULT_FINAL_CLTV_DELTA, CLTV_RANDOM_OFFSET_MIN, CLTV_RANDOM_OFFSET_MAX};
-use crate::surveillance::SurveillanceOperation;
-use crate::simulation::utils::generate_random_path;
+use crate::models::{ChannelHtlc, HTLC, HopPayload, HtlcChain, HtlcResolution, Invoice, LightningNetworkMap};
+use crate::models::htlc::{DEFAULT_FINAL_CLTV_DELTA, MAX_ROUTE_HOPS};
+use crate::simulation::config::{CltvConstruction, SimulatorConfig};
+use crate::simulation::events::{NetworkEvent, DEFAULT_EVENT_CAPACITY};
+use crate::simulation::observer::{spawn_observer, Observer};
+use crate::simulation::retry::GiveUp;
+use crate::simulation::slots::HtlcSlots;
+use crate::simulation::lsp::open_jit_channel;
+use crate::simulation::latency::HopLatency;
+use crate::error::{ThelmaError, lock_mutex, read_lock, write_lock};
+use crate::logging::Progress;
+
+// Ground truth for a simulated payment, used to score the surveillance results
+#[derive(Debug, Clone)]
+pub struct PaymentRecord {
+    pub payment_hash: String,
+    pub sender: String,
+    pub receiver: String,
+    pub path: Vec<String>,
+    pub amount: u64,
+    pub observed: bool,
+    // Dummy payment emitted as cover traffic rather than a real payment
+    pub cover: bool,
+}
+
+impl PaymentRecord {
+    pub fn hop_count(&self) -> usize {
+        self.path.len().saturating_sub(1)
+    }
+}
+
+// What became of one attempt at a payment
+enum Attempt {
+    // Paid; whether the adversary saw it
+    Settled(bool),
+    // Failed before anything was sent, so trying again wouldn't help
+    Refused(String),
+    // Failed back partway along the route
+    FailedInFlight(String),
+}
+
+// A node's view of a payment passing through: the HTLC it receives and its onion layer
+struct HopView {
+    amount: u64,
+    cltv_expiry: u32,
+    payload: HopPayload,
+}
 
 // Payment simulator for testing surveillance capabilities
 pub struct PaymentSimulator {
-    network: Arc<Mutex<LightningNetworkMap>>,
-    rng: rand::rngs::ThreadRng,
-    surveillance: Arc<Mutex<SurveillanceOperation>>,
-    // Optional delay between simulated payments for more realistic behavior
-    delay_ms: u64,
+    network: Arc<RwLock<LightningNetworkMap>>,
+    config: SimulatorConfig,
+    rng: StdRng,
+    // Published to every subscriber; None until someone subscribes
+    events: Option<broadcast::Sender<NetworkEvent>>,
+    payment_records: Vec<PaymentRecord>,
+    // Payments started so far, routed or not
+    attempted: usize,
+    // Payment each per-hop point belongs to, in PTLC mode
+    hop_owners: HashMap<String, String>,
+    // HTLC slots in use, shared with forked workers
+    slots: Arc<Mutex<HtlcSlots>>,
+    // Milliseconds senders of real payments spent waiting for their HTLCs to resolve
+    waiting_ms: u64,
+    // Msat senders of real payments paid on top of amounts and fees
+    overpaid_msat: u64,
+    // Route and amount of every shard of the payments that were split
+    shards: HashMap<String, Vec<(Vec<String>, u64)>>,
+    // HTLCs every settled payment locked along each of its routes, by payment hash
+    htlc_chains: HashMap<String, Vec<HtlcChain>>,
+    // Nodes reachable only over Tor, whose links are slower
+    tor_nodes: Arc<HashSet<String>>,
+    // Region of every node placed in one
+    node_regions: Arc<HashMap<String, String>>,
 }
 
 impl PaymentSimulator {
-    pub fn new(network: Arc<Mutex<LightningNetworkMap>>,
-               surveillance: Arc<Mutex<SurveillanceOperation>>,
-               delay_ms: u64) -> Self {
+    pub fn new(network: Arc<RwLock<LightningNetworkMap>>, config: SimulatorConfig) -> Self {
+        let rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_rng(&mut rand::rng()),
+        };
+        let slots = Arc::new(Mutex::new(HtlcSlots::new(config.htlc_hold)));
+        let (tor_nodes, node_regions) = {
+            let network = read_lock(&network);
+            let tor_nodes = network.nodes.values()
+                .filter(|node| node.tor_only)
+                .map(|node| node.pub_key.clone())
+                .collect();
+            let node_regions = network.nodes.values()
+                .filter_map(|node| Some((node.pub_key.clone(), node.region.clone()?)))
+                .collect();
+            (tor_nodes, node_regions)
+        };
         PaymentSimulator {
             network,
-            rng: rand::rng(),
-            surveillance,
-            delay_ms,
+            config,
+            rng,
+            events: None,
+            payment_records: Vec::new(),
+            attempted: 0,
+            hop_owners: HashMap::new(),
+            slots,
+            waiting_ms: 0,
+            overpaid_msat: 0,
+            shards: HashMap::new(),
+            htlc_chains: HashMap::new(),
+            tor_nodes: Arc::new(tor_nodes),
+            node_regions: Arc::new(node_regions),
         }
     }
 
-    // Simulate a single payment through the network
-    pub async fn simulate_payment(&mut self) -> Result<bool, Box<dyn Error>> {
-        // Get current network state
-        let network = self.network.lock().unwrap();
-        let current_height = network.current_block_height;
+    // Copy of this simulator with an RNG seeded from ours and no records, sharing the
+    // network and the event bus
+    fn fork(&mut self) -> Self {
+        PaymentSimulator {
+            network: self.network.clone(),
+            config: self.config.clone().workers(1),
+            rng: StdRng::from_rng(&mut self.rng),
+            events: self.events.clone(),
+            payment_records: Vec::new(),
+            attempted: 0,
+            hop_owners: HashMap::new(),
+            slots: self.slots.clone(),
+            waiting_ms: 0,
+            overpaid_msat: 0,
+            shards: HashMap::new(),
+            htlc_chains: HashMap::new(),
+            tor_nodes: self.tor_nodes.clone(),
+            node_regions: self.node_regions.clone(),
+        }
+    }
+
+    // Receive every event published from now on
+    pub fn subscribe(&mut self) -> broadcast::Receiver<NetworkEvent> {
+        self.events
+            .get_or_insert_with(|| broadcast::channel(DEFAULT_EVENT_CAPACITY).0)
+            .subscribe()
+    }
+
+    // Have an observer follow all traffic from now on. The returned task finishes once
+    // events are closed and the observer has caught up.
+    pub fn register_observer<O: Observer + 'static>(&mut self, observer: Arc<Mutex<O>>) -> JoinHandle<()> {
+        spawn_observer(observer, self.subscribe())
+    }
+
+    // Stop publishing, so subscribers see the bus close once they've caught up
+    pub fn close_events(&mut self) {
+        self.events = None;
+    }
+
+    fn publish(&self, event: NetworkEvent) {
+        if let Some(events) = &self.events {
+            // Only fails when nobody is listening
+            let _ = events.send(event);
+        }
+    }
+
+    // Ground truth for every payment that was routed so far
+    pub fn payment_records(&self) -> &[PaymentRecord] {
+        &self.payment_records
+    }
+
+    // Which payment each HTLC identifier belongs to. Empty unless payments use PTLCs,
+    // where hop identifiers differ from the payment hash.
+    pub fn hop_owners(&self) -> &HashMap<String, String> {
+        &self.hop_owners
+    }
+
+    // Time senders of real payments spent waiting on the network, failed attempts included
+    pub fn waiting_ms(&self) -> u64 {
+        self.waiting_ms
+    }
+
+    // Shards of the payments that were split, by payment hash
+    pub fn payment_shards(&self) -> &HashMap<String, Vec<(Vec<String>, u64)>> {
+        &self.shards
+    }
+
+    // HTLCs each settled payment locked over every channel of its routes, one chain per
+    // shard, by payment hash
+    pub fn htlc_chains(&self) -> &HashMap<String, Vec<HtlcChain>> {
+        &self.htlc_chains
+    }
+
+    // What senders of real payments overpaid, for settled payments only
+    pub fn overpaid_msat(&self) -> u64 {
+        self.overpaid_msat
+    }
 
+    // Simulate a single payment through the network
+    pub async fn simulate_payment(&mut self) -> Result<bool, ThelmaError> {
         // Get all node pubkeys
-        let node_keys: Vec<String> = network.nodes.keys().cloned().collect();
-        drop(network); // Release the lock
+        // Sorted so a seeded RNG picks the same endpoints in every process
+        let node_keys: Vec<String> = {
+            let network = read_lock(&self.network);
+            let mut keys: Vec<String> = network.nodes.keys().cloned().collect();
+            keys.sort();
+            keys
+        };
 
         if node_keys.len() < 2 {
-            return Err("Not enough nodes in the network".into());
+            return Err(ThelmaError::Graph("not enough nodes in the network to send a payment".to_string()));
         }
 
         // Pick random sender and receiver
@@ -55,165 +225,486 @@ impl PaymentSimulator {
         let sender = &node_keys[sender_idx];
         let receiver = &node_keys[receiver_idx];
 
-        println!("Simulating payment from {} to {}", sender, receiver);
+        debug!("Simulating payment from {} to {}", sender, receiver);
 
-        // Generate a random path between them
-        let path = generate_random_path(self.network.clone(), sender, receiver)?;
+        let observed = self.route_payment(sender, receiver, false)?;
 
-        if path.len() < 2 {
-            println!("  Couldn't find path, skipping payment");
-            return Ok(false);
-        }
+        self.emit_cover_traffic()?;
 
-        println!("  Found path with {} hops", path.len() - 1);
+        self.gossip_round();
 
-        // Create a unique payment hash
-        let payment_hash = format!("hash_{:016x}", self.rng.random::<u64>());
-        let amount = self.rng.random_range(10000..1000000); // Random amount in millisatoshis
+        self.pause().await;
 
-        // Add random offset for privacy
-        let random_offset = self.rng.random_range(CLTV_RANDOM_OFFSET_MIN..CLTV_RANDOM_OFFSET_MAX);
-
-        // Calculate the final CLTV expiry
-        let mut final_cltv_expiry = current_height + DEFAULT_FINAL_CLTV_DELTA + random_offset;
-
-        // Add CLTV deltas for each hop
-        let mut cltv_expiry_values = Vec::new();
-        let mut accumulated_delta = 0;
+        Ok(observed)
+    }
 
-        // Simulate CLTV values for each hop (in reverse)
-        for node_pubkey in path.iter().rev().skip(1) {
-            let network = self.network.lock().unwrap();
-            let delta = match network.nodes.get(node_pubkey) {
-                Some(node) => node.cltv_expiry_delta,
-                None => 14, // Minimum if unknown
-            };
-            drop(network);
+    // Simulate multiple payments. Stops early once the configured stop signal is raised,
+    // keeping everything simulated up to then.
+    pub async fn simulate_payments(&mut self, count: usize) -> Result<usize, ThelmaError> {
+        // Not entered: workers run on other threads, the span only marks how long this took
+        let _span = info_span!("simulation");
+        let started = self.attempted;
+        let progress = Progress::start(count, "Simulating payments");
+        let observed_count = if self.config.workers > 1 && count > 1 {
+            self.simulate_payments_in_parallel(count, &progress).await?
+        } else {
+            self.run_payments(count, &progress).await
+        };
+        progress.finish();
 
-            accumulated_delta += delta;
-            cltv_expiry_values.push(final_cltv_expiry + accumulated_delta);
+        let simulated = self.attempted - started;
+        if simulated < count {
+            info!("Stopped after {} of {} payments", simulated, count);
         }
+        info!("Simulated {} payments, {} were observed by surveillance nodes",
+              simulated, observed_count);
 
-        // Reverse to match the forward path
-        cltv_expiry_values.reverse();
-
-        // Add final value
-        cltv_expiry_values.push(final_cltv_expiry);
-
-        // Now simulate the HTLC being observed by malicious nodes
-        let surveillance = self.surveillance.lock().unwrap();
-        let malicious_nodes = surveillance.get_malicious_nodes().to_vec();
-        drop(surveillance);
-
-        let mut observed = false;
-
-        for (i, node) in path.iter().enumerate() {
-            if malicious_nodes.contains(node) {
-                let cltv_expiry = cltv_expiry_values[i];
-
-                let htlc = HTLC::new(
-                    &payment_hash,
-                    cltv_expiry,
-                    amount,
-                    current_height,
-                    node
-                );
+        Ok(observed_count)
+    }
 
-                // Record the observation
-                let mut surveillance = self.surveillance.lock().unwrap();
-                surveillance.record_htlc_observation(htlc);
-                drop(surveillance);
+    // Whether the stop signal has been raised
+    pub fn was_interrupted(&self) -> bool {
+        self.config.stop.as_ref().is_some_and(|stop| stop.load(Ordering::Relaxed))
+    }
 
-                println!("  Malicious node {} observed HTLC!", node);
-                observed = true;
-            }
-        }
+    // Payments started so far, including ones that found no route
+    pub fn payments_attempted(&self) -> usize {
+        self.attempted
+    }
 
-        // Simulate some time passing between payments if delay is set
-        if self.delay_ms > 0 {
-            sleep(Duration::from_millis(self.delay_ms)).await;
-        }
+    // Restart the RNG from a seed drawn from it and return that seed. A simulator built
+    // with the same seed carries on with the same random choices.
+    pub fn reseed(&mut self) -> u64 {
+        let seed = self.rng.random();
+        self.rng = StdRng::seed_from_u64(seed);
+        seed
+    }
 
-        Ok(observed)
+    // Pick up after payments simulated by an earlier run
+    pub fn resume(&mut self, attempted: usize, payment_records: Vec<PaymentRecord>) {
+        self.attempted = attempted;
+        self.payment_records = payment_records;
     }
 
-    // Simulate multiple payments
-    pub async fn simulate_payments(&mut self, count: usize) -> Result<usize, Box<dyn Error>> {
+    async fn run_payments(&mut self, count: usize, progress: &Progress) -> usize {
         let mut observed_count = 0;
 
         for i in 0..count {
-            println!("Simulating payment {}/{}", i+1, count);
+            if self.was_interrupted() {
+                break;
+            }
+            debug!("Simulating payment {}/{}", i+1, count);
+            self.attempted += 1;
 
             if let Ok(observed) = self.simulate_payment().await {
                 if observed {
                     observed_count += 1;
                 }
             }
+
+            progress.inc();
+
+            // Give subscribers on this thread a chance to keep up
+            tokio::task::yield_now().await;
+        }
+
+        observed_count
+    }
+
+    // Split the payments across worker tasks. Their delays overlap and route finding
+    // runs on the runtime's threads, so large workloads finish much sooner.
+    async fn simulate_payments_in_parallel(&mut self, count: usize, progress: &Progress) -> Result<usize, ThelmaError> {
+        let workers = self.config.workers.min(count);
+        let mut handles = Vec::with_capacity(workers);
+
+        for worker in 0..workers {
+            let share = count / workers + usize::from(worker < count % workers);
+            let mut simulator = self.fork();
+            let progress = progress.clone();
+            handles.push(tokio::spawn(async move {
+                let observed = simulator.run_payments(share, &progress).await;
+                (observed, simulator.attempted, simulator.payment_records, simulator.hop_owners,
+                 simulator.waiting_ms, simulator.overpaid_msat, simulator.shards, simulator.htlc_chains)
+            }));
         }
 
-        println!("Simulated {} payments, {} were observed by surveillance nodes",
-                 count, observed_count);
+        let mut observed_count = 0;
+        for handle in handles {
+            let (observed, attempted, records, hop_owners, waiting_ms, overpaid_msat, shards, htlc_chains) = handle.await?;
+            observed_count += observed;
+            self.attempted += attempted;
+            self.payment_records.extend(records);
+            self.hop_owners.extend(hop_owners);
+            self.waiting_ms += waiting_ms;
+            self.overpaid_msat += overpaid_msat;
+            self.shards.extend(shards);
+            self.htlc_chains.extend(htlc_chains);
+        }
 
         Ok(observed_count)
     }
 
     // Update the current block height (to simulate time passing)
     pub fn advance_block_height(&mut self, blocks: u32) {
-        let mut network = self.network.lock().unwrap();
-        network.current_block_height += blocks;
-        println!("Advanced block height by {}. New height: {}",
-                 blocks, network.current_block_height);
+        let height = {
+            let mut network = write_lock(&self.network);
+            network.current_block_height += blocks;
+            network.current_block_height
+        };
+        debug!("Advanced block height by {}. New height: {}", blocks, height);
+        self.publish(NetworkEvent::BlocksMined { height });
     }
 
     // Simulate a specific payment between two nodes
     pub async fn simulate_specific_payment(&mut self,
                                            from_node: &str,
-                                           to_node: &str) -> Result<bool, Box<dyn Error>> {
-        // Get current network state
-        let network = self.network.lock().unwrap();
-        let current_height = network.current_block_height;
-
+                                           to_node: &str) -> Result<bool, ThelmaError> {
         // Verify both nodes exist
-        if !network.nodes.contains_key(from_node) || !network.nodes.contains_key(to_node) {
-            drop(network);
-            return Err("One or both specified nodes don't exist in the network".into());
+        {
+            let network = read_lock(&self.network);
+            if let Some(missing) = [from_node, to_node].into_iter().find(|node| !network.nodes.contains_key(*node)) {
+                return Err(ThelmaError::Routing(format!("payment endpoint {} is not in the network", missing)));
+            }
         }
-        drop(network);
 
-        println!("Simulating specific payment from {} to {}", from_node, to_node);
+        debug!("Simulating specific payment from {} to {}", from_node, to_node);
 
-        // Generate a path between them
-        let path = generate_random_path(self.network.clone(), from_node, to_node)?;
+        let observed = self.route_payment(from_node, to_node, false)?;
 
-        if path.len() < 2 {
-            println!("  Couldn't find path, skipping payment");
-            return Ok(false);
+        self.emit_cover_traffic()?;
+
+        self.gossip_round();
+
+        self.pause().await;
+
+        Ok(observed)
+    }
+
+    // Mine the blocks between two payments and publish the channel updates sent meanwhile
+    fn gossip_round(&mut self) {
+        let Some(schedule) = self.config.gossip else { return };
+        if schedule.blocks_per_payment == 0 {
+            return;
         }
+        self.advance_block_height(schedule.blocks_per_payment);
+
+        let updates: Vec<(String, u32)> = {
+            let mut network = write_lock(&self.network);
+            let height = network.current_block_height;
+            let updated = schedule.updates(&network, schedule.blocks_per_payment, &mut self.rng);
+            updated.into_iter()
+                .map(|index| {
+                    let channel = &mut network.channels[index];
+                    channel.last_update = Some(height);
+                    (channel.channel_id.clone(), height)
+                })
+                .collect()
+        };
+        for (channel_id, block) in updates {
+            self.publish(NetworkEvent::ChannelUpdated { channel_id, block });
+        }
+    }
+
+    // Simulate some time passing between payments if delay is set. Builds without the
+    // native runtime have no timer, so they carry straight on.
+    async fn pause(&self) {
+        #[cfg(feature = "native")]
+        if self.config.delay_ms > 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(self.config.delay_ms)).await;
+        }
+    }
+
+    // Send dummy payments between random honest nodes if cover traffic is enabled
+    fn emit_cover_traffic(&mut self) -> Result<(), ThelmaError> {
+        let dummies = match &self.config.cover_traffic {
+            Some(defense) => defense.dummies_for_payment(&mut self.rng),
+            None => return Ok(()),
+        };
+
+        if dummies == 0 {
+            return Ok(());
+        }
+
+        let honest_nodes: Vec<String> = {
+            let network = read_lock(&self.network);
+            let mut keys: Vec<String> = network.nodes.keys()
+                .filter(|key| !self.config.malicious_nodes.contains(key))
+                .cloned()
+                .collect();
+            keys.sort();
+            keys
+        };
+
+        if honest_nodes.len() < 2 {
+            return Ok(());
+        }
+
+        for _ in 0..dummies {
+            let sender_idx = self.rng.random_range(0..honest_nodes.len());
+            let mut receiver_idx = self.rng.random_range(0..honest_nodes.len());
+            while receiver_idx == sender_idx {
+                receiver_idx = self.rng.random_range(0..honest_nodes.len());
+            }
+
+            debug!("  Emitting cover payment from {} to {}",
+                   honest_nodes[sender_idx], honest_nodes[receiver_idx]);
+            self.route_payment(&honest_nodes[sender_idx], &honest_nodes[receiver_idx], true)?;
+        }
+
+        Ok(())
+    }
+
+    fn publish_failure(&self, sender: &str, receiver: &str, reason: String) {
+        self.publish(NetworkEvent::PaymentFailed {
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            reason,
+        });
+    }
 
-        println!("  Found path with {} hops", path.len() - 1);
+    // Route a payment between two nodes and publish the HTLC each node on the route sees.
+    // Payments that fail in flight are retried under the same invoice if the config says so.
+    fn route_payment(&mut self, sender: &str, receiver: &str, cover: bool) -> Result<bool, ThelmaError> {
+        let _span = debug_span!("route_payment").entered();
+        let amount = self.config.amounts.sample(&mut self.rng);
 
-        // Create a unique payment hash
+        // Create a unique payment hash, kept by every attempt
         let payment_hash = format!("hash_{:016x}", self.rng.random::<u64>());
-        let amount = self.rng.random_range(10000..1000000); // Random amount in millisatoshis
 
-        // Add random offset for privacy
-        let random_offset = self.rng.random_range(CLTV_RANDOM_OFFSET_MIN..CLTV_RANDOM_OFFSET_MAX);
+        // The recipient's invoice sets the final delta and how long the sender has to pay
+        let invoice = {
+            let network = read_lock(&self.network);
+            let height = network.current_block_height;
+            match network.nodes.get(receiver) {
+                Some(node) => node.create_invoice(&payment_hash, amount, height),
+                None => Invoice::new(&payment_hash, receiver, amount, DEFAULT_FINAL_CLTV_DELTA, height),
+            }.expiry_blocks(self.config.invoice_expiry_blocks)
+        };
 
-        // Calculate the final CLTV expiry
-        let mut final_cltv_expiry = current_height + DEFAULT_FINAL_CLTV_DELTA + random_offset;
+        let mut attempts = 0;
+        let reason = loop {
+            attempts += 1;
+            match self.attempt_payment(sender, receiver, &invoice, cover) {
+                Attempt::Settled(observed) => return Ok(observed),
+                Attempt::Refused(reason) => break reason,
+                Attempt::FailedInFlight(reason) => {
+                    let Some(policy) = self.config.retries else { break reason };
+                    let height = read_lock(&self.network).current_block_height;
+                    match policy.next_attempt(attempts, &invoice, height) {
+                        Ok(retry_at) => {
+                            debug!("  Attempt {} failed, retrying at block {}", attempts, retry_at);
+                            self.advance_block_height(retry_at - height);
+                        }
+                        Err(GiveUp::InvoiceExpired) => {
+                            debug!("  Invoice expires at block {}, abandoning payment", invoice.expires_at());
+                            break format!("invoice expired after {} attempts", attempts);
+                        }
+                        Err(GiveUp::AttemptsExhausted) => break reason,
+                    }
+                }
+            }
+        };
+        self.publish_failure(sender, receiver, reason);
+        Ok(false)
+    }
+
+    // Try to pay an invoice once, over a route found from the network as it is now
+    fn attempt_payment(&mut self, sender: &str, receiver: &str, invoice: &Invoice, cover: bool) -> Attempt {
+        let amount = invoice.amount_msat;
+        let payment_hash = &invoice.payment_hash;
+        lock_mutex(&self.slots).tick();
+        if let Some(lsp) = self.config.jit_channels.get(receiver) {
+            let mut network = write_lock(&self.network);
+            if open_jit_channel(&mut network, receiver, lsp, amount, &mut self.rng) {
+                debug!("  {} opened a just-in-time channel to {}", lsp, receiver);
+            }
+        }
+
+        // Plan the whole route under one read lock, so concurrent analysis isn't blocked
+        let network_map = self.network.clone();
+        let network = read_lock(&network_map);
+
+        // Find a path whose channels can carry the amount, the way this sender would, or
+        // hand the payment to a trampoline that finds the rest of the way
+        let (mut path, trampoline_hop) = match self.trampoline_route(&network, sender, receiver, amount) {
+            Some((path, hop)) => (path, Some(hop)),
+            None => (self.config.router_of(sender).find_route(&network, sender, receiver, amount, &mut self.rng), None),
+        };
+
+        if path.len() < 2 {
+            debug!("  Couldn't find path, skipping payment");
+            return Attempt::Refused("no route can carry the amount".to_string());
+        }
+
+        debug!("  Found path with {} hops", path.len() - 1);
+
+        if path.len() - 1 > MAX_ROUTE_HOPS {
+            debug!("  Path exceeds the {}-hop onion limit, skipping payment", MAX_ROUTE_HOPS);
+            return Attempt::Refused(format!("route exceeds {} hops", MAX_ROUTE_HOPS));
+        }
+
+        if let Some(hop) = trampoline_hop {
+            debug!("  Routing through trampoline {}", path[hop]);
+        } else if let Some(defense) = &self.config.decoy_hops {
+            let padded = defense.pad_path(&network, &path, amount, &mut self.rng);
+            if padded.len() > path.len() && padded.len() - 1 <= MAX_ROUTE_HOPS {
+                debug!("  Padded route with {} decoy hops", padded.len() - path.len());
+                path = padded;
+            }
+        }
+
+        // Split it into shards over routes no two of which share an intermediary
+        let mut shards = vec![(path, amount)];
+        if trampoline_hop.is_none() {
+            if let Some(defense) = &self.config.payment_splitting {
+                let split = defense.split(&network, &shards[0].0, amount, &mut self.rng);
+                let usable = split.iter().all(|(route, part)| {
+                    route.len() - 1 <= MAX_ROUTE_HOPS
+                        && route.windows(2).all(|hop| network.can_carry(&hop[0], &hop[1], *part))
+                });
+                if split.len() > 1 && usable {
+                    debug!("  Split payment into {} shards over disjoint routes", split.len());
+                    shards = split;
+                }
+            }
+        }
+
+        let current_height = network.current_block_height;
+
+        // Add the sender's random offset for privacy, if its implementation adds one
+        let shadow_offset = network.nodes.get(sender).map(|node| node.shadow_offset).unwrap_or_default();
+        let random_offset = shadow_offset.sample(&mut self.rng);
+
+        // Calculate the final CLTV expiry, the same for every shard
+        let final_cltv_expiry = invoice.final_cltv_expiry(current_height) + random_offset;
+
+        let mut planned = Vec::with_capacity(shards.len());
+        let mut overpaid = 0;
+        for (path, part) in &shards {
+            let (hops, shard_overpaid) = self.plan_hops(&network, path, *part, final_cltv_expiry, trampoline_hop, receiver);
+
+            // The sender refuses routes locking funds up for longer than its cap
+            let total_cltv = hops[0].cltv_expiry - current_height;
+            if total_cltv > self.config.max_cltv_expiry {
+                debug!("  Route needs {} blocks of timelock (max {}), skipping payment",
+                       total_cltv, self.config.max_cltv_expiry);
+                return Attempt::Refused(format!("route needs {} blocks of timelock", total_cltv));
+            }
+            overpaid += shard_overpaid;
+            planned.push(hops);
+        }
+        drop(network);
+
+        // Every hop of every shard needs a free HTLC slot. The payment fails at the first
+        // one without, after the nodes up to it have seen the HTLC, and the recipient fails
+        // any shards that did arrive.
+        let mut reservations = Vec::with_capacity(shards.len());
+        for ((path, part), hops) in shards.iter().zip(&planned) {
+            let reservation = lock_mutex(&self.slots).reserve(&read_lock(&self.network), path, *part);
+            let Some(blocked) = reservation.blocked else {
+                reservations.push(reservation);
+                continue;
+            };
+            let (from, to) = (&path[blocked], &path[blocked + 1]);
+            debug!("  {} has no free HTLC slots towards {}, failing payment", from, to);
+            let mut waited = 0;
+            for ((arrived, _), (hops, reservation)) in shards.iter().zip(planned.iter().zip(&reservations)) {
+                let locks = self.forward_htlcs(payment_hash, arrived, hops, &reservation.channels, current_height);
+                waited = waited.max(self.resolve_htlcs(arrived, &locks, false));
+            }
+            let locks = self.forward_htlcs(payment_hash, &path[..=blocked], hops, &reservation.channels, current_height);
+            waited = waited.max(self.resolve_htlcs(&path[..=blocked], &locks, false));
+            if !cover {
+                self.waiting_ms += waited;
+            }
+            return Attempt::FailedInFlight(format!("no free HTLC slots from {} to {}", from, to));
+        }
+
+        // Every node on the route sees the HTLC with the expiry it carries there. The
+        // sender waits for the slowest shard.
+        let mut waited = 0;
+        let mut chains = Vec::with_capacity(shards.len());
+        for ((path, _), (hops, reservation)) in shards.iter().zip(planned.iter().zip(&reservations)) {
+            let locks = self.forward_htlcs(payment_hash, path, hops, &reservation.channels, current_height);
+            waited = waited.max(self.resolve_htlcs(path, &locks, true));
+            chains.push(htlc_chain(path, hops, &reservation.channels, &locks));
+        }
+        if !cover {
+            self.waiting_ms += waited;
+            self.overpaid_msat += overpaid;
+        }
+        let observed = shards.iter().flat_map(|(path, _)| path).any(|node| self.config.malicious_nodes.contains(node));
+        if observed {
+            debug!("  Malicious nodes observed the payment");
+        }
+
+        self.publish(NetworkEvent::PaymentSettled { payment_hash: payment_hash.clone() });
+
+        // A split payment is recorded over its first shard's route, with the rest kept apart
+        let path = shards[0].0.clone();
+        if shards.len() > 1 {
+            self.shards.insert(payment_hash.clone(), shards);
+        }
+        self.htlc_chains.insert(payment_hash.clone(), chains);
+        self.payment_records.push(PaymentRecord {
+            payment_hash: payment_hash.clone(),
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            path,
+            amount,
+            observed,
+            cover,
+        });
+
+        Attempt::Settled(observed)
+    }
+
+    // What every node on a route is handed for `amount` to reach its end: the HTLC, and
+    // its layer of the onion telling it what to pass on, or that it's the recipient. Also
+    // returns what the sender overpaid.
+    fn plan_hops(&mut self, network: &LightningNetworkMap, path: &[String], amount: u64, final_cltv_expiry: u32,
+                 trampoline_hop: Option<usize>, receiver: &str) -> (Vec<HopView>, u64) {
+        // Each forwarding node keeps its fee, so HTLCs grow towards the sender, which
+        // offers the first hop what that hop receives
+        let mut amounts = vec![amount; path.len()];
+        for i in (1..path.len() - 1).rev() {
+            let fee = network.hop_fee_msat(&path[i], &path[i + 1], amounts[i + 1])
+                .or_else(|| network.nodes.get(&path[i]).map(|node| node.forwarding_fee_msat(amounts[i + 1])))
+                .unwrap_or(0);
+            amounts[i] = amounts[i + 1] + fee;
+        }
+        amounts[0] = amounts[1];
+        let overpaid = match &self.config.overpayment {
+            Some(defense) => defense.overpay(&mut amounts, &mut self.rng),
+            None => 0,
+        };
+
+        // The sender names the channel each hop should forward over in its onion
+        let onion_channels: Vec<String> = path.windows(2).enumerate()
+            .map(|(i, hop)| {
+                network.hop_channel(&hop[0], &hop[1], amounts[i + 1])
+                    .or_else(|| network.channels_between(&hop[0], &hop[1]).into_iter().next())
+                    .map(|channel| channel.channel_id.clone())
+                    .unwrap_or_default()
+            })
+            .collect();
 
         // Add CLTV deltas for each hop
         let mut cltv_expiry_values = Vec::new();
         let mut accumulated_delta = 0;
 
         // Simulate CLTV values for each hop (in reverse)
-        for node_pubkey in path.iter().rev().skip(1) {
-            let network = self.network.lock().unwrap();
-            let delta = match network.nodes.get(node_pubkey) {
+        for (node_pubkey, channel) in path.iter().zip(&onion_channels).rev() {
+            let node_delta = match network.nodes.get(node_pubkey) {
                 Some(node) => node.cltv_expiry_delta,
                 None => 14, // Minimum if unknown
             };
-            drop(network);
+            let delta = match self.config.cltv_construction {
+                CltvConstruction::Bolt4 => network.channel(channel)
+                    .and_then(|channel| channel.cltv_delta_of(node_pubkey))
+                    .unwrap_or(node_delta),
+                CltvConstruction::Legacy => node_delta,
+            };
 
             accumulated_delta += delta;
             cltv_expiry_values.push(final_cltv_expiry + accumulated_delta);
@@ -225,40 +716,320 @@ impl PaymentSimulator {
         // Add final value
         cltv_expiry_values.push(final_cltv_expiry);
 
-        // Now simulate the HTLC being observed by malicious nodes
-        let surveillance = self.surveillance.lock().unwrap();
-        let malicious_nodes = surveillance.get_malicious_nodes().to_vec();
-        drop(surveillance);
+        let hops = (0..path.len())
+            .map(|i| HopView {
+                amount: amounts[i],
+                cltv_expiry: cltv_expiry_values[i],
+                payload: match onion_channels.get(i) {
+                    Some(channel) if trampoline_hop == Some(i) => {
+                        HopPayload::forward(channel, amounts[i + 1], cltv_expiry_values[i + 1]).via_trampoline(receiver)
+                    }
+                    Some(channel) => HopPayload::forward(channel, amounts[i + 1], cltv_expiry_values[i + 1]),
+                    None => HopPayload::final_hop(amounts[i], cltv_expiry_values[i]),
+                },
+            })
+            .collect();
+        (hops, overpaid)
+    }
 
-        let mut observed = false;
+    // Route from the sender to the closest trampoline, then on as the trampoline's own
+    // router finds it, with the trampoline's index on the route. None when no trampoline
+    // is configured or can help, e.g. when the sender or recipient is one.
+    fn trampoline_route(&mut self, network: &LightningNetworkMap, sender: &str, receiver: &str, amount: u64)
+                        -> Option<(Vec<String>, usize)> {
+        let (config, rng) = (&self.config, &mut self.rng);
+        let trampolines = &config.trampolines;
+        if trampolines.iter().any(|node| node == sender || node == receiver) {
+            return None;
+        }
+        let (mut path, trampoline) = trampolines.iter()
+            .map(|node| (config.router_of(sender).find_route(network, sender, node, amount, rng), node))
+            .filter(|(path, _)| path.len() >= 2)
+            .min_by_key(|(path, _)| path.len())?;
+        let onward = config.router_of(trampoline).find_route(network, trampoline, receiver, amount, rng);
+        if onward.len() < 2 {
+            return None;
+        }
+        let hop = path.len() - 1;
+        path.extend(onward.into_iter().skip(1));
+        let distinct: HashSet<&String> = path.iter().collect();
+        (distinct.len() == path.len()).then_some((path, hop))
+    }
 
-        for (i, node) in path.iter().enumerate() {
-            if malicious_nodes.contains(node) {
-                let cltv_expiry = cltv_expiry_values[i];
+    // Publish the HTLC each of these nodes, the start of a route, sees, having come in over
+    // the channels the hops before it used. Returns the lock each node saw.
+    fn forward_htlcs(&mut self, payment_hash: &str, nodes: &[String], hops: &[HopView],
+                     incoming_channels: &[String], current_height: u32) -> Vec<String> {
+        let mut locks = Vec::with_capacity(nodes.len());
 
-                let htlc = HTLC::new(
-                    &payment_hash,
-                    cltv_expiry,
-                    amount,
-                    current_height,
-                    node
-                );
+        for (i, node) in nodes.iter().enumerate() {
+            // With PTLCs the point is tweaked at every hop, so no two hops share one
+            let lock = if self.config.ptlc {
+                let point = format!("point_{:016x}", self.rng.random::<u64>());
+                self.hop_owners.insert(point.clone(), payment_hash.to_string());
+                point
+            } else {
+                payment_hash.to_string()
+            };
+            let incoming_channel = i.checked_sub(1).and_then(|prev| incoming_channels.get(prev));
+            let htlc = HTLC::new(
+                &lock,
+                hops[i].cltv_expiry,
+                hops[i].amount,
+                current_height,
+                node
+            ).with_onion(incoming_channel.map(String::as_str), hops[i].payload.clone());
+            self.publish(NetworkEvent::HtlcForwarded(htlc));
+            locks.push(lock);
+        }
 
-                // Record the observation
-                let mut surveillance = self.surveillance.lock().unwrap();
-                surveillance.record_htlc_observation(htlc);
-                drop(surveillance);
+        locks
+    }
 
-                println!("  Malicious node {} observed HTLC!", node);
-                observed = true;
+    // How long messages take between two peers: by their regions when geography is modeled,
+    // and slowed down when either is reachable only over Tor
+    fn link_latency(&self, a: &str, b: &str) -> HopLatency {
+        let mut latency = self.config.hop_latency;
+        let regions = (self.node_regions.get(a), self.node_regions.get(b));
+        if let (Some(model), (Some(region_a), Some(region_b))) = (&self.config.regions, regions) {
+            if let Some(mean_ms) = model.between(region_a, region_b) {
+                latency.mean_ms = mean_ms;
             }
         }
+        if self.tor_nodes.contains(a) || self.tor_nodes.contains(b) {
+            latency = latency.over_tor();
+        }
+        latency
+    }
 
-        // Simulate some time passing between payments if delay is set
-        if self.delay_ms > 0 {
-            sleep(Duration::from_millis(self.delay_ms)).await;
+    // The last of these nodes settles or fails its HTLC, and the preimage or failure makes
+    // its way back to the first. Publish when each of them saw its HTLC resolve, and return
+    // how long the first one waited.
+    fn resolve_htlcs(&mut self, nodes: &[String], locks: &[String], settled: bool) -> u64 {
+        let links: Vec<HopLatency> = nodes.windows(2).map(|pair| self.link_latency(&pair[0], &pair[1])).collect();
+        let mut holds = HopLatency::hold_times_over(&links, &mut self.rng);
+        if let Some(defense) = &self.config.latency_padding {
+            let honest: Vec<bool> = nodes.iter().map(|node| !self.config.malicious_nodes.contains(node)).collect();
+            defense.pad(&mut holds, &honest, &mut self.rng);
+        }
+        let waited = holds.first().copied().unwrap_or(0);
+        for ((node, lock), hold_ms) in nodes.iter().zip(locks).zip(holds).rev() {
+            self.publish(NetworkEvent::HtlcResolved(HtlcResolution::new(lock, node, settled, hold_ms)));
         }
+        waited
+    }
+}
 
-        Ok(observed)
+// The HTLC each node of a route was offered over the channel from the node before it
+fn htlc_chain(nodes: &[String], hops: &[HopView], channels: &[String], locks: &[String]) -> HtlcChain {
+    HtlcChain::new((1..nodes.len()).map(|i| ChannelHtlc {
+        channel_id: channels.get(i - 1).cloned().unwrap_or_default(),
+        from: nodes[i - 1].clone(),
+        to: nodes[i].clone(),
+        payment_hash: locks[i].clone(),
+        amount: hops[i].amount,
+        cltv_expiry: hops[i].cltv_expiry,
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use crate::models::{Node, Channel};
+    use crate::simulation::RetryPolicy;
+    use crate::surveillance::{SurveillanceConfig, SurveillanceOperation};
+
+    // Custom observer that only cares about failures
+    #[derive(Default)]
+    struct FailureCounter {
+        failed: usize,
+        reasons: Vec<String>,
+    }
+
+    impl Observer for FailureCounter {
+        fn on_fail(&mut self, _sender: &str, _receiver: &str, reason: &str) -> Result<(), ThelmaError> {
+            self.failed += 1;
+            self.reasons.push(reason.to_string());
+            Ok(())
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_parallel_simulation_publishes_to_every_subscriber() {
+        // Star around a malicious hub: every payment between leaves crosses it
+        let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(700000)));
+        {
+            let mut network = network_map.write().unwrap();
+            network.add_node(Node::new("hub", "Hub", 40));
+            for leaf in ["a", "b", "c", "d"] {
+                network.add_node(Node::new(leaf, leaf, 40));
+                network.add_channel(Channel::new(&format!("hub-{}", leaf), "hub", leaf, 10_000_000));
+            }
+        }
+
+        let operation = SurveillanceOperation::new(network_map.clone(), SurveillanceConfig::observing(vec!["hub".to_string()])).unwrap();
+        let surveillance = Arc::new(Mutex::new(operation));
+        let config = SimulatorConfig::new().workers(4).malicious_nodes(vec!["hub".to_string()]);
+        let mut simulator = PaymentSimulator::new(network_map, config);
+
+        // A second, independent subscriber sees the same traffic
+        let mut events = simulator.subscribe();
+        let observer = simulator.register_observer(surveillance.clone());
+        let failures = Arc::new(Mutex::new(FailureCounter::default()));
+        let failure_observer = simulator.register_observer(failures.clone());
+
+        let observed = simulator.simulate_payments(40).await.unwrap();
+        simulator.close_events();
+        observer.await.unwrap();
+        failure_observer.await.unwrap();
+        assert_eq!(failures.lock().unwrap().failed, 0);
+
+        let mut settled = 0;
+        while let Ok(event) = events.recv().await {
+            if let NetworkEvent::PaymentSettled { .. } = event {
+                settled += 1;
+            }
+        }
+        assert_eq!(settled, 40);
+
+        // Every worker's payments and observations end up in the same place
+        assert_eq!(simulator.payment_records().len(), 40);
+        assert_eq!(simulator.payment_records().iter().filter(|r| r.observed).count(), observed);
+        assert_eq!(surveillance.lock().unwrap().observation_count(), observed);
+        assert!(observed > 0);
+    }
+
+    #[tokio::test]
+    async fn test_stop_signal_ends_the_run_early() {
+        let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(700000)));
+        {
+            let mut network = network_map.write().unwrap();
+            for node in ["a", "b", "c"] {
+                network.add_node(Node::new(node, node, 40));
+            }
+            network.add_channel(Channel::new("a-b", "a", "b", 10_000_000));
+            network.add_channel(Channel::new("b-c", "b", "c", 10_000_000));
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let config = SimulatorConfig::new().workers(2).stop_signal(stop.clone());
+        let mut simulator = PaymentSimulator::new(network_map, config);
+
+        simulator.simulate_payments(10).await.unwrap();
+        assert!(!simulator.was_interrupted());
+        assert_eq!(simulator.payments_attempted(), 10);
+
+        // Once raised, no further payments start and earlier records are kept
+        stop.store(true, Ordering::Relaxed);
+        simulator.simulate_payments(10).await.unwrap();
+        assert!(simulator.was_interrupted());
+        assert_eq!(simulator.payments_attempted(), 10);
+        assert_eq!(simulator.payment_records().len(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_settled_payments_record_every_hops_htlcs() {
+        let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(700000)));
+        {
+            let mut network = network_map.write().unwrap();
+            for node in ["a", "b", "c", "d"] {
+                network.add_node(Node::new(node, node, 40));
+            }
+            for (from, to) in [("a", "b"), ("b", "c"), ("c", "d")] {
+                network.add_channel(Channel::new(&format!("{}-{}", from, to), from, to, 10_000_000));
+            }
+        }
+
+        let mut simulator = PaymentSimulator::new(network_map.clone(), SimulatorConfig::new());
+        simulator.simulate_specific_payment("a", "d").await.unwrap();
+        let record = simulator.payment_records()[0].clone();
+        let chain = &simulator.htlc_chains()[&record.payment_hash][0];
+
+        // One HTLC per channel crossed, the last paying the recipient exactly
+        assert_eq!(chain.hops.iter().map(|hop| hop.channel_id.as_str()).collect::<Vec<_>>(), vec!["a-b", "b-c", "c-d"]);
+        assert_eq!(chain.hops[2].amount, record.amount);
+        assert!(chain.hops.iter().all(|hop| hop.payment_hash == record.payment_hash));
+
+        // b and c each kept their fee and their delta
+        let network = network_map.read().unwrap();
+        let forwards: Vec<_> = chain.forwards().collect();
+        assert_eq!(forwards.iter().map(|forward| forward.node()).collect::<Vec<_>>(), vec!["b", "c"]);
+        for forward in forwards {
+            assert_eq!(forward.cltv_delta(), 40);
+            let fee = network.hop_fee_msat(forward.node(), &forward.outgoing.to, forward.outgoing.amount).unwrap();
+            assert_eq!(forward.fee_msat(), fee);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_timelocks_use_the_outgoing_channels_delta() {
+        let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(700000)));
+        {
+            let mut network = network_map.write().unwrap();
+            for node in ["a", "b", "c"] {
+                network.add_node(Node::new(node, node, 40));
+            }
+            network.add_channel(Channel::new("a-b", "a", "b", 10_000_000));
+            // b asks for 144 blocks on its channel to c, and its usual 40 elsewhere
+            network.add_channel(Channel::new("b-c", "b", "c", 10_000_000).with_cltv_delta("b", 144));
+        }
+
+        let mut deltas = Vec::new();
+        for construction in [CltvConstruction::Bolt4, CltvConstruction::Legacy] {
+            let config = SimulatorConfig::new().cltv_construction(construction);
+            let mut simulator = PaymentSimulator::new(network_map.clone(), config);
+            simulator.simulate_specific_payment("a", "c").await.unwrap();
+            let record = &simulator.payment_records()[0];
+            let chain = &simulator.htlc_chains()[&record.payment_hash][0];
+            deltas.push(chain.forwards().next().unwrap().cltv_delta());
+        }
+        assert_eq!(deltas, vec![144, 40]);
+    }
+
+    #[tokio::test]
+    async fn test_retries_stop_at_invoice_expiry() {
+        let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(700000)));
+        {
+            let mut network = network_map.write().unwrap();
+            for node in ["a", "b", "c"] {
+                network.add_node(Node::new(node, node, 40));
+            }
+            network.add_channel(Channel::new("a-b", "a", "b", 10_000_000));
+            network.add_channel(Channel::new("b-c", "b", "c", 10_000_000).with_max_accepted_htlcs(1));
+        }
+
+        // The first payment holds b's only slot towards c for longer than the next invoice lasts
+        let config = SimulatorConfig::new()
+            .htlc_hold(10)
+            .retries(RetryPolicy::new(5))
+            .invoice_expiry(3);
+        let mut simulator = PaymentSimulator::new(network_map.clone(), config);
+        let mut events = simulator.subscribe();
+        let failures = Arc::new(Mutex::new(FailureCounter::default()));
+        let failure_observer = simulator.register_observer(failures.clone());
+
+        assert!(!simulator.simulate_specific_payment("a", "c").await.unwrap());
+        assert!(!simulator.simulate_specific_payment("a", "c").await.unwrap());
+        simulator.close_events();
+        failure_observer.await.unwrap();
+
+        // Tried at three blocks, then abandoned rather than retried into expiry
+        assert_eq!(failures.lock().unwrap().reasons, vec!["invoice expired after 3 attempts"]);
+        assert_eq!(simulator.payment_records().len(), 1);
+        assert_eq!(network_map.read().unwrap().current_block_height, 700002);
+
+        // b saw the failed attempts under the same hash, once per block
+        let mut sightings = Vec::new();
+        while let Ok(event) = events.recv().await {
+            if let NetworkEvent::HtlcForwarded(htlc) = event {
+                if htlc.observed_by_node == "b" {
+                    sightings.push((htlc.payment_hash, htlc.observed_at_block));
+                }
+            }
+        }
+        assert_eq!(sightings.len(), 4);
+        assert!(sightings[1..].iter().all(|(hash, _)| *hash == sightings[1].0));
+        assert_eq!(sightings.iter().map(|(_, block)| *block).collect::<Vec<_>>(), vec![700000, 700000, 700001, 700002]);
     }
 }
@@ -0,0 +1,161 @@
+// Geographic regions with a one-way latency between every pair, so links inside a continent
+// are quick and links across oceans slow. Nodes are spread over the regions by their share.
+
+use rand::Rng;
+use serde_json::Value;
+use log::info;
+
+use crate::models::LightningNetworkMap;
+use crate::error::ThelmaError;
+
+// Name `--regions` takes for the built-in model
+pub const WORLD_REGIONS: &str = "world";
+
+// Roughly where public nodes are, and half the round trips between those places
+const WORLD: [(&str, f64); 5] = [
+    ("north-america", 0.35),
+    ("europe", 0.40),
+    ("asia", 0.15),
+    ("south-america", 0.05),
+    ("oceania", 0.05),
+];
+const WORLD_LATENCY_MS: [[u64; 5]; 5] = [
+    [20, 45, 90, 60, 80],
+    [45, 10, 100, 100, 140],
+    [90, 100, 30, 160, 60],
+    [60, 100, 160, 20, 150],
+    [80, 140, 60, 150, 15],
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegionLatency {
+    // Region names and the share of nodes in each
+    pub regions: Vec<(String, f64)>,
+    // Milliseconds a message takes from one region to another, by region index
+    pub latency_ms: Vec<Vec<u64>>,
+}
+
+impl Default for RegionLatency {
+    fn default() -> Self {
+        RegionLatency {
+            regions: WORLD.iter().map(|(name, share)| (name.to_string(), *share)).collect(),
+            latency_ms: WORLD_LATENCY_MS.iter().map(|row| row.to_vec()).collect(),
+        }
+    }
+}
+
+impl RegionLatency {
+    // `{"regions": [{"name": "europe", "share": 0.4}, ...], "latency_ms": [[10, 45], ...]}`,
+    // the matrix square with a row per region in order. Shares are relative.
+    pub fn parse(json: &str) -> Result<Self, ThelmaError> {
+        let value: Value = serde_json::from_str(json)?;
+        let invalid = |reason: &str| ThelmaError::Config(format!("invalid regions file: {}", reason));
+
+        let entries = value["regions"].as_array().filter(|r| !r.is_empty()).ok_or_else(|| invalid("no regions"))?;
+        let mut regions = Vec::new();
+        for entry in entries {
+            let name = entry["name"].as_str().ok_or_else(|| invalid("a region has no name"))?;
+            let share = entry["share"].as_f64().unwrap_or(1.0);
+            if share < 0.0 {
+                return Err(invalid(&format!("region {} has a negative share", name)));
+            }
+            regions.push((name.to_string(), share));
+        }
+        if regions.iter().all(|(_, share)| *share == 0.0) {
+            return Err(invalid("every share is zero"));
+        }
+
+        let rows = value["latency_ms"].as_array().ok_or_else(|| invalid("no latency_ms matrix"))?;
+        let latency_ms: Vec<Vec<u64>> = rows.iter()
+            .map(|row| row.as_array().map(|cells| cells.iter().filter_map(Value::as_u64).collect()))
+            .collect::<Option<_>>()
+            .ok_or_else(|| invalid("latency_ms rows must be arrays"))?;
+        if latency_ms.len() != regions.len() || latency_ms.iter().any(|row| row.len() != regions.len()) {
+            return Err(invalid(&format!("latency_ms must be {0}x{0} for {0} regions", regions.len())));
+        }
+        Ok(RegionLatency { regions, latency_ms })
+    }
+
+    // The built-in world, or a regions file
+    pub fn load(spec: &str) -> Result<Self, ThelmaError> {
+        if spec == WORLD_REGIONS {
+            return Ok(RegionLatency::default());
+        }
+        let contents = std::fs::read_to_string(spec)
+            .map_err(|e| ThelmaError::Config(format!("can't read regions file {}: {}", spec, e)))?;
+        let regions = RegionLatency::parse(&contents)?;
+        info!("Loaded {} regions from {}", regions.regions.len(), spec);
+        Ok(regions)
+    }
+
+    pub fn index(&self, region: &str) -> Option<usize> {
+        self.regions.iter().position(|(name, _)| name == region)
+    }
+
+    // One-way latency between two regions, if both are known
+    pub fn between(&self, a: &str, b: &str) -> Option<u64> {
+        Some(self.latency_ms[self.index(a)?][self.index(b)?])
+    }
+
+    // Latency between two nodes drawn by share, which a uniform latency model would assume
+    pub fn mean_ms(&self) -> u64 {
+        let total: f64 = self.regions.iter().map(|(_, share)| share).sum();
+        let mut mean = 0.0;
+        for (i, (_, a)) in self.regions.iter().enumerate() {
+            for (j, (_, b)) in self.regions.iter().enumerate() {
+                mean += a * b * self.latency_ms[i][j] as f64;
+            }
+        }
+        (mean / (total * total)).round() as u64
+    }
+
+    // Put every node in a region drawn by share, in key order so seeded runs agree
+    pub fn assign(&self, network: &mut LightningNetworkMap, rng: &mut impl Rng) {
+        let total: f64 = self.regions.iter().map(|(_, share)| share).sum();
+        let mut keys: Vec<String> = network.nodes.keys().cloned().collect();
+        keys.sort();
+        for key in keys {
+            let mut pick = rng.random::<f64>() * total;
+            let region = self.regions.iter()
+                .find(|(_, share)| {
+                    pick -= share;
+                    pick < 0.0
+                })
+                .or(self.regions.last())
+                .map(|(name, _)| name.clone());
+            if let Some(node) = network.nodes.get_mut(&key) {
+                node.region = region;
+            }
+        }
+        info!("Spread {} nodes over {} regions", network.nodes.len(), self.regions.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Node;
+
+    #[test]
+    fn test_regions_file_and_assignment() {
+        let regions = RegionLatency::parse(r#"{
+            "regions": [{"name": "east", "share": 1}, {"name": "west", "share": 0}],
+            "latency_ms": [[5, 70], [70, 8]]
+        }"#).unwrap();
+        assert_eq!(regions.between("east", "west"), Some(70));
+        assert_eq!(regions.between("west", "west"), Some(8));
+        assert_eq!(regions.between("east", "north"), None);
+        assert_eq!(regions.mean_ms(), 5);
+        assert!(RegionLatency::parse(r#"{"regions": [{"name": "east"}], "latency_ms": [[5, 70]]}"#).is_err());
+
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["a", "b", "c"] {
+            network.add_node(Node::new(key, key, 40));
+        }
+        regions.assign(&mut network, &mut rand::rng());
+        assert!(network.nodes.values().all(|node| node.region.as_deref() == Some("east")));
+
+        let world = RegionLatency::load(WORLD_REGIONS).unwrap();
+        assert_eq!(world.between("europe", "oceania"), world.between("oceania", "europe"));
+    }
+}
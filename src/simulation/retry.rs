@@ -0,0 +1,73 @@
+// Payment retries. A payment that fails in flight is tried again under the same invoice,
+// and so the same payment hash, until the sender runs out of attempts or the invoice
+// expires. Observers on the failed attempts see the hash again at a later block.
+
+use crate::models::Invoice;
+
+// Attempts after the first a sender makes by default
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+// Blocks mined while the sender waits to try again
+pub const DEFAULT_RETRY_BLOCKS: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub blocks_between_attempts: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::new(DEFAULT_MAX_RETRIES)
+    }
+}
+
+// Why a sender stops trying a failed payment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GiveUp {
+    AttemptsExhausted,
+    InvoiceExpired,
+}
+
+impl RetryPolicy {
+    pub fn new(max_retries: u32) -> Self {
+        RetryPolicy { max_retries, blocks_between_attempts: DEFAULT_RETRY_BLOCKS }
+    }
+
+    pub fn blocks_between_attempts(mut self, blocks: u32) -> Self {
+        self.blocks_between_attempts = blocks;
+        self
+    }
+
+    // Block a payment that has failed `attempts` times at `height` is tried again at.
+    // Senders abandon a payment whose invoice will have expired by then, however many
+    // attempts they have left.
+    pub fn next_attempt(&self, attempts: u32, invoice: &Invoice, height: u32) -> Result<u32, GiveUp> {
+        let retry_at = height.saturating_add(self.blocks_between_attempts);
+        if attempts > self.max_retries {
+            Err(GiveUp::AttemptsExhausted)
+        } else if invoice.is_expired(retry_at) {
+            Err(GiveUp::InvoiceExpired)
+        } else {
+            Ok(retry_at)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_senders_give_up_on_expiry() {
+        let invoice = Invoice::new("hash", "b", 50_000, 40, 700_000).expiry_blocks(3);
+        let policy = RetryPolicy::new(5);
+        assert_eq!(policy.next_attempt(1, &invoice, 700_000), Ok(700_001));
+        assert_eq!(policy.next_attempt(2, &invoice, 700_001), Ok(700_002));
+        assert_eq!(policy.next_attempt(3, &invoice, 700_002), Err(GiveUp::InvoiceExpired));
+
+        // Without expiry in the way the attempts run out
+        let invoice = invoice.expiry_blocks(100);
+        assert_eq!(policy.next_attempt(5, &invoice, 700_005), Ok(700_006));
+        assert_eq!(policy.next_attempt(6, &invoice, 700_006), Err(GiveUp::AttemptsExhausted));
+    }
+}
@@ -0,0 +1,252 @@
+// Pluggable path selection for senders
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::sync::Arc;
+use rand::Rng;
+use rand::rngs::StdRng;
+
+use crate::models::LightningNetworkMap;
+use crate::simulation::utils::find_path_avoiding;
+
+// Names accepted by `router_from_name`
+pub const ROUTER_NAMES: &[&str] = &["bfs", "dijkstra", "randomized"];
+
+// Nodes from the sender to the recipient, empty when no usable route exists
+pub type Route = Vec<String>;
+
+// How a sender picks the path for a payment. Only channels that can carry the amount
+// may be used. Routers are shared across simulation tasks, so they must be thread safe,
+// and draw any randomness from the caller's RNG so that a seed fixes the routes too.
+pub trait Router: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn find_route(&self, network: &LightningNetworkMap, source: &str, dest: &str, amount_msat: u64,
+                  rng: &mut StdRng) -> Route;
+}
+
+// Look up a router by its CLI name
+pub fn router_from_name(name: &str) -> Option<Arc<dyn Router>> {
+    match name {
+        "bfs" => Some(Arc::new(BfsRouter)),
+        "dijkstra" => Some(Arc::new(DijkstraRouter)),
+        "randomized" => Some(Arc::new(RandomizedRouter::default())),
+        _ => None,
+    }
+}
+
+// Fewest hops, the simulator's historical behavior
+pub struct BfsRouter;
+
+impl Router for BfsRouter {
+    fn name(&self) -> &'static str {
+        "bfs"
+    }
+
+    fn find_route(&self, network: &LightningNetworkMap, source: &str, dest: &str, amount_msat: u64,
+                  _rng: &mut StdRng) -> Route {
+        find_path_avoiding(network, source, dest, &HashSet::new(), amount_msat)
+    }
+}
+
+// Cheapest route by forwarding fees, as most implementations pick. Ties go to fewer hops.
+pub struct DijkstraRouter;
+
+impl Router for DijkstraRouter {
+    fn name(&self) -> &'static str {
+        "dijkstra"
+    }
+
+    fn find_route(&self, network: &LightningNetworkMap, source: &str, dest: &str, amount_msat: u64,
+                  _rng: &mut StdRng) -> Route {
+        // Every node after the sender forwards, except the recipient, each paying what the
+        // cheapest of its channels to the next node charges
+        let fee_of = |node: &str, next: &str| -> Option<u64> {
+            if node == source {
+                network.can_carry(node, next, amount_msat).then_some(0)
+            } else {
+                network.hop_fee_msat(node, next, amount_msat)
+            }
+        };
+
+        let mut best: HashMap<String, (u64, usize)> = HashMap::new();
+        let mut pred: HashMap<String, String> = HashMap::new();
+        let mut queue = BinaryHeap::new();
+
+        best.insert(source.to_string(), (0, 0));
+        queue.push(Reverse((0u64, 0usize, source.to_string())));
+
+        while let Some(Reverse((fee, hops, current))) = queue.pop() {
+            if current == dest {
+                break;
+            }
+            if best.get(&current).is_some_and(|&cost| cost < (fee, hops)) {
+                continue;
+            }
+
+            let Some(neighbors) = network.get_neighbors(&current) else { continue };
+            for neighbor in neighbors {
+                let Some(hop_fee) = fee_of(&current, neighbor) else { continue };
+                let cost = (fee + hop_fee, hops + 1);
+                if best.get(neighbor).is_none_or(|&known| cost < known) {
+                    best.insert(neighbor.clone(), cost);
+                    pred.insert(neighbor.clone(), current.clone());
+                    queue.push(Reverse((cost.0, cost.1, neighbor.clone())));
+                }
+            }
+        }
+
+        if source != dest && !pred.contains_key(dest) {
+            return Vec::new();
+        }
+
+        let mut route = vec![dest.to_string()];
+        let mut current = dest;
+        while current != source {
+            current = &pred[current];
+            route.push(current.to_string());
+        }
+        route.reverse();
+        route
+    }
+}
+
+// Detours through random intermediate nodes, so routes aren't always shortest
+pub struct RandomizedRouter {
+    // Chance of taking the shortest route anyway
+    pub direct_probability: f64,
+    // Most intermediate nodes to detour through
+    pub max_intermediates: usize,
+}
+
+impl Default for RandomizedRouter {
+    fn default() -> Self {
+        RandomizedRouter { direct_probability: 0.2, max_intermediates: 2 }
+    }
+}
+
+impl Router for RandomizedRouter {
+    fn name(&self) -> &'static str {
+        "randomized"
+    }
+
+    fn find_route(&self, network: &LightningNetworkMap, source: &str, dest: &str, amount_msat: u64,
+                  rng: &mut StdRng) -> Route {
+        let shortest = || find_path_avoiding(network, source, dest, &HashSet::new(), amount_msat);
+
+        if self.max_intermediates == 0 || rng.random_bool(self.direct_probability) {
+            return shortest();
+        }
+
+        // Pick distinct intermediates other than the endpoints
+        let mut candidates: Vec<&String> = network.nodes.keys()
+            .filter(|node| *node != source && *node != dest)
+            .collect();
+        // Sorted, since map order would change the picks between runs
+        candidates.sort();
+        if candidates.is_empty() {
+            return shortest();
+        }
+        let count = rng.random_range(1..=self.max_intermediates);
+        let mut waypoints: Vec<&str> = Vec::new();
+        for _ in 0..count {
+            let node = candidates[rng.random_range(0..candidates.len())].as_str();
+            if !waypoints.contains(&node) {
+                waypoints.push(node);
+            }
+        }
+        waypoints.push(dest);
+
+        // Join segments, never revisiting a node so the result is a simple path
+        let mut route = vec![source.to_string()];
+        let mut used: HashSet<String> = route.iter().cloned().collect();
+        for waypoint in waypoints {
+            // An earlier segment may already have passed through this waypoint
+            if used.contains(waypoint) {
+                continue;
+            }
+            let from = route.last().unwrap().clone();
+            // Later segments mustn't pass through the recipient before reaching it
+            let mut avoid = used.clone();
+            avoid.remove(&from);
+            if waypoint != dest {
+                avoid.insert(dest.to_string());
+            }
+
+            let segment = find_path_avoiding(network, &from, waypoint, &avoid, amount_msat);
+            if segment.len() < 2 {
+                return shortest();
+            }
+            for node in segment.into_iter().skip(1) {
+                used.insert(node.clone());
+                route.push(node);
+            }
+        }
+
+        route
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use crate::models::{Channel, ChannelFees, Node};
+
+    #[test]
+    fn test_routers_find_valid_routes() {
+        // s - hub - d is short but the hub is expensive; s - a - b - d is cheap
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["s", "a", "b", "d", "x"] {
+            network.add_node(Node::new(key, key, 40));
+        }
+        let mut hub = Node::new("hub", "hub", 40);
+        hub.base_fee_msat = 50_000;
+        network.add_node(hub);
+        for (id, a, b) in [("s-hub", "s", "hub"), ("hub-d", "hub", "d"), ("s-a", "s", "a"),
+                           ("a-b", "a", "b"), ("b-d", "b", "d"), ("b-x", "b", "x"), ("x-hub", "x", "hub")] {
+            network.add_channel(Channel::new(id, a, b, 1_000_000));
+        }
+
+        let amount = 100_000;
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(BfsRouter.find_route(&network, "s", "d", amount, &mut rng), vec!["s", "hub", "d"]);
+        assert_eq!(DijkstraRouter.find_route(&network, "s", "d", amount, &mut rng), vec!["s", "a", "b", "d"]);
+
+        // A cheap parallel channel from the hub to d makes the short route the cheapest, until
+        // it is replaced by one too small for the amount
+        let cheap = ChannelFees { base_fee_msat: 0, fee_rate_ppm: 0 };
+        let mut parallel = network.clone();
+        parallel.add_channel(Channel::new("hub-d-2", "hub", "d", 1_000_000).with_fees("hub", cheap));
+        assert_eq!(DijkstraRouter.find_route(&parallel, "s", "d", amount, &mut rng), vec!["s", "hub", "d"]);
+        assert_eq!(parallel.hop_channel("hub", "d", amount).map(|c| c.channel_id.as_str()), Some("hub-d-2"));
+        assert_eq!(parallel.route_fee_msat(&["s".to_string(), "hub".to_string(), "d".to_string()], amount), 0);
+        parallel.add_channel(Channel::new("hub-d-2", "hub", "d", 50).with_fees("hub", cheap));
+        assert_eq!(parallel.channels.len(), network.channels.len() + 1);
+        assert_eq!(parallel.channel("hub-d-2").map(|c| c.capacity), Some(50));
+        assert_eq!(DijkstraRouter.find_route(&parallel, "s", "d", amount, &mut rng), vec!["s", "a", "b", "d"]);
+
+        let randomized = RandomizedRouter { direct_probability: 0.0, max_intermediates: 2 };
+        for _ in 0..50 {
+            let route = randomized.find_route(&network, "s", "d", amount, &mut rng);
+            assert_eq!(route.first().map(String::as_str), Some("s"));
+            assert_eq!(route.last().map(String::as_str), Some("d"));
+            let unique: HashSet<&String> = route.iter().collect();
+            assert_eq!(unique.len(), route.len(), "route {:?} revisits a node", route);
+            assert!(route.windows(2).all(|hop| network.can_carry(&hop[0], &hop[1], amount)));
+        }
+
+        // The same seed detours through the same nodes
+        let detours = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..20).map(|_| randomized.find_route(&network, "s", "d", amount, &mut rng)).collect::<Vec<_>>()
+        };
+        assert_eq!(detours(7), detours(7));
+
+        // No channel carries this much
+        for name in ROUTER_NAMES {
+            let router = router_from_name(name).unwrap();
+            assert!(router.find_route(&network, "s", "d", 5_000_000_000, &mut rng).is_empty());
+        }
+    }
+}
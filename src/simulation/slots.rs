@@ -0,0 +1,130 @@
+// In-flight HTLC slots. Each side of a channel accepts at most `max_accepted_htlcs` HTLCs
+// at a time, and a payment holds a slot on every hop until it resolves. Once a hop's slots
+// are used up, payments through it fail until some are freed, which is what channel
+// jamming exploits.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::models::LightningNetworkMap;
+
+// A slot is held on a channel in the direction from the node offering the HTLC
+type SlotKey = (String, String);
+
+// Where a payment's HTLC went: the channel each hop forwarded it over, up to the hop that
+// had no free slot if there was one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reservation {
+    pub channels: Vec<String>,
+    pub blocked: Option<usize>,
+}
+
+// Slots in use across the network, with the payments they're released after. Time counts
+// payment attempts, the same clock traces use.
+#[derive(Debug, Default)]
+pub struct HtlcSlots {
+    // Payments an HTLC stays in flight for; 0 resolves it before the next one starts
+    hold_payments: u64,
+    clock: u64,
+    in_flight: HashMap<SlotKey, u16>,
+    // Slots to free, in the order they're due
+    held: VecDeque<(u64, Vec<SlotKey>)>,
+}
+
+impl HtlcSlots {
+    pub fn new(hold_payments: u64) -> Self {
+        HtlcSlots { hold_payments, ..HtlcSlots::default() }
+    }
+
+    // A payment attempt starts: free the slots of payments that have resolved by now
+    pub fn tick(&mut self) {
+        self.clock += 1;
+        while self.held.front().is_some_and(|(due, _)| *due <= self.clock) {
+            if let Some((_, keys)) = self.held.pop_front() {
+                self.release(&keys);
+            }
+        }
+    }
+
+    // HTLCs `node` has in flight over the channel
+    pub fn in_flight(&self, channel_id: &str, node: &str) -> u16 {
+        self.in_flight.get(&(channel_id.to_string(), node.to_string())).copied().unwrap_or(0)
+    }
+
+    // Take a slot on every hop of the path for a payment of this amount and hold them for
+    // the configured number of payments. A node may forward over any of its channels to
+    // the next hop, so a full channel only fails the payment when every channel that can
+    // carry the amount is full too. On failure nothing is taken.
+    pub fn reserve(&mut self, network: &LightningNetworkMap, path: &[String], amount_msat: u64) -> Reservation {
+        let mut keys = Vec::with_capacity(path.len().saturating_sub(1));
+        for (i, hop) in path.windows(2).enumerate() {
+            let preferred = network.hop_channel(&hop[0], &hop[1], amount_msat);
+            let channel = preferred.into_iter()
+                .chain(network.channels_between(&hop[0], &hop[1]).into_iter().filter(|c| c.can_forward(amount_msat)))
+                .find(|channel| self.in_flight(&channel.channel_id, &hop[0]) < channel.max_accepted_htlcs);
+            match channel {
+                Some(channel) => keys.push((channel.channel_id.clone(), hop[0].clone())),
+                None => {
+                    let channels = keys.into_iter().map(|(channel, _)| channel).collect();
+                    return Reservation { channels, blocked: Some(i) };
+                }
+            }
+        }
+        let channels = keys.iter().map(|(channel, _)| channel.clone()).collect();
+
+        if self.hold_payments > 0 {
+            for key in &keys {
+                *self.in_flight.entry(key.clone()).or_default() += 1;
+            }
+            self.held.push_back((self.clock + self.hold_payments, keys));
+        }
+        Reservation { channels, blocked: None }
+    }
+
+    fn release(&mut self, keys: &[SlotKey]) {
+        for key in keys {
+            if let Some(count) = self.in_flight.get_mut(key) {
+                *count -= 1;
+                if *count == 0 {
+                    self.in_flight.remove(key);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Channel, Node};
+
+    #[test]
+    fn test_slots_fill_up_and_free() {
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["a", "b", "c"] {
+            network.add_node(Node::new(key, key, 40));
+        }
+        network.add_channel(Channel::new("ab", "a", "b", 1_000_000).with_max_accepted_htlcs(4));
+        network.add_channel(Channel::new("bc", "b", "c", 1_000_000).with_max_accepted_htlcs(1));
+        let path: Vec<String> = ["a", "b", "c"].iter().map(|n| n.to_string()).collect();
+        let back: Vec<String> = ["c", "b"].iter().map(|n| n.to_string()).collect();
+
+        let mut slots = HtlcSlots::new(2);
+        slots.tick();
+        assert_eq!(slots.reserve(&network, &path, 50_000).channels, vec!["ab", "bc"]);
+        assert_eq!(slots.reserve(&network, &path, 50_000), Reservation { channels: vec!["ab".to_string()], blocked: Some(1) });
+        // The failed attempt took nothing, and the other direction has slots of its own
+        assert_eq!(slots.in_flight("ab", "a"), 1);
+        assert_eq!(slots.reserve(&network, &back, 50_000).blocked, None);
+
+        // A parallel channel with a free slot carries the hop instead
+        network.add_channel(Channel::new("bc2", "b", "c", 1_000_000).with_max_accepted_htlcs(1));
+        assert_eq!(slots.reserve(&network, &path, 50_000).channels, vec!["ab", "bc2"]);
+        assert!(slots.reserve(&network, &path, 50_000).blocked.is_some());
+
+        // Two payments later the first ones resolve
+        slots.tick();
+        slots.tick();
+        assert_eq!(slots.in_flight("ab", "a"), 0);
+        assert_eq!(slots.reserve(&network, &path, 50_000).blocked, None);
+    }
+}
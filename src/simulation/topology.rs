@@ -0,0 +1,548 @@
+// Topology models the simulated network can be built from
+
+use std::collections::{HashMap, HashSet};
+use rand::Rng;
+use rand::rngs::StdRng;
+use serde_json::Value;
+use log::info;
+
+use crate::models::{Node, Channel, ChannelFees, ChannelStatus, FinalDeltaPrior, LightningNetworkMap, ShortChannelId,
+                    DEFAULT_DUST_LIMIT_SAT, DEFAULT_MAX_DUST_EXPOSURE_MSAT};
+use crate::simulation::fixtures::FixtureTopology;
+use crate::error::ThelmaError;
+
+// Names accepted by `topology_from_name`, in the order they're listed in usage
+pub const TOPOLOGY_NAMES: &[&str] = &["simple", "scale-free", "small-world", "imported", "fixture"];
+
+// A way of populating a network map. New topology models implement this and get a name
+// in `topology_from_name`.
+pub trait TopologyGenerator {
+    fn name(&self) -> &'static str;
+
+    // Add nodes and channels to the network. Generated models create `node_count` nodes,
+    // models that load a real graph may ignore it. All randomness comes from `rng`, so a
+    // seeded one rebuilds the same network.
+    fn generate(&mut self, network: &mut LightningNetworkMap, node_count: usize, rng: &mut StdRng) -> Result<(), ThelmaError>;
+}
+
+// Look up a topology model by its CLI name. `source` is the graph file for `imported`.
+pub fn topology_from_name(name: &str, source: Option<&str>) -> Result<Box<dyn TopologyGenerator>, ThelmaError> {
+    match name {
+        "simple" => Ok(Box::new(SimpleTopology)),
+        "scale-free" => Ok(Box::new(ScaleFreeTopology::new(3))),
+        "small-world" => Ok(Box::new(SmallWorldTopology::new(4, 0.1))),
+        "imported" => match source {
+            Some(path) => Ok(Box::new(ImportedTopology::new(path))),
+            None => Err(ThelmaError::Config("the imported topology needs a graph file (--topology-file)".to_string())),
+        },
+        "fixture" => match source {
+            Some(fixture) => Ok(Box::new(FixtureTopology::new(fixture))),
+            None => Err(ThelmaError::Config("the fixture topology needs a fixture name (--fixture)".to_string())),
+        },
+        _ => Err(ThelmaError::Config(format!("unknown topology '{}' (expected one of: {})",
+                                            name, TOPOLOGY_NAMES.join(", ")))),
+    }
+}
+
+// Ring of nodes with random cross connections
+pub struct SimpleTopology;
+
+impl TopologyGenerator for SimpleTopology {
+    fn name(&self) -> &'static str {
+        "simple"
+    }
+
+    fn generate(&mut self, network: &mut LightningNetworkMap, node_count: usize, rng: &mut StdRng) -> Result<(), ThelmaError> {
+
+        // Add nodes with reasonable CLTV deltas
+        for i in 0..node_count {
+            // Generate a random CLTV delta between 14 and 50
+            let cltv_delta = if i % 5 == 0 {
+                // Every 5th node has standard delta of 40
+                40
+            } else {
+                rng.random_range(14..=50)
+            };
+
+            let node = Node::new(
+                &format!("node{}", i+1),
+                &format!("Node {}", i+1),
+                cltv_delta
+            ).with_min_final_cltv_expiry_delta(random_min_final_cltv_delta(rng));
+
+            network.add_node(node);
+        }
+
+        info!("Created {} nodes", node_count);
+
+        // Create a connected ring topology to ensure reachability
+        for i in 0..node_count {
+            let channel = Channel::new(
+                &new_short_channel_id(network, rng),
+                &format!("node{}", i+1),
+                &format!("node{}", (i+1) % node_count + 1),
+                1_000_000 + rng.random_range(0..5_000_000)
+            );
+
+            network.add_channel(with_random_htlc_limits(rng, channel));
+        }
+
+        // Add some random cross connections for a more realistic network
+        let extra_channels = node_count / 2;
+        for _ in 0..extra_channels {
+            let node1 = rng.random_range(1..=node_count);
+            let mut node2 = rng.random_range(1..=node_count);
+
+            // Ensure we don't connect a node to itself
+            while node1 == node2 {
+                node2 = rng.random_range(1..=node_count);
+            }
+
+            let channel = Channel::new(
+                &new_short_channel_id(network, rng),
+                &format!("node{}", node1),
+                &format!("node{}", node2),
+                500_000 + rng.random_range(0..3_000_000)
+            );
+
+            network.add_channel(with_random_htlc_limits(rng, channel));
+        }
+
+        info!("Created {} channels", node_count + extra_channels);
+
+        Ok(())
+    }
+}
+
+// Preferential attachment, which better models real-world topologies where some nodes are hubs
+pub struct ScaleFreeTopology {
+    // Channels each new node opens to the best-connected existing nodes
+    pub min_connections: usize,
+}
+
+impl ScaleFreeTopology {
+    pub fn new(min_connections: usize) -> Self {
+        ScaleFreeTopology { min_connections }
+    }
+}
+
+impl TopologyGenerator for ScaleFreeTopology {
+    fn name(&self) -> &'static str {
+        "scale-free"
+    }
+
+    fn generate(&mut self, network: &mut LightningNetworkMap, node_count: usize, rng: &mut StdRng) -> Result<(), ThelmaError> {
+        let min_connections = self.min_connections;
+
+        // Add nodes
+        for i in 0..node_count {
+            let cltv_delta = implementation_cltv_delta(rng, i);
+
+            let node = Node::new(
+                &format!("node{}", i+1),
+                &format!("Node {}", i+1),
+                cltv_delta
+            ).with_min_final_cltv_expiry_delta(random_min_final_cltv_delta(rng));
+
+            network.add_node(node);
+        }
+
+        info!("Created {} nodes", node_count);
+
+        // If we have at least min_connections nodes, create initial fully-connected cluster
+        let initial_nodes = std::cmp::min(node_count, min_connections);
+        for i in 0..initial_nodes {
+            for j in (i+1)..initial_nodes {
+                let channel = Channel::new(
+                    &new_short_channel_id(network, rng),
+                    &format!("node{}", i+1),
+                    &format!("node{}", j+1),
+                    1_000_000 + rng.random_range(0..5_000_000)
+                );
+
+                network.add_channel(with_random_htlc_limits(rng, channel));
+            }
+        }
+
+        // Add remaining nodes using preferential attachment
+        let mut channel_count = initial_nodes * initial_nodes.saturating_sub(1) / 2;
+
+        for i in initial_nodes..node_count {
+            // Connect to min_connections existing nodes with probability proportional
+            // to their current degree (number of connections)
+
+            // Count connections for each existing node
+            let mut connection_counts = Vec::new();
+            for j in 0..i {
+                let connections = network.degree(&format!("node{}", j+1));
+                connection_counts.push((j, connections));
+            }
+
+            // Sort by connection count (descending)
+            connection_counts.sort_by_key(|&(_, connections)| std::cmp::Reverse(connections));
+
+            // Connect to the top min_connections nodes
+            for &(j, _) in connection_counts.iter().take(std::cmp::min(min_connections, i)) {
+
+                let channel = Channel::new(
+                    &new_short_channel_id(network, rng),
+                    &format!("node{}", i+1),
+                    &format!("node{}", j+1),
+                    500_000 + rng.random_range(0..3_000_000)
+                );
+
+                network.add_channel(with_random_htlc_limits(rng, channel));
+                channel_count += 1;
+            }
+        }
+
+        info!("Created {} channels", channel_count);
+
+        Ok(())
+    }
+}
+
+// Watts-Strogatz small world: a ring lattice where a fraction of channels are rewired to
+// random nodes, giving short paths alongside tight local clustering
+pub struct SmallWorldTopology {
+    // Lattice neighbors per node, half on each side
+    pub neighbors: usize,
+    // Chance each lattice channel is rewired to a random node
+    pub rewire_probability: f64,
+}
+
+impl SmallWorldTopology {
+    pub fn new(neighbors: usize, rewire_probability: f64) -> Self {
+        SmallWorldTopology { neighbors, rewire_probability }
+    }
+}
+
+impl TopologyGenerator for SmallWorldTopology {
+    fn name(&self) -> &'static str {
+        "small-world"
+    }
+
+    fn generate(&mut self, network: &mut LightningNetworkMap, node_count: usize, rng: &mut StdRng) -> Result<(), ThelmaError> {
+
+        for i in 0..node_count {
+            let node = Node::new(
+                &format!("node{}", i+1),
+                &format!("Node {}", i+1),
+                implementation_cltv_delta(rng, i)
+            ).with_min_final_cltv_expiry_delta(random_min_final_cltv_delta(rng));
+
+            network.add_node(node);
+        }
+
+        info!("Created {} nodes", node_count);
+
+        // Lattice pairs, each rewired away from its clockwise end with the given probability
+        let half = (self.neighbors / 2).min(node_count.saturating_sub(1) / 2).max(1);
+        let mut linked: HashSet<(usize, usize)> = HashSet::new();
+        let mut channel_count = 0;
+        for i in 0..node_count {
+            for offset in 1..=half {
+                let mut j = (i + offset) % node_count;
+                if rng.random_bool(self.rewire_probability) {
+                    // Pick a random peer that isn't this node or already linked to it
+                    let candidates: Vec<usize> = (0..node_count)
+                        .filter(|&k| k != i && !linked.contains(&(i.min(k), i.max(k))))
+                        .collect();
+                    if !candidates.is_empty() {
+                        j = candidates[rng.random_range(0..candidates.len())];
+                    }
+                }
+
+                if i == j || !linked.insert((i.min(j), i.max(j))) {
+                    continue;
+                }
+
+                let channel = Channel::new(
+                    &new_short_channel_id(network, rng),
+                    &format!("node{}", i+1),
+                    &format!("node{}", j+1),
+                    500_000 + rng.random_range(0..5_000_000)
+                );
+
+                network.add_channel(with_random_htlc_limits(rng, channel));
+                channel_count += 1;
+            }
+        }
+
+        info!("Created {} channels", channel_count);
+
+        Ok(())
+    }
+}
+
+// A real graph snapshot in the JSON format of LND's `lncli describegraph`
+pub struct ImportedTopology {
+    pub path: String,
+}
+
+impl ImportedTopology {
+    pub fn new(path: &str) -> Self {
+        ImportedTopology { path: path.to_string() }
+    }
+}
+
+impl TopologyGenerator for ImportedTopology {
+    fn name(&self) -> &'static str {
+        "imported"
+    }
+
+    fn generate(&mut self, network: &mut LightningNetworkMap, _node_count: usize, _rng: &mut StdRng) -> Result<(), ThelmaError> {
+        let contents = std::fs::read_to_string(&self.path)
+            .map_err(|e| ThelmaError::Graph(format!("can't read graph file {}: {}", self.path, e)))?;
+        let graph: Value = serde_json::from_str(&contents)?;
+        let (nodes, channels) = parse_describegraph(&graph, network.current_block_height)?;
+
+        let (node_count, channel_count) = (nodes.len(), channels.len());
+        for node in nodes {
+            network.add_node(node);
+        }
+        for channel in channels {
+            network.add_channel(channel);
+        }
+
+        info!("Imported {} nodes and {} channels from {} ({} disabled or zombie)", node_count, channel_count,
+              self.path, network.inactive_channel_count());
+
+        Ok(())
+    }
+}
+
+// Seconds without a `channel_update` after which LND considers a channel a zombie
+const ZOMBIE_CHANNEL_AGE_SECS: u64 = 14 * 24 * 3600;
+// Seconds per block on average, to date updates in blocks
+const BLOCK_INTERVAL_SECS: u64 = 600;
+
+// Convert a describegraph dump into nodes and channels. A node takes its forwarding policy
+// from the first channel that announces one for it, since the map keeps one policy per node.
+// Channels whose announced policies are all disabled are marked disabled, and those not
+// updated in the two weeks before the snapshot's newest update are marked zombies. Update
+// times become blocks before `height`, taking the snapshot's newest update as `height`.
+// A node announcing addresses, all of them onion services
+fn is_tor_only(addresses: &Value) -> bool {
+    let Some(addresses) = addresses.as_array().filter(|a| !a.is_empty()) else { return false };
+    addresses.iter().all(|address| address["addr"].as_str().is_some_and(|addr| {
+        addr.rsplit_once(':').map_or(addr, |(host, _)| host).ends_with(".onion")
+    }))
+}
+
+pub(crate) fn parse_describegraph(graph: &Value, height: u32) -> Result<(Vec<Node>, Vec<Channel>), ThelmaError> {
+    let missing = |field: &str| ThelmaError::Graph(format!("graph file has no \"{}\" array", field));
+    let node_entries = graph["nodes"].as_array().ok_or_else(|| missing("nodes"))?;
+    let edge_entries = graph["edges"].as_array().ok_or_else(|| missing("edges"))?;
+
+    let mut nodes: Vec<Node> = Vec::new();
+    let mut index = HashMap::new();
+    for entry in node_entries {
+        let Some(pub_key) = entry["pub_key"].as_str() else { continue };
+        let alias = entry["alias"].as_str().filter(|a| !a.is_empty()).unwrap_or(pub_key);
+        index.insert(pub_key.to_string(), nodes.len());
+        let mut node = Node::new(pub_key, alias, 40);
+        node.tor_only = is_tor_only(&entry["addresses"]);
+        nodes.push(node);
+    }
+
+    let mut has_policy = vec![false; nodes.len()];
+    let mut channels = Vec::new();
+    // Newest update each channel got, to tell zombies once the snapshot's age is known
+    let mut last_updates = Vec::new();
+    for edge in edge_entries {
+        let (Some(node1), Some(node2)) = (edge["node1_pub"].as_str(), edge["node2_pub"].as_str()) else { continue };
+        // Channels to nodes missing from the snapshot can't be routed through reliably
+        let (Some(&i), Some(&j)) = (index.get(node1), index.get(node2)) else { continue };
+
+        let channel_id = edge["channel_id"].as_str().map(str::to_string)
+            .or_else(|| number(&edge["channel_id"]).map(|id| id.to_string()))
+            .unwrap_or_else(|| format!("{}-{}", node1, node2));
+        let capacity = number(&edge["capacity"]).unwrap_or(0);
+        let mut channel = Channel::new(&channel_id, node1, node2, capacity);
+        let mut last_update = None;
+        let (mut announced, mut disabled) = (0, 0);
+
+        for (slot, end, policy) in [(i, node1, &edge["node1_policy"]), (j, node2, &edge["node2_policy"])] {
+            if !policy.is_object() {
+                continue;
+            }
+            announced += 1;
+            last_update = last_update.max(number(&policy["last_update"]));
+            if policy["disabled"].as_bool() == Some(true) {
+                disabled += 1;
+                continue;
+            }
+
+            // Either direction's limits constrain what the channel carries
+            if let Some(minimum) = number(&policy["min_htlc"]) {
+                channel.htlc_minimum_msat = channel.htlc_minimum_msat.max(minimum);
+            }
+            if let Some(maximum) = number(&policy["max_htlc_msat"]).filter(|&m| m > 0) {
+                channel.htlc_maximum_msat = channel.htlc_maximum_msat.min(maximum);
+            }
+            // Each end's fees hold for forwarding out over this channel in particular
+            if let (Some(base_fee_msat), Some(fee_rate_ppm)) = (number(&policy["fee_base_msat"]), number(&policy["fee_rate_milli_msat"])) {
+                channel = channel.with_fees(end, ChannelFees { base_fee_msat, fee_rate_ppm });
+            }
+            if let Some(delta) = number(&policy["time_lock_delta"]) {
+                channel = channel.with_cltv_delta(end, delta as u32);
+            }
+
+            if !has_policy[slot] {
+                has_policy[slot] = true;
+                let node = &mut nodes[slot];
+                if let Some(delta) = number(&policy["time_lock_delta"]) {
+                    node.cltv_expiry_delta = delta as u32;
+                }
+                if let Some(base) = number(&policy["fee_base_msat"]) {
+                    node.base_fee_msat = base;
+                }
+                if let Some(rate) = number(&policy["fee_rate_milli_msat"]) {
+                    node.fee_rate_ppm = rate;
+                }
+            }
+        }
+
+        if announced > 0 && disabled == announced {
+            channel.status = ChannelStatus::Disabled;
+        }
+        last_updates.push(last_update);
+        channels.push(channel);
+    }
+
+    if let Some(newest) = last_updates.iter().flatten().max() {
+        let cutoff = newest.saturating_sub(ZOMBIE_CHANNEL_AGE_SECS);
+        for (channel, last_update) in channels.iter_mut().zip(&last_updates) {
+            let Some(update) = last_update else { continue };
+            if *update < cutoff {
+                channel.status = ChannelStatus::Zombie;
+            }
+            let blocks_ago = (newest - update) / BLOCK_INTERVAL_SECS;
+            channel.last_update = Some(height.saturating_sub(blocks_ago.min(u32::MAX as u64) as u32));
+        }
+    }
+
+    Ok((nodes, channels))
+}
+
+// LND encodes 64-bit values as strings, smaller ones as numbers
+fn number(value: &Value) -> Option<u64> {
+    value.as_u64().or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+}
+
+// Forwarding delta mirroring the defaults of the main implementations
+fn implementation_cltv_delta(rng: &mut impl Rng, i: usize) -> u32 {
+    match i % 10 {
+        0 => 40,  // LND default
+        1 => 34,  // Eclair default
+        2 => 42,  // C-lightning default
+        _ => rng.random_range(14..=50),
+    }
+}
+
+// A short channel id for a generated channel, funded some time before the network's current
+// height and not already taken
+pub(crate) fn new_short_channel_id(network: &LightningNetworkMap, rng: &mut impl Rng) -> String {
+    loop {
+        let id = ShortChannelId::random(network.current_block_height, rng).to_string();
+        if network.channel(&id).is_none() {
+            return id;
+        }
+    }
+}
+
+// Give a channel HTLC and dust limits resembling what different implementations and
+// operators use
+fn with_random_htlc_limits(rng: &mut impl Rng, channel: Channel) -> Channel {
+    let (minimum, dust_limit) = match rng.random_range(0..10) {
+        0..=5 => (1000, DEFAULT_DUST_LIMIT_SAT),  // LND defaults
+        6..=8 => (1, 546),                        // Core Lightning defaults
+        _ => (50_000, DEFAULT_DUST_LIMIT_SAT),    // Operators avoiding dust-sized HTLCs
+    };
+
+    // Some operators cap HTLC size well below capacity to limit exposure
+    let maximum = if rng.random_bool(0.1) {
+        500_000
+    } else {
+        channel.capacity * 1000
+    };
+
+    channel.with_htlc_limits(minimum, maximum)
+        .with_dust_limits(dust_limit, DEFAULT_MAX_DUST_EXPOSURE_MSAT)
+}
+
+// Invoice delta as set by the recipient's implementation, independent of its forwarding delta
+fn random_min_final_cltv_delta(rng: &mut impl Rng) -> u32 {
+    FinalDeltaPrior::default().sample(rng)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_every_registered_topology_generates() {
+        let path = std::env::temp_dir().join(format!("thelma_graph_{}.json", std::process::id()));
+        std::fs::write(&path, r#"{
+            "nodes": [
+                {"pub_key": "02aa", "alias": "alice", "addresses": [{"network": "tcp", "addr": "abcdef.onion:9735"}]},
+                {"pub_key": "03bb", "alias": "", "addresses": [{"network": "tcp", "addr": "1.2.3.4:9735"},
+                                                             {"network": "tcp", "addr": "abcdef.onion:9735"}]},
+                {"pub_key": "02cc", "alias": "carol"}
+            ],
+            "edges": [
+                {"channel_id": "101", "node1_pub": "02aa", "node2_pub": "03bb", "capacity": "2000000",
+                 "node1_policy": {"time_lock_delta": 80, "min_htlc": "1000", "fee_base_msat": "500",
+                                  "fee_rate_milli_msat": "100", "max_htlc_msat": "990000000"},
+                 "node2_policy": null},
+                {"channel_id": "102", "node1_pub": "03bb", "node2_pub": "02cc", "capacity": "1000000",
+                 "node1_policy": {"time_lock_delta": 144}, "node2_policy": {"time_lock_delta": 18}},
+                {"channel_id": "104", "node1_pub": "02aa", "node2_pub": "02cc", "capacity": "1000000",
+                 "node1_policy": {"disabled": true, "last_update": 1700000000}, "node2_policy": null},
+                {"channel_id": "105", "node1_pub": "02aa", "node2_pub": "02cc", "capacity": "1000000",
+                 "node1_policy": {"last_update": 1690000000}, "node2_policy": {"last_update": 1690000000}},
+                {"channel_id": "103", "node1_pub": "02cc", "node2_pub": "04ff", "capacity": "1000000"}
+            ]
+        }"#).unwrap();
+
+        for name in TOPOLOGY_NAMES {
+            let source = if *name == "fixture" { Some("star") } else { path.to_str() };
+            let mut topology = topology_from_name(name, source).unwrap();
+            assert_eq!(topology.name(), *name);
+
+            let mut network = LightningNetworkMap::new(700000);
+            topology.generate(&mut network, 12, &mut StdRng::seed_from_u64(1)).unwrap();
+            if *name == "imported" {
+                // The channel to a node missing from the snapshot is dropped
+                assert_eq!(network.nodes.len(), 3);
+                assert_eq!(network.channels.len(), 4);
+                assert_eq!(network.nodes["02aa"].cltv_expiry_delta, 80);
+                assert_eq!(network.nodes["02aa"].fee_rate_ppm, 100);
+                assert_eq!(network.nodes["03bb"].alias, "03bb");
+                assert!(network.nodes["02aa"].tor_only && !network.nodes["03bb"].tor_only);
+                assert_eq!(network.nodes["03bb"].cltv_expiry_delta, 144);
+                assert_eq!(network.channels[0].htlc_maximum_msat, 990_000_000);
+                let fees = network.channel("101").unwrap().fees_of("02aa");
+                assert_eq!(fees, Some(ChannelFees { base_fee_msat: 500, fee_rate_ppm: 100 }));
+                let statuses: Vec<ChannelStatus> = network.channels.iter().map(|c| c.status).collect();
+                assert_eq!(statuses, vec![ChannelStatus::Active, ChannelStatus::Active,
+                                          ChannelStatus::Disabled, ChannelStatus::Zombie]);
+                assert_eq!(network.channels[2].last_update, Some(700000));
+                assert_eq!(network.channels[3].last_update, Some(700000 - 10_000_000 / 600));
+            } else if *name == "fixture" {
+                // Fixtures keep their own size
+                assert_eq!((network.nodes.len(), network.channels.len()), (6, 5));
+            } else {
+                assert_eq!(network.nodes.len(), 12);
+                assert!(network.channels.len() >= 12, "{} is too sparse", name);
+            }
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(topology_from_name("imported", None).is_err());
+        assert!(topology_from_name("fixture", None).is_err());
+        assert!(topology_from_name("hypercube", None).is_err());
+    }
+}
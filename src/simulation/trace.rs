@@ -0,0 +1,291 @@
+// Recorded simulation traffic that can be replayed to other observers
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+
+use crate::checkpoint::{network_from_json, network_to_json};
+use crate::models::{HTLC, HtlcResolution, LightningNetworkMap};
+use crate::simulation::events::NetworkEvent;
+use crate::simulation::observer::{dispatch, Observer};
+use crate::simulation::PaymentRecord;
+use crate::surveillance::observation_store::{htlc_from_json, htlc_to_json};
+use crate::error::ThelmaError;
+
+// An event and the virtual time it happened at. Virtual time counts payments finished
+// before the event, so replays see the same order and spacing regardless of wall-clock
+// delays.
+#[derive(Debug, Clone)]
+pub struct TracedEvent {
+    pub at: u64,
+    pub event: NetworkEvent,
+}
+
+// Writes every event it observes to a trace file: one JSON line with a snapshot of the
+// network, then one short JSON line per event
+pub struct TraceRecorder {
+    writer: BufWriter<File>,
+    clock: u64,
+    // Bytes written so far, so checkpoints can note where the trace ends
+    written: u64,
+}
+
+impl TraceRecorder {
+    // Start a trace of traffic over this network
+    pub fn create(path: &str, network: &LightningNetworkMap) -> Result<Self, ThelmaError> {
+        let mut recorder = TraceRecorder {
+            writer: BufWriter::new(File::create(path)?),
+            clock: 0,
+            written: 0,
+        };
+        recorder.write(serde_json::json!({ "network": network_to_json(network) }))?;
+        Ok(recorder)
+    }
+
+    // Continue a trace from a checkpoint, dropping whatever was recorded after it
+    pub fn resume(path: &str, offset: u64, clock: u64) -> Result<Self, ThelmaError> {
+        let file = OpenOptions::new().append(true).open(path)?;
+        file.set_len(offset)?;
+
+        Ok(TraceRecorder { writer: BufWriter::new(file), clock, written: offset })
+    }
+
+    // Flush and return the trace's length
+    pub fn offset(&mut self) -> Result<u64, ThelmaError> {
+        self.writer.flush()?;
+        Ok(self.written)
+    }
+
+    fn write(&mut self, line: serde_json::Value) -> Result<(), ThelmaError> {
+        let line = format!("{}\n", line);
+        self.writer.write_all(line.as_bytes())?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+}
+
+impl Observer for TraceRecorder {
+    fn on_htlc_forward(&mut self, htlc: &HTLC) -> Result<(), ThelmaError> {
+        self.write(serde_json::json!({ "t": self.clock, "htlc": htlc_to_json(htlc) }))
+    }
+
+    fn on_htlc_resolved(&mut self, resolution: &HtlcResolution) -> Result<(), ThelmaError> {
+        self.write(serde_json::json!({
+            "t": self.clock,
+            "resolved": {
+                "payment_hash": resolution.payment_hash,
+                "node": resolution.observed_by_node,
+                "settled": resolution.settled,
+                "hold_ms": resolution.hold_ms,
+            },
+        }))
+    }
+
+    fn on_settle(&mut self, payment_hash: &str) -> Result<(), ThelmaError> {
+        self.write(serde_json::json!({ "t": self.clock, "settled": payment_hash }))?;
+        self.clock += 1;
+        Ok(())
+    }
+
+    fn on_fail(&mut self, sender: &str, receiver: &str, reason: &str) -> Result<(), ThelmaError> {
+        self.write(serde_json::json!({
+            "t": self.clock,
+            "failed": { "sender": sender, "receiver": receiver, "reason": reason },
+        }))?;
+        self.clock += 1;
+        Ok(())
+    }
+
+    fn on_blocks_mined(&mut self, height: u32) -> Result<(), ThelmaError> {
+        self.write(serde_json::json!({ "t": self.clock, "blocks": height }))
+    }
+
+    fn on_channel_update(&mut self, channel_id: &str, block: u32) -> Result<(), ThelmaError> {
+        self.write(serde_json::json!({ "t": self.clock, "gossip": { "channel_id": channel_id, "block": block } }))
+    }
+}
+
+// A trace read back from disk
+pub struct Trace {
+    pub network: LightningNetworkMap,
+    pub events: Vec<TracedEvent>,
+}
+
+impl Trace {
+    pub fn load(path: &str) -> Result<Self, ThelmaError> {
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let header: serde_json::Value = match lines.next() {
+            Some(line) => serde_json::from_str(&line?)?,
+            None => return Err(malformed("trace is empty")),
+        };
+        let network = network_from_json(&header["network"])?;
+
+        let mut events = Vec::new();
+        for line in lines {
+            let value: serde_json::Value = serde_json::from_str(&line?)?;
+            events.push(event_from_json(&value)?);
+        }
+
+        Ok(Trace { network, events })
+    }
+
+    // Feed every event to the observer in recorded order
+    pub fn replay(&self, observer: &mut dyn Observer) -> Result<(), ThelmaError> {
+        for traced in &self.events {
+            dispatch(observer, &traced.event)?;
+        }
+        Ok(())
+    }
+
+    // Ground truth rebuilt from the HTLCs each payment left along its route. Cover
+    // traffic looks like any other payment on the wire, so it counts as real here. HTLCs
+    // that failed back are left out when the trace says how each was resolved.
+    pub fn payment_records(&self, malicious_nodes: &[String]) -> Vec<PaymentRecord> {
+        let mut records: Vec<PaymentRecord> = Vec::new();
+        let mut index: HashMap<&str, usize> = HashMap::new();
+        let mut resolved = false;
+        let mut settled: HashSet<(&str, &str)> = HashSet::new();
+        for traced in &self.events {
+            if let NetworkEvent::HtlcResolved(resolution) = &traced.event {
+                resolved = true;
+                if resolution.settled {
+                    settled.insert((&resolution.payment_hash, &resolution.observed_by_node));
+                }
+            }
+        }
+
+        for traced in &self.events {
+            let NetworkEvent::HtlcForwarded(htlc) = &traced.event else { continue };
+            if resolved && !settled.contains(&(htlc.payment_hash.as_str(), htlc.observed_by_node.as_str())) {
+                continue;
+            }
+            let slot = *index.entry(htlc.payment_hash.as_str()).or_insert_with(|| {
+                records.push(PaymentRecord {
+                    payment_hash: htlc.payment_hash.clone(),
+                    sender: htlc.observed_by_node.clone(),
+                    receiver: String::new(),
+                    path: Vec::new(),
+                    amount: htlc.amount,
+                    observed: false,
+                    cover: false,
+                });
+                records.len() - 1
+            });
+
+            let record = &mut records[slot];
+            record.path.push(htlc.observed_by_node.clone());
+            record.receiver = htlc.observed_by_node.clone();
+            // Fees are paid along the way, so the amount is what reached the recipient
+            record.amount = htlc.amount;
+            record.observed |= malicious_nodes.contains(&htlc.observed_by_node);
+        }
+
+        records
+    }
+}
+
+fn malformed(message: &str) -> ThelmaError {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string()).into()
+}
+
+fn event_from_json(value: &serde_json::Value) -> Result<TracedEvent, ThelmaError> {
+    let at = value["t"].as_u64().ok_or_else(|| malformed("trace event is missing its time"))?;
+    let text = |field: &serde_json::Value| field.as_str().map(str::to_string)
+        .ok_or_else(|| malformed("trace event is missing a field"));
+
+    let event = if !value["htlc"].is_null() {
+        NetworkEvent::HtlcForwarded(htlc_from_json(&value["htlc"])?)
+    } else if !value["settled"].is_null() {
+        NetworkEvent::PaymentSettled { payment_hash: text(&value["settled"])? }
+    } else if !value["resolved"].is_null() {
+        let resolved = &value["resolved"];
+        let settled = resolved["settled"].as_bool().ok_or_else(|| malformed("trace event is missing a field"))?;
+        let hold_ms = resolved["hold_ms"].as_u64().ok_or_else(|| malformed("trace event is missing a field"))?;
+        NetworkEvent::HtlcResolved(HtlcResolution {
+            payment_hash: text(&resolved["payment_hash"])?,
+            observed_by_node: text(&resolved["node"])?,
+            settled,
+            hold_ms,
+        })
+    } else if !value["failed"].is_null() {
+        let failed = &value["failed"];
+        NetworkEvent::PaymentFailed {
+            sender: text(&failed["sender"])?,
+            receiver: text(&failed["receiver"])?,
+            reason: text(&failed["reason"])?,
+        }
+    } else if let Some(height) = value["blocks"].as_u64() {
+        NetworkEvent::BlocksMined { height: height as u32 }
+    } else if !value["gossip"].is_null() {
+        let gossip = &value["gossip"];
+        let block = gossip["block"].as_u64().ok_or_else(|| malformed("trace event is missing a field"))?;
+        NetworkEvent::ChannelUpdated { channel_id: text(&gossip["channel_id"])?, block: block as u32 }
+    } else {
+        return Err(malformed("unknown trace event"));
+    };
+
+    Ok(TracedEvent { at, event })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex, RwLock};
+    use crate::models::{Channel, Node};
+    use crate::simulation::{PaymentSimulator, SimulatorConfig};
+    use crate::surveillance::{SurveillanceConfig, SurveillanceOperation};
+
+    #[tokio::test]
+    async fn test_replay_matches_live_observation() {
+        // Line a - b - c - d: b sees every payment crossing it, c the others
+        let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(700000)));
+        {
+            let mut network = network_map.write().unwrap();
+            for node in ["a", "b", "c", "d"] {
+                network.add_node(Node::new(node, node, 40));
+            }
+            for (a, b) in [("a", "b"), ("b", "c"), ("c", "d")] {
+                network.add_channel(Channel::new(&format!("{}-{}", a, b), a, b, 10_000_000));
+            }
+        }
+
+        let path = std::env::temp_dir().join(format!("thelma-trace-test-{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+        let recorder = Arc::new(Mutex::new(TraceRecorder::create(path, &network_map.read().unwrap()).unwrap()));
+        let live = Arc::new(Mutex::new(
+            SurveillanceOperation::new(network_map.clone(), SurveillanceConfig::observing(vec!["b".to_string()])).unwrap()));
+
+        let mut simulator = PaymentSimulator::new(network_map.clone(), SimulatorConfig::new());
+        let recording = simulator.register_observer(recorder.clone());
+        let watching = simulator.register_observer(live.clone());
+        simulator.simulate_payments(20).await.unwrap();
+        simulator.close_events();
+        recording.await.unwrap();
+        watching.await.unwrap();
+        recorder.lock().unwrap().offset().unwrap();
+
+        let trace = Trace::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(trace.network.channel_count(), 3);
+        assert_eq!(trace.events.last().unwrap().at, 19);
+        // Every HTLC forwarded comes back resolved
+        let forwarded = trace.events.iter().filter(|t| matches!(t.event, NetworkEvent::HtlcForwarded(_))).count();
+        let resolved = trace.events.iter().filter(|t| matches!(t.event, NetworkEvent::HtlcResolved(_))).count();
+        assert!(forwarded > 0);
+        assert_eq!(resolved, forwarded);
+
+        // The same adversary sees exactly what it saw live
+        let mut replayed = SurveillanceOperation::new(Arc::new(RwLock::new(trace.network.clone())),
+                                                      SurveillanceConfig::observing(vec!["b".to_string()])).unwrap();
+        trace.replay(&mut replayed).unwrap();
+        assert_eq!(replayed.observation_count(), live.lock().unwrap().observation_count());
+
+        // Ground truth comes back from the routes alone
+        let records = trace.payment_records(&["c".to_string()]);
+        assert_eq!(records.len(), simulator.payment_records().len());
+        for (rebuilt, original) in records.iter().zip(simulator.payment_records()) {
+            assert_eq!(rebuilt.path, original.path);
+            assert_eq!(rebuilt.observed, original.path.contains(&"c".to_string()));
+        }
+    }
+}
@@ -1,17 +1,17 @@
 // Utility functions for Lightning Network simulation
 
-use std::collections::{HashMap, HashSet};
-use std::sync::{Arc, Mutex};
-use std::error::Error;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, RwLock};
 use rand::Rng;
 
 use crate::models::LightningNetworkMap;
+use crate::error::{ThelmaError, read_lock};
 
 // Generate a random path between two nodes
-pub fn generate_random_path(network_map: Arc<Mutex<LightningNetworkMap>>,
+pub fn generate_random_path(network_map: Arc<RwLock<LightningNetworkMap>>,
                             start: &str,
-                            end: &str) -> Result<Vec<String>, Box<dyn Error>> {
-    let network = network_map.lock().unwrap();
+                            end: &str) -> Result<Vec<String>, ThelmaError> {
+    let network = read_lock(&network_map);
 
     // Simple BFS to find a path
     let mut queue = Vec::new();
@@ -50,7 +50,7 @@ pub fn generate_random_path(network_map: Arc<Mutex<LightningNetworkMap>>,
 
     path.push(current.clone());
 
-    while current != start.to_string() {
+    while current != start {
         current = pred[&current].clone();
         path.push(current.clone());
     }
@@ -59,10 +59,57 @@ pub fn generate_random_path(network_map: Arc<Mutex<LightningNetworkMap>>,
     Ok(path)
 }
 
+// Find a shortest path between two nodes that doesn't pass through any of the avoided
+// nodes and only uses channels whose capacity and HTLC limits allow the amount
+pub fn find_path_avoiding(network: &LightningNetworkMap,
+                          start: &str,
+                          end: &str,
+                          avoid: &HashSet<String>,
+                          amount_msat: u64) -> Vec<String> {
+    let mut queue = VecDeque::new();
+    let mut visited = HashSet::new();
+    let mut pred: HashMap<String, String> = HashMap::new();
+
+    visited.insert(start.to_string());
+    queue.push_back(start.to_string());
+
+    while let Some(current) = queue.pop_front() {
+        if current == end {
+            break;
+        }
+
+        if let Some(neighbors) = network.get_neighbors(&current) {
+            for neighbor in neighbors {
+                if !visited.contains(neighbor) && !avoid.contains(neighbor)
+                    && network.can_carry(&current, neighbor, amount_msat) {
+                    visited.insert(neighbor.clone());
+                    pred.insert(neighbor.clone(), current.clone());
+                    queue.push_back(neighbor.clone());
+                }
+            }
+        }
+    }
+
+    if start != end && !pred.contains_key(end) {
+        return vec![];
+    }
+
+    let mut path = vec![end.to_string()];
+    let mut current = end.to_string();
+
+    while current != start {
+        current = pred[&current].clone();
+        path.push(current.clone());
+    }
+
+    path.reverse();
+    path
+}
+
 // Generate a random path with some randomization (not always shortest path)
-pub fn generate_randomized_path(network_map: Arc<Mutex<LightningNetworkMap>>,
+pub fn generate_randomized_path(network_map: Arc<RwLock<LightningNetworkMap>>,
                                 start: &str,
-                                end: &str) -> Result<Vec<String>, Box<dyn Error>> {
+                                end: &str) -> Result<Vec<String>, ThelmaError> {
     let mut rng = rand::rng();
 
     // If we get lucky (20% chance), just find a direct path
@@ -71,7 +118,7 @@ pub fn generate_randomized_path(network_map: Arc<Mutex<LightningNetworkMap>>,
     }
 
     // Otherwise, route through 1-2 random intermediate nodes
-    let network = network_map.lock().unwrap();
+    let network = read_lock(&network_map);
     let all_nodes: Vec<String> = network.nodes.keys().cloned().collect();
     drop(network);
 
@@ -138,11 +185,11 @@ pub fn generate_randomized_path(network_map: Arc<Mutex<LightningNetworkMap>>,
 }
 
 // Find all possible paths between two nodes up to a maximum hop count
-pub fn find_all_paths(network_map: Arc<Mutex<LightningNetworkMap>>,
+pub fn find_all_paths(network_map: Arc<RwLock<LightningNetworkMap>>,
                       start: &str,
                       end: &str,
                       max_hops: usize) -> Vec<Vec<String>> {
-    let network = network_map.lock().unwrap();
+    let network = read_lock(&network_map);
 
     let mut all_paths = Vec::new();
     let mut current_path = vec![start.to_string()];
@@ -202,10 +249,10 @@ mod tests {
     #[test]
     fn test_path_finding() {
         // Create a test network
-        let network_map = Arc::new(Mutex::new(LightningNetworkMap::new(700000)));
+        let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(700000)));
 
         {
-            let mut network = network_map.lock().unwrap();
+            let mut network = network_map.write().unwrap();
 
             // Add nodes in a simple path
             let nodes = vec![
@@ -237,5 +284,11 @@ mod tests {
         // Test finding all paths
         let all_paths = find_all_paths(network_map.clone(), "node1", "node4", 3);
         assert_eq!(all_paths.len(), 2); // There should be 2 paths: direct and through nodes 2-3
+
+        // Avoiding node2 forces the route through the node1-node4 shortcut
+        let network = network_map.read().unwrap();
+        let avoid: HashSet<String> = ["node2".to_string()].into_iter().collect();
+        assert_eq!(find_path_avoiding(&network, "node1", "node3", &avoid, 100_000),
+                   vec!["node1".to_string(), "node4".to_string(), "node3".to_string()]);
     }
 }
\ No newline at end of file
@@ -0,0 +1,356 @@
+// `thelma study`: the same seeded experiment repeated across values of one parameter, written
+// out as a tidy CSV, LaTeX tables (and a chart with the `plots` feature) for papers and notebooks
+
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+use log::info;
+
+use crate::models::LightningNetworkMap;
+use crate::graph::CentralityMeasure;
+use crate::defense::ScenarioMetrics;
+use crate::simulation::{PaymentSimulator, SimulatorConfig};
+use crate::surveillance::{AdversaryStrategy, CentralPlacement, RandomPlacement, SurveillanceConfig,
+                          SurveillanceOperation};
+use crate::error::{ThelmaError, lock_mutex, read_lock};
+use crate::latex::{LatexTable, LATEX_PREAMBLE_NOTE};
+#[cfg(feature = "plots")]
+use crate::plots::{LineChart, PlotFormat, save_plot};
+#[cfg(feature = "plots")]
+use plotters::style::{BLUE, RED};
+
+// Names accepted by `thelma study`, in the order they're listed in usage
+pub const STUDY_KINDS: &[&str] = &["scaling"];
+
+// Seed the first repeat of every value runs with unless another is given
+pub const DEFAULT_STUDY_SEED: u64 = 1;
+
+// The experiment setting a scaling study sweeps
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StudyParameter {
+    Nodes,
+    Payments,
+    Malicious,
+}
+
+impl StudyParameter {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "nodes" => Some(StudyParameter::Nodes),
+            "payments" => Some(StudyParameter::Payments),
+            "malicious" => Some(StudyParameter::Malicious),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StudyParameter::Nodes => "nodes",
+            StudyParameter::Payments => "payments",
+            StudyParameter::Malicious => "malicious",
+        }
+    }
+
+    // Column heading and what a paper would call the parameter, for tables
+    fn heading(&self) -> &'static str {
+        match self {
+            StudyParameter::Nodes => "Nodes",
+            StudyParameter::Payments => "Payments",
+            StudyParameter::Malicious => "Malicious nodes",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            StudyParameter::Nodes => "network size",
+            StudyParameter::Payments => "payment count",
+            StudyParameter::Malicious => "adversary size",
+        }
+    }
+}
+
+// Builds a run's network of `node_count` nodes from its seed
+pub type NetworkFactory = dyn Fn(u64, usize) -> Result<Arc<RwLock<LightningNetworkMap>>, ThelmaError>;
+
+// Payment settings for a run on this network with these malicious nodes; the seed is set per run
+pub type SimulatorFactory = dyn Fn(&Arc<RwLock<LightningNetworkMap>>, &[String]) -> SimulatorConfig;
+
+// Applies the analysis options to each run's adversary
+pub type SurveillanceAdjuster = dyn Fn(&Arc<RwLock<LightningNetworkMap>>, SurveillanceConfig) -> SurveillanceConfig;
+
+// What every run of a study shares; the swept parameter overrides one of the counts. The
+// network, payments and analysis are set up the way a single run would be.
+pub struct ExperimentSettings {
+    pub node_count: usize,
+    pub payment_count: usize,
+    pub malicious_count: usize,
+    // Centrality measure malicious nodes are placed by (random when unset)
+    pub placement: Option<CentralityMeasure>,
+    pub network: Box<NetworkFactory>,
+    pub simulator: Box<SimulatorFactory>,
+    pub surveillance: Box<SurveillanceAdjuster>,
+}
+
+// One run of a study
+#[derive(Debug, Clone)]
+pub struct StudyRow {
+    pub value: usize,
+    pub repeat: usize,
+    pub seed: u64,
+    pub node_count: usize,
+    pub payment_count: usize,
+    pub malicious_count: usize,
+    pub metrics: ScenarioMetrics,
+    // Wall-clock time of the whole run, network generation included
+    pub elapsed_ms: f64,
+}
+
+// Attack results as one parameter grows. Repeat r of every value runs with seed `seed + r`,
+// so networks, adversaries and payments are reproducible with the bfs and dijkstra routers.
+pub struct ScalingStudy {
+    pub parameter: StudyParameter,
+    pub values: Vec<usize>,
+    pub repeats: usize,
+    pub seed: u64,
+    pub rows: Vec<StudyRow>,
+}
+
+impl ScalingStudy {
+    pub fn new(parameter: StudyParameter, values: Vec<usize>) -> Self {
+        ScalingStudy { parameter, values, repeats: 1, seed: DEFAULT_STUDY_SEED, rows: Vec::new() }
+    }
+
+    pub fn repeats(mut self, repeats: usize) -> Self {
+        self.repeats = repeats.max(1);
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    // Run every value and repeat, replacing any rows from an earlier run
+    pub async fn run(&mut self, settings: &ExperimentSettings) -> Result<(), ThelmaError> {
+        self.rows.clear();
+        let total = self.values.len() * self.repeats;
+        for &value in &self.values {
+            for repeat in 0..self.repeats {
+                let seed = self.seed.wrapping_add(repeat as u64);
+                info!("Run {}/{}: {} = {}, seed {}", self.rows.len() + 1, total, self.parameter.name(), value, seed);
+                let row = run_once(settings, self.parameter, value, repeat, seed).await?;
+                info!("  observed {:.1}% of payments, identified {:.1}% of recipients in {:.0} ms",
+                      row.metrics.observation_rate() * 100.0, row.metrics.identification_rate() * 100.0,
+                      row.elapsed_ms);
+                self.rows.push(row);
+            }
+        }
+        Ok(())
+    }
+
+    // One row per run, one column per variable
+    pub fn generate_csv(&self) -> String {
+        let mut csv = String::from("parameter,value,repeat,seed,nodes,payments,malicious,routed_payments,\
+                                    observed_payments,observation_rate,recipients_identified,identification_rate,\
+                                    candidate_recall,attacker_precision,avg_anonymity_set,analysis_time_ms,elapsed_ms\n");
+        for row in &self.rows {
+            let metrics = &row.metrics;
+            csv.push_str(&format!("{},{},{},{},{},{},{},{},{},{:.6},{},{:.6},{:.6},{:.6},{:.4},{:.3},{:.3}\n",
+                                  self.parameter.name(), row.value, row.repeat, row.seed, row.node_count,
+                                  row.payment_count, row.malicious_count, metrics.payments,
+                                  metrics.observed_payments, metrics.observation_rate(),
+                                  metrics.recipients_identified, metrics.identification_rate(),
+                                  metrics.candidate_recall(), metrics.attacker_precision(),
+                                  metrics.avg_anonymity_set, metrics.analysis_time_ms, row.elapsed_ms));
+        }
+        csv
+    }
+
+    pub fn save_csv(&self, filename: &str) -> Result<(), ThelmaError> {
+        std::fs::write(filename, self.generate_csv())?;
+        info!("Study results saved to {}", filename);
+        Ok(())
+    }
+
+    // Observation and identification rates averaged over the repeats of each value
+    pub fn means(&self) -> Vec<(usize, f64, f64)> {
+        self.values.iter().map(|&value| {
+            let runs: Vec<&StudyRow> = self.rows.iter().filter(|row| row.value == value).collect();
+            let count = runs.len().max(1) as f64;
+            (value,
+             runs.iter().map(|row| row.metrics.observation_rate()).sum::<f64>() / count,
+             runs.iter().map(|row| row.metrics.identification_rate()).sum::<f64>() / count)
+        }).collect()
+    }
+
+    // Accuracy and anonymity sets against the swept parameter, one row per value, as
+    // mean ± standard deviation over repeats when there are several
+    pub fn generate_latex(&self) -> String {
+        let mut table = LatexTable::new(&format!("Timelock attack accuracy vs.\\ {} ({} per value).",
+                                                 self.parameter.description(),
+                                                 if self.repeats == 1 { "one run".to_string() } else { format!("{} runs", self.repeats) }),
+                                        &format!("tab:thelma-scaling-{}", self.parameter.name()))
+            .column(self.parameter.heading(), 'r')
+            .column("Observed (\\%)", 'r')
+            .column("Identified (\\%)", 'r')
+            .column("In candidates (\\%)", 'r')
+            .column("Precision (\\%)", 'r')
+            .column("Anonymity set", 'r')
+            .column("Median set", 'r');
+        for &value in &self.values {
+            let runs: Vec<&ScenarioMetrics> = self.rows.iter()
+                .filter(|row| row.value == value)
+                .map(|row| &row.metrics)
+                .collect();
+            if runs.is_empty() {
+                continue;
+            }
+            let cell = |metric: &dyn Fn(&ScenarioMetrics) -> f64, scale: f64, digits: usize| {
+                let values: Vec<f64> = runs.iter().map(|metrics| metric(metrics) * scale).collect();
+                let (mean, deviation) = mean_and_deviation(&values);
+                if runs.len() == 1 {
+                    format!("{:.*}", digits, mean)
+                } else {
+                    format!("{:.*} $\\pm$ {:.*}", digits, mean, digits, deviation)
+                }
+            };
+            table.row(vec![value.to_string(),
+                           cell(&ScenarioMetrics::observation_rate, 100.0, 1),
+                           cell(&ScenarioMetrics::identification_rate, 100.0, 1),
+                           cell(&ScenarioMetrics::candidate_recall, 100.0, 1),
+                           cell(&ScenarioMetrics::attacker_precision, 100.0, 1),
+                           cell(&|metrics| metrics.avg_anonymity_set, 1.0, 2),
+                           cell(&|metrics| metrics.anonymity_set_percentile(0.5) as f64, 1.0, 1)]);
+        }
+        format!("{}\n{}", LATEX_PREAMBLE_NOTE, table.generate_latex())
+    }
+
+    pub fn save_latex(&self, filename: &str) -> Result<(), ThelmaError> {
+        std::fs::write(filename, self.generate_latex())?;
+        info!("Study tables saved to {}", filename);
+        Ok(())
+    }
+
+    // Coverage and accuracy against the swept parameter, averaged over repeats
+    #[cfg(feature = "plots")]
+    pub fn save_plot(&self, stem: &str, format: PlotFormat) -> Result<String, ThelmaError> {
+        let means = self.means();
+        let chart = LineChart::new(&format!("Timelock attack vs. {}", self.parameter.name()),
+                                   self.parameter.name(), "share of payments")
+            .series("coverage (observed)", BLUE, means.iter().map(|m| (m.0 as f64, m.1)).collect())
+            .series("accuracy (recipient ranked first)", RED, means.iter().map(|m| (m.0 as f64, m.2)).collect());
+        save_plot(&chart, stem, format)
+    }
+}
+
+// Sample standard deviation, 0 for a single value
+fn mean_and_deviation(values: &[f64]) -> (f64, f64) {
+    let mean = values.iter().sum::<f64>() / values.len().max(1) as f64;
+    if values.len() < 2 {
+        return (mean, 0.0);
+    }
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64;
+    (mean, variance.sqrt())
+}
+
+async fn run_once(settings: &ExperimentSettings,
+                  parameter: StudyParameter,
+                  value: usize,
+                  repeat: usize,
+                  seed: u64) -> Result<StudyRow, ThelmaError> {
+    let (mut node_count, mut payment_count, mut malicious_count) =
+        (settings.node_count, settings.payment_count, settings.malicious_count);
+    match parameter {
+        StudyParameter::Nodes => node_count = value,
+        StudyParameter::Payments => payment_count = value,
+        StudyParameter::Malicious => malicious_count = value,
+    }
+    let started = Instant::now();
+
+    let network_map = (settings.network)(seed, node_count)?;
+
+    let strategy: Box<dyn AdversaryStrategy> = match settings.placement {
+        Some(measure) => Box::new(CentralPlacement::new(measure)),
+        None => Box::new(RandomPlacement::seeded(seed)),
+    };
+    let config = (settings.surveillance)(&network_map, SurveillanceConfig::with_strategy(strategy, malicious_count));
+    let operation = SurveillanceOperation::new(network_map.clone(), config)?;
+    let malicious_nodes = operation.get_malicious_nodes().to_vec();
+    let surveillance = Arc::new(Mutex::new(operation));
+
+    let config = (settings.simulator)(&network_map, &malicious_nodes).seed(seed);
+    let mut simulator = PaymentSimulator::new(network_map.clone(), config);
+    let observer = simulator.register_observer(surveillance.clone());
+    simulator.simulate_payments(payment_count).await?;
+    simulator.close_events();
+    observer.await?;
+
+    let surveillance = lock_mutex(&surveillance);
+    let analysis_started = Instant::now();
+    let results = surveillance.run_analysis();
+    let analysis_time = analysis_started.elapsed();
+    let mut metrics = ScenarioMetrics::compute(&format!("{}={}", parameter.name(), value),
+                                               simulator.payment_records(), &results, &read_lock(&network_map));
+    metrics.record_analysis_cost(surveillance.observation_count(), analysis_time);
+
+    Ok(StudyRow {
+        value,
+        repeat,
+        seed,
+        node_count,
+        payment_count,
+        malicious_count,
+        metrics,
+        elapsed_ms: started.elapsed().as_secs_f64() * 1000.0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::{NetworkGenerator, topology_from_name};
+
+    fn settings() -> ExperimentSettings {
+        ExperimentSettings {
+            node_count: 20,
+            payment_count: 15,
+            malicious_count: 3,
+            placement: None,
+            network: Box::new(|seed, node_count| {
+                let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(780000)));
+                let mut topology = topology_from_name("scale-free", None)?;
+                NetworkGenerator::seeded(seed).create_network(network_map.clone(), topology.as_mut(), node_count)?;
+                Ok(network_map)
+            }),
+            simulator: Box::new(|_, malicious_nodes| SimulatorConfig::new().malicious_nodes(malicious_nodes.to_vec())),
+            surveillance: Box::new(|_, config| config),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scaling_study_is_reproducible() {
+        let mut first = ScalingStudy::new(StudyParameter::Nodes, vec![10, 25]).repeats(2).seed(7);
+        first.run(&settings()).await.unwrap();
+        let mut second = ScalingStudy::new(StudyParameter::Nodes, vec![10, 25]).repeats(2).seed(7);
+        second.run(&settings()).await.unwrap();
+
+        assert_eq!(first.rows.len(), 4);
+        for (a, b) in first.rows.iter().zip(&second.rows) {
+            assert_eq!((a.value, a.seed, a.node_count), (b.value, b.seed, b.node_count));
+            assert_eq!(a.metrics.observed_payments, b.metrics.observed_payments);
+            assert_eq!(a.metrics.recipients_identified, b.metrics.recipients_identified);
+        }
+        assert_eq!(first.rows[3].node_count, 25);
+        assert_eq!(first.rows[3].seed, 8);
+
+        let csv = first.generate_csv();
+        assert_eq!(csv.lines().count(), 5);
+        assert!(csv.lines().nth(1).unwrap().starts_with("nodes,10,0,7,10,15,3,"));
+        assert_eq!(first.means().len(), 2);
+
+        let latex = first.generate_latex();
+        assert!(latex.contains("\\label{tab:thelma-scaling-nodes}"));
+        assert_eq!(latex.lines().filter(|line| line.trim_start().starts_with("10 & ")
+            || line.trim_start().starts_with("25 & ")).count(), 2);
+        assert!(latex.contains("$\\pm$"));
+    }
+}
@@ -0,0 +1,160 @@
+// Identification accuracy at chosen cut-offs: how often the true recipient ranks within the
+// top k candidates, and how often it hides in a candidate set no larger than a given size.
+// Papers quote both, and a list of raw candidates can't be compared across runs.
+
+use std::collections::HashMap;
+
+use crate::surveillance::analyzer::PotentialRecipient;
+
+pub const DEFAULT_ACCURACY_TOP_K: [usize; 3] = [1, 3, 10];
+pub const DEFAULT_ACCURACY_SET_SIZES: [usize; 3] = [1, 5, 10];
+
+// Cut-offs identification rates are reported at
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccuracyThresholds {
+    pub top_k: Vec<usize>,
+    pub set_sizes: Vec<usize>,
+}
+
+impl Default for AccuracyThresholds {
+    fn default() -> Self {
+        AccuracyThresholds::new(DEFAULT_ACCURACY_TOP_K.to_vec(), DEFAULT_ACCURACY_SET_SIZES.to_vec())
+    }
+}
+
+impl AccuracyThresholds {
+    // Sorted, without duplicates or zeros
+    pub fn new(mut top_k: Vec<usize>, mut set_sizes: Vec<usize>) -> Self {
+        for values in [&mut top_k, &mut set_sizes] {
+            values.retain(|v| *v > 0);
+            values.sort();
+            values.dedup();
+        }
+        AccuracyThresholds { top_k, set_sizes }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IdentificationAccuracy {
+    // Analyzed payments whose recipient is known
+    pub payments: usize,
+    // Those whose recipient was among the candidates at all
+    pub in_candidates: usize,
+    // (k, payments whose recipient ranked within the top k)
+    pub top_k: Vec<(usize, usize)>,
+    // (size, payments whose recipient was in a candidate set of at most that many nodes)
+    pub set_sizes: Vec<(usize, usize)>,
+}
+
+impl IdentificationAccuracy {
+    // Rank each payment's candidate nodes as `rank_candidates` does. None without a payment
+    // whose route is known.
+    pub fn compute(results: &HashMap<String, Vec<PotentialRecipient>>,
+                   routes: &HashMap<String, Vec<String>>,
+                   thresholds: &AccuracyThresholds) -> Option<Self> {
+        let mut accuracy = IdentificationAccuracy {
+            top_k: thresholds.top_k.iter().map(|k| (*k, 0)).collect(),
+            set_sizes: thresholds.set_sizes.iter().map(|size| (*size, 0)).collect(),
+            ..IdentificationAccuracy::default()
+        };
+        for (payment_hash, recipients) in results {
+            let Some(receiver) = routes.get(payment_hash).and_then(|route| route.last()) else { continue };
+            accuracy.payments += 1;
+
+            let ranked = rank_candidates(recipients);
+            let Some(rank) = ranked.iter().position(|(node, _)| node == receiver) else { continue };
+            accuracy.in_candidates += 1;
+
+            for (k, hits) in &mut accuracy.top_k {
+                if rank < *k {
+                    *hits += 1;
+                }
+            }
+            for (size, hits) in &mut accuracy.set_sizes {
+                if ranked.len() <= *size {
+                    *hits += 1;
+                }
+            }
+        }
+        (accuracy.payments > 0).then_some(accuracy)
+    }
+
+    fn rate(&self, hits: usize) -> f64 {
+        if self.payments == 0 { 0.0 } else { hits as f64 / self.payments as f64 }
+    }
+
+    pub fn generate_text_section(&self) -> String {
+        let mut section = String::from("### Identification Accuracy\n\n");
+        section.push_str(&format!("{} payments with a known recipient, {} ({:.1}%) of them among the candidates\n\n",
+                                  self.payments, self.in_candidates, self.rate(self.in_candidates) * 100.0));
+        section.push_str("| Recipient | Payments | Rate |\n");
+        section.push_str("|---|---|---|\n");
+        for (k, hits) in &self.top_k {
+            section.push_str(&format!("| Top-{} | {} | {:.1}% |\n", k, hits, self.rate(*hits) * 100.0));
+        }
+        for (size, hits) in &self.set_sizes {
+            section.push_str(&format!("| In a set of at most {} | {} | {:.1}% |\n", size, hits, self.rate(*hits) * 100.0));
+        }
+        section.push('\n');
+        section
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let top_k: Vec<serde_json::Value> = self.top_k.iter()
+            .map(|(k, hits)| serde_json::json!({ "k": k, "payments": hits, "rate": self.rate(*hits) }))
+            .collect();
+        let set_sizes: Vec<serde_json::Value> = self.set_sizes.iter()
+            .map(|(size, hits)| serde_json::json!({ "max_set_size": size, "payments": hits, "rate": self.rate(*hits) }))
+            .collect();
+        serde_json::json!({
+            "payments": self.payments,
+            "in_candidates": self.in_candidates,
+            "top_k": top_k,
+            "set_sizes": set_sizes,
+        })
+    }
+}
+
+// A payment's candidate nodes by their routes' confidence added up, ties in the analyzer's
+// order
+pub(crate) fn rank_candidates(recipients: &[PotentialRecipient]) -> Vec<(&str, f32)> {
+    let mut ranked: Vec<(&str, f32)> = Vec::new();
+    for recipient in recipients {
+        match ranked.iter_mut().find(|(node, _)| *node == recipient.node_id) {
+            Some(entry) => entry.1 += recipient.confidence_score,
+            None => ranked.push((&recipient.node_id, recipient.confidence_score)),
+        }
+    }
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::candidates;
+
+    #[test]
+    fn test_rates_at_configured_cut_offs() {
+        let results = HashMap::from([
+            ("p1".to_string(), candidates(&[("a", 1.0)])),
+            // b's two routes outrank c
+            ("p2".to_string(), candidates(&[("c", 0.5), ("b", 0.3), ("b", 0.3), ("d", 0.1)])),
+            ("p3".to_string(), candidates(&[("x", 0.9), ("y", 0.5), ("e", 0.1)])),
+            ("p4".to_string(), candidates(&[("z", 1.0)])),
+            ("unknown".to_string(), candidates(&[("a", 1.0)])),
+        ]);
+        let routes: HashMap<String, Vec<String>> = [("p1", "a"), ("p2", "b"), ("p3", "e"), ("p4", "f")].iter()
+            .map(|(hash, receiver)| (hash.to_string(), vec!["s".to_string(), receiver.to_string()]))
+            .collect();
+        let thresholds = AccuracyThresholds::new(vec![3, 1, 0, 1], vec![3, 1]);
+        let accuracy = IdentificationAccuracy::compute(&results, &routes, &thresholds).unwrap();
+
+        assert_eq!((accuracy.payments, accuracy.in_candidates), (4, 3));
+        assert_eq!(accuracy.top_k, vec![(1, 2), (3, 3)]);
+        assert_eq!(accuracy.set_sizes, vec![(1, 1), (3, 3)]);
+        assert!(accuracy.generate_text_section().contains("| Top-1 | 2 | 50.0% |"));
+
+        assert!(IdentificationAccuracy::compute(&results, &HashMap::new(), &thresholds).is_none());
+    }
+}
@@ -1,10 +1,44 @@
 // HTLC analysis algorithms for surveillance
 
-use std::collections::{HashMap, HashSet};
-use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use lru::LruCache;
 use rayon::prelude::*;
-use log::log;
-use crate::models::{HTLC, LightningNetworkMap, TimelockAnalysis, DEFAULT_FINAL_CLTV_DELTA};
+use log::{debug, trace};
+use tracing::debug_span;
+
+use crate::models::{BoundedRoutes, CltvConstruction, HopCountModel, HTLC, HtlcResolution, CLTV_EXPIRY_DELTA_MIN, LightningNetworkMap, Reachability, RouteSet, DEFAULT_FINAL_CLTV_DELTA,
+                    DEFAULT_MAX_CLTV_EXPIRY, MAX_ROUTE_HOPS, SearchLimits, ShadowOffsetPrior, TimelockAnalysis};
+use crate::graph::Communities;
+use crate::simulation::find_path_avoiding;
+use crate::surveillance::scorer::{ConfidenceScorer, HeuristicScorer};
+use crate::surveillance::position::{ObserverPosition, infer_positions};
+use crate::surveillance::freshness::FreshnessHeuristic;
+use crate::surveillance::channel_age::ChannelAgePrior;
+use crate::surveillance::settlement::SettlementTiming;
+use crate::surveillance::plausibility::RecipientProfile;
+use crate::surveillance::hierarchy::{hub_degree_threshold, hub_leaves, rank_final_hubs};
+use crate::error::{lock_mutex, read_lock};
+use crate::logging::Progress;
+
+// Route searches are cached per observer for budgets within this many blocks of each other
+const ROUTE_CACHE_BUCKET_BLOCKS: u32 = 16;
+pub const DEFAULT_ROUTE_CACHE_CAPACITY: usize = 64;
+// Routes sampled for an observation whose enumeration wouldn't fit the memory budget
+pub const MEMORY_FALLBACK_SAMPLES: usize = 1000;
+// Unless a depth is set, routes are searched deep enough for this share of the hop counts an
+// observation likely has left
+pub const DEFAULT_HOP_COVERAGE: f64 = 0.999;
+// Searches deeper than this many hops can blow up on dense graphs. Without a depth or memory
+// budget set, they get this budget, and are sampled like any other search that outgrows it.
+pub const ENUMERATED_HOPS: usize = 5;
+pub const DEEP_SEARCH_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+// Most fees can add to an amount along one route: senders cap them at 5% by default (LND's
+// fee limit), so observations further apart belong to different shards of a split payment
+pub const MAX_ROUTE_FEE_SHARE: f64 = 0.05;
 
 // Result of surveillance analysis for a potential recipient
 #[derive(Debug, Clone)]
@@ -15,44 +49,553 @@ pub struct PotentialRecipient {
     pub confidence_score: f32,
 }
 
+impl PotentialRecipient {
+    // A candidate known by its node and confidence alone, without a route
+    pub fn new(node_id: &str, confidence_score: f32) -> Self {
+        PotentialRecipient { node_id: node_id.to_string(), node_alias: None, route: Vec::new(), confidence_score }
+    }
+}
+
+// Candidate recipient community, reported when no single node stands out
+#[derive(Debug, Clone)]
+pub struct CandidateCommunity {
+    pub community_id: usize,
+    pub community_size: usize,
+    pub candidates: Vec<String>,
+    // Share of the total candidate confidence falling inside this community
+    pub confidence_share: f32,
+}
+
+// How candidate routes from an observer are explored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AnalysisMode {
+    // Enumerate every feasible route (exact, but exponential in route length)
+    #[default]
+    Exhaustive,
+    // Estimate recipient probabilities from randomly sampled feasible routes
+    MonteCarlo { samples: usize },
+    // Pick the most likely final hubs over the hub backbone, then name only their leaves
+    Hierarchical { hubs: usize },
+}
+
+// Route searches keyed by (observer, budget bucket, max hops). Each entry holds every route
+// whose timelock fits some budget in the bucket, regardless of channel limits, so it can
+// answer any observation from that observer in the bucket exactly.
+struct RouteCache {
+    topology_version: u64,
+    entries: LruCache<(String, u32, usize), Arc<RouteSet>>,
+}
+
 // Core HTLC analysis functionality
 pub struct HTLCAnalyzer {
-    network: Arc<Mutex<LightningNetworkMap>>,
+    network: Arc<RwLock<LightningNetworkMap>>,
+    mode: AnalysisMode,
+    // Largest total timelock a route may carry
+    max_cltv_expiry: u32,
+    // Total timelock cap assumed for the sender's implementation, if known
+    sender_cltv_cap: Option<u32>,
+    // Which delta routes are retraced with for each forwarding hop
+    cltv_construction: CltvConstruction,
+    // Disabled when None
+    route_cache: Option<Mutex<RouteCache>>,
+    // Where routes from each observer can end, kept until the topology changes
+    reachability: Mutex<HashMap<String, Arc<Reachability>>>,
+    // How many hops HTLCs likely have left, kept until the topology changes
+    hop_model: Mutex<Option<Arc<HopCountModel>>>,
+    // Ranks enumerated candidate routes
+    scorer: Arc<dyn ConfidenceScorer>,
+    // Makes routes through channels stale at payment time less likely, when set
+    freshness: Option<FreshnessHeuristic>,
+    // Makes routes through channels young at payment time less likely, when set
+    channel_age: Option<ChannelAgePrior>,
+    // Makes routes whose length doesn't fit how long HTLCs took to resolve less likely, when set
+    settlement: Option<SettlementTiming>,
+    // Leaves candidates that couldn't plausibly be paid out, when set
+    recipients: Option<RecipientProfile>,
+    // Bytes one observation's route enumeration may take before it's sampled instead, when set
+    memory_budget: Option<usize>,
+    // Most hops a route search goes, if not as deep as the observation's likely hop counts
+    max_search_hops: Option<usize>,
+    // How long one payment's analysis may take before it settles for the routes found so far
+    time_budget: Option<Duration>,
+    // Most bytes any one enumeration took, and observations sampled for lack of memory.
+    // Analysis runs in parallel, so these are atomic.
+    peak_route_bytes: AtomicUsize,
+    memory_fallbacks: AtomicUsize,
+    // Observations whose route search stopped short of hop counts they likely have left
+    truncated_searches: AtomicUsize,
+    // Payments whose analysis ran out of time
+    incomplete: Mutex<HashSet<String>>,
 }
 
 impl HTLCAnalyzer {
-    pub fn new(network: Arc<Mutex<LightningNetworkMap>>) -> Self {
-        HTLCAnalyzer { network }
+    pub fn new(network: Arc<RwLock<LightningNetworkMap>>) -> Self {
+        let mut analyzer = HTLCAnalyzer {
+            network,
+            mode: AnalysisMode::default(),
+            max_cltv_expiry: DEFAULT_MAX_CLTV_EXPIRY,
+            sender_cltv_cap: None,
+            cltv_construction: CltvConstruction::default(),
+            route_cache: None,
+            reachability: Mutex::new(HashMap::new()),
+            hop_model: Mutex::new(None),
+            scorer: Arc::new(HeuristicScorer),
+            freshness: None,
+            channel_age: None,
+            settlement: None,
+            recipients: None,
+            memory_budget: None,
+            max_search_hops: None,
+            time_budget: None,
+            peak_route_bytes: AtomicUsize::new(0),
+            memory_fallbacks: AtomicUsize::new(0),
+            truncated_searches: AtomicUsize::new(0),
+            incomplete: Mutex::new(HashSet::new()),
+        };
+        analyzer.set_route_cache_capacity(DEFAULT_ROUTE_CACHE_CAPACITY);
+        analyzer
+    }
+
+    // Number of route searches to keep around for reuse; 0 disables the cache
+    pub fn set_route_cache_capacity(&mut self, capacity: usize) {
+        self.route_cache = NonZeroUsize::new(capacity).map(|capacity| Mutex::new(RouteCache {
+            topology_version: 0,
+            entries: LruCache::new(capacity),
+        }));
+    }
+
+    pub fn set_analysis_mode(&mut self, mode: AnalysisMode) {
+        self.mode = mode;
+    }
+
+    pub fn set_max_cltv_expiry(&mut self, max_cltv_expiry: u32) {
+        self.max_cltv_expiry = max_cltv_expiry;
+    }
+
+    // Replace how enumerated routes are scored. Monte Carlo estimates use sample shares.
+    pub fn set_scorer(&mut self, scorer: Arc<dyn ConfidenceScorer>) {
+        self.scorer = scorer;
+    }
+
+    // Assume senders cap the total route timelock at this many blocks
+    pub fn set_sender_cltv_cap(&mut self, cap: u32) {
+        self.sender_cltv_cap = Some(cap);
+    }
+
+    // Retrace routes the way senders built them: matching `--cltv-construction` keeps the
+    // analysis' timelocks the simulator's
+    pub fn set_cltv_construction(&mut self, construction: CltvConstruction) {
+        self.cltv_construction = construction;
+    }
+
+    // Weigh enumerated routes by how fresh their channels' gossip was
+    pub fn set_freshness(&mut self, freshness: FreshnessHeuristic) {
+        self.freshness = Some(freshness);
+    }
+
+    // Weigh enumerated routes by how long their channels had been open
+    pub fn set_channel_age_prior(&mut self, prior: ChannelAgePrior) {
+        self.channel_age = Some(prior);
+    }
+
+    // Weigh enumerated routes by how long our nodes waited for their HTLCs to resolve
+    pub fn set_settlement_timing(&mut self, timing: SettlementTiming) {
+        self.settlement = Some(timing);
+    }
+
+    // Sample an observation's routes when enumerating them would take more than `bytes`
+    pub fn set_memory_budget(&mut self, bytes: usize) {
+        self.memory_budget = Some(bytes);
+    }
+
+    pub fn memory_budget(&self) -> Option<usize> {
+        self.memory_budget
+    }
+
+    // Most bytes one observation's route enumeration has taken so far
+    pub fn peak_route_bytes(&self) -> usize {
+        self.peak_route_bytes.load(Ordering::Relaxed)
+    }
+
+    // Observations sampled because enumerating their routes would have exceeded the budget,
+    // since the counter was last reset
+    pub fn memory_fallbacks(&self) -> usize {
+        self.memory_fallbacks.load(Ordering::Relaxed)
+    }
+
+    pub fn reset_memory_fallbacks(&self) {
+        self.memory_fallbacks.store(0, Ordering::Relaxed);
+    }
+
+    // Search routes at most `hops` hops deep, however many an observation likely has left
+    pub fn set_max_search_hops(&mut self, hops: usize) {
+        self.max_search_hops = Some(hops);
+    }
+
+    // Observations whose route search was cut short of hop counts they likely had left,
+    // since the counter was last reset
+    pub fn truncated_searches(&self) -> usize {
+        self.truncated_searches.load(Ordering::Relaxed)
+    }
+
+    pub fn reset_truncated_searches(&self) {
+        self.truncated_searches.store(0, Ordering::Relaxed);
+    }
+
+    // Give each payment's analysis this long, then rank the candidates found so far
+    pub fn set_time_budget(&mut self, budget: Duration) {
+        self.time_budget = Some(budget);
+    }
+
+    pub fn time_budget(&self) -> Option<Duration> {
+        self.time_budget
+    }
+
+    // Payments whose analysis ran out of time since the set was last reset
+    pub fn incomplete_payments(&self) -> HashSet<String> {
+        lock_mutex(&self.incomplete).clone()
+    }
+
+    pub fn reset_incomplete_payments(&self) {
+        lock_mutex(&self.incomplete).clear();
+    }
+
+    fn mark_incomplete(&self, htlc: &HTLC) {
+        debug!("Analysis of payment {} ran out of time, keeping the candidates found so far", htlc.payment_hash);
+        lock_mutex(&self.incomplete).insert(htlc.payment_hash.clone());
+    }
+
+    // Only consider recipients the profile admits
+    pub fn set_recipient_profile(&mut self, profile: RecipientProfile) {
+        self.recipients = Some(profile);
+    }
+
+    pub fn recipient_profile(&self) -> Option<&RecipientProfile> {
+        self.recipients.as_ref()
+    }
+
+    // Learn from an observation where payments end, for the recipient profile
+    pub fn record_sink(&mut self, htlc: &HTLC) {
+        if let Some(profile) = &mut self.recipients {
+            profile.record(htlc);
+        }
+    }
+
+    pub fn settlement_timing(&self) -> Option<&SettlementTiming> {
+        self.settlement.as_ref()
+    }
+
+    // Remember when one of our nodes saw an HTLC settle or fail
+    pub fn record_resolution(&mut self, resolution: &HtlcResolution) {
+        if let Some(settlement) = &mut self.settlement {
+            settlement.record(resolution);
+        }
+    }
+
+    // Remember a `channel_update` for judging later payments' routes
+    pub fn record_channel_update(&mut self, channel_id: &str, block: u32) {
+        if let Some(freshness) = &mut self.freshness {
+            freshness.record(channel_id, block);
+        }
     }
 
     // Analyze a specific HTLC observation to determine potential recipients
     pub fn analyze_htlc(&self, htlc: &HTLC) -> Vec<PotentialRecipient> {
-        log::info!("Analyzing HTLC");
-        let network = self.network.lock().unwrap();
+        self.analyze_htlc_until(htlc, None)
+    }
+
+    // Same as `analyze_htlc`, ranking the routes found by the deadline if enumerating them
+    // all takes longer, and noting the payment as incomplete
+    fn analyze_htlc_until(&self, htlc: &HTLC, deadline: Option<Instant>) -> Vec<PotentialRecipient> {
+        let network = read_lock(&self.network);
+
+        // The onion tells a recipient it's the one, and a trampoline who it is
+        if let Some(recipient) = Self::known_recipient(&network, htlc) {
+            return vec![recipient];
+        }
+        let next_hop = Self::next_hop(&network, htlc);
 
-        let timelock_analysis = htlc.timelock_analysis();
+        // Bound the search by the smallest invoice delta any candidate could have asked for
+        let timelock_analysis = htlc.timelock_analysis_with_model(&self.hop_model(&network));
         let observed_node = htlc.observed_by_node.clone();
-        let max_hops = timelock_analysis.max_remaining_hops;
 
-        let routes = network.find_possible_routes_with_budget(
-            &observed_node,
-            timelock_analysis.remaining_cltv_budget,
-            max_hops,
-        );
+        // No valid route carries more timelock than the protocol cap
+        let budget = std::cmp::min(timelock_analysis.remaining_cltv_budget, self.max_cltv_expiry);
+
+        if let AnalysisMode::MonteCarlo { samples } = self.mode {
+            debug!("HTLC Analysis for hash {} (sampling {} routes)", htlc.payment_hash, samples);
+            return self.estimate_recipients(&network, htlc, next_hop.as_deref(), budget, samples);
+        }
+        let max_hops = self.search_depth(htlc, &timelock_analysis);
+        if let AnalysisMode::Hierarchical { hubs } = self.mode {
+            debug!("HTLC Analysis for hash {} (over the {} likeliest final hubs)", htlc.payment_hash, hubs);
+            return self.analyze_through_hubs(&network, htlc, next_hop.as_deref(), budget, max_hops, hubs);
+        }
+
+        // Nothing to enumerate when no route from here fits what the HTLC has left
+        let reachable = self.reachability(&network, &observed_node).count_within(budget);
+        if reachable == 0 {
+            trace!("  No node reachable from {} within {} blocks", observed_node, budget);
+            return Vec::new();
+        }
+
+        let Some((mut routes, complete)) = self.find_routes_cached(&network, &observed_node, budget, max_hops,
+                                                                   htlc.forwarded_amount(), deadline) else {
+            self.memory_fallbacks.fetch_add(1, Ordering::Relaxed);
+            debug!("Routes from {} for payment {} exceed the memory budget, sampling {} instead",
+                   observed_node, htlc.payment_hash, MEMORY_FALLBACK_SAMPLES);
+            return self.estimate_recipients(&network, htlc, next_hop.as_deref(), budget, MEMORY_FALLBACK_SAMPLES);
+        };
+        if !complete {
+            self.mark_incomplete(htlc);
+        }
+        routes.retain(|route| follows_onion(route, next_hop.as_deref()));
+
+        debug!("HTLC Analysis for hash {}", htlc.payment_hash);
+        trace!("  Remaining CLTV budget: {}", timelock_analysis.remaining_cltv_budget);
+        trace!("  Searching up to {} of at most {} hops remaining", max_hops, timelock_analysis.max_remaining_hops);
+        trace!("  Potential final hop: {}", timelock_analysis.could_be_final_hop);
+        trace!("  Found {} potential routes from node {}", routes.len(), observed_node);
+
+        self.score_routes(&network, htlc, budget, routes)
+    }
+
+    // How many hops deep to search an observation's routes: as set, or deep enough for all
+    // but the unlikeliest hop counts it has left. Never past what its timelock allows.
+    fn search_depth(&self, htlc: &HTLC, analysis: &TimelockAnalysis) -> usize {
+        let bound = analysis.max_remaining_hops;
+        let depth = match self.max_search_hops {
+            Some(hops) => hops.min(bound),
+            None => analysis.remaining_hops.covering(DEFAULT_HOP_COVERAGE).map_or(bound, |hops| hops.min(bound)),
+        };
+        let unexplored = analysis.remaining_hops.beyond(depth);
+        if unexplored > 1.0 - DEFAULT_HOP_COVERAGE {
+            self.truncated_searches.fetch_add(1, Ordering::Relaxed);
+            debug!("Searching routes from {} for payment {} {} hops deep leaves out {:.1}% of its likely hop counts",
+                   htlc.observed_by_node, htlc.payment_hash, depth, unexplored * 100.0);
+        }
+        depth
+    }
+
+    // Routes ending at a leaf of one of the `hubs` likeliest final hubs, entered from that hub
+    fn analyze_through_hubs(&self,
+                            network: &LightningNetworkMap,
+                            htlc: &HTLC,
+                            next_hop: Option<&str>,
+                            budget: u32,
+                            max_hops: usize,
+                            hubs: usize) -> Vec<PotentialRecipient> {
+        let observer = &htlc.observed_by_node;
+        let final_hubs: Vec<String> = rank_final_hubs(network, observer, next_hop, budget, max_hops)
+            .into_iter()
+            .take(hubs)
+            .map(|(hub, _)| hub)
+            .collect();
+        let threshold = hub_degree_threshold(network);
+        let leaves: HashSet<String> = final_hubs.iter()
+            .flat_map(|hub| hub_leaves(network, hub, threshold))
+            .collect();
+        trace!("  Final hubs from {}: {:?} ({} leaves)", observer, final_hubs, leaves.len());
+        if leaves.is_empty() {
+            return Vec::new();
+        }
+
+        let mut routes = network.find_routes_to_candidates(observer, &leaves, budget, max_hops, htlc.forwarded_amount(),
+                                                           self.cltv_construction);
+        routes.retain(|route| {
+            route.len() >= 2 && final_hubs.contains(&route[route.len() - 2]) && follows_onion(route, next_hop)
+        });
+        self.score_routes(network, htlc, budget, routes)
+    }
+
+    // Same as `find_possible_routes_with_budget`, reusing an earlier search from the same
+    // observer when one covered this budget. None when the routes wouldn't fit the memory
+    // budget; otherwise the routes and whether the search finished before the deadline.
+    // Searches cut short aren't kept for reuse.
+    fn find_routes_cached(&self,
+                          network: &LightningNetworkMap,
+                          observer: &str,
+                          budget: u32,
+                          max_hops: usize,
+                          amount_msat: u64,
+                          deadline: Option<Instant>) -> Option<(Vec<Vec<String>>, bool)> {
+        // Every stored hop is turned back into a pubkey before scoring
+        let max_stored_hops = self.enumeration_budget(max_hops).map_or(usize::MAX, |bytes| bytes / network.route_hop_bytes());
+        let limits = SearchLimits { max_stored_hops, deadline };
+        let Some(cache) = &self.route_cache else {
+            let (routes, complete) = match network.find_routes_in_window_limited(observer,
+                                                                                 budget.saturating_sub(network.max_shadow_offset())..=budget,
+                                                                                 max_hops,
+                                                                                 Some(amount_msat),
+                                                                                 self.cltv_construction,
+                                                                                 limits) {
+                BoundedRoutes::Complete(routes) => (routes, true),
+                BoundedRoutes::TimedOut(routes) => (routes, false),
+                BoundedRoutes::TooMany => return None,
+            };
+            return Some((self.materialize(network, &routes, budget, amount_msat), complete));
+        };
+
+        let bucket = budget / ROUTE_CACHE_BUCKET_BLOCKS;
+        let key = (observer.to_string(), bucket, max_hops);
+        let cached = {
+            let mut cache = lock_mutex(cache);
+            if cache.topology_version != network.topology_version() {
+                cache.entries.clear();
+                cache.topology_version = network.topology_version();
+            }
+            cache.entries.get(&key).cloned()
+        };
+
+        let (routes, complete) = match cached {
+            Some(routes) => (routes, true),
+            None => {
+                let lowest = bucket * ROUTE_CACHE_BUCKET_BLOCKS;
+                let highest = lowest + ROUTE_CACHE_BUCKET_BLOCKS - 1;
+                match network.find_routes_in_window_limited(observer,
+                                                            lowest.saturating_sub(network.max_shadow_offset())..=highest,
+                                                            max_hops,
+                                                            None,
+                                                            self.cltv_construction,
+                                                            limits) {
+                    BoundedRoutes::Complete(routes) => {
+                        let routes = Arc::new(routes);
+                        lock_mutex(cache).entries.put(key, routes.clone());
+                        (routes, true)
+                    }
+                    BoundedRoutes::TimedOut(routes) => (Arc::new(routes), false),
+                    BoundedRoutes::TooMany => return None,
+                }
+            }
+        };
+
+        Some((self.materialize(network, &routes, budget, amount_msat), complete))
+    }
+
+    // Bytes a search `max_hops` deep may take before it's sampled instead, if limited
+    fn enumeration_budget(&self, max_hops: usize) -> Option<usize> {
+        self.memory_budget.or_else(|| {
+            (self.max_search_hops.is_none() && max_hops > ENUMERATED_HOPS).then_some(DEEP_SEARCH_MEMORY_BUDGET)
+        })
+    }
+
+    // Pubkeys of the routes that fit this budget and amount, noting the memory they take
+    fn materialize(&self, network: &LightningNetworkMap, routes: &RouteSet, budget: u32, amount_msat: u64) -> Vec<Vec<String>> {
+        let routes_found = routes.iter()
+            .filter(|(_, timelock)| *timelock <= budget && budget - timelock <= network.max_shadow_offset())
+            .filter(|(route, _)| network.route_can_carry(route, amount_msat))
+            .map(|(route, _)| network.pub_keys_of(route))
+            .collect::<Vec<_>>();
+        let bytes = routes.memory_bytes() + routes_found.capacity() * std::mem::size_of::<Vec<String>>()
+            + routes_found.iter()
+                .map(|route| route.capacity() * std::mem::size_of::<String>() + route.iter().map(String::capacity).sum::<usize>())
+                .sum::<usize>();
+        self.peak_route_bytes.fetch_max(bytes, Ordering::Relaxed);
+        routes_found
+    }
+
+    // Where routes from an observer can end, computed on first use and again whenever the
+    // topology changed
+    fn hop_model(&self, network: &LightningNetworkMap) -> Arc<HopCountModel> {
+        let mut cached = lock_mutex(&self.hop_model);
+        match cached.as_ref() {
+            Some(model) if model.topology_version() == network.topology_version()
+                && model.horizon() == self.max_cltv_expiry => model.clone(),
+            _ => cached.insert(Arc::new(HopCountModel::of(network, self.max_cltv_expiry))).clone(),
+        }
+    }
+
+    fn reachability(&self, network: &LightningNetworkMap, observer: &str) -> Arc<Reachability> {
+        let cached = lock_mutex(&self.reachability).get(observer)
+            .filter(|reachability| reachability.topology_version() == network.topology_version())
+            .cloned();
+        cached.unwrap_or_else(|| {
+            let reachability = Arc::new(network.reachability_from(observer, MAX_ROUTE_HOPS, self.cltv_construction));
+            lock_mutex(&self.reachability).insert(observer.to_string(), reachability.clone());
+            reachability
+        })
+    }
+
+    // Work out up front where routes from each of our nodes can end, so observations don't
+    // explore towards candidates out of reach one by one
+    pub fn precompute_reachability(&self, observers: &[String]) {
+        let network = read_lock(&self.network);
+        observers.par_iter().for_each(|observer| {
+            self.reachability(&network, observer);
+        });
+    }
+
+    // Analyze an HTLC when the recipient is already known to be one of `candidates`,
+    // meeting in the middle between the observer and the candidates
+    pub fn analyze_htlc_towards(&self, htlc: &HTLC, candidates: &HashSet<String>) -> Vec<PotentialRecipient> {
+        let network = read_lock(&self.network);
+
+        let timelock_analysis = htlc.timelock_analysis_with_model(&self.hop_model(&network));
+        let budget = std::cmp::min(timelock_analysis.remaining_cltv_budget, self.max_cltv_expiry);
+
+        if let Some(recipient) = Self::known_recipient(&network, htlc) {
+            return if candidates.contains(&recipient.node_id) { vec![recipient] } else { Vec::new() };
+        }
+        let max_hops = self.search_depth(htlc, &timelock_analysis);
+        let next_hop = Self::next_hop(&network, htlc);
+
+        // Only search towards candidates some route from this observer could still reach
+        let reachability = self.reachability(&network, &htlc.observed_by_node);
+        let candidates: HashSet<String> = candidates.iter()
+            .filter(|candidate| reachability.reachable_within(&network, candidate, budget))
+            .cloned()
+            .collect();
+        if candidates.is_empty() {
+            return Vec::new();
+        }
+        let mut routes = network.find_routes_to_candidates(&htlc.observed_by_node, &candidates,
+                                                           budget, max_hops, htlc.forwarded_amount(), self.cltv_construction);
+        routes.retain(|route| follows_onion(route, next_hop.as_deref()));
+        trace!("  Found {} routes from node {} to {} known candidates",
+               routes.len(), htlc.observed_by_node, candidates.len());
 
-        println!("HTLC Analysis for hash {}", htlc.payment_hash);
-        println!("  Remaining CLTV budget: {}", timelock_analysis.remaining_cltv_budget);
-        println!("  Estimated hops remaining: up to {}", max_hops);
-        println!("  Potential final hop: {}", timelock_analysis.could_be_final_hop);
-        println!("  Found {} potential routes from node {}", routes.len(), observed_node);
+        self.score_routes(&network, htlc, budget, routes)
+    }
 
+    // Turn candidate routes into recipients ranked by confidence. The timelock a route
+    // leaves unused is the sender's shadow offset, so routes are also weighed by how likely
+    // senders on this network are to pick that offset, and by their channels' freshness
+    // and age when those are taken into account.
+    fn score_routes(&self,
+                    network: &LightningNetworkMap,
+                    htlc: &HTLC,
+                    budget: u32,
+                    mut routes: Vec<Vec<String>>) -> Vec<PotentialRecipient> {
+        let found = routes.len();
+        canonicalize_routes(&mut routes);
+        if routes.len() < found {
+            trace!("  Dropped {} duplicate routes", found - routes.len());
+        }
+        let unique = routes.len();
+        routes.retain(|route| self.plausible(network, route));
+        if routes.len() < unique {
+            trace!("  Dropped {} routes to implausible recipients", unique - routes.len());
+        }
+        let prior = ShadowOffsetPrior::of(network);
+        let hops = self.hop_model(network).distribution(budget);
         let potential_recipients: Vec<PotentialRecipient> = routes
             .par_iter()
             .filter_map(|route| {
                 if let Some(recipient) = route.last() {
                     network.nodes.get(recipient).map(|node| {
-                        let confidence = Self::calculate_confidence_score(route, &timelock_analysis, &network);
-                        println!("  Potential recipient: {} with confidence {:.2}", node.alias, confidence);
+                        // Judge the final hop against the candidate's own invoice delta
+                        let recipient_analysis = htlc.timelock_analysis_for_invoice(node.min_final_cltv_expiry_delta);
+                        let offset = budget.saturating_sub(route_timelock(network, route, htlc.forwarded_amount(),
+                                                                                  self.cltv_construction));
+                        let freshness = self.freshness.as_ref()
+                            .map_or(1.0, |freshness| freshness.weight(network, route, htlc.observed_at_block));
+                        let age = self.channel_age.as_ref()
+                            .map_or(1.0, |age| age.weight(network, route, htlc.observed_at_block));
+                        let timing = self.settlement.as_ref()
+                            .map_or(1.0, |settlement| settlement.weight(htlc, route.len() - 1));
+                        let confidence = self.scorer.score(network, route, &recipient_analysis, htlc.amount)
+                            * (prior.weight(offset) * hops.weight(route.len() - 1) * freshness * age * timing) as f32;
+                        trace!("  Potential recipient: {} with confidence {:.2}", node.alias, confidence);
                         PotentialRecipient {
                             node_id: recipient.clone(),
                             node_alias: Some(node.alias.clone()),
@@ -71,6 +614,76 @@ impl HTLCAnalyzer {
         sorted_recipients
     }
 
+    // Whether a route ends at a node the recipient profile admits, or there is none
+    fn plausible(&self, network: &LightningNetworkMap, route: &[String]) -> bool {
+        self.recipients.as_ref()
+            .is_none_or(|profile| route.last().is_some_and(|recipient| profile.admits(network, recipient)))
+    }
+
+    // Sample routes instead of enumerating them. Sampling needs no hop cap beyond the
+    // protocol's, and a recipient's confidence is the share of samples ending there.
+    fn estimate_recipients(&self,
+                           network: &LightningNetworkMap,
+                           htlc: &HTLC,
+                           next_hop: Option<&str>,
+                           budget: u32,
+                           samples: usize) -> Vec<PotentialRecipient> {
+        let routes: Vec<Vec<String>> = (0..samples).into_par_iter()
+            .map_init(rand::rng, |rng, _| {
+                network.sample_route_with_budget(&htlc.observed_by_node, budget, MAX_ROUTE_HOPS,
+                                                 htlc.forwarded_amount(), self.cltv_construction, rng)
+            })
+            .flatten()
+            .filter(|route| follows_onion(route, next_hop))
+            .filter(|route| self.plausible(network, route))
+            .collect();
+
+        if routes.is_empty() {
+            return Vec::new();
+        }
+
+        // Per recipient: number of samples and how often each route to it came up
+        let mut hits: HashMap<&String, (usize, HashMap<&Vec<String>, usize>)> = HashMap::new();
+        for route in &routes {
+            if let Some(recipient) = route.last() {
+                let entry = hits.entry(recipient).or_default();
+                entry.0 += 1;
+                *entry.1.entry(route).or_default() += 1;
+            }
+        }
+
+        let mut recipients: Vec<PotentialRecipient> = hits.into_iter()
+            .map(|(recipient, (count, route_counts))| {
+                let route = route_counts.into_iter()
+                    .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(a.0)))
+                    .map(|(route, _)| route.clone())
+                    .unwrap_or_default();
+                PotentialRecipient {
+                    node_id: recipient.clone(),
+                    node_alias: network.nodes.get(recipient).map(|node| node.alias.clone()),
+                    route,
+                    confidence_score: count as f32 / routes.len() as f32,
+                }
+            })
+            .collect();
+
+        trace!("  Sampled {} feasible routes to {} potential recipients", routes.len(), recipients.len());
+
+        recipients.sort_by(|a, b| b.confidence_score.partial_cmp(&a.confidence_score).unwrap()
+            .then(a.node_id.cmp(&b.node_id)));
+        recipients
+    }
+
+    // Place the observers of one payment on its route, given the recipients inferred for it
+    pub fn infer_positions(&self, observations: &[HTLC], recipients: &[PotentialRecipient]) -> Vec<ObserverPosition> {
+        let network = read_lock(&self.network);
+        // The most upstream observation bounds how far the sender can be
+        let max_upstream_hops = self.sender_cltv_cap.and_then(|cap| {
+            observations.iter().max_by_key(|htlc| htlc.cltv_expiry).map(|htlc| htlc.max_upstream_hops(cap))
+        });
+        infer_positions(&network, observations, recipients, max_upstream_hops)
+    }
+
     // Correlate observations from multiple malicious nodes to narrow down senders/recipients
     pub fn correlate_observations(&self, observations: &[HTLC]) -> HashMap<String, Vec<PotentialRecipient>> {
         let mut payment_hash_map: HashMap<String, Vec<HTLC>> = HashMap::new();
@@ -78,108 +691,227 @@ impl HTLCAnalyzer {
         // Group observations by payment hash
         for htlc in observations {
             payment_hash_map.entry(htlc.payment_hash.clone())
-                .or_insert_with(Vec::new)
+                .or_default()
                 .push(htlc.clone());
         }
 
-        let mut results = HashMap::new();
+        // Payments are independent, so analyze them in parallel
+        let progress = Progress::start(payment_hash_map.len(), "Correlating payments");
 
-        // For each payment hash, correlate observations
-        for (payment_hash, observations) in payment_hash_map {
-            if observations.len() < 2 {
-                println!("Only one observation for payment hash {}, insufficient for correlation", payment_hash);
+        let results = payment_hash_map.into_par_iter()
+            .filter_map(|(payment_hash, observations)| {
+                let recipients = self.correlate_payment(&payment_hash, &observations);
+                progress.inc();
+                recipients.map(|recipients| (payment_hash, recipients))
+            })
+            .collect();
 
-                // We can still analyze single observations
-                if let Some(htlc) = observations.first() {
-                    let recipients = self.analyze_htlc(htlc);
-                    if !recipients.is_empty() {
-                        results.insert(payment_hash, recipients);
-                    }
-                }
+        progress.finish();
+        results
+    }
 
-                continue;
-            }
+    // Correlate all observations of one payment. Returns None when no candidate remains.
+    fn correlate_payment(&self, payment_hash: &str, observations: &[HTLC]) -> Option<Vec<PotentialRecipient>> {
+        let _span = debug_span!("correlate_payment").entered();
+        // Each payment has the time budget to itself
+        let deadline = self.time_budget.map(|budget| Instant::now() + budget);
+        if observations.len() < 2 {
+            debug!("Only one observation for payment hash {}, insufficient for correlation", payment_hash);
+
+            // We can still analyze single observations
+            let recipients = self.analyze_htlc_until(observations.first()?, deadline);
+            return if recipients.is_empty() { None } else { Some(recipients) };
+        }
+
+        // A payment retried after failing in flight shows up again at a later block, over a
+        // route of its own. Only the latest attempt can have gone through.
+        let latest_block = observations.iter().map(|htlc| htlc.observed_at_block).max()?;
+        let attempt: Vec<&HTLC> = observations.iter().filter(|htlc| htlc.observed_at_block == latest_block).collect();
+        if attempt.len() < observations.len() {
+            debug!("Payment hash {} was retried, keeping the {} observations from block {}",
+                   payment_hash, attempt.len(), latest_block);
+        }
 
-            println!("Correlating {} observations for payment hash {}", observations.len(), payment_hash);
+        // An observer the onion made the recipient, or asked to reach it, settles it
+        if let Some(recipient) = attempt.iter().find(|htlc| htlc.reached_recipient() || htlc.trampoline_destination().is_some()) {
+            return Some(self.analyze_htlc_until(recipient, deadline));
+        }
 
-            // Sort by CLTV expiry to establish order in the route
-            let mut sorted_obs = observations.clone();
-            sorted_obs.sort_by_key(|htlc| htlc.cltv_expiry);
+        debug!("Correlating {} observations for payment hash {}", attempt.len(), payment_hash);
 
-            // Analyze the last observation (closest to recipient)
-            if let Some(last_obs) = sorted_obs.last() {
-                println!("Analyzing last observation in route for payment hash {}", payment_hash);
-                // Analyze for potential recipients
-                let potential_recipients = self.analyze_htlc(last_obs);
+        // Sort by CLTV expiry to establish order in the route
+        let mut sorted_obs: Vec<HTLC> = attempt.into_iter().cloned().collect();
+        sorted_obs.sort_by_key(|htlc| htlc.cltv_expiry);
+        if Self::orderings_disagree(&sorted_obs) {
+            debug!("CLTV and amount orderings disagree for payment hash {}: decoys or nonstandard forwarding",
+                   payment_hash);
+        }
 
-                if !potential_recipients.is_empty() {
-                    // Store the results
-                    results.insert(payment_hash, potential_recipients);
-                }
+        // Analyze the last observation (closest to recipient)
+        let last_obs = sorted_obs.last()?;
+        trace!("Analyzing last observation in route for payment hash {}", payment_hash);
+        // Analyze for potential recipients
+        let mut potential_recipients = self.analyze_htlc_until(last_obs, deadline);
+
+        // The recipient must also be reachable from every other observation point.
+        // With the candidate set known, searching from both ends is cheap.
+        for other in sorted_obs.iter().filter(|o| o.observed_by_node != last_obs.observed_by_node) {
+            let candidates: HashSet<String> = potential_recipients.iter()
+                .map(|r| r.node_id.clone())
+                .collect();
+            if candidates.is_empty() {
+                break;
+            }
+            // Out of time: the candidates stay as narrowed so far
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                self.mark_incomplete(last_obs);
+                break;
+            }
+
+            let reachable: HashSet<String> = self.analyze_htlc_towards(other, &candidates).into_iter()
+                .map(|r| r.node_id)
+                .collect();
+            if !reachable.is_empty() {
+                potential_recipients.retain(|r| reachable.contains(&r.node_id));
             }
         }
 
-        results
+        if potential_recipients.is_empty() {
+            None
+        } else {
+            Some(potential_recipients)
+        }
     }
 
-    // Calculate a confidence score for a potential route
-    fn calculate_confidence_score(
-        route: &[String],
-        analysis: &TimelockAnalysis,
-        network: &LightningNetworkMap,
-    ) -> f32 {
-        // Base confidence starts at 1.0
-        let mut confidence = 1.0;
+    // Whether ordering a payment's observations by amount contradicts ordering them by
+    // timelock. Both shrink towards the recipient, amounts by the fees each hop keeps, so an
+    // observer that saw a later timelock but a smaller amount than another points at decoys
+    // or a node that doesn't forward the way the protocol says. Equal amounts (zero-fee hops)
+    // don't count against either order. Shards of a split payment share the hash but not the
+    // route, and their timelocks and amounts needn't line up, so payments showing parallel
+    // shards aren't checked.
+    pub fn orderings_disagree(observations: &[HTLC]) -> bool {
+        let pairs = || observations.iter().enumerate()
+            .flat_map(|(i, a)| observations[i + 1..].iter().map(move |b| (a, b)));
+        if pairs().any(|(a, b)| !Self::could_share_route(a, b)) {
+            return false;
+        }
+        pairs().any(|(a, b)| {
+            (a.cltv_expiry < b.cltv_expiry && a.amount > b.amount)
+                || (b.cltv_expiry < a.cltv_expiry && b.amount > a.amount)
+        })
+    }
 
-        // Penalize longer routes (prefer shorter)
-        confidence *= 1.0 / (route.len() as f32).powf(0.5);
+    // A route passes each node once, every hop between two observers adds at least the
+    // minimum delta to the timelock, and fees alone separate the amounts
+    fn could_share_route(a: &HTLC, b: &HTLC) -> bool {
+        a.observed_by_node != b.observed_by_node
+            && a.cltv_expiry.abs_diff(b.cltv_expiry) >= CLTV_EXPIRY_DELTA_MIN
+            && a.amount.abs_diff(b.amount) as f64 <= a.amount.max(b.amount) as f64 * MAX_ROUTE_FEE_SHARE
+    }
 
-        // Boost if could be final hop and route is short
-        if analysis.could_be_final_hop && route.len() <= 2 {
-            confidence *= 1.5;
+    // Group candidates by community when the best individual node holds less than
+    // `threshold` of the total confidence. Returns None when a single node stands out.
+    pub fn summarize_by_community(recipients: &[PotentialRecipient],
+                                  communities: &Communities,
+                                  threshold: f32) -> Option<Vec<CandidateCommunity>> {
+        let total: f32 = recipients.iter().map(|r| r.confidence_score).sum();
+        if total <= 0.0 {
+            return None;
         }
 
-        // Check if final node has standard CLTV delta
-        if let Some(recipient) = route.last() {
-            if let Some(node) = network.nodes.get(recipient) {
-                let delta_diff = (node.cltv_expiry_delta as i32 - DEFAULT_FINAL_CLTV_DELTA as i32).abs();
-                if delta_diff <= 5 {
-                    confidence *= 1.3;
-                }
-            }
+        // Several routes can end at the same node, so sum per node first
+        let mut per_node: HashMap<&String, f32> = HashMap::new();
+        for recipient in recipients {
+            *per_node.entry(&recipient.node_id).or_insert(0.0) += recipient.confidence_score;
         }
 
-        // Penalize route if links are not consistent
-        let mut consistent = true;
-        for i in 0..route.len().saturating_sub(1) {
-            let from = &route[i];
-            let to = &route[i + 1];
+        let top_share = per_node.values().cloned().fold(0.0, f32::max) / total;
+        if top_share >= threshold {
+            return None;
+        }
 
-            if let Some(neighbors) = network.get_neighbors(from) {
-                if !neighbors.contains(to) {
-                    consistent = false;
-                    break;
-                }
-            } else {
-                consistent = false;
-                break;
+        let mut grouped: HashMap<usize, (Vec<String>, f32)> = HashMap::new();
+        for (node, confidence) in per_node {
+            if let Some(community_id) = communities.community_of(node) {
+                let entry = grouped.entry(community_id).or_default();
+                entry.0.push(node.clone());
+                entry.1 += confidence;
             }
         }
 
-        if !consistent {
-            confidence *= 0.1;
-        }
+        let mut summary: Vec<CandidateCommunity> = grouped.into_iter()
+            .map(|(community_id, (mut candidates, confidence))| {
+                candidates.sort();
+                CandidateCommunity {
+                    community_id,
+                    community_size: communities.members(community_id).len(),
+                    candidates,
+                    confidence_share: confidence / total,
+                }
+            })
+            .collect();
+
+        summary.sort_by(|a, b| b.confidence_share.partial_cmp(&a.confidence_share).unwrap()
+            .then(a.community_id.cmp(&b.community_id)));
+        Some(summary)
+    }
 
-        confidence
+    // The node the observer's onion layer has it forward to, if the observation carries one
+    // and the channel is in the graph
+    fn next_hop(network: &LightningNetworkMap, htlc: &HTLC) -> Option<String> {
+        let channel = network.channel(htlc.outgoing_channel()?)?;
+        channel.peer_of(&htlc.observed_by_node).map(str::to_string)
     }
 
+    // The node the HTLC came in from, if the observation says over which channel
+    fn previous_hop(network: &LightningNetworkMap, htlc: &HTLC) -> Option<String> {
+        let channel = network.channel(htlc.incoming_channel.as_deref()?)?;
+        channel.peer_of(&htlc.observed_by_node).map(str::to_string)
+    }
+
+    // The recipient when the onion names it: the observer itself, or the node a trampoline
+    // is asked to reach, over the shortest route there
+    fn known_recipient(network: &LightningNetworkMap, htlc: &HTLC) -> Option<PotentialRecipient> {
+        let observer = &htlc.observed_by_node;
+        let (recipient, route) = if htlc.reached_recipient() {
+            (observer.clone(), vec![observer.clone()])
+        } else {
+            let destination = htlc.trampoline_destination()?;
+            let route = find_path_avoiding(network, observer, destination, &HashSet::new(), htlc.forwarded_amount());
+            (destination.to_string(), if route.len() >= 2 { route } else { vec![observer.clone(), destination.to_string()] })
+        };
+        Some(PotentialRecipient {
+            node_alias: network.nodes.get(&recipient).map(|node| node.alias.clone()),
+            node_id: recipient,
+            route,
+            confidence_score: 1.0,
+        })
+    }
 
     // Try to backtrack from an observation to find potential senders
     pub fn backtrack_potential_senders(&self, htlc: &HTLC) -> Vec<String> {
         // This is more complex in reality, but for demonstration we'll do a simple implementation
-        let network = self.network.lock().unwrap();
+        let network = read_lock(&self.network);
         let observed_node = &htlc.observed_by_node;
 
+        // The channel the HTLC came in over gives the previous hop away, so the sender is
+        // that node or somewhere behind it
+        if let Some(previous) = Self::previous_hop(&network, htlc) {
+            let Some(cap) = self.sender_cltv_cap else { return vec![previous] };
+            let mut senders = Self::nodes_within(&network, &previous, htlc.max_upstream_hops(cap));
+            senders.retain(|node| node != observed_node);
+            senders.insert(0, previous);
+            return senders;
+        }
+
+        // Senders whose implementation caps the total timelock can only be a few hops
+        // upstream when the observed HTLC still carries most of that cap
+        if let Some(cap) = self.sender_cltv_cap {
+            let max_distance = htlc.max_upstream_hops(cap) + 1;
+            return Self::nodes_within(&network, observed_node, max_distance);
+        }
+
         // Get direct neighbors as potential previous hops
         let mut potential_senders = Vec::new();
 
@@ -192,28 +924,130 @@ impl HTLCAnalyzer {
 
         potential_senders
     }
+
+    // All nodes at most `max_distance` hops from the given node, excluding itself
+    fn nodes_within(network: &LightningNetworkMap, origin: &str, max_distance: usize) -> Vec<String> {
+        let mut seen: HashSet<String> = HashSet::new();
+        let mut queue = VecDeque::new();
+        let mut found = Vec::new();
+
+        seen.insert(origin.to_string());
+        queue.push_back((origin.to_string(), 0));
+
+        while let Some((node, distance)) = queue.pop_front() {
+            if distance == max_distance {
+                continue;
+            }
+            if let Some(neighbors) = network.get_neighbors(&node) {
+                for neighbor in neighbors {
+                    if seen.insert(neighbor.clone()) {
+                        found.push(neighbor.clone());
+                        queue.push_back((neighbor.clone(), distance + 1));
+                    }
+                }
+            }
+        }
+
+        found
+    }
+}
+
+// Reduce candidate routes to one copy of each simple path before they're scored. A route
+// naming a node twice, going back and forth over parallel channels or passing its recipient
+// and returning, is cut back to the path it repeats: the first visit is kept and the loop
+// after it dropped. Later copies of a route go; the search's order is otherwise kept, as it
+// breaks ties between equally likely recipients. A simple route that is a strict prefix of
+// another stays: it ends at a different node, so it names a different recipient, and the
+// timelock left over is what tells the two apart.
+pub fn canonicalize_routes(routes: &mut Vec<Vec<String>>) {
+    for route in routes.iter_mut() {
+        let mut simple: Vec<String> = Vec::with_capacity(route.len());
+        for node in route.drain(..) {
+            match simple.iter().position(|seen| *seen == node) {
+                Some(first) => simple.truncate(first + 1),
+                None => simple.push(node),
+            }
+        }
+        *route = simple;
+    }
+    let first_copies: Vec<bool> = {
+        let mut seen: HashSet<&[String]> = HashSet::with_capacity(routes.len());
+        routes.iter().map(|route| seen.insert(route.as_slice())).collect()
+    };
+    let mut first_copies = first_copies.into_iter();
+    routes.retain(|_| first_copies.next().unwrap_or(true));
+}
+
+// Whether a candidate route leaves the observer towards the node its onion layer named
+fn follows_onion(route: &[String], next_hop: Option<&str>) -> bool {
+    next_hop.is_none_or(|next| route.get(1).is_some_and(|node| node == next))
+}
+
+// Timelock a route needs from its first node: every hop's forwarding delta, plus the
+// delta the recipient's invoice asks for
+fn route_timelock(network: &LightningNetworkMap, route: &[String], amount_msat: u64,
+                  construction: CltvConstruction) -> u32 {
+    let Some(recipient) = route.last() else { return 0 };
+    let forwarding: u32 = route.windows(2)
+        .map(|hop| network.hop_cltv_delta(&hop[0], &hop[1], amount_msat, construction))
+        .sum();
+    forwarding + network.nodes.get(recipient).map_or(DEFAULT_FINAL_CLTV_DELTA, |node| node.min_final_cltv_expiry_delta)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::models::{Node, Channel};
+    use crate::models::{Node, Channel, HopPayload, ShadowOffset};
+    use crate::test_fixtures::candidates;
+
+    #[test]
+    fn test_amount_ordering_cross_checks_timelocks() {
+        // Upstream observers see the later timelock and the larger amount
+        let upstream = HTLC::new("p", 700200, 1_002_000, 700000, "a");
+        let downstream = HTLC::new("p", 700120, 1_000_000, 700000, "b");
+        assert!(!HTLCAnalyzer::orderings_disagree(&[downstream.clone(), upstream.clone()]));
+
+        // A zero-fee hop leaves the amount unchanged, which contradicts neither order
+        let zero_fee = HTLC::new("p", 700080, 1_000_000, 700000, "c");
+        assert!(!HTLCAnalyzer::orderings_disagree(&[upstream.clone(), downstream.clone(), zero_fee]));
+
+        // The amount grew after the timelock shrank
+        let inflated = HTLC::new("p", 700040, 1_005_000, 700000, "d");
+        assert!(HTLCAnalyzer::orderings_disagree(&[upstream, downstream, inflated]));
+    }
+
+    #[test]
+    fn test_split_payments_are_not_ordering_conflicts() {
+        // Two shards of one payment over disjoint routes, each consistent on its own
+        let first = [HTLC::new("p", 700200, 600_600, 700000, "a"), HTLC::new("p", 700160, 600_000, 700000, "b")];
+        let second = HTLC::new("p", 700240, 400_400, 700000, "c");
+        assert!(!HTLCAnalyzer::orderings_disagree(&[first[0].clone(), first[1].clone(), second]));
+
+        // Shards of nearly the same amount, told apart by timelocks too close for a hop between them
+        let close = HTLC::new("p", 700190, 599_500, 700000, "d");
+        assert!(!HTLCAnalyzer::orderings_disagree(&[first[0].clone(), first[1].clone(), close]));
+
+        // A node seeing two shards, such as the recipient, doesn't order them either
+        let both = [HTLC::new("p", 700040, 600_000, 700000, "e"), HTLC::new("p", 700080, 400_000, 700000, "e")];
+        assert!(!HTLCAnalyzer::orderings_disagree(&both));
+    }
 
     #[test]
     fn test_htlc_analysis() {
         // Create a test network
-        let network_map = Arc::new(Mutex::new(LightningNetworkMap::new(700000)));
+        let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(700000)));
 
         println!("Creating test network");
 
         {
-            let mut network = network_map.lock().unwrap();
+            let mut network = network_map.write().unwrap();
 
             // Add nodes
             let nodes = vec![
-                Node::new("node1", "Node 1", 20),
+                // node1's invoices ask for far more than the HTLC has left
+                Node::new("node1", "Node 1", 20).with_min_final_cltv_expiry_delta(144),
                 Node::new("node2", "Node 2", 20),
-                Node::new("node3", "Node 3", 40), // Final node with standard delta
+                Node::new("node3", "Node 3", 40), // Invoices use the standard final delta
             ];
 
             for node in nodes {
@@ -225,12 +1059,15 @@ mod tests {
             network.add_channel(Channel::new("chan2", "node2", "node3", 1000000));
         }
 
-        let analyzer = HTLCAnalyzer::new(network_map);
+        let analyzer = HTLCAnalyzer::new(network_map.clone());
+
+        // Analysis only reads the graph, so it can run while others hold it
+        let _reader = network_map.read().unwrap();
 
         // Create an HTLC observation that suggests it's on the way to node3
         let htlc = HTLC::new(
             "test_hash",
-            700080,  // expiry: enough for node2 -> node3 with standard delta
+            700080,  // expiry: node2's delta plus node3's invoice delta and some offset
             100000,
             700000,
             "node2"  // observed at node2
@@ -244,5 +1081,325 @@ mod tests {
         assert!(!recipients.is_empty());
         assert_eq!(recipients[0].node_id, "node3");
         assert!(recipients[0].confidence_score > 0.5);
+        assert!(recipients.iter().all(|r| r.node_id != "node1"));
+    }
+
+    #[test]
+    fn test_onion_payload_narrows_analysis() {
+        // b forwards between a, c and d
+        let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(700000)));
+        {
+            let mut network = network_map.write().unwrap();
+            for key in ["a", "b", "c", "d"] {
+                network.add_node(Node::new(key, key, 20));
+            }
+            for (id, peer) in [("ab", "a"), ("bc", "c"), ("bd", "d")] {
+                network.add_channel(Channel::new(id, "b", peer, 1_000_000));
+            }
+        }
+        let analyzer = HTLCAnalyzer::new(network_map);
+
+        // Without the onion c and d fit the timelock equally well
+        let htlc = HTLC::new("hash", 700080, 100_000, 700000, "b");
+        let recipients: HashSet<String> = analyzer.analyze_htlc(&htlc).into_iter().map(|r| r.node_id).collect();
+        assert!(recipients.contains("c") && recipients.contains("d"));
+        assert_eq!(analyzer.backtrack_potential_senders(&htlc).len(), 3);
+
+        // b's layer names the channel to c, and the HTLC came in from a
+        let forwarded = htlc.clone().with_onion(Some("ab"), HopPayload::forward("bc", 99_000, 700060));
+        let recipients = analyzer.analyze_htlc(&forwarded);
+        assert!(!recipients.is_empty());
+        assert!(recipients.iter().all(|r| r.route[1] == "c"));
+        assert_eq!(analyzer.backtrack_potential_senders(&forwarded), vec!["a"]);
+
+        // The recipient's own layer gives it away
+        let received = HTLC::new("hash", 700060, 99_000, 700000, "c")
+            .with_onion(Some("bc"), HopPayload::final_hop(99_000, 700060));
+        let recipients = analyzer.analyze_htlc(&received);
+        assert_eq!(recipients.len(), 1);
+        assert_eq!((recipients[0].node_id.as_str(), recipients[0].confidence_score), ("c", 1.0));
+    }
+
+    #[test]
+    fn test_monte_carlo_analysis() {
+        let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(700000)));
+        {
+            let mut network = network_map.write().unwrap();
+            for key in ["a", "c", "d"] {
+                network.add_node(Node::new(key, key, 20));
+            }
+            // b's invoices ask for more than the HTLC has left, so b can only forward
+            network.add_node(Node::new("b", "b", 20).with_min_final_cltv_expiry_delta(144));
+            // b can forward to c over a big channel or to d over a tiny one
+            network.add_channel(Channel::new("ab", "a", "b", 1_000_000));
+            network.add_channel(Channel::new("bc", "b", "c", 10_000_000));
+            network.add_channel(Channel::new("bd", "b", "d", 100_000));
+        }
+
+        let mut analyzer = HTLCAnalyzer::new(network_map);
+        analyzer.set_analysis_mode(AnalysisMode::MonteCarlo { samples: 2000 });
+
+        // Observed at a with room for exactly two forwarding deltas (a, b) before the recipient
+        let htlc = HTLC::new("hash", 700080, 100000, 700000, "a");
+        let recipients = analyzer.analyze_htlc(&htlc);
+
+        let total: f32 = recipients.iter().map(|r| r.confidence_score).sum();
+        assert!((total - 1.0).abs() < 1e-4);
+
+        // The big channel attracts most of the sampled routes
+        assert_eq!(recipients[0].node_id, "c");
+        assert_eq!(recipients[0].route, vec!["a".to_string(), "b".to_string(), "c".to_string()]);
+        assert!(recipients.iter().any(|r| r.node_id == "d"));
+    }
+
+    #[test]
+    fn test_routes_are_canonicalized_before_scoring() {
+        let route = |nodes: &[&str]| nodes.iter().map(|n| n.to_string()).collect::<Vec<String>>();
+        let mut routes = vec![
+            route(&["a", "b", "c"]),
+            // Back and forth over parallel channels between a and b
+            route(&["a", "b", "a", "b", "c"]),
+            // Past c and back: the same hypothesis as a-b-c
+            route(&["a", "b", "c", "d", "c"]),
+            route(&["a", "b", "c", "d"]),
+            route(&["a", "b", "c"]),
+        ];
+        canonicalize_routes(&mut routes);
+        assert_eq!(routes, vec![route(&["a", "b", "c"]), route(&["a", "b", "c", "d"])]);
+
+        // Scoring sees each hypothesis once, so confidence isn't counted twice
+        let network_map = Arc::new(RwLock::new(crate::simulation::fixture("line").unwrap()));
+        let analyzer = HTLCAnalyzer::new(network_map.clone());
+        let htlc = HTLC::new("p", 700000 + 3 * 40, 100_000, 700000, "a");
+        let network = network_map.read().unwrap();
+        let scored = analyzer.score_routes(&network, &htlc, 120,
+                                           vec![route(&["a", "b", "c"]), route(&["a", "b", "a", "b", "c"])]);
+        assert_eq!(scored.len(), 1);
+
+        // A prefix and its extension are two recipients, both of them scored
+        let scored = analyzer.score_routes(&network, &htlc, 120,
+                                           vec![route(&["a", "b", "c"]), route(&["a", "b", "c", "d"])]);
+        let mut recipients: Vec<&str> = scored.iter().map(|recipient| recipient.node_id.as_str()).collect();
+        recipients.sort();
+        assert_eq!(recipients, vec!["c", "d"]);
+    }
+
+    #[test]
+    fn test_recipient_profile_shrinks_candidates() {
+        // Seen at b of a line, the recipient is a or c until only merchants count
+        let mut network = crate::simulation::fixture("line").unwrap();
+        network.nodes.get_mut("c").unwrap().label = Some("merchant".to_string());
+        let network_map = Arc::new(RwLock::new(network));
+        let htlc = HTLC::new("p", 700000 + 2 * 40, 100_000, 700000, "b");
+        assert_eq!(HTLCAnalyzer::new(network_map.clone()).analyze_htlc(&htlc).len(), 2);
+
+        for mode in [AnalysisMode::Exhaustive, AnalysisMode::MonteCarlo { samples: 200 }] {
+            let mut analyzer = HTLCAnalyzer::new(network_map.clone());
+            analyzer.set_analysis_mode(mode);
+            analyzer.set_recipient_profile(RecipientProfile::parse("merchant").unwrap());
+            let recipients = analyzer.analyze_htlc(&htlc);
+            assert_eq!(recipients.iter().map(|r| r.node_id.as_str()).collect::<Vec<_>>(), vec!["c"]);
+        }
+    }
+
+    #[test]
+    fn test_hierarchical_names_only_the_final_hubs_leaves() {
+        let network = crate::simulation::fixture("two-hub").unwrap();
+        let backbone = network.channels_between("hub1", "hub2")[0].channel_id.clone();
+        let network_map = Arc::new(RwLock::new(network));
+        // Seen at hub1, forwarded to hub2 with room for hub2 to forward once more
+        let htlc = HTLC::new("hash", 700000 + 3 * 40, 100000, 700000, "hub1")
+            .with_onion(None, HopPayload::forward(&backbone, 100000, 700000 + 2 * 40));
+
+        let mut analyzer = HTLCAnalyzer::new(network_map);
+        analyzer.set_analysis_mode(AnalysisMode::Hierarchical { hubs: 1 });
+        let recipients = analyzer.analyze_htlc(&htlc);
+
+        let mut names: Vec<&str> = recipients.iter().map(|r| r.node_id.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["d", "e", "f"]);
+        assert!(recipients.iter().all(|r| r.route[r.route.len() - 2] == "hub2"));
+    }
+
+    #[test]
+    fn test_memory_budget_falls_back_to_sampling() {
+        let network_map = Arc::new(RwLock::new(crate::simulation::fixture("star").unwrap()));
+        let htlc = HTLC::new("hash", 700000 + 2 * 40, 100000, 700000, "hub");
+
+        let analyzer = HTLCAnalyzer::new(network_map.clone());
+        let enumerated = analyzer.analyze_htlc(&htlc);
+        assert_eq!(enumerated.len(), 5);
+        assert!(analyzer.peak_route_bytes() > 0);
+        assert_eq!(analyzer.memory_fallbacks(), 0);
+
+        // Room for two of the five two-hop routes
+        let hop_bytes = network_map.read().unwrap().route_hop_bytes();
+        let mut analyzer = HTLCAnalyzer::new(network_map);
+        analyzer.set_memory_budget(2 * 2 * hop_bytes);
+        let sampled = analyzer.analyze_htlc(&htlc);
+        assert_eq!(analyzer.memory_fallbacks(), 1);
+        let total: f32 = sampled.iter().map(|r| r.confidence_score).sum();
+        assert!((total - 1.0).abs() < 1e-4);
+        assert!(sampled.iter().all(|r| r.node_id != "hub"));
+    }
+
+    #[test]
+    fn test_search_goes_as_deep_as_the_timelock_needs() {
+        // Seven hops down a line, every hop forwarding for 40 blocks and the invoice asking 40
+        let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(700000)));
+        {
+            let mut network = network_map.write().unwrap();
+            for i in 0..8 {
+                network.add_node(Node::new(&format!("n{}", i), "n", 40).with_shadow_offset(ShadowOffset::NONE));
+            }
+            for i in 0..7 {
+                network.add_channel(Channel::new(&format!("chan{}", i), &format!("n{}", i), &format!("n{}", i + 1), 1000000));
+            }
+        }
+        let htlc = HTLC::new("hash", 700000 + 8 * 40, 100000, 700000, "n0");
+
+        let analyzer = HTLCAnalyzer::new(network_map.clone());
+        let recipients = analyzer.analyze_htlc(&htlc);
+        assert_eq!(recipients.len(), 1);
+        assert_eq!(recipients[0].node_id, "n7");
+        assert_eq!(analyzer.truncated_searches(), 0);
+
+        // Capped where the search used to stop, the route is lost but not without notice
+        let mut analyzer = HTLCAnalyzer::new(network_map);
+        analyzer.set_max_search_hops(5);
+        assert!(analyzer.analyze_htlc(&htlc).is_empty());
+        assert_eq!(analyzer.truncated_searches(), 1);
+    }
+
+    #[test]
+    fn test_time_budget_flags_incomplete_payments() {
+        let network_map = Arc::new(RwLock::new(crate::simulation::fixture("star").unwrap()));
+        let htlc = HTLC::new("hash", 700000 + 2 * 40, 100000, 700000, "hub");
+
+        let analyzer = HTLCAnalyzer::new(network_map.clone());
+        assert_eq!(analyzer.correlate_observations(std::slice::from_ref(&htlc))["hash"].len(), 5);
+        assert!(analyzer.incomplete_payments().is_empty());
+
+        // No time at all: the search gives up before finding anything, and the payment says so
+        let mut analyzer = HTLCAnalyzer::new(network_map);
+        analyzer.set_time_budget(Duration::ZERO);
+        assert!(analyzer.correlate_observations(std::slice::from_ref(&htlc)).is_empty());
+        assert!(analyzer.incomplete_payments().contains("hash"));
+        // Cut short, the search wasn't cached as if it were complete
+        analyzer.time_budget = None;
+        analyzer.reset_incomplete_payments();
+        assert_eq!(analyzer.analyze_htlc(&htlc).len(), 5);
+        assert!(analyzer.incomplete_payments().is_empty());
+    }
+
+    #[test]
+    fn test_sender_cltv_cap_narrows_senders() {
+        let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(700000)));
+        {
+            let mut network = network_map.write().unwrap();
+            for key in ["a", "b", "c", "d"] {
+                network.add_node(Node::new(key, key, 40));
+            }
+            network.add_channel(Channel::new("ab", "a", "b", 1_000_000));
+            network.add_channel(Channel::new("bc", "b", "c", 1_000_000));
+            network.add_channel(Channel::new("cd", "c", "d", 1_000_000));
+        }
+
+        let mut analyzer = HTLCAnalyzer::new(network_map);
+
+        // Observed at c with 190 blocks left
+        let htlc = HTLC::new("hash", 700190, 100000, 700000, "c");
+
+        // A cap of 200 leaves no room upstream: only c's neighbors can have sent it
+        analyzer.set_sender_cltv_cap(200);
+        let mut senders = analyzer.backtrack_potential_senders(&htlc);
+        senders.sort();
+        assert_eq!(senders, vec!["b".to_string(), "d".to_string()]);
+
+        // A looser cap lets the sender sit further back
+        analyzer.set_sender_cltv_cap(230);
+        assert!(analyzer.backtrack_potential_senders(&htlc).contains(&"a".to_string()));
+    }
+
+    #[test]
+    fn test_correlate_many_payments() {
+        let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(700000)));
+        {
+            let mut network = network_map.write().unwrap();
+            for key in ["a", "b", "c"] {
+                network.add_node(Node::new(key, key, 40));
+            }
+            network.add_channel(Channel::new("ab", "a", "b", 1_000_000));
+            network.add_channel(Channel::new("bc", "b", "c", 1_000_000));
+        }
+
+        let analyzer = HTLCAnalyzer::new(network_map);
+
+        // Many independent payments, each seen once at b on the way to c
+        let observations: Vec<HTLC> = (0..50)
+            .map(|i| HTLC::new(&format!("hash{}", i), 700080, 100000, 700000, "b"))
+            .collect();
+
+        let results = analyzer.correlate_observations(&observations);
+
+        assert_eq!(results.len(), 50);
+        assert!(results.values().all(|recipients| recipients[0].node_id == "c"));
+    }
+
+    #[test]
+    fn test_route_cache_matches_uncached_search() {
+        let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(700000)));
+        {
+            let mut network = network_map.write().unwrap();
+            for key in ["a", "b", "c", "d", "e"] {
+                network.add_node(Node::new(key, key, 20));
+            }
+            network.add_channel(Channel::new("ab", "a", "b", 1_000_000));
+            network.add_channel(Channel::new("bc", "b", "c", 1_000_000));
+            network.add_channel(Channel::new("cd", "c", "d", 1_000_000));
+            // Too small for the larger payments
+            network.add_channel(Channel::new("be", "b", "e", 100));
+        }
+
+        let cached = HTLCAnalyzer::new(network_map.clone());
+        let mut uncached = HTLCAnalyzer::new(network_map.clone());
+        uncached.set_route_cache_capacity(0);
+
+        let network = network_map.read().unwrap();
+        for budget in [60, 64, 70, 75, 90] {
+            for amount in [50_000, 500_000] {
+                assert_eq!(cached.find_routes_cached(&network, "b", budget, 3, amount, None),
+                           uncached.find_routes_cached(&network, "b", budget, 3, amount, None));
+            }
+        }
+
+        // Budgets 64-79 share a bucket, so they reused one search
+        let cache = cached.route_cache.as_ref().unwrap().lock().unwrap();
+        assert_eq!(cache.entries.len(), 3);
+    }
+
+    #[test]
+    fn test_community_summary() {
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["a1", "a2", "a3", "b1", "b2", "b3"] {
+            network.add_node(Node::new(key, key, 40));
+        }
+        for (a, b) in [("a1", "a2"), ("a2", "a3"), ("a1", "a3"), ("b1", "b2"), ("b2", "b3"), ("b1", "b3"), ("a1", "b1")] {
+            network.add_channel(Channel::new(&format!("{}-{}", a, b), a, b, 1_000_000));
+        }
+        let communities = Communities::detect(&network);
+
+        // No single node stands out, but most of the confidence sits in the "a" cluster
+        let recipients = candidates(&[("a2", 1.0), ("a3", 1.0), ("b2", 1.0)]);
+        let summary = HTLCAnalyzer::summarize_by_community(&recipients, &communities, 0.5).unwrap();
+
+        assert_eq!(summary.len(), 2);
+        assert_eq!(summary[0].candidates, vec!["a2".to_string(), "a3".to_string()]);
+        assert!((summary[0].confidence_share - 2.0 / 3.0).abs() < 1e-6);
+        assert_eq!(summary[0].community_size, 3);
+
+        // A dominant candidate makes the community view unnecessary
+        let recipients = candidates(&[("a2", 5.0), ("b2", 1.0)]);
+        assert!(HTLCAnalyzer::summarize_by_community(&recipients, &communities, 0.5).is_none());
     }
 }
\ No newline at end of file
@@ -0,0 +1,154 @@
+// Calibration of confidence scores: with ground truth, how often a candidate carrying a given
+// confidence really was the recipient. A well-calibrated scorer's 0.8 candidates are right
+// about 80% of the time; the reliability diagram shows how far the heuristic scores are from
+// that.
+
+use std::collections::HashMap;
+
+use crate::surveillance::analyzer::PotentialRecipient;
+
+// Equal-width confidence buckets between 0 and 1
+pub const DEFAULT_CALIBRATION_BUCKETS: usize = 10;
+
+// Candidates whose confidence fell in one bucket
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CalibrationBucket {
+    pub lower: f64,
+    pub upper: f64,
+    pub candidates: usize,
+    pub correct: usize,
+    confidence_total: f64,
+}
+
+impl CalibrationBucket {
+    pub fn mean_confidence(&self) -> f64 {
+        if self.candidates == 0 { 0.0 } else { self.confidence_total / self.candidates as f64 }
+    }
+
+    // Share of the bucket's candidates that were the true recipient
+    pub fn accuracy(&self) -> f64 {
+        if self.candidates == 0 { 0.0 } else { self.correct as f64 / self.candidates as f64 }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CalibrationCurve {
+    pub buckets: Vec<CalibrationBucket>,
+    // Candidates scored against ground truth
+    pub candidates: usize,
+    // Mean squared gap between each candidate's confidence and whether it was right
+    pub brier_score: f64,
+}
+
+impl CalibrationCurve {
+    // Bucket every candidate of a payment whose route is known. Scores above 1 land in the
+    // top bucket. None without a payment whose route is known.
+    pub fn compute(results: &HashMap<String, Vec<PotentialRecipient>>,
+                   routes: &HashMap<String, Vec<String>>,
+                   bucket_count: usize) -> Option<Self> {
+        let bucket_count = bucket_count.max(1);
+        let width = 1.0 / bucket_count as f64;
+        let mut buckets: Vec<CalibrationBucket> = (0..bucket_count)
+            .map(|i| CalibrationBucket { lower: i as f64 * width, upper: (i + 1) as f64 * width, ..Default::default() })
+            .collect();
+        let mut candidates = 0;
+        let mut squared_error = 0.0;
+
+        for (payment_hash, recipients) in results {
+            let Some(receiver) = routes.get(payment_hash).and_then(|route| route.last()) else { continue };
+            for recipient in recipients {
+                let confidence = f64::from(recipient.confidence_score).max(0.0);
+                let correct = recipient.node_id == *receiver;
+                let bucket = &mut buckets[((confidence / width) as usize).min(bucket_count - 1)];
+                bucket.candidates += 1;
+                bucket.confidence_total += confidence;
+                if correct {
+                    bucket.correct += 1;
+                }
+                candidates += 1;
+                squared_error += (confidence.min(1.0) - f64::from(u8::from(correct))).powi(2);
+            }
+        }
+        if candidates == 0 {
+            return None;
+        }
+
+        Some(CalibrationCurve {
+            buckets,
+            candidates,
+            brier_score: squared_error / candidates as f64,
+        })
+    }
+
+    // Gap between confidence and accuracy, averaged over buckets weighted by their size
+    pub fn expected_calibration_error(&self) -> f64 {
+        self.buckets.iter()
+            .map(|bucket| bucket.candidates as f64 * (bucket.mean_confidence().min(1.0) - bucket.accuracy()).abs())
+            .sum::<f64>() / self.candidates.max(1) as f64
+    }
+
+    pub fn generate_text_section(&self) -> String {
+        let mut section = String::from("### Confidence Calibration\n\n");
+        section.push_str(&format!("{} candidates scored against ground truth. Expected calibration error: {:.3}; \
+                                   Brier score: {:.3}\n\n",
+                                  self.candidates, self.expected_calibration_error(), self.brier_score));
+        section.push_str("| Confidence | Candidates | Mean confidence | True recipient |\n");
+        section.push_str("|---|---|---|---|\n");
+        for bucket in self.buckets.iter().filter(|bucket| bucket.candidates > 0) {
+            section.push_str(&format!("| {:.1}-{:.1} | {} | {:.3} | {:.1}% |\n",
+                                      bucket.lower, bucket.upper, bucket.candidates,
+                                      bucket.mean_confidence(), bucket.accuracy() * 100.0));
+        }
+        section.push('\n');
+        section
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let buckets: Vec<serde_json::Value> = self.buckets.iter()
+            .map(|bucket| serde_json::json!({
+                "lower": bucket.lower,
+                "upper": bucket.upper,
+                "candidates": bucket.candidates,
+                "correct": bucket.correct,
+                "mean_confidence": bucket.mean_confidence(),
+                "accuracy": bucket.accuracy(),
+            }))
+            .collect();
+        serde_json::json!({
+            "candidates": self.candidates,
+            "expected_calibration_error": self.expected_calibration_error(),
+            "brier_score": self.brier_score,
+            "buckets": buckets,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::candidates;
+
+    #[test]
+    fn test_candidates_bucketed_by_confidence() {
+        let results = HashMap::from([
+            ("p1".to_string(), candidates(&[("a", 0.85), ("b", 0.82), ("c", 0.1)])),
+            ("p2".to_string(), candidates(&[("d", 1.2), ("a", 0.05)])),
+            ("unknown".to_string(), candidates(&[("a", 0.5)])),
+        ]);
+        let routes = HashMap::from([
+            ("p1".to_string(), vec!["s".to_string(), "a".to_string()]),
+            ("p2".to_string(), vec!["s".to_string(), "d".to_string()]),
+        ]);
+        let curve = CalibrationCurve::compute(&results, &routes, 10).unwrap();
+
+        assert_eq!(curve.candidates, 5);
+        // Half of the 0.8 candidates were right, and the score above 1 counts in the top bucket
+        assert_eq!((curve.buckets[8].candidates, curve.buckets[8].correct), (2, 1));
+        assert_eq!((curve.buckets[9].candidates, curve.buckets[9].correct), (1, 1));
+        assert_eq!(curve.buckets[0].accuracy(), 0.0);
+        assert!(curve.expected_calibration_error() > 0.0);
+        assert!(curve.generate_text_section().contains("| 0.8-0.9 | 2 | 0.835 | 50.0% |"));
+
+        assert!(CalibrationCurve::compute(&results, &HashMap::new(), 10).is_none());
+    }
+}
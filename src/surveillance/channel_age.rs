@@ -0,0 +1,83 @@
+// Channel age: a short channel id gives away the block a channel was funded in, and new
+// channels carry little traffic until senders' pathfinding has learned to trust them. The
+// adversary can read the same ids, so it can make routes through young channels less likely.
+
+use crate::models::{Channel, LightningNetworkMap};
+
+// Blocks after funding by which a channel routes as much as any, about a month
+pub const DEFAULT_CHANNEL_MATURITY_BLOCKS: u32 = 4320;
+// Weight of a channel funded in the very block of the payment
+const MIN_AGE_WEIGHT: f64 = 0.1;
+
+// Scales a route's confidence by how mature each hop's channels were when the payment was
+// made, in proportion to their age until they reach maturity. A hop counts as old as its
+// oldest routable channel; channels without a short channel id are taken to be mature.
+#[derive(Debug, Clone)]
+pub struct ChannelAgePrior {
+    pub maturity_blocks: u32,
+}
+
+impl Default for ChannelAgePrior {
+    fn default() -> Self {
+        ChannelAgePrior::new(DEFAULT_CHANNEL_MATURITY_BLOCKS)
+    }
+}
+
+impl ChannelAgePrior {
+    pub fn new(maturity_blocks: u32) -> Self {
+        ChannelAgePrior { maturity_blocks: maturity_blocks.max(1) }
+    }
+
+    // From MIN_AGE_WEIGHT for a channel funded at `block` up to 1 once it's mature. One
+    // funded later couldn't have carried the payment at all.
+    pub fn channel_weight(&self, channel: &Channel, block: u32) -> f64 {
+        match channel.short_channel_id() {
+            None => 1.0,
+            Some(scid) => match scid.age_at(block) {
+                None => 0.0,
+                Some(age) => (age as f64 / self.maturity_blocks as f64).clamp(MIN_AGE_WEIGHT, 1.0),
+            },
+        }
+    }
+
+    pub fn weight(&self, network: &LightningNetworkMap, route: &[String], block: u32) -> f64 {
+        route.windows(2)
+            .map(|hop| {
+                network.channels_between(&hop[0], &hop[1]).into_iter()
+                    .filter(|channel| channel.status.is_active())
+                    .map(|channel| self.channel_weight(channel, block))
+                    .reduce(f64::max)
+                    .unwrap_or(1.0)
+            })
+            .product()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Node, ShortChannelId};
+
+    #[test]
+    fn test_young_channels_weigh_less() {
+        let mut network = LightningNetworkMap::new(800_000);
+        for key in ["a", "b", "c", "d"] {
+            network.add_node(Node::new(key, key, 40));
+        }
+        let scid = |block| ShortChannelId::new(block, 7, 0).to_string();
+        network.add_channel(Channel::new(&scid(700_000), "a", "b", 1_000_000));
+        network.add_channel(Channel::new(&scid(799_000), "b", "c", 1_000_000));
+        // Not a short channel id, so taken to be mature
+        network.add_channel(Channel::new("cd", "c", "d", 1_000_000));
+
+        let prior = ChannelAgePrior::new(4000);
+        let route: Vec<String> = ["a", "b", "c", "d"].iter().map(|n| n.to_string()).collect();
+        assert_eq!(prior.weight(&network, &route, 800_000), 0.25);
+        assert_eq!(prior.weight(&network, &route, 799_000), MIN_AGE_WEIGHT);
+        assert_eq!(prior.weight(&network, &route, 798_000), 0.0);
+
+        // An older parallel channel makes the hop as old as it is
+        network.add_channel(Channel::new(&scid(750_000), "b", "c", 1_000_000));
+        assert_eq!(prior.weight(&network, &route, 800_000), 1.0);
+    }
+}
@@ -0,0 +1,210 @@
+// Coalitions: the malicious nodes need not answer to one adversary. Competing surveillance
+// firms each run their own nodes and keep what they see to themselves, so every coalition is
+// analyzed on its own observations and scored against the same payments.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::Write;
+use log::info;
+
+use crate::simulation::PaymentRecord;
+use crate::surveillance::{rate, PotentialRecipient};
+use crate::error::ThelmaError;
+
+// Split nodes into coalitions of the given sizes, in order. Nodes left over belong to none
+// of them; sizes past the last node get what remains.
+pub fn split_coalitions(nodes: &[String], sizes: &[usize]) -> Vec<Vec<String>> {
+    let mut rest = nodes;
+    sizes.iter()
+        .map(|&size| {
+            let (coalition, after) = rest.split_at(size.min(rest.len()));
+            rest = after;
+            coalition.to_vec()
+        })
+        .filter(|coalition| !coalition.is_empty())
+        .collect()
+}
+
+// How well one coalition did from its own observations
+#[derive(Debug, Clone)]
+pub struct CoalitionResult {
+    pub label: String,
+    pub nodes: Vec<String>,
+    pub payments: usize,
+    // Payments that crossed one of the coalition's nodes
+    pub observed_payments: usize,
+    pub recipients_identified: usize,
+    pub recipients_in_candidates: usize,
+    pub avg_anonymity_set: f64,
+}
+
+impl CoalitionResult {
+    pub fn compute(label: &str,
+                   nodes: &[String],
+                   records: &[PaymentRecord],
+                   results: &HashMap<String, Vec<PotentialRecipient>>) -> Self {
+        let members: HashSet<&String> = nodes.iter().collect();
+        let routed: Vec<&PaymentRecord> = records.iter()
+            .filter(|r| r.path.len() >= 2 && !r.cover)
+            .collect();
+
+        let mut observed_payments = 0;
+        let mut recipients_identified = 0;
+        let mut recipients_in_candidates = 0;
+        let mut anonymity_total = 0;
+        let mut analyzed = 0;
+        for record in routed.iter().filter(|r| r.path.iter().any(|node| members.contains(node))) {
+            observed_payments += 1;
+            let Some(candidates) = results.get(&record.payment_hash) else { continue };
+            let unique: HashSet<&String> = candidates.iter().map(|c| &c.node_id).collect();
+            anonymity_total += unique.len();
+            analyzed += 1;
+            if candidates.first().map(|c| &c.node_id) == Some(&record.receiver) {
+                recipients_identified += 1;
+            }
+            if unique.contains(&record.receiver) {
+                recipients_in_candidates += 1;
+            }
+        }
+
+        CoalitionResult {
+            label: label.to_string(),
+            nodes: nodes.to_vec(),
+            payments: routed.len(),
+            observed_payments,
+            recipients_identified,
+            recipients_in_candidates,
+            avg_anonymity_set: if analyzed == 0 { 0.0 } else { anonymity_total as f64 / analyzed as f64 },
+        }
+    }
+
+    pub fn observation_rate(&self) -> f64 {
+        rate(self.observed_payments, self.payments)
+    }
+
+    // Share of all payments whose recipient the coalition ranked first
+    pub fn identification_rate(&self) -> f64 {
+        rate(self.recipients_identified, self.payments)
+    }
+
+    // Share of the payments it saw whose recipient the coalition ranked first
+    pub fn identification_rate_when_observed(&self) -> f64 {
+        rate(self.recipients_identified, self.observed_payments)
+    }
+}
+
+// Every coalition next to the adversary that pools all their nodes
+pub struct CoalitionComparison {
+    full: CoalitionResult,
+    coalitions: Vec<CoalitionResult>,
+}
+
+impl CoalitionComparison {
+    pub fn new(full: CoalitionResult) -> Self {
+        CoalitionComparison { full, coalitions: Vec::new() }
+    }
+
+    pub fn add_coalition(&mut self, result: CoalitionResult) {
+        self.coalitions.push(result);
+    }
+
+    // Mean share of payments identified by coalitions of each size, the pooled adversary
+    // included
+    pub fn accuracy_by_size(&self) -> BTreeMap<usize, f64> {
+        let mut by_size: BTreeMap<usize, (f64, usize)> = BTreeMap::new();
+        for result in self.coalitions.iter().chain(std::iter::once(&self.full)) {
+            let entry = by_size.entry(result.nodes.len()).or_default();
+            entry.0 += result.identification_rate();
+            entry.1 += 1;
+        }
+        by_size.into_iter().map(|(size, (total, count))| (size, total / count as f64)).collect()
+    }
+
+    pub fn generate_text_report(&self) -> String {
+        let mut report = String::from("## THELMA: Coalition Comparison\n\n");
+        report.push_str("| Coalition | Nodes | Observed | Identified | Identified when observed | Recipient in candidates | Avg anonymity set |\n");
+        report.push_str("|---|---|---|---|---|---|---|\n");
+        for result in std::iter::once(&self.full).chain(self.coalitions.iter()) {
+            report.push_str(&format!("| {} | {} | {:.1}% | {:.1}% | {:.1}% | {} | {:.2} |\n",
+                                     result.label,
+                                     result.nodes.len(),
+                                     result.observation_rate() * 100.0,
+                                     result.identification_rate() * 100.0,
+                                     result.identification_rate_when_observed() * 100.0,
+                                     result.recipients_in_candidates,
+                                     result.avg_anonymity_set));
+        }
+
+        report.push_str("\n### Size vs accuracy\n\n");
+        for (size, accuracy) in self.accuracy_by_size() {
+            report.push_str(&format!("- {} node{}: {:.1}% of payments identified\n",
+                                     size, if size == 1 { "" } else { "s" }, accuracy * 100.0));
+        }
+        let alone: usize = self.coalitions.iter().map(|result| result.recipients_identified).sum();
+        report.push_str(&format!("\nApart the coalitions identified {} recipients; pooled, {}.\n",
+                                 alone, self.full.recipients_identified));
+        report
+    }
+
+    pub fn generate_json_report(&self) -> String {
+        let row = |result: &CoalitionResult| serde_json::json!({
+            "label": result.label,
+            "nodes": result.nodes,
+            "payments": result.payments,
+            "observed_payments": result.observed_payments,
+            "recipients_identified": result.recipients_identified,
+            "recipients_in_candidates": result.recipients_in_candidates,
+            "observation_rate": result.observation_rate(),
+            "identification_rate": result.identification_rate(),
+            "avg_anonymity_set": result.avg_anonymity_set,
+        });
+        let by_size: Vec<serde_json::Value> = self.accuracy_by_size().into_iter()
+            .map(|(size, accuracy)| serde_json::json!({ "nodes": size, "identification_rate": accuracy }))
+            .collect();
+        serde_json::to_string_pretty(&serde_json::json!({
+            "pooled": row(&self.full),
+            "coalitions": self.coalitions.iter().map(row).collect::<Vec<_>>(),
+            "accuracy_by_size": by_size,
+        })).unwrap_or_else(|_| "Error generating JSON report".to_string())
+    }
+
+    pub fn save_report_to_file(&self, filename: &str) -> Result<(), ThelmaError> {
+        let mut file = File::create(filename)?;
+        file.write_all(self.generate_text_report().as_bytes())?;
+
+        info!("Coalition comparison saved to {}", filename);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{candidates, record};
+
+    fn guess(recipient: &str) -> Vec<PotentialRecipient> {
+        candidates(&[(recipient, 1.0)])
+    }
+
+    #[test]
+    fn test_coalitions_score_on_their_own() {
+        let nodes: Vec<String> = ["m1", "m2", "m3"].iter().map(|n| n.to_string()).collect();
+        let coalitions = split_coalitions(&nodes, &[2, 5]);
+        assert_eq!(coalitions, vec![vec!["m1".to_string(), "m2".to_string()], vec!["m3".to_string()]]);
+
+        let records = vec![record("h1", &["a", "m1", "b"]), record("h2", &["a", "m3", "c"])];
+        let pooled = HashMap::from([("h1".to_string(), guess("b")), ("h2".to_string(), guess("c"))]);
+        let first = HashMap::from([("h1".to_string(), guess("b"))]);
+        let second = HashMap::from([("h2".to_string(), guess("a"))]);
+
+        let mut comparison = CoalitionComparison::new(CoalitionResult::compute("All", &nodes, &records, &pooled));
+        comparison.add_coalition(CoalitionResult::compute("Coalition 1", &coalitions[0], &records, &first));
+        comparison.add_coalition(CoalitionResult::compute("Coalition 2", &coalitions[1], &records, &second));
+
+        let by_size = comparison.accuracy_by_size();
+        assert_eq!(by_size[&1], 0.0);
+        assert_eq!(by_size[&2], 0.5);
+        assert_eq!(by_size[&3], 1.0);
+        assert!(comparison.generate_text_report().contains("Apart the coalitions identified 1 recipients; pooled, 2."));
+    }
+}
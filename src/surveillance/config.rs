@@ -0,0 +1,258 @@
+// Settings for a surveillance operation
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::models::{CltvConstruction, DEFAULT_MAX_CLTV_EXPIRY};
+use crate::graph::Communities;
+use crate::surveillance::analyzer::{AnalysisMode, DEFAULT_ROUTE_CACHE_CAPACITY};
+use crate::surveillance::scorer::{ConfidenceScorer, HeuristicScorer};
+use crate::surveillance::strategy::AdversaryStrategy;
+use crate::surveillance::probes::ProbeDetector;
+use crate::surveillance::fingerprint::FingerprintLinker;
+use crate::surveillance::channel_age::ChannelAgePrior;
+use crate::surveillance::settlement::SettlementTiming;
+use crate::surveillance::uptime::ObservationWindows;
+use crate::surveillance::sharing::ObservationSharing;
+use crate::surveillance::accuracy::AccuracyThresholds;
+use crate::surveillance::plausibility::RecipientProfile;
+use crate::simulation::RegionLatency;
+
+// Which nodes the adversary controls
+pub enum AdversaryModel {
+    // A fixed set of compromised nodes
+    Nodes(Vec<String>),
+    // A strategy picks `count` nodes and later decides capital allocation and probing
+    Strategy { strategy: Box<dyn AdversaryStrategy>, count: usize },
+}
+
+// Everything a `SurveillanceOperation` can be tuned with. Start from the adversary model
+// and chain the rest, so new options don't change the operation's constructor.
+pub struct SurveillanceConfig {
+    pub(crate) adversary: AdversaryModel,
+    pub(crate) analysis_mode: AnalysisMode,
+    // Largest total route timelock the analysis should consider valid
+    pub(crate) max_cltv_expiry: u32,
+    // Route searches kept for reuse across observations; 0 disables caching
+    pub(crate) route_cache_capacity: usize,
+    // Bytes one observation's route enumeration may take before it's sampled instead
+    pub(crate) memory_budget: Option<usize>,
+    // Most hops routes are searched, if not as deep as each observation likely needs
+    pub(crate) max_search_hops: Option<usize>,
+    // How long one payment's analysis may take before settling for what it found
+    pub(crate) time_budget: Option<Duration>,
+    // Total timelock cap assumed for senders' implementations
+    pub(crate) sender_cltv_cap: Option<u32>,
+    // How senders are assumed to build route timelocks
+    pub(crate) cltv_construction: CltvConstruction,
+    pub(crate) scorer: Arc<dyn ConfidenceScorer>,
+    // Refine candidates as each observation arrives
+    pub(crate) live_analysis: bool,
+    // Communities for cluster-level candidates, with the confidence share below which
+    // they're reported
+    pub(crate) community_inference: Option<(Communities, f32)>,
+    // Directory, in-memory limit and partition count for spilled observations
+    pub(crate) spill: Option<(PathBuf, usize, usize)>,
+    // Leave payments that look like probes out of the analysis
+    pub(crate) probe_detector: Option<ProbeDetector>,
+    // Link observations into payments by their fingerprint, for payments without a
+    // shared hash
+    pub(crate) fingerprint_linker: Option<FingerprintLinker>,
+    // Confidence taken off per hop through channels stale at payment time, and the blocks
+    // after which a channel counts as stale
+    pub(crate) stale_channel_penalty: Option<(f64, u32)>,
+    pub(crate) channel_age_prior: Option<ChannelAgePrior>,
+    pub(crate) settlement_timing: Option<SettlementTiming>,
+    // When each of our nodes is online to observe, if not always
+    pub(crate) observation_windows: Option<ObservationWindows>,
+    // How long observations take to reach the node the analysis runs at, if not at once
+    pub(crate) observation_sharing: Option<ObservationSharing>,
+    // Top-k and set-size cut-offs identification rates are reported at
+    pub(crate) accuracy_thresholds: AccuracyThresholds,
+    // Latency between regions the adversary guesses recipients' regions by, if it does
+    pub(crate) regions: Option<RegionLatency>,
+    // Nodes candidates are restricted to, if not every node
+    pub(crate) recipient_profile: Option<RecipientProfile>,
+}
+
+impl SurveillanceConfig {
+    // Watch from a fixed set of nodes
+    pub fn observing(malicious_nodes: Vec<String>) -> Self {
+        SurveillanceConfig::for_adversary(AdversaryModel::Nodes(malicious_nodes))
+    }
+
+    // Let a strategy pick the `count` nodes to compromise and keep it to decide when to probe
+    pub fn with_strategy(strategy: Box<dyn AdversaryStrategy>, count: usize) -> Self {
+        SurveillanceConfig::for_adversary(AdversaryModel::Strategy { strategy, count })
+    }
+
+    pub fn for_adversary(adversary: AdversaryModel) -> Self {
+        SurveillanceConfig {
+            adversary,
+            analysis_mode: AnalysisMode::default(),
+            max_cltv_expiry: DEFAULT_MAX_CLTV_EXPIRY,
+            route_cache_capacity: DEFAULT_ROUTE_CACHE_CAPACITY,
+            memory_budget: None,
+            max_search_hops: None,
+            time_budget: None,
+            sender_cltv_cap: None,
+            cltv_construction: CltvConstruction::default(),
+            scorer: Arc::new(HeuristicScorer),
+            live_analysis: false,
+            community_inference: None,
+            spill: None,
+            probe_detector: None,
+            fingerprint_linker: None,
+            stale_channel_penalty: None,
+            channel_age_prior: None,
+            settlement_timing: None,
+            observation_windows: None,
+            observation_sharing: None,
+            accuracy_thresholds: AccuracyThresholds::default(),
+            regions: None,
+            recipient_profile: None,
+        }
+    }
+
+    // Switch between exhaustive route enumeration and route sampling
+    pub fn analysis_mode(mut self, mode: AnalysisMode) -> Self {
+        self.analysis_mode = mode;
+        self
+    }
+
+    pub fn max_cltv_expiry(mut self, max_cltv_expiry: u32) -> Self {
+        self.max_cltv_expiry = max_cltv_expiry;
+        self
+    }
+
+    pub fn route_cache_capacity(mut self, capacity: usize) -> Self {
+        self.route_cache_capacity = capacity;
+        self
+    }
+
+    // Sample the routes of observations whose enumeration would take more than `bytes`
+    pub fn memory_budget(mut self, bytes: usize) -> Self {
+        self.memory_budget = Some(bytes);
+        self
+    }
+
+    // Search routes at most `hops` hops deep, trading longer routes for speed
+    pub fn max_search_hops(mut self, hops: usize) -> Self {
+        self.max_search_hops = Some(hops);
+        self
+    }
+
+    // Stop analyzing a payment after `budget`, reporting the candidates found so far
+    pub fn time_budget(mut self, budget: Duration) -> Self {
+        self.time_budget = Some(budget);
+        self
+    }
+
+    // Exploit senders whose implementations cap the total route timelock low
+    pub fn sender_cltv_cap(mut self, cap: u32) -> Self {
+        self.sender_cltv_cap = Some(cap);
+        self
+    }
+
+    // Retrace routes with the deltas senders built them with
+    pub fn cltv_construction(mut self, construction: CltvConstruction) -> Self {
+        self.cltv_construction = construction;
+        self
+    }
+
+    // How enumerated candidate routes are ranked
+    pub fn scorer(mut self, scorer: Arc<dyn ConfidenceScorer>) -> Self {
+        self.scorer = scorer;
+        self
+    }
+
+    // Refine candidates as each observation arrives instead of analyzing everything at the
+    // end. Analysis results then come from the live state.
+    pub fn live_analysis(mut self) -> Self {
+        self.live_analysis = true;
+        self
+    }
+
+    // Report candidate communities when individual-node confidence is below the threshold
+    pub fn community_inference(mut self, communities: Communities, threshold: f32) -> Self {
+        self.community_inference = Some((communities, threshold));
+        self
+    }
+
+    // Classify observed payments with the detector and analyze only those that don't look
+    // like probes
+    pub fn exclude_probes(mut self, detector: ProbeDetector) -> Self {
+        self.probe_detector = Some(detector);
+        self
+    }
+
+    // Group observations into payments by amount, timelock and timing instead of trusting
+    // their hashes, which PTLC payments don't share between hops
+    pub fn link_by_fingerprint(mut self, linker: FingerprintLinker) -> Self {
+        self.fingerprint_linker = Some(linker);
+        self
+    }
+
+    // Make routes less likely for every hop whose channels hadn't been gossiped for
+    // `stale_after_blocks` when the payment was made
+    pub fn deprioritize_stale_channels(mut self, penalty: f64, stale_after_blocks: u32) -> Self {
+        self.stale_channel_penalty = Some((penalty, stale_after_blocks));
+        self
+    }
+
+    // Make routes less likely for every hop whose channels had been open for less than
+    // `maturity_blocks` when the payment was made, going by their short channel ids
+    pub fn weigh_channel_age(mut self, maturity_blocks: u32) -> Self {
+        self.channel_age_prior = Some(ChannelAgePrior::new(maturity_blocks));
+        self
+    }
+
+    // Make routes less likely when their length doesn't fit how long our nodes waited for
+    // HTLCs to settle or fail, taking messages `hop_ms` to cross a link
+    pub fn time_settlements(mut self, hop_ms: u64) -> Self {
+        self.settlement_timing = Some(SettlementTiming::new(hop_ms));
+        self
+    }
+
+    // Keep each of our nodes online for `uptime` of every `period` payments, dropping what
+    // they would have seen the rest of the time
+    pub fn observation_windows(mut self, uptime: f64, period: u64) -> Self {
+        self.observation_windows = Some(ObservationWindows::new(uptime).period(period));
+        self
+    }
+
+    // Have our nodes send their observations to a hub, the first of them unless the sharing
+    // names one, where they arrive after the sharing's delay
+    pub fn share_observations(mut self, sharing: ObservationSharing) -> Self {
+        self.observation_sharing = Some(sharing);
+        self
+    }
+
+    // Report how often the recipient ranks within each top k and hides in candidate sets of
+    // at most each size, instead of the default cut-offs
+    pub fn accuracy_thresholds(mut self, thresholds: AccuracyThresholds) -> Self {
+        self.accuracy_thresholds = thresholds;
+        self
+    }
+
+    // Guess from settlement timing which region each payment ended in, knowing where our
+    // nodes are and the latency between regions. Needs `time_settlements`.
+    pub fn infer_geography(mut self, regions: RegionLatency) -> Self {
+        self.regions = Some(regions);
+        self
+    }
+
+    // Only name nodes the profile admits as candidate recipients
+    pub fn plausible_recipients(mut self, profile: RecipientProfile) -> Self {
+        self.recipient_profile = Some(profile);
+        self
+    }
+
+    // Keep at most `memory_limit` observations in memory and spill the rest to disk under
+    // `dir`, split into `partitions` files analyzed one at a time
+    pub fn spill_to(mut self, dir: impl Into<PathBuf>, memory_limit: usize, partitions: usize) -> Self {
+        self.spill = Some((dir.into(), memory_limit, partitions));
+        self
+    }
+}
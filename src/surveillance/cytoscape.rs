@@ -0,0 +1,151 @@
+// Cytoscape.js export: the network as an elements JSON, annotated with what the adversary
+// concluded about each node, for custom web visualizations
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use log::info;
+
+use crate::models::LightningNetworkMap;
+use crate::simulation::PaymentRecord;
+use crate::surveillance::PotentialRecipient;
+use crate::error::ThelmaError;
+
+// How strongly the adversary suspected a node of receiving payments
+#[derive(Debug, Clone, Default)]
+pub struct CandidateConfidence {
+    // Observed payments the node was a candidate recipient of
+    pub candidate_payments: usize,
+    // Payments it was ranked first for
+    pub top_candidate: usize,
+    // Its share of each payment's confidence, summed: the payments the adversary expects
+    // it received
+    pub expected_payments: f64,
+    // Payments it actually received
+    pub received: usize,
+}
+
+pub struct CytoscapeExport {
+    nodes: BTreeMap<String, (String, bool, CandidateConfidence)>,
+    // Channel id, ends and capacity
+    edges: Vec<(String, String, String, u64)>,
+}
+
+impl CytoscapeExport {
+    pub fn compute(network: &LightningNetworkMap,
+                   malicious_nodes: &[String],
+                   records: &[PaymentRecord],
+                   results: &HashMap<String, Vec<PotentialRecipient>>) -> Self {
+        let malicious: HashSet<&String> = malicious_nodes.iter().collect();
+        let mut nodes: BTreeMap<String, (String, bool, CandidateConfidence)> = network.nodes.iter()
+            .map(|(key, node)| (key.clone(), (node.alias.clone(), malicious.contains(key), CandidateConfidence::default())))
+            .collect();
+
+        for record in records.iter().filter(|r| !r.cover) {
+            if let Some(node) = nodes.get_mut(&record.receiver) {
+                node.2.received += 1;
+            }
+        }
+
+        for candidates in results.values() {
+            let total: f32 = candidates.iter().map(|c| c.confidence_score).sum();
+            let mut shares: HashMap<&str, f64> = HashMap::new();
+            for candidate in candidates {
+                let share = if total > 0.0 { candidate.confidence_score / total } else { 0.0 };
+                *shares.entry(candidate.node_id.as_str()).or_default() += share as f64;
+            }
+            for (node_id, share) in shares {
+                if let Some(node) = nodes.get_mut(node_id) {
+                    node.2.candidate_payments += 1;
+                    node.2.expected_payments += share;
+                }
+            }
+            if let Some(node) = candidates.first().and_then(|best| nodes.get_mut(&best.node_id)) {
+                node.2.top_candidate += 1;
+            }
+        }
+
+        let edges = network.channels.iter()
+            .map(|c| (c.channel_id.clone(), c.node1.clone(), c.node2.clone(), c.capacity))
+            .collect();
+        CytoscapeExport { nodes, edges }
+    }
+
+    pub fn confidence(&self, node_id: &str) -> Option<&CandidateConfidence> {
+        self.nodes.get(node_id).map(|node| &node.2)
+    }
+
+    // `{"elements": {"nodes": [...], "edges": [...]}}`, which `cytoscape({ elements })` and
+    // `cy.json()` both accept
+    pub fn generate_json(&self) -> String {
+        let nodes: Vec<serde_json::Value> = self.nodes.iter().map(|(key, (alias, malicious, confidence))| {
+            serde_json::json!({
+                "data": {
+                    "id": key,
+                    "label": alias,
+                    "malicious": malicious,
+                    "candidate_payments": confidence.candidate_payments,
+                    "top_candidate": confidence.top_candidate,
+                    "expected_payments": confidence.expected_payments,
+                    "received": confidence.received,
+                },
+                "classes": if *malicious { "malicious" } else { "honest" },
+            })
+        }).collect();
+
+        let edges: Vec<serde_json::Value> = self.edges.iter().map(|(id, source, target, capacity)| {
+            let malicious = self.nodes.get(source).is_some_and(|n| n.1) || self.nodes.get(target).is_some_and(|n| n.1);
+            serde_json::json!({
+                "data": {
+                    "id": id,
+                    "source": source,
+                    "target": target,
+                    "capacity": capacity,
+                    "malicious": malicious,
+                },
+            })
+        }).collect();
+
+        serde_json::to_string_pretty(&serde_json::json!({ "elements": { "nodes": nodes, "edges": edges } }))
+            .unwrap_or_else(|_| "{}".to_string())
+    }
+
+    pub fn save_to_file(&self, filename: &str) -> Result<(), ThelmaError> {
+        std::fs::write(filename, self.generate_json())?;
+        info!("Cytoscape elements saved to {}", filename);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Channel, Node};
+    use crate::test_fixtures::{candidates, record};
+
+    #[test]
+    fn test_cytoscape_export_annotates_candidates() {
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["a", "m", "c", "d"] {
+            network.add_node(Node::new(key, key, 40));
+        }
+        network.add_channel(Channel::new("am", "a", "m", 1_000_000));
+        network.add_channel(Channel::new("mc", "m", "c", 1_000_000));
+        network.add_channel(Channel::new("md", "m", "d", 1_000_000));
+
+        let records = vec![record("p1", &["a", "m", "c"])];
+        let results = HashMap::from([("p1".to_string(), candidates(&[("c", 3.0), ("d", 1.0)]))]);
+        let export = CytoscapeExport::compute(&network, &["m".to_string()], &records, &results);
+
+        let c = export.confidence("c").unwrap();
+        assert_eq!((c.candidate_payments, c.top_candidate, c.received), (1, 1, 1));
+        assert!((c.expected_payments - 0.75).abs() < 1e-6);
+        assert!((export.confidence("d").unwrap().expected_payments - 0.25).abs() < 1e-6);
+
+        let json: serde_json::Value = serde_json::from_str(&export.generate_json()).unwrap();
+        assert_eq!(json["elements"]["nodes"].as_array().unwrap().len(), 4);
+        let edges = json["elements"]["edges"].as_array().unwrap();
+        assert_eq!(edges.len(), 3);
+        assert!(edges.iter().all(|edge| edge["data"]["malicious"] == true));
+        assert_eq!(json["elements"]["nodes"][3]["data"]["id"], "m");
+        assert_eq!(json["elements"]["nodes"][3]["data"]["malicious"], true);
+    }
+}
@@ -0,0 +1,172 @@
+// Cost-benefit economics of running the surveillance operation
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Write;
+use log::info;
+
+use crate::models::LightningNetworkMap;
+use crate::simulation::PaymentRecord;
+use crate::error::ThelmaError;
+
+// What it costs the adversary to keep its malicious nodes in the network
+#[derive(Debug, Clone)]
+pub struct AdversaryBudget {
+    // On-chain fee paid to open each channel (sat)
+    pub channel_open_fee_sat: u64,
+    // Opportunity cost of capital locked in channels, per year
+    pub annual_cost_of_capital: f64,
+    // Real-world time span the simulated payments stand in for
+    pub campaign_days: f64,
+}
+
+impl Default for AdversaryBudget {
+    fn default() -> Self {
+        AdversaryBudget {
+            channel_open_fee_sat: 2_000,
+            annual_cost_of_capital: 0.05,
+            campaign_days: 30.0,
+        }
+    }
+}
+
+// Economics section combining the budget model with simulation results
+#[derive(Debug, Clone)]
+pub struct AttackEconomics {
+    pub malicious_nodes: usize,
+    pub channels_opened: usize,
+    pub capital_locked_sat: u64,
+    pub channel_open_cost_sat: u64,
+    pub capital_cost_sat: f64,
+    pub routing_fees_earned_msat: u64,
+    pub deanonymizations: usize,
+}
+
+impl AttackEconomics {
+    pub fn compute(budget: &AdversaryBudget,
+                   malicious_nodes: &[String],
+                   network: &LightningNetworkMap,
+                   records: &[PaymentRecord],
+                   deanonymizations: usize) -> Self {
+        let malicious: HashSet<&String> = malicious_nodes.iter().collect();
+
+        // Assume the adversary funded every channel touching one of its nodes
+        let adversary_channels: Vec<_> = network.channels.iter()
+            .filter(|c| malicious.contains(&c.node1) || malicious.contains(&c.node2))
+            .collect();
+        let capital_locked_sat: u64 = adversary_channels.iter().map(|c| c.capacity).sum();
+
+        // Fees earned whenever a malicious node forwarded a payment
+        let mut routing_fees_earned_msat = 0;
+        for record in records.iter().filter(|r| r.path.len() >= 3) {
+            for hop in record.path[1..].windows(2) {
+                if malicious.contains(&hop[0]) {
+                    routing_fees_earned_msat += network.hop_fee_msat(&hop[0], &hop[1], record.amount)
+                        .or_else(|| network.nodes.get(&hop[0]).map(|node| node.forwarding_fee_msat(record.amount)))
+                        .unwrap_or(0);
+                }
+            }
+        }
+
+        AttackEconomics {
+            malicious_nodes: malicious.len(),
+            channels_opened: adversary_channels.len(),
+            capital_locked_sat,
+            channel_open_cost_sat: adversary_channels.len() as u64 * budget.channel_open_fee_sat,
+            capital_cost_sat: capital_locked_sat as f64 * budget.annual_cost_of_capital * budget.campaign_days / 365.0,
+            routing_fees_earned_msat,
+            deanonymizations,
+        }
+    }
+
+    // Total cost of the campaign after routing fee income (sat)
+    pub fn net_cost_sat(&self) -> f64 {
+        self.channel_open_cost_sat as f64 + self.capital_cost_sat - self.routing_fees_earned_msat as f64 / 1000.0
+    }
+
+    // Net cost divided over successful recipient identifications
+    pub fn cost_per_deanonymization_sat(&self) -> Option<f64> {
+        if self.deanonymizations == 0 {
+            None
+        } else {
+            Some(self.net_cost_sat() / self.deanonymizations as f64)
+        }
+    }
+
+    // Generate the markdown economics section
+    pub fn generate_text_report(&self) -> String {
+        let mut report = String::from("## THELMA: Attack Economics\n\n");
+
+        report.push_str(&format!("Malicious nodes: {}\n", self.malicious_nodes));
+        report.push_str(&format!("Channels opened: {}\n", self.channels_opened));
+        report.push_str(&format!("Capital locked in channels: {} sat\n", self.capital_locked_sat));
+        report.push_str(&format!("Channel opening fees: {} sat\n", self.channel_open_cost_sat));
+        report.push_str(&format!("Cost of locked capital: {:.0} sat\n", self.capital_cost_sat));
+        report.push_str(&format!("Routing fees earned: {:.3} sat\n", self.routing_fees_earned_msat as f64 / 1000.0));
+        report.push_str(&format!("Net attack cost: {:.0} sat\n", self.net_cost_sat()));
+        report.push_str(&format!("Successful deanonymizations: {}\n", self.deanonymizations));
+
+        match self.cost_per_deanonymization_sat() {
+            Some(cost) => report.push_str(&format!("Cost per deanonymization: {:.0} sat\n", cost)),
+            None => report.push_str("Cost per deanonymization: n/a (no recipients identified)\n"),
+        }
+
+        report
+    }
+
+    // Generate a JSON version of the economics section
+    pub fn generate_json_report(&self) -> String {
+        serde_json::to_string_pretty(&serde_json::json!({
+            "malicious_nodes": self.malicious_nodes,
+            "channels_opened": self.channels_opened,
+            "capital_locked_sat": self.capital_locked_sat,
+            "channel_open_cost_sat": self.channel_open_cost_sat,
+            "capital_cost_sat": self.capital_cost_sat,
+            "routing_fees_earned_msat": self.routing_fees_earned_msat,
+            "net_cost_sat": self.net_cost_sat(),
+            "deanonymizations": self.deanonymizations,
+            "cost_per_deanonymization_sat": self.cost_per_deanonymization_sat(),
+        })).unwrap_or_else(|_| "Error generating JSON report".to_string())
+    }
+
+    // Save the economics section to file
+    pub fn save_report_to_file(&self, filename: &str) -> Result<(), ThelmaError> {
+        let mut file = File::create(filename)?;
+        file.write_all(self.generate_text_report().as_bytes())?;
+
+        info!("Attack economics saved to {}", filename);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Node, Channel, DEFAULT_BASE_FEE_MSAT};
+    use crate::test_fixtures::record;
+
+    #[test]
+    fn test_attack_economics() {
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["alice", "mallory", "bob"] {
+            network.add_node(Node::new(key, key, 40));
+        }
+        network.add_channel(Channel::new("chan1", "alice", "mallory", 1_000_000));
+        network.add_channel(Channel::new("chan2", "mallory", "bob", 2_000_000));
+
+        let records = vec![PaymentRecord { amount: 1_000_000, ..record("h1", &["alice", "mallory", "bob"]) }];
+
+        let budget = AdversaryBudget {
+            channel_open_fee_sat: 1_000,
+            annual_cost_of_capital: 0.0,
+            campaign_days: 30.0,
+        };
+
+        let economics = AttackEconomics::compute(&budget, &["mallory".to_string()], &network, &records, 2);
+
+        assert_eq!(economics.channels_opened, 2);
+        assert_eq!(economics.capital_locked_sat, 3_000_000);
+        assert_eq!(economics.routing_fees_earned_msat, DEFAULT_BASE_FEE_MSAT + 1);
+        assert_eq!(economics.cost_per_deanonymization_sat(), Some((2_000.0 - 1.001) / 2.0));
+    }
+}
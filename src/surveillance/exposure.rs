@@ -0,0 +1,97 @@
+// Exposed nodes: honest nodes the analysis keeps naming as likely recipients, whether they
+// were or not. Their place in the graph makes them easy targets, and being named wrongly
+// still draws an adversary's attention, so defenders get them as a ranked list.
+
+use std::collections::HashMap;
+
+use crate::surveillance::analyzer::PotentialRecipient;
+
+// Share of the top candidate's confidence from which a candidate counts as a likely recipient
+pub const HIGH_CONFIDENCE_RATIO: f32 = 0.5;
+// Nodes listed in reports
+pub const DEFAULT_EXPOSED_NODES: usize = 10;
+
+// How often the analysis pointed at one honest node
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeExposure {
+    pub node_id: String,
+    // Payments it was a high-confidence candidate for
+    pub payments: Vec<String>,
+    // Payments it was the top candidate for
+    pub top_ranked: usize,
+}
+
+impl NodeExposure {
+    pub fn high_confidence(&self) -> usize {
+        self.payments.len()
+    }
+}
+
+// Honest nodes ranked by the payments they were a high-confidence candidate for, then by
+// the ones they topped. A node's confidence over several routes adds up.
+pub fn rank_exposed_nodes(results: &HashMap<String, Vec<PotentialRecipient>>,
+                          malicious_nodes: &[String],
+                          limit: usize) -> Vec<NodeExposure> {
+    let mut exposure: HashMap<&str, NodeExposure> = HashMap::new();
+    for (payment_hash, recipients) in results {
+        let mut per_node: HashMap<&str, f32> = HashMap::new();
+        for recipient in recipients {
+            *per_node.entry(&recipient.node_id).or_insert(0.0) += recipient.confidence_score;
+        }
+        let top = recipients.first().map(|r| r.node_id.as_str());
+        let best = per_node.values().copied().fold(0.0, f32::max);
+        if best <= 0.0 {
+            continue;
+        }
+
+        for (node, confidence) in per_node {
+            if malicious_nodes.iter().any(|m| m == node) || confidence / best < HIGH_CONFIDENCE_RATIO {
+                continue;
+            }
+            let entry = exposure.entry(node).or_insert_with(|| NodeExposure {
+                node_id: node.to_string(),
+                payments: Vec::new(),
+                top_ranked: 0,
+            });
+            entry.payments.push(payment_hash.clone());
+            if top == Some(node) {
+                entry.top_ranked += 1;
+            }
+        }
+    }
+
+    let mut ranked: Vec<NodeExposure> = exposure.into_values().collect();
+    for node in &mut ranked {
+        node.payments.sort();
+    }
+    ranked.sort_by(|a, b| b.high_confidence().cmp(&a.high_confidence())
+        .then(b.top_ranked.cmp(&a.top_ranked))
+        .then(a.node_id.cmp(&b.node_id)));
+    ranked.truncate(limit);
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::candidates;
+
+    #[test]
+    fn test_honest_nodes_ranked_by_exposure() {
+        let results = HashMap::from([
+            ("h1".to_string(), candidates(&[("a", 0.6), ("m", 0.3), ("b", 0.1)])),
+            ("h2".to_string(), candidates(&[("b", 0.5), ("a", 0.3), ("a", 0.2)])),
+            ("h3".to_string(), candidates(&[("a", 1.0)])),
+        ]);
+        let ranked = rank_exposed_nodes(&results, &["m".to_string()], 5);
+
+        // The malicious node and b's low confidence in h1 don't count
+        assert_eq!(ranked.len(), 2);
+        assert_eq!(ranked[0].node_id, "a");
+        assert_eq!(ranked[0].payments, vec!["h1", "h2", "h3"]);
+        assert_eq!(ranked[0].top_ranked, 2);
+        assert_eq!((ranked[1].node_id.as_str(), ranked[1].high_confidence()), ("b", 1));
+
+        assert_eq!(rank_exposed_nodes(&results, &[], 1).len(), 1);
+    }
+}
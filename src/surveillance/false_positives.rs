@@ -0,0 +1,326 @@
+// False-positive analysis: which honest nodes the analysis wrongly names as likely
+// recipients, and what sets them apart in the graph. Every honest node is described by its
+// degree, its distance to the best-connected hubs, and whether it uses the network's most
+// common CLTV delta; a linear probability model over the standardized features summarizes
+// how each relates to being a false positive with the others held fixed.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::models::LightningNetworkMap;
+use crate::surveillance::analyzer::PotentialRecipient;
+use crate::surveillance::exposure::HIGH_CONFIDENCE_RATIO;
+
+// Best-connected nodes distances are measured to
+pub const DEFAULT_HUB_COUNT: usize = 5;
+
+const FEATURES: [&str; 3] = ["degree", "distance to hubs", "standard CLTV delta"];
+
+// How one feature relates to being a false positive
+#[derive(Debug, Clone)]
+pub struct FeatureEffect {
+    pub name: &'static str,
+    // None when no honest node falls in the group
+    pub mean_false_positive: Option<f64>,
+    pub mean_other: Option<f64>,
+    // Pearson correlation with being a false positive
+    pub correlation: f64,
+    // Change in the probability of being a false positive per standard deviation of the
+    // feature, the others held fixed. None when the feature doesn't vary.
+    pub coefficient: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FalsePositiveAnalysis {
+    pub hubs: Vec<String>,
+    pub standard_cltv_delta: u32,
+    pub nodes: usize,
+    // Honest nodes named a likely recipient of a payment they didn't receive, and how often
+    pub false_positive_nodes: usize,
+    pub false_positive_candidacies: usize,
+    pub features: Vec<FeatureEffect>,
+    // Share of the variance the model explains, when it could be fitted
+    pub r_squared: Option<f64>,
+}
+
+impl FalsePositiveAnalysis {
+    // Score candidates against the real routes. None without a payment whose route is known.
+    pub fn compute(network: &LightningNetworkMap,
+                   results: &HashMap<String, Vec<PotentialRecipient>>,
+                   routes: &HashMap<String, Vec<String>>,
+                   malicious_nodes: &[String]) -> Option<Self> {
+        let mut false_positives: HashMap<&str, usize> = HashMap::new();
+        let mut known = 0;
+        for (payment_hash, recipients) in results {
+            let Some(receiver) = routes.get(payment_hash).and_then(|route| route.last()) else { continue };
+            known += 1;
+            let mut per_node: HashMap<&str, f32> = HashMap::new();
+            for recipient in recipients {
+                *per_node.entry(&recipient.node_id).or_insert(0.0) += recipient.confidence_score;
+            }
+            let best = per_node.values().copied().fold(0.0, f32::max);
+            for (node, confidence) in per_node {
+                if best > 0.0 && confidence / best >= HIGH_CONFIDENCE_RATIO && node != receiver {
+                    *false_positives.entry(node).or_default() += 1;
+                }
+            }
+        }
+        if known == 0 {
+            return None;
+        }
+
+        let mut honest: Vec<&String> = network.nodes.keys().filter(|node| !malicious_nodes.contains(node)).collect();
+        honest.sort();
+        let hubs = hubs(network, DEFAULT_HUB_COUNT);
+        let distances = distances_from(network, &hubs);
+        let standard_cltv_delta = most_common_delta(network);
+
+        let rows: Vec<[f64; 3]> = honest.iter()
+            .map(|node| [
+                network.degree(node) as f64,
+                // Nodes cut off from every hub count as one hop further than the farthest
+                distances.get(node.as_str()).map_or(distances.values().max().copied().unwrap_or(0) + 1, |d| *d) as f64,
+                network.nodes.get(*node).map_or(0.0, |n| f64::from(u8::from(n.cltv_expiry_delta == standard_cltv_delta))),
+            ])
+            .collect();
+        let outcome: Vec<f64> = honest.iter()
+            .map(|node| if false_positives.contains_key(node.as_str()) { 1.0 } else { 0.0 })
+            .collect();
+
+        let (coefficients, r_squared) = match fit(&rows, &outcome) {
+            Some((coefficients, r_squared)) => (coefficients, Some(r_squared)),
+            None => ([None; 3], None),
+        };
+        let features = FEATURES.iter().enumerate()
+            .map(|(i, name)| {
+                let column: Vec<f64> = rows.iter().map(|row| row[i]).collect();
+                let (mean_false_positive, mean_other) = split_means(&column, &outcome);
+                FeatureEffect {
+                    name,
+                    mean_false_positive,
+                    mean_other,
+                    correlation: correlation(&column, &outcome),
+                    coefficient: coefficients[i],
+                }
+            })
+            .collect();
+
+        Some(FalsePositiveAnalysis {
+            hubs,
+            standard_cltv_delta,
+            nodes: honest.len(),
+            false_positive_nodes: outcome.iter().filter(|y| **y > 0.0).count(),
+            false_positive_candidacies: honest.iter().filter_map(|node| false_positives.get(node.as_str())).sum(),
+            features,
+            r_squared,
+        })
+    }
+
+    pub fn generate_text_section(&self) -> String {
+        let mut section = String::from("### False-Positive Analysis\n\n");
+        section.push_str(&format!("{} of {} honest nodes were wrongly named a likely recipient ({} times). \
+                                   Hubs: {}; standard CLTV delta: {}\n\n",
+                                  self.false_positive_nodes, self.nodes, self.false_positive_candidacies,
+                                  self.hubs.join(", "), self.standard_cltv_delta));
+        section.push_str("| Feature | Mean, false positives | Mean, others | Correlation | Coefficient (per SD) |\n");
+        section.push_str("|---|---|---|---|---|\n");
+        for feature in &self.features {
+            let value = |value: Option<f64>, signed: bool| match value {
+                Some(v) if signed => format!("{:+.3}", v),
+                Some(v) => format!("{:.2}", v),
+                None => "-".to_string(),
+            };
+            section.push_str(&format!("| {} | {} | {} | {:+.3} | {} |\n",
+                                      feature.name, value(feature.mean_false_positive, false),
+                                      value(feature.mean_other, false), feature.correlation,
+                                      value(feature.coefficient, true)));
+        }
+        if let Some(r_squared) = self.r_squared {
+            section.push_str(&format!("\nLinear probability model: R² {:.3}\n", r_squared));
+        }
+        section.push('\n');
+        section
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let features: Vec<serde_json::Value> = self.features.iter()
+            .map(|feature| serde_json::json!({
+                "feature": feature.name,
+                "mean_false_positive": feature.mean_false_positive,
+                "mean_other": feature.mean_other,
+                "correlation": feature.correlation,
+                "coefficient": feature.coefficient,
+            }))
+            .collect();
+        serde_json::json!({
+            "hubs": self.hubs,
+            "standard_cltv_delta": self.standard_cltv_delta,
+            "honest_nodes": self.nodes,
+            "false_positive_nodes": self.false_positive_nodes,
+            "false_positive_candidacies": self.false_positive_candidacies,
+            "features": features,
+            "r_squared": self.r_squared,
+        })
+    }
+}
+
+// The highest-degree nodes, ties broken by key
+fn hubs(network: &LightningNetworkMap, count: usize) -> Vec<String> {
+    let mut nodes: Vec<(usize, &String)> = network.nodes.keys().map(|node| (network.degree(node), node)).collect();
+    nodes.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+    nodes.into_iter().take(count).map(|(_, node)| node.clone()).collect()
+}
+
+// Hops from every reachable node to the nearest of `sources`
+fn distances_from<'a>(network: &'a LightningNetworkMap, sources: &'a [String]) -> HashMap<&'a str, usize> {
+    let mut distances: HashMap<&str, usize> = sources.iter().map(|node| (node.as_str(), 0)).collect();
+    let mut queue: VecDeque<&str> = sources.iter().map(String::as_str).collect();
+    let mut seen: HashSet<&str> = queue.iter().copied().collect();
+    while let Some(node) = queue.pop_front() {
+        let distance = distances[node];
+        for neighbor in network.get_neighbors(node).into_iter().flatten() {
+            if seen.insert(neighbor) {
+                distances.insert(neighbor, distance + 1);
+                queue.push_back(neighbor);
+            }
+        }
+    }
+    distances
+}
+
+fn most_common_delta(network: &LightningNetworkMap) -> u32 {
+    let mut counts: HashMap<u32, usize> = HashMap::new();
+    for node in network.nodes.values() {
+        *counts.entry(node.cltv_expiry_delta).or_default() += 1;
+    }
+    counts.into_iter().max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0))).map_or(0, |(delta, _)| delta)
+}
+
+fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() { 0.0 } else { values.iter().sum::<f64>() / values.len() as f64 }
+}
+
+fn std_dev(values: &[f64]) -> f64 {
+    let m = mean(values);
+    mean(&values.iter().map(|v| (v - m).powi(2)).collect::<Vec<_>>()).sqrt()
+}
+
+fn split_means(column: &[f64], outcome: &[f64]) -> (Option<f64>, Option<f64>) {
+    let (mut positive, mut other) = (Vec::new(), Vec::new());
+    for (x, y) in column.iter().zip(outcome) {
+        if *y > 0.0 { positive.push(*x) } else { other.push(*x) }
+    }
+    let group_mean = |values: Vec<f64>| (!values.is_empty()).then(|| mean(&values));
+    (group_mean(positive), group_mean(other))
+}
+
+fn correlation(x: &[f64], y: &[f64]) -> f64 {
+    let (sx, sy) = (std_dev(x), std_dev(y));
+    if sx == 0.0 || sy == 0.0 {
+        return 0.0;
+    }
+    let (mx, my) = (mean(x), mean(y));
+    mean(&x.iter().zip(y).map(|(a, b)| (a - mx) * (b - my)).collect::<Vec<_>>()) / (sx * sy)
+}
+
+// Least squares over standardized features, leaving out the ones that don't vary. Returns
+// each feature's coefficient and R², or None when the outcome doesn't vary or the
+// features are collinear.
+fn fit(rows: &[[f64; 3]], outcome: &[f64]) -> Option<([Option<f64>; 3], f64)> {
+    if std_dev(outcome) == 0.0 {
+        return None;
+    }
+    let columns: Vec<(usize, f64, f64)> = (0..3)
+        .filter_map(|i| {
+            let column: Vec<f64> = rows.iter().map(|row| row[i]).collect();
+            let sd = std_dev(&column);
+            (sd > 0.0).then(|| (i, mean(&column), sd))
+        })
+        .collect();
+    let design: Vec<Vec<f64>> = rows.iter()
+        .map(|row| std::iter::once(1.0).chain(columns.iter().map(|&(i, m, sd)| (row[i] - m) / sd)).collect())
+        .collect();
+    let k = columns.len() + 1;
+    if design.len() <= k {
+        return None;
+    }
+
+    // Normal equations, solved by Gaussian elimination with partial pivoting
+    let mut system = vec![vec![0.0; k + 1]; k];
+    for (x, y) in design.iter().zip(outcome) {
+        for r in 0..k {
+            for c in 0..k {
+                system[r][c] += x[r] * x[c];
+            }
+            system[r][k] += x[r] * y;
+        }
+    }
+    for col in 0..k {
+        let pivot = (col..k).max_by(|&a, &b| system[a][col].abs().total_cmp(&system[b][col].abs()))?;
+        if system[pivot][col].abs() < 1e-9 {
+            return None;
+        }
+        system.swap(col, pivot);
+        for row in 0..k {
+            if row != col {
+                let factor = system[row][col] / system[col][col];
+                let pivot_row = system[col].clone();
+                for (value, pivot_value) in system[row].iter_mut().zip(&pivot_row).skip(col) {
+                    *value -= factor * pivot_value;
+                }
+            }
+        }
+    }
+    let beta: Vec<f64> = (0..k).map(|i| system[i][k] / system[i][i]).collect();
+
+    let my = mean(outcome);
+    let residual: f64 = design.iter().zip(outcome)
+        .map(|(x, y)| (y - x.iter().zip(&beta).map(|(a, b)| a * b).sum::<f64>()).powi(2))
+        .sum();
+    let total: f64 = outcome.iter().map(|y| (y - my).powi(2)).sum();
+
+    let mut coefficients = [None; 3];
+    for (j, &(i, _, _)) in columns.iter().enumerate() {
+        coefficients[i] = Some(beta[j + 1]);
+    }
+    Some((coefficients, 1.0 - residual / total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Channel, Node};
+    use crate::test_fixtures::candidates;
+
+    #[test]
+    fn test_false_positives_described_by_topology() {
+        // A star around h with a tail: the leaves next to the hub get named wrongly
+        let mut network = LightningNetworkMap::new(700000);
+        for (key, delta) in [("h", 40), ("a", 40), ("b", 40), ("c", 40), ("d", 144), ("e", 80)] {
+            network.add_node(Node::new(key, key, delta));
+        }
+        for (id, x, y) in [("ha", "h", "a"), ("hb", "h", "b"), ("hc", "h", "c"), ("hd", "h", "d"), ("de", "d", "e")] {
+            network.add_channel(Channel::new(id, x, y, 10_000_000));
+        }
+        let results = HashMap::from([
+            ("p1".to_string(), candidates(&[("a", 1.0), ("b", 0.9), ("e", 0.1)])),
+            ("p2".to_string(), candidates(&[("b", 1.0), ("c", 0.8)])),
+            ("unknown".to_string(), candidates(&[("e", 1.0)])),
+        ]);
+        let routes = HashMap::from([
+            ("p1".to_string(), vec!["h".to_string(), "a".to_string()]),
+            ("p2".to_string(), vec!["h".to_string(), "a".to_string()]),
+        ]);
+        let analysis = FalsePositiveAnalysis::compute(&network, &results, &routes, &["h".to_string()]).unwrap();
+
+        // b twice, and c once
+        assert_eq!((analysis.nodes, analysis.false_positive_nodes, analysis.false_positive_candidacies), (5, 2, 3));
+        assert_eq!(analysis.standard_cltv_delta, 40);
+        let distance = &analysis.features[1];
+        assert!(distance.mean_false_positive < distance.mean_other);
+        assert!(distance.mean_false_positive.is_some());
+        assert!(distance.correlation < 0.0);
+        assert!(analysis.generate_text_section().contains("| standard CLTV delta | 1.00 |"));
+
+        assert!(FalsePositiveAnalysis::compute(&network, &results, &HashMap::new(), &[]).is_none());
+    }
+}
@@ -0,0 +1,247 @@
+// Linking observations without a shared hash. Under PTLCs every hop sees a different
+// point, so an observer can't tell which HTLCs seen elsewhere belong to the same payment.
+// What still gives them away is their shape: HTLCs of one payment arrive together, the
+// timelock shrinks by a delta or more per hop and the amount by no more than the fees.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::models::{HTLC, LightningNetworkMap, CLTV_EXPIRY_DELTA_MIN, MAX_ROUTE_HOPS};
+use crate::surveillance::PotentialRecipient;
+
+// Earlier observations an HTLC is compared against; payments further back are assumed done
+pub const DEFAULT_LINK_WINDOW: usize = 64;
+
+// Most each hop can take away from a payment's amount and timelock, from the network's
+// most expensive and slowest policies
+#[derive(Debug, Clone, Copy, Default)]
+struct HopBounds {
+    max_base_fee_msat: u64,
+    max_fee_rate_ppm: u64,
+    max_cltv_delta: u32,
+}
+
+impl HopBounds {
+    fn of(network: &LightningNetworkMap) -> Self {
+        let bounds = network.nodes.values().fold(HopBounds::default(), |bounds, node| HopBounds {
+            max_base_fee_msat: bounds.max_base_fee_msat.max(node.base_fee_msat),
+            max_fee_rate_ppm: bounds.max_fee_rate_ppm.max(node.fee_rate_ppm),
+            max_cltv_delta: bounds.max_cltv_delta.max(node.cltv_expiry_delta),
+        });
+        // Fees set for single channels may be higher than any node's usual ones
+        network.channels.iter()
+            .flat_map(|channel| [channel.node1_fees, channel.node2_fees])
+            .flatten()
+            .fold(bounds, |bounds, fees| HopBounds {
+                max_base_fee_msat: bounds.max_base_fee_msat.max(fees.base_fee_msat),
+                max_fee_rate_ppm: bounds.max_fee_rate_ppm.max(fees.fee_rate_ppm),
+                ..bounds
+            })
+    }
+}
+
+// An observation still in the window, with the cluster it was put in
+struct Sighting {
+    observer: String,
+    cltv_expiry: u32,
+    amount: u64,
+    block: u32,
+    cluster: String,
+}
+
+// Groups observations into probable payments as they arrive. Each HTLC joins the cluster
+// of the most recent earlier observation that could be the same payment further upstream,
+// or starts a cluster of its own named after its identifier.
+pub struct FingerprintLinker {
+    window: usize,
+    recent: VecDeque<Sighting>,
+    // Observers already in each cluster; a payment passes each node once
+    members: HashMap<String, HashSet<String>>,
+    // Cluster each original identifier was put in
+    links: HashMap<String, String>,
+    bounds: HopBounds,
+    // Topology version the bounds were taken at
+    topology_version: Option<u64>,
+}
+
+impl FingerprintLinker {
+    pub fn new(window: usize) -> Self {
+        FingerprintLinker {
+            window: window.max(1),
+            recent: VecDeque::new(),
+            members: HashMap::new(),
+            links: HashMap::new(),
+            bounds: HopBounds::default(),
+            topology_version: None,
+        }
+    }
+
+    // Put the HTLC in a cluster and replace its identifier with the cluster's
+    pub fn link(&mut self, network: &LightningNetworkMap, htlc: &mut HTLC) {
+        if self.topology_version != Some(network.topology_version()) {
+            self.topology_version = Some(network.topology_version());
+            self.bounds = HopBounds::of(network);
+        }
+
+        let cluster = self.recent.iter().rev()
+            .find(|upstream| self.matches(upstream, htlc))
+            .map(|upstream| upstream.cluster.clone())
+            .unwrap_or_else(|| htlc.payment_hash.clone());
+
+        self.members.entry(cluster.clone()).or_default().insert(htlc.observed_by_node.clone());
+        self.links.insert(htlc.payment_hash.clone(), cluster.clone());
+        self.recent.push_back(Sighting {
+            observer: htlc.observed_by_node.clone(),
+            cltv_expiry: htlc.cltv_expiry,
+            amount: htlc.amount,
+            block: htlc.observed_at_block,
+            cluster: cluster.clone(),
+        });
+        if self.recent.len() > self.window {
+            self.recent.pop_front();
+        }
+        htlc.payment_hash = cluster;
+    }
+
+    // Whether `upstream` could be the same payment seen some hops before `htlc`
+    fn matches(&self, upstream: &Sighting, htlc: &HTLC) -> bool {
+        if upstream.observer == htlc.observed_by_node
+            || upstream.block != htlc.observed_at_block
+            || upstream.cltv_expiry <= htlc.cltv_expiry
+            || upstream.amount < htlc.amount
+            || self.members.get(&upstream.cluster).is_some_and(|m| m.contains(&htlc.observed_by_node)) {
+            return false;
+        }
+
+        let gap = upstream.cltv_expiry - htlc.cltv_expiry;
+        if gap > self.bounds.max_cltv_delta.saturating_mul(MAX_ROUTE_HOPS as u32) {
+            return false;
+        }
+
+        // No more hops in between than the smallest delta allows, each charging at most
+        // the highest fee on the network
+        let hops = gap.div_ceil(CLTV_EXPIRY_DELTA_MIN) as u64;
+        let max_fee = self.bounds.max_base_fee_msat + htlc.amount * self.bounds.max_fee_rate_ppm / 1_000_000;
+        upstream.amount - htlc.amount <= hops * max_fee
+    }
+
+    // Cluster each original identifier was put in
+    pub fn links(&self) -> &HashMap<String, String> {
+        &self.links
+    }
+}
+
+impl Default for FingerprintLinker {
+    fn default() -> Self {
+        FingerprintLinker::new(DEFAULT_LINK_WINDOW)
+    }
+}
+
+// How well clusters matched the real payments, over pairs of observations: precision is
+// the share of linked pairs that were one payment, recall the share of one payment's pairs
+// that were linked
+#[derive(Debug, Clone, Default)]
+pub struct LinkingAccuracy {
+    pub observations: usize,
+    pub clusters: usize,
+    pub payments: usize,
+    pub linked_pairs: usize,
+    pub true_pairs: usize,
+    pub correct_pairs: usize,
+}
+
+impl LinkingAccuracy {
+    // Score the linker's clusters against the payment each identifier really belonged to
+    pub fn compute(links: &HashMap<String, String>, owners: &HashMap<String, String>) -> Self {
+        let mut by_cluster: HashMap<&str, usize> = HashMap::new();
+        let mut by_owner: HashMap<&str, usize> = HashMap::new();
+        let mut by_both: HashMap<(&str, &str), usize> = HashMap::new();
+        let mut observations = 0;
+        for (id, cluster) in links {
+            let Some(owner) = owners.get(id) else { continue };
+            observations += 1;
+            *by_cluster.entry(cluster).or_default() += 1;
+            *by_owner.entry(owner).or_default() += 1;
+            *by_both.entry((cluster, owner)).or_default() += 1;
+        }
+
+        LinkingAccuracy {
+            observations,
+            clusters: by_cluster.len(),
+            payments: by_owner.len(),
+            linked_pairs: by_cluster.values().map(|&n| pairs(n)).sum(),
+            true_pairs: by_owner.values().map(|&n| pairs(n)).sum(),
+            correct_pairs: by_both.values().map(|&n| pairs(n)).sum(),
+        }
+    }
+
+    // With nothing linked, or nothing to link, there was nothing to get wrong
+    pub fn precision(&self) -> f64 {
+        if self.linked_pairs == 0 { 1.0 } else { self.correct_pairs as f64 / self.linked_pairs as f64 }
+    }
+
+    pub fn recall(&self) -> f64 {
+        if self.true_pairs == 0 { 1.0 } else { self.correct_pairs as f64 / self.true_pairs as f64 }
+    }
+}
+
+fn pairs(n: usize) -> usize {
+    n * n.saturating_sub(1) / 2
+}
+
+// Analysis results keyed by the payment each cluster's first observation belonged to, so
+// they can be scored against ground truth kept by payment hash. Clusters named after an
+// identifier the owners don't know are kept as they are.
+pub fn attribute_to_payments(results: HashMap<String, Vec<PotentialRecipient>>,
+                             owners: &HashMap<String, String>) -> HashMap<String, Vec<PotentialRecipient>> {
+    if owners.is_empty() {
+        return results;
+    }
+    let mut attributed = HashMap::new();
+    for (cluster, candidates) in results {
+        let payment_hash = owners.get(&cluster).cloned().unwrap_or(cluster);
+        attributed.entry(payment_hash).or_insert(candidates);
+    }
+    attributed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Node;
+
+    #[test]
+    fn test_fingerprints_link_hops_of_one_payment() {
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["m", "n", "o"] {
+            network.add_node(Node::new(key, key, 40));
+        }
+        let mut linker = FingerprintLinker::default();
+
+        // Payment 1 passes m, n and o, paying fees on the way; payment 2 is seen by m and n
+        // with an amount n could not have forwarded for it
+        let mut observations = vec![
+            HTLC::new("p1m", 700200, 1_002_002, 700000, "m"),
+            HTLC::new("p1n", 700160, 1_001_001, 700000, "n"),
+            HTLC::new("p1o", 700120, 1_000_000, 700000, "o"),
+            HTLC::new("p2m", 700190, 5_000_000, 700000, "m"),
+            HTLC::new("p2n", 700150, 9_000_000, 700000, "n"),
+        ];
+        for htlc in &mut observations {
+            linker.link(&network, htlc);
+        }
+        let clusters: Vec<&str> = observations.iter().map(|htlc| htlc.payment_hash.as_str()).collect();
+        assert_eq!(clusters, vec!["p1m", "p1m", "p1m", "p2m", "p2n"]);
+
+        let owners: HashMap<String, String> = ["p1m", "p1n", "p1o", "p2m", "p2n"].iter()
+            .map(|id| (id.to_string(), id[..2].to_string()))
+            .collect();
+        let accuracy = LinkingAccuracy::compute(linker.links(), &owners);
+        assert_eq!((accuracy.linked_pairs, accuracy.true_pairs, accuracy.correct_pairs), (3, 4, 3));
+        assert_eq!(accuracy.precision(), 1.0);
+        assert_eq!(accuracy.recall(), 0.75);
+
+        let results = HashMap::from([("p1m".to_string(), Vec::new()), ("p2n".to_string(), Vec::new())]);
+        let attributed = attribute_to_payments(results, &owners);
+        assert!(attributed.contains_key("p1") && attributed.contains_key("p2"));
+    }
+}
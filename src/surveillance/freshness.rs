@@ -0,0 +1,121 @@
+// Gossip freshness: senders' pathfinding shies away from channels whose policies haven't
+// been re-announced in a long while, since their peers may be gone. The adversary hears the
+// same gossip, so it can make routes through channels that were stale when a payment was
+// made less likely.
+
+use std::collections::HashMap;
+
+use crate::models::LightningNetworkMap;
+
+// Blocks without a `channel_update` after which a channel's policy counts as stale, a week
+pub const DEFAULT_STALE_AFTER_BLOCKS: u32 = 1008;
+
+// When each channel's updates were heard, oldest first
+#[derive(Debug, Clone, Default)]
+pub struct GossipLog {
+    updates: HashMap<String, Vec<u32>>,
+}
+
+impl GossipLog {
+    // Start from the updates the graph already carries
+    pub fn of(network: &LightningNetworkMap) -> Self {
+        let mut log = GossipLog::default();
+        for channel in &network.channels {
+            if let Some(block) = channel.last_update {
+                log.record(&channel.channel_id, block);
+            }
+        }
+        log
+    }
+
+    pub fn record(&mut self, channel_id: &str, block: u32) {
+        let updates = self.updates.entry(channel_id.to_string()).or_default();
+        let at = updates.partition_point(|&seen| seen <= block);
+        updates.insert(at, block);
+    }
+
+    // Newest update at or before `block`, if any was heard by then
+    pub fn last_update_before(&self, channel_id: &str, block: u32) -> Option<u32> {
+        let updates = self.updates.get(channel_id)?;
+        updates[..updates.partition_point(|&seen| seen <= block)].last().copied()
+    }
+}
+
+// Scales a route's confidence by `1 - penalty` for every hop whose channels had all gone
+// stale by the time the payment was made. Channels never heard from are given the benefit
+// of the doubt.
+#[derive(Debug, Clone)]
+pub struct FreshnessHeuristic {
+    pub penalty: f64,
+    pub stale_after_blocks: u32,
+    log: GossipLog,
+}
+
+impl FreshnessHeuristic {
+    pub fn new(penalty: f64, log: GossipLog) -> Self {
+        FreshnessHeuristic {
+            penalty: penalty.clamp(0.0, 1.0),
+            stale_after_blocks: DEFAULT_STALE_AFTER_BLOCKS,
+            log,
+        }
+    }
+
+    pub fn stale_after_blocks(mut self, blocks: u32) -> Self {
+        self.stale_after_blocks = blocks;
+        self
+    }
+
+    pub fn record(&mut self, channel_id: &str, block: u32) {
+        self.log.record(channel_id, block);
+    }
+
+    // Hops of the route with no routable channel updated recently enough before `block`
+    pub fn stale_hops(&self, network: &LightningNetworkMap, route: &[String], block: u32) -> usize {
+        route.windows(2)
+            .filter(|hop| {
+                let ages: Vec<Option<u32>> = network.channels_between(&hop[0], &hop[1]).into_iter()
+                    .filter(|channel| channel.status.is_active())
+                    .map(|channel| self.log.last_update_before(&channel.channel_id, block).map(|seen| block - seen))
+                    .collect();
+                !ages.is_empty() && ages.iter().all(|age| age.is_some_and(|age| age > self.stale_after_blocks))
+            })
+            .count()
+    }
+
+    pub fn weight(&self, network: &LightningNetworkMap, route: &[String], block: u32) -> f64 {
+        (1.0 - self.penalty).powi(self.stale_hops(network, route, block) as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Channel, Node};
+
+    #[test]
+    fn test_stale_hops_are_judged_at_payment_time() {
+        let mut network = LightningNetworkMap::new(10_000);
+        for key in ["a", "b", "c", "d"] {
+            network.add_node(Node::new(key, key, 40));
+        }
+        network.add_channel(Channel::new("ab", "a", "b", 1_000_000).with_last_update(9_900));
+        network.add_channel(Channel::new("bc", "b", "c", 1_000_000).with_last_update(7_000));
+        // Never heard from
+        network.add_channel(Channel::new("cd", "c", "d", 1_000_000));
+
+        let mut heuristic = FreshnessHeuristic::new(0.5, GossipLog::of(&network));
+        let route: Vec<String> = ["a", "b", "c", "d"].iter().map(|n| n.to_string()).collect();
+        assert_eq!(heuristic.stale_hops(&network, &route, 10_000), 1);
+        assert_eq!(heuristic.weight(&network, &route, 10_000), 0.5);
+
+        // An update heard later doesn't make the channel fresh for earlier payments
+        heuristic.record("bc", 10_050);
+        assert_eq!(heuristic.stale_hops(&network, &route, 10_000), 1);
+        assert_eq!(heuristic.stale_hops(&network, &route, 10_100), 0);
+
+        // A parallel channel kept fresh is enough for the hop
+        network.add_channel(Channel::new("bc2", "b", "c", 1_000_000));
+        heuristic.record("bc2", 9_990);
+        assert_eq!(heuristic.stale_hops(&network, &route, 10_000), 0);
+    }
+}
@@ -0,0 +1,171 @@
+// Whether timing alone gives away where payments go. The adversary knows where its own nodes
+// are and roughly how long messages take between regions. Given how many hops remained,
+// which timelocks come close to telling it, it picks the recipient region whose expected
+// settlement wait is nearest the one it timed.
+
+use std::collections::HashMap;
+
+use crate::models::LightningNetworkMap;
+use crate::simulation::RegionLatency;
+use crate::surveillance::metrics::rate;
+use crate::surveillance::settlement::SettlementTiming;
+
+// Timed payments ending in one region
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RegionGuesses {
+    pub region: String,
+    pub sightings: usize,
+    pub guessed_right: usize,
+    // Sightings of payments ending elsewhere put down to this region
+    pub guessed_wrongly: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct GeographyInference {
+    pub sightings: usize,
+    pub correct: usize,
+    // Sightings always guessing the region most nodes are in would get right
+    pub baseline_correct: usize,
+    pub regions: Vec<RegionGuesses>,
+}
+
+impl GeographyInference {
+    // Guess the recipient's region for every settle our nodes timed on a route whose end,
+    // and the observer, have a region. None without a single one.
+    pub fn compute(network: &LightningNetworkMap,
+                   timing: &SettlementTiming,
+                   model: &RegionLatency,
+                   routes: &HashMap<String, Vec<String>>) -> Option<Self> {
+        let region_of = |node: &str| network.nodes.get(node)
+            .and_then(|node| node.region.as_deref())
+            .and_then(|region| model.index(region));
+        let total_share: f64 = model.regions.iter().map(|(_, share)| share).sum();
+        let shares: Vec<f64> = model.regions.iter().map(|(_, share)| share / total_share).collect();
+        let most_common = (0..shares.len()).max_by(|a, b| shares[*a].total_cmp(&shares[*b])).unwrap_or(0);
+
+        let mut guesses: Vec<RegionGuesses> = model.regions.iter()
+            .map(|(name, _)| RegionGuesses { region: name.clone(), ..RegionGuesses::default() })
+            .collect();
+        let (mut sightings, mut correct, mut baseline_correct) = (0, 0, 0);
+        for (lock, observer, settled, hold_ms) in timing.resolutions() {
+            let Some(route) = routes.get(lock).filter(|_| settled) else { continue };
+            let Some(hop) = route.iter().position(|node| node == observer) else { continue };
+            let remaining = route.len() - 1 - hop;
+            let (Some(from), Some(actual)) = (region_of(observer), region_of(&route[route.len() - 1])) else { continue };
+            if remaining == 0 {
+                continue;
+            }
+            let guess = (0..shares.len())
+                .min_by(|a, b| {
+                    let miss = |to: usize| (expected_wait_ms(model, &shares, from, to, remaining) - hold_ms as f64).abs();
+                    miss(*a).total_cmp(&miss(*b))
+                })
+                .unwrap_or(most_common);
+
+            sightings += 1;
+            guesses[actual].sightings += 1;
+            if guess == actual {
+                correct += 1;
+                guesses[actual].guessed_right += 1;
+            } else {
+                guesses[guess].guessed_wrongly += 1;
+            }
+            if most_common == actual {
+                baseline_correct += 1;
+            }
+        }
+        (sightings > 0).then_some(GeographyInference { sightings, correct, baseline_correct, regions: guesses })
+    }
+
+    pub fn accuracy(&self) -> f64 {
+        rate(self.correct, self.sightings)
+    }
+
+    pub fn baseline_accuracy(&self) -> f64 {
+        rate(self.baseline_correct, self.sightings)
+    }
+
+    pub fn generate_text_section(&self) -> String {
+        let mut section = String::from("### Geography Inference\n\n");
+        section.push_str(&format!("Recipient region guessed from the settlement wait for {} of {} timed settles ({:.1}%); \
+                                   always guessing the most common region gets {:.1}%\n\n",
+                                  self.correct, self.sightings, self.accuracy() * 100.0, self.baseline_accuracy() * 100.0));
+        section.push_str("| Recipient region | Settles timed | Guessed right | Others' payments put here |\n");
+        section.push_str("|---|---|---|---|\n");
+        for region in &self.regions {
+            section.push_str(&format!("| {} | {} | {} ({:.1}%) | {} |\n",
+                                      region.region, region.sightings, region.guessed_right,
+                                      rate(region.guessed_right, region.sightings) * 100.0, region.guessed_wrongly));
+        }
+        section.push('\n');
+        section
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let regions: Vec<serde_json::Value> = self.regions.iter()
+            .map(|region| serde_json::json!({
+                "region": region.region,
+                "settles_timed": region.sightings,
+                "guessed_right": region.guessed_right,
+                "guessed_wrongly": region.guessed_wrongly,
+            }))
+            .collect();
+        serde_json::json!({
+            "settles_timed": self.sightings,
+            "correct": self.correct,
+            "accuracy": self.accuracy(),
+            "baseline_accuracy": self.baseline_accuracy(),
+            "regions": regions,
+        })
+    }
+}
+
+// Round trip over `hops` links from region `from` to a recipient in `to`, the nodes in
+// between drawn from the regions by share
+fn expected_wait_ms(model: &RegionLatency, shares: &[f64], from: usize, to: usize, hops: usize) -> f64 {
+    let latency = |a: usize, b: usize| model.latency_ms[a][b] as f64;
+    let one_way = if hops == 1 {
+        latency(from, to)
+    } else {
+        let mean_from = |a: usize| shares.iter().enumerate().map(|(b, share)| share * latency(a, b)).sum::<f64>();
+        let into_to: f64 = shares.iter().enumerate().map(|(b, share)| share * latency(b, to)).sum();
+        let between: f64 = shares.iter().enumerate().map(|(a, share)| share * mean_from(a)).sum();
+        mean_from(from) + (hops - 2) as f64 * between + into_to
+    };
+    2.0 * one_way
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{HtlcResolution, Node};
+
+    #[test]
+    fn test_wait_points_at_the_recipient_region() {
+        let model = RegionLatency::parse(r#"{
+            "regions": [{"name": "near", "share": 3}, {"name": "far", "share": 1}],
+            "latency_ms": [[10, 200], [200, 10]]
+        }"#).unwrap();
+        let mut network = LightningNetworkMap::new(700000);
+        for (key, region) in [("m", "near"), ("a", "near"), ("b", "far")] {
+            let mut node = Node::new(key, key, 40);
+            node.region = Some(region.to_string());
+            network.add_node(node);
+        }
+        let routes: HashMap<String, Vec<String>> = [("h1", "a"), ("h2", "b")].iter()
+            .map(|(hash, receiver)| (hash.to_string(), vec!["s".to_string(), "m".to_string(), receiver.to_string()]))
+            .collect();
+        let mut timing = SettlementTiming::new(100);
+        timing.record(&HtlcResolution::new("h1", "m", true, 22));
+        timing.record(&HtlcResolution::new("h2", "m", true, 390));
+        let inference = GeographyInference::compute(&network, &timing, &model, &routes).unwrap();
+
+        assert_eq!((inference.sightings, inference.correct, inference.baseline_correct), (2, 2, 1));
+        assert_eq!(inference.regions[1], RegionGuesses { region: "far".to_string(), sightings: 1, guessed_right: 1, guessed_wrongly: 0 });
+
+        // A quick settle of the payment to b reads as a nearby recipient
+        timing.record(&HtlcResolution::new("h2", "m", true, 20));
+        let inference = GeographyInference::compute(&network, &timing, &model, &routes).unwrap();
+        assert_eq!((inference.correct, inference.regions[0].guessed_wrongly), (1, 1));
+    }
+}
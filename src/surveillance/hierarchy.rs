@@ -0,0 +1,130 @@
+// Hierarchical analysis: on hub-and-spoke graphs payments cross a backbone of hubs and end
+// at a leaf of the last one. Working out the final hub first, over the backbone alone, and
+// then naming only that hub's leaves is how such inference is done by hand, and avoids
+// enumerating every route through every leaf on the way.
+
+use std::collections::HashMap;
+
+use crate::models::LightningNetworkMap;
+
+// A node is a hub when it has at least this many times the network's mean number of channels
+const HUB_DEGREE_FACTOR: f64 = 2.0;
+
+// Fewest channels a hub has on this network
+pub fn hub_degree_threshold(network: &LightningNetworkMap) -> f64 {
+    let mean = 2.0 * network.channels.len() as f64 / network.nodes.len().max(1) as f64;
+    HUB_DEGREE_FACTOR * mean
+}
+
+// Neighbors of a hub that aren't hubs themselves, each once
+pub fn hub_leaves(network: &LightningNetworkMap, hub: &str, threshold: f64) -> Vec<String> {
+    let mut leaves: Vec<String> = network.get_neighbors(hub)
+        .map(|neighbors| neighbors.iter().filter(|node| (network.degree(node) as f64) < threshold).cloned().collect())
+        .unwrap_or_default();
+    leaves.sort();
+    leaves.dedup();
+    leaves
+}
+
+// Hubs an HTLC seen at `observer` with `budget` blocks left most likely reaches last, best
+// first. Every path over hubs alone is a guess that its end is the final hub, worth one per
+// leaf of that hub the remaining timelock fits, less for longer routes as the scorer has it.
+// The observer counts as a hub, so its own leaves are considered too.
+pub fn rank_final_hubs(network: &LightningNetworkMap,
+                       observer: &str,
+                       next_hop: Option<&str>,
+                       budget: u32,
+                       max_hops: usize) -> Vec<(String, f64)> {
+    let mut search = HubSearch {
+        network,
+        threshold: hub_degree_threshold(network),
+        next_hop,
+        lowest: budget.saturating_sub(network.max_shadow_offset()),
+        highest: budget,
+        max_hops,
+        smallest_final_delta: network.nodes.values().map(|node| node.min_final_cltv_expiry_delta).min().unwrap_or(0),
+        path: vec![observer.to_string()],
+        scores: HashMap::new(),
+    };
+    search.explore(0);
+
+    let mut ranked: Vec<(String, f64)> = search.scores.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ranked
+}
+
+struct HubSearch<'a> {
+    network: &'a LightningNetworkMap,
+    threshold: f64,
+    // Node the observer's onion layer says comes next, if it was read
+    next_hop: Option<&'a str>,
+    // Window the route's total timelock must fall in
+    lowest: u32,
+    highest: u32,
+    max_hops: usize,
+    smallest_final_delta: u32,
+    path: Vec<String>,
+    scores: HashMap<String, f64>,
+}
+
+impl HubSearch<'_> {
+    fn explore(&mut self, used_budget: u32) {
+        let current = self.path[self.path.len() - 1].clone();
+        let Some(node) = self.network.nodes.get(&current) else { return };
+        let onward = used_budget + node.cltv_expiry_delta;
+        // The onion names the first hop, so only it can follow the observer
+        let first = self.path.len() == 1;
+        let next_hop = self.next_hop;
+        let allowed = |next: &String| !first || next_hop.is_none_or(|hop| hop == next);
+
+        // Guess: the payment leaves the backbone here, for one of this hub's leaves
+        let fitting = hub_leaves(self.network, &current, self.threshold).iter()
+            .filter(|leaf| allowed(leaf) && !self.path.contains(leaf))
+            .filter_map(|leaf| self.network.nodes.get(leaf))
+            .filter(|leaf| (self.lowest..=self.highest).contains(&(onward + leaf.min_final_cltv_expiry_delta)))
+            .count();
+        if fitting > 0 {
+            // The route to a leaf is one hop longer than the path to its hub
+            let weight = fitting as f64 / ((self.path.len() + 1) as f64).sqrt();
+            *self.scores.entry(current.clone()).or_default() += weight;
+        }
+
+        // Going on to another hub and then a leaf takes two more hops
+        if self.path.len() + 1 > self.max_hops || onward + self.smallest_final_delta > self.highest {
+            return;
+        }
+        let mut hubs: Vec<String> = self.network.get_neighbors(&current)
+            .map(|neighbors| neighbors.iter().filter(|next| (self.network.degree(next) as f64) >= self.threshold).cloned().collect())
+            .unwrap_or_default();
+        hubs.sort();
+        hubs.dedup();
+        for hub in hubs {
+            if allowed(&hub) && !self.path.contains(&hub) {
+                self.path.push(hub);
+                self.explore(onward);
+                self.path.pop();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_final_hub_comes_from_the_timelock() {
+        let network = crate::simulation::fixture("two-hub").unwrap();
+        let threshold = hub_degree_threshold(&network);
+        assert_eq!(hub_leaves(&network, "hub1", threshold), vec!["a", "b", "c"]);
+        let hubs = |ranked: Vec<(String, f64)>| ranked.into_iter().map(|(hub, _)| hub).collect::<Vec<_>>();
+
+        // hub1 forwarding to hub2 forwarding to a leaf: 40 + 40 + the leaf's 40. The shadow
+        // offset leaves room for hub1's own leaves too, which are a hop closer.
+        assert_eq!(hubs(rank_final_hubs(&network, "hub1", None, 120, 20)), vec!["hub1", "hub2"]);
+        // Too little left to get past hub2
+        assert_eq!(hubs(rank_final_hubs(&network, "hub1", None, 80, 20)), vec!["hub1"]);
+        // The onion says hub1 forwards to hub2, so none of hub1's leaves is paid
+        assert_eq!(hubs(rank_final_hubs(&network, "hub1", Some("hub2"), 120, 20)), vec!["hub2"]);
+    }
+}
@@ -0,0 +1,160 @@
+// Incremental analysis that refines candidates as observations arrive
+
+use std::collections::{HashMap, HashSet};
+
+use crate::models::HTLC;
+use crate::surveillance::analyzer::{HTLCAnalyzer, PotentialRecipient};
+
+// Per-payment candidate sets kept up to date one observation at a time, so results are
+// available live instead of only after a full `run_analysis` pass
+#[derive(Default)]
+pub struct IncrementalAnalyzer {
+    payments: HashMap<String, PaymentState>,
+}
+
+struct PaymentState {
+    observations: Vec<HTLC>,
+    candidates: Vec<PotentialRecipient>,
+}
+
+impl IncrementalAnalyzer {
+    pub fn new() -> Self {
+        IncrementalAnalyzer::default()
+    }
+
+    // Fold a new observation into its payment's candidates and return the updated set
+    pub fn observe(&mut self, analyzer: &HTLCAnalyzer, htlc: HTLC) -> &[PotentialRecipient] {
+        let state = self.payments.entry(htlc.payment_hash.clone()).or_insert_with(|| PaymentState {
+            observations: Vec::new(),
+            candidates: Vec::new(),
+        });
+
+        // A later attempt at the payment supersedes the failed ones, and an earlier one
+        // arriving late has nothing to add
+        let attempt_block = state.observations.first().map(|o| o.observed_at_block);
+        if attempt_block.is_some_and(|block| htlc.observed_at_block < block) {
+            return &state.candidates;
+        }
+        if attempt_block.is_some_and(|block| htlc.observed_at_block > block) {
+            state.observations.clear();
+        }
+
+        // The observation with the lowest expiry sits closest to the recipient
+        let closest = state.observations.iter().map(|o| o.cltv_expiry).min();
+        let is_closest = closest.is_none_or(|expiry| htlc.cltv_expiry < expiry);
+        state.observations.push(htlc);
+        let latest = state.observations.len() - 1;
+
+        if is_closest {
+            // A new closest observer: start over from it and check the others against it
+            let newest = &state.observations[latest];
+            state.candidates = analyzer.analyze_htlc(newest);
+            let others: Vec<usize> = (0..latest)
+                .filter(|&i| state.observations[i].observed_by_node != newest.observed_by_node)
+                .collect();
+            for i in others {
+                Self::narrow(analyzer, &mut state.candidates, &state.observations[i]);
+            }
+        } else {
+            // Further upstream: the recipient must also be reachable from here
+            let closest_node = state.observations.iter()
+                .min_by_key(|o| o.cltv_expiry)
+                .map(|o| o.observed_by_node.clone());
+            if closest_node.as_ref() != Some(&state.observations[latest].observed_by_node) {
+                Self::narrow(analyzer, &mut state.candidates, &state.observations[latest]);
+            }
+        }
+
+        &state.candidates
+    }
+
+    // Keep the candidates reachable from another observation point, unless none are
+    fn narrow(analyzer: &HTLCAnalyzer, candidates: &mut Vec<PotentialRecipient>, other: &HTLC) {
+        let ids: HashSet<String> = candidates.iter().map(|r| r.node_id.clone()).collect();
+        if ids.is_empty() {
+            return;
+        }
+
+        let reachable: HashSet<String> = analyzer.analyze_htlc_towards(other, &ids).into_iter()
+            .map(|r| r.node_id)
+            .collect();
+        if !reachable.is_empty() {
+            candidates.retain(|r| reachable.contains(&r.node_id));
+        }
+    }
+
+    // Current candidates for a payment
+    pub fn candidates(&self, payment_hash: &str) -> Option<&[PotentialRecipient]> {
+        self.payments.get(payment_hash).map(|state| state.candidates.as_slice())
+    }
+
+    // Number of payments seen so far
+    pub fn payment_count(&self) -> usize {
+        self.payments.len()
+    }
+
+    // Current results in the same shape as a full analysis run
+    pub fn results(&self) -> HashMap<String, Vec<PotentialRecipient>> {
+        self.payments.iter()
+            .filter(|(_, state)| !state.candidates.is_empty())
+            .map(|(hash, state)| (hash.clone(), state.candidates.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, RwLock};
+    use crate::models::{Channel, LightningNetworkMap, Node};
+
+    #[test]
+    fn test_incremental_matches_batch_analysis() {
+        // a - b - c - d, with a dead end e off c
+        let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(700000)));
+        {
+            let mut network = network_map.write().unwrap();
+            for key in ["a", "b", "c", "d", "e"] {
+                network.add_node(Node::new(key, key, 20));
+            }
+            network.add_channel(Channel::new("ab", "a", "b", 1_000_000));
+            network.add_channel(Channel::new("bc", "b", "c", 1_000_000));
+            network.add_channel(Channel::new("cd", "c", "d", 1_000_000));
+            network.add_channel(Channel::new("ce", "c", "e", 1_000_000));
+        }
+        let analyzer = HTLCAnalyzer::new(network_map);
+
+        // One payment seen at b then c, another seen in the opposite order
+        let upstream = HTLC::new("p1", 700080, 100000, 700000, "b");
+        let downstream = HTLC::new("p1", 700060, 100000, 700000, "c");
+        let observations = vec![
+            upstream.clone(),
+            downstream.clone(),
+            HTLC { payment_hash: "p2".to_string(), ..downstream },
+            HTLC { payment_hash: "p2".to_string(), ..upstream.clone() },
+            // A third first seen at e, then retried a block later and seen only at b
+            HTLC::new("p3", 700060, 100000, 700000, "e"),
+            HTLC::new("p3", 700081, 100000, 700001, "b"),
+        ];
+
+        let mut incremental = IncrementalAnalyzer::new();
+        for htlc in &observations {
+            incremental.observe(&analyzer, htlc.clone());
+        }
+
+        let batch = analyzer.correlate_observations(&observations);
+        let live = incremental.results();
+        assert_eq!(live.len(), batch.len());
+        for (hash, recipients) in &batch {
+            let expected: HashSet<&String> = recipients.iter().map(|r| &r.node_id).collect();
+            let actual: HashSet<&String> = live[hash].iter().map(|r| &r.node_id).collect();
+            assert_eq!(actual, expected);
+        }
+        assert_eq!(incremental.payment_count(), 3);
+
+        // Only the retry counts
+        let retry: HashSet<String> = analyzer.analyze_htlc(&observations[5]).into_iter().map(|r| r.node_id).collect();
+        let correlated: HashSet<String> = batch["p3"].iter().map(|r| r.node_id.clone()).collect();
+        assert_eq!(correlated, retry);
+    }
+}
@@ -0,0 +1,137 @@
+// Identification broken down by the label of the node paid, answering questions like "how
+// often are payments to exchanges identified?" once a labels file has tagged the network
+
+use std::collections::HashMap;
+
+use crate::models::{LightningNetworkMap, UNLABELED};
+use crate::surveillance::analyzer::PotentialRecipient;
+use crate::surveillance::accuracy::rank_candidates;
+
+// Payments to nodes carrying one label
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LabelStats {
+    pub label: String,
+    pub payments: usize,
+    pub in_candidates: usize,
+    // Payments whose top candidate was the recipient
+    pub identified: usize,
+    // Payments to someone else whose top candidate carried this label
+    pub named_wrongly: usize,
+}
+
+impl LabelStats {
+    pub fn identification_rate(&self) -> f64 {
+        if self.payments == 0 { 0.0 } else { self.identified as f64 / self.payments as f64 }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LabelAccuracy {
+    // Most-paid labels first
+    pub labels: Vec<LabelStats>,
+}
+
+impl LabelAccuracy {
+    // Score analyzed payments with a known recipient by its label. None when no node is
+    // labeled or no recipient is known.
+    pub fn compute(network: &LightningNetworkMap,
+                   results: &HashMap<String, Vec<PotentialRecipient>>,
+                   routes: &HashMap<String, Vec<String>>) -> Option<Self> {
+        if network.nodes.values().all(|node| node.label.is_none()) {
+            return None;
+        }
+        let label_of = |node: &str| network.nodes.get(node)
+            .and_then(|n| n.label.clone())
+            .unwrap_or_else(|| UNLABELED.to_string());
+
+        let mut stats: HashMap<String, LabelStats> = HashMap::new();
+        for (payment_hash, recipients) in results {
+            let Some(receiver) = routes.get(payment_hash).and_then(|route| route.last()) else { continue };
+            let label = label_of(receiver);
+            let entry = stats.entry(label.clone()).or_insert_with(|| LabelStats { label, ..Default::default() });
+            entry.payments += 1;
+
+            let ranked = rank_candidates(recipients);
+            if ranked.iter().any(|(node, _)| node == receiver) {
+                entry.in_candidates += 1;
+            }
+            match ranked.first() {
+                Some((top, _)) if top == receiver => entry.identified += 1,
+                Some((top, _)) => {
+                    let label = label_of(top);
+                    stats.entry(label.clone()).or_insert_with(|| LabelStats { label, ..Default::default() })
+                        .named_wrongly += 1;
+                }
+                None => {}
+            }
+        }
+        if stats.is_empty() {
+            return None;
+        }
+
+        let mut labels: Vec<LabelStats> = stats.into_values().collect();
+        labels.sort_by(|a, b| b.payments.cmp(&a.payments).then_with(|| a.label.cmp(&b.label)));
+        Some(LabelAccuracy { labels })
+    }
+
+    pub fn generate_text_section(&self) -> String {
+        let mut section = String::from("### Identification by Label\n\n");
+        section.push_str("| Recipient label | Payments | Recipient in candidates | Recipient identified | Top candidate for others' payments |\n");
+        section.push_str("|---|---|---|---|---|\n");
+        for stats in &self.labels {
+            section.push_str(&format!("| {} | {} | {} | {} ({:.1}%) | {} |\n",
+                                      stats.label, stats.payments, stats.in_candidates, stats.identified,
+                                      stats.identification_rate() * 100.0, stats.named_wrongly));
+        }
+        section.push('\n');
+        section
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let labels: Vec<serde_json::Value> = self.labels.iter()
+            .map(|stats| serde_json::json!({
+                "label": stats.label,
+                "payments": stats.payments,
+                "in_candidates": stats.in_candidates,
+                "identified": stats.identified,
+                "identification_rate": stats.identification_rate(),
+                "named_wrongly": stats.named_wrongly,
+            }))
+            .collect();
+        serde_json::Value::Array(labels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Node, NodeLabels};
+    use crate::test_fixtures::candidates;
+
+    #[test]
+    fn test_identification_grouped_by_recipient_label() {
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["x1", "x2", "m", "p"] {
+            network.add_node(Node::new(key, key, 40));
+        }
+        let routes: HashMap<String, Vec<String>> = [("h1", "x1"), ("h2", "x2"), ("h3", "m")].iter()
+            .map(|(hash, receiver)| (hash.to_string(), vec!["s".to_string(), receiver.to_string()]))
+            .collect();
+        let results = HashMap::from([
+            ("h1".to_string(), candidates(&[("x1", 1.0)])),
+            ("h2".to_string(), candidates(&[("m", 0.9), ("x2", 0.2)])),
+            ("h3".to_string(), candidates(&[("p", 1.0)])),
+        ]);
+        assert!(LabelAccuracy::compute(&network, &results, &routes).is_none());
+
+        NodeLabels::parse(r#"{"x1": "exchange", "x2": "exchange", "m": "merchant"}"#).unwrap().apply(&mut network);
+        let accuracy = LabelAccuracy::compute(&network, &results, &routes).unwrap();
+
+        let exchange = &accuracy.labels[0];
+        assert_eq!((exchange.label.as_str(), exchange.payments, exchange.in_candidates, exchange.identified), ("exchange", 2, 2, 1));
+        let merchant = accuracy.labels.iter().find(|s| s.label == "merchant").unwrap();
+        assert_eq!((merchant.payments, merchant.identified, merchant.named_wrongly), (1, 0, 1));
+        let unlabeled = accuracy.labels.iter().find(|s| s.label == UNLABELED).unwrap();
+        assert_eq!((unlabeled.payments, unlabeled.named_wrongly), (0, 1));
+    }
+}
@@ -0,0 +1,205 @@
+// Mobile-wallet clients reach the network through one LSP, so an LSP that spies sits on
+// every payment its clients send or receive, next to them. This compares malicious LSPs with
+// the same number of malicious nodes placed at random, on the clients' payments above all.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Write;
+use log::info;
+
+use crate::simulation::{LspClients, PaymentRecord};
+use crate::surveillance::{rate, CoalitionResult, PotentialRecipient};
+use crate::surveillance::accuracy::rank_candidates;
+use crate::error::ThelmaError;
+
+// How one adversary did, overall and on payments to and from clients
+#[derive(Debug, Clone)]
+pub struct LspScenario {
+    pub overall: CoalitionResult,
+    pub client_payments_received: usize,
+    pub client_payments_received_observed: usize,
+    // Payments to a client whose top candidate was that client
+    pub client_recipients_identified: usize,
+    pub client_payments_sent: usize,
+    // Payments from a client whose first hop was the adversary's, which names the sender
+    pub client_senders_exposed: usize,
+}
+
+impl LspScenario {
+    pub fn compute(label: &str,
+                   nodes: &[String],
+                   clients: &LspClients,
+                   records: &[PaymentRecord],
+                   results: &HashMap<String, Vec<PotentialRecipient>>) -> Self {
+        let members: HashSet<&String> = nodes.iter().collect();
+        let mut scenario = LspScenario {
+            overall: CoalitionResult::compute(label, nodes, records, results),
+            client_payments_received: 0,
+            client_payments_received_observed: 0,
+            client_recipients_identified: 0,
+            client_payments_sent: 0,
+            client_senders_exposed: 0,
+        };
+        for record in records.iter().filter(|r| r.path.len() >= 2 && !r.cover) {
+            if clients.is_client(&record.sender) {
+                scenario.client_payments_sent += 1;
+                if members.contains(&record.path[1]) {
+                    scenario.client_senders_exposed += 1;
+                }
+            }
+            if !clients.is_client(&record.receiver) {
+                continue;
+            }
+            scenario.client_payments_received += 1;
+            if !record.path.iter().any(|node| members.contains(node)) {
+                continue;
+            }
+            scenario.client_payments_received_observed += 1;
+            let top = results.get(&record.payment_hash)
+                .and_then(|candidates| rank_candidates(candidates).first().map(|(node, _)| node.to_string()));
+            if top.as_ref() == Some(&record.receiver) {
+                scenario.client_recipients_identified += 1;
+            }
+        }
+        scenario
+    }
+
+    pub fn client_identification_rate(&self) -> f64 {
+        rate(self.client_recipients_identified, self.client_payments_received)
+    }
+
+    pub fn client_sender_exposure_rate(&self) -> f64 {
+        rate(self.client_senders_exposed, self.client_payments_sent)
+    }
+}
+
+pub struct LspComparison {
+    pub clients: usize,
+    pub lsps: usize,
+    pub jit_clients: usize,
+    pub jit_channels_opened: usize,
+    // Malicious LSPs first, then the adversaries they are compared with
+    pub scenarios: Vec<LspScenario>,
+}
+
+impl LspComparison {
+    pub fn new(clients: &LspClients, jit_channels_opened: usize) -> Self {
+        LspComparison {
+            clients: clients.clients.len(),
+            lsps: clients.lsps.len(),
+            jit_clients: clients.jit_clients.len(),
+            jit_channels_opened,
+            scenarios: Vec::new(),
+        }
+    }
+
+    pub fn add_scenario(&mut self, scenario: LspScenario) {
+        self.scenarios.push(scenario);
+    }
+
+    pub fn generate_text_report(&self) -> String {
+        let mut report = String::from("## THELMA: LSP Clients\n\n");
+        report.push_str(&format!("{} wallet clients behind {} LSPs; {} of them on just-in-time channels, {} opened during the run\n\n",
+                                 self.clients, self.lsps, self.jit_clients, self.jit_channels_opened));
+        report.push_str("| Adversary | Nodes | Observed | Identified | Client payments seen | Client recipients identified | Client senders exposed |\n");
+        report.push_str("|---|---|---|---|---|---|---|\n");
+        for scenario in &self.scenarios {
+            report.push_str(&format!("| {} | {} | {:.1}% | {:.1}% | {}/{} | {} ({:.1}%) | {}/{} ({:.1}%) |\n",
+                                     scenario.overall.label,
+                                     scenario.overall.nodes.len(),
+                                     scenario.overall.observation_rate() * 100.0,
+                                     scenario.overall.identification_rate() * 100.0,
+                                     scenario.client_payments_received_observed,
+                                     scenario.client_payments_received,
+                                     scenario.client_recipients_identified,
+                                     scenario.client_identification_rate() * 100.0,
+                                     scenario.client_senders_exposed,
+                                     scenario.client_payments_sent,
+                                     scenario.client_sender_exposure_rate() * 100.0));
+        }
+
+        if let (Some(lsps), Some(random)) = (self.scenarios.first(), self.scenarios.get(1)) {
+            report.push('\n');
+            if random.client_recipients_identified > 0 {
+                report.push_str(&format!("Malicious LSPs identified {:.1}x as many client recipients as {}.\n",
+                                         lsps.client_recipients_identified as f64 / random.client_recipients_identified as f64,
+                                         random.overall.label.to_lowercase()));
+            } else {
+                report.push_str(&format!("Malicious LSPs identified {} client recipients; {} identified none.\n",
+                                         lsps.client_recipients_identified, random.overall.label.to_lowercase()));
+            }
+        }
+        report
+    }
+
+    pub fn generate_json_report(&self) -> String {
+        let scenarios: Vec<serde_json::Value> = self.scenarios.iter()
+            .map(|scenario| serde_json::json!({
+                "label": scenario.overall.label,
+                "nodes": scenario.overall.nodes,
+                "observation_rate": scenario.overall.observation_rate(),
+                "identification_rate": scenario.overall.identification_rate(),
+                "client_payments_received": scenario.client_payments_received,
+                "client_payments_received_observed": scenario.client_payments_received_observed,
+                "client_recipients_identified": scenario.client_recipients_identified,
+                "client_identification_rate": scenario.client_identification_rate(),
+                "client_payments_sent": scenario.client_payments_sent,
+                "client_senders_exposed": scenario.client_senders_exposed,
+                "client_sender_exposure_rate": scenario.client_sender_exposure_rate(),
+            }))
+            .collect();
+        serde_json::to_string_pretty(&serde_json::json!({
+            "clients": self.clients,
+            "lsps": self.lsps,
+            "jit_clients": self.jit_clients,
+            "jit_channels_opened": self.jit_channels_opened,
+            "scenarios": scenarios,
+        })).unwrap_or_else(|_| "Error generating JSON report".to_string())
+    }
+
+    pub fn save_report_to_file(&self, filename: &str) -> Result<(), ThelmaError> {
+        let mut file = File::create(filename)?;
+        file.write_all(self.generate_text_report().as_bytes())?;
+
+        info!("LSP client comparison saved to {}", filename);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{candidates, record};
+
+    fn guess(recipient: &str) -> Vec<PotentialRecipient> {
+        candidates(&[(recipient, 1.0)])
+    }
+
+    #[test]
+    fn test_lsps_see_their_clients_payments() {
+        let clients = LspClients {
+            lsps: vec!["lsp".to_string()],
+            clients: vec![("c1".to_string(), "lsp".to_string()), ("c2".to_string(), "lsp".to_string())],
+            jit_clients: HashMap::new(),
+        };
+        let records = vec![
+            record("h1", &["a", "x", "lsp", "c1"]),
+            record("h2", &["c2", "lsp", "y", "b"]),
+            record("h3", &["a", "x", "b"]),
+        ];
+        let lsp_results = HashMap::from([("h1".to_string(), guess("c1")), ("h2".to_string(), guess("y"))]);
+        let random_results = HashMap::from([("h1".to_string(), guess("lsp")), ("h3".to_string(), guess("b"))]);
+
+        let mut comparison = LspComparison::new(&clients, 0);
+        comparison.add_scenario(LspScenario::compute("Malicious LSPs", &clients.lsps, &clients, &records, &lsp_results));
+        comparison.add_scenario(LspScenario::compute("Random placement", &["x".to_string()], &clients, &records, &random_results));
+
+        let lsps = &comparison.scenarios[0];
+        assert_eq!((lsps.client_payments_received_observed, lsps.client_recipients_identified), (1, 1));
+        assert_eq!((lsps.client_payments_sent, lsps.client_senders_exposed), (1, 1));
+        let random = &comparison.scenarios[1];
+        assert_eq!((random.client_payments_received_observed, random.client_recipients_identified), (1, 0));
+        assert_eq!(random.client_senders_exposed, 0);
+        assert!(comparison.generate_text_report().contains("random placement identified none"));
+    }
+}
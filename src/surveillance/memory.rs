@@ -0,0 +1,56 @@
+// Memory a run took: the channel graph, the largest route enumeration any one observation
+// needed, and the observations held at once. Estimates from sizes and capacities, without
+// allocator overhead, so large runs can be sized before they're started.
+
+// Peak memory of each part of the analysis, in bytes
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MemoryUsage {
+    pub graph_bytes: usize,
+    // Enumerated routes of one observation, as stored and as pubkeys
+    pub peak_route_bytes: usize,
+    // Observations held in memory rather than spilled
+    pub peak_observation_bytes: usize,
+    // Bytes one enumeration was allowed, when budgeted
+    pub budget: Option<usize>,
+    // Observations sampled because enumerating their routes would have exceeded the budget
+    pub sampled_observations: usize,
+}
+
+impl MemoryUsage {
+    pub fn generate_text_section(&self) -> String {
+        let mut section = String::from("### Memory Usage\n\n");
+        section.push_str("| Part | Peak |\n|---|---|\n");
+        section.push_str(&format!("| Channel graph | {} |\n", format_bytes(self.graph_bytes)));
+        section.push_str(&format!("| Route enumeration (one observation) | {} |\n", format_bytes(self.peak_route_bytes)));
+        section.push_str(&format!("| Observations in memory | {} |\n\n", format_bytes(self.peak_observation_bytes)));
+        if let Some(budget) = self.budget {
+            section.push_str(&format!("Enumeration budget: {}; {} observations were sampled instead.\n\n",
+                                      format_bytes(budget), self.sampled_observations));
+        }
+        section
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "graph_bytes": self.graph_bytes,
+            "peak_route_bytes": self.peak_route_bytes,
+            "peak_observation_bytes": self.peak_observation_bytes,
+            "budget_bytes": self.budget,
+            "sampled_observations": self.sampled_observations,
+        })
+    }
+}
+
+pub fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
@@ -0,0 +1,149 @@
+// Prometheus metrics for long-running surveillance, served over plain HTTP
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+#[cfg(feature = "native")]
+use std::sync::Arc;
+#[cfg(feature = "native")]
+use log::info;
+#[cfg(feature = "native")]
+use tokio::net::TcpListener;
+#[cfg(feature = "native")]
+use tokio::task::JoinHandle;
+
+#[cfg(feature = "native")]
+use crate::http::{self, Request, Response};
+#[cfg(feature = "native")]
+use crate::error::ThelmaError;
+
+// Upper bounds (seconds) of the analysis latency histogram buckets
+const LATENCY_BUCKETS: [f64; 8] = [0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+// Counters a surveillance operation updates as it ingests and analyzes observations.
+// Shared with the metrics endpoint, so everything is atomic.
+#[derive(Default)]
+pub struct SurveillanceMetrics {
+    observations_ingested: AtomicU64,
+    payments_analyzed: AtomicU64,
+    candidate_sets: AtomicU64,
+    // Cumulative counts per latency bucket, plus the +Inf bucket
+    latency_buckets: [AtomicU64; LATENCY_BUCKETS.len() + 1],
+    latency_sum_nanos: AtomicU64,
+}
+
+impl SurveillanceMetrics {
+    pub fn new() -> Self {
+        SurveillanceMetrics::default()
+    }
+
+    pub fn observation_ingested(&self) {
+        self.observations_ingested.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // A payment seen for the first time
+    pub fn payment_analyzed(&self) {
+        self.payments_analyzed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // An observation that left its payment with at least one candidate
+    pub fn candidate_set_produced(&self) {
+        self.candidate_sets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_latency(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        for (i, bound) in LATENCY_BUCKETS.iter().enumerate() {
+            if seconds <= *bound {
+                self.latency_buckets[i].fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.latency_buckets[LATENCY_BUCKETS.len()].fetch_add(1, Ordering::Relaxed);
+        self.latency_sum_nanos.fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    // Current values in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        let counters = [
+            ("thelma_observations_ingested_total", "HTLC observations recorded by malicious nodes",
+             &self.observations_ingested),
+            ("thelma_payments_analyzed_total", "Distinct payments the live analysis has seen", &self.payments_analyzed),
+            ("thelma_candidate_sets_total", "Observations after which their payment had candidates",
+             &self.candidate_sets),
+        ];
+        for (name, help, value) in counters {
+            text.push_str(&format!("# HELP {} {}\n# TYPE {} counter\n{} {}\n",
+                                   name, help, name, name, value.load(Ordering::Relaxed)));
+        }
+
+        let name = "thelma_analysis_latency_seconds";
+        text.push_str(&format!("# HELP {} Time spent analyzing each observation\n# TYPE {} histogram\n",
+                               name, name));
+        for (i, bound) in LATENCY_BUCKETS.iter().enumerate() {
+            text.push_str(&format!("{}_bucket{{le=\"{}\"}} {}\n",
+                                   name, bound, self.latency_buckets[i].load(Ordering::Relaxed)));
+        }
+        let count = self.latency_buckets[LATENCY_BUCKETS.len()].load(Ordering::Relaxed);
+        text.push_str(&format!("{}_bucket{{le=\"+Inf\"}} {}\n", name, count));
+        text.push_str(&format!("{}_sum {}\n", name,
+                               self.latency_sum_nanos.load(Ordering::Relaxed) as f64 / 1e9));
+        text.push_str(&format!("{}_count {}\n", name, count));
+
+        text
+    }
+}
+
+// Share of `total` that `count` makes up, zero when there was nothing to count
+pub fn rate(count: usize, total: usize) -> f64 {
+    if total == 0 { 0.0 } else { count as f64 / total as f64 }
+}
+
+// Answer `GET /metrics` on `addr` until the process exits
+#[cfg(feature = "native")]
+pub async fn serve_metrics(addr: &str, metrics: Arc<SurveillanceMetrics>) -> Result<JoinHandle<()>, ThelmaError> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("Serving metrics on http://{}/metrics", listener.local_addr()?);
+
+    let handler = move |request: Request| match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/metrics") => Response::text(200, "text/plain; version=0.0.4", metrics.render()),
+        _ => Response::not_found(),
+    };
+    // Scrapes carry no body
+    Ok(tokio::spawn(http::serve(listener, 0, Arc::new(handler))))
+}
+
+// Serving needs the native runtime's networking
+#[cfg(all(test, feature = "native"))]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_metrics_endpoint_serves_counters() {
+        let metrics = Arc::new(SurveillanceMetrics::new());
+        metrics.observation_ingested();
+        metrics.observation_ingested();
+        metrics.payment_analyzed();
+        metrics.candidate_set_produced();
+        metrics.record_latency(Duration::from_millis(20));
+
+        let text = metrics.render();
+        assert!(text.contains("thelma_observations_ingested_total 2\n"));
+        assert!(text.contains("thelma_analysis_latency_seconds_bucket{le=\"0.01\"} 0\n"));
+        assert!(text.contains("thelma_analysis_latency_seconds_bucket{le=\"0.05\"} 1\n"));
+        assert!(text.contains("thelma_analysis_latency_seconds_count 1\n"));
+
+        // Find a free port to serve on
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        drop(listener);
+        serve_metrics(&addr, metrics).await.unwrap();
+
+        let mut stream = tokio::net::TcpStream::connect(&addr).await.unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("thelma_payments_analyzed_total 1\n"));
+    }
+}
@@ -1,7 +1,67 @@
 pub mod analyzer;
 pub mod reporter;
 pub mod operation;
+pub mod economics;
+pub mod observation_store;
+pub mod incremental;
+pub mod strategy;
+pub mod config;
+pub mod scorer;
+pub mod metrics;
+pub mod placement;
+pub mod cytoscape;
+pub mod position;
+pub mod probes;
+pub mod fingerprint;
+pub mod freshness;
+pub mod channel_age;
+pub mod settlement;
+pub mod uptime;
+pub mod coalition;
+pub mod sharing;
+pub mod exposure;
+pub mod false_positives;
+pub mod calibration;
+pub mod accuracy;
+pub mod label_accuracy;
+pub mod lsp_exposure;
+pub mod tor_timing;
+pub mod geography;
+pub mod memory;
+pub mod plausibility;
+pub mod hierarchy;
+pub mod template;
 
 pub use analyzer::*;
 pub use reporter::*;
-pub use operation::*;
\ No newline at end of file
+pub use operation::*;
+pub use economics::*;
+pub use observation_store::*;
+pub use incremental::*;
+pub use strategy::*;
+pub use config::*;
+pub use scorer::*;
+pub use metrics::*;
+pub use placement::*;
+pub use cytoscape::*;
+pub use position::*;
+pub use probes::*;
+pub use fingerprint::*;
+pub use freshness::*;
+pub use channel_age::*;
+pub use settlement::*;
+pub use uptime::*;
+pub use coalition::*;
+pub use sharing::*;
+pub use exposure::*;
+pub use false_positives::*;
+pub use calibration::*;
+pub use accuracy::*;
+pub use label_accuracy::*;
+pub use lsp_exposure::*;
+pub use tor_timing::*;
+pub use geography::*;
+pub use memory::*;
+pub use plausibility::*;
+pub use hierarchy::*;
+pub use template::*;
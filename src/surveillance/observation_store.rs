@@ -0,0 +1,295 @@
+// Storage for HTLC observations that can spill to disk on long runs
+
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use log::info;
+
+use crate::models::{HopPayload, HTLC};
+use crate::error::ThelmaError;
+
+// Spill files to split observations into; each is analyzed on its own
+pub const DEFAULT_SPILL_PARTITIONS: usize = 16;
+
+// Observations recorded by the surveillance operation. Kept in memory by default; once
+// spilling is enabled, anything beyond the memory limit is appended to JSON-lines files
+// on disk. Spilled observations are partitioned by payment hash, so every batch handed
+// out holds all observations of the payments in it.
+pub struct ObservationStore {
+    buffer: Vec<HTLC>,
+    spill: Option<SpillFiles>,
+    len: usize,
+    // Estimated bytes of the observations held in memory, now and at most
+    buffer_bytes: usize,
+    peak_bytes: usize,
+}
+
+struct SpillFiles {
+    dir: PathBuf,
+    memory_limit: usize,
+    writers: Vec<BufWriter<File>>,
+}
+
+impl ObservationStore {
+    pub fn in_memory() -> Self {
+        ObservationStore {
+            buffer: Vec::new(),
+            spill: None,
+            len: 0,
+            buffer_bytes: 0,
+            peak_bytes: 0,
+        }
+    }
+
+    // Keep at most `memory_limit` observations in memory and spill the rest into
+    // `partitions` files under `dir`. Observations already held move along.
+    pub fn spill_to(&mut self, dir: &Path, memory_limit: usize, partitions: usize) -> Result<(), ThelmaError> {
+        // Bring back anything spilled to an earlier directory
+        if let Some(old) = &self.spill {
+            for partition in 0..old.writers.len() {
+                let spilled = read_partition(&partition_path(&old.dir, partition))?;
+                self.buffer_bytes += spilled.iter().map(observation_bytes).sum::<usize>();
+                self.buffer.extend(spilled);
+            }
+        }
+        if let Some(old) = self.spill.take() {
+            old.remove_files();
+        }
+
+        fs::create_dir_all(dir)?;
+        let mut writers = Vec::new();
+        for partition in 0..partitions.max(1) {
+            let file = File::create(partition_path(dir, partition))?;
+            writers.push(BufWriter::new(file));
+        }
+
+        self.spill = Some(SpillFiles {
+            dir: dir.to_path_buf(),
+            memory_limit: memory_limit.max(1),
+            writers,
+        });
+
+        self.spill_if_full()
+    }
+
+    pub fn push(&mut self, htlc: HTLC) -> Result<(), ThelmaError> {
+        self.buffer_bytes += observation_bytes(&htlc);
+        self.peak_bytes = self.peak_bytes.max(self.buffer_bytes);
+        self.buffer.push(htlc);
+        self.len += 1;
+        self.spill_if_full()
+    }
+
+    fn spill_if_full(&mut self) -> Result<(), ThelmaError> {
+        let Some(spill) = &mut self.spill else { return Ok(()) };
+        if self.buffer.len() < spill.memory_limit {
+            return Ok(());
+        }
+
+        info!("Spilling {} observations to {}", self.buffer.len(), spill.dir.display());
+        for htlc in self.buffer.drain(..) {
+            let partition = partition_of(&htlc.payment_hash, spill.writers.len());
+            writeln!(spill.writers[partition], "{}", htlc_to_json(&htlc))?;
+        }
+        // Flush now so batches can be read back through a shared reference
+        for writer in &mut spill.writers {
+            writer.flush()?;
+        }
+        self.buffer_bytes = 0;
+
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Most bytes the observations held in memory have taken at once
+    pub fn peak_memory_bytes(&self) -> usize {
+        self.peak_bytes
+    }
+
+    // All observations, one batch per partition. Payments never straddle batches.
+    pub fn payment_batches(&self) -> impl Iterator<Item = Result<Vec<HTLC>, ThelmaError>> + '_ {
+        let partitions = self.spill.as_ref().map_or(1, |spill| spill.writers.len());
+
+        (0..partitions).map(move |partition| {
+            let mut batch = match &self.spill {
+                Some(spill) => read_partition(&partition_path(&spill.dir, partition))?,
+                None => Vec::new(),
+            };
+            batch.extend(self.buffer.iter()
+                .filter(|htlc| partition_of(&htlc.payment_hash, partitions) == partition)
+                .cloned());
+            Ok(batch)
+        })
+    }
+
+    // Drop every observation, including spilled ones
+    pub fn clear(&mut self) -> Result<(), ThelmaError> {
+        self.buffer.clear();
+        self.len = 0;
+        self.buffer_bytes = 0;
+
+        if let Some(spill) = &mut self.spill {
+            for (partition, writer) in spill.writers.iter_mut().enumerate() {
+                *writer = BufWriter::new(File::create(partition_path(&spill.dir, partition))?);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Rough size of an observation held in memory, strings included
+fn observation_bytes(htlc: &HTLC) -> usize {
+    let payload = htlc.payload.as_ref().map_or(0, |payload| {
+        payload.short_channel_id.as_ref().map_or(0, String::capacity)
+            + payload.trampoline_destination.as_ref().map_or(0, String::capacity)
+    });
+    std::mem::size_of::<HTLC>() + htlc.payment_hash.capacity() + htlc.observed_by_node.capacity()
+        + htlc.incoming_channel.as_ref().map_or(0, String::capacity) + payload
+}
+
+impl Default for ObservationStore {
+    fn default() -> Self {
+        ObservationStore::in_memory()
+    }
+}
+
+impl SpillFiles {
+    fn remove_files(self) {
+        for partition in 0..self.writers.len() {
+            let _ = fs::remove_file(partition_path(&self.dir, partition));
+        }
+    }
+}
+
+impl Drop for ObservationStore {
+    fn drop(&mut self) {
+        if let Some(spill) = self.spill.take() {
+            spill.remove_files();
+        }
+    }
+}
+
+fn partition_path(dir: &Path, partition: usize) -> PathBuf {
+    dir.join(format!("observations-{}.jsonl", partition))
+}
+
+fn partition_of(payment_hash: &str, partitions: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    payment_hash.hash(&mut hasher);
+    (hasher.finish() % partitions as u64) as usize
+}
+
+fn read_partition(path: &Path) -> Result<Vec<HTLC>, ThelmaError> {
+    let mut batch = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        batch.push(htlc_from_json(&serde_json::from_str(&line?)?)?);
+    }
+    Ok(batch)
+}
+
+pub(crate) fn htlc_to_json(htlc: &HTLC) -> serde_json::Value {
+    let mut value = serde_json::json!({
+        "payment_hash": htlc.payment_hash,
+        "cltv_expiry": htlc.cltv_expiry,
+        "amount": htlc.amount,
+        "observed_at_block": htlc.observed_at_block,
+        "observed_by_node": htlc.observed_by_node,
+    });
+    if let Some(payload) = &htlc.payload {
+        value["incoming_channel"] = serde_json::json!(htlc.incoming_channel);
+        value["payload"] = serde_json::json!({
+            "amt_to_forward": payload.amt_to_forward,
+            "outgoing_cltv_value": payload.outgoing_cltv_value,
+            "short_channel_id": payload.short_channel_id,
+        });
+        if let Some(destination) = &payload.trampoline_destination {
+            value["payload"]["trampoline_destination"] = serde_json::json!(destination);
+        }
+    }
+    value
+}
+
+pub(crate) fn htlc_from_json(value: &serde_json::Value) -> Result<HTLC, ThelmaError> {
+    // An observation missing a field means the file was corrupted
+    let missing = |field: &str| io::Error::new(io::ErrorKind::InvalidData, format!("observation is missing {}", field));
+    let text = |field: &str| value[field].as_str().ok_or_else(|| missing(field));
+    let number = |field: &str| value[field].as_u64().ok_or_else(|| missing(field));
+
+    let htlc = HTLC::new(
+        text("payment_hash")?,
+        number("cltv_expiry")? as u32,
+        number("amount")?,
+        number("observed_at_block")? as u32,
+        text("observed_by_node")?,
+    );
+
+    // The onion is optional, but one that's there must be whole
+    let payload = &value["payload"];
+    if payload.is_null() {
+        return Ok(htlc);
+    }
+    let field = |name: &str| payload[name].as_u64().ok_or_else(|| missing(&format!("payload.{}", name)));
+    let payload = HopPayload {
+        amt_to_forward: field("amt_to_forward")?,
+        outgoing_cltv_value: field("outgoing_cltv_value")? as u32,
+        short_channel_id: payload["short_channel_id"].as_str().map(str::to_string),
+        trampoline_destination: payload["trampoline_destination"].as_str().map(str::to_string),
+    };
+    Ok(htlc.with_onion(value["incoming_channel"].as_str(), payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spilled_batches_keep_payments_together() {
+        let dir = std::env::temp_dir().join(format!("thelma-store-test-{}", std::process::id()));
+        let mut store = ObservationStore::in_memory();
+        store.spill_to(&dir, 5, 4).unwrap();
+
+        // Two observations of each of 20 payments, well past the memory limit
+        for i in 0..20 {
+            for (observer, expiry) in [("a", 700100), ("b", 700060)] {
+                store.push(HTLC::new(&format!("hash{}", i), expiry, 1000, 700000, observer)).unwrap();
+            }
+        }
+        assert_eq!(store.len(), 40);
+
+        let batches: Vec<Vec<HTLC>> = store.payment_batches().map(|b| b.unwrap()).collect();
+        assert_eq!(batches.iter().map(Vec::len).sum::<usize>(), 40);
+        for batch in &batches {
+            for htlc in batch {
+                assert_eq!(batch.iter().filter(|h| h.payment_hash == htlc.payment_hash).count(), 2);
+            }
+        }
+
+        drop(store);
+        assert!(!partition_path(&dir, 0).exists());
+        let _ = fs::remove_dir(&dir);
+    }
+
+    #[test]
+    fn test_onion_survives_json() {
+        let htlc = HTLC::new("hash", 700100, 1000, 700000, "b")
+            .with_onion(Some("ab"), HopPayload::forward("bc", 990, 700080));
+        let restored = htlc_from_json(&htlc_to_json(&htlc)).unwrap();
+        assert_eq!(restored.incoming_channel.as_deref(), Some("ab"));
+        assert_eq!(restored.payload, htlc.payload);
+
+        // Observations from before payloads were recorded still load, without one
+        let mut value = htlc_to_json(&htlc);
+        value.as_object_mut().unwrap().retain(|key, _| key != "payload" && key != "incoming_channel");
+        assert!(htlc_from_json(&value).unwrap().payload.is_none());
+    }
+}
@@ -1,67 +1,260 @@
 // Core surveillance operation logic
 
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+use log::{debug, info, warn};
+use tracing::info_span;
 
-use crate::models::{HTLC, LightningNetworkMap};
-use crate::surveillance::analyzer::{HTLCAnalyzer, PotentialRecipient};
-use crate::surveillance::reporter::SurveillanceReporter;
+use crate::models::{HTLC, HtlcResolution, LightningNetworkMap};
+use crate::graph::Communities;
+use crate::simulation::Observer;
+use crate::surveillance::analyzer::{HTLCAnalyzer, PotentialRecipient, CandidateCommunity};
+use crate::surveillance::reporter::{TrafficInferences, SurveillanceReporter};
+use crate::surveillance::template::ReportTemplate;
+use crate::surveillance::probes::{ProbeDetector, ProbeSignal};
+use crate::surveillance::position::ObserverPosition;
+use crate::surveillance::fingerprint::{FingerprintLinker, LinkingAccuracy};
+use crate::surveillance::freshness::{FreshnessHeuristic, GossipLog};
+use crate::simulation::PaymentRecord;
+use crate::surveillance::observation_store::ObservationStore;
+use crate::surveillance::incremental::IncrementalAnalyzer;
+use crate::surveillance::metrics::SurveillanceMetrics;
+use crate::surveillance::uptime::ObservationWindows;
+use crate::surveillance::exposure::{rank_exposed_nodes, DEFAULT_EXPOSED_NODES};
+use crate::surveillance::false_positives::FalsePositiveAnalysis;
+use crate::surveillance::calibration::{CalibrationCurve, DEFAULT_CALIBRATION_BUCKETS};
+use crate::surveillance::accuracy::{AccuracyThresholds, IdentificationAccuracy};
+use crate::surveillance::label_accuracy::LabelAccuracy;
+use crate::surveillance::tor_timing::TorTimingAnalysis;
+use crate::surveillance::geography::GeographyInference;
+use crate::surveillance::memory::MemoryUsage;
+use crate::simulation::RegionLatency;
+use crate::surveillance::sharing::ObservationSharing;
+use crate::surveillance::strategy::AdversaryStrategy;
+use crate::surveillance::config::{AdversaryModel, SurveillanceConfig};
+use crate::error::{ThelmaError, read_lock, write_lock};
 
 // Structure for our malicious surveillance operation
 pub struct SurveillanceOperation {
-    network: Arc<Mutex<LightningNetworkMap>>,
+    network: Arc<RwLock<LightningNetworkMap>>,
     malicious_nodes: Vec<String>,
-    observed_htlcs: Vec<HTLC>,
+    observed_htlcs: ObservationStore,
     analyzer: HTLCAnalyzer,
     reporter: SurveillanceReporter,
+    // Communities used to report cluster-level candidates, with the individual
+    // confidence share below which they kick in
+    community_inference: Option<(Communities, f32)>,
+    // Candidates refined as observations arrive, when live analysis is on
+    live: Option<IncrementalAnalyzer>,
+    // Attacker logic driving node selection, capital allocation and probing, if any
+    strategy: Option<Box<dyn AdversaryStrategy>>,
+    // Counters exported to the metrics endpoint, if one is running
+    metrics: Option<Arc<SurveillanceMetrics>>,
+    // Recognizes probes to leave out of the analysis, when excluding them
+    probe_detector: Option<ProbeDetector>,
+    // Regroups observations into payments by fingerprint, when hashes can't link them
+    fingerprint_linker: Option<FingerprintLinker>,
+    // When our nodes are online to observe, if not always
+    windows: Option<ObservationWindows>,
+    // When observations reach the node the analysis runs at, if not at once
+    sharing: Option<ObservationSharing>,
+    // Cut-offs identification rates are reported at
+    accuracy_thresholds: AccuracyThresholds,
+    // Latency between regions, when recipients' regions are guessed from timing
+    regions: Option<RegionLatency>,
 }
 
 impl SurveillanceOperation {
-    pub fn new(network: Arc<Mutex<LightningNetworkMap>>, malicious_nodes: Vec<String>) -> Self {
-        SurveillanceOperation {
-            analyzer: HTLCAnalyzer::new(network.clone()),
+    // Set up the operation, letting the adversary model pick its nodes
+    pub fn new(network: Arc<RwLock<LightningNetworkMap>>, config: SurveillanceConfig) -> Result<Self, ThelmaError> {
+        let (malicious_nodes, strategy) = match config.adversary {
+            AdversaryModel::Nodes(nodes) => (nodes, None),
+            AdversaryModel::Strategy { mut strategy, count } => {
+                let nodes = strategy.select_nodes(&read_lock(&network), count);
+                info!("Adversary strategy '{}' compromised {} nodes", strategy.name(), nodes.len());
+                (nodes, Some(strategy))
+            }
+        };
+
+        let mut analyzer = HTLCAnalyzer::new(network.clone());
+        analyzer.set_analysis_mode(config.analysis_mode);
+        analyzer.set_max_cltv_expiry(config.max_cltv_expiry);
+        analyzer.set_cltv_construction(config.cltv_construction);
+        analyzer.set_route_cache_capacity(config.route_cache_capacity);
+        analyzer.set_scorer(config.scorer);
+        if let Some(bytes) = config.memory_budget {
+            analyzer.set_memory_budget(bytes);
+        }
+        if let Some(hops) = config.max_search_hops {
+            analyzer.set_max_search_hops(hops);
+        }
+        if let Some(budget) = config.time_budget {
+            analyzer.set_time_budget(budget);
+        }
+        if let Some(profile) = config.recipient_profile {
+            analyzer.set_recipient_profile(profile);
+        }
+        if let Some(cap) = config.sender_cltv_cap {
+            analyzer.set_sender_cltv_cap(cap);
+        }
+        if let Some((penalty, stale_after_blocks)) = config.stale_channel_penalty {
+            let log = GossipLog::of(&read_lock(&network));
+            analyzer.set_freshness(FreshnessHeuristic::new(penalty, log).stale_after_blocks(stale_after_blocks));
+        }
+        if let Some(prior) = config.channel_age_prior {
+            analyzer.set_channel_age_prior(prior);
+        }
+        if let Some(timing) = config.settlement_timing {
+            analyzer.set_settlement_timing(timing);
+        }
+
+        let sharing = config.observation_sharing.map(|sharing| match (&sharing.hub, malicious_nodes.first()) {
+            (None, Some(first)) => sharing.hub(first),
+            _ => sharing,
+        });
+
+        let mut observed_htlcs = ObservationStore::in_memory();
+        if let Some((dir, memory_limit, partitions)) = &config.spill {
+            observed_htlcs.spill_to(dir, *memory_limit, *partitions)?;
+        }
+
+        Ok(SurveillanceOperation {
+            analyzer,
             reporter: SurveillanceReporter::new(network.clone()),
             network,
             malicious_nodes,
-            observed_htlcs: Vec::new(),
+            observed_htlcs,
+            community_inference: config.community_inference,
+            live: config.live_analysis.then(IncrementalAnalyzer::new),
+            strategy,
+            metrics: None,
+            probe_detector: config.probe_detector,
+            fingerprint_linker: config.fingerprint_linker,
+            windows: config.observation_windows,
+            sharing,
+            accuracy_thresholds: config.accuracy_thresholds,
+            regions: config.regions,
+        })
+    }
+
+    // Spend fresh capital on channels from our nodes as the strategy sees fit. Returns the
+    // number of channels opened.
+    pub fn invest(&mut self, capital_sat: u64) -> usize {
+        let Some(strategy) = &mut self.strategy else { return 0 };
+        let mut network = write_lock(&self.network);
+        let channels = strategy.allocate_budget(&network, &self.malicious_nodes, capital_sat);
+        let opened = channels.len();
+
+        for channel in channels {
+            debug!("Opening channel {} ({} sat)", channel.channel_id, channel.capacity);
+            network.add_channel(channel);
         }
+
+        opened
     }
 
     // Register malicious nodes for surveillance
     pub fn register_malicious_node(&mut self, node_id: &str) {
         if !self.malicious_nodes.contains(&node_id.to_string()) {
-            println!("Registering node {} for surveillance", node_id);
+            debug!("Registering node {} for surveillance", node_id);
             self.malicious_nodes.push(node_id.to_string());
         }
     }
 
+    // Keep these counters up to date as observations come in
+    pub fn set_metrics(&mut self, metrics: Arc<SurveillanceMetrics>) {
+        self.metrics = Some(metrics);
+    }
+
     // Get list of malicious nodes
     pub fn get_malicious_nodes(&self) -> &[String] {
         &self.malicious_nodes
     }
 
     // Record an HTLC observation from one of our malicious nodes
-    pub fn record_htlc_observation(&mut self, htlc: HTLC) {
+    pub fn record_htlc_observation(&mut self, mut htlc: HTLC) -> Result<(), ThelmaError> {
         // Make sure it's from one of our nodes
         if self.malicious_nodes.contains(&htlc.observed_by_node) {
-            println!("Malicious node {} observed HTLC: payment_hash={}, cltv={}, amount={}",
-                     htlc.observed_by_node, htlc.payment_hash, htlc.cltv_expiry, htlc.amount);
-            self.observed_htlcs.push(htlc);
+            if let Some(linker) = &mut self.fingerprint_linker {
+                linker.link(&read_lock(&self.network), &mut htlc);
+            }
+            debug!("Malicious node {} observed HTLC: payment_hash={}, cltv={}, amount={}",
+                   htlc.observed_by_node, htlc.payment_hash, htlc.cltv_expiry, htlc.amount);
+            if let Some(sharing) = &mut self.sharing {
+                sharing.record(&htlc);
+            }
+            self.analyzer.record_sink(&htlc);
+            if let Some(metrics) = &self.metrics {
+                metrics.observation_ingested();
+            }
+            if let Some(live) = &mut self.live {
+                let payment_hash = htlc.payment_hash.clone();
+                let first_seen = live.candidates(&payment_hash).is_none();
+                let started = Instant::now();
+                let candidates = live.observe(&self.analyzer, htlc.clone());
+                if let Some(metrics) = &self.metrics {
+                    metrics.record_latency(started.elapsed());
+                    if first_seen {
+                        metrics.payment_analyzed();
+                    }
+                    if !candidates.is_empty() {
+                        metrics.candidate_set_produced();
+                    }
+                }
+                match candidates.first() {
+                    Some(top) => debug!("Live: payment {} has {} candidates, top {} ({:.2})",
+                                        payment_hash, candidates.len(), top.node_id, top.confidence_score),
+                    None => debug!("Live: payment {} has no candidates yet", payment_hash),
+                }
+            }
+            self.observed_htlcs.push(htlc)?;
+
+            let observations = self.observed_htlcs.len();
+            if self.strategy.as_mut().is_some_and(|strategy| strategy.should_probe(observations)) {
+                let results = self.run_analysis();
+                info!("Probe after {} observations: candidates for {} payments", observations, results.len());
+            }
         } else {
-            println!("Ignoring HTLC from non-malicious node {}", htlc.observed_by_node);
+            debug!("Ignoring HTLC from non-malicious node {}", htlc.observed_by_node);
         }
+        Ok(())
     }
 
     // Record multiple HTLC observations at once
-    pub fn record_multiple_observations(&mut self, htlcs: Vec<HTLC>) {
+    pub fn record_multiple_observations(&mut self, htlcs: Vec<HTLC>) -> Result<(), ThelmaError> {
         for htlc in htlcs {
-            self.record_htlc_observation(htlc);
+            self.record_htlc_observation(htlc)?;
         }
+        Ok(())
+    }
+
+    // Current live candidates for a payment, best first; `None` without live analysis or
+    // before the payment was observed
+    pub fn live_candidates(&self, payment_hash: &str) -> Option<&[PotentialRecipient]> {
+        self.live.as_ref()?.candidates(payment_hash)
+    }
+
+    // Whether `live_candidates` has anything to offer
+    pub fn live_candidates_enabled(&self) -> bool {
+        self.live.is_some()
+    }
+
+    // Number of HTLC observations recorded so far
+    pub fn observation_count(&self) -> usize {
+        self.observed_htlcs.len()
     }
 
-    // Get all recorded HTLC observations
-    pub fn get_observations(&self) -> &[HTLC] {
-        &self.observed_htlcs
+    // Recorded observations in batches that each hold whole payments. Batches that can't
+    // be read back from disk are reported and skipped.
+    pub fn observation_batches(&self) -> impl Iterator<Item = Vec<HTLC>> + '_ {
+        self.observed_htlcs.payment_batches().filter_map(|batch| match batch {
+            Ok(batch) => Some(batch),
+            Err(e) => {
+                warn!("Failed to read spilled observations: {}", e);
+                None
+            }
+        })
     }
 
     // Analyze a specific HTLC
@@ -69,48 +262,349 @@ impl SurveillanceOperation {
         self.analyzer.analyze_htlc(htlc)
     }
 
+    // Guess the possible senders behind a specific HTLC
+    pub fn backtrack_senders(&self, htlc: &HTLC) -> Vec<String> {
+        self.analyzer.backtrack_potential_senders(htlc)
+    }
+
+    // Payments that look like probes, with what gave each away. Empty unless probes are
+    // being excluded.
+    pub fn detect_probes(&self) -> HashMap<String, Vec<ProbeSignal>> {
+        let Some(detector) = &self.probe_detector else { return HashMap::new() };
+        let mut scan = detector.scan();
+        for batch in self.observation_batches() {
+            for htlc in &batch {
+                scan.add(htlc);
+            }
+        }
+        scan.finish()
+    }
+
     // Run surveillance analysis on all collected data
     pub fn run_analysis(&self) -> HashMap<String, Vec<PotentialRecipient>> {
-        println!("Running surveillance analysis on {} observations", self.observed_htlcs.len());
-        self.analyzer.correlate_observations(&self.observed_htlcs)
+        let _span = info_span!("analysis").entered();
+        let probes = self.detect_probes();
+        if !probes.is_empty() {
+            info!("Leaving {} probable probe payments out of the analysis", probes.len());
+        }
+
+        if let Some(live) = &self.live {
+            info!("Using live analysis of {} payments", live.payment_count());
+            let mut results = live.results();
+            results.retain(|payment_hash, _| !probes.contains_key(payment_hash));
+            return results;
+        }
+
+        info!("Running surveillance analysis on {} observations", self.observed_htlcs.len());
+        if let Some(profile) = self.analyzer.recipient_profile() {
+            info!("Candidates restricted to {} plausible recipients ({})",
+                  profile.admitted(&read_lock(&self.network)), profile.describe());
+        }
+        self.analyze_batches(&probes, |_| true)
+    }
+
+    // Analysis run at the hub by the end of payment `at`, from only the observations shared
+    // by then. Without sharing delays every observation counts.
+    pub fn run_analysis_at(&self, at: u64) -> HashMap<String, Vec<PotentialRecipient>> {
+        let _span = info_span!("analysis_at").entered();
+        self.analyze_batches(&self.detect_probes(), |htlc| {
+            self.sharing.as_ref().is_none_or(|sharing| sharing.available_at(htlc) <= at)
+        })
+    }
+
+    // Every payment analyzed the moment it was made, from what the hub had by then: its own
+    // observations of the payment and earlier traffic, but nothing still on its way
+    pub fn run_realtime_analysis(&self) -> HashMap<String, Vec<PotentialRecipient>> {
+        let Some(sharing) = &self.sharing else { return self.run_analysis_at(u64::MAX) };
+        let _span = info_span!("realtime_analysis").entered();
+        let mut made: HashMap<&str, u64> = HashMap::new();
+        let batches: Vec<Vec<HTLC>> = self.observation_batches().collect();
+        for htlc in batches.iter().flatten() {
+            let at = sharing.made_at(htlc).unwrap_or(0);
+            made.entry(&htlc.payment_hash).and_modify(|first| *first = (*first).min(at)).or_insert(at);
+        }
+        self.analyze_batches(&self.detect_probes(), |htlc| {
+            sharing.available_at(htlc) <= made.get(htlc.payment_hash.as_str()).copied().unwrap_or(0)
+        })
+    }
+
+    // Observations sent between our nodes and what that cost, when sharing is modeled
+    pub fn sharing(&self) -> Option<&ObservationSharing> {
+        self.sharing.as_ref()
+    }
+
+    // Correlate the stored observations `keep` lets through, leaving probes out
+    fn analyze_batches(&self, probes: &HashMap<String, Vec<ProbeSignal>>, keep: impl Fn(&HTLC) -> bool)
+                       -> HashMap<String, Vec<PotentialRecipient>> {
+        self.analyzer.reset_memory_fallbacks();
+        self.analyzer.reset_truncated_searches();
+        self.analyzer.reset_incomplete_payments();
+        self.analyzer.precompute_reachability(&self.malicious_nodes);
+        let mut results = HashMap::new();
+        for mut batch in self.observation_batches() {
+            batch.retain(|htlc| !probes.contains_key(&htlc.payment_hash) && keep(htlc));
+            results.extend(self.analyzer.correlate_observations(&batch));
+        }
+        let sampled = self.analyzer.memory_fallbacks();
+        if sampled > 0 {
+            warn!("Enumerating routes would have exceeded the memory budget for {} observations, \
+                   sampled them instead", sampled);
+        }
+        let truncated = self.analyzer.truncated_searches();
+        if truncated > 0 {
+            warn!("Route searches stopped short of hop counts {} observations likely had left; \
+                   raise --max-hops to find their longer routes", truncated);
+        }
+        let incomplete = self.analyzer.incomplete_payments().len();
+        if incomplete > 0 {
+            warn!("Analysis ran out of time for {} payments, reporting the candidates found so far", incomplete);
+        }
+        results
+    }
+
+    // Summarize low-confidence payments by candidate community, if enabled
+    pub fn run_community_analysis(&self, results: &HashMap<String, Vec<PotentialRecipient>>)
+                                  -> Option<HashMap<String, Vec<CandidateCommunity>>> {
+        let (communities, threshold) = self.community_inference.as_ref()?;
+
+        Some(results.iter()
+            .filter_map(|(payment_hash, recipients)| {
+                HTLCAnalyzer::summarize_by_community(recipients, communities, *threshold)
+                    .map(|summary| (payment_hash.clone(), summary))
+            })
+            .collect())
     }
 
+    // Where on each analyzed payment's route its observers sat
+    pub fn run_position_inference(&self, results: &HashMap<String, Vec<PotentialRecipient>>)
+                                  -> HashMap<String, Vec<ObserverPosition>> {
+        let mut positions = HashMap::new();
+        for batch in self.observation_batches() {
+            let mut by_payment: HashMap<&str, Vec<HTLC>> = HashMap::new();
+            for htlc in &batch {
+                by_payment.entry(htlc.payment_hash.as_str()).or_default().push(htlc.clone());
+            }
+            for (payment_hash, observations) in by_payment {
+                let Some(recipients) = results.get(payment_hash) else { continue };
+                let inferred = self.analyzer.infer_positions(&observations, recipients);
+                if !inferred.is_empty() {
+                    positions.insert(payment_hash.to_string(), inferred);
+                }
+            }
+        }
+        positions
+    }
+
+    // Payments whose observations order differently by amount than by timelock. Only
+    // payments that were analyzed are checked.
+    pub fn run_ordering_check(&self, results: &HashMap<String, Vec<PotentialRecipient>>) -> HashSet<String> {
+        let mut conflicts = HashSet::new();
+        for batch in self.observation_batches() {
+            let mut by_payment: HashMap<&str, Vec<HTLC>> = HashMap::new();
+            for htlc in &batch {
+                by_payment.entry(htlc.payment_hash.as_str()).or_default().push(htlc.clone());
+            }
+            conflicts.extend(by_payment.into_iter()
+                .filter(|(payment_hash, _)| results.contains_key(*payment_hash))
+                .filter(|(_, observations)| {
+                    // A retry travels its own route at a later block, so only the latest
+                    // attempt's observations are ordered against each other
+                    let latest_block = observations.iter().map(|htlc| htlc.observed_at_block).max();
+                    let attempt: Vec<HTLC> = observations.iter()
+                        .filter(|htlc| Some(htlc.observed_at_block) == latest_block)
+                        .cloned()
+                        .collect();
+                    HTLCAnalyzer::orderings_disagree(&attempt)
+                })
+                .map(|(payment_hash, _)| payment_hash.to_string()));
+        }
+        conflicts
+    }
+
+    fn run_traffic_inference(&self, results: &HashMap<String, Vec<PotentialRecipient>>) -> TrafficInferences {
+        let positions = self.run_position_inference(results);
+        TrafficInferences {
+            tor_timing: self.reporter.ground_truth().and_then(|routes| {
+                TorTimingAnalysis::compute(&read_lock(&self.network), self.analyzer.settlement_timing(), &positions, routes)
+            }),
+            geography: self.reporter.ground_truth()
+                .zip(self.analyzer.settlement_timing())
+                .zip(self.regions.as_ref())
+                .and_then(|((routes, timing), regions)| {
+                    GeographyInference::compute(&read_lock(&self.network), timing, regions, routes)
+                }),
+            positions,
+            ordering_conflicts: self.run_ordering_check(results),
+            incomplete: self.analyzer.incomplete_payments().into_iter()
+                .filter(|payment_hash| results.contains_key(payment_hash))
+                .collect(),
+            probes: self.detect_probes(),
+            exposed_nodes: rank_exposed_nodes(results, &self.malicious_nodes, DEFAULT_EXPOSED_NODES),
+            false_positives: self.reporter.ground_truth().and_then(|routes| {
+                FalsePositiveAnalysis::compute(&read_lock(&self.network), results, routes, &self.malicious_nodes)
+            }),
+            calibration: self.reporter.ground_truth()
+                .and_then(|routes| CalibrationCurve::compute(results, routes, DEFAULT_CALIBRATION_BUCKETS)),
+            identification: self.reporter.ground_truth()
+                .and_then(|routes| IdentificationAccuracy::compute(results, routes, &self.accuracy_thresholds)),
+            labels: self.reporter.ground_truth()
+                .and_then(|routes| LabelAccuracy::compute(&read_lock(&self.network), results, routes)),
+            memory: Some(self.memory_usage()),
+        }
+    }
+
+    // Peak memory of the graph, route enumeration and stored observations so far
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            graph_bytes: read_lock(&self.network).memory_bytes(),
+            peak_route_bytes: self.analyzer.peak_route_bytes(),
+            peak_observation_bytes: self.observed_htlcs.peak_memory_bytes(),
+            budget: self.analyzer.memory_budget(),
+            sampled_observations: self.analyzer.memory_fallbacks(),
+        }
+    }
+
+    // The reports below take the results of `run_analysis`, so one analysis serves them all
+
     // Generate a surveillance report
-    pub fn generate_report(&self) -> String {
-        let results = self.run_analysis();
-        self.reporter.generate_text_report(&results)
+    pub fn generate_report(&self, results: &HashMap<String, Vec<PotentialRecipient>>) -> String {
+        let communities = self.run_community_analysis(results);
+        let inferences = self.run_traffic_inference(results);
+        self.reporter.generate_text_report(results, communities.as_ref(), &inferences)
     }
 
     // Save a surveillance report to file
-    pub fn save_report(&self, filename: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let results = self.run_analysis();
-        self.reporter.save_report_to_file(&results, filename)
+    pub fn save_report(&self, results: &HashMap<String, Vec<PotentialRecipient>>, filename: &str) -> Result<(), ThelmaError> {
+        let communities = self.run_community_analysis(results);
+        let inferences = self.run_traffic_inference(results);
+        self.reporter.save_report_to_file(results, communities.as_ref(), &inferences, filename)
     }
 
     // Generate JSON format report
-    pub fn generate_json_report(&self) -> String {
-        let results = self.run_analysis();
-        self.reporter.generate_json_report(&results)
+    pub fn generate_json_report(&self, results: &HashMap<String, Vec<PotentialRecipient>>) -> String {
+        let communities = self.run_community_analysis(results);
+        let inferences = self.run_traffic_inference(results);
+        self.reporter.generate_json_report(results, communities.as_ref(), &inferences)
+    }
+
+    // Render a user-supplied report template with the JSON report as its context
+    pub fn render_template(&self, template: &ReportTemplate,
+                           results: &HashMap<String, Vec<PotentialRecipient>>) -> Result<String, ThelmaError> {
+        let communities = self.run_community_analysis(results);
+        let inferences = self.run_traffic_inference(results);
+        template.render(&self.reporter.to_json(results, communities.as_ref(), &inferences))
+    }
+
+    // Let reports score inferred observer positions against the real routes
+    pub fn set_ground_truth(&mut self, records: &[PaymentRecord]) {
+        self.reporter.set_ground_truth(records);
+    }
+
+    // Score fingerprint linking against the payment each HTLC identifier belonged to, and
+    // include the result in reports. `None` unless observations are linked by fingerprint.
+    pub fn score_linking(&mut self, owners: &HashMap<String, String>) -> Option<LinkingAccuracy> {
+        let accuracy = LinkingAccuracy::compute(self.fingerprint_linker.as_ref()?.links(), owners);
+        self.reporter.set_linking_accuracy(accuracy.clone());
+        Some(accuracy)
+    }
+
+    // Note that the simulation stopped before all planned payments were made
+    pub fn mark_partial(&mut self, simulated: usize, planned: usize) {
+        self.reporter.mark_partial(simulated, planned);
     }
 
     // Clear all observations (for long-running operations)
-    pub fn clear_observations(&mut self) {
-        self.observed_htlcs.clear();
+    // Observation windows and sharing delays count time in finished payments
+    fn payment_finished(&mut self) {
+        if let Some(windows) = &mut self.windows {
+            windows.tick();
+        }
+        if let Some(sharing) = &mut self.sharing {
+            sharing.tick();
+        }
+    }
+
+    // HTLCs our nodes would have seen had they been online
+    pub fn missed_observations(&self) -> u64 {
+        self.windows.as_ref().map_or(0, ObservationWindows::missed)
+    }
+
+    // Whether one of our nodes is online to see what happens now. Only missed HTLCs count
+    // as missed observations, not their resolutions.
+    fn is_watching(&mut self, node: &str, counted: bool) -> bool {
+        match &mut self.windows {
+            Some(windows) if counted => windows.sees(node),
+            Some(windows) => windows.is_online(node),
+            None => true,
+        }
+    }
+
+    pub fn clear_observations(&mut self) -> Result<(), ThelmaError> {
+        if self.live.is_some() {
+            self.live = Some(IncrementalAnalyzer::new());
+        }
+        self.observed_htlcs.clear()
+    }
+}
+
+// Our nodes record the HTLCs they forward; everything else goes unseen
+impl Observer for SurveillanceOperation {
+    fn on_htlc_forward(&mut self, htlc: &HTLC) -> Result<(), ThelmaError> {
+        if self.malicious_nodes.contains(&htlc.observed_by_node) && self.is_watching(&htlc.observed_by_node, true) {
+            self.record_htlc_observation(htlc.clone())?;
+        }
+        Ok(())
+    }
+
+    // Our nodes time how long their HTLCs take to settle or fail. Observations linked by
+    // fingerprint are filed under their cluster, so their resolutions are too.
+    fn on_htlc_resolved(&mut self, resolution: &HtlcResolution) -> Result<(), ThelmaError> {
+        if !self.malicious_nodes.contains(&resolution.observed_by_node)
+            || !self.is_watching(&resolution.observed_by_node, false) {
+            return Ok(());
+        }
+        let cluster = self.fingerprint_linker.as_ref()
+            .and_then(|linker| linker.links().get(&resolution.payment_hash));
+        match cluster {
+            Some(cluster) => {
+                let mut linked = resolution.clone();
+                linked.payment_hash = cluster.clone();
+                self.analyzer.record_resolution(&linked);
+            }
+            None => self.analyzer.record_resolution(resolution),
+        }
+        Ok(())
+    }
+
+    fn on_settle(&mut self, _payment_hash: &str) -> Result<(), ThelmaError> {
+        self.payment_finished();
+        Ok(())
+    }
+
+    fn on_fail(&mut self, _sender: &str, _receiver: &str, _reason: &str) -> Result<(), ThelmaError> {
+        self.payment_finished();
+        Ok(())
+    }
+
+    // Gossip reaches everyone, the adversary included
+    fn on_channel_update(&mut self, channel_id: &str, block: u32) -> Result<(), ThelmaError> {
+        self.analyzer.record_channel_update(channel_id, block);
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::models::{Node, Channel};
+    use crate::models::Node;
 
     #[test]
     fn test_record_observation() {
         // Create a test network
-        let network_map = Arc::new(Mutex::new(LightningNetworkMap::new(700000)));
+        let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(700000)));
 
         {
-            let mut network = network_map.lock().unwrap();
+            let mut network = network_map.write().unwrap();
             network.add_node(Node::new("node1", "Node 1", 40));
             network.add_node(Node::new("node2", "Node 2", 40));
         }
@@ -118,8 +612,8 @@ mod tests {
         // Setup surveillance with node1 as malicious
         let mut surveillance = SurveillanceOperation::new(
             network_map,
-            vec!["node1".to_string()]
-        );
+            SurveillanceConfig::observing(vec!["node1".to_string()])
+        ).unwrap();
 
         // Create an HTLC observation from the malicious node
         let htlc = HTLC::new(
@@ -131,8 +625,8 @@ mod tests {
         );
 
         // Test recording the observation
-        surveillance.record_htlc_observation(htlc);
-        assert_eq!(surveillance.get_observations().len(), 1);
+        surveillance.record_htlc_observation(htlc).unwrap();
+        assert_eq!(surveillance.observation_count(), 1);
 
         // Create an HTLC from a non-malicious node
         let htlc2 = HTLC::new(
@@ -144,7 +638,30 @@ mod tests {
         );
 
         // This should be ignored
-        surveillance.record_htlc_observation(htlc2);
-        assert_eq!(surveillance.get_observations().len(), 1);
+        surveillance.record_htlc_observation(htlc2).unwrap();
+        assert_eq!(surveillance.observation_count(), 1);
+    }
+
+    #[test]
+    fn test_ordering_check_compares_only_the_latest_attempt() {
+        let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(700000)));
+        let observers: Vec<String> = ["a", "b", "c"].iter().map(|n| n.to_string()).collect();
+        let mut surveillance = SurveillanceOperation::new(network_map, SurveillanceConfig::observing(observers)).unwrap();
+
+        // Each attempt is consistent on its own, but the retry's later timelock comes with a
+        // smaller amount than the first attempt saw
+        surveillance.record_multiple_observations(vec![
+            HTLC::new("retried", 700200, 600_600, 700000, "a"),
+            HTLC::new("retried", 700160, 600_000, 700000, "b"),
+            HTLC::new("retried", 700240, 600_300, 700010, "c"),
+            // Out of order within one attempt
+            HTLC::new("conflicting", 700200, 600_000, 700000, "a"),
+            HTLC::new("conflicting", 700160, 600_600, 700000, "b"),
+        ]).unwrap();
+
+        let results: HashMap<String, Vec<PotentialRecipient>> = ["retried", "conflicting"].iter()
+            .map(|hash| (hash.to_string(), Vec::new()))
+            .collect();
+        assert_eq!(surveillance.run_ordering_check(&results), HashSet::from(["conflicting".to_string()]));
     }
 }
@@ -0,0 +1,240 @@
+// Choosing where to put the adversary's nodes so they see as much traffic as possible
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Write;
+use log::info;
+use rand::Rng;
+use rand::rngs::StdRng;
+
+use crate::models::LightningNetworkMap;
+use crate::simulation::{AmountDistribution, Router};
+use crate::error::ThelmaError;
+
+// Payments sampled to estimate coverage when none is given
+pub const DEFAULT_TRAFFIC_SAMPLES: usize = 2000;
+
+// Routes payments between uniformly random pairs of nodes would take. A node observes every
+// payment whose route passes through it, as in the simulator.
+pub struct TrafficModel {
+    routes: Vec<Vec<String>>,
+    // Payments sampled, including ones that found no route
+    sampled: usize,
+}
+
+impl TrafficModel {
+    pub fn sample(network: &LightningNetworkMap,
+                  router: &dyn Router,
+                  amounts: AmountDistribution,
+                  samples: usize,
+                  rng: &mut StdRng) -> Self {
+        // Sorted so a seeded RNG samples the same payments every time
+        let mut nodes: Vec<&String> = network.nodes.keys().collect();
+        nodes.sort();
+
+        let mut routes = Vec::new();
+        if nodes.len() >= 2 {
+            for _ in 0..samples {
+                let sender = rng.random_range(0..nodes.len());
+                let mut receiver = rng.random_range(0..nodes.len());
+                while receiver == sender {
+                    receiver = rng.random_range(0..nodes.len());
+                }
+                let amount = amounts.sample(rng);
+                let route = router.find_route(network, nodes[sender], nodes[receiver], amount, rng);
+                if route.len() >= 2 {
+                    routes.push(route);
+                }
+            }
+        }
+
+        TrafficModel { routes, sampled: samples }
+    }
+
+    // Share of routed payments at least one of `nodes` would observe
+    pub fn coverage(&self, nodes: &[String]) -> f64 {
+        if self.routes.is_empty() {
+            return 0.0;
+        }
+        let nodes: HashSet<&String> = nodes.iter().collect();
+        let covered = self.routes.iter().filter(|route| route.iter().any(|hop| nodes.contains(hop))).count();
+        covered as f64 / self.routes.len() as f64
+    }
+}
+
+// One pick of the placement, with what it added
+#[derive(Debug, Clone)]
+pub struct PlacementStep {
+    pub node: String,
+    // Sampled payments this node sees that none picked before it did
+    pub new_payments: usize,
+    // Share of routed payments observed once this node is in
+    pub coverage: f64,
+}
+
+// Nodes for an adversary with a budget of `budget` nodes, picked greedily: each step takes the
+// node seeing the most payments not yet covered. Coverage is submodular, so this gets within
+// a factor of 1 - 1/e of the best possible placement.
+#[derive(Debug, Clone)]
+pub struct PlacementPlan {
+    pub steps: Vec<PlacementStep>,
+    pub routed_payments: usize,
+    pub sampled_payments: usize,
+    // Coverage of other placements of the same size, for comparison
+    pub baselines: Vec<(String, f64)>,
+}
+
+impl PlacementPlan {
+    pub fn greedy(model: &TrafficModel, budget: usize) -> Self {
+        // Routes each node appears on
+        let mut routes_through: HashMap<&String, Vec<usize>> = HashMap::new();
+        for (i, route) in model.routes.iter().enumerate() {
+            let hops: HashSet<&String> = route.iter().collect();
+            for hop in hops {
+                routes_through.entry(hop).or_default().push(i);
+            }
+        }
+
+        let mut covered = vec![false; model.routes.len()];
+        let mut covered_count = 0;
+        let mut steps = Vec::new();
+        while steps.len() < budget && !routes_through.is_empty() {
+            // Ties go to the lowest node id, so plans are reproducible
+            let best = routes_through.iter()
+                .map(|(node, routes)| (*node, routes.iter().filter(|&&route| !covered[route]).count()))
+                .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(a.0)));
+            let Some((node, new_payments)) = best else { break };
+            let node = node.clone();
+
+            for &route in &routes_through[&node] {
+                covered[route] = true;
+            }
+            covered_count += new_payments;
+            routes_through.remove(&node);
+            steps.push(PlacementStep {
+                node,
+                new_payments,
+                coverage: covered_count as f64 / model.routes.len().max(1) as f64,
+            });
+        }
+
+        PlacementPlan {
+            steps,
+            routed_payments: model.routes.len(),
+            sampled_payments: model.sampled,
+            baselines: Vec::new(),
+        }
+    }
+
+    pub fn nodes(&self) -> Vec<String> {
+        self.steps.iter().map(|step| step.node.clone()).collect()
+    }
+
+    // Predicted share of payments the whole placement observes
+    pub fn coverage(&self) -> f64 {
+        self.steps.last().map_or(0.0, |step| step.coverage)
+    }
+
+    pub fn add_baseline(&mut self, label: &str, coverage: f64) {
+        self.baselines.push((label.to_string(), coverage));
+    }
+
+    // Generate the markdown placement report
+    pub fn generate_text_report(&self) -> String {
+        let mut report = String::from("## THELMA: Adversary Placement\n\n");
+        report.push_str(&format!("Traffic model: {} sampled payments between random nodes, {} routed\n",
+                                 self.sampled_payments, self.routed_payments));
+        report.push_str(&format!("Predicted coverage with {} nodes: {:.1}%\n\n",
+                                 self.steps.len(), self.coverage() * 100.0));
+
+        report.push_str("| # | Node | New payments | Coverage |\n|---|---|---|---|\n");
+        for (i, step) in self.steps.iter().enumerate() {
+            report.push_str(&format!("| {} | {} | {} | {:.1}% |\n",
+                                     i + 1, step.node, step.new_payments, step.coverage * 100.0));
+        }
+
+        if !self.baselines.is_empty() {
+            report.push_str("\nSame number of nodes placed by:\n");
+            for (label, coverage) in &self.baselines {
+                report.push_str(&format!("  {}: {:.1}%\n", label, coverage * 100.0));
+            }
+        }
+
+        report
+    }
+
+    // Generate a JSON version of the placement
+    pub fn generate_json_report(&self) -> String {
+        let steps: Vec<serde_json::Value> = self.steps.iter()
+            .map(|step| serde_json::json!({
+                "node": step.node,
+                "new_payments": step.new_payments,
+                "coverage": step.coverage,
+            }))
+            .collect();
+        let baselines: serde_json::Map<String, serde_json::Value> = self.baselines.iter()
+            .map(|(label, coverage)| (label.clone(), serde_json::json!(coverage)))
+            .collect();
+
+        serde_json::to_string_pretty(&serde_json::json!({
+            "nodes": self.nodes(),
+            "coverage": self.coverage(),
+            "sampled_payments": self.sampled_payments,
+            "routed_payments": self.routed_payments,
+            "steps": steps,
+            "baselines": baselines,
+        })).unwrap_or_else(|_| "Error generating JSON report".to_string())
+    }
+
+    // Save the placement report to file
+    pub fn save_report_to_file(&self, filename: &str) -> Result<(), ThelmaError> {
+        let mut file = File::create(filename)?;
+        file.write_all(self.generate_text_report().as_bytes())?;
+
+        info!("Placement saved to {}", filename);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use crate::models::{Channel, Node};
+    use crate::simulation::router_from_name;
+
+    #[test]
+    fn test_greedy_placement_covers_the_hubs_first() {
+        // Two stars joined at their hubs: every payment between the stars crosses both hubs,
+        // and every other payment crosses at least one of them
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["hub1", "hub2", "a1", "a2", "a3", "b1", "b2", "b3"] {
+            network.add_node(Node::new(key, key, 40));
+        }
+        network.add_channel(Channel::new("hubs", "hub1", "hub2", 10_000_000));
+        for leaf in ["a1", "a2", "a3"] {
+            network.add_channel(Channel::new(&format!("hub1-{}", leaf), "hub1", leaf, 10_000_000));
+        }
+        for leaf in ["b1", "b2", "b3"] {
+            network.add_channel(Channel::new(&format!("hub2-{}", leaf), "hub2", leaf, 10_000_000));
+        }
+
+        let router = router_from_name("bfs").unwrap();
+        let mut rng = StdRng::seed_from_u64(7);
+        let model = TrafficModel::sample(&network, router.as_ref(), AmountDistribution::Fixed(10_000), 500, &mut rng);
+        let plan = PlacementPlan::greedy(&model, 2);
+
+        let mut nodes = plan.nodes();
+        nodes.sort();
+        assert_eq!(nodes, vec!["hub1".to_string(), "hub2".to_string()]);
+        assert_eq!(plan.coverage(), 1.0);
+        assert_eq!(model.coverage(&plan.nodes()), 1.0);
+        assert!(plan.steps[0].new_payments >= plan.steps[1].new_payments);
+        assert!(model.coverage(&["a1".to_string(), "b1".to_string()]) < 1.0);
+
+        let json: serde_json::Value = serde_json::from_str(&plan.generate_json_report()).unwrap();
+        assert_eq!(json["coverage"], 1.0);
+        assert_eq!(json["routed_payments"], 500);
+    }
+}
@@ -0,0 +1,139 @@
+// Plausible recipients: on big graphs most candidates are routing nodes that never get paid.
+// A profile says which nodes could be paid at all, from what the adversary has seen of the
+// traffic and the gossip and from operator labels, and the analyzer leaves candidates outside
+// it out before scoring.
+
+use std::collections::HashSet;
+
+use crate::models::{HTLC, LightningNetworkMap};
+use crate::error::ThelmaError;
+
+// Nodes a payment can plausibly end at. A node fits when it meets any of the criteria set.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecipientProfile {
+    // Nodes seen being paid: made the recipient by an onion our node read, or named as the
+    // destination a trampoline was asked to reach
+    observed_sinks: bool,
+    // Nodes with a single peer in the gossip, which can't forward and so only send and receive
+    gossip_leaves: bool,
+    // Kinds of operator that take payments, by label
+    labels: Vec<String>,
+    // Nodes known to take payments, from invoices or directories, plus the sinks seen so far
+    sinks: HashSet<String>,
+}
+
+impl RecipientProfile {
+    pub fn new() -> Self {
+        RecipientProfile::default()
+    }
+
+    // `sinks`, `leaves` and any other words as labels, comma separated: "sinks,merchant"
+    pub fn parse(spec: &str) -> Result<Self, ThelmaError> {
+        let mut profile = RecipientProfile::new();
+        for criterion in spec.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+            profile = match criterion {
+                "sinks" => profile.observed_sinks(),
+                "leaves" => profile.gossip_leaves(),
+                label => profile.labeled(label),
+            };
+        }
+        if profile == RecipientProfile::new() {
+            return Err(ThelmaError::Config(format!("empty recipient profile '{}' (expected sinks, leaves \
+                                                    or operator labels)", spec)));
+        }
+        Ok(profile)
+    }
+
+    pub fn observed_sinks(mut self) -> Self {
+        self.observed_sinks = true;
+        self
+    }
+
+    pub fn gossip_leaves(mut self) -> Self {
+        self.gossip_leaves = true;
+        self
+    }
+
+    pub fn labeled(mut self, label: &str) -> Self {
+        self.labels.push(label.to_lowercase());
+        self
+    }
+
+    pub fn known_sinks(mut self, nodes: impl IntoIterator<Item = String>) -> Self {
+        self.sinks.extend(nodes);
+        self
+    }
+
+    // Note where an observation shows a payment ending, when sinks are watched for
+    pub fn record(&mut self, htlc: &HTLC) {
+        if !self.observed_sinks {
+            return;
+        }
+        if htlc.reached_recipient() {
+            self.sinks.insert(htlc.observed_by_node.clone());
+        }
+        if let Some(destination) = htlc.trampoline_destination() {
+            self.sinks.insert(destination.to_string());
+        }
+    }
+
+    pub fn admits(&self, network: &LightningNetworkMap, node: &str) -> bool {
+        self.sinks.contains(node)
+            || (self.gossip_leaves && network.get_neighbors(node).is_some_and(|peers| {
+                peers.first().is_some_and(|first| peers.iter().all(|peer| peer == first))
+            }))
+            || network.nodes.get(node)
+                .and_then(|node| node.label.as_ref())
+                .is_some_and(|label| self.labels.contains(label))
+    }
+
+    // Nodes of the network the profile admits
+    pub fn admitted(&self, network: &LightningNetworkMap) -> usize {
+        network.nodes.keys().filter(|node| self.admits(network, node)).count()
+    }
+
+    pub fn describe(&self) -> String {
+        let mut criteria = Vec::new();
+        if self.observed_sinks {
+            criteria.push("observed sinks".to_string());
+        }
+        if self.gossip_leaves {
+            criteria.push("gossip leaves".to_string());
+        }
+        criteria.extend(self.labels.iter().map(|label| format!("labeled {}", label)));
+        if !self.sinks.is_empty() {
+            criteria.push(format!("{} known sinks", self.sinks.len()));
+        }
+        criteria.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::HopPayload;
+
+    #[test]
+    fn test_profile_admits_sinks_leaves_and_labels() {
+        let mut network = crate::simulation::fixture("two-hub").unwrap();
+        network.nodes.get_mut("hub2").unwrap().label = Some("merchant".to_string());
+
+        let leaves = RecipientProfile::parse("leaves").unwrap();
+        assert!(leaves.admits(&network, "a") && !leaves.admits(&network, "hub1"));
+        assert_eq!(leaves.admitted(&network), 6);
+
+        let mut profile = RecipientProfile::parse("sinks, Merchant").unwrap();
+        assert!(profile.admits(&network, "hub2") && !profile.admits(&network, "hub1"));
+        // Our trampoline asked to reach b, and hub1 paid as the recipient
+        let trampoline = HTLC::new("p", 700100, 1_000, 700000, "hub2")
+            .with_onion(None, HopPayload::forward("x", 1_000, 700060).via_trampoline("b"));
+        let paid = HTLC::new("q", 700040, 1_000, 700000, "hub1").with_onion(None, HopPayload::final_hop(1_000, 700040));
+        assert!(!profile.admits(&network, "b"));
+        profile.record(&trampoline);
+        profile.record(&paid);
+        assert!(profile.admits(&network, "b") && profile.admits(&network, "hub1"));
+        assert_eq!(profile.describe(), "observed sinks, labeled merchant, 2 known sinks");
+
+        assert!(RecipientProfile::parse(" , ").is_err());
+    }
+}
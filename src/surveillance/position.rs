@@ -0,0 +1,206 @@
+// Where on a payment's route each observing node sat, inferred from the timelocks every
+// observer saw. Position is attacker knowledge in its own right: an observer known to be the
+// sender's first hop has all but found the sender.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::models::{HTLC, LightningNetworkMap, CLTV_EXPIRY_DELTA_MIN};
+use crate::surveillance::analyzer::PotentialRecipient;
+use crate::surveillance::metrics::rate;
+
+// An observer's inferred place on a route: `hop` hops after the sender (the sender itself
+// is hop 0) on a route of `route_hops` hops
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObserverPosition {
+    pub observer: String,
+    pub hop: usize,
+    pub route_hops: usize,
+}
+
+// How often inferred positions matched the real ones
+#[derive(Debug, Clone, Default)]
+pub struct PositionAccuracy {
+    pub sightings: usize,
+    pub hop_correct: usize,
+    // Off by at most one hop
+    pub hop_close: usize,
+    pub route_length_correct: usize,
+}
+
+impl PositionAccuracy {
+    // Score every observer's inferred position against the payment's real route
+    pub fn compute(positions: &HashMap<String, Vec<ObserverPosition>>,
+                   routes: &HashMap<String, Vec<String>>) -> Self {
+        let mut accuracy = PositionAccuracy::default();
+        for (payment_hash, observers) in positions {
+            let Some(route) = routes.get(payment_hash) else { continue };
+            for position in observers {
+                let Some(hop) = route.iter().position(|node| *node == position.observer) else { continue };
+                accuracy.sightings += 1;
+                if hop == position.hop {
+                    accuracy.hop_correct += 1;
+                }
+                if hop.abs_diff(position.hop) <= 1 {
+                    accuracy.hop_close += 1;
+                }
+                if route.len() - 1 == position.route_hops {
+                    accuracy.route_length_correct += 1;
+                }
+            }
+        }
+        accuracy
+    }
+
+    pub fn hop_accuracy(&self) -> f64 {
+        rate(self.hop_correct, self.sightings)
+    }
+
+    pub fn hop_close_rate(&self) -> f64 {
+        rate(self.hop_close, self.sightings)
+    }
+
+    pub fn route_length_accuracy(&self) -> f64 {
+        rate(self.route_length_correct, self.sightings)
+    }
+}
+
+// Place every observer of one payment on its route. Observers are ordered by the timelock
+// they saw; the gaps between them are the deltas of the nodes in between, and the top
+// candidate's route says how far the observer it was searched from is from the recipient. How far the first
+// observer was from the sender, the timelocks can't tell, so it's the most common distance
+// among the senders whose shortest route to the top candidate runs through it, and at most
+// `max_upstream_hops` when the sender's timelock cap bounds it. Compromised nodes make
+// payments too, so the observer itself counts as one of those senders.
+pub fn infer_positions(network: &LightningNetworkMap,
+                       observations: &[HTLC],
+                       recipients: &[PotentialRecipient],
+                       max_upstream_hops: Option<usize>) -> Vec<ObserverPosition> {
+    let Some(best) = recipients.first() else { return Vec::new() };
+
+    let mut sorted: Vec<&HTLC> = observations.iter().collect();
+    sorted.sort_by(|a, b| b.cltv_expiry.cmp(&a.cltv_expiry).then(a.observed_by_node.cmp(&b.observed_by_node)));
+    let mut seen = HashSet::new();
+    sorted.retain(|htlc| seen.insert(htlc.observed_by_node.as_str()));
+    let Some(first) = sorted.first() else { return Vec::new() };
+
+    let mean_delta = if network.nodes.is_empty() {
+        CLTV_EXPIRY_DELTA_MIN as f64
+    } else {
+        network.nodes.values().map(|node| node.cltv_expiry_delta as f64).sum::<f64>() / network.nodes.len() as f64
+    };
+
+    let upstream = upstream_hops(network, &first.observed_by_node, &best.node_id, max_upstream_hops);
+    let mut hops = Vec::with_capacity(sorted.len());
+    let mut hop = upstream;
+    for (i, htlc) in sorted.iter().enumerate() {
+        if i > 0 {
+            let gap = sorted[i - 1].cltv_expiry - htlc.cltv_expiry;
+            hop += ((gap as f64 / mean_delta).round() as usize).max(1);
+        }
+        hops.push(hop);
+    }
+
+    let downstream = best.route.len().saturating_sub(1);
+    let start = best.route.first()
+        .and_then(|node| sorted.iter().position(|htlc| htlc.observed_by_node == *node))
+        .unwrap_or(sorted.len() - 1);
+    let route_hops = (hops[start] + downstream).max(hop);
+
+    sorted.iter().zip(hops)
+        .map(|(htlc, hop)| ObserverPosition { observer: htlc.observed_by_node.clone(), hop, route_hops })
+        .collect()
+}
+
+fn upstream_hops(network: &LightningNetworkMap,
+                 observer: &str,
+                 recipient: &str,
+                 max_upstream_hops: Option<usize>) -> usize {
+    let from_observer = distances(network, observer);
+    let from_recipient = distances(network, recipient);
+    let Some(&remaining) = from_recipient.get(observer) else { return 0 };
+
+    // Senders whose shortest route to the recipient passes the observer, by distance
+    let mut counts: HashMap<usize, usize> = HashMap::new();
+    for (sender, &upstream) in &from_observer {
+        if sender == recipient || max_upstream_hops.is_some_and(|max| upstream > max) {
+            continue;
+        }
+        if from_recipient.get(sender) == Some(&(upstream + remaining)) {
+            *counts.entry(upstream).or_default() += 1;
+        }
+    }
+
+    counts.into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)))
+        .map(|(upstream, _)| upstream)
+        .unwrap_or(0)
+}
+
+// Hop distances from a node to every node it can reach
+fn distances(network: &LightningNetworkMap, origin: &str) -> HashMap<String, usize> {
+    let mut distances = HashMap::from([(origin.to_string(), 0)]);
+    let mut queue = VecDeque::from([origin.to_string()]);
+    while let Some(node) = queue.pop_front() {
+        let distance = distances[&node];
+        if let Some(neighbors) = network.get_neighbors(&node) {
+            for neighbor in neighbors {
+                if !distances.contains_key(neighbor) {
+                    distances.insert(neighbor.clone(), distance + 1);
+                    queue.push_back(neighbor.clone());
+                }
+            }
+        }
+    }
+    distances
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Channel, Node};
+
+    #[test]
+    fn test_observer_positions_on_a_line() {
+        // a - b - c - d - e - f with g and h also hanging off b, every node with a 40-block delta
+        let mut network = LightningNetworkMap::new(700000);
+        let keys = ["a", "b", "c", "d", "e", "f"];
+        for key in keys.iter().chain(&["g", "h"]) {
+            network.add_node(Node::new(key, key, 40));
+        }
+        for pair in keys.windows(2) {
+            network.add_channel(Channel::new(&format!("{}{}", pair[0], pair[1]), pair[0], pair[1], 1_000_000));
+        }
+        network.add_channel(Channel::new("bg", "b", "g", 1_000_000));
+        network.add_channel(Channel::new("bh", "b", "h", 1_000_000));
+
+        // The payment runs b -> f; c and e are malicious and 2 hops apart
+        let observations = vec![HTLC::new("p", 700120, 1000, 700000, "e"), HTLC::new("p", 700200, 1000, 700000, "c")];
+        let recipients = vec![PotentialRecipient {
+            route: vec!["e".to_string(), "f".to_string()],
+            ..PotentialRecipient::new("f", 1.0)
+        }];
+        let positions = infer_positions(&network, &observations, &recipients, None);
+
+        // Of the senders routing through c, a, g and h are two hops away, b one and c none
+        assert_eq!(positions[0], ObserverPosition { observer: "c".to_string(), hop: 2, route_hops: 5 });
+        assert_eq!(positions[1].hop, 4);
+
+        // The same route searched from c, the first observer
+        let from_c = vec![PotentialRecipient { route: ["c", "d", "e", "f"].map(String::from).to_vec(), ..recipients[0].clone() }];
+        assert_eq!(infer_positions(&network, &observations, &from_c, None)[1].route_hops, 5);
+
+        // A timelock cap allowing one hop upstream leaves b and c, tied, and ties go to the
+        // shorter distance
+        assert_eq!(infer_positions(&network, &observations, &recipients, Some(1))[0].hop, 0);
+
+        // Against the real route b..f
+        let positions = HashMap::from([("p".to_string(), vec![
+            ObserverPosition { observer: "c".to_string(), hop: 1, route_hops: 4 },
+            ObserverPosition { observer: "e".to_string(), hop: 2, route_hops: 4 },
+        ])]);
+        let routes = HashMap::from([("p".to_string(), ["b", "c", "d", "e", "f"].map(String::from).to_vec())]);
+        let accuracy = PositionAccuracy::compute(&positions, &routes);
+        assert_eq!((accuracy.sightings, accuracy.hop_correct, accuracy.hop_close), (2, 1, 2));
+        assert_eq!(accuracy.route_length_correct, 2);
+    }
+}
@@ -0,0 +1,174 @@
+// Probe detection: HTLCs that look like someone mapping channel liquidity rather than
+// paying. Probes make up a large share of real forwarding traffic, and left in they pass
+// for payments to whatever node the prober aimed at.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::models::HTLC;
+
+// Amounts at or below this many msat carry no value worth paying
+pub const DEFAULT_PROBE_TINY_MSAT: u64 = 1_000;
+// Amounts that are a whole multiple of this look picked by a tool rather than an invoice
+pub const DEFAULT_PROBE_ROUND_MSAT: u64 = 1_000_000;
+// Payments of the same amount an observer sees in one block before it looks like retries
+pub const DEFAULT_PROBE_BURST: usize = 3;
+// Hex characters of a payment hash compared, and payments sharing them, before the shared
+// prefix looks like a tool's signature
+pub const DEFAULT_PROBE_PREFIX_LEN: usize = 8;
+pub const DEFAULT_PROBE_PREFIX_REPEATS: usize = 3;
+
+// Why an observed payment looks like a probe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ProbeSignal {
+    TinyAmount,
+    RoundAmount,
+    // The same amount tried again and again through one observer within a block, the way
+    // a prober retries after each failure
+    RapidRetries,
+    RepeatedHashPrefix,
+}
+
+impl ProbeSignal {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProbeSignal::TinyAmount => "tiny amount",
+            ProbeSignal::RoundAmount => "round amount",
+            ProbeSignal::RapidRetries => "rapid retries",
+            ProbeSignal::RepeatedHashPrefix => "repeated hash prefix",
+        }
+    }
+
+    // Signals telling on their own; the rest only count together
+    fn is_conclusive(&self) -> bool {
+        matches!(self, ProbeSignal::TinyAmount | ProbeSignal::RepeatedHashPrefix)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ProbeDetector {
+    pub tiny_msat: u64,
+    pub round_msat: u64,
+    pub burst: usize,
+    pub prefix_len: usize,
+    pub prefix_repeats: usize,
+}
+
+impl Default for ProbeDetector {
+    fn default() -> Self {
+        ProbeDetector {
+            tiny_msat: DEFAULT_PROBE_TINY_MSAT,
+            round_msat: DEFAULT_PROBE_ROUND_MSAT,
+            burst: DEFAULT_PROBE_BURST,
+            prefix_len: DEFAULT_PROBE_PREFIX_LEN,
+            prefix_repeats: DEFAULT_PROBE_PREFIX_REPEATS,
+        }
+    }
+}
+
+impl ProbeDetector {
+    pub fn scan(&self) -> ProbeScan<'_> {
+        ProbeScan {
+            detector: self,
+            signals: HashMap::new(),
+            bursts: HashMap::new(),
+            prefixes: HashMap::new(),
+        }
+    }
+
+    // Probable probes among these observations, with the signals that gave each away
+    pub fn classify<'a>(&self, observations: impl IntoIterator<Item = &'a HTLC>) -> HashMap<String, Vec<ProbeSignal>> {
+        let mut scan = self.scan();
+        for htlc in observations {
+            scan.add(htlc);
+        }
+        scan.finish()
+    }
+}
+
+// Observations fed one at a time, for stores too big to hold at once. Retries and shared
+// prefixes span payments, so nothing is classified until every observation is in.
+pub struct ProbeScan<'a> {
+    detector: &'a ProbeDetector,
+    signals: HashMap<String, HashSet<ProbeSignal>>,
+    bursts: HashMap<(String, u32, u64), HashSet<String>>,
+    prefixes: HashMap<String, HashSet<String>>,
+}
+
+impl ProbeScan<'_> {
+    pub fn add(&mut self, htlc: &HTLC) {
+        let detector = self.detector;
+        let signals = self.signals.entry(htlc.payment_hash.clone()).or_default();
+        if htlc.amount <= detector.tiny_msat {
+            signals.insert(ProbeSignal::TinyAmount);
+        }
+        if detector.round_msat > 0 && htlc.amount.is_multiple_of(detector.round_msat) {
+            signals.insert(ProbeSignal::RoundAmount);
+        }
+
+        self.bursts.entry((htlc.observed_by_node.clone(), htlc.observed_at_block, htlc.amount))
+            .or_default()
+            .insert(htlc.payment_hash.clone());
+
+        // Simulated hashes carry a fixed label in front of the random part
+        let random_part = htlc.payment_hash.trim_start_matches("hash_");
+        if let Some(prefix) = random_part.get(..detector.prefix_len) {
+            self.prefixes.entry(prefix.to_string()).or_default().insert(htlc.payment_hash.clone());
+        }
+    }
+
+    pub fn finish(mut self) -> HashMap<String, Vec<ProbeSignal>> {
+        for hashes in self.bursts.values().filter(|hashes| hashes.len() >= self.detector.burst) {
+            for hash in hashes {
+                self.signals.entry(hash.clone()).or_default().insert(ProbeSignal::RapidRetries);
+            }
+        }
+        for hashes in self.prefixes.values().filter(|hashes| hashes.len() >= self.detector.prefix_repeats) {
+            for hash in hashes {
+                self.signals.entry(hash.clone()).or_default().insert(ProbeSignal::RepeatedHashPrefix);
+            }
+        }
+
+        self.signals.into_iter()
+            .filter(|(_, signals)| signals.len() >= 2 || signals.iter().any(ProbeSignal::is_conclusive))
+            .map(|(hash, signals)| {
+                let mut signals: Vec<ProbeSignal> = signals.into_iter().collect();
+                signals.sort();
+                (hash, signals)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_signals() {
+        let observations = vec![
+            // A 1-sat probe
+            HTLC::new("hash_00000000aaaa0001", 700100, 1_000, 700000, "m"),
+            // A round amount retried through m three times in one block
+            HTLC::new("hash_1f00000000000001", 700100, 5_000_000, 700000, "m"),
+            HTLC::new("hash_2f00000000000002", 700090, 5_000_000, 700000, "m"),
+            HTLC::new("hash_3f00000000000003", 700080, 5_000_000, 700000, "m"),
+            // Three hashes from the same tool
+            HTLC::new("hash_cafebabe00000001", 700100, 123_456, 700000, "m"),
+            HTLC::new("hash_cafebabe00000002", 700100, 234_567, 700000, "m"),
+            HTLC::new("hash_cafebabe00000003", 700100, 345_678, 700001, "n"),
+            // Round, but paid once: an ordinary payment
+            HTLC::new("hash_9999999999999999", 700100, 2_000_000, 700000, "m"),
+            // Ordinary payment seen by two observers
+            HTLC::new("hash_8888888888888888", 700100, 456_789, 700000, "m"),
+            HTLC::new("hash_8888888888888888", 700060, 456_000, 700000, "n"),
+        ];
+        let probes = ProbeDetector::default().classify(&observations);
+
+        assert_eq!(probes.len(), 7);
+        assert_eq!(probes["hash_00000000aaaa0001"], vec![ProbeSignal::TinyAmount]);
+        assert_eq!(probes["hash_2f00000000000002"], vec![ProbeSignal::RoundAmount, ProbeSignal::RapidRetries]);
+        assert_eq!(probes["hash_cafebabe00000003"], vec![ProbeSignal::RepeatedHashPrefix]);
+        assert!(!probes.contains_key("hash_9999999999999999"));
+        assert!(!probes.contains_key("hash_8888888888888888"));
+    }
+}
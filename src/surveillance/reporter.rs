@@ -1,38 +1,211 @@
 // Reporting functionality for surveillance results
 
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
 use std::fs::File;
 use std::io::Write;
-use std::error::Error;
+use log::info;
 
 use crate::models::LightningNetworkMap;
-use crate::surveillance::analyzer::PotentialRecipient;
+use crate::surveillance::analyzer::{PotentialRecipient, CandidateCommunity};
+use crate::surveillance::position::{ObserverPosition, PositionAccuracy};
+use crate::surveillance::probes::ProbeSignal;
+use crate::surveillance::fingerprint::LinkingAccuracy;
+use crate::surveillance::exposure::NodeExposure;
+use crate::surveillance::false_positives::FalsePositiveAnalysis;
+use crate::surveillance::calibration::CalibrationCurve;
+use crate::surveillance::accuracy::IdentificationAccuracy;
+use crate::surveillance::label_accuracy::LabelAccuracy;
+use crate::surveillance::tor_timing::TorTimingAnalysis;
+use crate::surveillance::geography::GeographyInference;
+use crate::surveillance::memory::MemoryUsage;
+use crate::simulation::PaymentRecord;
+use crate::error::{ThelmaError, read_lock};
+
+// What the attacker worked out about the traffic itself, next to the recipients
+#[derive(Debug, Clone, Default)]
+pub struct TrafficInferences {
+    // Where on its route each observer of a payment sat
+    pub positions: HashMap<String, Vec<ObserverPosition>>,
+    // Payments whose observations order differently by amount than by timelock
+    pub ordering_conflicts: HashSet<String>,
+    // Payments whose analysis ran out of time, ranked from the routes found by then
+    pub incomplete: HashSet<String>,
+    // Payments left out of the analysis as probable probes, with what gave each away
+    pub probes: HashMap<String, Vec<ProbeSignal>>,
+    // Honest nodes most often named as likely recipients
+    pub exposed_nodes: Vec<NodeExposure>,
+    // What sets wrongly named honest nodes apart, with ground truth
+    pub false_positives: Option<FalsePositiveAnalysis>,
+    // How often candidates of each confidence were right, with ground truth
+    pub calibration: Option<CalibrationCurve>,
+    // How often the recipient ranked within each top k or small set, with ground truth
+    pub identification: Option<IdentificationAccuracy>,
+    // Identification by the recipient's label, with ground truth and a labeled network
+    pub labels: Option<LabelAccuracy>,
+    // Whether settlement waits give Tor hops away, with ground truth and Tor-only nodes
+    pub tor_timing: Option<TorTimingAnalysis>,
+    // Recipients' regions guessed from settlement timing, with ground truth and regions
+    pub geography: Option<GeographyInference>,
+    // Peak memory the graph, route enumeration and observations took
+    pub memory: Option<MemoryUsage>,
+}
 
 // Reporter for surveillance operation results
 pub struct SurveillanceReporter {
-    network: Arc<Mutex<LightningNetworkMap>>,
+    network: Arc<RwLock<LightningNetworkMap>>,
+    // Payments simulated and planned, when the run was cut short
+    partial: Option<(usize, usize)>,
+    // Real route of every payment, when the simulator's ground truth is known
+    ground_truth: Option<HashMap<String, Vec<String>>>,
+    // How well fingerprints grouped observations into payments, when they had to
+    linking: Option<LinkingAccuracy>,
 }
 
 impl SurveillanceReporter {
-    pub fn new(network: Arc<Mutex<LightningNetworkMap>>) -> Self {
-        SurveillanceReporter { network }
+    pub fn new(network: Arc<RwLock<LightningNetworkMap>>) -> Self {
+        SurveillanceReporter { network, partial: None, ground_truth: None, linking: None }
+    }
+
+    // Flag reports as covering only part of the planned payments
+    pub fn mark_partial(&mut self, simulated: usize, planned: usize) {
+        self.partial = Some((simulated, planned));
+    }
+
+    pub fn set_ground_truth(&mut self, records: &[PaymentRecord]) {
+        self.ground_truth = Some(records.iter()
+            .map(|record| (record.payment_hash.clone(), record.path.clone()))
+            .collect());
+    }
+
+    pub fn set_linking_accuracy(&mut self, accuracy: LinkingAccuracy) {
+        self.linking = Some(accuracy);
+    }
+
+    // Real route of every payment, by payment hash
+    pub(crate) fn ground_truth(&self) -> Option<&HashMap<String, Vec<String>>> {
+        self.ground_truth.as_ref()
+    }
+
+    // What a labels file says runs the node
+    fn label_of(&self, node_id: &str) -> Option<String> {
+        read_lock(&self.network).nodes.get(node_id)?.label.clone()
+    }
+
+    // Where the observer really sat, as (hop, route hops)
+    fn true_position(&self, payment_hash: &str, observer: &str) -> Option<(usize, usize)> {
+        let route = self.ground_truth.as_ref()?.get(payment_hash)?;
+        let hop = route.iter().position(|node| node == observer)?;
+        Some((hop, route.len() - 1))
+    }
+
+    // Of the payments a node was a likely recipient for, those it really received and those
+    // whose recipient is known
+    fn true_recipient_count(&self, exposure: &NodeExposure) -> Option<(usize, usize)> {
+        let routes = self.ground_truth.as_ref()?;
+        let receivers: Vec<&String> = exposure.payments.iter()
+            .filter_map(|hash| routes.get(hash)?.last())
+            .collect();
+        Some((receivers.iter().filter(|&&receiver| *receiver == exposure.node_id).count(), receivers.len()))
     }
 
     // Generate a text report of surveillance results
-    pub fn generate_text_report(&self, results: &HashMap<String, Vec<PotentialRecipient>>) -> String {
+    pub fn generate_text_report(&self,
+                                results: &HashMap<String, Vec<PotentialRecipient>>,
+                                communities: Option<&HashMap<String, Vec<CandidateCommunity>>>,
+                                inferences: &TrafficInferences) -> String {
         let mut report = String::from("## THELMA: Lightning Network Surveillance Report\n\n");
+        if let Some((simulated, planned)) = self.partial {
+            report.push_str(&format!("Partial report: interrupted after {} of {} payments\n\n", simulated, planned));
+        }
         report.push_str(&format!("Total unique payments observed: {}\n\n", results.len()));
+        if let Some(linking) = &self.linking {
+            report.push_str(&format!("Fingerprint linking: {} observations grouped into {} payments (really {}), \
+                                      pairwise precision {:.1}%, recall {:.1}%\n\n",
+                                     linking.observations, linking.clusters, linking.payments,
+                                     linking.precision() * 100.0, linking.recall() * 100.0));
+        }
+        if !inferences.probes.is_empty() {
+            let counts: Vec<String> = probe_signal_counts(&inferences.probes).iter()
+                .map(|(signal, count)| format!("{} {}", count, signal.name()))
+                .collect();
+            report.push_str(&format!("Probable probes left out of the analysis: {} payments ({})\n\n",
+                                     inferences.probes.len(), counts.join(", ")));
+        }
+        if !inferences.ordering_conflicts.is_empty() {
+            report.push_str(&format!("Payments whose observations order differently by amount than by timelock \
+                                      (decoys or nonstandard forwarding): {}\n\n",
+                                     inferences.ordering_conflicts.len()));
+        }
+        if !inferences.incomplete.is_empty() {
+            report.push_str(&format!("Payments whose analysis ran out of time (candidates found so far): {}\n\n",
+                                     inferences.incomplete.len()));
+        }
+        if let Some(routes) = &self.ground_truth {
+            let accuracy = PositionAccuracy::compute(&inferences.positions, routes);
+            report.push_str(&format!("Observer position inference: hop index right for {} of {} sightings ({:.1}%), \
+                                      within one hop for {:.1}%, route length right for {:.1}%\n\n",
+                                     accuracy.hop_correct, accuracy.sightings, accuracy.hop_accuracy() * 100.0,
+                                     accuracy.hop_close_rate() * 100.0, accuracy.route_length_accuracy() * 100.0));
+        }
+        if let Some(analysis) = &inferences.tor_timing {
+            report.push_str(&analysis.generate_text_section());
+        }
+        if let Some(inference) = &inferences.geography {
+            report.push_str(&inference.generate_text_section());
+        }
+        if let Some(identification) = &inferences.identification {
+            report.push_str(&identification.generate_text_section());
+        }
+        if let Some(labels) = &inferences.labels {
+            report.push_str(&labels.generate_text_section());
+        }
+        if let Some(memory) = &inferences.memory {
+            report.push_str(&memory.generate_text_section());
+        }
+
+        if !inferences.exposed_nodes.is_empty() {
+            report.push_str("### Most-Exposed Honest Nodes\n\n");
+            report.push_str("| Rank | Node | Likely recipient of | Top candidate for | Actually recipient |\n");
+            report.push_str("|---|---|---|---|---|\n");
+            let network = read_lock(&self.network);
+            for (i, exposure) in inferences.exposed_nodes.iter().enumerate() {
+                let alias = network.nodes.get(&exposure.node_id).map_or("Unknown Node", |n| n.alias.as_str());
+                let truth = match self.true_recipient_count(exposure) {
+                    Some((actual, known)) => format!("{} of {}", actual, known),
+                    None => "-".to_string(),
+                };
+                report.push_str(&format!("| {} | {} ({}) | {} payments | {} | {} |\n",
+                                         i + 1, alias, exposure.node_id, exposure.high_confidence(),
+                                         exposure.top_ranked, truth));
+            }
+            report.push('\n');
+        }
+        if let Some(analysis) = &inferences.false_positives {
+            report.push_str(&analysis.generate_text_section());
+        }
+        if let Some(curve) = &inferences.calibration {
+            report.push_str(&curve.generate_text_section());
+        }
 
         for (payment_hash, recipients) in results {
             report.push_str(&format!("### Payment Hash: {}\n", payment_hash));
+            if inferences.ordering_conflicts.contains(payment_hash) {
+                report.push_str("Warning: amount and timelock orderings of the observations disagree\n");
+            }
+            if inferences.incomplete.contains(payment_hash) {
+                report.push_str("Warning: analysis ran out of time, candidates are those found so far\n");
+            }
             report.push_str(&format!("Potential recipients identified: {}\n", recipients.len()));
 
             for (i, recipient) in recipients.iter().enumerate() {
-                let node_name = match &recipient.node_alias {
+                let mut node_name = match &recipient.node_alias {
                     Some(alias) => alias.clone(),
                     None => "Unknown Node".to_string(),
                 };
+                if let Some(label) = self.label_of(&recipient.node_id) {
+                    node_name.push_str(&format!(" [{}]", label));
+                }
 
                 report.push_str(&format!("{}. {} ({}) - Confidence: {:.2}\n",
                                          i+1, node_name, recipient.node_id, recipient.confidence_score));
@@ -44,7 +217,7 @@ impl SurveillanceReporter {
                         report.push_str(" → ");
                     }
 
-                    let network = self.network.lock().unwrap();
+                    let network = read_lock(&self.network);
                     let node_alias = match network.nodes.get(node) {
                         Some(n) => n.alias.clone(),
                         None => node.clone(),
@@ -52,9 +225,30 @@ impl SurveillanceReporter {
 
                     report.push_str(&node_alias);
                 }
-                report.push_str("\n");
+                report.push('\n');
+            }
+
+            for position in inferences.positions.get(payment_hash).into_iter().flatten() {
+                report.push_str(&format!("Observer {}: likely hop {} of {}", position.observer, position.hop,
+                                         position.route_hops));
+                if let Some((hop, route_hops)) = self.true_position(payment_hash, &position.observer) {
+                    report.push_str(&format!(" (actually hop {} of {})", hop, route_hops));
+                }
+                report.push('\n');
+            }
+
+            // Fall back to cluster-level candidates when no single node stands out
+            if let Some(summary) = communities.and_then(|c| c.get(payment_hash)) {
+                report.push_str("Low individual confidence - candidate communities:\n");
+                for community in summary {
+                    report.push_str(&format!("   Community {} ({} nodes) - {:.0}% of confidence: {}\n",
+                                             community.community_id,
+                                             community.community_size,
+                                             community.confidence_share * 100.0,
+                                             community.candidates.join(", ")));
+                }
             }
-            report.push_str("\n");
+            report.push('\n');
         }
 
         report
@@ -62,27 +256,125 @@ impl SurveillanceReporter {
 
     // Save report to file
     pub fn save_report_to_file(&self, results: &HashMap<String, Vec<PotentialRecipient>>,
-                               filename: &str) -> Result<(), Box<dyn Error>> {
-        let report = self.generate_text_report(results);
+                               communities: Option<&HashMap<String, Vec<CandidateCommunity>>>,
+                               inferences: &TrafficInferences,
+                               filename: &str) -> Result<(), ThelmaError> {
+        let report = self.generate_text_report(results, communities, inferences);
 
         let mut file = File::create(filename)?;
         file.write_all(report.as_bytes())?;
 
-        println!("Report saved to {}", filename);
+        info!("Report saved to {}", filename);
         Ok(())
     }
 
     // Generate a JSON report
-    pub fn generate_json_report(&self, results: &HashMap<String, Vec<PotentialRecipient>>) -> String {
+    pub fn generate_json_report(&self,
+                                results: &HashMap<String, Vec<PotentialRecipient>>,
+                                communities: Option<&HashMap<String, Vec<CandidateCommunity>>>,
+                                inferences: &TrafficInferences) -> String {
+        serde_json::to_string_pretty(&self.to_json(results, communities, inferences))
+            .unwrap_or_else(|_| "Error generating JSON report".to_string())
+    }
+
+    // The JSON report as a value, which report templates render from
+    pub fn to_json(&self,
+                   results: &HashMap<String, Vec<PotentialRecipient>>,
+                   communities: Option<&HashMap<String, Vec<CandidateCommunity>>>,
+                   inferences: &TrafficInferences) -> serde_json::Value {
         let mut report_data = serde_json::Map::new();
 
         report_data.insert("total_payments".to_string(),
                            serde_json::Value::Number(serde_json::Number::from(results.len())));
 
+        if let Some((simulated, planned)) = self.partial {
+            report_data.insert("partial".to_string(),
+                               serde_json::json!({ "simulated": simulated, "planned": planned }));
+        }
+
+        if let Some(routes) = &self.ground_truth {
+            let accuracy = PositionAccuracy::compute(&inferences.positions, routes);
+            report_data.insert("position_accuracy".to_string(), serde_json::json!({
+                "sightings": accuracy.sightings,
+                "hop_correct": accuracy.hop_correct,
+                "hop_within_one": accuracy.hop_close,
+                "route_length_correct": accuracy.route_length_correct,
+                "hop_accuracy": accuracy.hop_accuracy(),
+            }));
+        }
+
+        if let Some(linking) = &self.linking {
+            report_data.insert("fingerprint_linking".to_string(), serde_json::json!({
+                "observations": linking.observations,
+                "clusters": linking.clusters,
+                "payments": linking.payments,
+                "precision": linking.precision(),
+                "recall": linking.recall(),
+            }));
+        }
+
+        if !inferences.probes.is_empty() {
+            let signals: serde_json::Map<String, serde_json::Value> = probe_signal_counts(&inferences.probes).iter()
+                .map(|(signal, count)| (signal.name().replace(' ', "_"), serde_json::json!(count)))
+                .collect();
+            report_data.insert("probes_excluded".to_string(), serde_json::json!({
+                "payments": inferences.probes.len(),
+                "signals": signals,
+            }));
+        }
+
+        if !inferences.exposed_nodes.is_empty() {
+            let exposed: Vec<serde_json::Value> = inferences.exposed_nodes.iter()
+                .map(|exposure| {
+                    let truth = self.true_recipient_count(exposure);
+                    serde_json::json!({
+                        "node_id": exposure.node_id,
+                        "high_confidence_payments": exposure.high_confidence(),
+                        "top_ranked_payments": exposure.top_ranked,
+                        "actually_recipient": truth.map(|t| t.0),
+                        "recipient_known": truth.map(|t| t.1),
+                    })
+                })
+                .collect();
+            report_data.insert("most_exposed_honest_nodes".to_string(), serde_json::Value::Array(exposed));
+        }
+
+        if let Some(analysis) = &inferences.false_positives {
+            report_data.insert("false_positive_analysis".to_string(), analysis.to_json());
+        }
+
+        if let Some(curve) = &inferences.calibration {
+            report_data.insert("confidence_calibration".to_string(), curve.to_json());
+        }
+
+        if let Some(identification) = &inferences.identification {
+            report_data.insert("identification_accuracy".to_string(), identification.to_json());
+        }
+
+        if let Some(labels) = &inferences.labels {
+            report_data.insert("identification_by_label".to_string(), labels.to_json());
+        }
+
+        if let Some(analysis) = &inferences.tor_timing {
+            report_data.insert("tor_timing".to_string(), analysis.to_json());
+        }
+
+        if let Some(inference) = &inferences.geography {
+            report_data.insert("geography_inference".to_string(), inference.to_json());
+        }
+
+        if let Some(memory) = &inferences.memory {
+            report_data.insert("memory_usage".to_string(), memory.to_json());
+        }
+
         let mut payments = serde_json::Map::new();
 
         for (payment_hash, recipients) in results {
             let mut payment_data = serde_json::Map::new();
+            payment_data.insert("ordering_conflict".to_string(),
+                                serde_json::Value::Bool(inferences.ordering_conflicts.contains(payment_hash)));
+            payment_data.insert("incomplete".to_string(),
+                                serde_json::Value::Bool(inferences.incomplete.contains(payment_hash)));
             payment_data.insert("recipient_count".to_string(),
                                 serde_json::Value::Number(serde_json::Number::from(recipients.len())));
 
@@ -99,6 +391,10 @@ impl SurveillanceReporter {
                                           serde_json::Value::String(alias.clone()));
                 }
 
+                if let Some(label) = self.label_of(&recipient.node_id) {
+                    recipient_data.insert("node_label".to_string(), serde_json::Value::String(label));
+                }
+
                 recipient_data.insert("confidence".to_string(),
                                       serde_json::Value::Number(
                                           serde_json::Number::from_f64(recipient.confidence_score as f64)
@@ -116,12 +412,53 @@ impl SurveillanceReporter {
             payment_data.insert("potential_recipients".to_string(),
                                 serde_json::Value::Array(recipients_data));
 
+            if let Some(observers) = inferences.positions.get(payment_hash) {
+                let positions_data: Vec<serde_json::Value> = observers.iter()
+                    .map(|position| {
+                        let truth = self.true_position(payment_hash, &position.observer);
+                        serde_json::json!({
+                            "observer": position.observer,
+                            "hop": position.hop,
+                            "route_hops": position.route_hops,
+                            "true_hop": truth.map(|t| t.0),
+                            "true_route_hops": truth.map(|t| t.1),
+                        })
+                    })
+                    .collect();
+
+                payment_data.insert("observer_positions".to_string(), serde_json::Value::Array(positions_data));
+            }
+
+            if let Some(summary) = communities.and_then(|c| c.get(payment_hash)) {
+                let communities_data: Vec<serde_json::Value> = summary.iter()
+                    .map(|community| serde_json::json!({
+                        "community_id": community.community_id,
+                        "community_size": community.community_size,
+                        "candidates": community.candidates,
+                        "confidence_share": community.confidence_share,
+                    }))
+                    .collect();
+
+                payment_data.insert("candidate_communities".to_string(),
+                                    serde_json::Value::Array(communities_data));
+            }
+
             payments.insert(payment_hash.clone(), serde_json::Value::Object(payment_data));
         }
 
         report_data.insert("payments".to_string(), serde_json::Value::Object(payments));
 
-        serde_json::to_string_pretty(&serde_json::Value::Object(report_data))
-            .unwrap_or_else(|_| "Error generating JSON report".to_string())
+        serde_json::Value::Object(report_data)
+    }
+}
+
+// Probable probes flagged by each signal, in signal order
+fn probe_signal_counts(probes: &HashMap<String, Vec<ProbeSignal>>) -> Vec<(ProbeSignal, usize)> {
+    let mut counts: HashMap<ProbeSignal, usize> = HashMap::new();
+    for signal in probes.values().flatten() {
+        *counts.entry(*signal).or_default() += 1;
     }
+    let mut counts: Vec<(ProbeSignal, usize)> = counts.into_iter().collect();
+    counts.sort();
+    counts
 }
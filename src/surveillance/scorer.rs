@@ -0,0 +1,59 @@
+// Confidence scoring for candidate routes
+
+use crate::models::{LightningNetworkMap, TimelockAnalysis};
+
+// How much the attacker believes a candidate route is the one the payment took. Scores
+// are relative: candidates for an observation are ranked by them.
+pub trait ConfidenceScorer: Send + Sync {
+    fn score(&self,
+             network: &LightningNetworkMap,
+             route: &[String],
+             analysis: &TimelockAnalysis,
+             amount_msat: u64) -> f32;
+}
+
+// Prefers short, consistent routes ending where the final hop could be, weighted by how
+// plausibly the route's channels carry the amount
+pub struct HeuristicScorer;
+
+impl ConfidenceScorer for HeuristicScorer {
+    fn score(&self,
+             network: &LightningNetworkMap,
+             route: &[String],
+             analysis: &TimelockAnalysis,
+             amount_msat: u64) -> f32 {
+        // Base confidence starts at 1.0
+        let mut confidence = 1.0;
+
+        // Penalize longer routes (prefer shorter)
+        confidence *= 1.0 / (route.len() as f32).powf(0.5);
+
+        // Boost if could be final hop and route is short
+        if analysis.could_be_final_hop && route.len() <= 2 {
+            confidence *= 1.5;
+        }
+
+        // Penalize route if links are not consistent
+        let mut consistent = true;
+        for i in 0..route.len().saturating_sub(1) {
+            let from = &route[i];
+            let to = &route[i + 1];
+
+            if let Some(neighbors) = network.get_neighbors(from) {
+                if !neighbors.contains(to) {
+                    consistent = false;
+                    break;
+                }
+            } else {
+                consistent = false;
+                break;
+            }
+        }
+
+        if !consistent {
+            confidence *= 0.1;
+        }
+
+        confidence * network.route_capacity_plausibility(route, amount_msat)
+    }
+}
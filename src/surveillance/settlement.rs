@@ -0,0 +1,90 @@
+// Settlement timing: a forwarding node sees its HTLC settle once the preimage has come back
+// from the recipient, so the wait grows with every hop still ahead of it. The adversary
+// times the settles and failures its nodes see and makes routes whose length doesn't fit
+// the wait less likely.
+
+use std::collections::HashMap;
+
+use crate::models::{HTLC, HtlcResolution};
+use crate::simulation::latency::{HopLatency, DEFAULT_HOP_JITTER};
+
+// Weight of a route whose length fits the observed wait as badly as can be
+const MIN_TIMING_WEIGHT: f64 = 0.01;
+// Spread assumed for any wait, covering processing the latency model leaves out
+const MIN_SPREAD_MS: f64 = 1.0;
+
+// How the adversary thinks messages cross links, and the resolutions its nodes saw
+#[derive(Debug, Clone)]
+pub struct SettlementTiming {
+    pub latency: HopLatency,
+    // (lock, observer) to whether it settled and after how long
+    resolutions: HashMap<(String, String), (bool, u64)>,
+}
+
+impl SettlementTiming {
+    pub fn new(hop_ms: u64) -> Self {
+        SettlementTiming { latency: HopLatency::new(hop_ms).jitter(DEFAULT_HOP_JITTER), resolutions: HashMap::new() }
+    }
+
+    // A later attempt under the same lock replaces an earlier one
+    pub fn record(&mut self, resolution: &HtlcResolution) {
+        self.resolutions.insert((resolution.payment_hash.clone(), resolution.observed_by_node.clone()),
+                                (resolution.settled, resolution.hold_ms));
+    }
+
+    pub fn len(&self) -> usize {
+        self.resolutions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.resolutions.is_empty()
+    }
+
+    // (lock, observer, settled, hold_ms) of every resolution seen
+    pub fn resolutions(&self) -> impl Iterator<Item = (&str, &str, bool, u64)> {
+        self.resolutions.iter()
+            .map(|((lock, observer), &(settled, hold_ms))| (lock.as_str(), observer.as_str(), settled, hold_ms))
+    }
+
+    // Likelihood of the wait the observer of `htlc` saw if the payment went `hops` further,
+    // each a crossing both ways, scaled so the best fitting length weighs 1. A failure came
+    // from some node on the way, so it only rules out routes too short to have taken that
+    // long. Unresolved HTLCs leave every route as likely.
+    pub fn weight(&self, htlc: &HTLC, hops: usize) -> f64 {
+        let key = (htlc.payment_hash.clone(), htlc.observed_by_node.clone());
+        let Some(&(settled, hold_ms)) = self.resolutions.get(&key) else { return 1.0 };
+        let crossings = 2.0 * hops as f64;
+        let mean_ms = self.latency.mean_ms as f64;
+        let expected = crossings * mean_ms;
+        if !settled && expected >= hold_ms as f64 {
+            return 1.0;
+        }
+        // Each crossing is uniform within the jitter around the mean
+        let spread = (crossings / 3.0).sqrt() * mean_ms * self.latency.jitter;
+        let z = (hold_ms as f64 - expected) / spread.max(MIN_SPREAD_MS);
+        (-z * z / 2.0).exp().max(MIN_TIMING_WEIGHT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wait_picks_route_length() {
+        let mut timing = SettlementTiming::new(100);
+        let htlc = HTLC::new("hash", 700_100, 50_000, 700_000, "a");
+        assert_eq!(timing.weight(&htlc, 2), 1.0);
+
+        // Two hops to go take four crossings
+        timing.record(&HtlcResolution::new("hash", "a", true, 400));
+        assert_eq!(timing.weight(&htlc, 2), 1.0);
+        assert!(timing.weight(&htlc, 2) > timing.weight(&htlc, 1));
+        assert_eq!(timing.weight(&htlc, 5), MIN_TIMING_WEIGHT);
+
+        // A failure that far along rules out only the shorter routes
+        timing.record(&HtlcResolution::new("hash", "a", false, 400));
+        assert_eq!(timing.weight(&htlc, 5), 1.0);
+        assert_eq!(timing.weight(&htlc, 1), MIN_TIMING_WEIGHT);
+    }
+}
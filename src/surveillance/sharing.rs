@@ -0,0 +1,179 @@
+// Observation sharing: colluding nodes don't see each other's observations the moment they're
+// made. Each one reaches the adversary's hub some payments later and costs something to
+// send, so an analysis run at a given time only has what was shared by then. Comparing that
+// against the full record tells real-time attacks from retrospective ones.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use log::info;
+
+use crate::models::HTLC;
+use crate::surveillance::coalition::CoalitionResult;
+use crate::error::ThelmaError;
+
+// When each observation was made and reached the hub. Time counts finished payments, the
+// same clock traces use.
+#[derive(Debug, Clone)]
+pub struct ObservationSharing {
+    pub delay_payments: u64,
+    // Paid for every observation sent to the hub
+    pub cost_msat: u64,
+    // Node the analysis runs at, whose own observations need no sharing; the first of our
+    // nodes when unset
+    pub hub: Option<String>,
+    clock: u64,
+    // (lock, observer, block) to the payment the observation was made during
+    made: HashMap<(String, String, u32), u64>,
+    shared: u64,
+}
+
+impl ObservationSharing {
+    pub fn new(delay_payments: u64) -> Self {
+        ObservationSharing { delay_payments, cost_msat: 0, hub: None, clock: 0, made: HashMap::new(), shared: 0 }
+    }
+
+    pub fn cost_msat(mut self, cost_msat: u64) -> Self {
+        self.cost_msat = cost_msat;
+        self
+    }
+
+    pub fn hub(mut self, node: &str) -> Self {
+        self.hub = Some(node.to_string());
+        self
+    }
+
+    // A payment finished
+    pub fn tick(&mut self) {
+        self.clock += 1;
+    }
+
+    // One of our nodes made an observation now, and sends it on unless it's the hub
+    pub fn record(&mut self, htlc: &HTLC) {
+        self.made.insert(key(htlc), self.clock);
+        if self.hub.as_deref() != Some(htlc.observed_by_node.as_str()) {
+            self.shared += 1;
+        }
+    }
+
+    // Payment during which an observation was made, if it was recorded
+    pub fn made_at(&self, htlc: &HTLC) -> Option<u64> {
+        self.made.get(&key(htlc)).copied()
+    }
+
+    // Payment by whose end the hub had the observation. Ones never recorded, like those
+    // restored from a checkpoint, count as shared from the start.
+    pub fn available_at(&self, htlc: &HTLC) -> u64 {
+        let Some(made) = self.made_at(htlc) else { return 0 };
+        if self.hub.as_deref() == Some(htlc.observed_by_node.as_str()) {
+            made
+        } else {
+            made + self.delay_payments
+        }
+    }
+
+    // Observations sent to the hub so far, and what sending them cost
+    pub fn shared(&self) -> u64 {
+        self.shared
+    }
+
+    pub fn total_cost_msat(&self) -> u64 {
+        self.shared * self.cost_msat
+    }
+}
+
+// How the adversary did analyzing at different times, next to what sharing cost it
+pub struct SharingReport {
+    pub delay_payments: u64,
+    pub hub: Option<String>,
+    pub shared: u64,
+    pub cost_msat: u64,
+    analyses: Vec<CoalitionResult>,
+}
+
+impl SharingReport {
+    pub fn new(sharing: &ObservationSharing) -> Self {
+        SharingReport {
+            delay_payments: sharing.delay_payments,
+            hub: sharing.hub.clone(),
+            shared: sharing.shared(),
+            cost_msat: sharing.total_cost_msat(),
+            analyses: Vec::new(),
+        }
+    }
+
+    // Results of one analysis, labeled by when it ran
+    pub fn add_analysis(&mut self, result: CoalitionResult) {
+        self.analyses.push(result);
+    }
+
+    pub fn generate_text_report(&self) -> String {
+        let mut report = String::from("## THELMA: Observation Sharing\n\n");
+        report.push_str(&format!("Observations reach {} {} payments after they're made.\n",
+                                 self.hub.as_deref().unwrap_or("the hub"), self.delay_payments));
+        report.push_str(&format!("Observations shared: {} for {:.3} sat\n\n", self.shared, self.cost_msat as f64 / 1000.0));
+        report.push_str("| Analysis | Identified | Identified when observed | Recipient in candidates | Avg anonymity set |\n");
+        report.push_str("|---|---|---|---|---|\n");
+        for result in &self.analyses {
+            report.push_str(&format!("| {} | {:.1}% | {:.1}% | {} | {:.2} |\n",
+                                     result.label,
+                                     result.identification_rate() * 100.0,
+                                     result.identification_rate_when_observed() * 100.0,
+                                     result.recipients_in_candidates,
+                                     result.avg_anonymity_set));
+        }
+        report
+    }
+
+    pub fn generate_json_report(&self) -> String {
+        let analyses: Vec<serde_json::Value> = self.analyses.iter()
+            .map(|result| serde_json::json!({
+                "label": result.label,
+                "recipients_identified": result.recipients_identified,
+                "recipients_in_candidates": result.recipients_in_candidates,
+                "identification_rate": result.identification_rate(),
+                "avg_anonymity_set": result.avg_anonymity_set,
+            }))
+            .collect();
+        serde_json::to_string_pretty(&serde_json::json!({
+            "delay_payments": self.delay_payments,
+            "hub": self.hub,
+            "observations_shared": self.shared,
+            "cost_msat": self.cost_msat,
+            "analyses": analyses,
+        })).unwrap_or_else(|_| "Error generating JSON report".to_string())
+    }
+
+    pub fn save_report_to_file(&self, filename: &str) -> Result<(), ThelmaError> {
+        let mut file = File::create(filename)?;
+        file.write_all(self.generate_text_report().as_bytes())?;
+
+        info!("Observation sharing report saved to {}", filename);
+        Ok(())
+    }
+}
+
+fn key(htlc: &HTLC) -> (String, String, u32) {
+    (htlc.payment_hash.clone(), htlc.observed_by_node.clone(), htlc.observed_at_block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_observations_reach_the_hub_late() {
+        let mut sharing = ObservationSharing::new(3).cost_msat(10).hub("hub");
+        let at_hub = HTLC::new("h1", 700_100, 50_000, 700_000, "hub");
+        let elsewhere = HTLC::new("h1", 700_140, 51_000, 700_000, "m2");
+        sharing.tick();
+        sharing.record(&at_hub);
+        sharing.record(&elsewhere);
+
+        assert_eq!(sharing.available_at(&at_hub), 1);
+        assert_eq!(sharing.available_at(&elsewhere), 4);
+        assert_eq!(sharing.available_at(&HTLC::new("h2", 700_100, 50_000, 700_000, "m2")), 0);
+        assert_eq!(sharing.shared(), 1);
+        assert_eq!(sharing.total_cost_msat(), 10);
+    }
+}
@@ -0,0 +1,168 @@
+// Pluggable attacker behavior
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use crate::graph::{CentralityMeasure, CentralityScores};
+use crate::models::{Channel, LightningNetworkMap};
+
+// Decides how the adversary attacks: which nodes it compromises, how it spends capital on
+// new channels and when it probes its observations mid-run. New attacks implement this
+// rather than changing the surveillance operation.
+pub trait AdversaryStrategy: Send {
+    fn name(&self) -> String;
+
+    // Nodes to compromise as observers
+    fn select_nodes(&mut self, network: &LightningNetworkMap, count: usize) -> Vec<String>;
+
+    // Channels the compromised nodes open with `capital_sat` of fresh capital. By default
+    // it's split evenly into one channel per node, towards the best-connected node it isn't
+    // already linked to, which pulls more traffic through the observers.
+    fn allocate_budget(&mut self,
+                       network: &LightningNetworkMap,
+                       nodes: &[String],
+                       capital_sat: u64) -> Vec<Channel> {
+        if nodes.is_empty() || capital_sat == 0 {
+            return Vec::new();
+        }
+
+        let mut by_degree: Vec<&String> = network.nodes.keys().collect();
+        by_degree.sort_by(|a, b| network.degree(b).cmp(&network.degree(a)).then(a.cmp(b)));
+
+        let per_node = capital_sat / nodes.len() as u64;
+        nodes.iter()
+            .filter_map(|node| {
+                let target = by_degree.iter()
+                    .find(|&&peer| peer != node && network.channels_between(node, peer).is_empty())?;
+                Some(Channel::new(&format!("adversary-{}-{}", node, target), node, target, per_node))
+            })
+            .collect()
+    }
+
+    // Whether to run an interim analysis now that this many observations are in
+    fn should_probe(&mut self, _observations: usize) -> bool {
+        false
+    }
+}
+
+// Compromise nodes uniformly at random, never probe mid-run
+pub struct RandomPlacement {
+    rng: StdRng,
+}
+
+impl RandomPlacement {
+    pub fn new() -> Self {
+        RandomPlacement { rng: StdRng::from_rng(&mut rand::rng()) }
+    }
+
+    // Same picks every time for the same seed and network
+    pub fn seeded(seed: u64) -> Self {
+        RandomPlacement { rng: StdRng::seed_from_u64(seed) }
+    }
+}
+
+impl Default for RandomPlacement {
+    fn default() -> Self {
+        RandomPlacement::new()
+    }
+}
+
+impl AdversaryStrategy for RandomPlacement {
+    fn name(&self) -> String {
+        "random".to_string()
+    }
+
+    fn select_nodes(&mut self, network: &LightningNetworkMap, count: usize) -> Vec<String> {
+        // Sorted so a seeded strategy picks the same nodes every time
+        let mut all_nodes: Vec<String> = network.nodes.keys().cloned().collect();
+        all_nodes.sort();
+
+        // Shuffle indices and take the first `count`
+        let mut indices: Vec<usize> = (0..all_nodes.len()).collect();
+        for i in 0..indices.len() {
+            let j = self.rng.random_range(i..indices.len());
+            indices.swap(i, j);
+        }
+
+        indices.into_iter()
+            .take(count)
+            .map(|i| all_nodes[i].clone())
+            .collect()
+    }
+}
+
+// Compromise the most central nodes, as a well-resourced attacker would
+pub struct CentralPlacement {
+    measure: CentralityMeasure,
+}
+
+impl CentralPlacement {
+    pub fn new(measure: CentralityMeasure) -> Self {
+        CentralPlacement { measure }
+    }
+}
+
+impl AdversaryStrategy for CentralPlacement {
+    fn name(&self) -> String {
+        format!("central ({:?})", self.measure).to_lowercase()
+    }
+
+    fn select_nodes(&mut self, network: &LightningNetworkMap, count: usize) -> Vec<String> {
+        CentralityScores::compute(network)
+            .ranked(self.measure)
+            .into_iter()
+            .take(count)
+            .map(|(node, _)| node)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Node;
+
+    // Plugin strategy: always takes the alphabetically first nodes and probes every 2 observations
+    struct FirstNodes;
+
+    impl AdversaryStrategy for FirstNodes {
+        fn name(&self) -> String {
+            "first".to_string()
+        }
+
+        fn select_nodes(&mut self, network: &LightningNetworkMap, count: usize) -> Vec<String> {
+            let mut nodes: Vec<String> = network.nodes.keys().cloned().collect();
+            nodes.sort();
+            nodes.truncate(count);
+            nodes
+        }
+
+        fn should_probe(&mut self, observations: usize) -> bool {
+            observations.is_multiple_of(2)
+        }
+    }
+
+    #[test]
+    fn test_custom_strategy_with_default_budget_allocation() {
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["a", "b", "hub", "x"] {
+            network.add_node(Node::new(key, key, 40));
+        }
+        network.add_channel(Channel::new("a-hub", "a", "hub", 1_000_000));
+        network.add_channel(Channel::new("x-hub", "x", "hub", 1_000_000));
+
+        let mut strategy = FirstNodes;
+        let nodes = strategy.select_nodes(&network, 2);
+        assert_eq!(nodes, vec!["a".to_string(), "b".to_string()]);
+
+        // a already has a channel to the hub, so it links to the next best-connected node
+        let channels = strategy.allocate_budget(&network, &nodes, 2_000_000);
+        assert_eq!(channels.len(), 2);
+        assert_eq!((channels[0].node1.as_str(), channels[0].node2.as_str()), ("a", "x"));
+        assert_eq!((channels[1].node1.as_str(), channels[1].node2.as_str()), ("b", "hub"));
+        assert!(channels.iter().all(|c| c.capacity == 1_000_000));
+
+        assert!(!strategy.should_probe(1));
+        assert!(strategy.should_probe(2));
+    }
+}
@@ -0,0 +1,118 @@
+// User-supplied report templates: Jinja-style files rendered with the JSON report as their
+// context, so reports can take any shape (paper tables, slides, other tools' formats) without
+// changing the reporter. See templates/ for examples.
+
+use std::path::Path;
+use minijinja::Environment;
+
+use crate::error::ThelmaError;
+use crate::latex;
+
+// Extensions that mark a file as a template rather than part of the output's name
+const TEMPLATE_EXTENSIONS: &[&str] = &["j2", "jinja", "jinja2"];
+
+// A parsed template, ready to render any number of reports
+pub struct ReportTemplate {
+    name: String,
+    environment: Environment<'static>,
+}
+
+impl ReportTemplate {
+    // Parse a template, failing on syntax errors before any report is rendered
+    pub fn new(name: &str, source: &str) -> Result<Self, ThelmaError> {
+        let mut environment = Environment::new();
+        environment.add_filter("percent", percent);
+        environment.add_filter("fixed", fixed);
+        environment.add_filter("latex", latex::escape);
+        environment.add_template_owned(name.to_string(), source.to_string())?;
+        Ok(ReportTemplate { name: name.to_string(), environment })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ThelmaError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)?;
+        let name = path.file_name().map_or_else(|| path.display().to_string(),
+                                                |name| name.to_string_lossy().into_owned());
+        Self::new(&name, &source)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // Where the rendered report is saved: `paper.tex.j2` goes to `thelma_report_paper.tex`
+    pub fn output_file(&self) -> String {
+        let name = match self.name.rsplit_once('.') {
+            Some((stem, extension)) if TEMPLATE_EXTENSIONS.contains(&extension) => stem,
+            _ => &self.name,
+        };
+        format!("thelma_report_{}", name)
+    }
+
+    pub fn render(&self, report: &serde_json::Value) -> Result<String, ThelmaError> {
+        Ok(self.environment.get_template(&self.name)?.render(report)?)
+    }
+}
+
+// `{{ 0.4567 | percent }}` is 45.7%, `{{ 0.4567 | percent(0) }}` 46%
+fn percent(value: f64, digits: Option<usize>) -> String {
+    format!("{:.*}%", digits.unwrap_or(1), value * 100.0)
+}
+
+// `{{ 0.5 | fixed(3) }}` is 0.500, unlike `round` which drops trailing zeros
+fn fixed(value: f64, digits: Option<usize>) -> String {
+    format!("{:.*}", digits.unwrap_or(2), value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_templates_render_the_report_and_bundled_examples_stay_valid() {
+        let report = serde_json::json!({
+            "total_payments": 2,
+            "identification_accuracy": {
+                "payments": 2,
+                "in_candidates": 2,
+                "top_k": [{ "k": 1, "payments": 1, "rate": 0.5 }],
+                "set_sizes": [],
+            },
+            "payments": {
+                "hash_a": {
+                    "recipient_count": 1,
+                    "incomplete": false,
+                    "ordering_conflict": false,
+                    "potential_recipients": [
+                        { "node_id": "node_7", "node_alias": "Bob & Co", "confidence": 0.8, "route": ["node_3", "node_7"] },
+                    ],
+                },
+                "hash_b": {
+                    "recipient_count": 0,
+                    "incomplete": true,
+                    "ordering_conflict": false,
+                    "potential_recipients": [],
+                },
+            },
+        });
+
+        let template = ReportTemplate::new("table.tex.j2", "{{ total_payments }} payments, top-1 \
+            {{ identification_accuracy.top_k[0].rate | percent }}\n\
+            {% for hash, payment in payments | dictsort %}{{ hash | latex }}: \
+            {% for r in payment.potential_recipients %}{{ r.node_alias | latex }} ({{ r.confidence | fixed(3) }}){% endfor %}\n\
+            {% endfor %}").unwrap();
+        assert_eq!(template.output_file(), "thelma_report_table.tex");
+        assert_eq!(template.render(&report).unwrap(),
+                   "2 payments, top-1 50.0%\nhash\\_a: Bob \\& Co (0.800)\nhash\\_b: \n");
+
+        // Templates that don't parse fail up front, and names without a template extension stay whole
+        assert!(matches!(ReportTemplate::new("broken.md", "{% for %}"), Err(ThelmaError::Template(_))));
+        assert_eq!(ReportTemplate::new("summary.md", "").unwrap().output_file(), "thelma_report_summary.md");
+
+        for (name, source) in [("paper.tex.j2", include_str!("../../templates/paper.tex.j2")),
+                               ("summary.md.j2", include_str!("../../templates/summary.md.j2"))] {
+            let rendered = ReportTemplate::new(name, source).unwrap().render(&report).unwrap();
+            assert!(rendered.contains("Bob \\& Co") || rendered.contains("Bob & Co"), "{}", name);
+        }
+    }
+}
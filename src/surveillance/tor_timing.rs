@@ -0,0 +1,220 @@
+// Tor-only nodes forward over circuits, so HTLCs routed through them take longer and vary
+// more to settle. This asks whether the waits our nodes time give away a Tor hop further
+// along the route, and how those hops change what timing and timelocks say about positions.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::models::LightningNetworkMap;
+use crate::surveillance::position::ObserverPosition;
+use crate::surveillance::settlement::SettlementTiming;
+
+// Sightings whose route went on through a Tor-only node, or didn't
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TimingGroup {
+    // Settled HTLCs our nodes timed
+    pub resolutions: usize,
+    wait_per_hop_ms: f64,
+    // Remaining hops read off the wait correctly, and how far off the reading was in all
+    pub hops_read_correctly: usize,
+    hop_errors: usize,
+    // Observers placed on routes from timelocks, and those placed at the right hop
+    pub positions: usize,
+    pub positions_correct: usize,
+}
+
+impl TimingGroup {
+    pub fn mean_wait_per_hop_ms(&self) -> f64 {
+        avg(self.wait_per_hop_ms, self.resolutions)
+    }
+
+    pub fn hop_reading_accuracy(&self) -> f64 {
+        avg(self.hops_read_correctly as f64, self.resolutions)
+    }
+
+    pub fn mean_hop_error(&self) -> f64 {
+        avg(self.hop_errors as f64, self.resolutions)
+    }
+
+    pub fn position_accuracy(&self) -> f64 {
+        avg(self.positions_correct as f64, self.positions)
+    }
+}
+
+fn avg(total: f64, count: usize) -> f64 {
+    if count == 0 { 0.0 } else { total / count as f64 }
+}
+
+#[derive(Debug, Clone)]
+pub struct TorTimingAnalysis {
+    pub tor_nodes: usize,
+    pub tor: TimingGroup,
+    pub clearnet: TimingGroup,
+    // Chance a sighting with a Tor hop ahead waited longer per hop than one without: 0.5
+    // means the waits can't tell them apart. None without timed sightings of both kinds.
+    pub detection_auc: Option<f64>,
+}
+
+impl TorTimingAnalysis {
+    // None in a network without Tor-only nodes. Timing is only scored when the adversary
+    // timed settlements.
+    pub fn compute(network: &LightningNetworkMap,
+                   timing: Option<&SettlementTiming>,
+                   positions: &HashMap<String, Vec<ObserverPosition>>,
+                   routes: &HashMap<String, Vec<String>>) -> Option<Self> {
+        let tor_nodes: HashSet<&String> = network.nodes.values()
+            .filter(|node| node.tor_only)
+            .map(|node| &node.pub_key)
+            .collect();
+        if tor_nodes.is_empty() {
+            return None;
+        }
+        // Where the observer sat on the route, and whether a Tor-only node lay ahead of it
+        let ahead = |payment_hash: &str, observer: &str| {
+            let route = routes.get(payment_hash)?;
+            let hop = route.iter().position(|node| node == observer)?;
+            Some((hop, route.len() - 1 - hop, route[hop..].iter().any(|node| tor_nodes.contains(node))))
+        };
+
+        let mut analysis = TorTimingAnalysis {
+            tor_nodes: tor_nodes.len(),
+            tor: TimingGroup::default(),
+            clearnet: TimingGroup::default(),
+            detection_auc: None,
+        };
+        let (mut tor_waits, mut clearnet_waits) = (Vec::new(), Vec::new());
+        if let Some(timing) = timing {
+            let crossing_ms = timing.latency.mean_ms.max(1) as f64;
+            for (lock, observer, settled, hold_ms) in timing.resolutions() {
+                let Some((_, remaining, tor)) = ahead(lock, observer).filter(|_| settled) else { continue };
+                if remaining == 0 {
+                    continue;
+                }
+                let (group, waits) = if tor {
+                    (&mut analysis.tor, &mut tor_waits)
+                } else {
+                    (&mut analysis.clearnet, &mut clearnet_waits)
+                };
+                let per_hop = hold_ms as f64 / remaining as f64;
+                let read = ((hold_ms as f64 / (2.0 * crossing_ms)).round() as usize).max(1);
+                group.resolutions += 1;
+                group.wait_per_hop_ms += per_hop;
+                group.hop_errors += read.abs_diff(remaining);
+                if read == remaining {
+                    group.hops_read_correctly += 1;
+                }
+                waits.push(per_hop);
+            }
+        }
+        for (payment_hash, observers) in positions {
+            for position in observers {
+                let Some((hop, _, tor)) = ahead(payment_hash, &position.observer) else { continue };
+                let group = if tor { &mut analysis.tor } else { &mut analysis.clearnet };
+                group.positions += 1;
+                if hop == position.hop {
+                    group.positions_correct += 1;
+                }
+            }
+        }
+        analysis.detection_auc = auc(&tor_waits, &clearnet_waits);
+        Some(analysis)
+    }
+
+    pub fn generate_text_section(&self) -> String {
+        let mut section = String::from("### Tor Timing\n\n");
+        section.push_str(&format!("{} nodes are reachable only over Tor. ", self.tor_nodes));
+        match self.detection_auc {
+            Some(auc) => section.push_str(&format!("Settlement waits per remaining hop tell a Tor hop ahead \
+                                                   from none with AUC {:.3} (0.5: no better than chance).\n\n", auc)),
+            None => section.push_str("Settlement waits weren't timed for routes of both kinds.\n\n"),
+        }
+        section.push_str("| Route ahead | Settles timed | Mean wait per hop | Hops read off the wait | Mean hop error | Positions inferred | Position correct |\n");
+        section.push_str("|---|---|---|---|---|---|---|\n");
+        for (label, group) in [("Through Tor", &self.tor), ("Clearnet only", &self.clearnet)] {
+            let timing = if group.resolutions == 0 {
+                "0 | - | - | -".to_string()
+            } else {
+                format!("{} | {:.0} ms | {:.1}% | {:.2}",
+                        group.resolutions, group.mean_wait_per_hop_ms(),
+                        group.hop_reading_accuracy() * 100.0, group.mean_hop_error())
+            };
+            section.push_str(&format!("| {} | {} | {} | {:.1}% |\n",
+                                      label, timing, group.positions, group.position_accuracy() * 100.0));
+        }
+        section.push('\n');
+        section
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let group = |group: &TimingGroup| serde_json::json!({
+            "settles_timed": group.resolutions,
+            "mean_wait_per_hop_ms": group.mean_wait_per_hop_ms(),
+            "hops_read_correctly": group.hops_read_correctly,
+            "hop_reading_accuracy": group.hop_reading_accuracy(),
+            "mean_hop_error": group.mean_hop_error(),
+            "positions_inferred": group.positions,
+            "positions_correct": group.positions_correct,
+            "position_accuracy": group.position_accuracy(),
+        });
+        serde_json::json!({
+            "tor_nodes": self.tor_nodes,
+            "detection_auc": self.detection_auc,
+            "through_tor": group(&self.tor),
+            "clearnet_only": group(&self.clearnet),
+        })
+    }
+}
+
+// Chance a positive scores above a negative, ties counting half
+fn auc(positives: &[f64], negatives: &[f64]) -> Option<f64> {
+    if positives.is_empty() || negatives.is_empty() {
+        return None;
+    }
+    let mut negatives = negatives.to_vec();
+    negatives.sort_by(f64::total_cmp);
+    let wins: f64 = positives.iter()
+        .map(|score| {
+            let below = negatives.partition_point(|n| n < score);
+            let tied = negatives.partition_point(|n| n <= score) - below;
+            below as f64 + tied as f64 / 2.0
+        })
+        .sum();
+    Some(wins / (positives.len() * negatives.len()) as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{HtlcResolution, Node};
+
+    #[test]
+    fn test_tor_hops_ahead_stretch_the_wait() {
+        let mut network = LightningNetworkMap::new(700000);
+        for key in ["s", "m", "x", "t", "r"] {
+            network.add_node(Node::new(key, key, 40));
+        }
+        let routes: HashMap<String, Vec<String>> = [("h1", vec!["s", "m", "x", "r"]), ("h2", vec!["s", "m", "t", "r"])]
+            .into_iter()
+            .map(|(hash, route)| (hash.to_string(), route.into_iter().map(str::to_string).collect()))
+            .collect();
+        let positions = HashMap::from([
+            ("h1".to_string(), vec![ObserverPosition { observer: "m".to_string(), hop: 1, route_hops: 3 }]),
+            ("h2".to_string(), vec![ObserverPosition { observer: "m".to_string(), hop: 2, route_hops: 4 }]),
+        ]);
+        assert!(TorTimingAnalysis::compute(&network, None, &positions, &routes).is_none());
+
+        network.nodes.get_mut("t").unwrap().tor_only = true;
+        let mut timing = SettlementTiming::new(100);
+        // Two hops to go: 400 ms over clearnet, far longer through t
+        timing.record(&HtlcResolution::new("h1", "m", true, 400));
+        timing.record(&HtlcResolution::new("h2", "m", true, 1400));
+        let analysis = TorTimingAnalysis::compute(&network, Some(&timing), &positions, &routes).unwrap();
+
+        assert_eq!(analysis.tor_nodes, 1);
+        assert_eq!((analysis.clearnet.resolutions, analysis.clearnet.hops_read_correctly), (1, 1));
+        assert_eq!((analysis.tor.resolutions, analysis.tor.hops_read_correctly, analysis.tor.mean_hop_error()), (1, 0, 5.0));
+        assert_eq!(analysis.tor.mean_wait_per_hop_ms(), 700.0);
+        assert_eq!((analysis.tor.positions, analysis.tor.positions_correct), (1, 0));
+        assert_eq!(analysis.clearnet.position_accuracy(), 1.0);
+        assert_eq!(analysis.detection_auc, Some(1.0));
+    }
+}
@@ -0,0 +1,86 @@
+// Observation windows: a compromised node isn't necessarily watching all the time. Each node
+// is online for a share of every period and sees nothing the rest of it, with periods
+// offset per node so the adversary's nodes don't all go dark together.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+// Payments a node's online and offline stretches repeat over by default
+pub const DEFAULT_UPTIME_PERIOD: u64 = 100;
+
+// Which of the adversary's nodes are watching as payments go by. Time counts finished
+// payments, the same clock traces use.
+#[derive(Debug, Clone)]
+pub struct ObservationWindows {
+    pub uptime: f64,
+    pub period: u64,
+    clock: u64,
+    missed: u64,
+}
+
+impl ObservationWindows {
+    pub fn new(uptime: f64) -> Self {
+        ObservationWindows { uptime: uptime.clamp(0.0, 1.0), period: DEFAULT_UPTIME_PERIOD, clock: 0, missed: 0 }
+    }
+
+    pub fn period(mut self, payments: u64) -> Self {
+        self.period = payments.max(1);
+        self
+    }
+
+    // A payment finished
+    pub fn tick(&mut self) {
+        self.clock += 1;
+    }
+
+    // Whether `node` is online now. Its window starts at an offset into the period fixed by
+    // its id.
+    pub fn is_online(&self, node: &str) -> bool {
+        let online = (self.uptime * self.period as f64).round() as u64;
+        let mut hasher = DefaultHasher::new();
+        node.hash(&mut hasher);
+        let phase = hasher.finish() % self.period;
+        (self.clock + phase) % self.period < online
+    }
+
+    // Whether `node` sees what happens now, counting what it misses
+    pub fn sees(&mut self, node: &str) -> bool {
+        let online = self.is_online(node);
+        if !online {
+            self.missed += 1;
+        }
+        online
+    }
+
+    // Observations lost to nodes being offline so far
+    pub fn missed(&self) -> u64 {
+        self.missed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nodes_are_online_for_their_share() {
+        let mut windows = ObservationWindows::new(0.6).period(10);
+        let mut online = 0;
+        for _ in 0..100 {
+            if windows.sees("node1") {
+                online += 1;
+            }
+            windows.tick();
+        }
+        assert_eq!(online, 60);
+        assert_eq!(windows.missed(), 40);
+
+        // One contiguous window per period, so the state flips twice going round it
+        let mut windows = ObservationWindows::new(0.5).period(4);
+        let states: Vec<bool> = (0..4).map(|_| { let online = windows.is_online("node2"); windows.tick(); online }).collect();
+        let flips = (0..4).filter(|&i| states[i] != states[(i + 1) % 4]).count();
+        assert_eq!(flips, 2);
+        assert!(ObservationWindows::new(1.0).is_online("node3"));
+        assert!(!ObservationWindows::new(0.0).is_online("node3"));
+    }
+}
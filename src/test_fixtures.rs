@@ -0,0 +1,22 @@
+// Analysis results and ground truth the unit tests build their scenarios from
+
+use crate::simulation::PaymentRecord;
+use crate::surveillance::PotentialRecipient;
+
+// Candidates without routes, in the order given as (node, confidence) pairs
+pub fn candidates(ranked: &[(&str, f32)]) -> Vec<PotentialRecipient> {
+    ranked.iter().map(|&(node, confidence)| PotentialRecipient::new(node, confidence)).collect()
+}
+
+// A 100 000 msat payment along `path` that the adversary saw
+pub fn record(hash: &str, path: &[&str]) -> PaymentRecord {
+    PaymentRecord {
+        payment_hash: hash.to_string(),
+        sender: path[0].to_string(),
+        receiver: path[path.len() - 1].to_string(),
+        path: path.iter().map(|node| node.to_string()).collect(),
+        amount: 100_000,
+        observed: true,
+        cover: false,
+    }
+}
@@ -0,0 +1,123 @@
+// Wall-clock time spent in each instrumented phase, collected from `tracing` spans
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::span::{Attributes, Id};
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::error::lock_mutex;
+
+// How often a phase ran and for how long in total
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PhaseStats {
+    pub calls: usize,
+    pub total: Duration,
+    pub longest: Duration,
+}
+
+impl PhaseStats {
+    pub fn mean(&self) -> Duration {
+        if self.calls == 0 { Duration::ZERO } else { self.total / self.calls as u32 }
+    }
+}
+
+// Times every span from creation to close, keyed by span name. Spans opened from
+// parallel workers overlap, so their totals can exceed the run's wall time.
+#[derive(Clone, Default)]
+pub struct PhaseTimer {
+    phases: Arc<Mutex<HashMap<&'static str, PhaseStats>>>,
+    // Order phases were first seen in, so the summary follows the run
+    order: Arc<Mutex<Vec<&'static str>>>,
+}
+
+// Creation time, stored on each span
+struct Opened(Instant);
+
+impl<S> Layer<S> for PhaseTimer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, _attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(Opened(Instant::now()));
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else { return };
+        let Some(elapsed) = span.extensions().get::<Opened>().map(|opened| opened.0.elapsed()) else { return };
+
+        let name = span.metadata().name();
+        let mut phases = lock_mutex(&self.phases);
+        let stats = phases.entry(name).or_insert_with(|| {
+            lock_mutex(&self.order).push(name);
+            PhaseStats::default()
+        });
+        stats.calls += 1;
+        stats.total += elapsed;
+        stats.longest = stats.longest.max(elapsed);
+    }
+}
+
+impl PhaseTimer {
+    // Collect span timings for the rest of the process
+    pub fn install() -> Self {
+        let timer = PhaseTimer::default();
+        let subscriber = tracing_subscriber::registry().with(timer.clone());
+        // Only fails when a subscriber is already installed, which is fine to keep
+        let _ = tracing::subscriber::set_global_default(subscriber);
+        timer
+    }
+
+    pub fn phases(&self) -> Vec<(&'static str, PhaseStats)> {
+        let phases = lock_mutex(&self.phases);
+        lock_mutex(&self.order).iter().map(|name| (*name, phases[name])).collect()
+    }
+
+    // Table of every phase in the order it first finished
+    pub fn generate_text_report(&self) -> String {
+        let mut report = String::from("## THELMA: Timing Summary\n\n");
+        report.push_str("| Phase | Calls | Total | Mean | Longest |\n");
+        report.push_str("|---|---|---|---|---|\n");
+
+        for (name, stats) in self.phases() {
+            report.push_str(&format!("| {} | {} | {:.3?} | {:.3?} | {:.3?} |\n",
+                                     name, stats.calls, stats.total, stats.mean(), stats.longest));
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing::info_span;
+
+    #[test]
+    fn test_phase_timer_aggregates_spans() {
+        let timer = PhaseTimer::default();
+        let subscriber = tracing_subscriber::registry().with(timer.clone());
+
+        tracing::subscriber::with_default(subscriber, || {
+            let _outer = info_span!("simulation").entered();
+            for _ in 0..3 {
+                let _payment = info_span!("route_payment").entered();
+                std::thread::sleep(Duration::from_millis(2));
+            }
+        });
+
+        let phases = timer.phases();
+        assert_eq!(phases.iter().map(|(name, _)| *name).collect::<Vec<_>>(), vec!["route_payment", "simulation"]);
+
+        let (_, payments) = phases[0];
+        let (_, simulation) = phases[1];
+        assert_eq!(payments.calls, 3);
+        assert!(payments.mean() >= Duration::from_millis(2));
+        assert!(simulation.total >= payments.total);
+        assert!(timer.generate_text_report().contains("| route_payment | 3 |"));
+    }
+}
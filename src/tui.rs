@@ -0,0 +1,283 @@
+// Full-screen terminal view of a running simulation
+
+use std::collections::{HashMap, HashSet};
+use std::io::IsTerminal;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use ratatui::Frame;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Gauge, Paragraph, Row, Table};
+
+use crate::models::HTLC;
+use crate::simulation::Observer;
+use crate::surveillance::SurveillanceOperation;
+use crate::error::{ThelmaError, lock_mutex};
+use crate::logging;
+
+// How often the view is redrawn
+const FRAME_INTERVAL: Duration = Duration::from_millis(200);
+
+// Width of the confidence bars, in cells
+const BAR_WIDTH: usize = 20;
+
+// One payment as the adversary saw it
+struct ObservedPayment {
+    hash: String,
+    amount: u64,
+    observers: Vec<String>,
+}
+
+// Everything the view shows, fed from the event bus
+pub struct LiveStats {
+    malicious: HashSet<String>,
+    planned: usize,
+    started: Instant,
+    settled: usize,
+    failed: usize,
+    observations: usize,
+    // Observed payments in the order they were first seen
+    payments: Vec<ObservedPayment>,
+    index: HashMap<String, usize>,
+}
+
+impl LiveStats {
+    // `already` payments were simulated before this view started, e.g. by a resumed run
+    pub fn new(malicious: &[String], planned: usize, already: usize) -> Self {
+        LiveStats {
+            malicious: malicious.iter().cloned().collect(),
+            planned,
+            started: Instant::now(),
+            settled: already,
+            failed: 0,
+            observations: 0,
+            payments: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    fn simulated(&self) -> usize {
+        self.settled + self.failed
+    }
+
+    // Payments finished per second since the view started
+    fn throughput(&self) -> f64 {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        if elapsed > 0.0 { self.simulated() as f64 / elapsed } else { 0.0 }
+    }
+}
+
+impl Observer for LiveStats {
+    fn on_htlc_forward(&mut self, htlc: &HTLC) -> Result<(), ThelmaError> {
+        if !self.malicious.contains(&htlc.observed_by_node) {
+            return Ok(());
+        }
+        self.observations += 1;
+
+        let next = self.payments.len();
+        let slot = *self.index.entry(htlc.payment_hash.clone()).or_insert(next);
+        if slot == next {
+            self.payments.push(ObservedPayment {
+                hash: htlc.payment_hash.clone(),
+                amount: htlc.amount,
+                observers: Vec::new(),
+            });
+        }
+        self.payments[slot].observers.push(htlc.observed_by_node.clone());
+        Ok(())
+    }
+
+    fn on_settle(&mut self, _payment_hash: &str) -> Result<(), ThelmaError> {
+        self.settled += 1;
+        Ok(())
+    }
+
+    fn on_fail(&mut self, _sender: &str, _receiver: &str, _reason: &str) -> Result<(), ThelmaError> {
+        self.failed += 1;
+        Ok(())
+    }
+}
+
+// The running view. Console output is held back until it finishes, or until it's dropped when
+// the run returns early with an error.
+pub struct LiveView {
+    done: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl LiveView {
+    // Take over the terminal and redraw until `finish`. Pressing q or Ctrl-C raises `stop`,
+    // just like Ctrl-C does without the view.
+    pub fn start(stats: Arc<Mutex<LiveStats>>,
+                 surveillance: Arc<Mutex<SurveillanceOperation>>,
+                 stop: Arc<AtomicBool>) -> Result<Self, ThelmaError> {
+        if !std::io::stdout().is_terminal() {
+            return Err(ThelmaError::Config("--tui needs a terminal".to_string()));
+        }
+
+        logging::hold_output();
+        let done = Arc::new(AtomicBool::new(false));
+        let finished = done.clone();
+        let thread = std::thread::spawn(move || {
+            let mut terminal = ratatui::init();
+            while !finished.load(Ordering::Relaxed) {
+                let drawn = terminal.draw(|frame| {
+                    let stats = lock_mutex(&stats);
+                    draw(frame, &stats, &lock_mutex(&surveillance), stop.load(Ordering::Relaxed));
+                });
+                if drawn.is_err() {
+                    break;
+                }
+
+                if event::poll(FRAME_INTERVAL).unwrap_or(false) {
+                    if let Ok(Event::Key(key)) = event::read() {
+                        let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                        if key.kind == KeyEventKind::Press && (key.code == KeyCode::Char('q') || ctrl_c) {
+                            stop.store(true, Ordering::Relaxed);
+                        }
+                    }
+                }
+            }
+            ratatui::restore();
+        });
+
+        Ok(LiveView { done, thread: Some(thread) })
+    }
+
+    // Give the terminal back and print what was logged in the meantime
+    pub fn finish(self) {
+        drop(self);
+    }
+}
+
+impl Drop for LiveView {
+    fn drop(&mut self) {
+        self.done.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        // The drawing thread restores the terminal when it stops, but not if it panicked
+        ratatui::restore();
+        logging::release_output();
+    }
+}
+
+fn draw(frame: &mut Frame, stats: &LiveStats, surveillance: &SurveillanceOperation, stopping: bool) {
+    let [header, progress, table] = Layout::vertical([
+        Constraint::Length(4),
+        Constraint::Length(3),
+        Constraint::Min(5),
+    ]).areas(frame.area());
+
+    let simulated = stats.simulated();
+    let coverage = if simulated > 0 { stats.payments.len() as f64 / simulated as f64 } else { 0.0 };
+    let status = if stopping { "stopping after the payment in flight" } else { "q to stop" };
+    let summary = vec![
+        Line::from(format!("Simulated {} of {} payments ({} failed), {:.1} payments/s",
+                           simulated, stats.planned, stats.failed, stats.throughput())),
+        Line::from(format!("Observed {} payments ({:.1}% coverage) through {} HTLCs at {} malicious nodes",
+                           stats.payments.len(), coverage * 100.0, stats.observations, stats.malicious.len())),
+    ];
+    frame.render_widget(Paragraph::new(summary)
+                            .block(Block::bordered().title(format!(" THELMA live view ({}) ", status))), header);
+
+    let ratio = if stats.planned > 0 { (simulated as f64 / stats.planned as f64).min(1.0) } else { 0.0 };
+    frame.render_widget(Gauge::default()
+                            .block(Block::bordered().title(" Progress "))
+                            .gauge_style(Style::default().fg(Color::Cyan))
+                            .ratio(ratio), progress);
+
+    // Newest payments first, as many as fit
+    let visible = table.height.saturating_sub(3) as usize;
+    let rows: Vec<Row> = stats.payments.iter().rev().take(visible).map(|payment| {
+        let candidates = surveillance.live_candidates(&payment.hash).unwrap_or_default();
+        let (top, bar) = match candidates.first() {
+            Some(best) => {
+                // Share of the payment's confidence held by the top candidate
+                let total: f32 = candidates.iter().map(|c| c.confidence_score).sum();
+                let share = if total > 0.0 { best.confidence_score / total } else { 0.0 };
+                let filled = (share * BAR_WIDTH as f32).round() as usize;
+                (best.node_id.clone(),
+                 format!("{}{} {:>3.0}%", "█".repeat(filled), "░".repeat(BAR_WIDTH - filled.min(BAR_WIDTH)),
+                         share * 100.0))
+            }
+            None => ("-".to_string(), String::new()),
+        };
+
+        Row::new(vec![
+            payment.hash.chars().take(16).collect::<String>(),
+            payment.amount.to_string(),
+            payment.observers.join(" > "),
+            candidates.len().to_string(),
+            top,
+            bar,
+        ])
+    }).collect();
+
+    let title = if surveillance.live_candidates_enabled() {
+        " Observed payments "
+    } else {
+        " Observed payments (run with --live for candidate rankings) "
+    };
+    frame.render_widget(Table::new(rows, [
+        Constraint::Length(16),
+        Constraint::Length(10),
+        Constraint::Fill(1),
+        Constraint::Length(10),
+        Constraint::Length(12),
+        Constraint::Length(BAR_WIDTH as u16 + 5),
+    ])
+        .header(Row::new(vec!["Payment", "Amount", "Observed at", "Candidates", "Top", "Confidence"]).bold())
+        .block(Block::bordered().title(title)), table);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::RwLock;
+    use ratatui::Terminal;
+    use ratatui::backend::TestBackend;
+    use crate::models::{Channel, LightningNetworkMap, Node};
+    use crate::surveillance::SurveillanceConfig;
+
+    #[test]
+    fn test_live_view_shows_observed_payments() {
+        let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(700000)));
+        {
+            let mut network = network_map.write().unwrap();
+            for key in ["a", "b", "c"] {
+                network.add_node(Node::new(key, key, 20));
+            }
+            network.add_channel(Channel::new("ab", "a", "b", 1_000_000));
+            network.add_channel(Channel::new("bc", "b", "c", 1_000_000));
+        }
+        let malicious = vec!["b".to_string()];
+        let mut surveillance = SurveillanceOperation::new(network_map,
+                                                          SurveillanceConfig::observing(malicious.clone()).live_analysis())
+            .unwrap();
+
+        let mut stats = LiveStats::new(&malicious, 4, 0);
+        for htlc in [HTLC::new("payment1", 700060, 5000, 700000, "b"), HTLC::new("payment1", 700080, 5000, 700000, "a")] {
+            stats.on_htlc_forward(&htlc).unwrap();
+            surveillance.on_htlc_forward(&htlc).unwrap();
+        }
+        stats.on_settle("payment1").unwrap();
+        stats.on_fail("a", "c", "no route").unwrap();
+
+        // Only the malicious node's observation counts
+        assert_eq!(stats.observations, 1);
+        assert_eq!(stats.simulated(), 2);
+
+        let mut terminal = Terminal::new(TestBackend::new(120, 16)).unwrap();
+        terminal.draw(|frame| draw(frame, &stats, &surveillance, false)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+        assert!(screen.contains("Simulated 2 of 4 payments (1 failed)"));
+        assert!(screen.contains("payment1"));
+        // b could be forwarding to a or c, so the top candidate holds half the confidence
+        assert!(screen.contains("██████████░░░░░░░░░░  50%"));
+    }
+}
@@ -0,0 +1,116 @@
+// Bindings for running the timelock attack in a browser. Everything runs on the calling
+// thread, without timers or files, and results go back to JavaScript as JSON.
+
+use std::sync::{Arc, Mutex, RwLock};
+use wasm_bindgen::prelude::*;
+
+use crate::checkpoint::network_to_json;
+use crate::defense::ScenarioMetrics;
+use crate::error::{ThelmaError, lock_mutex, read_lock};
+use crate::models::{HtlcChain, LightningNetworkMap};
+use crate::simulation::{NetworkGenerator, PaymentSimulator, SimulatorConfig, topology_from_name};
+use crate::surveillance::{RandomPlacement, SurveillanceConfig, SurveillanceOperation};
+
+// Block height simulated networks start at, as in the command-line tool
+const BLOCK_HEIGHT: u32 = 780000;
+
+// Candidates listed for each payment
+const TOP_CANDIDATES: usize = 5;
+
+// Generate a network, place `malicious_count` surveillance nodes at random, simulate
+// `payment_count` payments and run the attacker's analysis. The JSON holds the network,
+// the adversary, every payment with its true recipient and top candidates, and the
+// scenario's headline metrics.
+#[wasm_bindgen(js_name = runAttack)]
+pub fn run_attack(topology: &str,
+                  node_count: usize,
+                  payment_count: usize,
+                  malicious_count: usize) -> Result<String, JsError> {
+    Ok(attack(topology, node_count, payment_count, malicious_count)?.to_string())
+}
+
+fn attack(topology: &str,
+          node_count: usize,
+          payment_count: usize,
+          malicious_count: usize) -> Result<serde_json::Value, ThelmaError> {
+    let network_map = Arc::new(RwLock::new(LightningNetworkMap::new(BLOCK_HEIGHT)));
+    let mut topology = topology_from_name(topology, None)?;
+    NetworkGenerator::new().create_network(network_map.clone(), topology.as_mut(), node_count)?;
+
+    let config = SurveillanceConfig::with_strategy(Box::new(RandomPlacement::new()), malicious_count);
+    let operation = SurveillanceOperation::new(network_map.clone(), config)?;
+    let malicious_nodes = operation.get_malicious_nodes().to_vec();
+    let surveillance = Arc::new(Mutex::new(operation));
+
+    // Observers are tasks on the event bus, so the simulation still needs a runtime,
+    // but a single-threaded one without timers or IO will do
+    let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+    let config = SimulatorConfig::new().malicious_nodes(malicious_nodes.clone());
+    let mut simulator = PaymentSimulator::new(network_map.clone(), config);
+    runtime.block_on(async {
+        let observer = simulator.register_observer(surveillance.clone());
+        simulator.simulate_payments(payment_count).await?;
+        simulator.close_events();
+        observer.await?;
+        Ok::<(), ThelmaError>(())
+    })?;
+
+    let results = lock_mutex(&surveillance).run_analysis();
+    let network = read_lock(&network_map);
+    let metrics = ScenarioMetrics::compute("Browser", simulator.payment_records(), &results, &network);
+
+    let payments: Vec<serde_json::Value> = simulator.payment_records().iter().map(|record| {
+        let candidates: Vec<serde_json::Value> = results.get(&record.payment_hash)
+            .map(|candidates| candidates.iter().take(TOP_CANDIDATES).map(|candidate| serde_json::json!({
+                "node_id": candidate.node_id,
+                "confidence": candidate.confidence_score,
+            })).collect())
+            .unwrap_or_default();
+        serde_json::json!({
+            "payment_hash": record.payment_hash,
+            "sender": record.sender,
+            "receiver": record.receiver,
+            "path": record.path,
+            "amount": record.amount,
+            "observed": record.observed,
+            "htlcs": simulator.htlc_chains().get(&record.payment_hash)
+                .map(|chains| chains.iter().map(HtlcChain::to_json).collect::<Vec<_>>())
+                .unwrap_or_default(),
+            "candidates": candidates,
+        })
+    }).collect();
+
+    Ok(serde_json::json!({
+        "network": network_to_json(&network),
+        "malicious_nodes": malicious_nodes,
+        "payments": payments,
+        "metrics": {
+            "payments": metrics.payments,
+            "observed_payments": metrics.observed_payments,
+            "recipients_identified": metrics.recipients_identified,
+            "recipients_in_candidates": metrics.recipients_in_candidates,
+            "observation_rate": metrics.observation_rate(),
+            "identification_rate": metrics.identification_rate(),
+            "avg_anonymity_set": metrics.avg_anonymity_set,
+        },
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attack_runs_without_native_runtime() {
+        let result = attack("scale-free", 15, 20, 3).unwrap();
+        assert_eq!(result["network"]["nodes"].as_array().unwrap().len(), 15);
+        assert_eq!(result["malicious_nodes"].as_array().unwrap().len(), 3);
+
+        let payments = result["payments"].as_array().unwrap();
+        let observed = payments.iter().filter(|p| p["observed"] == true).count();
+        assert_eq!(result["metrics"]["observed_payments"], observed);
+        assert!(payments.iter().all(|p| p["candidates"].as_array().unwrap().len() <= TOP_CANDIDATES));
+
+        assert!(attack("hypercube", 15, 20, 3).is_err());
+    }
+}